@@ -0,0 +1,223 @@
+use crate::error::Error;
+use crate::methods;
+use crate::simulator::Responder;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_CAPACITY: usize = 200;
+
+// Une requête unicast et sa réponse, telles qu'échangées sur le réseau
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Exchange {
+    // Heure d'envoi, [ms Unix]
+    pub sent_at_ms: u64,
+    pub target: String,
+    pub method: String,
+    pub request: serde_json::Value,
+    pub response: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub rtt_ms: Option<f64>,
+}
+
+// Fichier de capture ouvert : un échange JSON par ligne, en ajout
+struct Capture {
+    path: PathBuf,
+    file: File,
+}
+
+// Derniers échanges, pour les rapports de bug : les plus anciens sont écrasés.
+// En mode capture, chaque échange est aussi écrit sur disque, pour être rejoué par Replay
+pub struct TrafficLog {
+    capacity: usize,
+    exchanges: Mutex<VecDeque<Exchange>>,
+    capture: Mutex<Option<Capture>>,
+}
+
+impl Default for TrafficLog {
+    fn default() -> Self {
+        Self { capacity: DEFAULT_CAPACITY, exchanges: Mutex::new(VecDeque::new()), capture: Mutex::new(None) }
+    }
+}
+
+// Le mot de passe Wi-Fi ne quitte jamais la requête
+fn redact(method: &str, mut request: serde_json::Value) -> serde_json::Value {
+    if method == methods::WIFI_SET_CONFIG {
+        if let Some(password) = request.pointer_mut("/params/config/password") {
+            *password = serde_json::json!("***");
+        }
+    }
+    request
+}
+
+impl TrafficLog {
+    pub(crate) fn record(
+        &self,
+        target: String,
+        method: &str,
+        request: serde_json::Value,
+        sent_at: SystemTime,
+        outcome: Result<(&serde_json::Value, Duration), String>,
+    ) {
+        let (response, rtt_ms, error) = match outcome {
+            Ok((response, rtt)) => (Some(response.clone()), Some(rtt.as_secs_f64() * 1000.0), None),
+            Err(e) => (None, None, Some(e)),
+        };
+        let exchange = Exchange {
+            sent_at_ms: sent_at.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default(),
+            target,
+            method: method.to_string(),
+            request: redact(method, request),
+            response,
+            error,
+            rtt_ms,
+        };
+        self.write_capture(&exchange);
+        let mut exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+        if exchanges.len() >= self.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    // Les `limit` derniers échanges, du plus ancien au plus récent
+    pub fn recent(&self, limit: usize) -> Vec<Exchange> {
+        let exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+        exchanges.iter().skip(exchanges.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    // Une capture déjà en cours est refermée au profit de la nouvelle
+    pub fn start_capture(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *self.capture.lock().unwrap_or_else(|e| e.into_inner()) = Some(Capture { path: path.to_path_buf(), file });
+        Ok(())
+    }
+
+    // Renvoie le fichier refermé, s'il y en avait un
+    pub fn stop_capture(&self) -> Option<PathBuf> {
+        self.capture.lock().unwrap_or_else(|e| e.into_inner()).take().map(|c| c.path)
+    }
+
+    pub fn capture_path(&self) -> Option<PathBuf> {
+        self.capture.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|c| c.path.clone())
+    }
+
+    // Une écriture en échec arrête la capture plutôt que de perdre des lignes en silence
+    fn write_capture(&self, exchange: &Exchange) {
+        let mut capture = self.capture.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(open) = capture.as_mut() else { return };
+        let written = serde_json::to_string(exchange)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(open.file, "{}", line));
+        if let Err(e) = written {
+            tracing::warn!("stopping capture to {}: {}", open.path.display(), e);
+            *capture = None;
+        }
+    }
+}
+
+// Réponses enregistrées d'une méthode (None : sans réponse) et prochaine à servir
+type Recorded = (Vec<Option<serde_json::Value>>, usize);
+
+// Réponses enregistrées, servies à nouveau méthode par méthode dans l'ordre de la capture
+// (puis en boucle) : reproduit hors ligne le comportement d'un firmware exotique
+pub struct Replay {
+    responses: Mutex<HashMap<String, Recorded>>,
+}
+
+impl Replay {
+    // target : ne garder que les échanges avec cette adresse ("ip" ou "ip:port")
+    pub fn load(path: &Path, target: Option<&str>) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut responses: HashMap<String, Recorded> = HashMap::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange =
+                serde_json::from_str(&line).map_err(|e| Error::Parse(format!("{} line {}: {}", path.display(), number + 1, e)))?;
+            let host = exchange.target.rsplit_once(':').map_or(exchange.target.as_str(), |(host, _)| host);
+            if target.is_some_and(|t| t != exchange.target && t != host) {
+                continue;
+            }
+            responses.entry(exchange.method).or_default().0.push(exchange.response);
+        }
+        if responses.is_empty() {
+            return Err(Error::InvalidInput(format!("No exchange to replay in {}", path.display())));
+        }
+        Ok(Self { responses: Mutex::new(responses) })
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        let mut methods: Vec<String> = self.responses.lock().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect();
+        methods.sort();
+        methods
+    }
+}
+
+impl Responder for Replay {
+    // Échange sans réponse à l'enregistrement : pas de réponse non plus au rejeu
+    fn handle(&self, request: &serde_json::Value) -> Option<serde_json::Value> {
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or_default();
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let Some((recorded, next)) = responses.get_mut(method) else {
+            return Some(serde_json::json!({"id": request["id"], "error": {"code": -32601, "message": "not in capture"}}));
+        };
+        let mut response = recorded[*next % recorded.len()].clone()?;
+        *next += 1;
+        response["id"] = request["id"].clone();
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_and_redacts_wifi_password() {
+        let log = TrafficLog { capacity: 2, ..Default::default() };
+        let reply = serde_json::json!({"result": {}});
+        for method in ["ES.GetStatus", "Bat.GetStatus", methods::WIFI_SET_CONFIG] {
+            let request = serde_json::json!({"id": 1, "method": method, "params": {"config": {"ssid": "home", "password": "secret123"}}});
+            log.record("10.0.0.2:30000".to_string(), method, request, SystemTime::now(), Ok((&reply, Duration::from_millis(40))));
+        }
+        let recent = log.recent(10);
+        assert_eq!(recent.iter().map(|e| e.method.as_str()).collect::<Vec<_>>(), ["Bat.GetStatus", methods::WIFI_SET_CONFIG]);
+        assert_eq!(recent[1].request.pointer("/params/config/password"), Some(&serde_json::json!("***")));
+        assert_eq!(recent[0].request.pointer("/params/config/password"), Some(&serde_json::json!("secret123")));
+        assert_eq!(log.recent(1).len(), 1);
+    }
+
+    #[test]
+    fn captured_exchanges_replay_in_order() {
+        let path = std::env::temp_dir().join(format!("marstek-capture-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = TrafficLog::default();
+        log.start_capture(&path).unwrap();
+        for soc in [40, 41] {
+            let reply = serde_json::json!({"id": 9, "result": {"soc": soc}});
+            log.record("10.0.0.2:30000".to_string(), "Bat.GetStatus", serde_json::json!({}), SystemTime::now(), Ok((&reply, Duration::from_millis(5))));
+        }
+        log.record("10.0.0.3:30000".to_string(), "Bat.GetStatus", serde_json::json!({}), SystemTime::now(), Err("timed out".to_string()));
+        assert_eq!(log.stop_capture(), Some(path.clone()));
+
+        let replay = Replay::load(&path, Some("10.0.0.2")).unwrap();
+        let request = serde_json::json!({"id": 77, "method": "Bat.GetStatus"});
+        let socs: Vec<_> = (0..3).map(|_| replay.handle(&request).unwrap()["result"]["soc"].clone()).collect();
+        assert_eq!(socs, [40, 41, 40]);
+        assert_eq!(replay.handle(&request).unwrap()["id"], 77);
+        assert_eq!(replay.handle(&serde_json::json!({"id": 1, "method": "ES.GetMode"})).unwrap()["error"]["code"], -32601);
+        // L'échange sans réponse reste muet au rejeu
+        assert!(Replay::load(&path, Some("10.0.0.3")).unwrap().handle(&request).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
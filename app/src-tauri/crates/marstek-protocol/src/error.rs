@@ -0,0 +1,41 @@
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    // Pas de réponse de l'appareil dans le délai
+    Timeout(String),
+    // Objet error JSON-RPC renvoyé par l'appareil
+    Rejected { code: i64, message: String },
+    // Réponse illisible
+    Parse(String),
+    Io(String),
+    // Requête refusée avant tout envoi (plage invalide, mode inconnu...)
+    InvalidInput(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rejected { code, message } => write!(f, "Device rejected the request ({}): {}", code, message),
+            Error::Timeout(message) | Error::Parse(message) | Error::Io(message) | Error::InvalidInput(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            // macOS remonte EAGAIN (os error 35) sur un socket sans réponse
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Error::Timeout(e.to_string()),
+            _ => Error::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
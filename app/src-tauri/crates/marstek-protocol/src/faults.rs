@@ -0,0 +1,122 @@
+use serde::Serialize;
+
+// Bits des registres d'alarme (36000) et de défaut (36100) de la Venus E, d'après sa table Modbus ;
+// un bit absent de la table reste signalé avec une description générique.
+const ALARMS: &[(u8, &str)] = &[
+    (0, "PLL abnormal restart"),
+    (1, "Overtemperature derating"),
+    (2, "Low temperature derating"),
+    (3, "Fan abnormal"),
+    (4, "Low battery SOC"),
+    (5, "Output overcurrent"),
+    (6, "Abnormal line sequence"),
+    (7, "WiFi module abnormal"),
+    (8, "BMS communication lost"),
+    (9, "CT meter communication lost"),
+];
+
+const FAULTS: &[(u8, &str)] = &[
+    (0, "Grid overvoltage"),
+    (1, "Grid undervoltage"),
+    (2, "Grid overfrequency"),
+    (3, "Grid underfrequency"),
+    (4, "Grid peak voltage abnormal"),
+    (5, "DC component of output current too high"),
+    (6, "DC component of output voltage too high"),
+    (7, "Bus overvoltage"),
+    (8, "Bus undervoltage"),
+    (9, "Inverter overcurrent"),
+    (10, "Inverter hardware overcurrent"),
+    (11, "Insulation resistance too low"),
+    (12, "Leakage current too high"),
+    (13, "Inverter overtemperature"),
+    (14, "Relay fault"),
+    (15, "Battery overvoltage"),
+    (16, "Battery undervoltage"),
+    (17, "Battery overcurrent"),
+    (18, "Battery overtemperature"),
+    (19, "Battery undertemperature"),
+    (20, "Cell voltage imbalance"),
+    (21, "BMS internal fault"),
+    (22, "Off-grid output overload"),
+    (23, "Off-grid output short circuit"),
+];
+
+// Codes d'erreur numériques de Bat.GetStatus / ES.GetStatus (err_code), sur les firmwares qui les renvoient
+const STATUS_CODES: &[(u32, &str)] = &[
+    (1, "Battery overvoltage"),
+    (2, "Battery undervoltage"),
+    (3, "Battery overcurrent"),
+    (4, "Battery overtemperature"),
+    (5, "Battery undertemperature"),
+    (6, "BMS communication lost"),
+    (7, "Grid abnormal"),
+    (8, "Inverter overtemperature"),
+    (9, "Inverter overload"),
+];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FaultKind {
+    // Avertissement : l'appareil continue de fonctionner, éventuellement bridé
+    Alarm,
+    // Arrêt de la charge ou de la décharge
+    Fault,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DeviceFault {
+    pub kind: FaultKind,
+    // "A4" (bit d'alarme), "F13" (bit de défaut), "E6" (err_code)
+    pub code: String,
+    pub description: String,
+}
+
+fn describe(table: &[(u8, &str)], bit: u8) -> String {
+    table.iter().find(|(b, _)| *b == bit).map_or_else(|| format!("Unknown (bit {})", bit), |(_, d)| d.to_string())
+}
+
+fn bits(kind: FaultKind, prefix: char, table: &'static [(u8, &'static str)], value: u32) -> impl Iterator<Item = DeviceFault> {
+    (0..32u8).filter(move |bit| value & (1 << bit) != 0).map(move |bit| DeviceFault {
+        kind,
+        code: format!("{}{}", prefix, bit),
+        description: describe(table, bit),
+    })
+}
+
+// Registres alarm_bits / fault_bits de ModbusStatus ; les défauts d'abord
+pub fn decode_registers(alarm_bits: u32, fault_bits: u32) -> Vec<DeviceFault> {
+    bits(FaultKind::Fault, 'F', FAULTS, fault_bits).chain(bits(FaultKind::Alarm, 'A', ALARMS, alarm_bits)).collect()
+}
+
+// 0 : pas d'erreur
+pub fn decode_status_code(code: u32) -> Option<DeviceFault> {
+    (code != 0).then(|| DeviceFault {
+        kind: FaultKind::Fault,
+        code: format!("E{}", code),
+        description: STATUS_CODES.iter().find(|(c, _)| *c == code).map_or_else(|| format!("Unknown error code {}", code), |(_, d)| d.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_register_bits() {
+        let faults = decode_registers(1 << 4, (1 << 13) | (1 << 30));
+        let codes: Vec<&str> = faults.iter().map(|f| f.code.as_str()).collect();
+        assert_eq!(codes, vec!["F13", "F30", "A4"]);
+        assert_eq!(faults[0].description, "Inverter overtemperature");
+        assert_eq!(faults[1].description, "Unknown (bit 30)");
+        assert_eq!(faults[2].kind, FaultKind::Alarm);
+        assert!(decode_registers(0, 0).is_empty());
+    }
+
+    #[test]
+    fn decodes_status_codes() {
+        assert_eq!(decode_status_code(0), None);
+        assert_eq!(decode_status_code(6).unwrap().description, "BMS communication lost");
+        assert_eq!(decode_status_code(99).unwrap().code, "E99");
+    }
+}
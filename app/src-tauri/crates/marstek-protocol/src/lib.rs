@@ -0,0 +1,29 @@
+// API Open Marstek (JSON-RPC sur UDP) et registres Modbus TCP, sans dépendance à Tauri ni à l'application
+mod capture;
+mod error;
+mod faults;
+pub mod methods;
+pub mod modbus;
+mod mode;
+mod registers;
+pub mod simulator;
+mod stats;
+mod transport;
+mod types;
+mod variant;
+
+pub use capture::{Exchange, Replay, TrafficLog};
+pub use error::Error;
+pub use faults::{decode_registers as decode_fault_registers, decode_status_code, DeviceFault, FaultKind};
+pub use modbus::ModbusClient;
+pub use mode::{accepted, from_mode_result, validate_slots, ManualSlot, ModeRequest, PassiveConfig, MAX_SLOTS};
+pub use registers::{
+    apply_control, read_fault_bits, read_power_limits, read_status as read_modbus_status, write_power_limits, ModbusControl, ModbusStatus, PowerLimits, WorkMode, MAX_FORCE_POWER,
+};
+pub use stats::{LinkMonitor, LinkStats};
+pub use transport::{encode_request, endpoint, host, parse_response, Probe, Reply, UdpTransport};
+pub use types::{parse_lenient, BatteryStatus, DeviceEvent, DeviceInfo, EnergyStatus, EventPage, HeaterStatus, MeterConfig, MeterStatus, ModeStatus, UnknownFields, WifiStatus};
+pub use variant::{family, resolve, ProtocolVariant};
+
+// Port d'écoute des appareils, en UDP
+pub const DEFAULT_PORT: u16 = 30000;
@@ -0,0 +1,109 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+// Méthodes JSON-RPC documentées par l'API Open Marstek (dialecte Venus)
+pub const GET_DEVICE: &str = "Marstek.GetDevice";
+pub const WIFI_GET_STATUS: &str = "Wifi.GetStatus";
+pub const BAT_GET_STATUS: &str = "Bat.GetStatus";
+pub const ES_GET_STATUS: &str = "ES.GetStatus";
+pub const ES_GET_MODE: &str = "ES.GetMode";
+pub const ES_SET_MODE: &str = "ES.SetMode";
+pub const EM_GET_STATUS: &str = "EM.GetStatus";
+// Non documentée dans l'Open API : celle qu'utilise l'application Marstek à l'appairage
+pub const WIFI_SET_CONFIG: &str = "Wifi.SetConfig";
+// Non documentée non plus : redémarrage du module, sans paramètre utile
+pub const REBOOT: &str = "Marstek.Reboot";
+// Mise à jour du firmware depuis le cloud Marstek, absente des firmwares anciens
+pub const OTA_UPDATE: &str = "Marstek.Update";
+// Horloge interne, qui cadence les plages Manual
+pub const GET_TIME: &str = "Marstek.GetTime";
+pub const SET_TIME: &str = "Marstek.SetTime";
+// Source de mesure du réseau et appairage du CT sans fil, absentes de l'Open API
+pub const EM_GET_CONFIG: &str = "EM.GetConfig";
+pub const EM_SET_CONFIG: &str = "EM.SetConfig";
+// Journal d'événements de l'appareil, firmwares récents seulement : une page par appel, la plus récente en 0
+pub const GET_EVENTS: &str = "Marstek.GetEvents";
+// Sortie de secours (prise off-grid), absentes de l'Open API : enable 0 ou 1
+pub const ES_GET_BACKUP: &str = "ES.GetBackup";
+pub const ES_SET_BACKUP: &str = "ES.SetBackup";
+// Chauffage de la batterie par temps froid (Venus E), absentes de l'Open API : auto_heat 0 ou 1
+pub const BAT_GET_HEATER: &str = "Bat.GetHeater";
+pub const BAT_SET_HEATER: &str = "Bat.SetHeater";
+
+// Marstek.GetDevice : "0" accepte n'importe quel appareil (sonde de découverte)
+pub fn probe_params() -> serde_json::Value {
+    serde_json::json!({"ble_mac": "0"})
+}
+
+// Paramètres des méthodes Get* par composant
+pub fn status_params() -> serde_json::Value {
+    serde_json::json!({"id": 0})
+}
+
+// Valeurs de meter_mode (EM.GetConfig / EM.SetConfig)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MeterMode {
+    Ct,
+    External,
+}
+
+// pair : oublie le CT appairé et cherche le plus proche, comme l'application Marstek
+pub fn meter_config_params(mode: Option<MeterMode>, pair: bool) -> Result<serde_json::Value, Error> {
+    let mut config = serde_json::Map::new();
+    if let Some(mode) = mode {
+        config.insert("meter_mode".to_string(), serde_json::json!(mode as u8));
+    }
+    if pair {
+        config.insert("pair".to_string(), serde_json::json!(1));
+    }
+    if config.is_empty() {
+        return Err(Error::InvalidInput("Nothing to change in the meter configuration".to_string()));
+    }
+    Ok(serde_json::json!({"id": 0, "config": config}))
+}
+
+pub fn events_params(page: u32) -> serde_json::Value {
+    serde_json::json!({"id": 0, "page": page})
+}
+
+pub fn backup_params(enabled: bool) -> serde_json::Value {
+    serde_json::json!({"id": 0, "enable": u8::from(enabled)})
+}
+
+pub fn heater_params(auto_heat: bool) -> serde_json::Value {
+    serde_json::json!({"id": 0, "auto_heat": u8::from(auto_heat)})
+}
+
+// Réseau WPA2 (mot de passe de 8 à 63 caractères) ou ouvert (mot de passe vide)
+pub fn wifi_config_params(ssid: &str, password: &str) -> Result<serde_json::Value, Error> {
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err(Error::InvalidInput("SSID must be between 1 and 32 bytes".to_string()));
+    }
+    if !password.is_empty() && !(8..=63).contains(&password.len()) {
+        return Err(Error::InvalidInput("Wi-Fi password must be empty or between 8 and 63 characters".to_string()));
+    }
+    Ok(serde_json::json!({"id": 0, "config": {"ssid": ssid, "password": password}}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wifi_config_validation() {
+        assert!(wifi_config_params("home", "secret123").is_ok());
+        assert!(wifi_config_params("guest", "").is_ok());
+        assert!(wifi_config_params("", "secret123").is_err());
+        assert!(wifi_config_params("home", "short").is_err());
+        assert!(wifi_config_params(&"x".repeat(33), "secret123").is_err());
+        assert_eq!(wifi_config_params("home", "secret123").unwrap()["config"]["ssid"], "home");
+    }
+
+    #[test]
+    fn meter_config_params_require_a_change() {
+        assert!(meter_config_params(None, false).is_err());
+        assert_eq!(meter_config_params(Some(MeterMode::External), false).unwrap()["config"], serde_json::json!({"meter_mode": 1}));
+        assert_eq!(meter_config_params(None, true).unwrap()["config"], serde_json::json!({"pair": 1}));
+    }
+}
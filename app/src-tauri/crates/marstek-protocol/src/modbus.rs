@@ -0,0 +1,193 @@
+use crate::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+pub const DEFAULT_MODBUS_PORT: u16 = 502;
+pub const DEFAULT_UNIT_ID: u8 = 1;
+// Limite du protocole pour la fonction 0x03
+pub const MAX_READ_REGISTERS: u16 = 125;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+// En-tête MBAP : transaction, protocole (0), longueur, unité
+const MBAP_LEN: usize = 7;
+
+fn exception_reason(code: u8) -> &'static str {
+    match code {
+        0x01 => "Illegal function",
+        0x02 => "Illegal data address: register not available on this firmware",
+        0x03 => "Illegal data value",
+        0x04 => "Server device failure",
+        0x06 => "Server device busy",
+        0x0B => "Gateway target device failed to respond",
+        _ => "Unknown Modbus exception",
+    }
+}
+
+// Client Modbus TCP bloquant : une connexion, requêtes séquentielles
+pub struct ModbusClient {
+    stream: TcpStream,
+    unit_id: u8,
+    transaction: u16,
+}
+
+impl ModbusClient {
+    // target : "ip:port"
+    pub fn connect(target: &str, unit_id: u8, timeout: Duration) -> Result<Self, Error> {
+        let addr = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::Io(format!("Cannot resolve {}", target)))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, unit_id, transaction: 0 })
+    }
+
+    // PDU envoyée -> PDU de la réponse (code fonction compris)
+    fn exchange(&mut self, pdu: &[u8]) -> Result<Vec<u8>, Error> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut frame = Vec::with_capacity(MBAP_LEN + pdu.len());
+        frame.extend_from_slice(&self.transaction.to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(self.unit_id);
+        frame.extend_from_slice(pdu);
+        self.stream.write_all(&frame)?;
+
+        let mut header = [0u8; MBAP_LEN];
+        self.stream.read_exact(&mut header)?;
+        let transaction = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if transaction != self.transaction || length < 2 {
+            return Err(Error::Parse(format!("Unexpected Modbus response (transaction {}, length {})", transaction, length)));
+        }
+        let mut response = vec![0u8; length - 1];
+        self.stream.read_exact(&mut response)?;
+
+        let function = pdu[0];
+        if response[0] == function | 0x80 {
+            let code = response.get(1).copied().unwrap_or_default();
+            return Err(Error::Rejected { code: code as i64, message: format!("{} (function 0x{:02X})", exception_reason(code), function) });
+        }
+        if response[0] != function {
+            return Err(Error::Parse(format!("Modbus response to function 0x{:02X} instead of 0x{:02X}", response[0], function)));
+        }
+        Ok(response)
+    }
+
+    pub fn read_holding(&mut self, address: u16, count: u16) -> Result<Vec<u16>, Error> {
+        if count == 0 || count > MAX_READ_REGISTERS {
+            return Err(Error::InvalidInput(format!("Cannot read {} registers (1-{})", count, MAX_READ_REGISTERS)));
+        }
+        let mut pdu = vec![READ_HOLDING_REGISTERS];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&count.to_be_bytes());
+        let response = self.exchange(&pdu)?;
+        let bytes = response.get(2..).unwrap_or_default();
+        if response.len() < 2 || response[1] as usize != bytes.len() || bytes.len() != count as usize * 2 {
+            return Err(Error::Parse(format!("Modbus read of {} registers at {} returned {} bytes", count, address, bytes.len())));
+        }
+        Ok(bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect())
+    }
+
+    // La réponse normale renvoie l'adresse et la valeur écrites
+    pub fn write_single(&mut self, address: u16, value: u16) -> Result<(), Error> {
+        let mut pdu = vec![WRITE_SINGLE_REGISTER];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&value.to_be_bytes());
+        let response = self.exchange(&pdu)?;
+        if response != pdu {
+            return Err(Error::Parse(format!("Modbus write of {} to register {} was not echoed", value, address)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // Serveur Modbus simulé : valeur de chaque registre donnée par `registers`, exception 0x02 si None ;
+    // les écritures (0x06) sont renvoyées en écho et transmises à `writes`
+    pub(crate) fn fake_server(registers: impl Fn(u16) -> Option<u16> + Send + 'static) -> String {
+        fake_server_with_writes(registers, std::sync::mpsc::channel().0)
+    }
+
+    pub(crate) fn fake_server_with_writes(
+        registers: impl Fn(u16) -> Option<u16> + Send + 'static,
+        writes: std::sync::mpsc::Sender<(u16, u16)>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; MBAP_LEN];
+            while stream.read_exact(&mut header).is_ok() {
+                let mut pdu = vec![0u8; u16::from_be_bytes([header[4], header[5]]) as usize - 1];
+                stream.read_exact(&mut pdu).unwrap();
+                let address = u16::from_be_bytes([pdu[1], pdu[2]]);
+                let count = u16::from_be_bytes([pdu[3], pdu[4]]);
+                let values: Option<Vec<u16>> = match pdu[0] {
+                    WRITE_SINGLE_REGISTER => registers(address).map(|_| Vec::new()),
+                    _ => (address..address + count).map(&registers).collect(),
+                };
+                let reply = match values {
+                    Some(_) if pdu[0] == WRITE_SINGLE_REGISTER => {
+                        let _ = writes.send((address, count));
+                        pdu.clone()
+                    }
+                    Some(values) => {
+                        let mut reply = vec![pdu[0], (count * 2) as u8];
+                        values.iter().for_each(|v| reply.extend_from_slice(&v.to_be_bytes()));
+                        reply
+                    }
+                    None => vec![pdu[0] | 0x80, 0x02],
+                };
+                let mut frame = header[..4].to_vec();
+                frame.extend_from_slice(&(reply.len() as u16 + 1).to_be_bytes());
+                frame.push(header[6]);
+                frame.extend_from_slice(&reply);
+                stream.write_all(&frame).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn reads_holding_registers() {
+        let addr = fake_server(|a| Some(a & 0xFF));
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        assert_eq!(client.read_holding(0x0102, 3).unwrap(), vec![0x02, 0x03, 0x04]);
+        assert_eq!(client.read_holding(0x0110, 1).unwrap(), vec![0x10]);
+    }
+
+    #[test]
+    fn exceptions_are_rejections() {
+        let addr = fake_server(|a| (a < 40000).then_some(0));
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        assert!(matches!(client.read_holding(40000, 2), Err(Error::Rejected { code: 2, .. })));
+        assert!(client.read_holding(100, 2).is_ok());
+    }
+
+    #[test]
+    fn writes_single_register() {
+        let (sender, writes) = std::sync::mpsc::channel();
+        let addr = fake_server_with_writes(|a| (a < 40000).then_some(0), sender);
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        client.write_single(100, 0x55AA).unwrap();
+        assert_eq!(writes.recv().unwrap(), (100, 0x55AA));
+        assert!(matches!(client.write_single(40000, 1), Err(Error::Rejected { code: 2, .. })));
+    }
+
+    #[test]
+    fn read_count_is_bounded() {
+        let addr = fake_server(|_| Some(0));
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        assert!(matches!(client.read_holding(0, 0), Err(Error::InvalidInput(_))));
+        assert!(matches!(client.read_holding(0, MAX_READ_REGISTERS + 1), Err(Error::InvalidInput(_))));
+    }
+}
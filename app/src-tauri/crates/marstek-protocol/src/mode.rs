@@ -0,0 +1,242 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+// Venus C/E : plages 0 à 9
+pub const MAX_SLOTS: u8 = 10;
+
+// Une plage du mode Manual, au format manual_cfg de ES.SetMode
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ManualSlot {
+    pub time_num: u8,
+    // "hh:mm"
+    pub start_time: String,
+    pub end_time: String,
+    // Bit 0 : lundi ... bit 6 : dimanche (127 : tous les jours)
+    pub week_set: u8,
+    // [W] : > 0 en décharge, < 0 en charge
+    pub power: i64,
+    // 1 : active, 0 : désactivée
+    pub enable: u8,
+}
+
+// passive_cfg de ES.SetMode
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PassiveConfig {
+    // [W] : > 0 en décharge, < 0 en charge
+    pub power: i64,
+    // Durée avant retour au mode précédent, [s]
+    pub cd_time: u32,
+}
+
+impl PassiveConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.cd_time == 0 {
+            return Err(Error::InvalidInput("passive_cfg.cd_time must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// "hh:mm" -> minutes depuis minuit ; "24:00" désigne la fin de journée
+fn minutes(field: &str, value: &str) -> Result<u16, Error> {
+    let invalid = || Error::InvalidInput(format!("{} must be hh:mm, got {:?}", field, value));
+    let (h, m) = value.split_once(':').ok_or_else(invalid)?;
+    let h: u16 = h.parse().ok().filter(|h| *h <= 24).ok_or_else(invalid)?;
+    let m: u16 = m.parse().ok().filter(|m| *m < 60).ok_or_else(invalid)?;
+    Some(h * 60 + m).filter(|t| *t <= 24 * 60).ok_or_else(invalid)
+}
+
+impl ManualSlot {
+    // Plage écrite pour effacer un numéro retiré de l'emploi du temps
+    pub fn disabled(&self) -> Self {
+        Self { enable: 0, ..self.clone() }
+    }
+
+    // [début, fin) en minutes
+    fn window(&self) -> Result<(u16, u16), Error> {
+        Ok((minutes("start_time", &self.start_time)?, minutes("end_time", &self.end_time)?))
+    }
+
+    // Contrôles propres à la plage ; la limite de puissance dépend de l'appareil et se vérifie à part
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Error::InvalidInput(format!("Slot {}: {}", self.time_num, message));
+        if self.time_num >= MAX_SLOTS {
+            return Err(invalid(format!("time_num must be between 0 and {}", MAX_SLOTS - 1)));
+        }
+        let (start, end) = self.window().map_err(|e| invalid(e.to_string()))?;
+        if end <= start {
+            return Err(invalid(format!("end_time {} is not after start_time {}", self.end_time, self.start_time)));
+        }
+        if self.week_set == 0 || self.week_set > 127 {
+            return Err(invalid(format!("week_set {} is not a valid day mask (1-127)", self.week_set)));
+        }
+        if self.enable > 1 {
+            return Err(invalid(format!("enable must be 0 or 1, got {}", self.enable)));
+        }
+        Ok(())
+    }
+}
+
+// Ensemble complet : chaque plage, numéros uniques, pas de chevauchement entre plages actives un même jour
+pub fn validate_slots(slots: &[ManualSlot]) -> Result<(), Error> {
+    for slot in slots {
+        slot.validate()?;
+    }
+    for (i, a) in slots.iter().enumerate() {
+        for b in &slots[i + 1..] {
+            if a.time_num == b.time_num {
+                return Err(Error::InvalidInput(format!("time_num {} is used by several slots", a.time_num)));
+            }
+            if a.enable == 0 || b.enable == 0 || a.week_set & b.week_set == 0 {
+                continue;
+            }
+            let ((a_start, a_end), (b_start, b_end)) = (a.window()?, b.window()?);
+            if a_start < b_end && b_start < a_end {
+                return Err(Error::InvalidInput(format!(
+                    "Slots {} ({}-{}) and {} ({}-{}) overlap on the same day",
+                    a.time_num, a.start_time, a.end_time, b.time_num, b.start_time, b.end_time
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+// ES.GetMode ne documente que le mode courant ; certains firmwares renvoient aussi
+// manual_cfg, en objet (plage active) ou en tableau (toutes les plages)
+pub fn from_mode_result(result: &serde_json::Value) -> Option<Vec<ManualSlot>> {
+    let parse = |v: &serde_json::Value| serde_json::from_value::<ManualSlot>(v.clone()).ok();
+    let mut slots = match result.get("manual_cfg")? {
+        serde_json::Value::Array(items) => items.iter().filter_map(parse).collect(),
+        other => vec![parse(other)?],
+    };
+    slots.sort_by_key(|s| s.time_num);
+    Some(slots)
+}
+
+// Requête ES.SetMode validée, avant tout envoi
+#[derive(Clone, Debug)]
+pub enum ModeRequest {
+    Auto,
+    Ai,
+    Manual(ManualSlot),
+    Passive(PassiveConfig),
+}
+
+// Membre `key` de la config, champs manquants ou mal typés refusés avant tout envoi
+fn typed_config<T: serde::de::DeserializeOwned>(config: &serde_json::Value, key: &str) -> Result<T, Error> {
+    let value = config.get(key).ok_or_else(|| Error::InvalidInput(format!("Missing {} in config", key)))?;
+    serde_json::from_value(value.clone()).map_err(|e| Error::InvalidInput(format!("Invalid {}: {}", key, e)))
+}
+
+impl ModeRequest {
+    // mode : "Auto", "AI", "Manual" ou "Passive" ; config : {"manual_cfg": {...}} ou {"passive_cfg": {...}}
+    pub fn parse(mode: &str, config: Option<&serde_json::Value>) -> Result<Self, Error> {
+        let request = match mode {
+            "Auto" => ModeRequest::Auto,
+            "AI" => ModeRequest::Ai,
+            "Manual" => {
+                let cfg = config.ok_or_else(|| Error::InvalidInput("Manual mode requires config with manual_cfg".to_string()))?;
+                let slot: ManualSlot = typed_config(cfg, "manual_cfg")?;
+                slot.validate()?;
+                ModeRequest::Manual(slot)
+            }
+            "Passive" => {
+                let cfg = config.ok_or_else(|| Error::InvalidInput("Passive mode requires config with passive_cfg".to_string()))?;
+                let passive: PassiveConfig = typed_config(cfg, "passive_cfg")?;
+                passive.validate()?;
+                ModeRequest::Passive(passive)
+            }
+            _ => return Err(Error::InvalidInput(format!("Unknown mode: {}", mode))),
+        };
+        Ok(request)
+    }
+
+    // [W] : > 0 en décharge, < 0 en charge
+    pub fn power(&self) -> Option<i64> {
+        match self {
+            ModeRequest::Manual(slot) => Some(slot.power),
+            ModeRequest::Passive(passive) => Some(passive.power),
+            ModeRequest::Auto | ModeRequest::Ai => None,
+        }
+    }
+
+    // Paramètres de ES.SetMode
+    pub fn params(&self) -> serde_json::Value {
+        let config = match self {
+            ModeRequest::Auto => serde_json::json!({ "mode": "Auto", "auto_cfg": { "enable": 1 } }),
+            ModeRequest::Ai => serde_json::json!({ "mode": "AI", "ai_cfg": { "enable": 1 } }),
+            ModeRequest::Manual(slot) => serde_json::json!({ "mode": "Manual", "manual_cfg": slot }),
+            ModeRequest::Passive(passive) => serde_json::json!({ "mode": "Passive", "passive_cfg": passive }),
+        };
+        serde_json::json!({ "id": 0, "config": config })
+    }
+}
+
+// Résultat de ES.SetMode : set_result si présent, sinon true (pas d'erreur)
+pub fn accepted(result: &serde_json::Value) -> bool {
+    result.get("set_result").and_then(|v| v.as_bool()).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(time_num: u8, start: &str, end: &str, week_set: u8) -> ManualSlot {
+        ManualSlot { time_num, start_time: start.to_string(), end_time: end.to_string(), week_set, power: -800, enable: 1 }
+    }
+
+    #[test]
+    fn slot_window_checks() {
+        assert!(slot(0, "08:00", "24:00", 127).validate().is_ok());
+        assert!(slot(0, "08:00", "08:00", 127).validate().is_err());
+        assert!(slot(0, "8h", "09:00", 127).validate().is_err());
+        assert!(slot(0, "23:00", "24:30", 127).validate().is_err());
+        assert!(slot(MAX_SLOTS, "08:00", "09:00", 127).validate().is_err());
+        assert!(slot(0, "08:00", "09:00", 0).validate().is_err());
+    }
+
+    #[test]
+    fn overlapping_slots_are_refused_on_shared_days() {
+        let monday = slot(0, "08:00", "10:00", 0b0000001);
+        assert!(validate_slots(&[monday.clone(), slot(1, "09:00", "11:00", 0b0000001)]).is_err());
+        assert!(validate_slots(&[monday.clone(), slot(1, "09:00", "11:00", 0b0000010)]).is_ok());
+        assert!(validate_slots(&[monday.clone(), slot(1, "09:00", "11:00", 0b0000001).disabled()]).is_ok());
+        assert!(validate_slots(&[monday.clone(), slot(0, "12:00", "13:00", 0b0000001)]).is_err());
+    }
+
+    #[test]
+    fn mode_result_slots() {
+        let single = serde_json::json!({"mode": "Manual", "manual_cfg": slot(3, "01:00", "02:00", 127)});
+        assert_eq!(from_mode_result(&single), Some(vec![slot(3, "01:00", "02:00", 127)]));
+        let many = serde_json::json!({"manual_cfg": [slot(2, "03:00", "04:00", 1), slot(1, "01:00", "02:00", 1)]});
+        assert_eq!(from_mode_result(&many).unwrap().iter().map(|s| s.time_num).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(from_mode_result(&serde_json::json!({"mode": "Auto"})), None);
+    }
+
+    #[test]
+    fn set_mode_params() {
+        assert_eq!(
+            ModeRequest::parse("Auto", None).unwrap().params(),
+            serde_json::json!({"id": 0, "config": {"mode": "Auto", "auto_cfg": {"enable": 1}}})
+        );
+        let passive = ModeRequest::parse("Passive", Some(&serde_json::json!({"passive_cfg": {"power": -500, "cd_time": 60}}))).unwrap();
+        assert_eq!(passive.power(), Some(-500));
+        assert_eq!(passive.params()["config"]["passive_cfg"], serde_json::json!({"power": -500, "cd_time": 60}));
+    }
+
+    #[test]
+    fn invalid_mode_requests() {
+        assert!(ModeRequest::parse("Eco", None).is_err());
+        assert!(ModeRequest::parse("Manual", None).is_err());
+        assert!(ModeRequest::parse("Passive", Some(&serde_json::json!({"passive_cfg": {"power": 100}}))).is_err());
+        assert!(ModeRequest::parse("Passive", Some(&serde_json::json!({"passive_cfg": {"power": 100, "cd_time": 0}}))).is_err());
+    }
+
+    #[test]
+    fn set_result() {
+        assert!(accepted(&serde_json::json!({"id": 0, "set_result": true})));
+        assert!(!accepted(&serde_json::json!({"set_result": false})));
+        assert!(accepted(&serde_json::Value::Null));
+    }
+}
@@ -0,0 +1,365 @@
+use crate::error::Error;
+use crate::modbus::ModbusClient;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::BTreeMap;
+
+// Table Modbus de la Venus E (registres de maintien, fonction 0x03), lue par blocs contigus.
+// Un bloc absent du firmware (exception 0x02) laisse ses champs vides.
+struct Block {
+    name: &'static str,
+    address: u16,
+    count: u16,
+}
+
+const BLOCKS: &[Block] = &[
+    Block { name: "device_name", address: 31000, count: 10 },
+    Block { name: "serial", address: 31200, count: 10 },
+    Block { name: "battery", address: 32100, count: 6 },
+    Block { name: "ac", address: 32200, count: 5 },
+    Block { name: "energy", address: 33000, count: 12 },
+    Block { name: "cells", address: 34018, count: 16 },
+    Block { name: "temperature", address: 35000, count: 3 },
+    Block { name: "cell_temperature", address: 35010, count: 2 },
+    Block { name: "state", address: 35100, count: 1 },
+    Block { name: "alarm", address: ALARM_BITS, count: 2 },
+    Block { name: "fault", address: FAULT_BITS, count: 2 },
+];
+const ALARM_BITS: u16 = 36000;
+const FAULT_BITS: u16 = 36100;
+
+// Registres de commande
+const RS485_CONTROL: u16 = 42000;
+const FORCE_MODE: u16 = 42010;
+const FORCE_CHARGE_POWER: u16 = 42020;
+const FORCE_DISCHARGE_POWER: u16 = 42021;
+const WORK_MODE: u16 = 43000;
+// Limites de puissance permanentes de l'onduleur, [W]
+const MAX_CHARGE_POWER: u16 = 44002;
+const MAX_DISCHARGE_POWER: u16 = 44003;
+// Valeurs de RS485_CONTROL : la consigne forcée n'est suivie qu'en mode RS485
+const RS485_ENABLE: u16 = 0x55AA;
+const RS485_DISABLE: u16 = 0x55BB;
+// Plage acceptée par FORCE_CHARGE_POWER et FORCE_DISCHARGE_POWER, [W]
+pub const MAX_FORCE_POWER: u16 = 2500;
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct ModbusStatus {
+    pub device_name: Option<String>,
+    pub serial: Option<String>,
+    // [V], [A], [W] : puissance > 0 en décharge
+    pub battery_voltage: Option<f32>,
+    pub battery_current: Option<f32>,
+    pub battery_power: Option<i32>,
+    // [%]
+    pub soc: Option<u16>,
+    // Énergie restante, [kWh]
+    pub battery_energy: Option<f32>,
+    // Côté réseau : [V], [A], [W], [Hz]
+    pub ac_voltage: Option<f32>,
+    pub ac_current: Option<f32>,
+    pub ac_power: Option<i32>,
+    pub ac_frequency: Option<f32>,
+    // Compteurs, [kWh]
+    pub total_charge_energy: Option<f32>,
+    pub total_discharge_energy: Option<f32>,
+    pub daily_charge_energy: Option<f32>,
+    pub daily_discharge_energy: Option<f32>,
+    pub monthly_charge_energy: Option<f32>,
+    pub monthly_discharge_energy: Option<f32>,
+    // [V], cellules présentes seulement
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cell_voltages: Vec<f32>,
+    // [°C]
+    pub internal_temp: Option<f32>,
+    pub mos1_temp: Option<f32>,
+    pub mos2_temp: Option<f32>,
+    pub max_cell_temp: Option<f32>,
+    pub min_cell_temp: Option<f32>,
+    // sleep, standby, charge, discharge, backup, upgrade
+    pub inverter_state: Option<String>,
+    // Champs de bits bruts, 0 : rien à signaler
+    pub alarm_bits: Option<u32>,
+    pub fault_bits: Option<u32>,
+    // Bloc -> erreur de lecture
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+fn u32_at(regs: &[u16], i: usize) -> u32 {
+    (regs[i] as u32) << 16 | regs[i + 1] as u32
+}
+
+fn i32_at(regs: &[u16], i: usize) -> i32 {
+    u32_at(regs, i) as i32
+}
+
+fn scaled(value: impl Into<f64>, scale: f64) -> f32 {
+    (value.into() / scale) as f32
+}
+
+// Deux caractères ASCII par registre, complétés par des zéros
+fn ascii(regs: &[u16]) -> Option<String> {
+    let bytes: Vec<u8> = regs.iter().flat_map(|r| r.to_be_bytes()).take_while(|b| *b != 0).collect();
+    let text = String::from_utf8_lossy(&bytes).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn state_name(value: u16) -> String {
+    match value {
+        0 => "sleep".to_string(),
+        1 => "standby".to_string(),
+        2 => "charge".to_string(),
+        3 => "discharge".to_string(),
+        4 => "backup".to_string(),
+        5 => "upgrade".to_string(),
+        other => format!("unknown({})", other),
+    }
+}
+
+impl ModbusStatus {
+    // regs : contenu complet du bloc `name` de BLOCKS
+    fn apply(&mut self, name: &str, regs: &[u16]) {
+        match name {
+            "device_name" => self.device_name = ascii(regs),
+            "serial" => self.serial = ascii(regs),
+            "battery" => {
+                self.battery_voltage = Some(scaled(regs[0], 100.0));
+                self.battery_current = Some(scaled(regs[1] as i16, 100.0));
+                self.battery_power = Some(i32_at(regs, 2));
+                self.soc = Some(regs[4]);
+                self.battery_energy = Some(scaled(regs[5], 1000.0));
+            }
+            "ac" => {
+                self.ac_voltage = Some(scaled(regs[0], 10.0));
+                self.ac_current = Some(scaled(regs[1], 100.0));
+                self.ac_power = Some(i32_at(regs, 2));
+                self.ac_frequency = Some(scaled(regs[4] as i16, 100.0));
+            }
+            "energy" => {
+                let kwh = |i| Some(scaled(u32_at(regs, i), 100.0));
+                self.total_charge_energy = kwh(0);
+                self.total_discharge_energy = kwh(2);
+                self.daily_charge_energy = kwh(4);
+                self.daily_discharge_energy = kwh(6);
+                self.monthly_charge_energy = kwh(8);
+                self.monthly_discharge_energy = kwh(10);
+            }
+            "cells" => self.cell_voltages = regs.iter().filter(|v| **v > 0).map(|v| scaled(*v, 1000.0)).collect(),
+            "temperature" => {
+                self.internal_temp = Some(scaled(regs[0] as i16, 10.0));
+                self.mos1_temp = Some(scaled(regs[1] as i16, 10.0));
+                self.mos2_temp = Some(scaled(regs[2] as i16, 10.0));
+            }
+            "cell_temperature" => {
+                self.max_cell_temp = Some(scaled(regs[0] as i16, 10.0));
+                self.min_cell_temp = Some(scaled(regs[1] as i16, 10.0));
+            }
+            "state" => self.inverter_state = Some(state_name(regs[0])),
+            "alarm" => self.alarm_bits = Some(u32_at(regs, 0)),
+            "fault" => self.fault_bits = Some(u32_at(regs, 0)),
+            _ => {}
+        }
+    }
+}
+
+// Valeurs de WORK_MODE
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkMode {
+    Manual,
+    // Anti-injection (mode Auto de l'API UDP)
+    Auto,
+    // Arbitrage (mode AI de l'API UDP)
+    Ai,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModbusControl {
+    // [W] : > 0 en décharge, < 0 en charge, 0 : arrêt ; prend la main en mode RS485
+    Setpoint(i64),
+    // Arrête la consigne et rend la main au mode de travail
+    Release,
+    WorkMode(WorkMode),
+}
+
+impl ModbusControl {
+    // Écritures (registre, valeur) dans l'ordre d'envoi, validées avant toute connexion
+    pub fn writes(&self) -> Result<Vec<(u16, u16)>, Error> {
+        let writes = match *self {
+            ModbusControl::Setpoint(power) => {
+                let watts = u16::try_from(power.unsigned_abs())
+                    .ok()
+                    .filter(|w| *w <= MAX_FORCE_POWER)
+                    .ok_or_else(|| Error::InvalidInput(format!("Modbus setpoint {} W is outside ±{} W", power, MAX_FORCE_POWER)))?;
+                match power.signum() {
+                    1 => vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_DISCHARGE_POWER, watts), (FORCE_MODE, 2)],
+                    -1 => vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_CHARGE_POWER, watts), (FORCE_MODE, 1)],
+                    _ => vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_MODE, 0)],
+                }
+            }
+            ModbusControl::Release => vec![(FORCE_MODE, 0), (RS485_CONTROL, RS485_DISABLE)],
+            ModbusControl::WorkMode(mode) => vec![(WORK_MODE, mode as u16)],
+        };
+        Ok(writes)
+    }
+}
+
+// S'arrête à la première écriture refusée
+pub fn apply_control(client: &mut ModbusClient, control: ModbusControl) -> Result<(), Error> {
+    for (address, value) in control.writes()? {
+        client.write_single(address, value)?;
+    }
+    Ok(())
+}
+
+// Limites réglées sur l'appareil, conservées après redémarrage ; toute consigne est plafonnée par elles
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PowerLimits {
+    pub max_charge_w: u16,
+    pub max_discharge_w: u16,
+}
+
+impl PowerLimits {
+    // hardware_max : puissance nominale du modèle
+    pub fn validate(&self, hardware_max: u16) -> Result<(), Error> {
+        for (name, value) in [("charge", self.max_charge_w), ("discharge", self.max_discharge_w)] {
+            if value == 0 || value > hardware_max {
+                return Err(Error::InvalidInput(format!("Max {} power {} W must be between 1 and {} W", name, value, hardware_max)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn writes(&self) -> Vec<(u16, u16)> {
+        vec![(MAX_CHARGE_POWER, self.max_charge_w), (MAX_DISCHARGE_POWER, self.max_discharge_w)]
+    }
+}
+
+pub fn read_power_limits(client: &mut ModbusClient) -> Result<PowerLimits, Error> {
+    let regs = client.read_holding(MAX_CHARGE_POWER, 2)?;
+    Ok(PowerLimits { max_charge_w: regs[0], max_discharge_w: regs[1] })
+}
+
+pub fn write_power_limits(client: &mut ModbusClient, limits: &PowerLimits) -> Result<(), Error> {
+    for (address, value) in limits.writes() {
+        client.write_single(address, value)?;
+    }
+    Ok(())
+}
+
+// (alarm_bits, fault_bits) seuls, sans relire toute la table
+pub fn read_fault_bits(client: &mut ModbusClient) -> Result<(u32, u32), Error> {
+    let alarm = client.read_holding(ALARM_BITS, 2)?;
+    let fault = client.read_holding(FAULT_BITS, 2)?;
+    Ok((u32_at(&alarm, 0), u32_at(&fault, 0)))
+}
+
+// Échoue seulement si aucun bloc n'a pu être lu
+pub fn read_status(client: &mut ModbusClient) -> Result<ModbusStatus, Error> {
+    let mut status = ModbusStatus::default();
+    let mut first_error = None;
+    for block in BLOCKS {
+        match client.read_holding(block.address, block.count) {
+            Ok(regs) => status.apply(block.name, &regs),
+            Err(e) => {
+                status.errors.insert(block.name.to_string(), e.to_string());
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if status.errors.len() == BLOCKS.len() => Err(e),
+        _ => Ok(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::tests::fake_server;
+    use crate::modbus::DEFAULT_UNIT_ID;
+    use std::time::Duration;
+
+    #[test]
+    fn decodes_battery_block() {
+        let mut status = ModbusStatus::default();
+        // 52.10 V, -12.34 A, -643 W, 76 %, 3.920 kWh
+        status.apply("battery", &[5210, (-1234i16) as u16, 0xFFFF, (-643i32) as u16, 76, 3920]);
+        assert_eq!(status.battery_voltage, Some(52.1));
+        assert_eq!(status.battery_current, Some(-12.34));
+        assert_eq!(status.battery_power, Some(-643));
+        assert_eq!(status.soc, Some(76));
+        assert_eq!(status.battery_energy, Some(3.92));
+    }
+
+    #[test]
+    fn decodes_text_and_cells() {
+        let mut status = ModbusStatus::default();
+        status.apply("device_name", &[0x5645, 0x4E55, 0x5300, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(status.device_name.as_deref(), Some("VENUS"));
+        status.apply("cells", &[3301, 3305, 0, 0]);
+        assert_eq!(status.cell_voltages, vec![3.301, 3.305]);
+        status.apply("state", &[3]);
+        assert_eq!(status.inverter_state.as_deref(), Some("discharge"));
+    }
+
+    #[test]
+    fn setpoint_writes() {
+        assert_eq!(
+            ModbusControl::Setpoint(-800).writes().unwrap(),
+            vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_CHARGE_POWER, 800), (FORCE_MODE, 1)]
+        );
+        assert_eq!(
+            ModbusControl::Setpoint(1200).writes().unwrap(),
+            vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_DISCHARGE_POWER, 1200), (FORCE_MODE, 2)]
+        );
+        assert_eq!(ModbusControl::Setpoint(0).writes().unwrap(), vec![(RS485_CONTROL, RS485_ENABLE), (FORCE_MODE, 0)]);
+        assert!(ModbusControl::Setpoint(2501).writes().is_err());
+        assert!(ModbusControl::Setpoint(i64::MIN).writes().is_err());
+        assert_eq!(ModbusControl::WorkMode(WorkMode::Ai).writes().unwrap(), vec![(WORK_MODE, 2)]);
+    }
+
+    #[test]
+    fn control_is_written_in_order() {
+        let (sender, writes) = std::sync::mpsc::channel();
+        let addr = crate::modbus::tests::fake_server_with_writes(|_| Some(0), sender);
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        apply_control(&mut client, ModbusControl::Release).unwrap();
+        assert_eq!(writes.try_iter().collect::<Vec<_>>(), vec![(FORCE_MODE, 0), (RS485_CONTROL, RS485_DISABLE)]);
+    }
+
+    #[test]
+    fn power_limits_round_trip() {
+        let limits = PowerLimits { max_charge_w: 1500, max_discharge_w: 800 };
+        assert!(limits.validate(2500).is_ok());
+        assert!(limits.validate(1000).is_err());
+        assert!(PowerLimits { max_charge_w: 0, max_discharge_w: 800 }.validate(2500).is_err());
+
+        let (sender, writes) = std::sync::mpsc::channel();
+        let addr = crate::modbus::tests::fake_server_with_writes(|a| Some(if a == MAX_CHARGE_POWER { 1500 } else { 800 }), sender);
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        assert_eq!(read_power_limits(&mut client).unwrap(), limits);
+        write_power_limits(&mut client, &limits).unwrap();
+        assert_eq!(writes.try_iter().collect::<Vec<_>>(), vec![(MAX_CHARGE_POWER, 1500), (MAX_DISCHARGE_POWER, 800)]);
+    }
+
+    #[test]
+    fn missing_blocks_are_reported() {
+        // Firmware sans les blocs alarme et défaut
+        let addr = fake_server(|a| (a < 36000).then_some(1));
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        let status = read_status(&mut client).unwrap();
+        assert_eq!(status.soc, Some(1));
+        assert_eq!(status.alarm_bits, None);
+        assert_eq!(status.errors.keys().collect::<Vec<_>>(), vec!["alarm", "fault"]);
+    }
+
+    #[test]
+    fn fails_when_nothing_answers() {
+        let addr = fake_server(|_| None);
+        let mut client = ModbusClient::connect(&addr, DEFAULT_UNIT_ID, Duration::from_secs(2)).unwrap();
+        assert!(matches!(read_status(&mut client), Err(Error::Rejected { code: 2, .. })));
+    }
+}
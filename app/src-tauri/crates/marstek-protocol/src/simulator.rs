@@ -0,0 +1,582 @@
+use crate::methods;
+use crate::mode::{ManualSlot, ModeRequest};
+use std::f64::consts::PI;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const MODEL: &str = "VenusE";
+pub const FIRMWARE: u32 = 153;
+// Caractéristiques d'un Venus E : capacité [Wh], puissance AC max [W], SOC plancher de décharge [%]
+const CAPACITY_WH: f64 = 5120.0;
+const MAX_POWER_W: f64 = 2500.0;
+const MIN_SOC: f64 = 10.0;
+// Crête de l'installation PV simulée, [W]
+const PV_PEAK_W: f64 = 2400.0;
+// Pas d'intégration : un long silence du client n'applique pas la dernière puissance pendant des heures
+const STEP: Duration = Duration::from_secs(30);
+const MAX_CATCH_UP: Duration = Duration::from_secs(86_400);
+// Une panne toutes les 30 min en moyenne, de 20 à 90 s
+const MEAN_FAULT_INTERVAL_S: f64 = 1800.0;
+const FAULT_MIN_S: f64 = 20.0;
+const FAULT_MAX_S: f64 = 90.0;
+// Absence après Marstek.Reboot, Marstek.Update et Wifi.SetConfig
+const REBOOT_DOWNTIME: Duration = Duration::from_secs(20);
+const UPDATE_DOWNTIME: Duration = Duration::from_secs(60);
+// Entrées par page de Marstek.GetEvents
+const EVENTS_PER_PAGE: usize = 10;
+// Attente maximale de recv_from avant de vérifier l'arrêt demandé
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fault {
+    // Aucune réponse, comme un module Wi-Fi décroché
+    Unresponsive,
+    // Lectures refusées en -32603
+    Busy,
+    // Pince CT débranchée : EM.GetStatus sans mesure
+    CtLost,
+}
+
+#[derive(Clone, Debug)]
+enum Mode {
+    Auto,
+    Ai,
+    Manual,
+    // Puissance [W] (> 0 en décharge) jusqu'à l'échéance, puis retour au mode précédent
+    Passive { power: i64, until: Instant, previous: Box<Mode> },
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::Auto => "Auto",
+            Mode::Ai => "AI",
+            Mode::Manual => "Manual",
+            Mode::Passive { .. } => "Passive",
+        }
+    }
+}
+
+// Générateur xorshift : pas besoin de qualité cryptographique pour du bruit de mesure
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next()
+    }
+}
+
+struct State {
+    soc: f64,
+    mode: Mode,
+    manual: Vec<ManualSlot>,
+    pv_w: f64,
+    load_w: f64,
+    bat_w: f64,
+    temp: f64,
+    // Passage nuageux : facteur appliqué au PV, marche aléatoire entre 0,3 et 1
+    clouds: f64,
+    // Appareil allumé (bouilloire, four) : puissance et échéance
+    spike: Option<(f64, Instant)>,
+    // Cumuls, [Wh]
+    pv_wh: f64,
+    output_wh: f64,
+    input_wh: f64,
+    load_wh: f64,
+    fault: Option<(Fault, Instant)>,
+    offline_until: Option<Instant>,
+    firmware: u32,
+    ssid: String,
+    // Sortie de secours active
+    backup: bool,
+    meter_mode: u64,
+    // Journal servi par Marstek.GetEvents, plus récent en dernier : (horodatage, code, message)
+    events: Vec<(i64, u32, String)>,
+    // Dérive de l'horloge interne, [s]
+    clock_offset_s: i64,
+    last: Instant,
+    rng: Rng,
+}
+
+// Appareil servi sur la boucle locale : réponse complète à une requête JSON-RPC, None s'il reste muet
+pub trait Responder: Send + Sync {
+    fn handle(&self, request: &serde_json::Value) -> Option<serde_json::Value>;
+}
+
+// Batterie Venus E imaginaire : PV sinusoïdal, consommation du foyer avec pics, SOC intégré
+// et pannes occasionnelles, pour développer et démontrer l'application sans matériel
+pub struct Simulator {
+    // Décalage du fuseau local, pour la courbe de production et les plages Manual, [s]
+    utc_offset_s: i64,
+    ble_mac: String,
+    wifi_mac: String,
+    // Pannes aléatoires désactivables (tests)
+    faults: bool,
+    state: Mutex<State>,
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+impl Simulator {
+    pub fn new(seed: u64, utc_offset_s: i64) -> Self {
+        let mut rng = Rng(seed | 1);
+        let mac = |rng: &mut Rng| (0..6).map(|_| format!("{:02x}", (rng.next() * 256.0) as u8)).collect::<String>();
+        let (ble_mac, wifi_mac) = (mac(&mut rng), mac(&mut rng));
+        let soc = rng.range(40.0, 80.0);
+        let clock_offset_s = rng.range(-90.0, 90.0) as i64;
+        Self {
+            utc_offset_s,
+            ble_mac,
+            wifi_mac,
+            faults: true,
+            state: Mutex::new(State {
+                soc,
+                mode: Mode::Auto,
+                manual: Vec::new(),
+                pv_w: 0.0,
+                load_w: 0.0,
+                bat_w: 0.0,
+                temp: 20.0,
+                clouds: 1.0,
+                spike: None,
+                pv_wh: 0.0,
+                output_wh: 0.0,
+                input_wh: 0.0,
+                load_wh: 0.0,
+                fault: None,
+                offline_until: None,
+                firmware: FIRMWARE,
+                ssid: "Marstip-Sim".to_string(),
+                backup: true,
+                meter_mode: 0,
+                events: Vec::new(),
+                clock_offset_s,
+                last: Instant::now(),
+                rng,
+            }),
+        }
+    }
+
+    pub fn without_faults(mut self) -> Self {
+        self.faults = false;
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Heure locale en heures décimales et jour de la semaine (0 : lundi)
+    fn local_time(&self, unix: i64) -> (f64, u32) {
+        let local = unix + self.utc_offset_s;
+        let hour = local.rem_euclid(86_400) as f64 / 3600.0;
+        // Le 1er janvier 1970 était un jeudi
+        let weekday = (local.div_euclid(86_400) + 3).rem_euclid(7) as u32;
+        (hour, weekday)
+    }
+
+    fn active_slot(&self, state: &State, unix: i64) -> Option<i64> {
+        let (hour, weekday) = self.local_time(unix);
+        let minute = (hour * 60.0) as u32;
+        let parse = |t: &str| t.split_once(':').and_then(|(h, m)| Some(h.parse::<u32>().ok()? * 60 + m.parse::<u32>().ok()?));
+        state
+            .manual
+            .iter()
+            .filter(|s| s.enable == 1 && s.week_set & (1 << weekday) != 0)
+            .find(|s| matches!((parse(&s.start_time), parse(&s.end_time)), (Some(start), Some(end)) if start <= minute && minute < end))
+            .map(|s| s.power)
+    }
+
+    // Avance la simulation jusqu'à maintenant, par pas de STEP au plus ; les lectures
+    // d'un même rafraîchissement (moins d'une seconde) voient le même état
+    fn advance(&self, state: &mut State, now: Instant) {
+        let mut elapsed = now.saturating_duration_since(state.last).min(MAX_CATCH_UP);
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let mut unix = unix_now() - elapsed.as_secs() as i64;
+        state.last = now;
+        while !elapsed.is_zero() {
+            let dt = elapsed.min(STEP);
+            elapsed -= dt;
+            unix += dt.as_secs() as i64;
+            self.step(state, dt.as_secs_f64(), unix, now - elapsed);
+        }
+    }
+
+    fn step(&self, state: &mut State, dt_s: f64, unix: i64, at: Instant) {
+        let (hour, _) = self.local_time(unix);
+        let rng = &mut state.rng;
+
+        state.clouds = (state.clouds + rng.range(-0.05, 0.05) * dt_s.sqrt()).clamp(0.3, 1.0);
+        let daylight = ((hour - 6.0) / 14.0 * PI).sin().max(0.0);
+        state.pv_w = PV_PEAK_W * daylight * state.clouds;
+
+        if state.spike.is_some_and(|(_, until)| until <= at) {
+            state.spike = None;
+        }
+        if state.spike.is_none() && rng.next() < dt_s / 2400.0 {
+            state.spike = Some((rng.range(1200.0, 2200.0), at + Duration::from_secs_f64(rng.range(60.0, 600.0))));
+        }
+        let bump = |center: f64, width: f64| (-((hour - center) / width).powi(2)).exp();
+        state.load_w = 220.0 + 250.0 * bump(7.5, 1.0) + 450.0 * bump(19.5, 1.5) + rng.range(-30.0, 30.0) + state.spike.map_or(0.0, |(w, _)| w);
+
+        if let Mode::Passive { until, previous, .. } = &state.mode {
+            if *until <= at {
+                state.mode = (**previous).clone();
+            }
+        }
+        // > 0 en charge, comme bat_power
+        let target = match &state.mode {
+            Mode::Auto | Mode::Ai => state.pv_w - state.load_w,
+            Mode::Manual => -(self.active_slot(state, unix).unwrap_or(0) as f64),
+            Mode::Passive { power, .. } => -(*power as f64),
+        };
+        let mut bat = target.clamp(-MAX_POWER_W, MAX_POWER_W);
+        if (bat > 0.0 && state.soc >= 100.0) || (bat < 0.0 && state.soc <= MIN_SOC) {
+            bat = 0.0;
+        }
+        state.bat_w = bat;
+
+        let hours = dt_s / 3600.0;
+        state.soc = (state.soc + bat * hours / CAPACITY_WH * 100.0).clamp(0.0, 100.0);
+        state.pv_wh += state.pv_w * hours;
+        state.load_wh += state.load_w * hours;
+        if bat > 0.0 {
+            state.input_wh += bat * hours;
+        } else {
+            state.output_wh -= bat * hours;
+        }
+        state.temp += (18.0 + 10.0 * bat.abs() / MAX_POWER_W - state.temp) * (dt_s / 900.0).min(1.0);
+
+        if state.fault.is_some_and(|(_, until)| until <= at) {
+            state.fault = None;
+        }
+        if self.faults && state.fault.is_none() && state.rng.next() < dt_s / MEAN_FAULT_INTERVAL_S {
+            let fault = [Fault::Unresponsive, Fault::Busy, Fault::CtLost][(state.rng.next() * 3.0) as usize % 3];
+            let duration = Duration::from_secs_f64(state.rng.range(FAULT_MIN_S, FAULT_MAX_S));
+            tracing::debug!(?fault, "simulated fault for {} s", duration.as_secs());
+            state.fault = Some((fault, at + duration));
+        }
+    }
+
+    fn grid_w(state: &State) -> f64 {
+        state.load_w - state.pv_w + state.bat_w
+    }
+
+    fn respond(&self, request: &serde_json::Value) -> Option<serde_json::Value> {
+        let id = request.get("id").cloned().unwrap_or(serde_json::Value::Null);
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or_default();
+        let params = request.get("params").cloned().unwrap_or(serde_json::Value::Null);
+        let now = Instant::now();
+        let mut state = self.lock();
+        if state.offline_until.is_some_and(|until| until > now) {
+            return None;
+        }
+        state.offline_until = None;
+        self.advance(&mut state, now);
+        let fault = state.fault.map(|(fault, _)| fault);
+        if fault == Some(Fault::Unresponsive) {
+            return None;
+        }
+        let result = if fault == Some(Fault::Busy) && method.contains(".Get") && method != methods::GET_DEVICE {
+            Err((-32603, "device busy".to_string()))
+        } else {
+            self.dispatch(&mut state, method, &params, fault, now)
+        };
+        Some(match result {
+            Ok(result) => serde_json::json!({"id": id, "src": format!("{}-{}", MODEL, self.ble_mac), "result": result}),
+            Err((code, message)) => serde_json::json!({"id": id, "error": {"code": code, "message": message}}),
+        })
+    }
+
+    fn dispatch(&self, state: &mut State, method: &str, params: &serde_json::Value, fault: Option<Fault>, now: Instant) -> Result<serde_json::Value, (i64, String)> {
+        let round = |w: f64| w.round() as i64;
+        let soc = state.soc.round() as u32;
+        let result = match method {
+            methods::GET_DEVICE => serde_json::json!({
+                "device": MODEL,
+                "ver": state.firmware,
+                "ble_mac": self.ble_mac,
+                "wifi_mac": self.wifi_mac,
+                "wifi_name": state.ssid,
+                "ip": "127.0.0.1",
+            }),
+            methods::WIFI_GET_STATUS => serde_json::json!({
+                "id": 0,
+                "ssid": state.ssid,
+                "rssi": round(-58.0 + state.rng.range(-6.0, 6.0)),
+                "sta_ip": "127.0.0.1",
+            }),
+            methods::BAT_GET_STATUS => serde_json::json!({
+                "id": 0,
+                "soc": soc,
+                "charg_flag": state.soc < 100.0,
+                "dischrg_flag": state.soc > MIN_SOC,
+                "bat_temp": (state.temp * 10.0).round() / 10.0,
+                "bat_capacity": round(CAPACITY_WH * state.soc / 100.0),
+                "rated_capacity": CAPACITY_WH,
+            }),
+            methods::ES_GET_STATUS => serde_json::json!({
+                "id": 0,
+                "bat_soc": soc,
+                "bat_cap": CAPACITY_WH,
+                "pv_power": round(state.pv_w),
+                "ongrid_power": round(-state.bat_w),
+                "offgrid_power": 0,
+                "bat_power": round(state.bat_w),
+                "total_pv_energy": round(state.pv_wh),
+                "total_grid_output_energy": round(state.output_wh),
+                "total_grid_input_energy": round(state.input_wh),
+                "total_load_energy": round(state.load_wh),
+                "grid_voltage": (2300.0 + state.rng.range(-40.0, 40.0)).round() / 10.0,
+                "grid_frequency": (5000.0 + state.rng.range(-5.0, 5.0)).round() / 100.0,
+            }),
+            methods::ES_GET_MODE => {
+                let mut result = serde_json::json!({
+                    "id": 0,
+                    "mode": state.mode.name(),
+                    "ongrid_power": round(-state.bat_w),
+                    "offgrid_power": 0,
+                    "bat_soc": soc,
+                });
+                if matches!(state.mode, Mode::Manual) {
+                    result["manual_cfg"] = serde_json::json!(state.manual);
+                }
+                result
+            }
+            methods::ES_SET_MODE => {
+                let config = params.get("config").ok_or((-32602, "missing config".to_string()))?;
+                let mode = config.get("mode").and_then(|m| m.as_str()).unwrap_or_default();
+                let request = ModeRequest::parse(mode, Some(config)).map_err(|e| (-32602, e.to_string()))?;
+                let previous = match &state.mode {
+                    Mode::Passive { previous, .. } => (**previous).clone(),
+                    other => other.clone(),
+                };
+                state.events.push((unix_now(), 1, format!("Mode set to {}", mode)));
+                state.mode = match request {
+                    ModeRequest::Auto => Mode::Auto,
+                    ModeRequest::Ai => Mode::Ai,
+                    ModeRequest::Manual(slot) => {
+                        state.manual.retain(|s| s.time_num != slot.time_num);
+                        state.manual.push(slot);
+                        state.manual.sort_by_key(|s| s.time_num);
+                        Mode::Manual
+                    }
+                    ModeRequest::Passive(passive) => Mode::Passive {
+                        power: passive.power,
+                        until: now + Duration::from_secs(u64::from(passive.cd_time)),
+                        previous: Box::new(previous),
+                    },
+                };
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            methods::EM_GET_STATUS => {
+                if fault == Some(Fault::CtLost) {
+                    serde_json::json!({"id": 0, "ct_state": 0, "a_power": 0, "b_power": 0, "c_power": 0, "total_power": 0})
+                } else {
+                    let grid = Self::grid_w(state);
+                    // Répartition inégale entre phases, comme dans une maison réelle
+                    let (a, b) = (round(grid * 0.5), round(grid * 0.3));
+                    let c = round(grid) - a - b;
+                    let volts = [0, 1, 2].map(|_| (2300.0 + state.rng.range(-40.0, 40.0)).round() / 10.0);
+                    let amps = |power: i64, volts: f64| (power.abs() as f64 / volts * 100.0).round() / 100.0;
+                    serde_json::json!({
+                        "id": 0, "ct_state": 1, "a_power": a, "b_power": b, "c_power": c, "total_power": round(grid),
+                        "a_voltage": volts[0], "b_voltage": volts[1], "c_voltage": volts[2],
+                        "a_current": amps(a, volts[0]), "b_current": amps(b, volts[1]), "c_current": amps(c, volts[2]),
+                    })
+                }
+            }
+            methods::GET_TIME => {
+                let timestamp = unix_now() + state.clock_offset_s;
+                serde_json::json!({"id": 0, "timestamp": timestamp})
+            }
+            methods::SET_TIME => {
+                let timestamp = params.get("timestamp").and_then(|t| t.as_i64()).ok_or((-32602, "missing timestamp".to_string()))?;
+                state.clock_offset_s = timestamp - unix_now();
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            methods::EM_GET_CONFIG => serde_json::json!({"id": 0, "meter_mode": state.meter_mode, "ct_type": "CT002", "ct_mac": self.wifi_mac, "pair_state": 2}),
+            methods::EM_SET_CONFIG => {
+                let config = params.get("config").ok_or((-32602, "missing config".to_string()))?;
+                if let Some(mode) = config.get("meter_mode") {
+                    state.meter_mode = mode.as_u64().filter(|m| *m <= 1).ok_or((-32602, "meter_mode must be 0 or 1".to_string()))?;
+                }
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            methods::GET_EVENTS => {
+                let page = params.get("page").and_then(|p| p.as_u64()).unwrap_or(0) as usize;
+                let events: Vec<serde_json::Value> = state
+                    .events
+                    .iter()
+                    .rev()
+                    .skip(page * EVENTS_PER_PAGE)
+                    .take(EVENTS_PER_PAGE)
+                    .map(|(ts, code, msg)| serde_json::json!({"ts": ts, "code": code, "msg": msg}))
+                    .collect();
+                serde_json::json!({"id": 0, "page": page, "total_pages": state.events.len().div_ceil(EVENTS_PER_PAGE), "events": events})
+            }
+            methods::ES_GET_BACKUP => serde_json::json!({"id": 0, "enable": u8::from(state.backup)}),
+            methods::ES_SET_BACKUP => {
+                let enable = params.get("enable").and_then(|e| e.as_u64()).filter(|e| *e <= 1).ok_or((-32602, "enable must be 0 or 1".to_string()))?;
+                state.backup = enable == 1;
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            methods::WIFI_SET_CONFIG => {
+                let ssid = params.pointer("/config/ssid").and_then(|s| s.as_str()).ok_or((-32602, "missing ssid".to_string()))?;
+                state.ssid = ssid.to_string();
+                state.offline_until = Some(now + REBOOT_DOWNTIME);
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            methods::REBOOT => {
+                state.events.push((unix_now(), 2, "Reboot requested".to_string()));
+                state.offline_until = Some(now + REBOOT_DOWNTIME);
+                serde_json::json!({"id": 0})
+            }
+            methods::OTA_UPDATE => {
+                state.firmware += 1;
+                state.events.push((unix_now(), 3, format!("Firmware updated to {}", state.firmware)));
+                state.offline_until = Some(now + UPDATE_DOWNTIME);
+                serde_json::json!({"id": 0, "set_result": true})
+            }
+            _ => return Err((-32601, format!("{} is not simulated", method))),
+        };
+        Ok(result)
+    }
+}
+
+impl Responder for Simulator {
+    fn handle(&self, request: &serde_json::Value) -> Option<serde_json::Value> {
+        self.respond(request)
+    }
+}
+
+// Simulateur (ou rejeu) à l'écoute sur la boucle locale ; arrêté quand le handle est abandonné
+pub struct SimulatorHandle {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl SimulatorHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for SimulatorHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// port : 0 pour un port éphémère
+pub fn serve(responder: Arc<dyn Responder>, port: u16) -> std::io::Result<SimulatorHandle> {
+    let socket = UdpSocket::bind(("127.0.0.1", port))?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let addr = socket.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+    std::thread::Builder::new().name(format!("simulator-{}", addr.port())).spawn(move || {
+        let mut buf = vec![0u8; 65_536];
+        while !stopped.load(Ordering::Relaxed) {
+            let Ok((len, from)) = socket.recv_from(&mut buf) else { continue };
+            let Ok(request) = serde_json::from_slice::<serde_json::Value>(&buf[..len]) else { continue };
+            if let Some(response) = responder.handle(&request) {
+                if let Err(e) = serde_json::to_vec(&response).map(|bytes| socket.send_to(&bytes, from)) {
+                    tracing::warn!("simulator reply failed: {}", e);
+                }
+            }
+        }
+    })?;
+    Ok(SimulatorHandle { addr, stop })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::UdpTransport;
+    use crate::types::EventPage;
+
+    fn call(simulator: &Simulator, method: &str, params: serde_json::Value) -> serde_json::Value {
+        simulator.handle(&serde_json::json!({"id": 1, "method": method, "params": params})).unwrap()
+    }
+
+    #[test]
+    fn passive_discharge_lowers_soc_then_reverts() {
+        let simulator = Simulator::new(7, 0).without_faults();
+        let params = ModeRequest::parse("Passive", Some(&serde_json::json!({"passive_cfg": {"power": 2000, "cd_time": 3600}}))).unwrap().params();
+        assert_eq!(call(&simulator, methods::ES_SET_MODE, params)["result"]["set_result"], true);
+        let before = simulator.lock().soc;
+        {
+            let mut state = simulator.lock();
+            state.last -= Duration::from_secs(1800);
+            simulator.advance(&mut state, Instant::now());
+        }
+        let after = simulator.lock().soc;
+        assert!((before - after - 2000.0 * 0.5 / CAPACITY_WH * 100.0).abs() < 0.1);
+        assert_eq!(call(&simulator, methods::ES_GET_MODE, serde_json::Value::Null)["result"]["mode"], "Passive");
+        let status = call(&simulator, methods::ES_GET_STATUS, serde_json::Value::Null);
+        assert!(status["result"]["bat_power"].as_i64().unwrap() <= 0);
+        assert_eq!(call(&simulator, "Foo.Bar", serde_json::Value::Null)["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn meter_balances_flows() {
+        let simulator = Simulator::new(3, 3600).without_faults();
+        simulator.lock().last -= Duration::from_secs(600);
+        let energy = call(&simulator, methods::ES_GET_STATUS, serde_json::Value::Null)["result"].clone();
+        let meter = call(&simulator, methods::EM_GET_STATUS, serde_json::Value::Null)["result"].clone();
+        let state = simulator.lock();
+        assert_eq!(meter["total_power"].as_i64().unwrap(), Simulator::grid_w(&state).round() as i64);
+        assert_eq!(energy["bat_power"].as_i64().unwrap(), state.bat_w.round() as i64);
+    }
+
+    #[test]
+    fn event_log_is_paged_newest_first() {
+        let simulator = Simulator::new(9, 0).without_faults();
+        let params = ModeRequest::parse("AI", None).unwrap().params();
+        for _ in 0..12 {
+            call(&simulator, methods::ES_SET_MODE, params.clone());
+        }
+        call(&simulator, methods::OTA_UPDATE, serde_json::Value::Null);
+        simulator.lock().offline_until = None;
+        let first: EventPage = serde_json::from_value(call(&simulator, methods::GET_EVENTS, methods::events_params(0))["result"].clone()).unwrap();
+        assert_eq!(first.total_pages, Some(2));
+        assert_eq!(first.events.len(), EVENTS_PER_PAGE);
+        assert_eq!(first.events[0].code, Some(3));
+        let second: EventPage = serde_json::from_value(call(&simulator, methods::GET_EVENTS, methods::events_params(1))["result"].clone()).unwrap();
+        assert_eq!(second.events.len(), 3);
+    }
+
+    #[test]
+    fn backup_output_toggles() {
+        let simulator = Simulator::new(5, 0).without_faults();
+        assert_eq!(call(&simulator, methods::ES_GET_BACKUP, serde_json::Value::Null)["result"]["enable"], 1);
+        assert_eq!(call(&simulator, methods::ES_SET_BACKUP, methods::backup_params(false))["result"]["set_result"], true);
+        assert_eq!(call(&simulator, methods::ES_GET_BACKUP, serde_json::Value::Null)["result"]["enable"], 0);
+        assert_eq!(call(&simulator, methods::ES_SET_BACKUP, serde_json::json!({"id": 0, "enable": 2}))["error"]["code"], -32602);
+    }
+
+    #[tokio::test]
+    async fn answers_over_udp() {
+        let handle = serve(Arc::new(Simulator::new(11, 0).without_faults()), 0).unwrap();
+        let transport = UdpTransport::default();
+        let result = transport
+            .request(0, &handle.addr().to_string(), methods::GET_DEVICE, methods::probe_params(), Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(result["device"], MODEL);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Default)]
+struct Counters {
+    requests: u64,
+    replies: u64,
+    timeouts: u64,
+    retries: u64,
+    rtt_total: Duration,
+    rtt_min: Option<Duration>,
+    rtt_max: Duration,
+    rtt_last: Option<Duration>,
+}
+
+// Qualité du lien avec un appareil, depuis le démarrage
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LinkStats {
+    pub requests: u64,
+    pub replies: u64,
+    pub timeouts: u64,
+    // Nouvelles tentatives après un échec (DeviceClient)
+    pub retries: u64,
+    // Part des requêtes restées sans réponse, [%]
+    pub loss_percent: f64,
+    pub rtt_avg_ms: Option<f64>,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    pub rtt_last_ms: Option<f64>,
+}
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl From<&Counters> for LinkStats {
+    fn from(c: &Counters) -> Self {
+        LinkStats {
+            requests: c.requests,
+            replies: c.replies,
+            timeouts: c.timeouts,
+            retries: c.retries,
+            loss_percent: if c.requests > 0 { c.timeouts as f64 * 100.0 / c.requests as f64 } else { 0.0 },
+            rtt_avg_ms: (c.replies > 0).then(|| as_ms(c.rtt_total) / c.replies as f64),
+            rtt_min_ms: c.rtt_min.map(as_ms),
+            rtt_max_ms: (c.replies > 0).then(|| as_ms(c.rtt_max)),
+            rtt_last_ms: c.rtt_last.map(as_ms),
+        }
+    }
+}
+
+// Compteurs par adresse d'appareil, tenus par le transport à chaque requête
+#[derive(Default)]
+pub struct LinkMonitor {
+    links: Mutex<HashMap<IpAddr, Counters>>,
+    // Cible "hôte:port" -> adresse obtenue à la dernière requête (nom DNS compris)
+    resolved: Mutex<HashMap<String, IpAddr>>,
+}
+
+impl LinkMonitor {
+    fn update(&self, ip: IpAddr, f: impl FnOnce(&mut Counters)) {
+        f(self.links.lock().unwrap_or_else(|e| e.into_inner()).entry(ip).or_default());
+    }
+
+    pub(crate) fn resolved(&self, target: &str, ip: IpAddr) {
+        self.resolved.lock().unwrap_or_else(|e| e.into_inner()).insert(target.to_string(), ip);
+    }
+
+    // Adresse à laquelle la cible a été jointe, sans nouvelle résolution ; None avant toute requête
+    pub fn address(&self, target: &str) -> Option<IpAddr> {
+        self.resolved.lock().unwrap_or_else(|e| e.into_inner()).get(target).copied()
+    }
+
+    pub(crate) fn request(&self, ip: IpAddr) {
+        self.update(ip, |c| c.requests += 1);
+    }
+
+    pub(crate) fn reply(&self, ip: IpAddr, rtt: Duration) {
+        self.update(ip, |c| {
+            c.replies += 1;
+            c.rtt_total += rtt;
+            c.rtt_min = Some(c.rtt_min.map_or(rtt, |min| min.min(rtt)));
+            c.rtt_max = c.rtt_max.max(rtt);
+            c.rtt_last = Some(rtt);
+        });
+    }
+
+    pub(crate) fn timeout(&self, ip: IpAddr) {
+        self.update(ip, |c| c.timeouts += 1);
+    }
+
+    pub fn retry(&self, ip: IpAddr) {
+        self.update(ip, |c| c.retries += 1);
+    }
+
+    pub fn get(&self, ip: IpAddr) -> LinkStats {
+        self.links.lock().unwrap_or_else(|e| e.into_inner()).get(&ip).map(LinkStats::from).unwrap_or_default()
+    }
+
+    pub fn all(&self) -> HashMap<IpAddr, LinkStats> {
+        self.links.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(ip, c)| (*ip, LinkStats::from(c))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_and_rtt() {
+        let monitor = LinkMonitor::default();
+        let ip: IpAddr = "192.168.1.20".parse().unwrap();
+        for rtt in [40, 60] {
+            monitor.request(ip);
+            monitor.reply(ip, Duration::from_millis(rtt));
+        }
+        monitor.request(ip);
+        monitor.timeout(ip);
+        monitor.retry(ip);
+        let stats = monitor.get(ip);
+        assert_eq!((stats.requests, stats.replies, stats.timeouts, stats.retries), (3, 2, 1, 1));
+        assert!((stats.loss_percent - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.rtt_avg_ms, Some(50.0));
+        assert_eq!(stats.rtt_min_ms, Some(40.0));
+        assert_eq!(stats.rtt_last_ms, Some(60.0));
+        assert_eq!(monitor.get("10.0.0.1".parse().unwrap()), LinkStats::default());
+    }
+}
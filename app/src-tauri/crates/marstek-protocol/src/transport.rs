@@ -0,0 +1,482 @@
+use crate::capture::TrafficLog;
+use crate::error::Error;
+use crate::stats::LinkMonitor;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+// Plus grand datagramme UDP possible : rien n'est jamais tronqué à la réception
+const RECV_BUFFER_SIZE: usize = 65_536;
+// Réponse découpée en plusieurs datagrammes (plannings complets, journaux) : taille et délai max de reconstitution
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+struct ApiRequest<'a> {
+    id: u32,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+pub type Reply = (SocketAddr, serde_json::Value);
+
+// Sonde de découverte : destination, et adresse locale de l'interface d'émission
+// (absente : socket principal, interface choisie par le routage)
+#[derive(Clone, Copy, Debug)]
+pub struct Probe {
+    pub from: Option<IpAddr>,
+    pub to: SocketAddr,
+}
+
+// Cible "hôte:port" pour request : IPv6 entre crochets, zone numérique ("fe80::1%3") conservée,
+// nom d'hôte laissé à la résolution
+pub fn endpoint(host: &str, port: u16) -> String {
+    let host = host.trim();
+    let bare = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    if let Ok(ip) = bare.parse::<IpAddr>() {
+        return SocketAddr::new(ip, port).to_string();
+    }
+    if let Some((ip, zone)) = bare.split_once('%') {
+        if let (Ok(ip), Ok(scope)) = (ip.parse::<Ipv6Addr>(), zone.parse::<u32>()) {
+            return SocketAddrV6::new(ip, port, 0, scope).to_string();
+        }
+    }
+    format!("{}:{}", host, port)
+}
+
+// Adresse d'un répondeur telle qu'on l'enregistre : une IPv6 lien-local garde sa zone
+pub fn host(addr: &SocketAddr) -> String {
+    match addr {
+        SocketAddr::V6(v6) if v6.scope_id() != 0 => format!("{}%{}", v6.ip(), v6.scope_id()),
+        _ => addr.ip().to_string(),
+    }
+}
+
+// Codes d'erreur JSON-RPC documentés par l'API Open Marstek
+fn rpc_reason(code: i64) -> &'static str {
+    match code {
+        -32700 => "Parse error: the device could not parse the request",
+        -32600 => "Invalid request",
+        -32601 => "Method not found: not available on this model or firmware",
+        -32602 => "Invalid params",
+        -32603 => "Internal device error",
+        -32099..=-32000 => "Device error",
+        _ => "Unknown device error",
+    }
+}
+
+// Réponse complète -> membre result (Null s'il est absent), ou l'objet error converti
+pub fn parse_response(method: &str, response: serde_json::Value) -> Result<serde_json::Value, Error> {
+    if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+        return Err(rpc_error(method, error));
+    }
+    Ok(response.get("result").cloned().unwrap_or(serde_json::Value::Null))
+}
+
+// Membre error d'une réponse : { "code": -32601, "message": "...", "data": ... }
+fn rpc_error(method: &str, error: &serde_json::Value) -> Error {
+    let code = error.get("code").and_then(|v| v.as_i64()).unwrap_or_default();
+    let mut message = format!("{} ({})", rpc_reason(code), method);
+    if let Some(detail) = error.get("message").and_then(|v| v.as_str()).filter(|m| !m.is_empty()) {
+        message.push_str(": ");
+        message.push_str(detail);
+    }
+    if let Some(data) = error.get("data").filter(|d| !d.is_null()) {
+        message.push_str(&format!(" [{}]", data));
+    }
+    Error::Rejected { code, message }
+}
+
+struct Pending {
+    // None pour une diffusion : toutes les adresses sont acceptées
+    from: Option<IpAddr>,
+    replies: mpsc::UnboundedSender<Reply>,
+}
+
+// Requête JSON-RPC telle qu'envoyée sur le réseau (UDP ou BLE)
+pub fn encode_request(id: u32, method: &str, params: serde_json::Value) -> Result<Vec<u8>, Error> {
+    Ok(serde_json::to_vec(&ApiRequest { id, method, params })?)
+}
+
+type PendingMap = Arc<Mutex<HashMap<u32, Pending>>>;
+
+// Retire la requête de la table quand le future est abandonné (timeout, annulation)
+struct PendingGuard {
+    id: u32,
+    pending: PendingMap,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+    }
+}
+
+struct BoundSocket {
+    local_port: u16,
+    socket: Arc<UdpSocket>,
+    receiver: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for BoundSocket {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+// Un seul socket partagé par toutes les requêtes ; les réponses sont aiguillées par id JSON-RPC.
+// Les méthodes s'exécutent dans un runtime Tokio (la tâche de réception y est lancée).
+pub struct UdpTransport {
+    bound: tokio::sync::Mutex<Option<BoundSocket>>,
+    // Socket IPv6 distinct, ouvert au premier échange avec une adresse IPv6
+    bound_v6: tokio::sync::Mutex<Option<BoundSocket>>,
+    pending: PendingMap,
+    next_id: AtomicU32,
+    // Latence et pertes des requêtes unicast, par appareil
+    pub links: LinkMonitor,
+    // Derniers échanges unicast, pour les rapports de diagnostic
+    pub traffic: TrafficLog,
+}
+
+impl Default for UdpTransport {
+    fn default() -> Self {
+        Self {
+            bound: tokio::sync::Mutex::new(None),
+            bound_v6: tokio::sync::Mutex::new(None),
+            pending: PendingMap::default(),
+            next_id: AtomicU32::new(1),
+            links: LinkMonitor::default(),
+            traffic: TrafficLog::default(),
+        }
+    }
+}
+
+// Datagrammes d'une même adresse accumulés jusqu'à former un document JSON complet
+#[derive(Default)]
+struct Reassembler {
+    partial: HashMap<SocketAddr, (Vec<u8>, Instant)>,
+}
+
+impl Reassembler {
+    fn push(&mut self, from: SocketAddr, data: &[u8]) -> Option<serde_json::Value> {
+        self.partial.retain(|_, (_, at)| at.elapsed() < REASSEMBLY_TIMEOUT);
+        if let Some((mut bytes, _)) = self.partial.remove(&from) {
+            bytes.extend_from_slice(data);
+            match self.parse(from, bytes) {
+                Some(response) => return Some(response),
+                // Suite illisible : le datagramme commence peut-être une nouvelle réponse
+                None if self.partial.contains_key(&from) => return None,
+                None => {}
+            }
+        }
+        self.parse(from, data.to_vec())
+    }
+
+    fn parse(&mut self, from: SocketAddr, bytes: Vec<u8>) -> Option<serde_json::Value> {
+        match serde_json::from_slice(&bytes) {
+            Ok(response) => Some(response),
+            Err(e) if e.is_eof() && bytes.len() < MAX_RESPONSE_SIZE => {
+                self.partial.insert(from, (bytes, Instant::now()));
+                None
+            }
+            Err(_) => {
+                tracing::debug!(%from, len = bytes.len(), "ignoring non-JSON datagram");
+                None
+            }
+        }
+    }
+}
+
+async fn receive_loop(socket: Arc<UdpSocket>, pending: PendingMap) {
+    let mut buf = vec![0u8; RECV_BUFFER_SIZE];
+    let mut reassembler = Reassembler::default();
+    loop {
+        // Windows remonte les ICMP "port unreachable" comme erreurs de réception : on les ignore
+        let Ok((len, from)) = socket.recv_from(&mut buf).await else { continue };
+        let Some(response) = reassembler.push(from, &buf[..len]) else { continue };
+        let pending = pending.lock().unwrap_or_else(|e| e.into_inner());
+        let target = match response.get("id").and_then(|v| v.as_u64()) {
+            Some(id) => pending.get(&(id as u32)),
+            // Réponse sans id : acceptée seulement si une seule requête attend cette adresse,
+            // sinon impossible de savoir à laquelle elle répond
+            None => {
+                let mut candidates = pending.values().filter(|p| p.from == Some(from.ip()));
+                match (candidates.next(), candidates.next()) {
+                    (Some(p), None) => Some(p),
+                    _ => None,
+                }
+            }
+        };
+        match target {
+            Some(p) if p.from.is_none_or(|ip| ip == from.ip()) => {
+                let _ = p.replies.send((from, response));
+            }
+            // Id inconnu : réponse tardive d'une requête expirée ou abandonnée
+            _ => tracing::debug!(%from, id = ?response.get("id"), "dropping stale or unsolicited response"),
+        }
+    }
+}
+
+// Some Marstek firmwares only answer when source port = destination port (30000)
+fn bind(ip: IpAddr, local_port: u16) -> std::io::Result<std::net::UdpSocket> {
+    std::net::UdpSocket::bind((ip, local_port)).or_else(|e| {
+        // Port occupé (autre instance, Home Assistant, socket principal pour un socket d'interface...) :
+        // repli sur un port éphémère
+        tracing::debug!("cannot bind local port {} on {}: {}, using an ephemeral port", local_port, ip, e);
+        std::net::UdpSocket::bind((ip, 0))
+    })
+}
+
+fn unspecified(ipv6: bool) -> IpAddr {
+    if ipv6 {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    }
+}
+
+impl UdpTransport {
+    async fn socket(&self, local_port: u16, ipv6: bool) -> Result<Arc<UdpSocket>, Error> {
+        let mut bound = if ipv6 { self.bound_v6.lock().await } else { self.bound.lock().await };
+        if let Some(b) = bound.as_ref().filter(|b| b.local_port == local_port) {
+            return Ok(Arc::clone(&b.socket));
+        }
+        // Premier appel, ou port local modifié dans les réglages
+        *bound = None;
+        let opened = self.open(unspecified(ipv6), local_port)?;
+        let socket = Arc::clone(&opened.socket);
+        *bound = Some(opened);
+        Ok(socket)
+    }
+
+    // Socket et sa tâche de réception, qui aiguille vers la même table de requêtes que les autres
+    fn open(&self, ip: IpAddr, local_port: u16) -> Result<BoundSocket, Error> {
+        let std_socket = bind(ip, local_port)?;
+        // Attendu pour un socket d'interface : le port est déjà pris par le socket principal
+        if local_port != 0 && std_socket.local_addr()?.port() != local_port && ip.is_unspecified() {
+            tracing::warn!("cannot bind local port {} on {}, using an ephemeral port", local_port, ip);
+        }
+        std_socket.set_nonblocking(true)?;
+        // Pas de diffusion en IPv6 : la découverte y passe par le multicast
+        if ip.is_ipv4() {
+            std_socket.set_broadcast(true)?;
+        }
+        let socket = Arc::new(UdpSocket::from_std(std_socket)?);
+        let receiver = tokio::spawn(receive_loop(Arc::clone(&socket), Arc::clone(&self.pending)));
+        Ok(BoundSocket { local_port, socket, receiver })
+    }
+
+    // Ferme les sockets : les suivants sont recréés au prochain échange (sortie de veille, changement de réseau)
+    pub async fn reset(&self) {
+        *self.bound.lock().await = None;
+        *self.bound_v6.lock().await = None;
+    }
+
+    fn register(&self, from: Option<IpAddr>) -> (u32, PendingGuard, mpsc::UnboundedReceiver<Reply>) {
+        let (replies, receiver) = mpsc::unbounded_channel();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let mut id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        while id == 0 || pending.contains_key(&id) {
+            id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        }
+        pending.insert(id, Pending { from, replies });
+        (id, PendingGuard { id, pending: Arc::clone(&self.pending) }, receiver)
+    }
+
+    async fn send(&self, socket: &UdpSocket, addr: SocketAddr, id: u32, method: &str, params: serde_json::Value) -> Result<(), Error> {
+        let message = encode_request(id, method, params)?;
+        socket.send_to(&message, addr).await?;
+        Ok(())
+    }
+
+    // target : "ip:port" ou "hôte:port" ; renvoie le membre result (Null s'il est absent)
+    pub async fn request(&self, local_port: u16, target: &str, method: &str, params: serde_json::Value, timeout: Duration) -> Result<serde_json::Value, Error> {
+        let response = self.request_raw(local_port, target, method, params, timeout).await?;
+        parse_response(method, response)
+    }
+
+    // Réponse complète, objet error compris
+    pub async fn request_raw(&self, local_port: u16, target: &str, method: &str, params: serde_json::Value, timeout: Duration) -> Result<serde_json::Value, Error> {
+        let addr = tokio::net::lookup_host(target)
+            .await?
+            .next()
+            .ok_or_else(|| Error::Io(format!("Cannot resolve {}", target)))?;
+        let socket = self.socket(local_port, addr.is_ipv6()).await?;
+        let (id, _guard, mut replies) = self.register(Some(addr.ip()));
+        let request = serde_json::json!({ "id": id, "method": method, "params": params.clone() });
+        self.send(&socket, addr, id, method, params).await?;
+        self.links.resolved(target, addr.ip());
+        self.links.request(addr.ip());
+
+        let sent_at = SystemTime::now();
+        let sent = Instant::now();
+        let received = tokio::time::timeout(timeout, replies.recv())
+            .await
+            .map_err(|_| {
+                self.links.timeout(addr.ip());
+                Error::Timeout(format!("{} timed out after {} ms", method, timeout.as_millis()))
+            })
+            .and_then(|reply| reply.ok_or_else(|| Error::Io("Transport closed".to_string())));
+        let outcome = received.as_ref().map(|(_, response)| (response, sent.elapsed())).map_err(|e| e.to_string());
+        self.traffic.record(addr.to_string(), method, request, sent_at, outcome);
+        let (_, response) = received?;
+        self.links.reply(addr.ip(), sent.elapsed());
+        Ok(response)
+    }
+
+    // Envoie chaque sonde (diffusion IPv4 ou multicast IPv6) sous le même id, toutes en même temps,
+    // et collecte toutes les réponses reçues pendant `window`.
+    // Une sonde avec interface part d'un socket lié à son adresse, fermé à la fin de la fenêtre.
+    pub async fn broadcast(&self, local_port: u16, probes: &[Probe], method: &str, params: serde_json::Value, window: Duration) -> Result<Vec<Reply>, Error> {
+        let (id, _guard, mut replies) = self.register(None);
+        // Les appareils qui répondent toujours au port 30000 visent le socket principal : il doit écouter
+        let _ = self.socket(local_port, false).await;
+        let mut interface_sockets: Vec<(IpAddr, BoundSocket)> = Vec::new();
+        for probe in probes {
+            let socket = match probe.from {
+                None => self.socket(local_port, probe.to.is_ipv6()).await,
+                Some(from) => match interface_sockets.iter().find(|(ip, _)| *ip == from) {
+                    Some((_, bound)) => Ok(Arc::clone(&bound.socket)),
+                    None => self.open(from, local_port).map(|bound| {
+                        let socket = Arc::clone(&bound.socket);
+                        interface_sockets.push((from, bound));
+                        socket
+                    }),
+                },
+            };
+            let sent = match socket {
+                Ok(socket) => self.send(&socket, probe.to, id, method, params.clone()).await,
+                Err(e) => Err(e),
+            };
+            // Une interface qui refuse l'envoi ne doit pas priver les autres de la sonde
+            if let Err(e) = sent {
+                if probes.len() == 1 {
+                    return Err(e);
+                }
+                tracing::warn!(to = %probe.to, from = ?probe.from, "discovery broadcast failed: {}", e);
+            }
+        }
+
+        let mut collected = Vec::new();
+        let deadline = tokio::time::Instant::now() + window;
+        while let Ok(Some(reply)) = tokio::time::timeout_at(deadline, replies.recv()).await {
+            collected.push(reply);
+        }
+        Ok(collected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Appareil simulé sur la boucle locale : répond à chaque requête avec `reply(request)`
+    fn fake_device(reply: impl Fn(serde_json::Value) -> Option<serde_json::Value> + Send + 'static) -> String {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; RECV_BUFFER_SIZE];
+            while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                let request: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+                if let Some(response) = reply(request) {
+                    socket.send_to(&serde_json::to_vec(&response).unwrap(), from).unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn rpc_error_message() {
+        let error = rpc_error("ES.SetMode", &serde_json::json!({"code": -32601, "message": "nope", "data": 3}));
+        match error {
+            Error::Rejected { code, message } => {
+                assert_eq!(code, -32601);
+                assert_eq!(message, "Method not found: not available on this model or firmware (ES.SetMode): nope [3]");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn request_returns_result() {
+        let addr = fake_device(|request| Some(serde_json::json!({"id": request["id"], "result": {"soc": 55}})));
+        let transport = UdpTransport::default();
+        let result = transport.request(0, &addr, "Bat.GetStatus", serde_json::json!({"id": 0}), Duration::from_secs(2)).await.unwrap();
+        assert_eq!(result, serde_json::json!({"soc": 55}));
+    }
+
+    #[tokio::test]
+    async fn request_surfaces_device_errors() {
+        let addr = fake_device(|request| Some(serde_json::json!({"id": request["id"], "error": {"code": -32602}})));
+        let transport = UdpTransport::default();
+        let result = transport.request(0, &addr, "ES.SetMode", serde_json::Value::Null, Duration::from_secs(2)).await;
+        assert!(matches!(result, Err(Error::Rejected { code: -32602, .. })));
+    }
+
+    #[tokio::test]
+    async fn request_times_out_without_reply() {
+        let addr = fake_device(|_| None);
+        let transport = UdpTransport::default();
+        let result = transport.request(0, &addr, "ES.GetMode", serde_json::Value::Null, Duration::from_millis(100)).await;
+        assert!(matches!(result, Err(Error::Timeout(_))));
+        let stats = transport.links.get("127.0.0.1".parse().unwrap());
+        assert_eq!((stats.requests, stats.timeouts), (1, 1));
+        assert_eq!(transport.links.address(&addr), Some("127.0.0.1".parse().unwrap()));
+        assert_eq!(transport.links.address("venus.lan:30000"), None);
+    }
+
+    #[test]
+    fn endpoints_bracket_ipv6() {
+        assert_eq!(endpoint("192.168.1.20", 30000), "192.168.1.20:30000");
+        assert_eq!(endpoint("2001:db8::20", 30000), "[2001:db8::20]:30000");
+        assert_eq!(endpoint("[2001:db8::20]", 30000), "[2001:db8::20]:30000");
+        assert_eq!(endpoint("fe80::1%3", 30000), "[fe80::1%3]:30000");
+        assert_eq!(endpoint("venus.lan", 30000), "venus.lan:30000");
+        let scoped: SocketAddr = "[fe80::1%3]:30000".parse().unwrap();
+        assert_eq!(host(&scoped), "fe80::1%3");
+        assert_eq!(host(&"192.168.1.20:30000".parse().unwrap()), "192.168.1.20");
+    }
+
+    #[test]
+    fn reassembles_split_responses() {
+        let from: SocketAddr = "192.168.1.20:30000".parse().unwrap();
+        let other: SocketAddr = "192.168.1.21:30000".parse().unwrap();
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(from, br#"{"id":1,"result":{"sl"#), None);
+        assert_eq!(reassembler.push(other, br#"{"id":2,"result":{}}"#), Some(serde_json::json!({"id": 2, "result": {}})));
+        assert_eq!(reassembler.push(from, br#"ots":[1,2]}}"#), Some(serde_json::json!({"id": 1, "result": {"slots": [1, 2]}})));
+        // Début orphelin suivi d'une nouvelle réponse complète
+        assert_eq!(reassembler.push(from, br#"{"id":3,"res"#), None);
+        assert_eq!(reassembler.push(from, br#"{"id":4,"result":null}"#), Some(serde_json::json!({"id": 4, "result": null})));
+        assert_eq!(reassembler.push(from, b"garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_not_truncated() {
+        let payload = "x".repeat(20_000);
+        let expected = payload.clone();
+        let addr = fake_device(move |request| Some(serde_json::json!({"id": request["id"], "result": {"log": payload}})));
+        let transport = UdpTransport::default();
+        let result = transport.request(0, &addr, "Marstek.GetLog", serde_json::Value::Null, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(result["log"], expected);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_matched_by_id() {
+        let addr = fake_device(|request| Some(serde_json::json!({"id": request["id"], "result": {"method": request["method"]}})));
+        let transport = UdpTransport::default();
+        let timeout = Duration::from_secs(2);
+        let (a, b) = tokio::join!(
+            transport.request(0, &addr, "ES.GetStatus", serde_json::Value::Null, timeout),
+            transport.request(0, &addr, "Wifi.GetStatus", serde_json::Value::Null, timeout),
+        );
+        assert_eq!(a.unwrap()["method"], "ES.GetStatus");
+        assert_eq!(b.unwrap()["method"], "Wifi.GetStatus");
+    }
+}
@@ -0,0 +1,281 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use std::collections::BTreeMap;
+
+// Membres result des méthodes Get*. Tous les champs sont optionnels : chaque modèle
+// et chaque firmware n'en renvoie qu'une partie. Les membres que nous ne connaissons pas
+// sont gardés dans `unknown` et resérialisés tels quels.
+
+pub type UnknownFields = BTreeMap<String, serde_json::Value>;
+
+// Marstek.GetDevice
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct DeviceInfo {
+    pub device: Option<String>,
+    pub ver: Option<u32>,
+    pub ble_mac: Option<String>,
+    pub wifi_mac: Option<String>,
+    pub wifi_name: Option<String>,
+    pub ip: Option<String>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// Bat.GetStatus
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct BatteryStatus {
+    pub soc: Option<u32>,
+    pub charg_flag: Option<bool>,
+    pub dischrg_flag: Option<bool>,
+    pub bat_temp: Option<f32>,
+    pub bat_capacity: Option<f32>,
+    pub rated_capacity: Option<f32>,
+    // Code d'erreur de certains firmwares, 0 : aucune erreur
+    pub err_code: Option<u32>,
+    // État de santé [%] et nombre de cycles, exposés par certains firmwares seulement
+    #[serde(alias = "bat_soh")]
+    pub soh: Option<u32>,
+    #[serde(alias = "cycle_num", alias = "cycles")]
+    pub cycle_count: Option<u32>,
+    // Chauffage en marche et sa consommation [W], sur les modèles qui en ont un
+    #[serde(alias = "heat_state", alias = "heating")]
+    pub heater_on: Option<bool>,
+    #[serde(alias = "heat_power")]
+    pub heater_power: Option<f32>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// ES.GetStatus
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct EnergyStatus {
+    pub bat_soc: Option<u32>,
+    pub bat_cap: Option<f32>,
+    pub pv_power: Option<f32>,
+    pub ongrid_power: Option<f32>,
+    pub offgrid_power: Option<f32>,
+    pub bat_power: Option<f32>,
+    pub total_pv_energy: Option<f32>,
+    pub total_grid_output_energy: Option<f32>,
+    pub total_grid_input_energy: Option<f32>,
+    pub total_load_energy: Option<f32>,
+    // Part de pv_power venant d'onduleurs externes, renseignée par l'appelant
+    #[serde(skip_deserializing)]
+    pub external_pv_power: Option<f32>,
+    // Non documentés dans l'Open API : renseignés si le firmware les expose
+    pub grid_voltage: Option<f32>,
+    pub grid_frequency: Option<f32>,
+    pub err_code: Option<u32>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// ES.GetMode
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ModeStatus {
+    pub mode: Option<String>,
+    pub ongrid_power: Option<f32>,
+    pub offgrid_power: Option<f32>,
+    pub bat_soc: Option<u32>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// EM.GetStatus
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct MeterStatus {
+    // 1 : CT connecté
+    pub ct_state: Option<u32>,
+    pub a_power: Option<f32>,
+    pub b_power: Option<f32>,
+    pub c_power: Option<f32>,
+    pub total_power: Option<f32>,
+    // Par phase, seulement sur les firmwares (ou compteurs) qui les mesurent : [V], [A]
+    pub a_voltage: Option<f32>,
+    pub b_voltage: Option<f32>,
+    pub c_voltage: Option<f32>,
+    pub a_current: Option<f32>,
+    pub b_current: Option<f32>,
+    pub c_current: Option<f32>,
+    // Compteur externe ayant fourni la mesure, renseigné par l'appelant ; absent : CT Marstek
+    #[serde(skip_deserializing)]
+    pub source: Option<String>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// EM.GetConfig
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct MeterConfig {
+    // 0 : CT Marstek sans fil, 1 : compteur externe lu par l'appareil
+    pub meter_mode: Option<u32>,
+    // Modèle du CT appairé ("CT002", "CT003"...)
+    pub ct_type: Option<String>,
+    pub ct_mac: Option<String>,
+    // 0 : aucun CT, 1 : appairage en cours, 2 : appairé
+    pub pair_state: Option<u32>,
+}
+
+// Bat.GetHeater ; drapeaux souvent en 0/1, à lire avec parse_lenient
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct HeaterStatus {
+    // Chauffage automatique autorisé par le BMS
+    pub auto_heat: Option<bool>,
+    #[serde(alias = "heat_state", alias = "heating")]
+    pub heater_on: Option<bool>,
+    // [W]
+    #[serde(alias = "heat_power")]
+    pub heater_power: Option<f32>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// Entrée de Marstek.GetEvents
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct DeviceEvent {
+    // Secondes Unix, horloge de l'appareil
+    pub ts: i64,
+    pub code: Option<u32>,
+    pub msg: Option<String>,
+}
+
+// Marstek.GetEvents
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct EventPage {
+    pub page: Option<u32>,
+    pub total_pages: Option<u32>,
+    #[serde(default)]
+    pub events: Vec<DeviceEvent>,
+}
+
+// Wifi.GetStatus
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct WifiStatus {
+    pub ssid: Option<String>,
+    pub rssi: Option<i32>,
+    pub sta_ip: Option<String>,
+    // Membres non typés (nouveau firmware...), à intégrer aux champs ci-dessus
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+// Valeur d'un autre type que celui du dialecte Venus : nombre en chaîne ("85"), drapeau en 0/1
+fn coerce(value: &Value) -> Option<Value> {
+    if let Some(text) = value.as_str().map(str::trim) {
+        if let Ok(int) = text.parse::<i64>() {
+            return Some(Value::from(int));
+        }
+        return text.parse::<f64>().ok().filter(|f| f.is_finite()).map(Value::from);
+    }
+    value.as_u64().filter(|n| *n <= 1).map(|n| Value::Bool(n == 1))
+}
+
+// Lecture tolérante d'un membre result : un champ connu au type inattendu ne vide plus toute la section.
+// Il est converti s'il le peut, sinon écarté ; renvoie aussi les noms des champs écartés.
+pub fn parse_lenient<T: DeserializeOwned + Default>(value: Value) -> (T, Vec<String>) {
+    let Value::Object(map) = value else { return (T::default(), Vec::new()) };
+    if let Ok(parsed) = serde_json::from_value(Value::Object(map.clone())) {
+        return (parsed, Vec::new());
+    }
+    let fits = |key: &str, value: &Value| serde_json::from_value::<T>(Value::Object([(key.to_string(), value.clone())].into_iter().collect())).is_ok();
+    let mut kept = serde_json::Map::new();
+    let mut dropped = Vec::new();
+    for (key, value) in map {
+        match coerce(&value) {
+            _ if fits(&key, &value) => {
+                kept.insert(key, value);
+            }
+            Some(coerced) if fits(&key, &coerced) => {
+                kept.insert(key, coerced);
+            }
+            _ => dropped.push(key),
+        }
+    }
+    (serde_json::from_value(Value::Object(kept)).unwrap_or_default(), dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_results_deserialize() {
+        let bat: BatteryStatus = serde_json::from_value(serde_json::json!({"soc": 87, "bat_temp": 21.5, "unknown": 1})).unwrap();
+        assert_eq!(bat.soc, Some(87));
+        assert_eq!(bat.bat_temp, Some(21.5));
+        assert_eq!(bat.charg_flag, None);
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let bat: BatteryStatus = serde_json::from_value(serde_json::json!({"soc": 87, "cell_max_v": 3.41, "heater": {"on": false}})).unwrap();
+        assert_eq!(bat.unknown.keys().collect::<Vec<_>>(), ["cell_max_v", "heater"]);
+        assert_eq!(bat.unknown["cell_max_v"], serde_json::json!(3.41));
+        let round_trip = serde_json::to_value(&bat).unwrap();
+        assert_eq!(round_trip, serde_json::json!({"soc": 87, "cell_max_v": 3.41, "heater": {"on": false}}));
+    }
+
+    #[test]
+    fn health_fields_accept_firmware_aliases() {
+        let bat: BatteryStatus = serde_json::from_value(serde_json::json!({"bat_soh": 97, "cycle_num": 212})).unwrap();
+        assert_eq!((bat.soh, bat.cycle_count), (Some(97), Some(212)));
+        assert!(bat.unknown.is_empty());
+    }
+
+    #[test]
+    fn heater_flags_read_as_integers() {
+        let (heater, dropped): (HeaterStatus, _) = parse_lenient(serde_json::json!({"auto_heat": 1, "heat_state": 0, "heat_power": 120}));
+        assert_eq!((heater.auto_heat, heater.heater_on, heater.heater_power), (Some(true), Some(false), Some(120.0)));
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn caller_fields_are_not_read_from_the_device() {
+        let energy: EnergyStatus = serde_json::from_value(serde_json::json!({"pv_power": 300, "external_pv_power": 999})).unwrap();
+        assert_eq!(energy.pv_power, Some(300.0));
+        assert_eq!(energy.external_pv_power, None);
+    }
+
+    #[test]
+    fn lenient_parse_converts_or_drops_mistyped_fields() {
+        let (bat, dropped): (BatteryStatus, _) =
+            parse_lenient(serde_json::json!({"soc": "85", "charg_flag": 1, "bat_temp": 21.5, "bat_capacity": [1, 2], "cell_max_v": "3.4"}));
+        assert_eq!((bat.soc, bat.charg_flag, bat.bat_temp), (Some(85), Some(true), Some(21.5)));
+        assert_eq!(bat.bat_capacity, None);
+        assert_eq!(bat.unknown["cell_max_v"], serde_json::json!("3.4"));
+        assert_eq!(dropped, ["bat_capacity"]);
+    }
+
+    #[test]
+    fn lenient_parse_of_a_valid_result_drops_nothing() {
+        let (energy, dropped): (EnergyStatus, _) = parse_lenient(serde_json::json!({"pv_power": 300, "bat_power": -120}));
+        assert_eq!((energy.pv_power, energy.bat_power), (Some(300.0), Some(-120.0)));
+        assert!(dropped.is_empty());
+        let (wifi, dropped): (WifiStatus, _) = parse_lenient(serde_json::Value::Null);
+        assert_eq!((wifi, dropped.len()), (WifiStatus::default(), 0));
+    }
+
+    #[test]
+    fn absent_fields_are_not_serialized() {
+        let wifi = WifiStatus { rssi: Some(-60), ..Default::default() };
+        assert_eq!(serde_json::to_value(&wifi).unwrap(), serde_json::json!({"rssi": -60}));
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Différences de dialecte JSON-RPC par famille de produits. Le dialecte Venus (doc Open API)
+// sert de référence ; B2500 et Jupiter n'étant pas documentés, leurs écarts se déclarent
+// dans la configuration de l'appelant plutôt que d'être devinés ici.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ProtocolVariant {
+    // Méthode canonique -> méthode réelle de l'appareil
+    pub methods: HashMap<String, String>,
+    // Champ renvoyé par l'appareil -> champ canonique
+    pub fields: HashMap<String, String>,
+}
+
+impl ProtocolVariant {
+    pub fn method<'a>(&'a self, canonical: &'a str) -> &'a str {
+        self.methods.get(canonical).map(String::as_str).unwrap_or(canonical)
+    }
+
+    pub fn normalize(&self, result: serde_json::Value) -> serde_json::Value {
+        if self.fields.is_empty() {
+            return result;
+        }
+        match result {
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .map(|(key, value)| (self.fields.get(&key).cloned().unwrap_or(key), value))
+                .collect(),
+            other => other,
+        }
+    }
+}
+
+pub fn family(model: &str) -> &'static str {
+    let model = model.to_lowercase();
+    if model.starts_with("venus") {
+        "venus"
+    } else if model.starts_with("duo") {
+        "duo"
+    } else if model.contains("b2500") || model.starts_with("hm") {
+        "b2500"
+    } else if model.starts_with("jupiter") {
+        "jupiter"
+    } else {
+        "unknown"
+    }
+}
+
+pub fn resolve(model: Option<&str>, variants: &HashMap<String, ProtocolVariant>) -> ProtocolVariant {
+    model
+        .and_then(|m| variants.get(family(m)))
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn b2500() -> ProtocolVariant {
+        ProtocolVariant {
+            methods: HashMap::from([("ES.GetStatus".to_string(), "ES.GetStat".to_string())]),
+            fields: HashMap::from([("soc_pct".to_string(), "soc".to_string())]),
+        }
+    }
+
+    #[test]
+    fn families() {
+        assert_eq!(family("VenusE 3.0"), "venus");
+        assert_eq!(family("HMA-1"), "b2500");
+        assert_eq!(family("Jupiter C"), "jupiter");
+        assert_eq!(family("Other"), "unknown");
+    }
+
+    #[test]
+    fn methods_fall_back_to_canonical() {
+        let variant = b2500();
+        assert_eq!(variant.method("ES.GetStatus"), "ES.GetStat");
+        assert_eq!(variant.method("Bat.GetStatus"), "Bat.GetStatus");
+    }
+
+    #[test]
+    fn normalize_renames_fields() {
+        let result = b2500().normalize(serde_json::json!({"soc_pct": 40, "bat_temp": 20}));
+        assert_eq!(result, serde_json::json!({"soc": 40, "bat_temp": 20}));
+    }
+
+    #[test]
+    fn resolve_by_family() {
+        let variants = HashMap::from([("b2500".to_string(), b2500())]);
+        assert_eq!(resolve(Some("HMJ-2"), &variants).method("ES.GetStatus"), "ES.GetStat");
+        assert_eq!(resolve(Some("VenusC"), &variants).method("ES.GetStatus"), "ES.GetStatus");
+        assert!(resolve(None, &variants).methods.is_empty());
+    }
+}
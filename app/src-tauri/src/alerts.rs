@@ -0,0 +1,290 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub const ALERTS_FILE: &str = "alerts.jsonl";
+// Historique tronqué à l'ouverture au-delà de ce nombre d'événements
+const MAX_HISTORY: usize = 5000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    // [°C]
+    BatTempAbove,
+    // [°C] : la charge d'une batterie LFP gelée l'abîme
+    BatTempBelow,
+    // [%]
+    SocBelow,
+    // Aucune réponse au polling depuis N minutes
+    UnreachableFor,
+    // Injection réseau, [W]
+    GridExportAbove,
+    // Nombre d'alarmes et défauts signalés par l'appareil
+    DeviceFaults,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlertRule {
+    pub id: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub metric: AlertMetric,
+    // Unité de la métrique
+    pub threshold: f64,
+    pub severity: Severity,
+    // Absent : tous les appareils
+    #[serde(default)]
+    pub device: Option<String>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub enabled: bool,
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        let rule = |id: &str, enabled, metric, threshold, severity| AlertRule {
+            id: id.to_string(),
+            enabled,
+            metric,
+            threshold,
+            severity,
+            device: None,
+        };
+        Self {
+            enabled: true,
+            rules: vec![
+                rule("bat_temp", true, AlertMetric::BatTempAbove, 50.0, Severity::Warning),
+                rule("bat_temp_low", true, AlertMetric::BatTempBelow, 0.0, Severity::Warning),
+                rule("low_soc", true, AlertMetric::SocBelow, 10.0, Severity::Warning),
+                rule("offline", true, AlertMetric::UnreachableFor, 10.0, Severity::Critical),
+                rule("grid_export", false, AlertMetric::GridExportAbove, 800.0, Severity::Info),
+                rule("device_fault", true, AlertMetric::DeviceFaults, 0.0, Severity::Critical),
+            ],
+        }
+    }
+}
+
+impl AlertConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut ids = HashSet::new();
+        for rule in &self.rules {
+            if rule.id.trim().is_empty() || !ids.insert(rule.id.as_str()) {
+                return Err(AppError::InvalidInput(format!("Alert rule ids must be unique and non-empty ({:?})", rule.id)));
+            }
+            let valid = match rule.metric {
+                AlertMetric::SocBelow => (0.0..=100.0).contains(&rule.threshold),
+                AlertMetric::UnreachableFor => rule.threshold > 0.0,
+                AlertMetric::BatTempAbove | AlertMetric::BatTempBelow => rule.threshold.is_finite(),
+                AlertMetric::GridExportAbove | AlertMetric::DeviceFaults => rule.threshold >= 0.0,
+            };
+            if !valid {
+                return Err(AppError::InvalidInput(format!("Invalid threshold {} for alert rule {:?}", rule.threshold, rule.id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Valeurs d'un poll réussi
+pub struct AlertSample {
+    pub bat_temp: Option<f32>,
+    // Chauffage de la batterie ; None : pas de chauffage ou état non rapporté
+    pub heater_on: Option<bool>,
+    pub soc: Option<u32>,
+    // > 0 en soutirage, < 0 en injection
+    pub grid_power: Option<f32>,
+    // "F13 Inverter overtemperature"... ; None : l'appareil ne signale pas ses défauts
+    pub faults: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Raised,
+    Cleared,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlertEvent {
+    pub timestamp: String,
+    pub rule: String,
+    pub device: String,
+    pub metric: AlertMetric,
+    pub severity: Severity,
+    pub state: AlertState,
+    pub value: f64,
+    pub threshold: f64,
+    // Défauts actifs à la levée (métrique device_faults), état du chauffage (bat_temp_below)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AlertLog {
+    // Alertes levées et pas encore retombées
+    pub active: Vec<AlertEvent>,
+    pub history: Vec<AlertEvent>,
+}
+
+#[derive(Default)]
+struct EngineState {
+    // (règle, appareil) -> événement de levée
+    active: HashMap<(String, String), AlertEvent>,
+    // Dernier poll réussi ; à défaut, premier échec constaté
+    last_seen: HashMap<String, Instant>,
+}
+
+// Règles évaluées à chaque poll ; seuls les franchissements produisent un événement
+pub struct AlertEngine {
+    path: PathBuf,
+    state: Mutex<EngineState>,
+}
+
+fn read_events(path: &PathBuf) -> Vec<AlertEvent> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+impl AlertEngine {
+    pub fn open(path: PathBuf) -> Self {
+        let events = read_events(&path);
+        if events.len() > MAX_HISTORY {
+            let kept: Vec<String> = events[events.len() - MAX_HISTORY..]
+                .iter()
+                .filter_map(|e| serde_json::to_string(e).ok())
+                .collect();
+            if let Err(e) = fs::write(&path, kept.join("\n") + "\n") {
+                tracing::warn!("failed to trim alert history: {}", e);
+            }
+        }
+        Self { path, state: Mutex::new(EngineState::default()) }
+    }
+
+    // sample absent : le poll a échoué
+    pub fn observe(&self, config: &AlertConfig, device: &str, sample: Option<&AlertSample>) -> Vec<AlertEvent> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let offline_minutes = match sample {
+            Some(_) => {
+                state.last_seen.insert(device.to_string(), now);
+                0.0
+            }
+            None => now.duration_since(*state.last_seen.entry(device.to_string()).or_insert(now)).as_secs_f64() / 60.0,
+        };
+        if !config.enabled {
+            return Vec::new();
+        }
+
+        let timestamp = chrono::Local::now().to_rfc3339();
+        let mut events = Vec::new();
+        for rule in config.rules.iter().filter(|r| r.enabled && r.device.as_deref().is_none_or(|d| d == device)) {
+            // None : valeur inconnue sur ce poll, l'état de l'alerte ne change pas
+            let reading = match (rule.metric, sample) {
+                (AlertMetric::UnreachableFor, _) => Some((offline_minutes, offline_minutes >= rule.threshold)),
+                (_, None) => None,
+                (AlertMetric::BatTempAbove, Some(s)) => s.bat_temp.map(f64::from).map(|v| (v, v > rule.threshold)),
+                (AlertMetric::BatTempBelow, Some(s)) => s.bat_temp.map(f64::from).map(|v| (v, v < rule.threshold)),
+                (AlertMetric::SocBelow, Some(s)) => s.soc.map(f64::from).map(|v| (v, v < rule.threshold)),
+                (AlertMetric::GridExportAbove, Some(s)) => s.grid_power.map(|p| f64::from(-p)).map(|v| (v, v > rule.threshold)),
+                (AlertMetric::DeviceFaults, Some(s)) => s.faults.as_ref().map(|f| f.len() as f64).map(|v| (v, v > rule.threshold)),
+            };
+            let detail = match (rule.metric, sample) {
+                (AlertMetric::DeviceFaults, Some(s)) => s.faults.as_ref().filter(|f| !f.is_empty()).map(|f| f.join(", ")),
+                (AlertMetric::BatTempBelow, Some(s)) => s.heater_on.map(|on| if on { "heater on" } else { "heater off" }.to_string()),
+                _ => None,
+            };
+            let Some((value, breached)) = reading else { continue };
+            let key = (rule.id.clone(), device.to_string());
+            let event = |state| AlertEvent {
+                timestamp: timestamp.clone(),
+                rule: rule.id.clone(),
+                device: device.to_string(),
+                metric: rule.metric,
+                severity: rule.severity,
+                state,
+                value,
+                threshold: rule.threshold,
+                detail: detail.clone(),
+            };
+            match (breached, state.active.contains_key(&key)) {
+                (true, false) => {
+                    let raised = event(AlertState::Raised);
+                    state.active.insert(key, raised.clone());
+                    events.push(raised);
+                }
+                (false, true) => {
+                    state.active.remove(&key);
+                    events.push(event(AlertState::Cleared));
+                }
+                _ => {}
+            }
+        }
+        // Règle supprimée ou désactivée : son alerte ne retombera jamais d'elle-même
+        state.active.retain(|(rule, _), _| config.rules.iter().any(|r| r.enabled && &r.id == rule));
+
+        for event in &events {
+            if event.state == AlertState::Raised {
+                tracing::warn!(device, rule = %event.rule, "alert raised: {:?} {} (threshold {})", event.metric, event.value, event.threshold);
+            }
+            if let Err(e) = self.append(event) {
+                tracing::warn!("failed to record alert: {}", e);
+            }
+        }
+        events
+    }
+
+    fn append(&self, event: &AlertEvent) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+        Ok(())
+    }
+
+    // Plus récents en premier
+    pub fn log(&self, limit: Option<usize>, device: Option<&str>) -> AlertLog {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut active: Vec<AlertEvent> = state.active.values().filter(|e| device.is_none_or(|d| d == e.device)).cloned().collect();
+        active.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        let history = read_events(&self.path)
+            .into_iter()
+            .rev()
+            .filter(|e| device.is_none_or(|d| d == e.device))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        AlertLog { active, history }
+    }
+
+    pub fn clear_history(&self) -> Result<(), AppError> {
+        let _state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use crate::audit::CommandSource;
+use crate::error::AppError;
+use crate::netaccess::{self, ApiTokens, RateLimiter, SourceRange};
+use crate::{apply_mode, dashboard_with_fallback, device_target, ha_statistics, AppState};
+use axum::extract::{ConnectInfo, Query, Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiServerConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+    // HTTPS ; sans certificat fourni, un certificat auto-signé est généré dans le dossier de données
+    pub tls: bool,
+    // Certificat et clé privée au format PEM, fournis ensemble
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    // Clients acceptés, IP ou plage ("192.168.1.0/24", "fd00::/8") ; vide : tous
+    pub allowed_sources: Vec<String>,
+    // Requêtes par minute et par IP cliente, en rafale comprise ; 0 : sans limite
+    pub rate_limit_per_minute: u32,
+    // Écoute sur 127.0.0.1 seulement, quel que soit bind_address
+    pub localhost_only: bool,
+    // Sans jeton, n'importe quel appareil du réseau peut piloter la batterie
+    pub require_token: bool,
+    // Jeton de pilotage, valable sur toutes les routes ; ici uniquement si le trousseau système est indisponible
+    pub token: Option<String>,
+    // Jeton de lecture (tableaux de bord, statistiques, /metrics) : refusé sur /api/mode
+    pub read_token: Option<String>,
+    // Origines autorisées pour un navigateur (ex. "http://192.168.1.20:3000") ; "*" les accepte toutes
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for ApiServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "0.0.0.0".to_string(),
+            port: 8080,
+            tls: false,
+            tls_cert_path: None,
+            tls_key_path: None,
+            allowed_sources: Vec::new(),
+            rate_limit_per_minute: 120,
+            localhost_only: false,
+            require_token: true,
+            token: None,
+            read_token: None,
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl ApiServerConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !self.localhost_only && self.bind_address.parse::<std::net::IpAddr>().is_err() {
+            return Err(AppError::InvalidInput(format!("Invalid API bind address: {}", self.bind_address)));
+        }
+        if let Some(origin) = self.allowed_origins.iter().find(|o| *o != "*" && !o.starts_with("http://") && !o.starts_with("https://")) {
+            return Err(AppError::InvalidInput(format!("Invalid allowed origin: {} (expected http(s)://host[:port] or *)", origin)));
+        }
+        if let Some(source) = self.allowed_sources.iter().find(|s| SourceRange::parse(s).is_none()) {
+            return Err(AppError::InvalidInput(format!("Invalid allowed source: {} (expected an IP or a range such as 192.168.1.0/24)", source)));
+        }
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(_), None) | (None, Some(_)) => Err(AppError::InvalidInput("A TLS certificate and its private key must be set together".to_string())),
+            (Some(cert), Some(key)) => match [cert, key].into_iter().find(|path| !Path::new(path).is_file()) {
+                Some(missing) => Err(AppError::InvalidInput(format!("TLS file not found: {}", missing))),
+                None => Ok(()),
+            },
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn listen_address(&self) -> String {
+        let host = if self.localhost_only { "127.0.0.1" } else { self.bind_address.as_str() };
+        marstek_protocol::endpoint(host, self.port)
+    }
+
+    // Adresse à joindre depuis la même machine : une écoute sur toutes les interfaces répond aussi en local
+    pub fn local_address(&self) -> String {
+        let unspecified = self.bind_address.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_unspecified());
+        let host = if self.localhost_only || unspecified { "127.0.0.1" } else { self.bind_address.as_str() };
+        marstek_protocol::endpoint(host, self.port)
+    }
+
+    pub fn local_url(&self) -> String {
+        format!("{}://{}", if self.tls { "https" } else { "http" }, self.local_address())
+    }
+}
+
+// Certificat fourni, sinon auto-signé dans le dossier de données
+fn tls_files(app: &AppHandle, config: &ApiServerConfig) -> Result<(PathBuf, PathBuf), String> {
+    if let (Some(cert), Some(key)) = (&config.tls_cert_path, &config.tls_key_path) {
+        return Ok((PathBuf::from(cert), PathBuf::from(key)));
+    }
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let mut names = vec!["localhost".to_string(), config.bind_address.clone()];
+    names.extend(if_addrs::get_if_addrs().unwrap_or_default().iter().map(|interface| interface.ip().to_string()));
+    names.retain(|name| name.parse::<IpAddr>().map_or(true, |ip| !ip.is_unspecified()));
+    names.sort();
+    names.dedup();
+    netaccess::self_signed_certificate(&dir, &names)
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct ApiServerStatus {
+    pub running: bool,
+    pub last_error: Option<String>,
+}
+
+type ApiError = (StatusCode, Json<AppError>);
+
+fn reject(e: AppError) -> ApiError {
+    let status = match &e {
+        AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        AppError::NotConfigured(_) => StatusCode::NOT_FOUND,
+        AppError::DeviceRejected { .. } | AppError::ParseError(_) | AppError::IoError(_) => StatusCode::BAD_GATEWAY,
+        AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+        AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(e))
+}
+
+// Règles d'accès figées au démarrage du serveur
+struct Access {
+    allowed_sources: Vec<SourceRange>,
+    limiter: RateLimiter,
+    require_token: bool,
+    tokens: ApiTokens,
+    allowed_origins: Vec<String>,
+}
+
+impl Access {
+    fn origin_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+}
+
+// Source et débit du client, avant tout le reste : un client refusé n'obtient ni preflight ni vérification de jeton
+async fn guard(State(access): State<Arc<Access>>, request: Request, next: Next) -> Response {
+    let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(request).await;
+    };
+    let ip = peer.ip().to_canonical();
+    if !netaccess::source_allowed(&access.allowed_sources, ip) {
+        tracing::debug!(client = %ip, "REST request from a source outside the allowlist");
+        return (StatusCode::FORBIDDEN, Json(AppError::Forbidden("Source address not allowed.".to_string()))).into_response();
+    }
+    if let Err(retry_after) = access.limiter.take(ip) {
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(AppError::Forbidden("Too many requests.".to_string()))).into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    }
+    next.run(request).await
+}
+
+// Un navigateur envoie toujours Origin en cross-origin : une origine non autorisée est refusée
+// avant d'atteindre la commande, y compris pour les POST "simples" qui échappent au preflight
+async fn cors(State(access): State<Arc<Access>>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    if !origin.to_str().is_ok_and(|o| access.origin_allowed(o)) {
+        return (StatusCode::FORBIDDEN, Json(AppError::Forbidden("Origin not allowed.".to_string()))).into_response();
+    }
+    let mut response = if request.method() == Method::OPTIONS {
+        let mut preflight = StatusCode::NO_CONTENT.into_response();
+        let headers = preflight.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST"));
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("authorization, content-type"));
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("600"));
+        preflight
+    } else {
+        next.run(request).await
+    };
+    response.headers_mut().insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    response.headers_mut().insert(header::VARY, HeaderValue::from_static("Origin"));
+    response
+}
+
+// "Authorization: Bearer <jeton>" sur toutes les routes, /metrics compris ; tout ce qui n'est pas GET pilote la batterie
+async fn authorize(State(access): State<Arc<Access>>, request: Request, next: Next) -> Response {
+    if access.require_token {
+        let provided = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match access.tokens.scope(provided) {
+            None => {
+                let mut response =
+                    (StatusCode::UNAUTHORIZED, Json(AppError::Forbidden("A valid API token is required.".to_string()))).into_response();
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                return response;
+            }
+            Some(scope) if !scope.allows(matches!(*request.method(), Method::GET | Method::HEAD)) => {
+                return (StatusCode::FORBIDDEN, Json(AppError::Forbidden("This API token only grants read access.".to_string()))).into_response();
+            }
+            Some(_) => {}
+        }
+    }
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+struct DeviceQuery {
+    device: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModeRequest {
+    mode: String,
+    config: Option<serde_json::Value>,
+    pin: Option<String>,
+    device: Option<String>,
+}
+
+// Les commandes de l'app sont synchrones (UDP bloquant) : on les sort du runtime async
+async fn blocking<T: Send + 'static>(
+    app: AppHandle,
+    f: impl FnOnce(&AppHandle, &AppState) -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(move || f(&app, &app.state::<AppState>()))
+        .await
+        .map_err(|e| reject(AppError::Internal(e.to_string())))?
+}
+
+// Lecture seule : les clients REST n'alimentent ni l'historique ni les exports, c'est le rôle du polling
+async fn dashboard(State(app): State<AppHandle>, Query(query): Query<DeviceQuery>) -> Result<Json<crate::DashboardData>, ApiError> {
+    blocking(app, move |app, state| {
+        dashboard_with_fallback(app, state, query.device.as_deref(), false).map_err(reject)
+    })
+    .await
+    .map(Json)
+}
+
+#[derive(Deserialize)]
+struct StatisticsQuery {
+    device: Option<String>,
+    days: Option<u32>,
+}
+
+async fn statistics(
+    State(app): State<AppHandle>,
+    Query(query): Query<StatisticsQuery>,
+) -> Result<Json<Vec<crate::hastats::ExternalStatistic>>, ApiError> {
+    blocking(app, move |_, state| ha_statistics(state, query.days, query.device.as_deref()).map_err(reject))
+        .await
+        .map(Json)
+}
+
+async fn devices(State(app): State<AppHandle>) -> Result<Json<Vec<crate::devices::DeviceEntry>>, ApiError> {
+    let state = app.state::<AppState>();
+    let registry = state.devices.lock().map_err(|e| reject(AppError::Internal(e.to_string())))?;
+    Ok(Json(registry.list()))
+}
+
+async fn metrics(State(app): State<AppHandle>) -> Result<([(axum::http::HeaderName, &'static str); 1], String), ApiError> {
+    let state = app.state::<AppState>();
+    let latest = state.latest.lock().map_err(|e| reject(AppError::Internal(e.to_string())))?;
+    let snapshots: Vec<_> = latest.values().collect();
+    let body = crate::prometheus::render(&snapshots, &state.metrics.snapshot());
+    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
+
+async fn mode(State(app): State<AppHandle>, Json(request): Json<ModeRequest>) -> Result<Json<bool>, ApiError> {
+    blocking(app, move |_, state| {
+        state.ensure_writable().map_err(reject)?;
+        state.check_pin(request.pin.as_deref()).map_err(reject)?;
+        let target = device_target(state, request.device.as_deref()).map_err(reject)?;
+        apply_mode(state, CommandSource::Api, &target, &request.mode, request.config, true).map_err(reject)
+    })
+    .await
+    .map(Json)
+}
+
+#[derive(Default)]
+struct ServerState {
+    shutdown: Option<oneshot::Sender<()>>,
+    // Évite qu'un serveur en cours d'arrêt n'écrase le statut du suivant
+    generation: u64,
+    status: ApiServerStatus,
+}
+
+#[derive(Default)]
+pub struct ApiServer {
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl ApiServer {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ServerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self) -> ApiServerStatus {
+        self.lock().status.clone()
+    }
+
+    // Arrête le serveur en cours puis le relance si la config l'active
+    pub fn restart(&self, app: &AppHandle, config: &ApiServerConfig, tokens: ApiTokens) {
+        let mut state = self.lock();
+        if let Some(shutdown) = state.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        state.generation += 1;
+        state.status = ApiServerStatus::default();
+        if !config.enabled {
+            return;
+        }
+        let tls = match config.tls.then(|| tls_files(app, config)).transpose() {
+            Ok(tls) => tls,
+            Err(e) => {
+                tracing::warn!("REST API not started, TLS unavailable: {}", e);
+                state.status.last_error = Some(e);
+                return;
+            }
+        };
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        state.shutdown = Some(shutdown);
+        if config.require_token && tokens.is_empty() {
+            tracing::warn!("REST API requires a token but none is set: every request will be rejected");
+        }
+        let access = Arc::new(Access {
+            allowed_sources: config.allowed_sources.iter().filter_map(|source| SourceRange::parse(source)).collect(),
+            limiter: RateLimiter::new(config.rate_limit_per_minute),
+            require_token: config.require_token,
+            tokens,
+            allowed_origins: config.allowed_origins.clone(),
+        });
+        // Le dernier layer est le plus externe : source et débit d'abord, puis le preflight CORS avant le contrôle du jeton
+        let router = Router::new()
+            .route("/api/dashboard", get(dashboard))
+            .route("/api/devices", get(devices))
+            .route("/api/statistics", get(statistics))
+            .route("/api/mode", post(mode))
+            .route("/metrics", get(metrics))
+            .layer(middleware::from_fn_with_state(Arc::clone(&access), authorize))
+            .layer(middleware::from_fn_with_state(Arc::clone(&access), cors))
+            .layer(middleware::from_fn_with_state(access, guard))
+            .with_state(app.clone());
+        let address = config.listen_address();
+        let shared = Arc::clone(&self.state);
+        let generation = state.generation;
+        tauri::async_runtime::spawn(async move {
+            let result = match tokio::net::TcpListener::bind(&address).await {
+                Ok(listener) => {
+                    tracing::info!("REST API listening on {} ({})", address, if tls.is_some() { "https" } else { "http" });
+                    {
+                        let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                        if state.generation == generation {
+                            state.status.running = true;
+                        }
+                    }
+                    let service = router.into_make_service_with_connect_info::<SocketAddr>();
+                    match tls {
+                        None => {
+                            axum::serve(listener, service)
+                                .with_graceful_shutdown(async {
+                                    let _ = stopped.await;
+                                })
+                                .await
+                        }
+                        // axum::serve ne parle pas TLS : axum-server reprend le même socket et le même routeur
+                        Some((cert, key)) => {
+                            // ring, déjà utilisé par reqwest ; déjà installé aux redémarrages suivants
+                            let _ = rustls::crypto::ring::default_provider().install_default();
+                            match (RustlsConfig::from_pem_file(&cert, &key).await, listener.into_std()) {
+                                (Ok(rustls), Ok(listener)) => {
+                                    let handle = axum_server::Handle::new();
+                                    let stopping = handle.clone();
+                                    tauri::async_runtime::spawn(async move {
+                                        let _ = stopped.await;
+                                        stopping.graceful_shutdown(None);
+                                    });
+                                    axum_server::from_tcp_rustls(listener, rustls).handle(handle).serve(service).await
+                                }
+                                (Err(e), _) | (_, Err(e)) => Err(e),
+                            }
+                        }
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                tracing::warn!("REST API on {} stopped: {}", address, e);
+            }
+            let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+            if state.generation == generation {
+                state.status = ApiServerStatus { running: false, last_error: result.err().map(|e| e.to_string()) };
+            }
+        });
+    }
+}
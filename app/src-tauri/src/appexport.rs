@@ -0,0 +1,303 @@
+use crate::error::AppError;
+use crate::history::HistorySample;
+use crate::kpi::{self, DailyKpis};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::Serialize;
+
+// Export de l'application Marstek, en CSV ou en JSON : une ligne par jour avec les énergies,
+// ou une ligne par relevé horodaté avec les puissances. Les colonnes sont reconnues à leur nom,
+// l'unité entre parenthèses ou crochets ("PV (kWh)", "Grid power [W]") ; sans unité, kWh et W.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Column {
+    Time,
+    Pv,
+    GridImport,
+    GridExport,
+    Charge,
+    Discharge,
+    Soc,
+    PvPower,
+    GridPower,
+    BatteryPower,
+    Temperature,
+}
+
+const NAMES: [(Column, &[&str]); 11] = [
+    (Column::Time, &["date", "day", "time", "timestamp", "datetime", "date_time"]),
+    (Column::Pv, &["pv", "pv_energy", "solar", "solar_energy", "pv_generation", "generation"]),
+    (Column::GridImport, &["grid_import", "grid_in", "import", "from_grid", "grid_consumption"]),
+    (Column::GridExport, &["grid_export", "grid_out", "export", "to_grid", "feed_in"]),
+    (Column::Charge, &["charge", "charged", "charge_energy", "battery_charge"]),
+    (Column::Discharge, &["discharge", "discharged", "discharge_energy", "battery_discharge"]),
+    (Column::Soc, &["soc", "bat_soc", "battery_soc"]),
+    (Column::PvPower, &["pv_power", "solar_power"]),
+    (Column::GridPower, &["grid_power", "meter_power", "total_power"]),
+    (Column::BatteryPower, &["battery_power", "bat_power"]),
+    (Column::Temperature, &["temperature", "temp", "bat_temp"]),
+];
+
+// Contenu reconnu d'un export, avant import
+#[derive(Default)]
+pub struct AppExport {
+    pub days: Vec<DailyKpis>,
+    // Secondes Unix
+    pub samples: Vec<(i64, HistorySample)>,
+    // Lignes sans date lisible ou sans aucune mesure
+    pub skipped: usize,
+}
+
+#[derive(Serialize)]
+pub struct ExportImport {
+    pub days: usize,
+    pub samples: usize,
+    // Ajoutés ; les autres étaient déjà importés ou couverts par les relevés locaux
+    pub imported_days: usize,
+    pub imported_samples: usize,
+    pub skipped: usize,
+}
+
+// Colonne et facteur vers Wh ou W
+fn column(header: &str) -> Option<(Column, f64)> {
+    let header = header.trim().trim_start_matches('\u{feff}').to_lowercase();
+    let (name, unit) = match header.find(['(', '[']) {
+        Some(start) => (&header[..start], header[start + 1..].trim_end_matches([')', ']']).trim().to_string()),
+        None => (header.as_str(), String::new()),
+    };
+    let key = name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_");
+    let (column, _) = NAMES.iter().find(|(_, names)| names.contains(&key.as_str()))?;
+    let column = match (*column, unit.as_str()) {
+        // "PV (W)" dans un export de relevés : une puissance, pas une énergie
+        (Column::Pv, "w" | "kw") => Column::PvPower,
+        (column, _) => column,
+    };
+    let scale = match (column, unit.as_str()) {
+        (Column::Pv | Column::GridImport | Column::GridExport | Column::Charge | Column::Discharge, "wh") => 1.0,
+        (Column::Pv | Column::GridImport | Column::GridExport | Column::Charge | Column::Discharge, _) => 1000.0,
+        (Column::PvPower | Column::GridPower | Column::BatteryPower, "kw") => 1000.0,
+        _ => 1.0,
+    };
+    Some((column, scale))
+}
+
+enum Stamp {
+    Day(NaiveDate),
+    At(i64),
+}
+
+// Date seule : bilan journalier ; date et heure (locales sans fuseau) ou secondes/millisecondes Unix : relevé
+fn stamp(text: &str) -> Option<Stamp> {
+    let text = text.trim();
+    if let Ok(value) = text.parse::<i64>() {
+        return match value {
+            1_000_000_000..=9_999_999_999 => Some(Stamp::At(value)),
+            1_000_000_000_000..=9_999_999_999_999 => Some(Stamp::At(value / 1000)),
+            _ => None,
+        };
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Some(Stamp::At(at.timestamp()));
+    }
+    for date in ["%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%d.%m.%Y"] {
+        if let Ok(day) = NaiveDate::parse_from_str(text, date) {
+            return Some(Stamp::Day(day));
+        }
+        for time in ["%H:%M:%S", "%H:%M"] {
+            for separator in [" ", "T"] {
+                if let Ok(at) = NaiveDateTime::parse_from_str(text, &format!("{}{}{}", date, separator, time)) {
+                    return Local.from_local_datetime(&at).earliest().map(|at| Stamp::At(at.timestamp()));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn number(text: &str, decimal_comma: bool) -> Option<f64> {
+    let text = text.trim();
+    let value = if decimal_comma { text.replace(',', ".").parse::<f64>() } else { text.parse::<f64>() };
+    value.ok().filter(|v| v.is_finite())
+}
+
+impl AppExport {
+    fn add(&mut self, cells: &[(Column, f64, String)], decimal_comma: bool) {
+        let value = |column: Column| {
+            cells.iter().find(|(c, ..)| *c == column).and_then(|(_, scale, text)| number(text, decimal_comma).map(|v| v * scale))
+        };
+        match cells.iter().find(|(c, ..)| *c == Column::Time).and_then(|(_, _, text)| stamp(text)) {
+            Some(Stamp::Day(day)) => {
+                let values = [Column::Pv, Column::GridImport, Column::GridExport, Column::Charge, Column::Discharge].map(value);
+                if values.iter().all(Option::is_none) {
+                    self.skipped += 1;
+                    return;
+                }
+                let [pv, import, export, charge, discharge] = values.map(|v| v.unwrap_or(0.0).max(0.0));
+                self.days.push(kpi::from_totals(day.format("%Y-%m-%d").to_string(), pv, import, export, charge, discharge));
+            }
+            Some(Stamp::At(timestamp)) => {
+                let sample = HistorySample {
+                    soc: value(Column::Soc).map(|soc| soc.round().clamp(0.0, 100.0) as u32),
+                    pv_power: value(Column::PvPower).map(|v| v as f32),
+                    grid_power: value(Column::GridPower).map(|v| v as f32),
+                    battery_power: value(Column::BatteryPower).map(|v| v as f32),
+                    temperature: value(Column::Temperature).map(|v| v as f32),
+                };
+                let empty = sample.soc.is_none()
+                    && [sample.pv_power, sample.grid_power, sample.battery_power, sample.temperature].iter().all(Option::is_none);
+                if empty {
+                    self.skipped += 1;
+                    return;
+                }
+                self.samples.push((timestamp, sample));
+            }
+            None => self.skipped += 1,
+        }
+    }
+}
+
+// Guillemets doublés à l'intérieur d'un champ entre guillemets
+fn split(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// L'en-tête est la première ligne qui nomme une colonne de date : les lignes de titre avant sont ignorées.
+// Séparateur ";" ou tabulation : virgule décimale, comme dans les exports en français ou en allemand.
+fn parse_csv(content: &str) -> Result<AppExport, AppError> {
+    let mut lines = content.lines();
+    let (delimiter, columns) = lines
+        .by_ref()
+        .find_map(|line| {
+            let delimiter = [',', ';', '\t'].into_iter().max_by_key(|d| line.matches(*d).count())?;
+            let columns: Vec<_> = split(line, delimiter).iter().map(|header| column(header)).collect();
+            columns.iter().flatten().any(|(c, _)| *c == Column::Time).then_some((delimiter, columns))
+        })
+        .ok_or_else(|| AppError::ParseError("No date or time column found in the export".to_string()))?;
+    let mut export = AppExport::default();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let cells: Vec<_> = columns
+            .iter()
+            .zip(split(line, delimiter))
+            .filter_map(|(column, text)| column.map(|(column, scale)| (column, scale, text)))
+            .collect();
+        export.add(&cells, delimiter != ',');
+    }
+    Ok(export)
+}
+
+// Tableau d'objets, ou objet dont le premier tableau contient les lignes ({"data": [...]})
+fn parse_json(content: &str) -> Result<AppExport, AppError> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    let rows = match &value {
+        serde_json::Value::Array(rows) => rows,
+        serde_json::Value::Object(fields) => fields
+            .get("data")
+            .and_then(|data| data.as_array())
+            .or_else(|| fields.values().find_map(|v| v.as_array()))
+            .ok_or_else(|| AppError::ParseError("No list of records found in the export".to_string()))?,
+        _ => return Err(AppError::ParseError("No list of records found in the export".to_string())),
+    };
+    let mut export = AppExport::default();
+    for row in rows {
+        let Some(fields) = row.as_object() else {
+            export.skipped += 1;
+            continue;
+        };
+        let cells: Vec<_> = fields
+            .iter()
+            .filter_map(|(name, value)| {
+                let text = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    serde_json::Value::Number(number) => number.to_string(),
+                    _ => return None,
+                };
+                column(name).map(|(column, scale)| (column, scale, text))
+            })
+            .collect();
+        export.add(&cells, false);
+    }
+    Ok(export)
+}
+
+pub fn parse(content: &str) -> Result<AppExport, AppError> {
+    let export = match content.trim_start_matches('\u{feff}').trim_start().chars().next() {
+        Some('[' | '{') => parse_json(content.trim_start_matches('\u{feff}'))?,
+        _ => parse_csv(content)?,
+    };
+    if export.days.is_empty() && export.samples.is_empty() {
+        return Err(AppError::ParseError(format!("No usable row in the export ({} skipped)", export.skipped)));
+    }
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daily_csv_with_decimal_commas() {
+        let content = "Marstek energy report\nDate;PV (kWh);Grid import (kWh);Grid export (kWh);Charge (kWh);Discharge (kWh)\n\
+                       2024-03-01;12,5;3;1,5;6;4,25\n2024-03-02;;;;;\nTotal;12,5;3;1,5;6;4,25\n";
+        let export = parse(content).unwrap();
+        assert_eq!(export.days.len(), 1);
+        let day = &export.days[0];
+        assert_eq!(day.date, "2024-03-01");
+        assert_eq!((day.pv_wh, day.import_wh, day.export_wh, day.charge_wh, day.discharge_wh), (12_500.0, 3000.0, 1500.0, 6000.0, 4250.0));
+        assert_eq!(export.skipped, 2, "an empty day and the total line");
+    }
+
+    #[test]
+    fn timestamped_csv_becomes_samples() {
+        let content = "time,SOC (%),PV (W),Grid power [kW],\"Battery power (W)\"\n\
+                       1709283600,55,820,-0.3,400\n\
+                       1709283900000,56,,0.1,\n";
+        let export = parse(content).unwrap();
+        assert!(export.days.is_empty());
+        let (at, first) = &export.samples[0];
+        assert_eq!(*at, 1_709_283_600);
+        assert_eq!((first.soc, first.pv_power, first.grid_power, first.battery_power), (Some(55), Some(820.0), Some(-300.0), Some(400.0)));
+        assert_eq!(export.samples[1].0, 1_709_283_900);
+        assert_eq!(export.samples[1].1.pv_power, None);
+    }
+
+    #[test]
+    fn local_date_times_are_read_in_local_time() {
+        let export = parse("Date;SOC\n01.03.2024 14:30;60\n").unwrap();
+        let expected = Local.with_ymd_and_hms(2024, 3, 1, 14, 30, 0).earliest().unwrap().timestamp();
+        assert_eq!(export.samples[0].0, expected);
+    }
+
+    #[test]
+    fn json_records_like_the_cloud() {
+        let content = r#"{"code": 1, "data": [
+            {"day": "2024-03-01", "pv_energy": 10.0, "grid_in": 2, "grid_out": "0.5", "charge": 4, "discharge": 3},
+            {"day": "yesterday", "pv_energy": 1}
+        ]}"#;
+        let export = parse(content).unwrap();
+        assert_eq!(export.days.len(), 1);
+        assert_eq!((export.days[0].pv_wh, export.days[0].export_wh), (10_000.0, 500.0));
+        assert_eq!(export.skipped, 1);
+    }
+
+    #[test]
+    fn exports_without_usable_rows_are_rejected() {
+        assert!(parse("name;value\nfoo;1\n").is_err());
+        assert!(parse("date;pv\nnot a date;1\n").is_err());
+        assert!(parse("{}").is_err());
+    }
+}
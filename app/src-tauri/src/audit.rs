@@ -0,0 +1,273 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandSource {
+    Ui,
+    Automation,
+    Mqtt,
+    Api,
+    Cli,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: String,
+    pub source: CommandSource,
+    pub device: String,
+    pub method: String,
+    pub params: serde_json::Value,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+// Filtres de get_command_log ; tous facultatifs
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CommandLogQuery {
+    // Adresse de l'appareil telle qu'enregistrée dans l'entrée
+    pub device: Option<String>,
+    pub source: Option<CommandSource>,
+    pub method: Option<String>,
+    // Bornes RFC 3339, incluses
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub errors_only: bool,
+    // Entrées les plus récentes conservées après filtrage
+    pub limit: Option<usize>,
+}
+
+impl CommandLogQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let at = chrono::DateTime::parse_from_rfc3339(&entry.timestamp).ok();
+        let bound = |value: &Option<String>| value.as_deref().and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok());
+        self.device.as_ref().is_none_or(|d| *d == entry.device)
+            && self.source.is_none_or(|s| s == entry.source)
+            && self.method.as_ref().is_none_or(|m| *m == entry.method)
+            && bound(&self.since).is_none_or(|since| at.is_some_and(|at| at >= since))
+            && bound(&self.until).is_none_or(|until| at.is_some_and(|at| at <= until))
+            && (!self.errors_only || entry.error.is_some())
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        for value in [&self.since, &self.until].into_iter().flatten() {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .map_err(|_| AppError::InvalidInput(format!("Invalid date: {} (expected RFC 3339)", value)))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+    // false si une ligne a été modifiée ou supprimée
+    pub chain_valid: bool,
+}
+
+struct AuditState {
+    seq: u64,
+    last_hash: String,
+    // Taille du fichier après notre dernière écriture : marstip-cli peut y ajouter des entrées
+    len: u64,
+}
+
+fn file_len(path: &PathBuf) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+impl AuditState {
+    fn load(path: &PathBuf) -> Self {
+        let last = read_entries(path).0.pop();
+        Self {
+            seq: last.as_ref().map(|e| e.seq + 1).unwrap_or(0),
+            last_hash: last.map(|e| e.hash).unwrap_or_default(),
+            len: file_len(path),
+        }
+    }
+}
+
+// Journal chaîné : chaque entrée contient le hash de la précédente
+pub struct AuditTrail {
+    path: PathBuf,
+    state: Mutex<AuditState>,
+}
+
+fn entry_hash(entry: &AuditEntry) -> String {
+    let mut unsigned = entry.clone();
+    unsigned.hash = String::new();
+    let payload = serde_json::to_string(&unsigned).unwrap_or_default();
+    format!("{:x}", Sha256::digest(payload.as_bytes()))
+}
+
+// Entrées lisibles, et false si une ligne ne se relit pas (altérée ou tronquée)
+fn read_entries(path: &PathBuf) -> (Vec<AuditEntry>, bool) {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let mut intact = true;
+    let entries = content
+        .lines()
+        .filter_map(|line| {
+            let entry = serde_json::from_str(line).ok();
+            intact &= entry.is_some();
+            entry
+        })
+        .collect();
+    (entries, intact)
+}
+
+impl AuditTrail {
+    pub fn open(path: PathBuf) -> Self {
+        let state = AuditState::load(&path);
+        Self { path, state: Mutex::new(state) }
+    }
+
+    pub fn record(
+        &self,
+        source: CommandSource,
+        device: &str,
+        method: &str,
+        params: &serde_json::Value,
+        outcome: &Result<serde_json::Value, AppError>,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        // Fichier modifié par un autre processus : on repart de sa dernière entrée
+        if file_len(&self.path) != state.len {
+            *state = AuditState::load(&self.path);
+        }
+        let mut entry = AuditEntry {
+            seq: state.seq,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            source,
+            device: device.to_string(),
+            method: method.to_string(),
+            params: params.clone(),
+            result: outcome.as_ref().ok().cloned(),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+            prev_hash: state.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&entry);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).map_err(|e| e.to_string())?;
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+
+        state.seq += 1;
+        state.last_hash = entry.hash;
+        state.len = file_len(&self.path);
+        Ok(())
+    }
+
+    pub fn read(&self, limit: Option<usize>) -> Result<AuditLog, String> {
+        self.query(&CommandLogQuery { limit, ..Default::default() })
+    }
+
+    // La chaîne est vérifiée sur tout le fichier, avant filtrage
+    pub fn query(&self, query: &CommandLogQuery) -> Result<AuditLog, String> {
+        let _state = self.state.lock().map_err(|e| e.to_string())?;
+        let (entries, intact) = read_entries(&self.path);
+
+        let mut prev_hash = String::new();
+        let chain_valid = intact && entries.iter().all(|entry| {
+            let ok = entry.prev_hash == prev_hash && entry.hash == entry_hash(entry);
+            prev_hash = entry.hash.clone();
+            ok
+        });
+
+        let entries: Vec<AuditEntry> = entries.into_iter().filter(|e| query.matches(e)).collect();
+        let skip = query.limit.map(|l| entries.len().saturating_sub(l)).unwrap_or(0);
+        Ok(AuditLog {
+            entries: entries.into_iter().skip(skip).collect(),
+            chain_valid,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trail_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marstip-audit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(AUDIT_FILE)
+    }
+
+    fn record(trail: &AuditTrail, source: CommandSource, method: &str) {
+        trail.record(source, "192.168.1.20", method, &serde_json::json!({ "id": 0 }), &Ok(serde_json::json!({ "set_result": true }))).unwrap();
+    }
+
+    #[test]
+    fn entries_from_another_process_keep_the_chain() {
+        let path = trail_path("shared");
+        let app = AuditTrail::open(path.clone());
+        record(&app, CommandSource::Ui, "ES.SetMode");
+        // marstip-cli ouvre le même fichier pendant que l'application tourne
+        record(&AuditTrail::open(path.clone()), CommandSource::Cli, "ES.SetMode");
+        record(&app, CommandSource::Automation, "ES.SetMode");
+
+        let log = app.read(None).unwrap();
+        assert!(log.chain_valid);
+        assert_eq!(log.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(log.entries[1].source == CommandSource::Cli);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    // Trois entrées, puis le contenu du fichier réécrit par `tamper`
+    fn tampered(name: &str, tamper: impl Fn(Vec<String>) -> Vec<String>) -> AuditLog {
+        let path = trail_path(name);
+        let trail = AuditTrail::open(path.clone());
+        for method in ["ES.SetMode", "ES.SetBackup", "ES.SetMode"] {
+            record(&trail, CommandSource::Ui, method);
+        }
+        assert!(trail.read(None).unwrap().chain_valid);
+        let lines = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        fs::write(&path, tamper(lines).join("\n") + "\n").unwrap();
+        let log = trail.read(None).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        log
+    }
+
+    #[test]
+    fn modified_entry_breaks_the_chain() {
+        let log = tampered("modified", |mut lines| {
+            lines[1] = lines[1].replace("ES.SetBackup", "ES.SetMode");
+            lines
+        });
+        assert!(!log.chain_valid);
+    }
+
+    #[test]
+    fn removed_entry_breaks_the_chain() {
+        let log = tampered("removed", |mut lines| {
+            lines.remove(1);
+            lines
+        });
+        assert_eq!(log.entries.len(), 2);
+        assert!(!log.chain_valid);
+    }
+
+    #[test]
+    fn garbled_line_breaks_the_chain() {
+        let log = tampered("garbled", |mut lines| {
+            lines[1].truncate(20);
+            lines
+        });
+        assert_eq!(log.entries.len(), 2);
+        assert!(!log.chain_valid);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::error::AppError;
+use crate::schedule::{self, ManualSlot};
+use crate::tariff::DayPrices;
+use chrono::{DateTime, Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// Cadence de vérification du plan : le changement d'heure est pris au plus une minute en retard
+const TICK_S: u64 = 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanExecutor {
+    // Consigne Passive heure par heure : les limites de SOC sont surveillées en continu
+    Passive,
+    // Plages Manual écrites une fois par jour : l'appareil suit le plan seul, sans garde de SOC
+    Manual,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PriceRules {
+    pub enabled: bool,
+    // Appareil piloté ; absent : appareil courant
+    pub device: Option<String>,
+    pub executor: PlanExecutor,
+    // Charge pendant les N heures les moins chères...
+    pub charge_hours: u32,
+    pub charge_power_w: u32,
+    // ...si elles coûtent au plus ce prix, [devise/kWh]
+    pub charge_max_price: Option<f64>,
+    // Décharge pendant les M heures les plus chères...
+    pub discharge_hours: u32,
+    pub discharge_power_w: u32,
+    // ...si elles coûtent au moins ce prix, [devise/kWh]
+    pub discharge_min_price: Option<f64>,
+    // Jamais de décharge à ce SOC ou en dessous, [%]
+    pub min_soc: u32,
+    // Pas de charge à ce SOC ou au-dessus, [%]
+    pub max_soc: u32,
+}
+
+impl Default for PriceRules {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            executor: PlanExecutor::Passive,
+            charge_hours: 3,
+            charge_power_w: 2500,
+            charge_max_price: None,
+            discharge_hours: 3,
+            discharge_power_w: 800,
+            discharge_min_price: None,
+            min_soc: 15,
+            max_soc: 100,
+        }
+    }
+}
+
+impl PriceRules {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.charge_hours + self.discharge_hours > 24 {
+            return Err(AppError::InvalidInput("charge_hours + discharge_hours cannot exceed 24".to_string()));
+        }
+        if self.min_soc >= self.max_soc || self.max_soc > 100 {
+            return Err(AppError::InvalidInput(format!("Invalid SOC window {}-{} %", self.min_soc, self.max_soc)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Charge,
+    Discharge,
+    // Mode d'origine de l'appareil (Auto en général)
+    Idle,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PlannedHour {
+    pub start: String,
+    pub hour: u32,
+    pub price: f64,
+    pub action: PlanAction,
+    // > 0 en décharge, < 0 en charge, [W]
+    pub power: i64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Plan {
+    pub day: String,
+    pub currency: String,
+    pub hours: Vec<PlannedHour>,
+    // Traduction en plages Manual (exécuteur manual)
+    pub slots: Vec<ManualSlot>,
+    pub warnings: Vec<String>,
+}
+
+impl Plan {
+    pub fn action_at(&self, hour: u32) -> Option<&PlannedHour> {
+        self.hours.iter().find(|h| h.hour == hour)
+    }
+}
+
+pub fn plan(rules: &PriceRules, prices: &DayPrices) -> Plan {
+    let mut hours: Vec<PlannedHour> = prices
+        .prices
+        .iter()
+        .filter_map(|p| {
+            let start = DateTime::parse_from_rfc3339(&p.start).ok()?;
+            Some(PlannedHour { start: p.start.clone(), hour: start.hour(), price: p.price, action: PlanAction::Idle, power: 0 })
+        })
+        .collect();
+    let mut warnings = Vec::new();
+
+    let mut by_price: Vec<usize> = (0..hours.len()).collect();
+    by_price.sort_by(|a, b| hours[*a].price.total_cmp(&hours[*b].price));
+    let charge: Vec<usize> = by_price
+        .iter()
+        .copied()
+        .filter(|i| rules.charge_max_price.is_none_or(|max| hours[*i].price <= max))
+        .take(rules.charge_hours as usize)
+        .collect();
+    // Une heure de décharge doit rapporter plus que la plus chère des heures de charge
+    let charge_ceiling = charge.iter().map(|i| hours[*i].price).fold(f64::NEG_INFINITY, f64::max);
+    let discharge: Vec<usize> = by_price
+        .iter()
+        .rev()
+        .copied()
+        .filter(|i| !charge.contains(i))
+        .filter(|i| rules.discharge_min_price.is_none_or(|min| hours[*i].price >= min))
+        .filter(|i| hours[*i].price > charge_ceiling)
+        .take(rules.discharge_hours as usize)
+        .collect();
+    for i in charge {
+        hours[i].action = PlanAction::Charge;
+        hours[i].power = -i64::from(rules.charge_power_w);
+    }
+    for i in discharge {
+        hours[i].action = PlanAction::Discharge;
+        hours[i].power = i64::from(rules.discharge_power_w);
+    }
+    if hours.len() < 24 {
+        warnings.push(format!("Only {} hourly prices available for {}", hours.len(), prices.day));
+    }
+
+    // Heures consécutives de même action regroupées en une plage, sur le seul jour planifié
+    let week_set = chrono::NaiveDate::parse_from_str(&prices.day, "%Y-%m-%d")
+        .map(|d| 1u8 << d.weekday().num_days_from_monday())
+        .unwrap_or(127);
+    let mut slots: Vec<ManualSlot> = Vec::new();
+    let mut active = hours.iter().filter(|h| h.action != PlanAction::Idle).peekable();
+    while let Some(first) = active.next() {
+        let mut end = first.hour + 1;
+        while let Some(next) = active.peek().filter(|n| n.hour == end && n.power == first.power) {
+            end = next.hour + 1;
+            active.next();
+        }
+        slots.push(ManualSlot {
+            time_num: slots.len() as u8,
+            start_time: format!("{:02}:00", first.hour),
+            end_time: format!("{:02}:00", end),
+            week_set,
+            power: first.power,
+            enable: 1,
+        });
+    }
+    if slots.len() > schedule::MAX_SLOTS as usize {
+        warnings.push(format!("Plan needs {} slots, only the first {} fit in manual mode", slots.len(), schedule::MAX_SLOTS));
+        slots.truncate(schedule::MAX_SLOTS as usize);
+    }
+
+    Plan { day: prices.day.clone(), currency: prices.currency.clone(), hours, slots, warnings }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct AutomationStatus {
+    pub day: Option<String>,
+    pub action: Option<PlanAction>,
+    // Période du calendrier d'exceptions appliquée aujourd'hui
+    pub exception: Option<String>,
+    pub last_error: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+#[derive(Default)]
+struct EngineState {
+    plan: Option<Plan>,
+    // Action en cours appliquée par le moteur (exécuteur passive)
+    applied: Option<PlanAction>,
+    // Jour dont les plages ont été écrites (exécuteur manual)
+    written_day: Option<String>,
+    status: AutomationStatus,
+}
+
+pub struct PriceAutomation {
+    state: Mutex<EngineState>,
+    wake: Condvar,
+}
+
+impl Default for PriceAutomation {
+    fn default() -> Self {
+        Self { state: Mutex::new(EngineState::default()), wake: Condvar::new() }
+    }
+}
+
+impl PriceAutomation {
+    pub fn status(&self) -> AutomationStatus {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).status.clone()
+    }
+
+    // Règles modifiées : plan recalculé et réappliqué au prochain tour
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.plan = None;
+        state.written_day = None;
+        self.wake.notify_all();
+    }
+
+    pub fn wait_tick(&self) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self.wake.wait_timeout(state, Duration::from_secs(TICK_S));
+    }
+
+    pub fn plan_for(&self, day: &str) -> Option<Plan> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).plan.clone().filter(|p| p.day == day)
+    }
+
+    pub fn set_plan(&self, plan: Plan) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).plan = Some(plan);
+    }
+
+    pub fn applied(&self) -> Option<PlanAction> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).applied
+    }
+
+    pub fn set_applied(&self, action: Option<PlanAction>) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).applied = action;
+    }
+
+    pub fn written_day(&self) -> Option<String> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).written_day.clone()
+    }
+
+    pub fn set_written_day(&self, day: String) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).written_day = Some(day);
+    }
+
+    pub fn record(&self, status: AutomationStatus) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).status = status;
+    }
+}
@@ -0,0 +1,75 @@
+use crate::error::AppError;
+use crate::schedule::{validate_slots, ManualSlot};
+use marstek_protocol::PowerLimits;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::BTreeMap;
+
+// Version du format de fichier ; un fichier plus récent est refusé
+pub const BACKUP_FORMAT: u32 = 1;
+
+// Réglages conservés par l'appareil, pour remettre en état une unité réinitialisée ou remplacée.
+// Une section illisible au moment de la sauvegarde reste absente et son erreur est notée.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeviceBackup {
+    pub format: u32,
+    // UTC ISO-8601
+    pub created_at: String,
+    // Appareil d'origine, à titre indicatif
+    pub device: Option<String>,
+    pub model: Option<String>,
+    pub firmware: Option<u32>,
+    pub mode: Option<String>,
+    // Plages Manual, rétablies même si le mode sauvegardé est un autre
+    #[serde(default)]
+    pub slots: Vec<ManualSlot>,
+    // Modbus TCP uniquement
+    pub power_limits: Option<PowerLimits>,
+    // 0 : CT Marstek, 1 : compteur externe
+    pub meter_mode: Option<u32>,
+    pub backup_output: Option<bool>,
+    // Chauffage automatique de la batterie, modèles équipés seulement
+    pub auto_heat: Option<bool>,
+    // Le mot de passe n'est jamais lu : le Wi-Fi se rétablit avec set_wifi_config
+    pub wifi_name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+impl DeviceBackup {
+    pub fn parse(content: &str) -> Result<Self, AppError> {
+        let backup: Self = serde_json::from_str(content).map_err(|e| AppError::ParseError(format!("Invalid device backup: {}", e)))?;
+        if backup.format > BACKUP_FORMAT {
+            return Err(AppError::InvalidInput(format!("Device backup format {} is not supported", backup.format)));
+        }
+        validate_slots(&backup.slots)?;
+        if backup.meter_mode.is_some_and(|mode| mode > 1) {
+            return Err(AppError::InvalidInput("Device backup has an unknown meter mode".to_string()));
+        }
+        Ok(backup)
+    }
+}
+
+// Par section : rétablie, ignorée (absente de la sauvegarde, non applicable) ou en échec
+#[derive(Serialize, Clone, Default)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    pub skipped: BTreeMap<String, String>,
+    pub failed: BTreeMap<String, String>,
+}
+
+impl RestoreReport {
+    pub fn record(&mut self, section: &str, result: Result<(), AppError>) {
+        match result {
+            Ok(()) => self.restored.push(section.to_string()),
+            Err(e) => {
+                self.failed.insert(section.to_string(), e.to_string());
+            }
+        }
+    }
+
+    pub fn skip(&mut self, section: &str, reason: &str) {
+        self.skipped.insert(section.to_string(), reason.to_string());
+    }
+}
@@ -0,0 +1,237 @@
+// Client en ligne de commande, sans interface graphique (Raspberry Pi, cron, scripts)
+use marstip_lib::client::{
+    app_data_dir, validate_slots, AppError, AuditTrail, CommandSource, ConnectionSettings, DeviceClient, DiscoveryConfig,
+    ManualSlot, ModeRequest, PassiveConfig, ProtocolVariant, Replay, UdpTransport, AUDIT_FILE, DEFAULT_CD_TIME, DEFAULT_PORT,
+};
+use marstek_protocol::methods;
+use marstek_protocol::simulator;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: marstip-cli <command> [options]
+
+Commands:
+  discover [--subnet CIDR] [--json]
+  dashboard [--ip IP] [--port PORT] [--json]
+  set-mode <auto|ai|manual|passive> [--ip IP] [--port PORT]
+           manual:  --file schedule.json (array of manual_cfg slots)
+           passive: --power W [--cd-time S]
+  watch [--ip IP] [--port PORT] [--interval-ms MS]
+  replay --file capture.jsonl [--target IP] [--json]
+         serves a recorded capture on the loopback and parses it like a live device
+
+Options:
+  --capture FILE   append every request/response pair to FILE (JSON Lines)
+  --audit FILE     command audit log written by set-mode (default: the app's audit.jsonl)";
+
+// --option valeur, et drapeaux sans valeur (--json)
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, AppError> {
+        let mut parsed = Args { positional: Vec::new(), options: HashMap::new(), flags: Vec::new() };
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+            match name {
+                "json" | "help" => parsed.flags.push(name.to_string()),
+                _ => {
+                    let value = args.next().ok_or_else(|| AppError::InvalidInput(format!("Missing value for --{}", name)))?;
+                    parsed.options.insert(name.to_string(), value);
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn option<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, AppError> {
+        self.options
+            .get(name)
+            .map(|v| v.parse().map_err(|_| AppError::InvalidInput(format!("Invalid value for --{}: {}", name, v))))
+            .transpose()
+    }
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<(), AppError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// --ip absent : premier appareil trouvé par diffusion
+fn target(client: &DeviceClient, args: &Args) -> Result<(String, u16), AppError> {
+    let port = args.option("port")?.unwrap_or(DEFAULT_PORT);
+    if let Some(ip) = args.options.get("ip") {
+        return Ok((ip.clone(), port));
+    }
+    let device = client
+        .discover(&DiscoveryConfig::default(), None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::InvalidInput("No device found; pass --ip".to_string()))?;
+    Ok((device.ip, device.port))
+}
+
+fn discover(client: &DeviceClient, args: &Args) -> Result<(), AppError> {
+    let devices = client.discover(&DiscoveryConfig::default(), args.options.get("subnet").cloned())?;
+    if args.flag("json") {
+        return print_json(&devices);
+    }
+    if devices.is_empty() {
+        println!("No device found");
+    }
+    for device in devices {
+        println!("{}:{}\t{}", device.ip, device.port, device.device.as_deref().unwrap_or("?"));
+    }
+    Ok(())
+}
+
+fn dashboard(client: &DeviceClient, args: &Args) -> Result<(), AppError> {
+    let (ip, port) = target(client, args)?;
+    let status = client.status(&ip, port, &ProtocolVariant::default())?;
+    if args.flag("json") {
+        return print_json(&status);
+    }
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    println!("Device   {} ({})", or_dash(status.device.device.clone()), ip);
+    println!("Mode     {}", or_dash(status.mode.mode.clone()));
+    println!("SOC      {} %", or_dash(status.battery.soc.or(status.energy.bat_soc).map(|v| v.to_string())));
+    println!("Battery  {} W", or_dash(status.energy.bat_power.map(|v| format!("{:.0}", v))));
+    println!("PV       {} W", or_dash(status.energy.pv_power.map(|v| format!("{:.0}", v))));
+    println!("Grid     {} W", or_dash(status.meter.as_ref().and_then(|m| m.total_power).map(|v| format!("{:.0}", v))));
+    for (section, error) in &status.errors {
+        eprintln!("{}: {}", section, error);
+    }
+    Ok(())
+}
+
+fn set_mode(client: &DeviceClient, args: &Args) -> Result<(), AppError> {
+    let mode = args.positional.get(1).map(|m| m.to_lowercase()).ok_or_else(|| AppError::InvalidInput("Missing mode".to_string()))?;
+    let requests = match mode.as_str() {
+        "auto" => vec![ModeRequest::Auto],
+        "ai" => vec![ModeRequest::Ai],
+        "manual" => {
+            let path = args.options.get("file").ok_or_else(|| AppError::InvalidInput("manual requires --file".to_string()))?;
+            let slots: Vec<ManualSlot> = serde_json::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| AppError::InvalidInput(format!("Invalid schedule file: {}", e)))?;
+            validate_slots(&slots)?;
+            slots.into_iter().map(ModeRequest::Manual).collect()
+        }
+        "passive" => {
+            let power = args.option("power")?.ok_or_else(|| AppError::InvalidInput("passive requires --power".to_string()))?;
+            let passive = PassiveConfig { power, cd_time: args.option("cd-time")?.unwrap_or(DEFAULT_CD_TIME) };
+            passive.validate()?;
+            vec![ModeRequest::Passive(passive)]
+        }
+        _ => return Err(AppError::InvalidInput(format!("Unknown mode: {}", mode))),
+    };
+
+    let (ip, port) = target(client, args)?;
+    // Même journal que l'application : les commandes du CLI apparaissent dans get_command_log
+    let audit_path = match args.options.get("audit") {
+        Some(path) => Some(path.into()),
+        None => app_data_dir().map(|dir| dir.join(AUDIT_FILE)),
+    };
+    let audit = audit_path.map(AuditTrail::open);
+    // Une requête par plage : ES.SetMode n'accepte qu'un manual_cfg à la fois
+    for request in &requests {
+        let result = client.set_mode(&ip, port, &ProtocolVariant::default(), request);
+        if let Some(audit) = &audit {
+            let outcome = result.clone().map(|accepted| serde_json::json!({ "set_result": accepted }));
+            if let Err(e) = audit.record(CommandSource::Cli, &ip, methods::ES_SET_MODE, &request.params(), &outcome) {
+                eprintln!("warning: failed to write audit entry: {}", e);
+            }
+        }
+        if !result? {
+            return Err(AppError::DeviceRejected { code: 0, message: format!("{} mode was not accepted", mode) });
+        }
+    }
+    println!("{} mode set on {}", mode, ip);
+    Ok(())
+}
+
+// Une ligne JSON par relevé, jusqu'à Ctrl-C
+fn watch(client: &DeviceClient, args: &Args) -> Result<(), AppError> {
+    let interval = Duration::from_millis(args.option("interval-ms")?.unwrap_or(5000));
+    let (ip, port) = target(client, args)?;
+    loop {
+        match client.status(&ip, port, &ProtocolVariant::default()) {
+            Ok(status) => {
+                let line = serde_json::json!({ "timestamp": chrono::Local::now().to_rfc3339(), "status": status });
+                println!("{}", line);
+            }
+            Err(e) => eprintln!("{}: {}", chrono::Local::now().to_rfc3339(), e),
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+// Rejoue une capture reçue d'un utilisateur : mêmes décodages que pour un appareil réel, sans matériel
+fn replay(client: &DeviceClient, args: &Args) -> Result<(), AppError> {
+    let path = args.options.get("file").ok_or_else(|| AppError::InvalidInput("replay requires --file".to_string()))?;
+    let replay = Replay::load(Path::new(path), args.options.get("target").map(String::as_str))?;
+    eprintln!("replaying {}", replay.methods().join(", "));
+    let server = simulator::serve(Arc::new(replay), 0)?;
+    let status = client.status("127.0.0.1", server.addr().port(), &ProtocolVariant::default())?;
+    if args.flag("json") {
+        return print_json(&status);
+    }
+    println!("{:#?}", status.device);
+    println!("{:#?}", status.battery);
+    println!("{:#?}", status.energy);
+    println!("{:#?}", status.mode);
+    println!("{:#?}", status.meter);
+    println!("{:#?}", status.wifi);
+    for (section, error) in &status.errors {
+        eprintln!("{}: {}", section, error);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let transport = UdpTransport::default();
+    if let Some(path) = args.options.get("capture") {
+        if let Err(e) = transport.traffic.start_capture(Path::new(path)) {
+            eprintln!("error: cannot capture to {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    let client = DeviceClient::new(&transport, ConnectionSettings::default());
+    let result = match args.positional.first().map(String::as_str) {
+        Some("discover") if !args.flag("help") => discover(&client, &args),
+        Some("dashboard") if !args.flag("help") => dashboard(&client, &args),
+        Some("set-mode") if !args.flag("help") => set_mode(&client, &args),
+        Some("watch") if !args.flag("help") => watch(&client, &args),
+        Some("replay") if !args.flag("help") => replay(&client, &args),
+        _ => {
+            println!("{}", USAGE);
+            return ExitCode::from(if args.flag("help") { 0 } else { 2 });
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::error::AppError;
+use btleplug::api::bleuuid::uuid_from_u16;
+use btleplug::api::{Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, ValueNotification, WriteType};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+// Service GATT des batteries Marstek : requêtes JSON-RPC écrites sur FF01, réponses notifiées sur FF02
+const SERVICE: u16 = 0xFF00;
+const WRITE: u16 = 0xFF01;
+const NOTIFY: u16 = 0xFF02;
+// ATT MTU par défaut (23) moins l'en-tête : taille sûre sans négociation
+const CHUNK_SIZE: usize = 20;
+// Noms annoncés : MST_VNSE3_... (Venus), HMA-... / HMJ-... (B2500)
+const NAME_PREFIXES: [&str; 2] = ["MST", "HM"];
+pub const MAX_SCAN_MS: u64 = 30_000;
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct BleDevice {
+    // Adresse BLE, sert d'identifiant à connect_ble
+    pub address: String,
+    pub name: Option<String>,
+    pub rssi: Option<i16>,
+}
+
+type Notifications = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+struct Link {
+    peripheral: Peripheral,
+    write: Characteristic,
+    notifications: Notifications,
+    address: String,
+}
+
+// Une seule connexion à la fois, requêtes sérialisées
+#[derive(Default)]
+pub struct BleTransport {
+    link: tokio::sync::Mutex<Option<Link>>,
+    next_id: AtomicU32,
+}
+
+fn ble_error(e: btleplug::Error) -> AppError {
+    AppError::IoError(format!("Bluetooth: {}", e))
+}
+
+async fn adapter() -> Result<Adapter, AppError> {
+    let manager = Manager::new().await.map_err(ble_error)?;
+    manager
+        .adapters()
+        .await
+        .map_err(ble_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotConfigured("No Bluetooth adapter found".to_string()))
+}
+
+async fn describe(peripheral: &Peripheral) -> Option<BleDevice> {
+    let properties = peripheral.properties().await.ok()??;
+    let marstek = properties.services.contains(&uuid_from_u16(SERVICE))
+        || properties.local_name.as_deref().is_some_and(|name| NAME_PREFIXES.iter().any(|p| name.starts_with(p)));
+    marstek.then(|| BleDevice { address: properties.address.to_string(), name: properties.local_name, rssi: properties.rssi })
+}
+
+async fn scan(adapter: &Adapter, window: Duration) -> Result<Vec<(Peripheral, BleDevice)>, AppError> {
+    adapter.start_scan(ScanFilter::default()).await.map_err(ble_error)?;
+    tokio::time::sleep(window).await;
+    let _ = adapter.stop_scan().await;
+    let mut found = Vec::new();
+    for peripheral in adapter.peripherals().await.map_err(ble_error)? {
+        if let Some(device) = describe(&peripheral).await {
+            found.push((peripheral, device));
+        }
+    }
+    Ok(found)
+}
+
+impl BleTransport {
+    pub async fn discover(&self, window: Duration) -> Result<Vec<BleDevice>, AppError> {
+        let adapter = adapter().await?;
+        let mut devices: Vec<BleDevice> = scan(&adapter, window).await?.into_iter().map(|(_, d)| d).collect();
+        devices.sort_by_key(|d| std::cmp::Reverse(d.rssi));
+        Ok(devices)
+    }
+
+    // Remplace la connexion en cours ; l'appareil doit avoir été vu par un scan récent
+    pub async fn connect(&self, address: &str, window: Duration) -> Result<BleDevice, AppError> {
+        self.disconnect().await;
+        let adapter = adapter().await?;
+        let (peripheral, device) = scan(&adapter, window)
+            .await?
+            .into_iter()
+            .find(|(_, d)| d.address.eq_ignore_ascii_case(address))
+            .ok_or_else(|| AppError::NotConfigured(format!("Bluetooth device {} not found", address)))?;
+
+        peripheral.connect().await.map_err(ble_error)?;
+        peripheral.discover_services().await.map_err(ble_error)?;
+        let characteristic = |uuid: u16| {
+            peripheral
+                .characteristics()
+                .into_iter()
+                .find(|c| c.uuid == uuid_from_u16(uuid))
+                .ok_or_else(|| AppError::NotConfigured(format!("{} does not expose characteristic {:04X}", address, uuid)))
+        };
+        let (write, notify) = (characteristic(WRITE)?, characteristic(NOTIFY)?);
+        peripheral.subscribe(&notify).await.map_err(ble_error)?;
+        let notifications = peripheral.notifications().await.map_err(ble_error)?;
+
+        *self.link.lock().await = Some(Link { peripheral, write, notifications, address: device.address.clone() });
+        Ok(device)
+    }
+
+    pub async fn disconnect(&self) {
+        if let Some(link) = self.link.lock().await.take() {
+            let _ = link.peripheral.disconnect().await;
+        }
+    }
+
+    pub async fn connected(&self) -> Option<String> {
+        self.link.lock().await.as_ref().map(|l| l.address.clone())
+    }
+
+    // Même JSON-RPC qu'en UDP, découpé en paquets ; la réponse peut arriver en plusieurs notifications
+    pub async fn request(&self, method: &str, params: serde_json::Value, timeout: Duration) -> Result<serde_json::Value, AppError> {
+        let mut guard = self.link.lock().await;
+        let link = guard.as_mut().ok_or_else(|| AppError::NotConfigured("No Bluetooth device connected. Call connect_ble first.".to_string()))?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let message = marstek_protocol::encode_request(id, method, params)?;
+        for chunk in message.chunks(CHUNK_SIZE) {
+            link.peripheral.write(&link.write, chunk, WriteType::WithoutResponse).await.map_err(ble_error)?;
+        }
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut buffer = Vec::new();
+        loop {
+            let notification = tokio::time::timeout_at(deadline, link.notifications.next())
+                .await
+                .map_err(|_| AppError::Timeout(format!("{} timed out after {} ms over Bluetooth", method, timeout.as_millis())))?
+                .ok_or_else(|| AppError::IoError("Bluetooth device disconnected".to_string()))?;
+            if notification.uuid != uuid_from_u16(NOTIFY) {
+                continue;
+            }
+            buffer.extend_from_slice(&notification.value);
+            match serde_json::from_slice::<serde_json::Value>(&buffer) {
+                Ok(response) if response.get("id").and_then(|v| v.as_u64()) == Some(id as u64) => {
+                    return Ok(marstek_protocol::parse_response(method, response)?);
+                }
+                // Réponse tardive à une requête expirée
+                Ok(_) => buffer.clear(),
+                Err(e) if e.is_eof() => {}
+                Err(_) => {
+                    tracing::debug!("dropping unreadable Bluetooth notification");
+                    buffer.clear();
+                }
+            }
+        }
+    }
+}
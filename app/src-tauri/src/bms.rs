@@ -0,0 +1,137 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use serde_with::skip_serializing_none;
+use std::collections::BTreeMap;
+
+// Non documentée dans l'Open API : détail BMS exposé par certains firmwares seulement
+pub const BMS_METHOD: &str = "BMS.GetStatus";
+
+// Noms rencontrés selon les firmwares, nom canonique en premier
+const SOC: &[&str] = &["soc", "bat_soc"];
+const SOH: &[&str] = &["soh", "bat_soh"];
+const BAT_TEMP: &[&str] = &["bat_temp"];
+const BAT_VOLTAGE: &[&str] = &["bat_voltage", "bat_vol", "voltage"];
+const BAT_CURRENT: &[&str] = &["bat_current", "bat_cur", "current"];
+const BAT_CAPACITY: &[&str] = &["bat_capacity", "bat_cap"];
+const RATED_CAPACITY: &[&str] = &["rated_capacity"];
+const CYCLE_COUNT: &[&str] = &["cycle_count", "cycle_num", "cycles", "cycle"];
+const CELL_VOLTAGES: &[&str] = &["cell_voltages", "cell_vol", "cell_volt", "cells"];
+const CELL_TEMPS: &[&str] = &["cell_temps", "cell_temp", "temps"];
+const MAX_CELL_VOLTAGE: &[&str] = &["max_cell_voltage", "max_cell_vol", "cell_vol_max"];
+const MIN_CELL_VOLTAGE: &[&str] = &["min_cell_voltage", "min_cell_vol", "cell_vol_min"];
+const MAX_CELL_TEMP: &[&str] = &["max_cell_temp", "cell_temp_max", "max_temp"];
+const MIN_CELL_TEMP: &[&str] = &["min_cell_temp", "cell_temp_min", "min_temp"];
+// Déjà présents dans BatteryStatus, sans intérêt pour le diagnostic
+const IGNORED: &[&str] = &["id", "charg_flag", "dischrg_flag"];
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct BatteryDetails {
+    pub soc: Option<u32>,
+    // État de santé, [%]
+    pub soh: Option<u32>,
+    pub bat_temp: Option<f32>,
+    // [V]
+    pub bat_voltage: Option<f32>,
+    // [A] : > 0 en charge
+    pub bat_current: Option<f32>,
+    pub bat_capacity: Option<f32>,
+    pub rated_capacity: Option<f32>,
+    pub cycle_count: Option<u32>,
+    // [V], dans l'ordre du BMS
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cell_voltages: Vec<f32>,
+    pub max_cell_voltage: Option<f32>,
+    pub min_cell_voltage: Option<f32>,
+    // Écart entre cellules extrêmes, [mV]
+    pub cell_voltage_delta: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cell_temps: Vec<f32>,
+    pub max_cell_temp: Option<f32>,
+    pub min_cell_temp: Option<f32>,
+    // Champs renvoyés mais non reconnus, tels quels
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, Value>,
+    // Méthodes ayant répondu
+    pub sources: Vec<String>,
+}
+
+fn as_f32(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|v| v as f32),
+        // Certains firmwares renvoient des nombres en chaîne
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn field<'a>(map: &'a Map<String, Value>, names: &[&str]) -> Option<&'a Value> {
+    names.iter().find_map(|name| map.get(*name))
+}
+
+fn number(map: &Map<String, Value>, names: &[&str]) -> Option<f32> {
+    field(map, names).and_then(as_f32)
+}
+
+fn list(map: &Map<String, Value>, names: &[&str]) -> Vec<f32> {
+    match field(map, names) {
+        Some(Value::Array(values)) => values.iter().filter_map(as_f32).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Tensions de cellule en mV sur certains firmwares
+fn volts(value: f32) -> f32 {
+    if value > 100.0 { value / 1000.0 } else { value }
+}
+
+impl BatteryDetails {
+    // Réponse d'une méthode ajoutée aux détails : le premier champ trouvé l'emporte
+    pub fn merge(&mut self, method: &str, result: &Value) {
+        let Some(map) = result.as_object() else { return };
+        self.sources.push(method.to_string());
+        let count = |names: &[&str]| number(map, names).filter(|v| *v >= 0.0).map(|v| v.round() as u32);
+        self.soc = self.soc.or_else(|| count(SOC));
+        self.soh = self.soh.or_else(|| count(SOH));
+        self.cycle_count = self.cycle_count.or_else(|| count(CYCLE_COUNT));
+        self.bat_temp = self.bat_temp.or_else(|| number(map, BAT_TEMP));
+        self.bat_voltage = self.bat_voltage.or_else(|| number(map, BAT_VOLTAGE));
+        self.bat_current = self.bat_current.or_else(|| number(map, BAT_CURRENT));
+        self.bat_capacity = self.bat_capacity.or_else(|| number(map, BAT_CAPACITY));
+        self.rated_capacity = self.rated_capacity.or_else(|| number(map, RATED_CAPACITY));
+        self.max_cell_voltage = self.max_cell_voltage.or_else(|| number(map, MAX_CELL_VOLTAGE).map(volts));
+        self.min_cell_voltage = self.min_cell_voltage.or_else(|| number(map, MIN_CELL_VOLTAGE).map(volts));
+        self.max_cell_temp = self.max_cell_temp.or_else(|| number(map, MAX_CELL_TEMP));
+        self.min_cell_temp = self.min_cell_temp.or_else(|| number(map, MIN_CELL_TEMP));
+        if self.cell_voltages.is_empty() {
+            self.cell_voltages = list(map, CELL_VOLTAGES).into_iter().map(volts).collect();
+        }
+        if self.cell_temps.is_empty() {
+            self.cell_temps = list(map, CELL_TEMPS);
+        }
+
+        let known = [
+            SOC, SOH, BAT_TEMP, BAT_VOLTAGE, BAT_CURRENT, BAT_CAPACITY, RATED_CAPACITY, CYCLE_COUNT, CELL_VOLTAGES,
+            CELL_TEMPS, MAX_CELL_VOLTAGE, MIN_CELL_VOLTAGE, MAX_CELL_TEMP, MIN_CELL_TEMP, IGNORED,
+        ];
+        for (key, value) in map {
+            if !known.iter().any(|names| names.contains(&key.as_str())) {
+                self.extra.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+
+    // Extrêmes déduits des listes quand le firmware ne les donne pas
+    pub fn finish(mut self) -> Self {
+        let max = |values: &[f32]| values.iter().copied().reduce(f32::max);
+        let min = |values: &[f32]| values.iter().copied().reduce(f32::min);
+        self.max_cell_voltage = self.max_cell_voltage.or_else(|| max(&self.cell_voltages));
+        self.min_cell_voltage = self.min_cell_voltage.or_else(|| min(&self.cell_voltages));
+        self.max_cell_temp = self.max_cell_temp.or_else(|| max(&self.cell_temps));
+        self.min_cell_temp = self.min_cell_temp.or_else(|| min(&self.cell_temps));
+        if let (Some(max), Some(min)) = (self.max_cell_voltage, self.min_cell_voltage) {
+            self.cell_voltage_delta = Some(((max - min) * 1000.0).round());
+        }
+        self
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::AppError;
+use crate::schedule::{validate_slots, ManualSlot};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// Comportement des automatismes un jour d'exception
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ExceptionProfile {
+    // Aucune décharge : plages de décharge retirées, heures de décharge ramenées au repos
+    pub no_discharge: bool,
+    // SOC plancher de l'automatisme tarifaire (exécuteur passive), [%]
+    pub min_soc: Option<u32>,
+    // Plages Manual écrites à la place du planning du jour ; absentes : planning du jour ajusté
+    pub slots: Option<Vec<ManualSlot>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExceptionPeriod {
+    pub name: String,
+    // "YYYY-MM-DD", bornes incluses
+    pub from: String,
+    pub to: String,
+    // Chaque année aux mêmes dates (Noël...) ; la période peut alors enjamber le 1er janvier
+    #[serde(default)]
+    pub yearly: bool,
+    #[serde(default)]
+    pub profile: ExceptionProfile,
+}
+
+impl ExceptionPeriod {
+    fn bounds(&self) -> Result<(NaiveDate, NaiveDate), AppError> {
+        let parse = |value: &str| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| AppError::InvalidInput(format!("{}: invalid date {} (expected YYYY-MM-DD)", self.name, value)))
+        };
+        Ok((parse(&self.from)?, parse(&self.to)?))
+    }
+
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        let Ok((from, to)) = self.bounds() else { return false };
+        if !self.yearly {
+            return from <= day && day <= to;
+        }
+        let key = |d: NaiveDate| (d.month(), d.day());
+        let (from, to, day) = (key(from), key(to), key(day));
+        if from <= to {
+            from <= day && day <= to
+        } else {
+            day >= from || day <= to
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ExceptionCalendar {
+    pub enabled: bool,
+    pub periods: Vec<ExceptionPeriod>,
+}
+
+impl ExceptionCalendar {
+    pub fn validate(&self) -> Result<(), AppError> {
+        for period in &self.periods {
+            if period.name.trim().is_empty() {
+                return Err(AppError::InvalidInput("Exception period name is required".to_string()));
+            }
+            let (from, to) = period.bounds()?;
+            if !period.yearly && to < from {
+                return Err(AppError::InvalidInput(format!("{}: {} is before {}", period.name, period.to, period.from)));
+            }
+            if period.profile.min_soc.is_some_and(|soc| soc > 100) {
+                return Err(AppError::InvalidInput(format!("{}: minimum SOC must be between 0 and 100 %", period.name)));
+            }
+            if let Some(slots) = &period.profile.slots {
+                validate_slots(slots)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Première période qui couvre le jour, dans l'ordre de la liste
+    pub fn active(&self, day: NaiveDate) -> Option<&ExceptionPeriod> {
+        if !self.enabled {
+            return None;
+        }
+        self.periods.iter().find(|p| p.contains(day))
+    }
+}
+
+impl ExceptionProfile {
+    // Plages à écrire à la place de celles calculées par l'automatisme
+    pub fn adjust_slots(&self, slots: Vec<ManualSlot>) -> Vec<ManualSlot> {
+        let slots = self.slots.clone().unwrap_or(slots);
+        if !self.no_discharge {
+            return slots;
+        }
+        slots.into_iter().filter(|s| s.power <= 0).collect()
+    }
+}
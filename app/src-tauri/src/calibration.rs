@@ -0,0 +1,202 @@
+use crate::error::AppError;
+use marstek_protocol::MAX_FORCE_POWER;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// Relevés gardés en mémoire : deux jours à un relevé par minute
+const MAX_POINTS: usize = 2880;
+// En deçà, la batterie ne suit plus la consigne : BMS en butée, [W]
+const IDLE_POWER_W: f32 = 30.0;
+
+// Charge complète puis décharge profonde en mode Passive, pour recaler une jauge de SOC dérivée
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CalibrationRequest {
+    pub device: Option<String>,
+    // [W]
+    pub charge_power_w: u32,
+    pub discharge_power_w: u32,
+    // Durée sans courant batterie malgré la consigne avant de passer à la phase suivante, [s]
+    pub settle_s: u64,
+    // Au-delà, la phase est abandonnée et le mode d'origine rétabli, [h]
+    pub max_phase_h: u64,
+    pub sample_interval_s: u64,
+}
+
+impl Default for CalibrationRequest {
+    fn default() -> Self {
+        Self { device: None, charge_power_w: 1500, discharge_power_w: 800, settle_s: 600, max_phase_h: 24, sample_interval_s: 60 }
+    }
+}
+
+impl CalibrationRequest {
+    pub fn validate(&self) -> Result<(), AppError> {
+        for (name, power) in [("Charge", self.charge_power_w), ("Discharge", self.discharge_power_w)] {
+            if power == 0 || power > u32::from(MAX_FORCE_POWER) {
+                return Err(AppError::InvalidInput(format!("{} power must be between 1 and {} W", name, MAX_FORCE_POWER)));
+            }
+        }
+        if !(60..=3600).contains(&self.settle_s) {
+            return Err(AppError::InvalidInput("Settle time must be between 60 and 3600 s".to_string()));
+        }
+        if !(1..=48).contains(&self.max_phase_h) {
+            return Err(AppError::InvalidInput("Maximum phase duration must be between 1 and 48 h".to_string()));
+        }
+        if !(10..=600).contains(&self.sample_interval_s) {
+            return Err(AppError::InvalidInput("Sample interval must be between 10 and 600 s".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn max_phase(&self) -> Duration {
+        Duration::from_secs(self.max_phase_h * 3600)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CalibrationPhase {
+    Charging,
+    Discharging,
+    Restoring,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct CalibrationPoint {
+    // Secondes Unix
+    pub timestamp: i64,
+    pub phase: CalibrationPhase,
+    pub soc: Option<u32>,
+    // [V]
+    pub voltage: Option<f32>,
+    // [W] : > 0 en charge
+    pub battery_power: Option<f32>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct CalibrationStatus {
+    pub active: bool,
+    pub device: Option<String>,
+    pub phase: Option<CalibrationPhase>,
+    pub started_at: Option<i64>,
+    pub phase_started_at: Option<i64>,
+    pub error: Option<String>,
+    // Réduits au dernier relevé dans l'événement "calibration-progress"
+    pub points: Vec<CalibrationPoint>,
+}
+
+// Fin d'une phase : plus de courant batterie malgré la consigne pendant settle
+#[derive(Default)]
+pub struct Settle {
+    idle_since: Option<Instant>,
+}
+
+impl Settle {
+    pub fn observe(&mut self, battery_power: Option<f32>, settle: Duration) -> bool {
+        match battery_power {
+            Some(power) if power.abs() < IDLE_POWER_W => self.idle_since.get_or_insert_with(Instant::now).elapsed() >= settle,
+            _ => {
+                self.idle_since = None;
+                false
+            }
+        }
+    }
+}
+
+// Une calibration à la fois ; les automatismes restent suspendus tant qu'elle dure
+#[derive(Default)]
+pub struct Calibration {
+    status: Mutex<CalibrationStatus>,
+    wake: Condvar,
+    cancel: Mutex<bool>,
+}
+
+impl Calibration {
+    fn lock(&self) -> MutexGuard<'_, CalibrationStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn start(&self, device: &str) -> Result<(), AppError> {
+        let mut status = self.lock();
+        if let Some(current) = status.device.as_deref().filter(|_| status.active) {
+            return Err(AppError::Forbidden(format!("A calibration is already running on {}", current)));
+        }
+        let now = chrono::Utc::now().timestamp();
+        *status = CalibrationStatus {
+            active: true,
+            device: Some(device.to_string()),
+            phase: Some(CalibrationPhase::Charging),
+            started_at: Some(now),
+            phase_started_at: Some(now),
+            ..Default::default()
+        };
+        *self.cancel.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        Ok(())
+    }
+
+    pub fn active(&self) -> Option<String> {
+        let status = self.lock();
+        status.device.clone().filter(|_| status.active)
+    }
+
+    pub fn set_phase(&self, phase: CalibrationPhase) {
+        let mut status = self.lock();
+        status.phase = Some(phase);
+        status.phase_started_at = Some(chrono::Utc::now().timestamp());
+    }
+
+    pub fn record(&self, point: CalibrationPoint) {
+        let mut status = self.lock();
+        if status.points.len() >= MAX_POINTS {
+            status.points.remove(0);
+        }
+        status.points.push(point);
+    }
+
+    pub fn finish(&self, phase: CalibrationPhase, error: Option<String>) {
+        let mut status = self.lock();
+        status.active = false;
+        status.phase = Some(phase);
+        status.error = error;
+    }
+
+    pub fn cancel(&self) -> bool {
+        if self.active().is_none() {
+            return false;
+        }
+        *self.cancel.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.wake.notify_all();
+        true
+    }
+
+    // Attente entre deux relevés ; true : annulée entre-temps
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let cancel = self.cancel.lock().unwrap_or_else(|e| e.into_inner());
+        let (cancel, _) = self.wake.wait_timeout_while(cancel, timeout, |cancel| !*cancel).unwrap_or_else(|e| e.into_inner());
+        *cancel
+    }
+
+    pub fn status(&self) -> CalibrationStatus {
+        self.lock().clone()
+    }
+
+    pub fn progress(&self) -> CalibrationStatus {
+        let status = self.lock();
+        CalibrationStatus {
+            active: status.active,
+            device: status.device.clone(),
+            phase: status.phase,
+            started_at: status.started_at,
+            phase_started_at: status.phase_started_at,
+            error: status.error.clone(),
+            points: status.points.last().cloned().into_iter().collect(),
+        }
+    }
+}
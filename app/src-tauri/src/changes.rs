@@ -0,0 +1,109 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub const SOC_CHANGED: &str = "soc-changed";
+pub const MODE_CHANGED: &str = "mode-changed";
+pub const GRID_POWER_CHANGED: &str = "grid-power-changed";
+
+// Hystérésis des événements de changement : un écart plus faible ne produit rien
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ChangeConfig {
+    pub enabled: bool,
+    // [%]
+    pub soc_step: u32,
+    // [W]
+    pub grid_power_w: f32,
+}
+
+impl Default for ChangeConfig {
+    fn default() -> Self {
+        Self { enabled: true, soc_step: 1, grid_power_w: 50.0 }
+    }
+}
+
+impl ChangeConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.soc_step == 0 || self.soc_step > 100 {
+            return Err(AppError::InvalidInput("SOC change step must be between 1 and 100 %".to_string()));
+        }
+        if !self.grid_power_w.is_finite() || self.grid_power_w < 0.0 {
+            return Err(AppError::InvalidInput("Grid power hysteresis must be a positive number of watts".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ChangeEvent {
+    // Nom de l'événement Tauri (soc-changed...)
+    pub event: &'static str,
+    pub id: String,
+    pub previous: serde_json::Value,
+    pub value: serde_json::Value,
+}
+
+// Valeurs du dernier événement émis, pas du dernier relevé : une dérive lente finit par franchir le seuil
+#[derive(Default)]
+struct Reference {
+    soc: Option<u32>,
+    mode: Option<String>,
+    grid_power: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct ChangeDetector {
+    devices: Mutex<HashMap<String, Reference>>,
+}
+
+impl ChangeDetector {
+    // Premier relevé et valeurs absentes exclus : seule une vraie transition produit un événement
+    pub fn observe(
+        &self,
+        config: &ChangeConfig,
+        device: &str,
+        soc: Option<u32>,
+        mode: Option<&str>,
+        grid_power: Option<f32>,
+    ) -> Vec<ChangeEvent> {
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let reference = devices.entry(device.to_string()).or_default();
+        let mut events = Vec::new();
+        let mut push = |event, previous: serde_json::Value, value: serde_json::Value| {
+            events.push(ChangeEvent { event, id: device.to_string(), previous, value });
+        };
+
+        if let Some(soc) = soc {
+            match reference.soc {
+                Some(previous) if previous.abs_diff(soc) < config.soc_step => {}
+                previous => {
+                    reference.soc = Some(soc);
+                    if let Some(previous) = previous {
+                        push(SOC_CHANGED, previous.into(), soc.into());
+                    }
+                }
+            }
+        }
+        if let Some(mode) = mode {
+            if reference.mode.as_deref() != Some(mode) {
+                if let Some(previous) = reference.mode.replace(mode.to_string()) {
+                    push(MODE_CHANGED, previous.into(), mode.into());
+                }
+            }
+        }
+        if let Some(power) = grid_power {
+            match reference.grid_power {
+                Some(previous) if (previous - power).abs() <= config.grid_power_w => {}
+                previous => {
+                    reference.grid_power = Some(power);
+                    if let Some(previous) = previous {
+                        push(GRID_POWER_CHANGED, previous.into(), power.into());
+                    }
+                }
+            }
+        }
+        events
+    }
+}
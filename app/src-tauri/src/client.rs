@@ -0,0 +1,215 @@
+// Accès aux appareils sans Tauri : partagé par l'application et marstip-cli
+use crate::discovery;
+use crate::{BatteryStatus, DeviceInfo, EnergyStatus, MeterStatus, ModeStatus, WifiStatus};
+use marstek_protocol::methods;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use crate::audit::{AuditTrail, CommandSource, AUDIT_FILE};
+pub use crate::discovery::{DiscoveredDevice, DiscoveryConfig};
+pub use crate::error::AppError;
+pub use crate::passive::DEFAULT_CD_TIME;
+pub use crate::settings::ConnectionSettings;
+pub use marstek_protocol::{accepted, validate_slots, ManualSlot, ModeRequest, PassiveConfig, ProtocolVariant, Replay, UdpTransport, DEFAULT_PORT};
+
+// Identifiant Tauri (tauri.conf.json) : nom du dossier de données de l'application
+const APP_IDENTIFIER: &str = "com.jsys.marstip";
+
+// Même dossier que app_data_dir() côté Tauri, pour partager le journal d'audit
+pub fn app_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+// Lecture des cinq sections d'état ; une section en échec reste vide
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct DeviceStatus {
+    pub device: DeviceInfo,
+    pub battery: BatteryStatus,
+    pub energy: EnergyStatus,
+    pub mode: ModeStatus,
+    pub meter: Option<MeterStatus>,
+    pub wifi: WifiStatus,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+// Section de DeviceStatus -> méthode et paramètres
+pub fn status_queries() -> [(&'static str, &'static str, serde_json::Value); 6] {
+    [
+        ("device", methods::GET_DEVICE, methods::probe_params()),
+        ("energy", methods::ES_GET_STATUS, methods::status_params()),
+        ("battery", methods::BAT_GET_STATUS, methods::status_params()),
+        ("wifi", methods::WIFI_GET_STATUS, methods::status_params()),
+        ("mode", methods::ES_GET_MODE, methods::status_params()),
+        ("meter", methods::EM_GET_STATUS, methods::status_params()),
+    ]
+}
+
+impl DeviceStatus {
+    // (section, résultat) : "device", "energy", "battery", "wifi", "mode", "meter"
+    pub fn assemble<'s>(sections: impl IntoIterator<Item = (&'s str, Result<serde_json::Value, AppError>)>) -> Result<Self, AppError> {
+        let mut errors = BTreeMap::new();
+        let mut values = BTreeMap::new();
+        for (name, result) in sections {
+            match result {
+                Ok(value) => {
+                    values.insert(name, value);
+                }
+                Err(e) => {
+                    errors.insert(name.to_string(), e);
+                }
+            }
+        }
+        if values.is_empty() {
+            return Err(errors.into_values().next().unwrap_or_else(|| AppError::Internal("No response".to_string())));
+        }
+        fn section<T: serde::de::DeserializeOwned + Default>(values: &BTreeMap<&str, serde_json::Value>, name: &str) -> T {
+            values.get(name).and_then(|v| serde_json::from_value(v.clone()).ok()).unwrap_or_default()
+        }
+        let meter: Option<MeterStatus> = values.get("meter").and_then(|v| serde_json::from_value(v.clone()).ok());
+        Ok(DeviceStatus {
+            device: section(&values, "device"),
+            battery: section(&values, "battery"),
+            energy: section(&values, "energy"),
+            mode: section(&values, "mode"),
+            meter: meter.filter(|m| m.ct_state != Some(0)),
+            wifi: section(&values, "wifi"),
+            errors: errors.into_iter().map(|(name, e)| (name, e.to_string())).collect(),
+        })
+    }
+}
+
+pub struct DeviceClient<'a> {
+    transport: &'a UdpTransport,
+    connection: ConnectionSettings,
+}
+
+impl<'a> DeviceClient<'a> {
+    pub fn new(transport: &'a UdpTransport, connection: ConnectionSettings) -> Self {
+        Self { transport, connection }
+    }
+
+    pub fn call(&self, ip: &str, port: u16, method: &str, params: serde_json::Value) -> Result<serde_json::Value, AppError> {
+        let connection = &self.connection;
+        let target = discovery::endpoint(ip, port);
+        let timeout = connection.timeout_for(method);
+        let attempt = || {
+            tauri::async_runtime::block_on(self.transport.request(connection.local_port, &target, method, params.clone(), timeout))
+                .map_err(AppError::from)
+        };
+
+        let mut result = attempt();
+        // Un datagramme perdu ne doit pas faire échouer tout le rafraîchissement ;
+        // un refus explicite de l'appareil serait simplement répété
+        for retry in 1..=connection.retries {
+            let Err(e) = &result else { break };
+            if matches!(e, AppError::DeviceRejected { .. }) {
+                break;
+            }
+            if let Some(ip) = self.transport.links.address(&target) {
+                self.transport.links.retry(ip);
+            }
+            let delay = connection.retry_delay(retry);
+            tracing::info!(retry, of = connection.retries, delay_ms = delay.as_millis() as u64, error = %e, "retrying device call");
+            std::thread::sleep(delay);
+            result = attempt();
+        }
+        result
+    }
+
+    // subnet : plage à sonder en unicast si la diffusion ne trouve rien (sinon celle de config)
+    pub fn discover(&self, config: &DiscoveryConfig, subnet: Option<String>) -> Result<Vec<DiscoveredDevice>, AppError> {
+        let connection = &self.connection;
+        let mut probes = discovery::broadcast_probes(config, DEFAULT_PORT)?;
+        probes.extend(discovery::multicast_probes(config, DEFAULT_PORT)?);
+        let statics = discovery::static_probes(config, DEFAULT_PORT);
+        probes.extend(statics.iter().map(|(_, probe)| *probe));
+        let window = Duration::from_millis(connection.timeout_ms);
+
+        let mut devices = Vec::new();
+        // mDNS en parallèle de la diffusion, sur la même durée totale
+        let mdns_window = window * (connection.retries + 1);
+        let mdns = std::thread::scope(|s| -> Result<_, AppError> {
+            let mdns = config
+                .mdns_service
+                .as_deref()
+                .map(|service| s.spawn(move || discovery::browse_mdns(service, DEFAULT_PORT, mdns_window)));
+
+            // Chaque tentative rediffuse la requête ; les réponses s'accumulent
+            for attempt in 0..=connection.retries {
+                if attempt > 0 {
+                    std::thread::sleep(Duration::from_millis(connection.retry_delay_ms));
+                }
+                let replies = tauri::async_runtime::block_on(self.transport.broadcast(
+                    connection.local_port,
+                    &probes,
+                    methods::GET_DEVICE,
+                    methods::probe_params(),
+                    window,
+                ))?;
+
+                for (addr, response) in replies {
+                    if let Some(result) = response.get("result") {
+                        // Hôte fixe : on garde le nom saisi plutôt que l'adresse résolue
+                        let ip = statics
+                            .iter()
+                            .find(|(_, probe)| probe.to.ip() == addr.ip())
+                            .map_or_else(|| marstek_protocol::host(&addr), |(host, _)| host.clone());
+                        // Éviter les doublons, y compris un appareil qui répond en IPv4 et en IPv6
+                        let found = DiscoveredDevice::from_result(ip, DEFAULT_PORT, result);
+                        if !devices.iter().any(|d| d.same_device(&found)) {
+                            devices.push(found);
+                        }
+                    }
+                }
+            }
+            Ok(mdns.map(|handle| handle.join().unwrap_or_else(|_| Err(AppError::Internal("mDNS browse panicked".to_string())))))
+        })?;
+        match mdns {
+            Some(Ok(found)) => {
+                for device in found {
+                    if !devices.iter().any(|d| d.same_device(&device)) {
+                        devices.push(device);
+                    }
+                }
+            }
+            Some(Err(e)) => tracing::warn!("mDNS discovery failed: {}", e),
+            None => {}
+        }
+
+        // Diffusion filtrée (isolation client, autre VLAN) : balayage unicast de la plage
+        if devices.is_empty() {
+            if let Some(cidr) = subnet.or(config.sweep_cidr.clone()) {
+                devices = self.sweep(&cidr, DEFAULT_PORT, config)?;
+            }
+        }
+        Ok(devices)
+    }
+
+    // Sonde Marstek.GetDevice de chaque adresse de la plage, sur `port`
+    pub fn sweep(&self, cidr: &str, port: u16, config: &DiscoveryConfig) -> Result<Vec<DiscoveredDevice>, AppError> {
+        let hosts = discovery::hosts(cidr)?;
+        Ok(discovery::sweep(self.transport, self.connection.local_port, &hosts, port, config))
+    }
+
+    pub fn status(&self, ip: &str, port: u16, variant: &ProtocolVariant) -> Result<DeviceStatus, AppError> {
+        let query = |method: &str, params: serde_json::Value| -> Result<serde_json::Value, AppError> {
+            Ok(variant.normalize(self.call(ip, port, variant.method(method), params)?))
+        };
+        let sections = std::thread::scope(|s| {
+            status_queries()
+                .map(|(name, method, params)| (name, s.spawn(move || query(method, params))))
+                .map(|(name, handle)| (name, handle.join().unwrap_or_else(|_| Err(AppError::Internal("Device query panicked".to_string())))))
+        });
+
+        DeviceStatus::assemble(sections)
+    }
+
+    pub fn set_mode(&self, ip: &str, port: u16, variant: &ProtocolVariant, request: &ModeRequest) -> Result<bool, AppError> {
+        Ok(accepted(&self.call(ip, port, variant.method(methods::ES_SET_MODE), request.params())?))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::error::AppError;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ClockConfig {
+    // Vérification pendant le polling, au plus une fois par intervalle ; 0 : désactivée
+    pub check_interval_s: u64,
+    // Écart toléré avant avertissement, [s]
+    pub max_drift_s: u64,
+    // Remet l'horloge à l'heure quand l'écart dépasse max_drift_s (hors lecture seule)
+    pub auto_correct: bool,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self { check_interval_s: 3600, max_drift_s: 120, auto_correct: false }
+    }
+}
+
+impl ClockConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.max_drift_s == 0 {
+            return Err(AppError::InvalidInput("Clock drift threshold must be at least 1 s".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct DeviceTime {
+    // Heure de l'appareil, RFC 3339
+    pub device_time: String,
+    pub local_time: String,
+    // Appareil en avance si > 0, [s]
+    pub offset_s: i64,
+}
+
+// Réponse de Marstek.GetTime : "timestamp" Unix, ou "time" en heure locale "YYYY-MM-DD HH:MM:SS" selon le firmware
+pub fn parse(result: &serde_json::Value) -> Result<DeviceTime, AppError> {
+    let device: DateTime<Local> = if let Some(ts) = result.get("timestamp").and_then(|v| v.as_i64()) {
+        Utc.timestamp_opt(ts, 0).single().map(|t| t.with_timezone(&Local))
+    } else if let Some(text) = result.get("time").and_then(|v| v.as_str()) {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").ok().and_then(|t| Local.from_local_datetime(&t).earliest())
+    } else {
+        None
+    }
+    .ok_or_else(|| AppError::ParseError(format!("Unreadable device time: {}", result)))?;
+    let now = Local::now();
+    Ok(DeviceTime { device_time: device.to_rfc3339(), local_time: now.to_rfc3339(), offset_s: (device - now).num_seconds() })
+}
+
+// Heure de ce poste, avec le décalage du fuseau pour les plages Manual (heure locale)
+pub fn set_params() -> serde_json::Value {
+    let now = Local::now();
+    serde_json::json!({
+        "id": 0,
+        "timestamp": now.timestamp(),
+        "time": now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        "timezone": now.offset().local_minus_utc() / 60,
+    })
+}
+
+// Dernière vérification de chaque appareil
+#[derive(Default)]
+pub struct DriftWatch {
+    checked: Mutex<HashMap<String, Instant>>,
+}
+
+impl DriftWatch {
+    pub fn due(&self, device: &str, interval: Duration) -> bool {
+        let mut checked = self.checked.lock().unwrap_or_else(|e| e.into_inner());
+        if checked.get(device).is_some_and(|at| at.elapsed() < interval) {
+            return false;
+        }
+        checked.insert(device.to_string(), Instant::now());
+        true
+    }
+}
@@ -0,0 +1,214 @@
+use crate::devices::normalize_mac;
+use crate::error::AppError;
+use crate::kpi::{self, DailyKpis};
+use chrono::NaiveDate;
+use crate::{BatteryStatus, DeviceInfo, EnergyStatus, MeterStatus};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+// Jeton expiré ou invalidé par une connexion depuis l'application mobile
+const TOKEN_ERROR_CODES: [&str; 2] = ["-1", "8"];
+// Plage maximale d'une requête de bilans journaliers, [jours]
+pub const DAYS_PER_REQUEST: u64 = 31;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CloudConfig {
+    pub enabled: bool,
+    // Identifiants du compte de l'application Marstek
+    pub email: String,
+    // Uniquement si le trousseau système est indisponible
+    pub password: Option<String>,
+    pub base_url: String,
+}
+
+impl Default for CloudConfig {
+    fn default() -> Self {
+        Self { enabled: false, email: String::new(), password: None, base_url: "https://eu.hamedata.com".to_string() }
+    }
+}
+
+impl CloudConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.enabled && self.email.is_empty() {
+            return Err(AppError::InvalidInput("The Marstek account e-mail is required".to_string()));
+        }
+        if !self.base_url.starts_with("https://") {
+            return Err(AppError::InvalidInput(format!("Invalid Marstek cloud URL: {}", self.base_url)));
+        }
+        Ok(())
+    }
+}
+
+// Appareil tel que renvoyé par getDeviceList ; puissances en W
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CloudDevice {
+    pub devid: String,
+    pub name: Option<String>,
+    pub mac: Option<String>,
+    #[serde(rename = "type")]
+    pub model: Option<String>,
+    pub version: Option<u32>,
+    pub soc: Option<u32>,
+    pub charge: Option<f32>,
+    pub discharge: Option<f32>,
+    pub pv: Option<f32>,
+    pub grid: Option<f32>,
+}
+
+impl CloudDevice {
+    pub fn device_info(&self) -> DeviceInfo {
+        DeviceInfo { device: self.model.clone(), ver: self.version, wifi_mac: self.mac.clone(), ..Default::default() }
+    }
+
+    pub fn battery(&self) -> BatteryStatus {
+        BatteryStatus { soc: self.soc, ..Default::default() }
+    }
+
+    // Même convention que ES.GetStatus : bat_power > 0 en charge
+    pub fn energy(&self) -> EnergyStatus {
+        let bat_power = match (self.charge, self.discharge) {
+            (None, None) => None,
+            (charge, discharge) => Some(charge.unwrap_or(0.0) - discharge.unwrap_or(0.0)),
+        };
+        EnergyStatus { bat_soc: self.soc, pv_power: self.pv, bat_power, ..Default::default() }
+    }
+
+    pub fn meter(&self) -> Option<MeterStatus> {
+        self.grid.map(|grid| MeterStatus { total_power: Some(grid), source: Some("cloud".to_string()), ..Default::default() })
+    }
+}
+
+// Bilan journalier tel que renvoyé par getDeviceDayData ; énergies en kWh
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CloudDay {
+    // YYYY-MM-DD, fuseau du compte
+    #[serde(alias = "day")]
+    pub date: String,
+    #[serde(alias = "pv_energy")]
+    pub pv: Option<f64>,
+    #[serde(alias = "grid_in")]
+    pub grid_import: Option<f64>,
+    #[serde(alias = "grid_out")]
+    pub grid_export: Option<f64>,
+    pub charge: Option<f64>,
+    pub discharge: Option<f64>,
+}
+
+impl CloudDay {
+    // None : date illisible, ou journée sans aucune mesure
+    pub fn daily(&self) -> Option<DailyKpis> {
+        let date = NaiveDate::parse_from_str(&self.date, "%Y-%m-%d").ok()?;
+        let values = [self.pv, self.grid_import, self.grid_export, self.charge, self.discharge];
+        if values.iter().all(Option::is_none) {
+            return None;
+        }
+        let [pv, import, export, charge, discharge] = values.map(|v| v.unwrap_or(0.0).max(0.0) * 1000.0);
+        Some(kpi::from_totals(date.format("%Y-%m-%d").to_string(), pv, import, export, charge, discharge))
+    }
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    #[serde(default)]
+    code: serde_json::Value,
+    #[serde(default)]
+    msg: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+}
+
+impl Reply {
+    fn code(&self) -> String {
+        match &self.code {
+            serde_json::Value::String(code) => code.clone(),
+            code => code.to_string(),
+        }
+    }
+}
+
+fn get(url: &str, query: &[(&str, &str)]) -> Result<Reply, AppError> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .and_then(|client| client.get(url).query(query).send())
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| if e.is_timeout() { AppError::Timeout(e.to_string()) } else { AppError::IoError(e.to_string()) })?
+        .json()
+        .map_err(|e| AppError::ParseError(format!("Invalid Marstek cloud response: {}", e)))
+}
+
+// Jeton de session conservé entre deux replis
+#[derive(Default)]
+pub struct CloudClient {
+    token: Mutex<Option<String>>,
+}
+
+impl CloudClient {
+    fn login(&self, config: &CloudConfig, password: &str) -> Result<String, AppError> {
+        let hash = format!("{:x}", Md5::digest(password.as_bytes()));
+        let url = format!("{}/app/Solar/v2_get_device.php", config.base_url);
+        let reply = get(&url, &[("mailbox", &config.email), ("pwd", &hash)])?;
+        let token = reply.token.filter(|t| !t.is_empty()).ok_or_else(|| {
+            AppError::Forbidden(format!("Marstek cloud login failed: {}", reply.msg.unwrap_or_else(|| "no token".to_string())))
+        })?;
+        *self.token.lock().unwrap_or_else(|e| e.into_inner()) = Some(token.clone());
+        Ok(token)
+    }
+
+    // None : jeton refusé, à renouveler
+    fn fetch<T: serde::de::DeserializeOwned>(url: &str, query: &[(&str, &str)], what: &str) -> Result<Option<T>, AppError> {
+        let reply = get(url, query)?;
+        if TOKEN_ERROR_CODES.contains(&reply.code().as_str()) {
+            return Ok(None);
+        }
+        let data = reply.data.ok_or_else(|| AppError::ParseError(format!("Marstek cloud: {}", reply.msg.unwrap_or_default())))?;
+        serde_json::from_value(data).map(Some).map_err(|e| AppError::ParseError(format!("Invalid Marstek cloud {}: {}", what, e)))
+    }
+
+    // Jeton conservé, puis une nouvelle connexion s'il est refusé
+    fn with_token<T>(&self, config: &CloudConfig, password: &str, request: impl Fn(&str) -> Result<Option<T>, AppError>) -> Result<T, AppError> {
+        let cached = self.token.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(token) = cached {
+            if let Some(value) = request(&token)? {
+                return Ok(value);
+            }
+        }
+        let token = self.login(config, password)?;
+        request(&token)?.ok_or_else(|| AppError::Forbidden("Marstek cloud rejected a fresh session token".to_string()))
+    }
+
+    pub fn devices(&self, config: &CloudConfig, password: &str) -> Result<Vec<CloudDevice>, AppError> {
+        let url = format!("{}/ems/api/v1/getDeviceList", config.base_url);
+        self.with_token(config, password, |token| Self::fetch(&url, &[("token", token)], "device list"))
+    }
+
+    // from, to inclus, au plus DAYS_PER_REQUEST jours
+    pub fn daily(&self, config: &CloudConfig, password: &str, devid: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<CloudDay>, AppError> {
+        let url = format!("{}/ems/api/v1/getDeviceDayData", config.base_url);
+        let (from, to) = (from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string());
+        self.with_token(config, password, |token| {
+            Self::fetch(&url, &[("token", token), ("devid", devid), ("startDate", &from), ("endDate", &to)], "daily energy")
+        })
+    }
+
+    pub fn reset(&self) {
+        *self.token.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+// Appareil du registre retrouvé par MAC ; un compte à un seul appareil n'a pas d'ambiguïté
+pub fn find<'a>(devices: &'a [CloudDevice], macs: &[Option<&str>]) -> Option<&'a CloudDevice> {
+    let macs: Vec<String> = macs.iter().flatten().map(|m| normalize_mac(m)).collect();
+    devices
+        .iter()
+        .find(|d| d.mac.as_deref().is_some_and(|mac| macs.contains(&normalize_mac(mac))))
+        .or(if devices.len() == 1 { devices.first() } else { None })
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+const MAX_VIOLATIONS: usize = 100;
+
+// Balkonkraftwerk : sortie cumulée onduleur PV + batterie plafonnée (800 W en Allemagne)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ComplianceConfig {
+    pub enabled: bool,
+    pub max_output: f32,
+}
+
+impl Default for ComplianceConfig {
+    fn default() -> Self {
+        Self { enabled: false, max_output: 800.0 }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Violation {
+    pub timestamp: String,
+    pub battery_output: f32,
+    pub pv_output: f32,
+    pub limit: f32,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    last_pv_output: f32,
+    violations: VecDeque<Violation>,
+}
+
+#[derive(Default)]
+pub struct ComplianceMonitor {
+    state: Mutex<MonitorState>,
+}
+
+impl ComplianceMonitor {
+    // Puissance de décharge maximale autorisée compte tenu de la dernière production PV externe
+    pub fn allowed_discharge(&self, config: &ComplianceConfig) -> Option<f32> {
+        if !config.enabled {
+            return None;
+        }
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        Some((config.max_output - state.last_pv_output).max(0.0))
+    }
+
+    pub fn check_setpoint(&self, config: &ComplianceConfig, discharge_power: i64) -> Result<(), String> {
+        let Some(allowed) = self.allowed_discharge(config) else { return Ok(()) };
+        if discharge_power as f32 > allowed {
+            return Err(format!(
+                "Discharge of {} W would exceed the {} W balcony-plant output limit ({} W available)",
+                discharge_power, config.max_output, allowed
+            ));
+        }
+        Ok(())
+    }
+
+    // battery_output : ongrid_power (> 0 en décharge), pv_output : onduleurs externes
+    pub fn observe(&self, config: &ComplianceConfig, battery_output: f32, pv_output: f32) -> Option<Violation> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.last_pv_output = pv_output;
+        if !config.enabled || battery_output.max(0.0) + pv_output <= config.max_output {
+            return None;
+        }
+
+        let violation = Violation {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            battery_output,
+            pv_output,
+            limit: config.max_output,
+        };
+        tracing::warn!(
+            "balcony-plant limit exceeded: battery {} W + PV {} W > {} W",
+            battery_output, pv_output, config.max_output
+        );
+        if state.violations.len() >= MAX_VIOLATIONS {
+            state.violations.pop_front();
+        }
+        state.violations.push_back(violation.clone());
+        Some(violation)
+    }
+
+    pub fn violations(&self) -> Vec<Violation> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.violations.iter().cloned().collect()
+    }
+}
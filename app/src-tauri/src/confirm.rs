@@ -0,0 +1,129 @@
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Durée de validité d'un jeton : le temps de lire la description et de confirmer
+pub const TOKEN_TTL: Duration = Duration::from_secs(60);
+
+// Renvoyée par le premier appel d'une opération destructive ; le second appel doit reprendre token
+#[derive(Serialize, Clone)]
+pub struct Confirmation {
+    pub token: String,
+    // "ota", "calibration", "reboot", "backup_off"
+    pub action: &'static str,
+    pub device: String,
+    // Ce qui va se passer, à montrer avant de confirmer
+    pub description: String,
+    pub expires_in_s: u64,
+}
+
+// Réponse en deux temps : confirmation demandée, puis résultat de l'opération
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Confirmed<T> {
+    ConfirmationRequired(Confirmation),
+    Done { result: T },
+}
+
+struct Pending {
+    action: &'static str,
+    device: String,
+    // Paramètres de l'appel : un jeton ne confirme pas une autre puissance ou une autre durée
+    params: String,
+    expires: Instant,
+}
+
+// Jetons à usage unique, liés à l'action, à l'appareil et aux paramètres
+#[derive(Default)]
+pub struct Confirmations {
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl Confirmations {
+    pub fn issue(&self, action: &'static str, device: &str, params: String, description: String) -> Confirmation {
+        self.issue_at(action, device, params, description, Instant::now())
+    }
+
+    fn issue_at(&self, action: &'static str, device: &str, params: String, description: String, now: Instant) -> Confirmation {
+        let mut bytes = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|_, p| p.expires > now);
+        pending.insert(token.clone(), Pending { action, device: device.to_string(), params, expires: now + TOKEN_TTL });
+        Confirmation { token, action, device: device.to_string(), description, expires_in_s: TOKEN_TTL.as_secs() }
+    }
+
+    // Consomme le jeton, même s'il ne correspond pas : un jeton ne sert qu'une fois
+    pub fn redeem(&self, token: &str, action: &str, device: &str, params: &str) -> Result<(), String> {
+        self.redeem_at(token, action, device, params, Instant::now())
+    }
+
+    fn redeem_at(&self, token: &str, action: &str, device: &str, params: &str, now: Instant) -> Result<(), String> {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(token);
+        match pending {
+            Some(p) if p.expires <= now => Err("Confirmation token expired: request a new one".to_string()),
+            Some(p) if p.action == action && p.device == device && p.params == params => Ok(()),
+            Some(_) => Err("Confirmation token was issued for another operation".to_string()),
+            None => Err("Unknown or already used confirmation token".to_string()),
+        }
+    }
+
+    // Sans jeton : un nouveau est émis ; avec : il doit correspondre à cette opération
+    pub fn check(
+        &self,
+        token: Option<&str>,
+        action: &'static str,
+        device: &str,
+        params: String,
+        description: impl FnOnce() -> String,
+    ) -> Result<Option<Confirmation>, String> {
+        match token {
+            None => Ok(Some(self.issue(action, device, params, description()))),
+            Some(token) => self.redeem(token, action, device, &params).map(|_| None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_token_confirms_once() {
+        let confirmations = Confirmations::default();
+        let issued = confirmations.check(None, "reboot", "dev", String::new(), || "Reboot dev".to_string()).unwrap().unwrap();
+        assert_eq!((issued.action, issued.device.as_str(), issued.expires_in_s), ("reboot", "dev", TOKEN_TTL.as_secs()));
+        assert!(confirmations.check(Some(&issued.token), "reboot", "dev", String::new(), String::new).unwrap().is_none());
+        assert!(confirmations.redeem(&issued.token, "reboot", "dev", "").is_err(), "already used");
+    }
+
+    #[test]
+    fn a_token_is_bound_to_its_action_device_and_parameters() {
+        let confirmations = Confirmations::default();
+        for (action, device, params) in [("ota", "dev", "{}"), ("calibration", "other", "{}"), ("calibration", "dev", "{\"power\":900}")] {
+            let issued = confirmations.issue("calibration", "dev", "{}".to_string(), String::new());
+            assert!(confirmations.redeem(&issued.token, action, device, params).is_err());
+            // Un essai raté consomme le jeton
+            assert!(confirmations.redeem(&issued.token, "calibration", "dev", "{}").is_err());
+        }
+    }
+
+    #[test]
+    fn expired_tokens_are_refused() {
+        let confirmations = Confirmations::default();
+        let start = Instant::now();
+        let issued = confirmations.issue_at("ota", "dev", String::new(), String::new(), start);
+        let result = confirmations.redeem_at(&issued.token, "ota", "dev", "", start + TOKEN_TTL);
+        assert!(result.unwrap_err().contains("expired"));
+        let fresh = confirmations.issue_at("ota", "dev", String::new(), String::new(), start);
+        assert!(confirmations.redeem_at(&fresh.token, "ota", "dev", "", start + TOKEN_TTL - Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn unknown_tokens_are_refused() {
+        assert!(Confirmations::default().redeem("0123456789abcdef", "reboot", "dev", "").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::AppError;
+use crate::history::HistorySample;
+use crate::kpi::{self, Interval};
+use chrono::{DateTime, Local, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Période d'un tarif heures pleines / heures creuses, heure locale "HH:MM" ; end <= start : passe minuit
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RatePeriod {
+    pub start: String,
+    pub end: String,
+    pub price: f64,
+}
+
+// Prix du kWh, [devise/kWh]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rate {
+    Flat { price: f64 },
+    // Hors des périodes : default_price
+    TimeOfUse { default_price: f64, periods: Vec<RatePeriod> },
+    // Prix spot du fournisseur configuré (tariff) × multiplier + markup (taxes, acheminement)
+    Dynamic {
+        #[serde(default)]
+        markup: f64,
+        #[serde(default = "one")]
+        multiplier: f64,
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, AppError> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| AppError::InvalidInput(format!("Invalid time: {} (expected HH:MM)", value)))
+}
+
+impl Rate {
+    fn validate(&self) -> Result<(), AppError> {
+        let prices: Vec<f64> = match self {
+            Rate::Flat { price } => vec![*price],
+            Rate::TimeOfUse { default_price, periods } => {
+                for period in periods {
+                    parse_time(&period.start)?;
+                    parse_time(&period.end)?;
+                }
+                std::iter::once(*default_price).chain(periods.iter().map(|p| p.price)).collect()
+            }
+            Rate::Dynamic { markup, multiplier } => vec![*markup, *multiplier],
+        };
+        if prices.iter().any(|p| !p.is_finite()) {
+            return Err(AppError::InvalidInput("Tariff prices must be finite numbers".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, Rate::Dynamic { .. })
+    }
+
+    // spot : prix de l'heure chez le fournisseur, None s'il n'est pas connu
+    fn price_at(&self, time: DateTime<Local>, spot: Option<f64>) -> Option<f64> {
+        match self {
+            Rate::Flat { price } => Some(*price),
+            Rate::TimeOfUse { default_price, periods } => {
+                let now = time.time();
+                let period = periods.iter().find(|p| {
+                    let (Ok(start), Ok(end)) = (parse_time(&p.start), parse_time(&p.end)) else { return false };
+                    if start < end {
+                        now >= start && now < end
+                    } else {
+                        now >= start || now < end
+                    }
+                });
+                Some(period.map_or(*default_price, |p| p.price))
+            }
+            Rate::Dynamic { markup, multiplier } => spot.map(|spot| spot * multiplier + markup),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CostConfig {
+    pub import: Rate,
+    // Rachat du surplus ; 0 sans contrat de revente
+    pub export: Rate,
+    pub currency: String,
+}
+
+impl Default for CostConfig {
+    fn default() -> Self {
+        Self { import: Rate::Flat { price: 0.25 }, export: Rate::Flat { price: 0.0 }, currency: "EUR".to_string() }
+    }
+}
+
+impl CostConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.import.validate()?;
+        self.export.validate()
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.import.is_dynamic() || self.export.is_dynamic()
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct DailyCost {
+    // YYYY-MM-DD, heure locale
+    pub date: String,
+    pub import_kwh: f64,
+    pub export_kwh: f64,
+    // Achat au réseau
+    pub cost: f64,
+    // Vente du surplus
+    pub revenue: f64,
+    // cost - revenue
+    pub net: f64,
+    // Même consommation et même production, sans batterie
+    pub baseline_net: f64,
+    pub savings: f64,
+    // Énergie d'heures sans prix dynamique connu, exclue du calcul
+    pub unpriced_kwh: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CostReport {
+    pub currency: String,
+    pub days: Vec<DailyCost>,
+    pub total: DailyCost,
+}
+
+// spot : prix horaires du fournisseur, clé = début de l'heure en secondes Unix
+pub fn report(config: &CostConfig, samples: &[(i64, HistorySample)], spot: &HashMap<i64, f64>) -> CostReport {
+    let mut days: Vec<DailyCost> = Vec::new();
+    for Interval { start, hours, grid, battery, .. } in kpi::intervals(samples) {
+        let Some(time) = DateTime::from_timestamp(start, 0).map(|t| t.with_timezone(&Local)) else { continue };
+        let date = time.format("%Y-%m-%d").to_string();
+        if days.last().is_none_or(|d| d.date != date) {
+            days.push(DailyCost { date, ..Default::default() });
+        }
+        let Some(day) = days.last_mut() else { continue };
+
+        let hour = start - i64::from(time.minute() * 60 + time.second());
+        let spot = spot.get(&hour).copied();
+        let (Some(import_price), Some(export_price)) = (config.import.price_at(time, spot), config.export.price_at(time, spot)) else {
+            day.unpriced_kwh += grid.abs() * hours / 1000.0;
+            continue;
+        };
+        // Sans batterie, sa puissance de charge n'est plus soutirée et sa décharge doit l'être
+        let flows = |grid: f64| {
+            let import = grid.max(0.0) * hours / 1000.0;
+            let export = (-grid).max(0.0) * hours / 1000.0;
+            (import, export, import * import_price - export * export_price)
+        };
+        let (import, export, net) = flows(grid);
+        let (_, _, baseline_net) = flows(grid - battery);
+        day.import_kwh += import;
+        day.export_kwh += export;
+        day.cost += import * import_price;
+        day.revenue += export * export_price;
+        day.net += net;
+        day.baseline_net += baseline_net;
+    }
+
+    let mut total = DailyCost::default();
+    for day in &mut days {
+        day.savings = day.baseline_net - day.net;
+        total.import_kwh += day.import_kwh;
+        total.export_kwh += day.export_kwh;
+        total.cost += day.cost;
+        total.revenue += day.revenue;
+        total.net += day.net;
+        total.baseline_net += day.baseline_net;
+        total.savings += day.savings;
+        total.unpriced_kwh += day.unpriced_kwh;
+    }
+    CostReport { currency: config.currency.clone(), days, total }
+}
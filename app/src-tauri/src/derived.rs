@@ -0,0 +1,74 @@
+use crate::plugins::sandboxed_engine;
+use rhai::{Dynamic, Map, Scope};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DerivedSensor {
+    pub name: String,
+    // Ex : "total_power + bat_power + (energy.pv_power ?? 0.0)"
+    pub expression: String,
+}
+
+fn to_float(value: &serde_json::Value) -> Option<Dynamic> {
+    value.as_f64().map(Dynamic::from_float)
+}
+
+// Chaque section est exposée comme map (energy.pv_power) et ses champs numériques
+// aussi comme variables simples (pv_power), la première section gagnant en cas de doublon
+fn sample_scope(sample: &serde_json::Value) -> Scope<'static> {
+    let mut scope = Scope::new();
+    let Some(sections) = sample.as_object() else { return scope };
+
+    for (section, fields) in sections {
+        let Some(fields) = fields.as_object() else { continue };
+        let mut map = Map::new();
+        for (field, value) in fields {
+            let Some(number) = to_float(value) else { continue };
+            map.insert(field.as_str().into(), number.clone());
+            if !scope.contains(field) {
+                scope.push_dynamic(field.clone(), number);
+            }
+        }
+        scope.push_constant(section.clone(), map);
+    }
+    scope
+}
+
+pub fn validate(sensors: &[DerivedSensor]) -> Result<(), String> {
+    let engine = sandboxed_engine();
+    for sensor in sensors {
+        let valid_name = sensor.name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && sensor.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(format!("Invalid sensor name: {}", sensor.name));
+        }
+        engine
+            .compile_expression(&sensor.expression)
+            .map_err(|e| format!("{}: {}", sensor.name, e))?;
+    }
+    Ok(())
+}
+
+// Évalue les capteurs dans l'ordre : un capteur peut réutiliser les précédents
+pub fn evaluate(sensors: &[DerivedSensor], sample: &serde_json::Value) -> BTreeMap<String, f64> {
+    let engine = sandboxed_engine();
+    let mut scope = sample_scope(sample);
+    let mut values = BTreeMap::new();
+
+    for sensor in sensors {
+        let value = engine
+            .eval_expression_with_scope::<Dynamic>(&mut scope, &sensor.expression)
+            .map_err(|e| e.to_string())
+            .and_then(|v| v.as_float().or_else(|_| v.as_int().map(|i| i as f64)).map_err(|t| t.to_string()));
+        match value {
+            Ok(value) if value.is_finite() => {
+                scope.push_dynamic(sensor.name.clone(), Dynamic::from_float(value));
+                values.insert(sensor.name.clone(), value);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::debug!("derived sensor {} not evaluated: {}", sensor.name, e),
+        }
+    }
+    values
+}
@@ -0,0 +1,321 @@
+use crate::error::AppError;
+use crate::schedule::ManualSlot;
+use marstek_protocol::modbus::{DEFAULT_MODBUS_PORT, DEFAULT_UNIT_ID};
+use marstek_protocol::PowerLimits;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const DEVICES_FILE: &str = "devices.json";
+// Ancien format mono-appareil, importé au premier démarrage
+pub const LEGACY_DEVICE_FILE: &str = "device.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeviceConfig {
+    pub ip: String,
+    pub port: u16,
+    #[serde(default)]
+    pub name: Option<String>,
+    // Renseigné au premier Marstek.GetDevice
+    #[serde(skip)]
+    pub model: Option<String>,
+    // Champ ver du même appel
+    #[serde(skip)]
+    pub firmware: Option<u32>,
+    // Dernières limites de puissance lues ou écrites en Modbus
+    #[serde(skip)]
+    pub power_limits: Option<PowerLimits>,
+    // Identité matérielle : retrouve l'appareil quand son IP change (bail DHCP)
+    #[serde(default)]
+    pub ble_mac: Option<String>,
+    #[serde(default)]
+    pub wifi_mac: Option<String>,
+    // Dernières plages Manual écrites, relues quand le firmware ne les renvoie pas
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manual_slots: Vec<ManualSlot>,
+    // Dernier mode durable demandé (Auto, AI ou Manual), remis en place par le watchdog après un redémarrage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desired_mode: Option<String>,
+    // Absent : Modbus TCP non utilisé pour cet appareil
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modbus: Option<ModbusSettings>,
+    // Batterie imaginaire servie par l'app sur la boucle locale, à ip:port
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub simulated: bool,
+    // Capture rejouée par le simulateur à la place des données inventées
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<String>,
+}
+
+// Accès Modbus TCP exposé par certains firmwares, en plus de l'API UDP
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ModbusSettings {
+    pub port: u16,
+    pub unit_id: u8,
+}
+
+impl Default for ModbusSettings {
+    fn default() -> Self {
+        Self { port: DEFAULT_MODBUS_PORT, unit_id: DEFAULT_UNIT_ID }
+    }
+}
+
+impl ModbusSettings {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.port == 0 {
+            return Err(AppError::InvalidInput("Modbus port must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl DeviceConfig {
+    // Sans MAC connue des deux côtés, seule l'adresse permet de reconnaître l'appareil
+    pub fn matches(&self, ip: &str, ble_mac: Option<&str>, wifi_mac: Option<&str>) -> bool {
+        let same = |a: &Option<String>, b: Option<&str>| matches!((a, b), (Some(a), Some(b)) if a.eq_ignore_ascii_case(b));
+        let known = (self.ble_mac.is_some() && ble_mac.is_some()) || (self.wifi_mac.is_some() && wifi_mac.is_some());
+        if known {
+            same(&self.ble_mac, ble_mac) || same(&self.wifi_mac, wifi_mac)
+        } else {
+            self.ip == ip
+        }
+    }
+}
+
+// Surnom et notes saisis par l'utilisateur, pour distinguer deux appareils identiques
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct DeviceProfile {
+    pub nickname: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DeviceEntry {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub name: Option<String>,
+    pub model: Option<String>,
+    pub selected: bool,
+    pub ble_mac: Option<String>,
+    pub wifi_mac: Option<String>,
+    pub nickname: Option<String>,
+    pub notes: Option<String>,
+    pub modbus: Option<ModbusSettings>,
+    pub simulated: bool,
+    pub replay: Option<String>,
+}
+
+// "AA:BB:CC:DD:EE:FF", "aabbccddeeff"... -> "aabbccddeeff"
+pub fn normalize_mac(mac: &str) -> String {
+    mac.chars().filter(|c| c.is_ascii_hexdigit()).collect::<String>().to_ascii_lowercase()
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DeviceRegistry {
+    // Clé : identifiant choisi à l'ajout (l'IP par défaut)
+    pub devices: BTreeMap<String, DeviceConfig>,
+    // Appareil utilisé quand une commande ne précise pas d'id
+    pub selected: Option<String>,
+    // Clé : MAC normalisée (BLE ou Wi-Fi) ; survit aux changements d'IP et d'id
+    pub profiles: BTreeMap<String, DeviceProfile>,
+}
+
+impl DeviceRegistry {
+    pub fn load(path: &Path, legacy_path: &Path) -> Self {
+        if let Ok(content) = fs::read_to_string(path) {
+            return serde_json::from_str(&content)
+                .map_err(|e| tracing::warn!("ignoring invalid {}: {}", path.display(), e))
+                .unwrap_or_default();
+        }
+        let mut registry = Self::default();
+        let legacy = fs::read_to_string(legacy_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<DeviceConfig>(&content).ok());
+        if let Some(device) = legacy {
+            let id = registry.add(None, device.ip, device.port, device.name);
+            registry.selected = Some(id);
+        }
+        registry
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        Ok(fs::write(path, content)?)
+    }
+
+    // Un id existant est mis à jour ; le premier appareil ajouté devient l'appareil courant
+    pub fn add(&mut self, id: Option<String>, ip: String, port: u16, name: Option<String>) -> String {
+        let id = id.filter(|id| !id.trim().is_empty()).unwrap_or_else(|| ip.clone());
+        // Les MAC, les plages, le mode voulu, l'accès Modbus et la simulation ne sont conservés que si l'entrée désigne toujours la même adresse
+        let (ble_mac, wifi_mac, manual_slots, desired_mode, modbus, simulated, replay) = match self.devices.get(&id) {
+            Some(previous) if previous.ip == ip => (
+                previous.ble_mac.clone(),
+                previous.wifi_mac.clone(),
+                previous.manual_slots.clone(),
+                previous.desired_mode.clone(),
+                previous.modbus.clone(),
+                previous.simulated,
+                previous.replay.clone(),
+            ),
+            _ => (None, None, Vec::new(), None, None, false, None),
+        };
+        self.devices.insert(
+            id.clone(),
+            DeviceConfig { ip, port, name, model: None, firmware: None, power_limits: None, ble_mac, wifi_mac, manual_slots, desired_mode, modbus, simulated, replay },
+        );
+        if self.selected.is_none() {
+            self.selected = Some(id.clone());
+        }
+        id
+    }
+
+    // Premier id libre parmi "simulator", "simulator-2"... ("replay" pour une capture rejouée)
+    pub fn add_simulated(&mut self, port: u16, name: Option<String>, replay: Option<String>) -> String {
+        let (prefix, default_name) = if replay.is_some() { ("replay", "Replayed capture") } else { ("simulator", "Simulated battery") };
+        let id = (1..)
+            .map(|n| if n == 1 { prefix.to_string() } else { format!("{}-{}", prefix, n) })
+            .find(|id| !self.devices.contains_key(id))
+            .unwrap_or_default();
+        self.add(Some(id.clone()), "127.0.0.1".to_string(), port, name.or_else(|| Some(default_name.to_string())));
+        if let Some(config) = self.devices.get_mut(&id) {
+            config.simulated = true;
+            config.replay = replay;
+        }
+        id
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<(), AppError> {
+        self.devices.remove(id).ok_or_else(|| AppError::NotConfigured(format!("Unknown device: {}", id)))?;
+        if self.selected.as_deref() == Some(id) {
+            self.selected = self.devices.keys().next().cloned();
+        }
+        Ok(())
+    }
+
+    pub fn select(&mut self, id: &str) -> Result<(), AppError> {
+        if !self.devices.contains_key(id) {
+            return Err(AppError::NotConfigured(format!("Unknown device: {}", id)));
+        }
+        self.selected = Some(id.to_string());
+        Ok(())
+    }
+
+    // id absent : appareil courant
+    pub fn get(&self, id: Option<&str>) -> Result<(&str, &DeviceConfig), AppError> {
+        let id = id
+            .or(self.selected.as_deref())
+            .ok_or_else(|| AppError::NotConfigured("Device not configured. Call set_device first.".to_string()))?;
+        self.devices
+            .get_key_value(id)
+            .map(|(id, config)| (id.as_str(), config))
+            .ok_or_else(|| AppError::NotConfigured(format!("Unknown device: {}", id)))
+    }
+
+    pub fn set_model(&mut self, id: &str, model: &str, firmware: Option<u32>) {
+        if let Some(config) = self.devices.get_mut(id) {
+            config.model = Some(model.to_string());
+            config.firmware = firmware.or(config.firmware);
+        }
+    }
+
+    // Renvoie true si quelque chose a changé (à sauvegarder)
+    pub fn set_identity(&mut self, id: &str, ble_mac: Option<&str>, wifi_mac: Option<&str>) -> bool {
+        let Some(config) = self.devices.get_mut(id) else { return false };
+        let mut changed = false;
+        for (field, value) in [(&mut config.ble_mac, ble_mac), (&mut config.wifi_mac, wifi_mac)] {
+            if let Some(value) = value.filter(|v| !v.is_empty() && field.as_deref() != Some(*v)) {
+                *field = Some(value.to_string());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn set_address(&mut self, id: &str, ip: &str, port: u16) -> bool {
+        let Some(config) = self.devices.get_mut(id) else { return false };
+        let changed = config.ip != ip || config.port != port;
+        config.ip = ip.to_string();
+        config.port = port;
+        changed
+    }
+
+    pub fn set_manual_slots(&mut self, id: &str, slots: Vec<ManualSlot>) {
+        if let Some(config) = self.devices.get_mut(id) {
+            config.manual_slots = slots;
+        }
+    }
+
+    // Renvoie true si le mode a changé
+    pub fn set_desired_mode(&mut self, id: &str, mode: &str) -> bool {
+        let Some(config) = self.devices.get_mut(id) else { return false };
+        let changed = config.desired_mode.as_deref() != Some(mode);
+        config.desired_mode = Some(mode.to_string());
+        changed
+    }
+
+    pub fn set_power_limits(&mut self, id: &str, limits: Option<PowerLimits>) {
+        if let Some(config) = self.devices.get_mut(id) {
+            config.power_limits = limits;
+        }
+    }
+
+    pub fn set_modbus(&mut self, id: &str, modbus: Option<ModbusSettings>) -> Result<(), AppError> {
+        let config = self.devices.get_mut(id).ok_or_else(|| AppError::NotConfigured(format!("Unknown device: {}", id)))?;
+        config.modbus = modbus;
+        config.power_limits = None;
+        Ok(())
+    }
+
+    pub fn profile(&self, ble_mac: Option<&str>, wifi_mac: Option<&str>) -> Option<&DeviceProfile> {
+        [ble_mac, wifi_mac]
+            .into_iter()
+            .flatten()
+            .find_map(|mac| self.profiles.get(&normalize_mac(mac)))
+    }
+
+    // Profil vide : supprimé
+    pub fn set_profile(&mut self, mac: &str, profile: DeviceProfile) -> Result<(), AppError> {
+        let key = normalize_mac(mac);
+        if key.len() != 12 {
+            return Err(AppError::InvalidInput(format!("Invalid MAC address: {}", mac)));
+        }
+        let blank = |v: &Option<String>| v.as_deref().is_none_or(|v| v.trim().is_empty());
+        if blank(&profile.nickname) && blank(&profile.notes) {
+            self.profiles.remove(&key);
+        } else {
+            self.profiles.insert(key, profile);
+        }
+        Ok(())
+    }
+
+    pub fn entry(&self, id: &str, config: &DeviceConfig) -> DeviceEntry {
+        let profile = self.profile(config.ble_mac.as_deref(), config.wifi_mac.as_deref()).cloned().unwrap_or_default();
+        DeviceEntry {
+            id: id.to_string(),
+            ip: config.ip.clone(),
+            port: config.port,
+            name: config.name.clone(),
+            model: config.model.clone(),
+            selected: self.selected.as_deref() == Some(id),
+            ble_mac: config.ble_mac.clone(),
+            wifi_mac: config.wifi_mac.clone(),
+            nickname: profile.nickname,
+            notes: profile.notes,
+            modbus: config.modbus.clone(),
+            simulated: config.simulated,
+            replay: config.replay.clone(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<DeviceEntry> {
+        self.devices.iter().map(|(id, config)| self.entry(id, config)).collect()
+    }
+}
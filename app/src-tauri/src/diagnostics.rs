@@ -0,0 +1,100 @@
+use crate::error::AppError;
+use chrono::{Datelike, Local, Timelike};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+
+// Exchanges UDP inclus par défaut dans le rapport
+pub const DEFAULT_EXCHANGES: usize = 50;
+pub const LOG_LINES: usize = 1000;
+pub const COMMAND_LOG_ENTRIES: usize = 200;
+// Captures UDP du mode debug, dans le dossier de données
+pub const CAPTURES_DIR: &str = "captures";
+// Clés de settings.json dont la valeur n'est jamais exportée
+const SECRET_KEYS: [&str; 5] = ["password", "token", "api_key", "pin_hash", "secret"];
+
+// Remplace les secrets, à toute profondeur ; une valeur absente (null) reste visible
+pub fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.iter().any(|k| key.ends_with(k)) && !value.is_null() {
+                    *value = serde_json::json!("***");
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+// Archive ZIP minimale (deflate, sans ZIP64) : quelques fichiers de diagnostic, bien en dessous de 4 Gio
+#[derive(Default)]
+pub struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+fn dos_time() -> (u16, u16) {
+    let now = Local::now();
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let date = (((now.year().max(1980) - 1980) as u32) << 9) | (now.month() << 5) | now.day();
+    (time, date as u16)
+}
+
+impl ZipWriter {
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<(), AppError> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(data);
+        let (time, date) = dos_time();
+        let offset = self.out.len() as u32;
+
+        // En-tête commun aux deux enregistrements, à partir de "version needed"
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&8u16.to_le_bytes());
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        self.out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(&compressed);
+
+        self.central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        self.central.extend_from_slice(&20u16.to_le_bytes());
+        self.central.extend_from_slice(&common);
+        // Commentaire, disque, attributs internes et externes
+        self.central.extend_from_slice(&[0; 10]);
+        self.central.extend_from_slice(&offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        let size = self.central.len() as u32;
+        self.out.append(&mut self.central);
+        self.out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        self.out.extend_from_slice(&[0; 4]);
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&size.to_le_bytes());
+        self.out.extend_from_slice(&offset.to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes());
+        self.out
+    }
+}
@@ -0,0 +1,432 @@
+use crate::error::AppError;
+use marstek_protocol::{methods, Probe, UdpTransport};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Valeur de DiscoveryConfig::interface : diffusion sur chaque interface IPv4 (comme en son absence)
+pub const ALL_INTERFACES: &str = "all";
+// Valeur de DiscoveryConfig::interface : une seule diffusion 255.255.255.255, interface choisie par le système
+pub const OS_ROUTE: &str = "route";
+// Plus grand balayage accepté : un /20
+const MAX_SWEEP_ADDRESSES: u64 = 4096;
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct DiscoveredDevice {
+    pub ip: String,
+    pub port: u16,
+    pub device: Option<String>,
+    pub ver: Option<u32>,
+    pub ble_mac: Option<String>,
+    pub wifi_mac: Option<String>,
+    // Surnom du profil enregistré pour cette MAC
+    pub nickname: Option<String>,
+}
+
+impl DiscoveredDevice {
+    // Même appareil vu par une autre adresse (IPv4 et IPv6 d'une pile double)
+    pub fn same_device(&self, other: &DiscoveredDevice) -> bool {
+        self.ip == other.ip
+            || (self.wifi_mac.is_some() && self.wifi_mac == other.wifi_mac)
+            || (self.ble_mac.is_some() && self.ble_mac == other.ble_mac)
+    }
+
+    // result : membre result d'une réponse Marstek.GetDevice ; ip avec sa zone pour une IPv6 lien-local
+    pub fn from_result(ip: String, port: u16, result: &serde_json::Value) -> Self {
+        Self {
+            ip,
+            port,
+            device: result.get("device").and_then(|v| v.as_str()).map(String::from),
+            ver: result.get("ver").and_then(|v| v.as_u64()).map(|v| v as u32),
+            ble_mac: result.get("ble_mac").and_then(|v| v.as_str()).map(String::from),
+            wifi_mac: result.get("wifi_mac").and_then(|v| v.as_str()).map(String::from),
+            nickname: None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReachabilityEvent {
+    pub id: String,
+    pub ip: String,
+    pub online: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    // Sonde Marstek.GetDevice de chaque appareil, indépendante du polling, [s] ; 0 : désactivée
+    pub interval_s: u64,
+    // Échecs consécutifs avant de déclarer l'appareil hors ligne
+    pub failures_before_offline: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { interval_s: 30, failures_before_offline: 3 }
+    }
+}
+
+impl HeartbeatConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.failures_before_offline == 0 {
+            return Err(AppError::InvalidInput("failures_before_offline must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Link {
+    online: Option<bool>,
+    failures: u32,
+}
+
+// Dernier état connu de chaque appareil du registre, mis à jour par la redécouverte et le heartbeat
+#[derive(Default)]
+pub struct Reachability {
+    links: Mutex<HashMap<String, Link>>,
+}
+
+impl Reachability {
+    pub fn snapshot(&self) -> HashMap<String, bool> {
+        let links = self.links.lock().unwrap_or_else(|e| e.into_inner());
+        links.iter().filter_map(|(id, link)| link.online.map(|online| (id.clone(), online))).collect()
+    }
+
+    // Renvoie true au changement d'état (premier relevé compris)
+    pub fn update(&self, id: &str, online: bool) -> bool {
+        let mut links = self.links.lock().unwrap_or_else(|e| e.into_inner());
+        let link = links.entry(id.to_string()).or_default();
+        link.failures = if online { 0 } else { link.failures };
+        link.online.replace(online) != Some(online)
+    }
+
+    // Heartbeat : hors ligne seulement après `threshold` échecs consécutifs. Renvoie le nouvel état s'il change.
+    pub fn record(&self, id: &str, ok: bool, threshold: u32) -> Option<bool> {
+        let mut links = self.links.lock().unwrap_or_else(|e| e.into_inner());
+        let link = links.entry(id.to_string()).or_default();
+        link.failures = if ok { 0 } else { link.failures + 1 };
+        let online = match ok {
+            true => true,
+            false if link.failures >= threshold => false,
+            // Échec isolé : l'état ne bouge pas encore
+            false => return None,
+        };
+        (link.online.replace(online) != Some(online)).then_some(online)
+    }
+
+    // Jamais sondé : considéré joignable
+    pub fn is_offline(&self, id: &str) -> bool {
+        self.links.lock().unwrap_or_else(|e| e.into_inner()).get(id).is_some_and(|link| link.online == Some(false))
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub prefix_len: u8,
+    pub broadcast: Ipv4Addr,
+}
+
+// Interfaces IPv4 hors loopback, avec leur adresse de diffusion dirigée
+pub fn interfaces() -> Result<Vec<NetworkInterface>, AppError> {
+    Ok(if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter_map(|interface| match interface.addr {
+            if_addrs::IfAddr::V4(addr) if !addr.is_loopback() => Some(NetworkInterface {
+                name: interface.name,
+                ip: addr.ip,
+                prefix_len: addr.prefixlen,
+                broadcast: addr.broadcast.unwrap_or_else(|| Ipv4Addr::from(u32::from(addr.ip) | !u32::from(addr.netmask))),
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    // Interface de diffusion (nom système), "route" pour laisser 255.255.255.255 au routage,
+    // ou "all"/absente : chaque interface en même temps
+    pub interface: Option<String>,
+    // Type de service mDNS annoncé par les firmwares récents ; absent : pas de recherche mDNS
+    pub mdns_service: Option<String>,
+    // Diffusions dirigées vers d'autres sous-réseaux (VLAN IoT routé), ex. "192.168.20.255", en plus de celles des interfaces
+    pub broadcast_addresses: Vec<String>,
+    // Appareils interrogés en unicast à chaque découverte : IP ou nom DNS, ex. "192.168.20.15", "venus.iot.lan"
+    pub static_hosts: Vec<String>,
+    // Plage sondée en unicast quand la diffusion ne trouve rien (isolation client, VLAN), ex. "192.168.10.0/24"
+    pub sweep_cidr: Option<String>,
+    // Sondes en vol simultanément
+    pub sweep_concurrency: usize,
+    // Écart minimal entre deux envois, [ms]
+    pub sweep_interval_ms: u64,
+    // Attente d'une réponse par adresse, [ms]
+    pub sweep_timeout_ms: u64,
+    // Redécouverte en arrière-plan (suivi des IP et de la joignabilité), [s] ; 0 : désactivée
+    pub rediscovery_interval_s: u64,
+    // Sonde aussi envoyée en multicast IPv6 (ff02::1) sur chaque interface
+    pub ipv6_multicast: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            interface: None,
+            mdns_service: Some("_marstek._udp.local.".to_string()),
+            broadcast_addresses: Vec::new(),
+            static_hosts: Vec::new(),
+            sweep_cidr: None,
+            sweep_concurrency: 32,
+            sweep_interval_ms: 5,
+            sweep_timeout_ms: 500,
+            rediscovery_interval_s: 300,
+            ipv6_multicast: true,
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if let Some(service) = &self.mdns_service {
+            if !service.starts_with('_') || !service.ends_with(".local.") {
+                return Err(AppError::InvalidInput(format!("Invalid mDNS service type: {} (expected _name._udp.local.)", service)));
+            }
+        }
+        for address in &self.broadcast_addresses {
+            address
+                .trim()
+                .parse::<Ipv4Addr>()
+                .map_err(|_| AppError::InvalidInput(format!("Invalid broadcast address: {} (expected e.g. 192.168.20.255)", address)))?;
+        }
+        // Pas de résolution ici : un nom peut être injoignable au moment de l'enregistrement
+        if let Some(host) = self.static_hosts.iter().find(|h| !valid_host(h)) {
+            return Err(AppError::InvalidInput(format!("Invalid device address: {}", host)));
+        }
+        if let Some(cidr) = &self.sweep_cidr {
+            hosts(cidr)?;
+        }
+        if self.sweep_concurrency == 0 || self.sweep_timeout_ms == 0 {
+            return Err(AppError::InvalidInput("sweep_concurrency and sweep_timeout_ms must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Groupe "tous les nœuds" du lien : l'équivalent IPv6 de la diffusion
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+fn interface_index(name: &str) -> Option<u32> {
+    if_addrs::get_if_addrs().ok()?.into_iter().find(|i| i.name == name)?.index
+}
+
+// Cible d'une requête UDP ou Modbus ; une zone nommée ("fe80::1%eth0") est remplacée par l'index de l'interface
+pub fn endpoint(host: &str, port: u16) -> String {
+    let host = host.trim();
+    let bare = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    let numbered = bare
+        .split_once('%')
+        .filter(|(_, zone)| zone.parse::<u32>().is_err())
+        .and_then(|(ip, zone)| Some(format!("{}%{}", ip, interface_index(zone)?)));
+    marstek_protocol::endpoint(numbered.as_deref().unwrap_or(host), port)
+}
+
+// IP ou nom DNS ("venus.iot.lan"), sans port ni espace
+pub fn valid_host(host: &str) -> bool {
+    let host = host.trim();
+    let bare = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    if bare.parse::<IpAddr>().is_ok() || bare.split_once('%').is_some_and(|(ip, zone)| ip.parse::<Ipv6Addr>().is_ok() && !zone.is_empty()) {
+        return true;
+    }
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+// Nom DNS plutôt qu'adresse : conservé tel quel dans le registre, résolu à chaque requête
+pub fn is_hostname(host: &str) -> bool {
+    let host = host.trim();
+    let bare = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    bare.parse::<IpAddr>().is_err() && !bare.contains(':')
+}
+
+// Adresse d'un appareil du registre, résolue par le DNS pour un nom d'hôte
+pub fn resolve(host: &str, port: u16) -> Result<SocketAddr, AppError> {
+    endpoint(host, port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| AppError::NotConfigured(format!("Cannot resolve {}", host.trim())))
+}
+
+// Sonde multicast ff02::1 sur chaque interface IPv6 retenue par config.interface ; la zone choisit l'interface
+pub fn multicast_probes(config: &DiscoveryConfig, port: u16) -> Result<Vec<Probe>, AppError> {
+    let selected = config.interface.as_deref().unwrap_or(ALL_INTERFACES);
+    if !config.ipv6_multicast || selected == OS_ROUTE {
+        return Ok(Vec::new());
+    }
+    let mut indexes: Vec<u32> = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|i| matches!(i.addr, if_addrs::IfAddr::V6(_)) && !i.is_loopback())
+        .filter(|i| selected == ALL_INTERFACES || i.name == selected)
+        .filter_map(|i| i.index)
+        .collect();
+    indexes.sort();
+    indexes.dedup();
+    Ok(indexes
+        .into_iter()
+        .map(|index| Probe { from: None, to: SocketAddr::V6(SocketAddrV6::new(ALL_NODES, port, 0, index)) })
+        .collect())
+}
+
+// Sondes IPv4 : diffusion dirigée depuis l'adresse de chaque interface retenue, pour qu'une seconde
+// carte réseau ou un pont Wi-Fi reçoive aussi la sienne
+pub fn broadcast_probes(config: &DiscoveryConfig, port: u16) -> Result<Vec<Probe>, AppError> {
+    let limited = vec![Probe { from: None, to: SocketAddr::from((Ipv4Addr::BROADCAST, port)) }];
+    let selected = config.interface.as_deref().unwrap_or(ALL_INTERFACES);
+    let mut probes = if selected == OS_ROUTE {
+        limited
+    } else {
+        let mut probes: Vec<Probe> = interfaces()?
+            .into_iter()
+            .filter(|i| selected == ALL_INTERFACES || i.name == selected)
+            .map(|i| Probe { from: Some(IpAddr::V4(i.ip)), to: SocketAddr::from((i.broadcast, port)) })
+            .collect();
+        probes.sort_by_key(|p| (p.from, p.to));
+        probes.dedup_by_key(|p| (p.from, p.to));
+        match probes.is_empty() {
+            // Aucune interface active (câble débranché...) : on garde la diffusion limitée
+            true if selected == ALL_INTERFACES => limited,
+            true => return Err(AppError::NotConfigured(format!("Network interface {} not found or has no IPv4 address", selected))),
+            false => probes,
+        }
+    };
+    // Sous-réseaux distants : le routeur doit relayer la diffusion dirigée, le système choisit l'interface de sortie
+    probes.extend(
+        config
+            .broadcast_addresses
+            .iter()
+            .filter_map(|address| address.trim().parse::<Ipv4Addr>().ok())
+            .map(|address| Probe { from: None, to: SocketAddr::from((address, port)) }),
+    );
+    Ok(probes)
+}
+
+// Sondes unicast vers les hôtes fixes, envoyées avec la diffusion, chacune avec l'hôte tel que saisi ;
+// un nom qui ne se résout pas est seulement signalé
+pub fn static_probes(config: &DiscoveryConfig, port: u16) -> Vec<(String, Probe)> {
+    let mut probes: Vec<(String, Probe)> = Vec::new();
+    for host in &config.static_hosts {
+        match resolve(host, port) {
+            Ok(to) if !probes.iter().any(|(_, p)| p.to == to) => probes.push((host.trim().to_string(), Probe { from: None, to })),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("static discovery host skipped: {}", e),
+        }
+    }
+    probes
+}
+
+// Instances résolues pendant `window` ; le port annoncé remplace le port par défaut
+pub fn browse_mdns(service: &str, default_port: u16, window: Duration) -> Result<Vec<DiscoveredDevice>, AppError> {
+    let daemon = mdns_sd::ServiceDaemon::new().map_err(|e| AppError::IoError(e.to_string()))?;
+    let events = daemon.browse(service).map_err(|e| AppError::IoError(e.to_string()))?;
+    let deadline = Instant::now() + window;
+    let mut found: Vec<DiscoveredDevice> = Vec::new();
+    while let Ok(event) = events.recv_deadline(deadline) {
+        let mdns_sd::ServiceEvent::ServiceResolved(info) = event else { continue };
+        for ip in info.get_addresses_v4() {
+            if found.iter().any(|d| d.ip == ip.to_string()) {
+                continue;
+            }
+            found.push(DiscoveredDevice {
+                ip: ip.to_string(),
+                port: Some(info.get_port()).filter(|p| *p != 0).unwrap_or(default_port),
+                device: info.get_property_val_str("device").map(String::from),
+                ver: info.get_property_val_str("ver").and_then(|v| v.parse().ok()),
+                ble_mac: info.get_property_val_str("ble_mac").map(String::from),
+                wifi_mac: info.get_property_val_str("wifi_mac").map(String::from),
+                nickname: None,
+            });
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+// Adresses hôtes d'une plage IPv4 "a.b.c.d/n" (réseau et broadcast exclus au-delà d'un /31)
+pub fn hosts(cidr: &str) -> Result<Vec<Ipv4Addr>, AppError> {
+    let cidr = cidr.trim();
+    let (addr, prefix) = cidr.split_once('/').unwrap_or((cidr, "32"));
+    let addr: Ipv4Addr = addr
+        .parse()
+        .map_err(|_| AppError::InvalidInput(format!("Invalid IPv4 address: {}", addr)))?;
+    let prefix: u32 = prefix
+        .parse()
+        .ok()
+        .filter(|p| *p <= 32)
+        .ok_or_else(|| AppError::InvalidInput(format!("Invalid prefix length: {}", prefix)))?;
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_SWEEP_ADDRESSES {
+        return Err(AppError::InvalidInput(format!("Range {} is too large (at most /20)", cidr)));
+    }
+    let network = u64::from(u32::from(addr) & u32::MAX.checked_shl(32 - prefix).unwrap_or(0));
+    let (first, last) = if size <= 2 { (network, network + size - 1) } else { (network + 1, network + size - 2) };
+    Ok((first..=last).map(|ip| Ipv4Addr::from(ip as u32)).collect())
+}
+
+// Espace les envois de toutes les sondes d'un balayage
+struct Pacer {
+    next: Mutex<Instant>,
+    interval: Duration,
+}
+
+impl Pacer {
+    fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        std::thread::sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
+
+// Sonde chaque adresse en unicast ; les adresses muettes sont simplement ignorées
+pub fn sweep(transport: &UdpTransport, local_port: u16, hosts: &[Ipv4Addr], port: u16, config: &DiscoveryConfig) -> Vec<DiscoveredDevice> {
+    let next = AtomicUsize::new(0);
+    let found = Mutex::new(Vec::new());
+    let pacer = Pacer { next: Mutex::new(Instant::now()), interval: Duration::from_millis(config.sweep_interval_ms) };
+    let timeout = Duration::from_millis(config.sweep_timeout_ms);
+    tracing::info!(hosts = hosts.len(), "sweeping for devices");
+
+    std::thread::scope(|s| {
+        for _ in 0..config.sweep_concurrency.clamp(1, hosts.len().max(1)) {
+            s.spawn(|| {
+                while let Some(ip) = hosts.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    pacer.wait();
+                    let target = SocketAddr::from((*ip, port)).to_string();
+                    let reply = tauri::async_runtime::block_on(transport.request(local_port, &target, methods::GET_DEVICE, methods::probe_params(), timeout));
+                    if let Ok(result) = reply {
+                        let device = DiscoveredDevice::from_result(ip.to_string(), port, &result);
+                        found.lock().unwrap_or_else(|e| e.into_inner()).push(device);
+                    }
+                }
+            });
+        }
+    });
+
+    let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    found.sort_by_key(|d| d.ip.parse::<Ipv4Addr>().ok());
+    found
+}
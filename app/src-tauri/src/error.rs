@@ -0,0 +1,117 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+// Erreur renvoyée par les commandes : le front réagit sur `code` (stable),
+// `message` est le contexte en anglais destiné aux logs
+#[derive(Debug, Clone)]
+pub enum AppError {
+    // Pas de réponse de l'appareil (ou d'un service externe) dans le délai
+    Timeout(String),
+    // Aucun appareil enregistré, appareil inconnu, service non configuré
+    NotConfigured(String),
+    // Objet error JSON-RPC renvoyé par l'appareil
+    DeviceRejected { code: i64, message: String },
+    // Réponse ou entrée illisible
+    ParseError(String),
+    IoError(String),
+    // Paramètre refusé avant tout envoi (mode inconnu, intervalle trop court...)
+    InvalidInput(String),
+    // Lecture seule, PIN absent ou invalide
+    Forbidden(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Timeout(_) => "timeout",
+            AppError::NotConfigured(_) => "not_configured",
+            AppError::DeviceRejected { .. } => "device_rejected",
+            AppError::ParseError(_) => "parse_error",
+            AppError::IoError(_) => "io_error",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::DeviceRejected { code, message } => write!(f, "Device rejected the request ({}): {}", code, message),
+            AppError::Timeout(message)
+            | AppError::NotConfigured(message)
+            | AppError::ParseError(message)
+            | AppError::IoError(message)
+            | AppError::InvalidInput(message)
+            | AppError::Forbidden(message)
+            | AppError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+// { "code": "device_rejected", "message": "...", "device_code": -32601 }
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AppError", 3)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.to_string())?;
+        if let AppError::DeviceRejected { code, .. } = self {
+            s.serialize_field("device_code", code)?;
+        } else {
+            s.skip_field("device_code")?;
+        }
+        s.end()
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// Erreurs encore textuelles des modules internes
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Internal(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Internal(message.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            // macOS remonte EAGAIN (os error 35) sur un socket sans réponse
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => AppError::Timeout(e.to_string()),
+            _ => AppError::IoError(e.to_string()),
+        }
+    }
+}
+
+impl From<marstek_protocol::Error> for AppError {
+    fn from(e: marstek_protocol::Error) -> Self {
+        match e {
+            marstek_protocol::Error::Timeout(message) => AppError::Timeout(message),
+            marstek_protocol::Error::Rejected { code, message } => AppError::DeviceRejected { code, message },
+            marstek_protocol::Error::Parse(message) => AppError::ParseError(message),
+            marstek_protocol::Error::Io(message) => AppError::IoError(message),
+            marstek_protocol::Error::InvalidInput(message) => AppError::InvalidInput(message),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::ParseError(e.to_string())
+    }
+}
+
+impl From<AppError> for String {
+    fn from(e: AppError) -> Self {
+        e.to_string()
+    }
+}
@@ -0,0 +1,287 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const HTTP_TIMEOUT_MS: u64 = 5000;
+const EASEE_API: &str = "https://api.easee.com/api";
+// Relevé plus ancien que N périodes : état inconnu, la décharge n'est plus bridée
+const STALE_PERIODS: u32 = 3;
+
+// Source de l'état de charge de la voiture
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EvChargerSource {
+    // OpenEVSE : GET /status (state 3 : en charge)
+    OpenEvse { host: String },
+    // go-e Charger, API v2 locale : GET /api/status (car 2 : en charge)
+    GoE { host: String },
+    // easee : API cloud, compte de l'application easee
+    Easee { charger_id: String, username: String },
+    // Sujet MQTT du broker configuré : "1", "true", "on", "charging" ou une puissance en W
+    Mqtt { topic: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EvPolicy {
+    // État affiché, automatismes inchangés
+    Ignore,
+    // Aucune décharge pendant la charge de la voiture
+    #[default]
+    BlockDischarge,
+    // Décharge plafonnée à limit_w
+    LimitDischarge,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct EvChargerConfig {
+    pub enabled: bool,
+    pub source: Option<EvChargerSource>,
+    pub policy: EvPolicy,
+    // Plafond de décharge (policy limit_discharge), [W]
+    pub limit_w: u32,
+    // Au-delà de cette puissance la voiture est considérée en charge (sources qui la mesurent), [W]
+    pub min_power_w: f32,
+    pub interval_s: u64,
+    // easee, uniquement si le trousseau système est indisponible
+    pub password: Option<String>,
+}
+
+impl Default for EvChargerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: None,
+            policy: EvPolicy::BlockDischarge,
+            limit_w: 200,
+            min_power_w: 1000.0,
+            interval_s: 10,
+            password: None,
+        }
+    }
+}
+
+impl EvChargerConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.interval_s < 2 {
+            return Err(AppError::InvalidInput("EV charger interval must be at least 2 s".to_string()));
+        }
+        if self.min_power_w < 0.0 {
+            return Err(AppError::InvalidInput("min_power_w must be >= 0".to_string()));
+        }
+        if self.enabled && self.source.is_none() {
+            return Err(AppError::InvalidInput("Choose an EV charger source".to_string()));
+        }
+        Ok(())
+    }
+
+    // Plafond de décharge imposé aux boucles de régulation, [W] ; None : pas de contrainte
+    pub fn discharge_cap(&self, charging: Option<bool>) -> Option<i64> {
+        if !self.enabled || charging != Some(true) {
+            return None;
+        }
+        match self.policy {
+            EvPolicy::Ignore => None,
+            EvPolicy::BlockDischarge => Some(0),
+            EvPolicy::LimitDischarge => Some(i64::from(self.limit_w)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct EvReading {
+    pub charging: bool,
+    pub power_w: Option<f32>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default, PartialEq)]
+pub struct EvChargerStatus {
+    // None : état inconnu (pas encore lu, source muette)
+    pub charging: Option<bool>,
+    pub power_w: Option<f32>,
+    pub last_update: Option<String>,
+    pub last_error: Option<String>,
+}
+
+fn client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn fetch_json(url: &str, token: Option<&str>) -> Result<serde_json::Value, String> {
+    let mut request = client()?.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().and_then(|r| r.error_for_status()).and_then(|r| r.json()).map_err(|e| e.to_string())
+}
+
+// Au-dessus du seuil, ou état « en charge » de la borne quand elle ne mesure rien
+fn reading(state_charging: bool, power_w: Option<f32>, min_power_w: f32) -> EvReading {
+    let charging = match power_w {
+        Some(power) => state_charging && power >= min_power_w,
+        None => state_charging,
+    };
+    EvReading { charging, power_w }
+}
+
+pub fn parse_mqtt(payload: &str, min_power_w: f32) -> Result<EvReading, String> {
+    let payload = payload.trim().trim_matches('"').to_ascii_lowercase();
+    if let Ok(power) = payload.parse::<f32>() {
+        // 0/1 : état ; au-delà : puissance
+        if power == 0.0 || power == 1.0 {
+            return Ok(EvReading { charging: power == 1.0, power_w: None });
+        }
+        return Ok(EvReading { charging: power >= min_power_w, power_w: Some(power) });
+    }
+    match payload.as_str() {
+        "true" | "on" | "charging" => Ok(EvReading { charging: true, power_w: None }),
+        "false" | "off" | "idle" | "connected" | "disconnected" | "complete" => Ok(EvReading { charging: false, power_w: None }),
+        _ => Err(format!("Unrecognized EV charger payload: {}", payload)),
+    }
+}
+
+struct EaseeToken {
+    token: String,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    config: EvChargerConfig,
+    password: Option<String>,
+    easee: Option<EaseeToken>,
+    status: EvChargerStatus,
+    updated: Option<Instant>,
+}
+
+// Dernier état connu de la borne, lu par la boucle de polling dédiée et par les automatismes
+pub struct EvChargerMonitor {
+    state: Mutex<MonitorState>,
+    wake: Condvar,
+}
+
+impl Default for EvChargerMonitor {
+    fn default() -> Self {
+        Self { state: Mutex::new(MonitorState::default()), wake: Condvar::new() }
+    }
+}
+
+impl EvChargerMonitor {
+    fn lock(&self) -> std::sync::MutexGuard<'_, MonitorState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn configure(&self, config: &EvChargerConfig, password: Option<String>) {
+        let mut state = self.lock();
+        state.config = config.clone();
+        state.password = password;
+        state.easee = None;
+        state.status = EvChargerStatus::default();
+        state.updated = None;
+        self.wake.notify_all();
+    }
+
+    pub fn config(&self) -> EvChargerConfig {
+        self.lock().config.clone()
+    }
+
+    pub fn status(&self) -> EvChargerStatus {
+        let state = self.lock();
+        let stale = Duration::from_secs(state.config.interval_s.max(1) * u64::from(STALE_PERIODS));
+        if state.updated.is_some_and(|at| at.elapsed() > stale) {
+            return EvChargerStatus { charging: None, ..state.status.clone() };
+        }
+        state.status.clone()
+    }
+
+    // Plafond de décharge à appliquer maintenant
+    pub fn discharge_cap(&self) -> Option<i64> {
+        let charging = self.status().charging;
+        self.lock().config.discharge_cap(charging)
+    }
+
+    pub fn wait_next(&self) {
+        let mut state = self.lock();
+        if state.config.enabled {
+            let interval = Duration::from_secs(state.config.interval_s);
+            state = self.wake.wait_timeout(state, interval).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        while !state.config.enabled {
+            state = self.wake.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    // Renvoie true si l'état de charge a changé
+    pub fn record(&self, result: Result<EvReading, String>) -> bool {
+        let before = self.status().charging;
+        let mut state = self.lock();
+        match result {
+            Ok(reading) => {
+                state.status = EvChargerStatus {
+                    charging: Some(reading.charging),
+                    power_w: reading.power_w,
+                    last_update: Some(chrono::Local::now().to_rfc3339()),
+                    last_error: None,
+                };
+                state.updated = Some(Instant::now());
+            }
+            Err(e) => state.status.last_error = Some(e),
+        }
+        drop(state);
+        self.status().charging != before
+    }
+
+    // Sources HTTP ; la source MQTT arrive par le pont MQTT
+    pub fn poll(&self) -> Option<Result<EvReading, String>> {
+        let (config, password) = {
+            let state = self.lock();
+            (state.config.clone(), state.password.clone())
+        };
+        let min_power_w = config.min_power_w;
+        let result = match config.source? {
+            EvChargerSource::OpenEvse { host } => fetch_json(&format!("http://{}/status", host), None).map(|json| {
+                let power = json.get("power").and_then(|v| v.as_f64()).map(|v| v as f32);
+                reading(json.get("state").and_then(|v| v.as_i64()) == Some(3), power, min_power_w)
+            }),
+            EvChargerSource::GoE { host } => fetch_json(&format!("http://{}/api/status?filter=car,nrg", host), None).map(|json| {
+                // nrg[11] : puissance totale, [W]
+                let power = json.pointer("/nrg/11").and_then(|v| v.as_f64()).map(|v| v as f32);
+                reading(json.get("car").and_then(|v| v.as_i64()) == Some(2), power, min_power_w)
+            }),
+            EvChargerSource::Easee { charger_id, username } => self.easee_token(&username, password.as_deref()).and_then(|token| {
+                let json = fetch_json(&format!("{}/chargers/{}/state", EASEE_API, charger_id), Some(&token))?;
+                // chargerOpMode 3 : en charge ; totalPower en kW
+                let power = json.get("totalPower").and_then(|v| v.as_f64()).map(|kw| (kw * 1000.0) as f32);
+                Ok(reading(json.get("chargerOpMode").and_then(|v| v.as_i64()) == Some(3), power, min_power_w))
+            }),
+            EvChargerSource::Mqtt { .. } => return None,
+        };
+        Some(result)
+    }
+
+    fn easee_token(&self, username: &str, password: Option<&str>) -> Result<String, String> {
+        if let Some(token) = self.lock().easee.as_ref().filter(|t| t.expires > Instant::now()) {
+            return Ok(token.token.clone());
+        }
+        let password = password.ok_or("easee password is not set")?;
+        let json: serde_json::Value = client()?
+            .post(format!("{}/accounts/login", EASEE_API))
+            .json(&serde_json::json!({ "userName": username, "password": password }))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .map_err(|e| e.to_string())?;
+        let token = json.get("accessToken").and_then(|v| v.as_str()).ok_or("easee login returned no token")?.to_string();
+        // Marge d'une minute avant l'expiration annoncée
+        let expires_in = json.get("expiresIn").and_then(|v| v.as_u64()).unwrap_or(3600).saturating_sub(60);
+        self.lock().easee = Some(EaseeToken { token: token.clone(), expires: Instant::now() + Duration::from_secs(expires_in) });
+        Ok(token)
+    }
+}
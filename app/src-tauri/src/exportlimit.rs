@@ -0,0 +1,148 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+pub const MIN_INTERVAL_MS: u64 = 2000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportLimitExecutor {
+    // Consigne Passive (ES.SetMode)
+    #[default]
+    Passive,
+    // Consigne Modbus TCP (contrôle avancé requis)
+    Modbus,
+}
+
+// Plafond d'injection du gestionnaire de réseau : le surplus PV au-delà part dans la batterie
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ExportLimitConfig {
+    pub enabled: bool,
+    // Appareil piloté ; absent : appareil courant
+    pub device: Option<String>,
+    pub executor: ExportLimitExecutor,
+    // Injection maximale autorisée, [W]
+    pub limit_w: f32,
+    // Marge visée sous la limite ; la charge n'est réduite qu'au-delà de deux fois cette marge, [W]
+    pub hysteresis_w: f32,
+    pub interval_ms: u64,
+    pub max_charge_w: u32,
+    // Plus de charge à ce SOC ou au-dessus, [%]
+    pub max_soc: u32,
+}
+
+impl Default for ExportLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            executor: ExportLimitExecutor::Passive,
+            limit_w: 800.0,
+            hysteresis_w: 100.0,
+            interval_ms: 5000,
+            max_charge_w: 2500,
+            max_soc: 100,
+        }
+    }
+}
+
+impl ExportLimitConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.interval_ms < MIN_INTERVAL_MS {
+            return Err(AppError::InvalidInput(format!("Export limit interval must be at least {} ms", MIN_INTERVAL_MS)));
+        }
+        if self.limit_w < 0.0 || self.hysteresis_w < 0.0 {
+            return Err(AppError::InvalidInput("limit_w and hysteresis_w must be >= 0".to_string()));
+        }
+        if self.max_soc == 0 || self.max_soc > 100 {
+            return Err(AppError::InvalidInput(format!("Invalid max SOC {} %", self.max_soc)));
+        }
+        Ok(())
+    }
+
+    // Nouvelle consigne (< 0 en charge) ; grid_power : > 0 en soutirage, < 0 en injection
+    pub fn setpoint(&self, current: i64, grid_power: f32, soc: Option<u32>) -> i64 {
+        let export = -grid_power;
+        // Ni dépassement ni charge en cours : une décharge pilotée ailleurs n'est pas touchée
+        if current >= 0 && export <= self.limit_w {
+            return current;
+        }
+        if soc.is_some_and(|soc| soc >= self.max_soc) {
+            return current.max(0);
+        }
+        let current = current.min(0) as f32;
+        let wanted = if export > self.limit_w {
+            current - (export - self.limit_w + self.hysteresis_w)
+        } else if export < self.limit_w - 2.0 * self.hysteresis_w {
+            // Surplus retombé : la charge est réduite pour revenir à une marge sous la limite
+            current + (self.limit_w - self.hysteresis_w - export)
+        } else {
+            current
+        };
+        wanted.clamp(-(self.max_charge_w as f32), 0.0).round() as i64
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct ExportLimitStatus {
+    pub device: Option<String>,
+    pub grid_power: Option<f32>,
+    pub soc: Option<u32>,
+    // Injection au-delà de la limite, [W]
+    pub excess_w: Option<f32>,
+    // [W] : < 0 en charge
+    pub setpoint: Option<i64>,
+    // Batterie pleine ou charge maximale atteinte : la limite ne peut plus être tenue
+    pub saturated: bool,
+    pub last_error: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+pub struct ExportLimiter {
+    config: Mutex<ExportLimitConfig>,
+    wake: Condvar,
+    status: Mutex<ExportLimitStatus>,
+}
+
+impl ExportLimiter {
+    pub fn new(config: ExportLimitConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            wake: Condvar::new(),
+            status: Mutex::new(ExportLimitStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> ExportLimitConfig {
+        self.config.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn configure(&self, config: ExportLimitConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        self.wake.notify_all();
+    }
+
+    pub fn status(&self) -> ExportLimitStatus {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn record(&self, status: ExportLimitStatus) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    // Même cadence que le Poller : bloque tant que le contrôleur est désactivé
+    pub fn wait_next(&self) {
+        let mut config = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        if config.enabled {
+            let interval = Duration::from_millis(config.interval_ms);
+            config = self.wake.wait_timeout(config, interval).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        while !config.enabled {
+            config = self.wake.wait(config).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
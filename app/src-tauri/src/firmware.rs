@@ -0,0 +1,96 @@
+use crate::error::AppError;
+use crate::DeviceInfo;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FirmwareConfig {
+    // JSON { "<modèle>": { "version": 153, "notes": "..." } }, modèle tel que renvoyé par Marstek.GetDevice
+    pub manifest_url: Option<String>,
+}
+
+impl FirmwareConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        match &self.manifest_url {
+            Some(url) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                Err(AppError::InvalidInput(format!("Invalid firmware manifest URL: {}", url)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Release {
+    pub version: u32,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+pub fn fetch_manifest(url: &str) -> Result<HashMap<String, Release>, AppError> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .and_then(|client| client.get(url).send())
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| if e.is_timeout() { AppError::Timeout(e.to_string()) } else { AppError::IoError(e.to_string()) })?
+        .json()
+        .map_err(|e| AppError::ParseError(format!("Invalid firmware manifest: {}", e)))
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct FirmwareCheck {
+    pub device: Option<String>,
+    pub current: Option<u32>,
+    // Absent : modèle inconnu du manifeste
+    pub latest: Option<u32>,
+    pub update_available: bool,
+    pub notes: Option<String>,
+}
+
+pub fn check(info: &DeviceInfo, manifest: &HashMap<String, Release>) -> FirmwareCheck {
+    let release = info
+        .device
+        .as_deref()
+        .and_then(|model| manifest.iter().find(|(name, _)| name.eq_ignore_ascii_case(model)))
+        .map(|(_, release)| release);
+    FirmwareCheck {
+        device: info.device.clone(),
+        current: info.ver,
+        latest: release.map(|r| r.version),
+        update_available: matches!((info.ver, release), (Some(current), Some(r)) if r.version > current),
+        notes: release.and_then(|r| r.notes.clone()),
+    }
+}
+
+// Une mise à jour à la fois ; les commandes de pilotage sont refusées tant qu'elle dure
+#[derive(Default)]
+pub struct OtaTracker {
+    active: Mutex<Option<String>>,
+}
+
+impl OtaTracker {
+    pub fn start(&self, device: &str) -> Result<(), AppError> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(current) = active.as_deref() {
+            return Err(AppError::Forbidden(format!("A firmware update is already running on {}", current)));
+        }
+        *active = Some(device.to_string());
+        Ok(())
+    }
+
+    pub fn finish(&self) {
+        *self.active.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    pub fn active(&self) -> Option<String> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
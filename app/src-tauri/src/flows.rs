@@ -0,0 +1,104 @@
+use crate::kpi;
+use crate::{EnergyStatus, MeterStatus};
+use serde::Serialize;
+
+// En deçà, un flux est du bruit de mesure et vaut 0, [W]
+const NOISE_W: f64 = 5.0;
+
+// Flux orientés entre PV, batterie, réseau et foyer, [W], toujours >= 0.
+// Répartition : le PV alimente d'abord le foyer, puis la batterie, puis le réseau ; la batterie en
+// décharge alimente le foyer avant le réseau ; le réseau complète le foyer puis la charge.
+// Sans compteur réseau, les flux qui passent par le réseau ou le foyer restent inconnus (None).
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct EnergyFlows {
+    pub pv_to_load: Option<f64>,
+    pub pv_to_battery: Option<f64>,
+    pub pv_to_grid: Option<f64>,
+    pub grid_to_load: Option<f64>,
+    pub grid_to_battery: Option<f64>,
+    pub battery_to_load: Option<f64>,
+    pub battery_to_grid: Option<f64>,
+    // Consommation du foyer déduite du bilan
+    pub load_power: Option<f64>,
+    // Sources - consommations après répartition : 0 pour des relevés cohérents, [W]
+    pub imbalance: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl EnergyFlows {
+    // Noms communs à l'interface et aux exports (MQTT, InfluxDB)
+    pub fn named(&self) -> [(&'static str, Option<f64>); 8] {
+        [
+            ("pv_to_load", self.pv_to_load),
+            ("pv_to_battery", self.pv_to_battery),
+            ("pv_to_grid", self.pv_to_grid),
+            ("grid_to_load", self.grid_to_load),
+            ("grid_to_battery", self.grid_to_battery),
+            ("battery_to_load", self.battery_to_load),
+            ("battery_to_grid", self.battery_to_grid),
+            ("load_power", self.load_power),
+        ]
+    }
+}
+
+fn clean(power: f64) -> f64 {
+    if power < NOISE_W { 0.0 } else { power.round() }
+}
+
+// Prend au plus `wanted` dans `available` et décompte des deux
+fn take(available: &mut f64, wanted: &mut f64) -> f64 {
+    let amount = available.min(*wanted).max(0.0);
+    *available -= amount;
+    *wanted -= amount;
+    amount
+}
+
+// Conventions des relevés : réseau > 0 en soutirage, batterie > 0 en charge
+pub fn resolve(energy: &EnergyStatus, meter: Option<&MeterStatus>) -> EnergyFlows {
+    let mut warnings = Vec::new();
+    let raw_pv = f64::from(energy.pv_power.unwrap_or(0.0));
+    if raw_pv < -NOISE_W {
+        warnings.push(format!("PV power is negative ({:.0} W), counted as 0", raw_pv));
+    }
+    let battery = f64::from(energy.bat_power.unwrap_or(0.0));
+    let mut pv = raw_pv.max(0.0);
+    let mut charge = battery.max(0.0);
+    let mut discharge = (-battery).max(0.0);
+
+    let Some(grid) = meter.and_then(|m| m.total_power).map(f64::from) else {
+        warnings.push("No grid meter reading: grid and load flows are unknown".to_string());
+        return EnergyFlows { pv_to_battery: Some(clean(take(&mut pv, &mut charge))), warnings, ..Default::default() };
+    };
+    let mut import = grid.max(0.0);
+    let mut export = (-grid).max(0.0);
+    let sources = pv + import + discharge;
+    let raw_load = pv + grid - battery;
+    if raw_load < -NOISE_W {
+        warnings.push(format!("Readings imply a negative load ({:.0} W), counted as 0", raw_load));
+    }
+    let load = kpi::load(pv, grid, battery);
+    let sinks = load + charge + export;
+
+    let mut demand = load;
+    let pv_to_load = take(&mut pv, &mut demand);
+    let battery_to_load = take(&mut discharge, &mut demand);
+    let grid_to_load = take(&mut import, &mut demand);
+    let pv_to_battery = take(&mut pv, &mut charge);
+    let grid_to_battery = take(&mut import, &mut charge);
+    let pv_to_grid = take(&mut pv, &mut export);
+    let battery_to_grid = take(&mut discharge, &mut export);
+
+    EnergyFlows {
+        pv_to_load: Some(clean(pv_to_load)),
+        pv_to_battery: Some(clean(pv_to_battery)),
+        pv_to_grid: Some(clean(pv_to_grid)),
+        grid_to_load: Some(clean(grid_to_load)),
+        grid_to_battery: Some(clean(grid_to_battery)),
+        battery_to_load: Some(clean(battery_to_load)),
+        battery_to_grid: Some(clean(battery_to_grid)),
+        load_power: Some(clean(load)),
+        imbalance: (sources - sinks).round(),
+        warnings,
+    }
+}
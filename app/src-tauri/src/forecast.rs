@@ -0,0 +1,245 @@
+use crate::error::AppError;
+use chrono::{DateTime, Local, NaiveDateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const FORECAST_FILE: &str = "forecast.json";
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+// Forecast.Solar gratuit : 12 appels par heure ; les prévisions ne bougent guère plus vite
+const REFRESH_INTERVAL_S: i64 = 3600;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastProvider {
+    ForecastSolar,
+    OpenMeteo,
+}
+
+// Un pan de toit : azimut 0 = sud, -90 = est, 90 = ouest
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PvPlane {
+    // Inclinaison, [°] (0 : à plat)
+    pub declination: f64,
+    pub azimuth: f64,
+    pub kwp: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ForecastConfig {
+    pub enabled: bool,
+    pub provider: ForecastProvider,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub planes: Vec<PvPlane>,
+    // Open-Meteo : rendement global (onduleur, câbles, température) appliqué à l'irradiance
+    pub performance_ratio: f64,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: ForecastProvider::ForecastSolar,
+            latitude: 0.0,
+            longitude: 0.0,
+            planes: Vec::new(),
+            performance_ratio: 0.85,
+        }
+    }
+}
+
+impl ForecastConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(AppError::InvalidInput(format!("Invalid location {}, {}", self.latitude, self.longitude)));
+        }
+        for plane in &self.planes {
+            if !(0.0..=90.0).contains(&plane.declination) || !(-180.0..=180.0).contains(&plane.azimuth) || plane.kwp <= 0.0 {
+                return Err(AppError::InvalidInput("Each PV plane needs a declination of 0-90°, an azimuth of -180-180° and a positive kWp".to_string()));
+            }
+        }
+        if self.enabled && self.planes.is_empty() {
+            return Err(AppError::InvalidInput("At least one PV plane is required".to_string()));
+        }
+        Ok(())
+    }
+
+    fn source(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ForecastHour {
+    // Début de l'heure, heure locale RFC 3339
+    pub start: String,
+    // Puissance moyenne prévue sur l'heure, [W]
+    pub power: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ForecastDay {
+    pub day: String,
+    // [Wh]
+    pub energy: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SolarForecast {
+    pub fetched_at: Option<String>,
+    pub hours: Vec<ForecastHour>,
+    pub days: Vec<ForecastDay>,
+}
+
+fn fetch_json(url: &str) -> Result<serde_json::Value, AppError> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .and_then(|client| client.get(url).send())
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| if e.is_timeout() { AppError::Timeout(e.to_string()) } else { AppError::IoError(e.to_string()) })
+}
+
+// Points (instant, W) d'un pan
+fn fetch_plane(config: &ForecastConfig, plane: &PvPlane) -> Result<Vec<(DateTime<Utc>, f64)>, AppError> {
+    match config.provider {
+        ForecastProvider::ForecastSolar => {
+            let url = format!(
+                "https://api.forecast.solar/estimate/watts/{}/{}/{}/{}/{}?time=iso8601",
+                config.latitude, config.longitude, plane.declination, plane.azimuth, plane.kwp
+            );
+            let json = fetch_json(&url)?;
+            let watts = json
+                .pointer("/result")
+                .and_then(|v| v.as_object())
+                .ok_or_else(|| AppError::ParseError("Forecast.Solar: missing result".to_string()))?;
+            Ok(watts
+                .iter()
+                .filter_map(|(time, w)| Some((DateTime::parse_from_rfc3339(time).ok()?.with_timezone(&Utc), w.as_f64()?)))
+                .collect())
+        }
+        ForecastProvider::OpenMeteo => {
+            let url = format!(
+                "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&hourly=global_tilted_irradiance&tilt={}&azimuth={}&timezone=UTC&forecast_days=2",
+                config.latitude, config.longitude, plane.declination, plane.azimuth
+            );
+            let json = fetch_json(&url)?;
+            let times = json.pointer("/hourly/time").and_then(|v| v.as_array());
+            let values = json.pointer("/hourly/global_tilted_irradiance").and_then(|v| v.as_array());
+            let (Some(times), Some(values)) = (times, values) else {
+                return Err(AppError::ParseError("Open-Meteo: missing hourly irradiance".to_string()));
+            };
+            // Irradiance moyenne de l'heure écoulée [W/m²] ; 1000 W/m² = puissance crête
+            Ok(times
+                .iter()
+                .zip(values)
+                .filter_map(|(time, gti)| {
+                    let end = NaiveDateTime::parse_from_str(time.as_str()?, "%Y-%m-%dT%H:%M").ok()?.and_utc();
+                    let power = gti.as_f64()? * plane.kwp * config.performance_ratio;
+                    Some((end - chrono::Duration::hours(1), power))
+                })
+                .collect())
+        }
+    }
+}
+
+pub fn fetch(config: &ForecastConfig) -> Result<SolarForecast, AppError> {
+    // Puissance moyenne de chaque heure, additionnée sur les pans
+    let mut hours: BTreeMap<DateTime<Utc>, f64> = BTreeMap::new();
+    for plane in &config.planes {
+        let mut plane_hours: BTreeMap<DateTime<Utc>, (f64, u32)> = BTreeMap::new();
+        for (time, power) in fetch_plane(config, plane)? {
+            let hour = time.with_minute(0).and_then(|t| t.with_second(0)).unwrap_or(time);
+            let entry = plane_hours.entry(hour).or_default();
+            entry.0 += power;
+            entry.1 += 1;
+        }
+        for (hour, (sum, count)) in plane_hours {
+            *hours.entry(hour).or_default() += sum / f64::from(count);
+        }
+    }
+
+    let mut days: BTreeMap<String, f64> = BTreeMap::new();
+    let hours: Vec<ForecastHour> = hours
+        .into_iter()
+        .map(|(start, power)| {
+            let local = start.with_timezone(&Local);
+            *days.entry(local.format("%Y-%m-%d").to_string()).or_default() += power;
+            ForecastHour { start: local.to_rfc3339(), power }
+        })
+        .collect();
+    Ok(SolarForecast {
+        fetched_at: Some(Local::now().to_rfc3339()),
+        hours,
+        days: days.into_iter().map(|(day, energy)| ForecastDay { day, energy }).collect(),
+    })
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CacheFile {
+    source: String,
+    forecast: SolarForecast,
+}
+
+// Dernière prévision, conservée sur disque pour la consulter hors ligne
+pub struct ForecastCache {
+    path: PathBuf,
+    file: Mutex<CacheFile>,
+}
+
+impl ForecastCache {
+    pub fn open(path: PathBuf) -> Self {
+        let file = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, file: Mutex::new(file) }
+    }
+
+    // refresh : ignore l'ancienneté du cache ; en cas d'échec, la dernière prévision reste servie
+    pub fn get(&self, config: &ForecastConfig, refresh: bool) -> Result<SolarForecast, AppError> {
+        let source = config.source();
+        let cached = {
+            let file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            (file.source == source).then(|| file.forecast.clone())
+        };
+        let fresh = cached.as_ref().and_then(|f| f.fetched_at.as_deref()).and_then(|t| DateTime::parse_from_rfc3339(t).ok()).is_some_and(|t| {
+            Local::now().signed_duration_since(t).num_seconds() < REFRESH_INTERVAL_S
+        });
+        if let Some(forecast) = cached.as_ref().filter(|_| fresh && !refresh) {
+            return Ok(forecast.clone());
+        }
+
+        match fetch(config) {
+            Ok(forecast) => {
+                let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+                *file = CacheFile { source, forecast: forecast.clone() };
+                if let Err(e) = self.save(&file) {
+                    tracing::warn!("failed to write forecast cache: {}", e);
+                }
+                Ok(forecast)
+            }
+            Err(e) => match cached {
+                Some(forecast) => {
+                    tracing::warn!("solar forecast refresh failed, serving cached forecast: {}", e);
+                    Ok(forecast)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn save(&self, file: &CacheFile) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(fs::write(&self.path, serde_json::to_string(file)?)?)
+    }
+}
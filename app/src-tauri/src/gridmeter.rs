@@ -0,0 +1,88 @@
+use crate::MeterStatus;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const HTTP_TIMEOUT_MS: u64 = 2000;
+
+// Compteur de référence remplaçant le CT Marstek (EM.GetStatus) ; puissances > 0 en soutirage
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GridMeter {
+    // HomeWizard P1 (API locale v1 activée) : GET /api/v1/data
+    HomeWizardP1 { host: String },
+    // Shelly EM (Gen1) : GET /status, une pince par canal
+    ShellyEm {
+        host: String,
+        #[serde(default)]
+        channel: usize,
+    },
+    // Shelly 3EM (Gen1) : GET /status
+    Shelly3Em { host: String },
+    // Shelly Pro 3EM (Gen2) : GET /rpc/EM.GetStatus?id=0
+    ShellyPro3Em { host: String },
+}
+
+fn fetch_json(url: &str) -> Result<serde_json::Value, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| e.to_string())
+}
+
+fn number(json: &serde_json::Value, pointer: &str) -> Option<f32> {
+    json.pointer(pointer).and_then(|v| v.as_f64()).map(|v| v as f32)
+}
+
+// Valeurs par phase : puissance, tension, courant
+type Phases = [Option<f32>; 3];
+
+fn meter(source: &str, total_power: Option<f32>, power: Phases, voltage: Phases, current: Phases) -> Result<MeterStatus, String> {
+    let ([a_power, b_power, c_power], [a_voltage, b_voltage, c_voltage], [a_current, b_current, c_current]) = (power, voltage, current);
+    Ok(MeterStatus {
+        ct_state: None,
+        a_power,
+        b_power,
+        c_power,
+        total_power: Some(total_power.ok_or("No total power in meter response")?),
+        a_voltage,
+        b_voltage,
+        c_voltage,
+        a_current,
+        b_current,
+        c_current,
+        source: Some(source.to_string()),
+        unknown: Default::default(),
+    })
+}
+
+pub fn read(source: &GridMeter) -> Result<MeterStatus, String> {
+    match source {
+        GridMeter::HomeWizardP1 { host } => {
+            let json = fetch_json(&format!("http://{}/api/v1/data", host))?;
+            // Compteurs monophasés : pas de valeurs l2/l3
+            let phases = |field: &str, unit: &str| [1, 2, 3].map(|n| number(&json, &format!("/active_{}_l{}_{}", field, n, unit)));
+            meter("homewizard_p1", number(&json, "/active_power_w"), phases("power", "w"), phases("voltage", "v"), phases("current", "a"))
+        }
+        GridMeter::ShellyEm { host, channel } => {
+            let json = fetch_json(&format!("http://{}/status", host))?;
+            let voltage = [number(&json, &format!("/emeters/{}/voltage", channel)), None, None];
+            meter("shelly_em", number(&json, &format!("/emeters/{}/power", channel)), [None, None, None], voltage, [None, None, None])
+        }
+        GridMeter::Shelly3Em { host } => {
+            let json = fetch_json(&format!("http://{}/status", host))?;
+            let phases = |field: &str| [0, 1, 2].map(|n| number(&json, &format!("/emeters/{}/{}", n, field)));
+            meter("shelly_3em", number(&json, "/total_power"), phases("power"), phases("voltage"), phases("current"))
+        }
+        GridMeter::ShellyPro3Em { host } => {
+            let json = fetch_json(&format!("http://{}/rpc/EM.GetStatus?id=0", host))?;
+            let phases = |field: &str| ["a", "b", "c"].map(|p| number(&json, &format!("/{}_{}", p, field)));
+            meter("shelly_pro_3em", number(&json, "/total_act_power"), phases("act_power"), phases("voltage"), phases("current"))
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+const MAX_EVENTS: usize = 200;
+
+// Valeurs par défaut : EN 50160 (230 V ±10 %, 50 Hz ±1 %)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GridQualityConfig {
+    pub alerts_enabled: bool,
+    pub min_voltage: f32,
+    pub max_voltage: f32,
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+}
+
+impl Default for GridQualityConfig {
+    fn default() -> Self {
+        Self {
+            alerts_enabled: true,
+            min_voltage: 207.0,
+            max_voltage: 253.0,
+            min_frequency: 49.5,
+            max_frequency: 50.5,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct GridQualityEvent {
+    pub timestamp: String,
+    pub quantity: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct GridQualityReport {
+    pub voltage: Option<f32>,
+    pub frequency: Option<f32>,
+    pub min_voltage_seen: Option<f32>,
+    pub max_voltage_seen: Option<f32>,
+    pub min_frequency_seen: Option<f32>,
+    pub max_frequency_seen: Option<f32>,
+    pub events: Vec<GridQualityEvent>,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    report: GridQualityReport,
+    events: VecDeque<GridQualityEvent>,
+}
+
+#[derive(Default)]
+pub struct GridQualityMonitor {
+    state: Mutex<MonitorState>,
+}
+
+fn widen(seen: &mut Option<f32>, value: f32, pick: fn(f32, f32) -> f32) {
+    *seen = Some(seen.map_or(value, |s| pick(s, value)));
+}
+
+impl GridQualityMonitor {
+    // Retourne les dépassements détectés sur cet échantillon
+    pub fn observe(&self, config: &GridQualityConfig, voltage: Option<f32>, frequency: Option<f32>) -> Vec<GridQualityEvent> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let MonitorState { report, events } = &mut *state;
+        report.voltage = voltage;
+        report.frequency = frequency;
+
+        let mut out_of_range = Vec::new();
+        let timestamp = chrono::Local::now().to_rfc3339();
+        let mut check = |quantity: &'static str, value: f32, min: f32, max: f32| {
+            if value < min || value > max {
+                out_of_range.push(GridQualityEvent { timestamp: timestamp.clone(), quantity, value, min, max });
+            }
+        };
+        if let Some(v) = voltage {
+            widen(&mut report.min_voltage_seen, v, f32::min);
+            widen(&mut report.max_voltage_seen, v, f32::max);
+            check("voltage", v, config.min_voltage, config.max_voltage);
+        }
+        if let Some(f) = frequency {
+            widen(&mut report.min_frequency_seen, f, f32::min);
+            widen(&mut report.max_frequency_seen, f, f32::max);
+            check("frequency", f, config.min_frequency, config.max_frequency);
+        }
+
+        for event in &out_of_range {
+            tracing::warn!("grid {} out of range: {} (allowed {}..{})", event.quantity, event.value, event.min, event.max);
+            if events.len() >= MAX_EVENTS {
+                events.pop_front();
+            }
+            events.push_back(event.clone());
+        }
+
+        if config.alerts_enabled { out_of_range } else { Vec::new() }
+    }
+
+    pub fn report(&self) -> GridQualityReport {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        GridQualityReport {
+            events: state.events.iter().cloned().collect(),
+            ..state.report.clone()
+        }
+    }
+}
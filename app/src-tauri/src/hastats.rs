@@ -0,0 +1,85 @@
+use crate::history::HistorySample;
+use crate::kpi::{self, Interval};
+use crate::mqtt::object_id;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const SOURCE: &str = "marstip";
+
+// (clé, libellé, énergie de l'intervalle en Wh)
+type Channel = (&'static str, &'static str, fn(&Interval) -> f64);
+
+const CHANNELS: [Channel; 5] = [
+    ("grid_import", "Grid import", |i| i.grid.max(0.0) * i.hours),
+    ("grid_export", "Grid export", |i| (-i.grid).max(0.0) * i.hours),
+    ("pv", "PV production", |i| i.pv.max(0.0) * i.hours),
+    ("battery_charge", "Battery charge", |i| i.battery.max(0.0) * i.hours),
+    ("battery_discharge", "Battery discharge", |i| (-i.battery).max(0.0) * i.hours),
+];
+
+// Métadonnées attendues par recorder.async_add_external_statistics
+#[derive(Serialize)]
+pub struct StatisticMetadata {
+    pub source: &'static str,
+    // "marstip:<appareil>_<flux>", minuscules
+    pub statistic_id: String,
+    pub name: String,
+    pub unit_of_measurement: &'static str,
+    pub has_mean: bool,
+    pub has_sum: bool,
+}
+
+#[derive(Serialize)]
+pub struct StatisticPoint {
+    // Début de l'heure, RFC 3339
+    pub start: String,
+    // Cumul depuis le début de l'export, [kWh] ; state reprend sum faute de vrai index de compteur
+    pub state: f64,
+    pub sum: f64,
+}
+
+#[derive(Serialize)]
+pub struct ExternalStatistic {
+    pub metadata: StatisticMetadata,
+    pub stats: Vec<StatisticPoint>,
+}
+
+// Sommes horaires des flux d'énergie, format du tableau de bord Énergie de Home Assistant
+pub fn statistics(device: &str, label: &str, samples: &[(i64, HistorySample)]) -> Vec<ExternalStatistic> {
+    let mut hours: BTreeMap<i64, [f64; CHANNELS.len()]> = BTreeMap::new();
+    for interval in kpi::intervals(samples) {
+        let hour = interval.start - interval.start.rem_euclid(3600);
+        let sums = hours.entry(hour).or_default();
+        for (sum, (_, _, energy)) in sums.iter_mut().zip(CHANNELS) {
+            *sum += energy(&interval);
+        }
+    }
+
+    let id = object_id(device).to_lowercase();
+    CHANNELS
+        .iter()
+        .enumerate()
+        .map(|(index, (key, name, _))| {
+            let mut total = 0.0;
+            let stats = hours
+                .iter()
+                .filter_map(|(hour, sums)| {
+                    total += sums[index] / 1000.0;
+                    let start = chrono::DateTime::from_timestamp(*hour, 0)?.to_rfc3339();
+                    Some(StatisticPoint { start, state: total, sum: total })
+                })
+                .collect();
+            ExternalStatistic {
+                metadata: StatisticMetadata {
+                    source: SOURCE,
+                    statistic_id: format!("{}:{}_{}", SOURCE, id, key),
+                    name: format!("{} {}", label, name),
+                    unit_of_measurement: "kWh",
+                    has_mean: false,
+                    has_sum: true,
+                },
+                stats,
+            }
+        })
+        .collect()
+}
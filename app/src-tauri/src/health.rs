@@ -0,0 +1,130 @@
+use crate::history::{HealthSample, HistorySample};
+use crate::kpi::MAX_SAMPLE_GAP_S;
+use serde::Serialize;
+
+pub const DEFAULT_DAYS: u32 = 90;
+// Baisse de SOC minimale d'une décharge pour en déduire la capacité : en deçà, l'arrondi du SOC domine, [%]
+const MIN_SOC_SWING: f64 = 30.0;
+// Estimations moyennées pour la capacité mesurée actuelle
+const RECENT_ESTIMATES: usize = 5;
+// Estimations nécessaires avant de donner une tendance
+const MIN_TREND_ESTIMATES: usize = 3;
+const SECONDS_PER_YEAR: f64 = 365.25 * 86400.0;
+
+// Une décharge continue (sans recharge ni trou de polling) assez profonde
+#[derive(Serialize, Clone)]
+pub struct CapacityEstimate {
+    // Début de la décharge, secondes Unix
+    pub timestamp: i64,
+    pub discharge_wh: f64,
+    // [points de %]
+    pub soc_drop: f64,
+    // Énergie restituée ramenée à un cycle complet 100 → 0 %, [Wh]
+    pub capacity_wh: f64,
+    // capacity_wh / capacité nominale, [%]
+    pub percent_of_rated: Option<f64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct HealthReport {
+    pub device: String,
+    pub rated_capacity_wh: Option<f64>,
+    // Dernières valeurs du firmware, absentes s'il ne les expose pas
+    pub soh: Option<u32>,
+    pub cycle_count: Option<u32>,
+    pub history: Vec<HealthSample>,
+    pub discharges: Vec<CapacityEstimate>,
+    // Moyenne des dernières estimations, [Wh]
+    pub measured_capacity_wh: Option<f64>,
+    // Énergie déchargée sur la période / capacité nominale
+    pub equivalent_cycles: Option<f64>,
+    // Pente de percent_of_rated, [points de % par an] : négative quand la capacité baisse
+    pub fade_per_year: Option<f64>,
+}
+
+struct Discharge {
+    start: i64,
+    soc: f64,
+    wh: f64,
+    last_soc: f64,
+}
+
+impl Discharge {
+    fn estimate(&self, rated: Option<f64>) -> Option<CapacityEstimate> {
+        let soc_drop = self.soc - self.last_soc;
+        (soc_drop >= MIN_SOC_SWING && self.wh > 0.0).then(|| {
+            let capacity_wh = self.wh * 100.0 / soc_drop;
+            CapacityEstimate {
+                timestamp: self.start,
+                discharge_wh: self.wh,
+                soc_drop,
+                capacity_wh,
+                percent_of_rated: rated.map(|rated| capacity_wh / rated * 100.0),
+            }
+        })
+    }
+}
+
+// samples : (secondes Unix, échantillon) triés ; puissance batterie négative en décharge
+pub fn discharges(samples: &[(i64, HistorySample)], rated: Option<f64>) -> (Vec<CapacityEstimate>, f64) {
+    let mut estimates = Vec::new();
+    let mut total_wh = 0.0;
+    let mut current: Option<Discharge> = None;
+    for pair in samples.windows(2) {
+        let ((start, sample), (end, next)) = (&pair[0], &pair[1]);
+        let gap = end - start;
+        let power = sample.battery_power.map(f64::from).unwrap_or(0.0);
+        // Recharge ou trou de polling : la décharge en cours s'arrête là
+        let usable = gap > 0 && gap <= MAX_SAMPLE_GAP_S && power <= 0.0;
+        let Some(soc) = sample.soc.map(f64::from).filter(|_| usable) else {
+            if let Some(estimate) = current.take().and_then(|d| d.estimate(rated)) {
+                estimates.push(estimate);
+            }
+            continue;
+        };
+        let wh = -power * gap as f64 / 3600.0;
+        total_wh += wh;
+        let discharge = current.get_or_insert(Discharge { start: *start, soc, wh: 0.0, last_soc: soc });
+        discharge.wh += wh;
+        if let Some(soc) = next.soc {
+            discharge.last_soc = f64::from(soc);
+        }
+    }
+    if let Some(estimate) = current.and_then(|d| d.estimate(rated)) {
+        estimates.push(estimate);
+    }
+    (estimates, total_wh)
+}
+
+// Régression linéaire sur (temps, % de la capacité nominale)
+fn trend(estimates: &[CapacityEstimate]) -> Option<f64> {
+    let points: Vec<(f64, f64)> = estimates
+        .iter()
+        .filter_map(|e| e.percent_of_rated.map(|p| (e.timestamp as f64 / SECONDS_PER_YEAR, p)))
+        .collect();
+    if points.len() < MIN_TREND_ESTIMATES {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    (variance > 0.0).then(|| points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>() / variance)
+}
+
+pub fn report(device: String, rated: Option<f64>, samples: &[(i64, HistorySample)], history: Vec<HealthSample>) -> HealthReport {
+    let (discharges, total_wh) = discharges(samples, rated);
+    let recent = &discharges[discharges.len().saturating_sub(RECENT_ESTIMATES)..];
+    let measured_capacity_wh = (!recent.is_empty()).then(|| recent.iter().map(|e| e.capacity_wh).sum::<f64>() / recent.len() as f64);
+    HealthReport {
+        device,
+        rated_capacity_wh: rated,
+        soh: history.iter().rev().find_map(|h| h.soh),
+        cycle_count: history.iter().rev().find_map(|h| h.cycle_count),
+        fade_per_year: trend(&discharges),
+        equivalent_cycles: rated.map(|rated| total_wh / rated),
+        measured_capacity_wh,
+        discharges,
+        history,
+    }
+}
@@ -0,0 +1,866 @@
+use crate::error::AppError;
+use crate::kpi::{self, DailyKpis};
+use crate::units::UnitPreferences;
+use crate::{DeviceEvent, MeterStatus};
+use crate::rollup::{MetricSummary, Rollup, SummaryPeriod};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const HISTORY_FILE: &str = "history.sqlite";
+pub const METRICS: [&str; 5] = ["soc", "pv_power", "grid_power", "battery_power", "temperature"];
+
+const HOUR_S: i64 = 3600;
+const DAY_S: i64 = 86_400;
+const LAST_VACUUM: &str = "last_vacuum";
+
+// Les relevés bruts plus anciens que raw_days sont moyennés par heure puis supprimés.
+// Les KPI journaliers et le rapport de santé batterie n'utilisent que les relevés bruts.
+// Les résumés min/max/moyenne/dernier sont tenus à jour à chaque relevé : horaires purgés après hourly_days,
+// journaliers conservés indéfiniment.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    // Relevés bruts et mesures par phase, [jours]
+    pub raw_days: u32,
+    // Moyennes horaires, [jours] ; 0 : conservées indéfiniment
+    pub hourly_days: u32,
+    // Journal de l'appareil et santé batterie, [jours] ; 0 : conservés indéfiniment
+    pub events_days: u32,
+    // Sous-échantillonnage et purge, [h]
+    pub maintenance_interval_h: u32,
+    // Compactage (VACUUM) après une purge, au plus une fois par intervalle, [jours] ; 0 : jamais
+    pub vacuum_interval_days: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self { enabled: true, raw_days: 90, hourly_days: 730, events_days: 730, maintenance_interval_h: 6, vacuum_interval_days: 7 }
+    }
+}
+
+impl RetentionConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.raw_days == 0 {
+            return Err(AppError::InvalidInput("Raw samples must be kept for at least 1 day".to_string()));
+        }
+        if self.hourly_days != 0 && self.hourly_days < self.raw_days {
+            return Err(AppError::InvalidInput("Hourly aggregates must be kept at least as long as raw samples".to_string()));
+        }
+        if !(1..=168).contains(&self.maintenance_interval_h) {
+            return Err(AppError::InvalidInput("Maintenance interval must be between 1 and 168 h".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Bilan d'une passe de maintenance
+#[derive(Serialize, Clone, Default)]
+pub struct MaintenanceReport {
+    // Heures ajoutées aux moyennes horaires
+    pub downsampled_hours: usize,
+    pub deleted_samples: usize,
+    pub deleted_hourly: usize,
+    pub deleted_phases: usize,
+    pub deleted_events: usize,
+    pub vacuumed: bool,
+    // Secondes Unix
+    pub timestamp: i64,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct TableStats {
+    pub name: &'static str,
+    pub rows: i64,
+    // Secondes Unix
+    pub oldest: Option<i64>,
+    pub newest: Option<i64>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct StorageStats {
+    pub path: String,
+    // Fichier principal et journal WAL éventuel, [octets]
+    pub file_bytes: u64,
+    // Pages libres récupérables par un VACUUM, [octets]
+    pub free_bytes: i64,
+    pub tables: Vec<TableStats>,
+    pub last_vacuum: Option<i64>,
+}
+
+// Une ligne par snapshot du dashboard
+#[derive(Serialize, Clone, Default)]
+pub struct HistorySample {
+    pub soc: Option<u32>,
+    pub pv_power: Option<f32>,
+    // Compteur CT, négatif en injection
+    pub grid_power: Option<f32>,
+    pub battery_power: Option<f32>,
+    pub temperature: Option<f32>,
+}
+
+// Moyennes sur un intervalle de `resolution` secondes
+#[derive(Serialize, Clone)]
+pub struct HistoryPoint {
+    // Début de l'intervalle, secondes Unix
+    pub timestamp: i64,
+    pub soc: Option<f64>,
+    pub pv_power: Option<f64>,
+    pub grid_power: Option<f64>,
+    pub battery_power: Option<f64>,
+    pub temperature: Option<f64>,
+}
+
+impl HistorySample {
+    fn metrics(&self) -> [(&'static str, Option<f64>); 5] {
+        [
+            ("soc", self.soc.map(f64::from)),
+            ("pv_power", self.pv_power.map(f64::from)),
+            ("grid_power", self.grid_power.map(f64::from)),
+            ("battery_power", self.battery_power.map(f64::from)),
+            ("temperature", self.temperature.map(f64::from)),
+        ]
+    }
+}
+
+fn rollup_table(period: SummaryPeriod) -> &'static str {
+    match period {
+        SummaryPeriod::Hour => "hourly_rollups",
+        SummaryPeriod::Day => "daily_rollups",
+    }
+}
+
+fn insert_sample(tx: &Transaction, device: &str, timestamp: i64, sample: &HistorySample) -> Result<(), String> {
+    tx.execute(
+        "INSERT INTO samples (timestamp, device, soc, pv_power, grid_power, battery_power, temperature)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![timestamp, device, sample.soc, sample.pv_power, sample.grid_power, sample.battery_power, sample.temperature],
+    )
+    .map_err(|e| e.to_string())?;
+    for (metric, value) in sample.metrics() {
+        let Some(value) = value else { continue };
+        for period in [SummaryPeriod::Hour, SummaryPeriod::Day] {
+            let (table, bucket) = (rollup_table(period), period.start(timestamp));
+            let rollup = tx
+                .query_row(
+                    &format!("SELECT min, max, sum, count, last, last_at FROM {} WHERE device = ?1 AND timestamp = ?2 AND metric = ?3", table),
+                    params![device, bucket, metric],
+                    |row| Ok(Rollup { min: row.get(0)?, max: row.get(1)?, sum: row.get(2)?, count: row.get(3)?, last: row.get(4)?, last_at: row.get(5)? }),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let rollup = match rollup {
+                Some(mut rollup) => {
+                    rollup.add(value, timestamp);
+                    rollup
+                }
+                None => Rollup::new(value, timestamp),
+            };
+            tx.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {} (timestamp, device, metric, min, max, sum, count, last, last_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    table
+                ),
+                params![bucket, device, metric, rollup.min, rollup.max, rollup.sum, rollup.count, rollup.last, rollup.last_at],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+impl HistoryPoint {
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        match name {
+            "soc" => self.soc,
+            "pv_power" => self.pv_power,
+            "grid_power" => self.grid_power,
+            "battery_power" => self.battery_power,
+            "temperature" => self.temperature,
+            _ => None,
+        }
+    }
+}
+
+const PHASE_NAMES: [&str; 3] = ["A", "B", "C"];
+
+// Mesures par phase du compteur, enregistrées à part : la plupart des installations n'en ont pas
+#[derive(Clone, Copy, Default)]
+pub struct PhaseSample {
+    pub voltage: [Option<f32>; 3],
+    pub current: [Option<f32>; 3],
+    pub power: [Option<f32>; 3],
+}
+
+impl PhaseSample {
+    // None : compteur sans aucune valeur par phase
+    pub fn from_meter(meter: &MeterStatus) -> Option<Self> {
+        let sample = Self {
+            voltage: [meter.a_voltage, meter.b_voltage, meter.c_voltage],
+            current: [meter.a_current, meter.b_current, meter.c_current],
+            power: [meter.a_power, meter.b_power, meter.c_power],
+        };
+        [sample.voltage, sample.current, sample.power].iter().flatten().any(Option::is_some).then_some(sample)
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct PhaseValues {
+    pub phase: &'static str,
+    pub voltage: Option<f64>,
+    pub current: Option<f64>,
+    pub power: Option<f64>,
+}
+
+// Moyennes par phase sur un intervalle de `resolution` secondes
+#[derive(Serialize, Clone)]
+pub struct PhaseHistoryPoint {
+    pub timestamp: i64,
+    pub phases: Vec<PhaseValues>,
+    // Écart entre la phase la plus haute et la plus basse, au moins deux phases mesurées : [V], [A]
+    pub voltage_spread: Option<f64>,
+    pub current_spread: Option<f64>,
+}
+
+fn spread(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    let values: Vec<f64> = values.flatten().collect();
+    (values.len() >= 2).then(|| values.iter().cloned().fold(f64::MIN, f64::max) - values.iter().cloned().fold(f64::MAX, f64::min))
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineSource {
+    // Journal de l'appareil (Marstek.GetEvents)
+    Device,
+    Alert,
+    // Commande envoyée par marstip (journal d'audit)
+    Command,
+}
+
+// Ligne de la chronologie commune : journal de l'appareil, alertes et commandes de l'app
+#[derive(Serialize, Clone)]
+pub struct TimelineEntry {
+    // Secondes Unix
+    pub timestamp: i64,
+    pub source: TimelineSource,
+    pub title: String,
+    pub code: Option<u32>,
+}
+
+// Santé relevée par le firmware : une ligne à chaque changement, au plus une par heure sinon
+const HEALTH_INTERVAL_S: i64 = 3600;
+
+#[derive(Serialize, Clone, PartialEq)]
+pub struct HealthSample {
+    // Secondes Unix
+    pub timestamp: i64,
+    // [%]
+    pub soh: Option<u32>,
+    pub cycle_count: Option<u32>,
+}
+
+pub fn validate_metrics(metrics: &[String]) -> Result<(), String> {
+    match metrics.iter().find(|m| !METRICS.contains(&m.as_str())) {
+        Some(unknown) => Err(format!("Unknown metric: {} (expected one of {})", unknown, METRICS.join(", "))),
+        None => Ok(()),
+    }
+}
+
+// Échappement CSV minimal pour un libellé libre (surnom)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Horodatage et unités selon les préférences ; valeur absente = cellule vide
+// device : surnom de l'appareil, ou son id à défaut
+pub fn to_csv(points: &[HistoryPoint], metrics: &[String], device: &str, units: &UnitPreferences) -> String {
+    let device = csv_field(device);
+    let columns: Vec<String> = metrics.iter().map(|m| units.column(m)).collect();
+    let mut out = format!("timestamp,device,{}\n", columns.join(","));
+    let decimals = usize::from(units.decimals);
+    for point in points {
+        let time = chrono::DateTime::from_timestamp(point.timestamp, 0).map(|t| units.export_time(t)).unwrap_or_default();
+        let values: Vec<String> = metrics
+            .iter()
+            .map(|m| point.metric(m).map(|v| format!("{:.*}", decimals, units.convert(m, v))).unwrap_or_default())
+            .collect();
+        out.push_str(&format!("{},{},{}\n", time, device, values.join(",")));
+    }
+    out
+}
+
+// Horodatage UTC ISO-8601 quelle que soit la préférence : le JSON est relu par d'autres outils
+pub fn to_json(points: &[HistoryPoint], metrics: &[String], device: &str, units: &UnitPreferences) -> Result<String, String> {
+    let rows: Vec<serde_json::Value> = points
+        .iter()
+        .map(|point| {
+            let mut row = serde_json::Map::new();
+            let time = chrono::DateTime::from_timestamp(point.timestamp, 0).map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+            row.insert("timestamp".to_string(), serde_json::json!(time));
+            row.insert("device".to_string(), serde_json::json!(device));
+            for m in metrics {
+                row.insert(units.column(m), serde_json::json!(point.metric(m).map(|v| units.convert(m, v))));
+            }
+            serde_json::Value::Object(row)
+        })
+        .collect();
+    serde_json::to_string_pretty(&rows).map_err(|e| e.to_string())
+}
+
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                soc INTEGER,
+                pv_power REAL,
+                grid_power REAL,
+                battery_power REAL,
+                temperature REAL
+            );
+            CREATE INDEX IF NOT EXISTS samples_device_time ON samples (device, timestamp);
+            CREATE TABLE IF NOT EXISTS hourly_rollups (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                metric TEXT NOT NULL,
+                min REAL NOT NULL,
+                max REAL NOT NULL,
+                sum REAL NOT NULL,
+                count INTEGER NOT NULL,
+                last REAL NOT NULL,
+                last_at INTEGER NOT NULL,
+                PRIMARY KEY (device, timestamp, metric)
+            );
+            CREATE TABLE IF NOT EXISTS daily_rollups (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                metric TEXT NOT NULL,
+                min REAL NOT NULL,
+                max REAL NOT NULL,
+                sum REAL NOT NULL,
+                count INTEGER NOT NULL,
+                last REAL NOT NULL,
+                last_at INTEGER NOT NULL,
+                PRIMARY KEY (device, timestamp, metric)
+            );
+            CREATE TABLE IF NOT EXISTS phase_samples (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                a_voltage REAL, b_voltage REAL, c_voltage REAL,
+                a_current REAL, b_current REAL, c_current REAL,
+                a_power REAL, b_power REAL, c_power REAL
+            );
+            CREATE INDEX IF NOT EXISTS phase_samples_device_time ON phase_samples (device, timestamp);
+            CREATE TABLE IF NOT EXISTS device_events (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                code INTEGER,
+                message TEXT,
+                UNIQUE (device, timestamp, code, message)
+            );
+            CREATE TABLE IF NOT EXISTS battery_health (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                soh INTEGER,
+                cycle_count INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS battery_health_device_time ON battery_health (device, timestamp);
+            CREATE TABLE IF NOT EXISTS hourly_samples (
+                timestamp INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                soc REAL,
+                pv_power REAL,
+                grid_power REAL,
+                battery_power REAL,
+                temperature REAL,
+                samples INTEGER NOT NULL,
+                PRIMARY KEY (device, timestamp)
+            );
+            CREATE TABLE IF NOT EXISTS daily_energy (
+                date TEXT NOT NULL,
+                device TEXT NOT NULL,
+                pv_wh REAL NOT NULL,
+                import_wh REAL NOT NULL,
+                export_wh REAL NOT NULL,
+                charge_wh REAL NOT NULL,
+                discharge_wh REAL NOT NULL,
+                source TEXT NOT NULL,
+                PRIMARY KEY (device, date)
+            );
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value INTEGER
+            );",
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(Self { conn: Mutex::new(conn), path: path.to_path_buf() })
+    }
+
+    // Heures entières au-delà de raw_days moyennées puis supprimées, puis purge des tables au-delà de leur durée.
+    // VACUUM au plus une fois par vacuum_interval_days, et seulement si des lignes ont été supprimées.
+    pub fn maintain(&self, config: &RetentionConfig, now: i64, force_vacuum: bool) -> Result<MaintenanceReport, String> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let raw_cutoff = (now - i64::from(config.raw_days) * DAY_S) / HOUR_S * HOUR_S;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut report = MaintenanceReport { timestamp: now, ..Default::default() };
+        report.downsampled_hours = tx
+            .execute(
+                "INSERT OR REPLACE INTO hourly_samples (timestamp, device, soc, pv_power, grid_power, battery_power, temperature, samples)
+                 SELECT (timestamp / ?2) * ?2 AS bucket, device, AVG(soc), AVG(pv_power), AVG(grid_power), AVG(battery_power), AVG(temperature), COUNT(*)
+                 FROM samples WHERE timestamp < ?1
+                 GROUP BY device, bucket",
+                params![raw_cutoff, HOUR_S],
+            )
+            .map_err(|e| e.to_string())?;
+        report.deleted_samples = tx.execute("DELETE FROM samples WHERE timestamp < ?1", params![raw_cutoff]).map_err(|e| e.to_string())?;
+        report.deleted_phases = tx.execute("DELETE FROM phase_samples WHERE timestamp < ?1", params![raw_cutoff]).map_err(|e| e.to_string())?;
+        if config.hourly_days > 0 {
+            let cutoff = now - i64::from(config.hourly_days) * DAY_S;
+            report.deleted_hourly = tx.execute("DELETE FROM hourly_samples WHERE timestamp < ?1", params![cutoff]).map_err(|e| e.to_string())?
+                + tx.execute("DELETE FROM hourly_rollups WHERE timestamp < ?1", params![cutoff]).map_err(|e| e.to_string())?;
+        }
+        if config.events_days > 0 {
+            let cutoff = now - i64::from(config.events_days) * DAY_S;
+            report.deleted_events = tx.execute("DELETE FROM device_events WHERE timestamp < ?1", params![cutoff]).map_err(|e| e.to_string())?
+                + tx.execute("DELETE FROM battery_health WHERE timestamp < ?1", params![cutoff]).map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+
+        let deleted = report.deleted_samples + report.deleted_hourly + report.deleted_phases + report.deleted_events;
+        let last_vacuum: Option<i64> =
+            conn.query_row("SELECT value FROM meta WHERE key = ?1", params![LAST_VACUUM], |row| row.get(0)).optional().map_err(|e| e.to_string())?;
+        let due = config.vacuum_interval_days > 0
+            && deleted > 0
+            && last_vacuum.is_none_or(|last| now - last >= i64::from(config.vacuum_interval_days) * DAY_S);
+        if force_vacuum || due {
+            conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+            conn.execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)", params![LAST_VACUUM, now]).map_err(|e| e.to_string())?;
+            report.vacuumed = true;
+        }
+        Ok(report)
+    }
+
+    pub fn storage_stats(&self) -> Result<StorageStats, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tables = Vec::new();
+        // Les bilans importés sont datés au jour (minuit UTC)
+        for name in ["samples", "hourly_samples", "hourly_rollups", "daily_rollups", "phase_samples", "device_events", "battery_health", "daily_energy"] {
+            let column = if name == "daily_energy" { "CAST(strftime('%s', date) AS INTEGER)" } else { "timestamp" };
+            let (rows, oldest, newest) = conn
+                .query_row(&format!("SELECT COUNT(*), MIN({1}), MAX({1}) FROM {0}", name, column), [], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })
+                .map_err(|e| e.to_string())?;
+            tables.push(TableStats { name, rows, oldest, newest });
+        }
+        let pragma = |name: &str| conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0)).map_err(|e| e.to_string());
+        let free_bytes = pragma("freelist_count")? * pragma("page_size")?;
+        let last_vacuum = conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![LAST_VACUUM], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let wal = PathBuf::from(format!("{}-wal", self.path.display()));
+        let file_bytes = [self.path.as_path(), wal.as_path()].iter().filter_map(|p| std::fs::metadata(p).ok()).map(|m| m.len()).sum();
+        Ok(StorageStats { path: self.path.display().to_string(), file_bytes, free_bytes, tables, last_vacuum })
+    }
+
+    // Relevé brut et résumés de son heure et de son jour, dans la même transaction
+    pub fn record(&self, device: &str, timestamp: i64, sample: &HistorySample) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        insert_sample(&tx, device, timestamp, sample)?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    // Relevés importés ; une heure déjà couverte par des relevés avant l'import est ignorée en entier,
+    // ce qui rend un second import du même fichier sans effet
+    pub fn import_samples(&self, device: &str, samples: &[(i64, HistorySample)]) -> Result<usize, String> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut covered = HashMap::new();
+        for (timestamp, _) in samples {
+            let hour = timestamp.div_euclid(HOUR_S) * HOUR_S;
+            if covered.contains_key(&hour) {
+                continue;
+            }
+            let recorded: bool = tx
+                .query_row(
+                    "SELECT EXISTS (
+                         SELECT 1 FROM samples WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                         UNION ALL
+                         SELECT 1 FROM hourly_samples WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                     )",
+                    params![device, hour, hour + HOUR_S],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            covered.insert(hour, recorded);
+        }
+        let mut added = 0;
+        for (timestamp, sample) in samples {
+            if covered.get(&(timestamp.div_euclid(HOUR_S) * HOUR_S)) == Some(&false) {
+                insert_sample(&tx, device, *timestamp, sample)?;
+                added += 1;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(added)
+    }
+
+    // Résumés par heure ou par jour, toutes métriques, dans l'ordre chronologique
+    pub fn summaries(&self, device: &str, period: SummaryPeriod, from: i64, to: i64) -> Result<Vec<MetricSummary>, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT timestamp, metric, min, max, sum, count, last, last_at FROM {}
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 ORDER BY timestamp, metric",
+                rollup_table(period)
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to], |row| {
+                let rollup = Rollup { min: row.get(2)?, max: row.get(3)?, sum: row.get(4)?, count: row.get(5)?, last: row.get(6)?, last_at: row.get(7)? };
+                Ok(rollup.summary(row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    // Intervalles d'une heure ou plus : lus dans les résumés horaires, sans parcourir les relevés bruts
+    pub fn record_phases(&self, device: &str, timestamp: i64, sample: &PhaseSample) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let ([av, bv, cv], [ai, bi, ci], [ap, bp, cp]) = (sample.voltage, sample.current, sample.power);
+        conn.execute(
+            "INSERT INTO phase_samples (timestamp, device, a_voltage, b_voltage, c_voltage, a_current, b_current, c_current, a_power, b_power, c_power)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![timestamp, device, av, bv, cv, ai, bi, ci, ap, bp, cp],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    // Renvoie false quand la valeur précédente est identique et encore récente
+    pub fn record_health(&self, device: &str, sample: &HealthSample) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let last: Option<(i64, Option<u32>, Option<u32>)> = conn
+            .query_row(
+                "SELECT timestamp, soh, cycle_count FROM battery_health WHERE device = ?1 ORDER BY timestamp DESC LIMIT 1",
+                params![device],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if last.is_some_and(|(timestamp, soh, cycle_count)| {
+            soh == sample.soh && cycle_count == sample.cycle_count && sample.timestamp - timestamp < HEALTH_INTERVAL_S
+        }) {
+            return Ok(false);
+        }
+        conn.execute(
+            "INSERT INTO battery_health (timestamp, device, soh, cycle_count) VALUES (?1, ?2, ?3, ?4)",
+            params![sample.timestamp, device, sample.soh, sample.cycle_count],
+        )
+        .map(|_| true)
+        .map_err(|e| e.to_string())
+    }
+
+    pub fn health(&self, device: &str, from: i64, to: i64) -> Result<Vec<HealthSample>, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(
+                "SELECT timestamp, soh, cycle_count FROM battery_health
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 ORDER BY timestamp",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to], |row| {
+                Ok(HealthSample { timestamp: row.get(0)?, soh: row.get(1)?, cycle_count: row.get(2)? })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    // Renvoie le nombre d'entrées inconnues jusque-là ; les pages déjà vues sont ignorées
+    pub fn record_device_events(&self, device: &str, events: &[DeviceEvent]) -> Result<usize, String> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut added = 0;
+        for event in events {
+            added += tx
+                .execute(
+                    "INSERT OR IGNORE INTO device_events (timestamp, device, code, message) VALUES (?1, ?2, ?3, ?4)",
+                    params![event.ts, device, event.code, event.msg],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(added)
+    }
+
+    pub fn device_events(&self, device: &str, from: i64, to: i64) -> Result<Vec<TimelineEntry>, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(
+                "SELECT timestamp, code, message FROM device_events
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 ORDER BY timestamp",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to], |row| {
+                let code: Option<u32> = row.get(1)?;
+                let message: Option<String> = row.get(2)?;
+                Ok(TimelineEntry {
+                    timestamp: row.get(0)?,
+                    source: TimelineSource::Device,
+                    title: message.unwrap_or_else(|| format!("Event {}", code.unwrap_or_default())),
+                    code,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    pub fn query_phases(&self, device: &str, from: i64, to: i64, resolution: i64) -> Result<Vec<PhaseHistoryPoint>, String> {
+        let resolution = resolution.max(1);
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(
+                "SELECT (timestamp / ?4) * ?4 AS bucket,
+                        AVG(a_voltage), AVG(b_voltage), AVG(c_voltage),
+                        AVG(a_current), AVG(b_current), AVG(c_current),
+                        AVG(a_power), AVG(b_power), AVG(c_power)
+                 FROM phase_samples
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 GROUP BY bucket ORDER BY bucket",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to, resolution], |row| {
+                let mut phases = Vec::with_capacity(3);
+                for (i, phase) in PHASE_NAMES.into_iter().enumerate() {
+                    phases.push(PhaseValues { phase, voltage: row.get(1 + i)?, current: row.get(4 + i)?, power: row.get(7 + i)? });
+                }
+                Ok(PhaseHistoryPoint {
+                    timestamp: row.get(0)?,
+                    voltage_spread: spread(phases.iter().map(|p| p.voltage)),
+                    current_spread: spread(phases.iter().map(|p| p.current)),
+                    phases,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    pub fn query(&self, device: &str, from: i64, to: i64, resolution: i64) -> Result<Vec<HistoryPoint>, String> {
+        let resolution = resolution.max(1);
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let sql = if resolution % HOUR_S == 0 {
+            "SELECT (timestamp / ?4) * ?4 AS bucket,
+                    SUM(CASE metric WHEN 'soc' THEN sum END) / SUM(CASE metric WHEN 'soc' THEN count END),
+                    SUM(CASE metric WHEN 'pv_power' THEN sum END) / SUM(CASE metric WHEN 'pv_power' THEN count END),
+                    SUM(CASE metric WHEN 'grid_power' THEN sum END) / SUM(CASE metric WHEN 'grid_power' THEN count END),
+                    SUM(CASE metric WHEN 'battery_power' THEN sum END) / SUM(CASE metric WHEN 'battery_power' THEN count END),
+                    SUM(CASE metric WHEN 'temperature' THEN sum END) / SUM(CASE metric WHEN 'temperature' THEN count END)
+             FROM hourly_rollups
+             WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             GROUP BY bucket ORDER BY bucket"
+        } else {
+            "SELECT (timestamp / ?4) * ?4 AS bucket, AVG(soc), AVG(pv_power), AVG(grid_power), AVG(battery_power), AVG(temperature)
+             FROM (
+                 SELECT timestamp, soc, pv_power, grid_power, battery_power, temperature FROM samples
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 UNION ALL
+                 SELECT timestamp, soc, pv_power, grid_power, battery_power, temperature FROM hourly_samples
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+             )
+             GROUP BY bucket ORDER BY bucket"
+        };
+        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to, resolution], |row| {
+                Ok(HistoryPoint {
+                    timestamp: row.get(0)?,
+                    soc: row.get(1)?,
+                    pv_power: row.get(2)?,
+                    grid_power: row.get(3)?,
+                    battery_power: row.get(4)?,
+                    temperature: row.get(5)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    // Bilans journaliers importés ; un jour déjà importé ou couvert par des relevés locaux est ignoré
+    pub fn import_daily(&self, device: &str, days: &[DailyKpis], source: &str) -> Result<usize, String> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut added = 0;
+        for day in days {
+            let recorded: bool = tx
+                .query_row(
+                    "SELECT EXISTS (
+                         SELECT 1 FROM samples WHERE device = ?1 AND date(timestamp, 'unixepoch', 'localtime') = ?2
+                         UNION ALL
+                         SELECT 1 FROM hourly_samples WHERE device = ?1 AND date(timestamp, 'unixepoch', 'localtime') = ?2
+                     )",
+                    params![device, day.date],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if recorded {
+                continue;
+            }
+            added += tx
+                .execute(
+                    "INSERT OR IGNORE INTO daily_energy (date, device, pv_wh, import_wh, export_wh, charge_wh, discharge_wh, source)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![day.date, device, day.pv_wh, day.import_wh, day.export_wh, day.charge_wh, day.discharge_wh, source],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(added)
+    }
+
+    // from, to : YYYY-MM-DD inclus
+    pub fn imported_daily(&self, device: &str, from: &str, to: &str) -> Result<Vec<DailyKpis>, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(
+                "SELECT date, pv_wh, import_wh, export_wh, charge_wh, discharge_wh FROM daily_energy
+                 WHERE device = ?1 AND date >= ?2 AND date <= ?3
+                 ORDER BY date",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to], |row| {
+                Ok(kpi::from_totals(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
+    // Échantillons bruts dans l'ordre chronologique, pour intégrer l'énergie
+    pub fn samples(&self, device: &str, from: i64, to: i64) -> Result<Vec<(i64, HistorySample)>, String> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare(
+                "SELECT timestamp, soc, pv_power, grid_power, battery_power, temperature
+                 FROM samples
+                 WHERE device = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 ORDER BY timestamp",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![device, from, to], |row| {
+                Ok((
+                    row.get(0)?,
+                    HistorySample {
+                        soc: row.get(1)?,
+                        pv_power: row.get(2)?,
+                        grid_power: row.get(3)?,
+                        battery_power: row.get(4)?,
+                        temperature: row.get(5)?,
+                    },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> (HistoryStore, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("marstip-history-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (HistoryStore::open(&dir.join(HISTORY_FILE)).unwrap(), dir)
+    }
+
+    fn sample(soc: u32, grid_power: Option<f32>) -> HistorySample {
+        HistorySample { soc: Some(soc), grid_power, ..Default::default() }
+    }
+
+    #[test]
+    fn record_keeps_hourly_and_daily_rollups() {
+        let (history, dir) = store("rollups");
+        let hour = SummaryPeriod::Hour.start(1_700_000_000);
+        history.record("dev", hour + 10, &sample(40, Some(100.0))).unwrap();
+        history.record("dev", hour + 30, &sample(70, None)).unwrap();
+        // Arrivé en retard : compte dans les statistiques sans devenir la dernière valeur
+        history.record("dev", hour + 20, &sample(10, Some(-50.0))).unwrap();
+        history.record("other", hour + 15, &sample(99, None)).unwrap();
+
+        let hourly = history.summaries("dev", SummaryPeriod::Hour, hour, hour + HOUR_S).unwrap();
+        assert_eq!(hourly.iter().map(|s| s.metric.as_str()).collect::<Vec<_>>(), ["grid_power", "soc"]);
+        let soc = &hourly[1];
+        assert_eq!((soc.timestamp, soc.min, soc.max, soc.avg, soc.last, soc.samples), (hour, 10.0, 70.0, 40.0, 70.0, 3));
+        let grid = &hourly[0];
+        assert_eq!((grid.min, grid.max, grid.avg, grid.last, grid.samples), (-50.0, 100.0, 25.0, -50.0, 2));
+
+        let day = SummaryPeriod::Day.start(hour);
+        let daily = history.summaries("dev", SummaryPeriod::Day, day, day + 2 * 86_400).unwrap();
+        assert!(daily.iter().all(|s| s.timestamp == day));
+        assert_eq!(daily.iter().find(|s| s.metric == "soc").map(|s| s.samples), Some(3));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn hourly_resolutions_read_the_rollups() {
+        let (history, dir) = store("query");
+        // Aligné sur deux heures
+        let hour = 1_699_999_200;
+        for (offset, soc) in [(0, 20), (1_800, 40), (HOUR_S, 60), (HOUR_S + 60, 80)] {
+            history.record("dev", hour + offset, &sample(soc, None)).unwrap();
+        }
+        let hourly = history.query("dev", hour, hour + 2 * HOUR_S, HOUR_S).unwrap();
+        assert_eq!(hourly.iter().map(|p| (p.timestamp, p.soc)).collect::<Vec<_>>(), [(hour, Some(30.0)), (hour + HOUR_S, Some(70.0))]);
+        assert_eq!(hourly[0].grid_power, None);
+        // Sur deux heures : moyenne pondérée par le nombre de relevés de chaque heure
+        let merged = history.query("dev", hour, hour + 2 * HOUR_S, 2 * HOUR_S).unwrap();
+        assert_eq!(merged.iter().map(|p| (p.timestamp, p.soc)).collect::<Vec<_>>(), [(hour, Some(50.0))]);
+        // Sous l'heure : relevés bruts
+        let raw = history.query("dev", hour, hour + HOUR_S, 1_800).unwrap();
+        assert_eq!(raw.iter().map(|p| p.soc).collect::<Vec<_>>(), [Some(20.0), Some(40.0)]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn imported_samples_skip_hours_already_recorded() {
+        let (history, dir) = store("import");
+        let hour = 1_699_999_200;
+        history.record("dev", hour + 600, &sample(50, None)).unwrap();
+        let export = [(hour, sample(48, None)), (hour + 1_200, sample(49, None)), (hour + HOUR_S, sample(60, None)), (hour + HOUR_S + 300, sample(62, None))];
+        assert_eq!(history.import_samples("dev", &export).unwrap(), 2, "the first hour is already covered");
+        assert_eq!(history.import_samples("dev", &export).unwrap(), 0, "a second import adds nothing");
+        let summary = history.summaries("dev", SummaryPeriod::Hour, hour + HOUR_S, hour + 2 * HOUR_S).unwrap();
+        assert_eq!(summary.iter().map(|s| (s.metric.as_str(), s.samples, s.last)).collect::<Vec<_>>(), [("soc", 2, 62.0)]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::DashboardData;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const HTTP_TIMEOUT_MS: u64 = 5000;
+// Lignes conservées pendant une panne du serveur ; les plus anciennes sont abandonnées
+const MAX_BUFFERED_LINES: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct InfluxConfig {
+    pub enabled: bool,
+    // Ex : http://192.168.1.10:8086
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    // Uniquement si le trousseau système est indisponible
+    pub token: Option<String>,
+    pub measurement: String,
+    // Envoi dès que ce nombre de points est atteint...
+    pub batch_size: usize,
+    // ...ou que ce délai est écoulé depuis le dernier envoi, [s]
+    pub flush_interval_s: u64,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            org: String::new(),
+            bucket: String::new(),
+            token: None,
+            measurement: "marstip".to_string(),
+            batch_size: 12,
+            flush_interval_s: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct InfluxStatus {
+    pub buffered: usize,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+}
+
+// Échappement des clés/valeurs de tags (line protocol)
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+fn line(measurement: &str, device: &str, model: Option<&str>, data: &DashboardData, timestamp: i64) -> Option<String> {
+    let flows = data.flows.named().map(|(name, value)| (name, value.map(|v| v as f32)));
+    let fields: Vec<String> = [
+        ("soc", data.battery.soc.or(data.energy.bat_soc).map(|v| v as f32)),
+        ("temperature", data.battery.bat_temp),
+        ("heater_power", data.battery.heater_power),
+        ("pv_power", data.energy.pv_power),
+        ("battery_power", data.energy.bat_power),
+        ("ongrid_power", data.energy.ongrid_power),
+        ("offgrid_power", data.energy.offgrid_power),
+        ("grid_power", data.meter.as_ref().and_then(|m| m.total_power)),
+        ("total_pv_energy", data.energy.total_pv_energy),
+        ("total_grid_output_energy", data.energy.total_grid_output_energy),
+        ("total_grid_input_energy", data.energy.total_grid_input_energy),
+        ("total_load_energy", data.energy.total_load_energy),
+        ("self_consumption", data.kpis.self_consumption.map(|v| v as f32)),
+        ("self_sufficiency", data.kpis.self_sufficiency.map(|v| v as f32)),
+        ("round_trip_efficiency", data.kpis.round_trip_efficiency.map(|v| v as f32)),
+    ]
+    .into_iter()
+    .chain(flows)
+    .filter_map(|(name, value)| value.map(|v| format!("{}={}", name, v)))
+    .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let mut tags = format!("{},device={}", escape_tag(measurement), escape_tag(device));
+    if let Some(model) = model {
+        tags.push_str(&format!(",model={}", escape_tag(model)));
+    }
+    Some(format!("{} {} {}", tags, fields.join(","), timestamp))
+}
+
+struct ExporterState {
+    config: InfluxConfig,
+    token: Option<String>,
+    lines: VecDeque<String>,
+    last_flush: Instant,
+    status: InfluxStatus,
+}
+
+pub struct InfluxExporter {
+    state: Mutex<ExporterState>,
+}
+
+impl Default for InfluxExporter {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ExporterState {
+                config: InfluxConfig::default(),
+                token: None,
+                lines: VecDeque::new(),
+                last_flush: Instant::now(),
+                status: InfluxStatus::default(),
+            }),
+        }
+    }
+}
+
+fn write(config: &InfluxConfig, token: Option<&str>, body: String) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client
+        .post(format!("{}/api/v2/write", config.url.trim_end_matches('/')))
+        .query(&[("org", config.org.as_str()), ("bucket", config.bucket.as_str()), ("precision", "s")])
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+    request.send().and_then(|r| r.error_for_status()).map(|_| ()).map_err(|e| e.to_string())
+}
+
+impl InfluxExporter {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ExporterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn configure(&self, config: &InfluxConfig, token: Option<String>) {
+        let mut state = self.lock();
+        state.config = config.clone();
+        state.token = token;
+        if !config.enabled {
+            state.lines.clear();
+        }
+        state.status = InfluxStatus::default();
+    }
+
+    pub fn status(&self) -> InfluxStatus {
+        let state = self.lock();
+        InfluxStatus { buffered: state.lines.len(), ..state.status.clone() }
+    }
+
+    pub fn push(&self, device: &str, model: Option<&str>, data: &DashboardData, timestamp: i64) {
+        let mut state = self.lock();
+        if !state.config.enabled || state.config.url.is_empty() {
+            return;
+        }
+        let Some(line) = line(&state.config.measurement, device, model, data, timestamp) else { return };
+        if state.lines.len() >= MAX_BUFFERED_LINES {
+            state.lines.pop_front();
+        }
+        state.lines.push_back(line);
+
+        let interval = Duration::from_secs(state.config.flush_interval_s);
+        if state.lines.len() < state.config.batch_size.max(1) && state.last_flush.elapsed() < interval {
+            return;
+        }
+        // En cas d'échec les lignes restent en tampon et repartent au prochain envoi
+        state.last_flush = Instant::now();
+        let body = state.lines.iter().cloned().collect::<Vec<_>>().join("\n");
+        match write(&state.config, state.token.as_deref(), body) {
+            Ok(()) => {
+                state.lines.clear();
+                state.status.last_success = Some(chrono::Local::now().to_rfc3339());
+                state.status.last_error = None;
+            }
+            Err(e) => {
+                tracing::warn!("InfluxDB write failed ({} lines buffered): {}", state.lines.len(), e);
+                state.status.last_error = Some(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const HTTP_TIMEOUT_MS: u64 = 2000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PvSourceKind {
+    // Hoymiles via OpenDTU : GET /api/livedata/status
+    OpenDtu { host: String },
+    // N'importe quelle API JSON locale, valeurs lues par pointeur JSON (RFC 6901)
+    HttpJson {
+        url: String,
+        power_pointer: String,
+        #[serde(default)]
+        energy_pointer: Option<String>,
+        // Facteur vers des Wh (1000 si l'onduleur renvoie des kWh)
+        #[serde(default = "default_energy_scale")]
+        energy_scale: f64,
+    },
+}
+
+fn default_energy_scale() -> f64 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PvSource {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: PvSourceKind,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct PvReading {
+    // [W]
+    pub power: f32,
+    // [Wh]
+    pub total_energy: Option<f32>,
+}
+
+fn fetch_json(url: &str) -> Result<serde_json::Value, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| e.to_string())
+}
+
+fn read_number(json: &serde_json::Value, pointer: &str) -> Result<f64, String> {
+    json.pointer(pointer)
+        .and_then(|v| v.as_f64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+        .ok_or_else(|| format!("No numeric value at {}", pointer))
+}
+
+pub fn read(source: &PvSource) -> Result<PvReading, String> {
+    match &source.kind {
+        PvSourceKind::OpenDtu { host } => {
+            let json = fetch_json(&format!("http://{}/api/livedata/status", host))?;
+            Ok(PvReading {
+                power: read_number(&json, "/total/Power/v")? as f32,
+                // YieldTotal est exprimé en kWh
+                total_energy: read_number(&json, "/total/YieldTotal/v").ok().map(|kwh| (kwh * 1000.0) as f32),
+            })
+        }
+        PvSourceKind::HttpJson { url, power_pointer, energy_pointer, energy_scale } => {
+            let json = fetch_json(url)?;
+            Ok(PvReading {
+                power: read_number(&json, power_pointer)? as f32,
+                total_energy: energy_pointer
+                    .as_deref()
+                    .and_then(|p| read_number(&json, p).ok())
+                    .map(|v| (v * energy_scale) as f32),
+            })
+        }
+    }
+}
+
+// Somme des sources joignables ; une source en erreur est ignorée pour ce cycle
+pub fn read_all(sources: &[PvSource]) -> Option<PvReading> {
+    let mut total: Option<PvReading> = None;
+    for source in sources {
+        match read(source) {
+            Ok(reading) => {
+                let sum = total.get_or_insert_with(PvReading::default);
+                sum.power += reading.power;
+                if let Some(energy) = reading.total_energy {
+                    sum.total_energy = Some(sum.total_energy.unwrap_or(0.0) + energy);
+                }
+            }
+            Err(e) => tracing::warn!("PV source {} unavailable: {}", source.name, e),
+        }
+    }
+    total
+}
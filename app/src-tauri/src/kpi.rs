@@ -0,0 +1,147 @@
+use crate::history::HistorySample;
+use crate::{EnergyStatus, MeterStatus};
+use serde::Serialize;
+
+// Au-delà de cet écart entre deux échantillons, on n'intègre pas l'énergie, [s]
+pub const MAX_SAMPLE_GAP_S: i64 = 300;
+pub const DEFAULT_DAYS: u32 = 7;
+pub const MAX_DAYS: u32 = 366;
+
+// Indicateurs calculés côté backend pour que l'interface et les exports affichent les mêmes chiffres, [%]
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct Kpis {
+    // Part de la production PV consommée sur place
+    pub self_consumption: Option<f64>,
+    // Part de la consommation couverte sans soutirage réseau
+    pub self_sufficiency: Option<f64>,
+    // Énergie restituée / énergie stockée, sur les compteurs cumulés de l'appareil
+    pub round_trip_efficiency: Option<f64>,
+}
+
+fn percent(part: f64, total: f64) -> Option<f64> {
+    (total > 0.0).then(|| (part / total * 100.0).clamp(0.0, 100.0))
+}
+
+fn self_consumption(pv: f64, export: f64) -> Option<f64> {
+    percent(pv - export, pv)
+}
+
+fn self_sufficiency(load: f64, import: f64) -> Option<f64> {
+    percent(load - import, load)
+}
+
+// Consommation du foyer : PV + réseau (négatif en injection) - batterie (positif en charge)
+pub fn load(pv: f64, grid: f64, battery: f64) -> f64 {
+    (pv + grid - battery).max(0.0)
+}
+
+// Sans compteur réseau, ni autoconsommation ni autarcie ne sont mesurables
+pub fn instant(energy: &EnergyStatus, meter: Option<&MeterStatus>) -> Kpis {
+    let grid = meter.and_then(|m| m.total_power).map(f64::from);
+    let pv = f64::from(energy.pv_power.unwrap_or(0.0));
+    let battery = f64::from(energy.bat_power.unwrap_or(0.0));
+    // Venus E : toute la charge passe par le port réseau, d'où le rapport sortie / entrée
+    let round_trip_efficiency = match (energy.total_grid_output_energy, energy.total_grid_input_energy) {
+        (Some(output), Some(input)) => percent(f64::from(output), f64::from(input)),
+        _ => None,
+    };
+    Kpis {
+        self_consumption: grid.and_then(|grid| self_consumption(pv, (-grid).max(0.0))),
+        self_sufficiency: grid.and_then(|grid| self_sufficiency(load(pv, grid, battery), grid.max(0.0))),
+        round_trip_efficiency,
+    }
+}
+
+// Énergies d'une journée locale intégrées depuis l'historique, [Wh]
+#[derive(Serialize, Clone, Default)]
+pub struct DailyKpis {
+    // YYYY-MM-DD, heure locale
+    pub date: String,
+    pub pv_wh: f64,
+    pub import_wh: f64,
+    pub export_wh: f64,
+    pub charge_wh: f64,
+    pub discharge_wh: f64,
+    pub load_wh: f64,
+    #[serde(flatten)]
+    pub kpis: Kpis,
+}
+
+fn local_date(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+// Flux d'un intervalle entre deux échantillons, [W] ; réseau négatif en injection, batterie positive en charge
+pub struct Interval {
+    pub start: i64,
+    pub hours: f64,
+    pub pv: f64,
+    pub grid: f64,
+    pub battery: f64,
+}
+
+// samples : (secondes Unix, échantillon) triés ; chaque échantillon vaut jusqu'au suivant.
+// Trous de polling et échantillons sans compteur réseau ignorés
+pub fn intervals(samples: &[(i64, HistorySample)]) -> impl Iterator<Item = Interval> + '_ {
+    samples.windows(2).filter_map(|pair| {
+        let ((start, sample), (end, _)) = (&pair[0], &pair[1]);
+        let gap = end - start;
+        let grid = sample.grid_power.map(f64::from).filter(|_| gap > 0 && gap <= MAX_SAMPLE_GAP_S)?;
+        Some(Interval {
+            start: *start,
+            hours: gap as f64 / 3600.0,
+            pv: f64::from(sample.pv_power.unwrap_or(0.0)),
+            grid,
+            battery: f64::from(sample.battery_power.unwrap_or(0.0)),
+        })
+    })
+}
+
+pub fn daily(samples: &[(i64, HistorySample)]) -> Vec<DailyKpis> {
+    let mut days: Vec<DailyKpis> = Vec::new();
+    for Interval { start, hours, pv, grid, battery } in intervals(samples) {
+        let date = local_date(start);
+        if days.last().is_none_or(|d| d.date != date) {
+            days.push(DailyKpis { date, ..Default::default() });
+        }
+        let Some(day) = days.last_mut() else { continue };
+        day.pv_wh += pv * hours;
+        day.import_wh += grid.max(0.0) * hours;
+        day.export_wh += (-grid).max(0.0) * hours;
+        day.charge_wh += battery.max(0.0) * hours;
+        day.discharge_wh += (-battery).max(0.0) * hours;
+        day.load_wh += load(pv, grid, battery) * hours;
+    }
+    for day in &mut days {
+        day.kpis = totals(day);
+    }
+    days
+}
+
+fn totals(day: &DailyKpis) -> Kpis {
+    Kpis {
+        self_consumption: self_consumption(day.pv_wh, day.export_wh),
+        self_sufficiency: self_sufficiency(day.load_wh, day.import_wh),
+        // Sur une journée l'écart de SOC fausse le rapport : indicatif seulement
+        round_trip_efficiency: percent(day.discharge_wh, day.charge_wh),
+    }
+}
+
+// Bilan d'une journée connu seulement par ses totaux (import cloud) ; la consommation est déduite
+pub fn from_totals(date: String, pv_wh: f64, import_wh: f64, export_wh: f64, charge_wh: f64, discharge_wh: f64) -> DailyKpis {
+    let load_wh = (pv_wh + import_wh - export_wh - charge_wh + discharge_wh).max(0.0);
+    let mut day = DailyKpis { date, pv_wh, import_wh, export_wh, charge_wh, discharge_wh, load_wh, kpis: Kpis::default() };
+    day.kpis = totals(&day);
+    day
+}
+
+// Journées importées complétant celles intégrées depuis les relevés, qui priment à date égale
+pub fn merge(computed: Vec<DailyKpis>, imported: Vec<DailyKpis>) -> Vec<DailyKpis> {
+    let mut days = computed;
+    let known: Vec<String> = days.iter().map(|d| d.date.clone()).collect();
+    days.extend(imported.into_iter().filter(|d| !known.contains(&d.date)));
+    days.sort_by(|a, b| a.date.cmp(&b.date));
+    days
+}
@@ -1,195 +1,855 @@
+mod alerts;
+mod api;
+mod appexport;
+mod audit;
+mod automation;
+mod backup;
+mod ble;
+mod bms;
+mod calendar;
+mod calibration;
+mod changes;
+pub mod client;
+mod clock;
+mod cloud;
+mod compliance;
+mod confirm;
+mod costs;
+mod derived;
+mod devices;
+mod diagnostics;
+mod discovery;
+mod error;
+mod ev;
+mod exportlimit;
+mod firmware;
+mod flows;
+mod forecast;
+mod gridmeter;
+mod gridquality;
+mod hastats;
+mod health;
+mod history;
+mod influx;
+mod inverter;
+mod kpi;
+mod logs;
+mod metrics;
+mod models;
+mod mqtt;
+mod netaccess;
+mod notify;
+mod passive;
+mod peakshaving;
+mod phases;
+mod pin;
+mod plugins;
+mod polling;
+mod prometheus;
+mod pvoutput;
+mod recovery;
+mod regulation;
+mod rollup;
+mod scenes;
+mod schedule;
+mod scheduler;
+mod secrets;
+mod service;
+mod settings;
+mod sgready;
+mod simulators;
+mod site;
+mod solar;
+mod soclimits;
+mod tariff;
+mod tray;
+mod units;
+mod watchdog;
+mod webhooks;
+
+use alerts::{AlertConfig, AlertEngine, AlertLog, AlertSample};
+use api::{ApiServer, ApiServerConfig, ApiServerStatus};
+use appexport::ExportImport;
+use audit::{AuditLog, AuditTrail, CommandLogQuery, CommandSource};
+use backup::{DeviceBackup, RestoreReport};
+use automation::{AutomationStatus, Plan, PlanAction, PlanExecutor, PriceAutomation, PriceRules};
+use ble::{BleDevice, BleTransport};
+use bms::BatteryDetails;
+use calendar::{ExceptionCalendar, ExceptionPeriod};
+use calibration::{Calibration, CalibrationPhase, CalibrationPoint, CalibrationRequest, CalibrationStatus, Settle};
+use changes::{ChangeConfig, ChangeDetector};
+use client::{DeviceClient, DeviceStatus, ModeRequest, DEFAULT_PORT};
+use compliance::{ComplianceConfig, ComplianceMonitor, Violation};
+use confirm::{Confirmations, Confirmed};
+use costs::{CostConfig, CostReport};
+use derived::DerivedSensor;
+use devices::{DeviceConfig, DeviceEntry, DeviceProfile, DeviceRegistry, ModbusSettings};
+use discovery::{DiscoveredDevice, DiscoveryConfig, HeartbeatConfig, NetworkInterface, Reachability, ReachabilityEvent};
+use error::AppError;
+use ev::{EvChargerConfig, EvChargerMonitor, EvChargerStatus};
+use clock::{ClockConfig, DeviceTime, DriftWatch};
+use cloud::{CloudClient, CloudConfig};
+use flows::EnergyFlows;
+use firmware::{FirmwareCheck, FirmwareConfig, OtaTracker};
+use forecast::{ForecastCache, ForecastConfig, SolarForecast};
+use gridmeter::GridMeter;
+use gridquality::{GridQualityConfig, GridQualityMonitor, GridQualityReport};
+use health::HealthReport;
+use history::{HealthSample, HistoryPoint, HistorySample, HistoryStore, MaintenanceReport, PhaseHistoryPoint, PhaseSample, RetentionConfig, StorageStats, TimelineEntry, TimelineSource};
+use influx::{InfluxConfig, InfluxExporter, InfluxStatus};
+use exportlimit::{ExportLimitConfig, ExportLimitExecutor, ExportLimitStatus, ExportLimiter};
+use inverter::{PvReading, PvSource};
+use logs::{LogConfig, LogLine, RecentLogs};
+use kpi::{DailyKpis, Kpis};
+use marstek_protocol::methods::MeterMode;
+use marstek_protocol::{methods, parse_lenient, DeviceFault, EventPage, HeaterStatus, LinkStats, ModbusClient, ModbusControl, ModbusStatus, PowerLimits, ProtocolVariant, UdpTransport, WorkMode, MAX_FORCE_POWER};
+pub use marstek_protocol::{BatteryStatus, DeviceEvent, DeviceInfo, EnergyStatus, MeterConfig, MeterStatus, ModeStatus, UnknownFields, WifiStatus};
+use metrics::{CommandMetrics, MethodStatsEntry};
+use pin::PinAttempts;
+use models::{CapabilityMap, ModelCapabilities};
+use passive::{PassiveKeeper, PassiveSession, PassiveStatus};
+use peakshaving::{PeakShaver, PeakShavingConfig, PeakShavingStatus};
+use phases::{PhaseAnalysis, PhaseAnalyzer};
+use mqtt::{MqttBridge, MqttCommand, MqttConfig, MqttStatus};
+use netaccess::{ApiScope, ApiTokens};
+use notify::{ModeWatch, NotificationConfig};
+use plugins::{PluginAction, PluginInfo, PluginManager, PluginRun, ScriptContext};
+use polling::{PollRate, Poller, PollingConfig};
+use rollup::{MetricSummary, SummaryPeriod};
+use recovery::{NetworkWatch, Recovery, RecoveryConfig, RecoveryReason};
+use pvoutput::{PvOutputConfig, PvOutputStatus, PvOutputUploader};
+use scenes::{Scene, SceneStore};
+use schedule::{ManualSlot, ScheduleSource, Schedules};
+use regulation::{RegulationConfig, RegulationStatus, Regulator};
+use scheduler::{Priority, RequestScheduler, SchedulerConfig};
 use serde::{Deserialize, Serialize};
-use std::net::UdpSocket;
+use site::{SiteDashboard, SiteDevice};
+use units::{DisplayValues, UnitPreferences};
+use serde_with::skip_serializing_none;
+use settings::{ConnectionSettings, Settings};
+use service::CoreStatus;
+use sgready::{SgReadyConfig, SgReadyController, SgReadyStatus};
+use simulators::Simulators;
+use soclimits::SocLimits;
+use solar::{SolarScheduleConfig, SolarScheduleStatus, SolarScheduler};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use std::time::Duration;
-use tauri::State;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
+use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_notification::NotificationExt;
+use tariff::{DayPrices, PriceCache, TariffConfig};
+use watchdog::{ModeRestore, ModeWatchdog, ModeWatchdogConfig};
+use webhooks::{WebhookConfig, WebhookDispatcher, WebhookStatus};
 
-const DEFAULT_PORT: u16 = 30000;
-const DEFAULT_TIMEOUT_MS: u64 = 2000;
+const COMPLIANCE_CD_TIME: u32 = 300;
+// Durée du scan Bluetooth (découverte, recherche avant connexion), [ms]
+const BLE_SCAN_MS: u64 = 5000;
+// Attente du retour de l'appareil après un changement de réseau Wi-Fi, [s]
+const WIFI_RECONNECT_TIMEOUT_S: u64 = 90;
+const WIFI_RECONNECT_MAX_TIMEOUT_S: u64 = 600;
+const WIFI_RECONNECT_POLL: Duration = Duration::from_secs(5);
+// Redémarrage : délai avant de sonder (l'appareil répond encore juste après la requête), puis attente du retour, [s]
+const REBOOT_GRACE: Duration = Duration::from_secs(10);
+const REBOOT_TIMEOUT_S: u64 = 180;
+const REBOOT_POLL: Duration = Duration::from_secs(5);
+// Téléchargement et flashage : bien plus long qu'un simple redémarrage, [s]
+const OTA_TIMEOUT_S: u64 = 900;
+const OTA_POLL: Duration = Duration::from_secs(10);
 
-// State management
-#[derive(Default)]
-struct DeviceConfig {
-    ip: Option<String>,
-    port: u16,
-    timeout_ms: u64,
-}
+// Marstek.GetEvents : garde-fou si l'appareil n'annonce pas total_pages
+const MAX_EVENT_PAGES: u32 = 50;
 
+// State management
 struct AppState {
-    device: Mutex<DeviceConfig>,
+    devices: Mutex<DeviceRegistry>,
+    metrics: CommandMetrics,
+    scheduler: RequestScheduler,
+    settings: Mutex<Settings>,
+    settings_path: PathBuf,
+    devices_path: PathBuf,
+    // Lancé avec --read-only : impossible de repasser en écriture pendant la session
+    read_only_locked: bool,
+    pin_attempts: PinAttempts,
+    audit: AuditTrail,
+    plugins: PluginManager,
+    sg_ready: SgReadyController,
+    compliance: ComplianceMonitor,
+    phases: PhaseAnalyzer,
+    grid_quality: GridQualityMonitor,
+    poller: Poller,
+    // None si la base n'a pas pu être ouverte : l'app reste utilisable en direct
+    history: Option<HistoryStore>,
+    mqtt: MqttBridge,
+    api_server: ApiServer,
+    influx: InfluxExporter,
+    pvoutput: PvOutputUploader,
+    // Dernier dashboard de chaque appareil, servi par /metrics sans interroger la batterie
+    latest: Mutex<BTreeMap<String, prometheus::DeviceSnapshot>>,
+    transport: UdpTransport,
+    reachability: Reachability,
+    // Veille, changements d'adresse locale et échecs répétés du polling
+    network: NetworkWatch,
+    passive: PassiveKeeper,
+    regulator: Regulator,
+    prices: PriceCache,
+    automation: PriceAutomation,
+    forecast: ForecastCache,
+    peak_shaver: PeakShaver,
+    export_limiter: ExportLimiter,
+    alerts: AlertEngine,
+    mode_watch: ModeWatch,
+    mode_watchdog: ModeWatchdog,
+    // Régulation, écrêtage et automatisme tarifaire suspendus (menu du tray)
+    automation_paused: AtomicBool,
+    // Appareils dont la consigne est tenue en Modbus : ES.SetMode leur est refusé
+    modbus_control: Mutex<HashSet<String>>,
+    ble: BleTransport,
+    // Jetons des opérations destructives (mise à jour, calibration, redémarrage, coupure de la sortie de secours)
+    confirmations: Confirmations,
+    ota: OtaTracker,
+    calibration: Calibration,
+    cloud: CloudClient,
+    capabilities: CapabilityMap,
+    clock_watch: DriftWatch,
+    changes: ChangeDetector,
+    logs: RecentLogs,
+    simulators: Simulators,
+    webhooks: WebhookDispatcher,
+    scenes: SceneStore,
+    solar: SolarScheduler,
+    ev_charger: EvChargerMonitor,
+    core: CoreStatus,
 }
 
-#[derive(Serialize, Clone)]
-pub struct DiscoveredDevice {
-    pub ip: String,
-    pub port: u16,
-    pub device: Option<String>,
-    pub ver: Option<u32>,
+impl AppState {
+    fn ensure_automation_running(&self) -> Result<(), AppError> {
+        if self.automation_paused.load(Ordering::Relaxed) {
+            return Err(AppError::Forbidden("Automation is paused".to_string()));
+        }
+        if let Some(device) = self.calibration.active() {
+            return Err(AppError::Forbidden(format!("Battery calibration in progress on {}: automation is suspended", device)));
+        }
+        Ok(())
+    }
+
+    fn ensure_writable(&self) -> Result<(), AppError> {
+        let read_only = self.read_only_locked || self.settings.lock().map_err(|e| e.to_string())?.read_only;
+        if read_only {
+            return Err(AppError::Forbidden("Read-only mode is enabled: control commands are disabled.".to_string()));
+        }
+        if let Some(device) = self.ota.active() {
+            return Err(AppError::Forbidden(format!("Firmware update in progress on {}: control commands are disabled.", device)));
+        }
+        Ok(())
+    }
+
+    // Sans polling de fond, le rafraîchissement de l'interface en tient lieu et ingère ses relevés.
+    // Rattachée à un cœur sans fenêtre, l'interface ne fait que lire : le cœur enregistre, publie et pilote.
+    fn interface_ingests(&self) -> bool {
+        !self.core.attached && !self.poller.config().enabled
+    }
+
+    // Heartbeat en échec : les automatismes attendent le retour de l'appareil plutôt que d'empiler les erreurs
+    fn ensure_online(&self, id: &str) -> Result<(), AppError> {
+        if self.reachability.is_offline(id) {
+            return Err(AppError::Timeout(format!("{} is offline: automation paused until it answers again", id)));
+        }
+        Ok(())
+    }
+
+    fn ensure_advanced_control(&self) -> Result<(), AppError> {
+        if !self.settings.lock().map_err(|e| e.to_string())?.advanced_control {
+            return Err(AppError::Forbidden("Advanced control is disabled: Modbus writes are not allowed.".to_string()));
+        }
+        Ok(())
+    }
+
+    // Verrou entre les deux chemins de commande : une consigne Modbus et un ES.SetMode ne coexistent pas
+    fn ensure_udp_control(&self, device: &str) -> Result<(), AppError> {
+        if self.modbus_control.lock().unwrap_or_else(|e| e.into_inner()).contains(device) {
+            return Err(AppError::Forbidden(format!("Device {} is under Modbus control; release it before using ES.SetMode.", device)));
+        }
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<ConnectionSettings, AppError> {
+        Ok(self.settings.lock().map_err(|e| e.to_string())?.connection.clone())
+    }
+
+    fn client(&self) -> Result<DeviceClient<'_>, AppError> {
+        Ok(DeviceClient::new(&self.transport, self.connection()?))
+    }
+
+    fn protocol_variant(&self, model: Option<&str>) -> Result<ProtocolVariant, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(marstek_protocol::resolve(model, &settings.protocol_variants))
+    }
+
+    fn set_model(&self, id: &str, model: &str, firmware: Option<u32>) -> Result<(), AppError> {
+        self.devices.lock().map_err(|e| e.to_string())?.set_model(id, model, firmware);
+        Ok(())
+    }
+
+    fn set_identity(&self, id: &str, ble_mac: Option<&str>, wifi_mac: Option<&str>) -> Result<(), AppError> {
+        let mut registry = self.devices.lock().map_err(|e| e.to_string())?;
+        if registry.set_identity(id, ble_mac, wifi_mac) {
+            registry.save(&self.devices_path)?;
+        }
+        Ok(())
+    }
+
+    // settings.json seulement, jamais le trousseau : un trousseau injoignable se lit comme vide
+    fn pin_hash(&self) -> Result<Option<String>, AppError> {
+        Ok(self.settings.lock().map_err(|e| e.to_string())?.pin_hash.clone())
+    }
+
+    fn mqtt_password(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::MQTT_PASSWORD, settings.mqtt.password.as_ref())?)
+    }
+
+    fn influx_token(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::INFLUX_TOKEN, settings.influx.token.as_ref())?)
+    }
+
+    fn pvoutput_api_key(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::PVOUTPUT_API_KEY, settings.pvoutput.api_key.as_ref())?)
+    }
+
+    // Clés de signature par nom de webhook ; un webhook sans clé n'est pas signé
+    fn webhook_secrets(&self) -> Result<HashMap<String, String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        let mut secrets = HashMap::new();
+        for hook in &settings.webhooks.hooks {
+            if let Some(secret) = secrets::lookup(&secrets::webhook_secret(&hook.name), hook.secret.as_ref())? {
+                secrets.insert(hook.name.clone(), secret);
+            }
+        }
+        Ok(secrets)
+    }
+
+    fn tariff_api_key(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::TARIFF_API_KEY, settings.tariff.api_key.as_ref())?)
+    }
+
+    fn ev_charger_password(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::EV_CHARGER_PASSWORD, settings.ev_charger.password.as_ref())?)
+    }
+
+    fn api_token(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::API_TOKEN, settings.api_server.token.as_ref())?)
+    }
+
+    fn api_tokens(&self) -> Result<ApiTokens, AppError> {
+        let read = {
+            let settings = self.settings.lock().map_err(|e| e.to_string())?;
+            secrets::lookup(secrets::API_READ_TOKEN, settings.api_server.read_token.as_ref())?
+        };
+        Ok(ApiTokens { control: self.api_token()?, read })
+    }
+
+    fn cloud_password(&self) -> Result<Option<String>, AppError> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        Ok(secrets::lookup(secrets::CLOUD_PASSWORD, settings.cloud.password.as_ref())?)
+    }
+
+    fn check_pin(&self, pin: Option<&str>) -> Result<(), AppError> {
+        let Some(stored) = self.pin_hash()? else { return Ok(()) };
+        let Some(pin) = pin else { return Err(AppError::Forbidden("A PIN is required for control commands.".to_string())) };
+        self.pin_attempts.ensure_allowed().map_err(AppError::Forbidden)?;
+        if pin::verify_pin(pin, &stored) {
+            self.pin_attempts.succeeded();
+            Ok(())
+        } else {
+            self.pin_attempts.failed();
+            Err(AppError::Forbidden("Invalid PIN.".to_string()))
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct ApiRequest {
-    id: u32,
-    method: String,
-    params: serde_json::Value,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct DeviceInfo {
-    pub device: Option<String>,
-    pub ver: Option<u32>,
-    pub ble_mac: Option<String>,
-    pub wifi_mac: Option<String>,
-    pub wifi_name: Option<String>,
-    pub ip: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct BatteryStatus {
-    pub soc: Option<u32>,
-    pub charg_flag: Option<bool>,
-    pub dischrg_flag: Option<bool>,
-    pub bat_temp: Option<f32>,
-    pub bat_capacity: Option<f32>,
-    pub rated_capacity: Option<f32>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct EnergyStatus {
-    pub bat_soc: Option<u32>,
-    pub bat_cap: Option<f32>,
-    pub pv_power: Option<f32>,
-    pub ongrid_power: Option<f32>,
-    pub offgrid_power: Option<f32>,
-    pub bat_power: Option<f32>,
-    pub total_pv_energy: Option<f32>,
-    pub total_grid_output_energy: Option<f32>,
-    pub total_grid_input_energy: Option<f32>,
-    pub total_load_energy: Option<f32>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct ModeStatus {
-    pub mode: Option<String>,
-    pub ongrid_power: Option<f32>,
-    pub offgrid_power: Option<f32>,
-    pub bat_soc: Option<u32>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct MeterStatus {
-    pub ct_state: Option<u32>,
-    pub a_power: Option<f32>,
-    pub b_power: Option<f32>,
-    pub c_power: Option<f32>,
-    pub total_power: Option<f32>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct WifiStatus {
-    pub ssid: Option<String>,
-    pub rssi: Option<i32>,
-    pub sta_ip: Option<String>,
+#[derive(Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DataSource {
+    #[default]
+    Local,
+    Cloud,
 }
 
+#[skip_serializing_none]
 #[derive(Serialize, Clone)]
 pub struct DashboardData {
     pub device: DeviceInfo,
     pub battery: BatteryStatus,
     pub energy: EnergyStatus,
     pub mode: ModeStatus,
-    pub meter: MeterStatus,
+    pub meter: Option<MeterStatus>,
     pub wifi: WifiStatus,
+    // UTC ISO-8601 ; version affichable dans display
     pub timestamp: String,
+    // Valeurs mises en forme selon les préférences d'unités ; les champs numériques restent en W et Wh
+    pub display: DisplayValues,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub derived: BTreeMap<String, f64>,
+    // Sections dont la sous-requête a échoué (section -> erreur) ; elles restent vides
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+    // Sections que le modèle n'expose pas (composant absent, méthode refusée) : ni interrogées ni en erreur
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unsupported: Vec<String>,
+    // Champs d'un type inattendu écartés par section (autre modèle ou firmware) ; le reste de la section est lu
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ignored_fields: BTreeMap<String, Vec<String>>,
+    pub profile: Option<DeviceProfile>,
+    pub source: DataSource,
+    // Qualité du lien UDP, affichée avec la section Wi-Fi
+    pub link: Option<LinkStats>,
+    // Dernier relevé réussi, rendu faute de réponse ; age_s depuis sa collecte
+    pub stale: bool,
+    pub age_s: Option<u64>,
+    pub kpis: Kpis,
+    // Flux PV / batterie / réseau / foyer résolus une fois ici pour l'interface et les exports
+    pub flows: EnergyFlows,
+    // Limites de puissance de l'onduleur (Modbus TCP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_limits: Option<PowerLimits>,
+    // Alarmes et défauts actifs ; absent : ni err_code ni registres Modbus pour les connaître
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faults: Option<Vec<DeviceFault>>,
 }
 
-fn send_command(ip: &str, port: u16, timeout_ms: u64, method: &str, params: serde_json::Value) -> Result<serde_json::Value, String> {
-    // Try port 30000 first (some Marstek devices require source port = destination port)
-    let socket = UdpSocket::bind(format!("0.0.0.0:{}", DEFAULT_PORT))
-        .or_else(|_| UdpSocket::bind("0.0.0.0:0"))
-        .map_err(|e| e.to_string())?;
-    socket.set_read_timeout(Some(Duration::from_millis(timeout_ms))).map_err(|e| e.to_string())?;
+fn send_command(state: &AppState, priority: Priority, ip: &str, port: u16, method: &str, params: serde_json::Value) -> Result<serde_json::Value, AppError> {
+    let client = state.client()?;
+    let _permit = state.scheduler.acquire_for(priority.for_method(method), ip);
+    let span = tracing::debug_span!("device_call", method, ip);
+    let _guard = span.enter();
 
-    let request = ApiRequest {
-        id: 1,
-        method: method.to_string(),
-        params,
-    };
+    let start = Instant::now();
+    let result = client.call(ip, port, method, params);
+    let elapsed = start.elapsed();
+    state.metrics.record(method, elapsed, result.is_ok());
+    tracing::debug!(elapsed_ms = elapsed.as_millis() as u64, ok = result.is_ok(), "device call finished");
 
-    let message = serde_json::to_string(&request).map_err(|e| e.to_string())?;
-    let addr = format!("{}:{}", ip, port);
+    result
+}
 
-    socket.send_to(message.as_bytes(), &addr).map_err(|e| e.to_string())?;
+// Les appels appareil sont bloquants (file du scheduler, retries) : on les sort du thread principal
+async fn run_blocking<T: Send + 'static>(
+    app: AppHandle,
+    f: impl FnOnce(&AppHandle, &AppState) -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+    tauri::async_runtime::spawn_blocking(move || f(&app, &app.state::<AppState>()))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    let mut buf = [0u8; 4096];
-    let (len, _) = socket.recv_from(&mut buf).map_err(|e| e.to_string())?;
+// subnet : plage à sonder en unicast si la diffusion ne trouve rien (sinon celle des réglages)
+fn discover(state: &AppState, priority: Priority, subnet: Option<String>) -> Result<Vec<DiscoveredDevice>, AppError> {
+    let _permit = state.scheduler.acquire(priority);
+    let config = state.settings.lock().map_err(|e| e.to_string())?.discovery.clone();
+    let mut devices = state.client()?.discover(&config, subnet)?;
 
-    let response: serde_json::Value = serde_json::from_slice(&buf[..len]).map_err(|e| e.to_string())?;
+    let registry = state.devices.lock().map_err(|e| e.to_string())?;
+    for device in &mut devices {
+        device.nickname = registry.profile(device.ble_mac.as_deref(), device.wifi_mac.as_deref()).and_then(|p| p.nickname.clone());
+    }
 
-    Ok(response.get("result").cloned().unwrap_or(serde_json::Value::Null))
+    Ok(devices)
 }
 
 #[tauri::command]
-fn discover_devices() -> Result<Vec<DiscoveredDevice>, String> {
-    // Try port 30000 first (some Marstek devices require source port = destination port)
-    let socket = UdpSocket::bind(format!("0.0.0.0:{}", DEFAULT_PORT))
-        .or_else(|_| UdpSocket::bind("0.0.0.0:0"))
-        .map_err(|e| e.to_string())?;
-    socket.set_broadcast(true).map_err(|e| e.to_string())?;
-    socket.set_read_timeout(Some(Duration::from_secs(3))).map_err(|e| e.to_string())?;
+async fn discover_devices(app: AppHandle, subnet: Option<String>) -> Result<Vec<DiscoveredDevice>, AppError> {
+    run_blocking(app, move |_, state| discover(state, Priority::Interactive, subnet)).await
+}
+
+// Rapproche une découverte du registre : nouvelles IP, MAC apprises, appareils (in)joignables
+fn rediscover(app: &AppHandle, state: &AppState) -> Result<(), AppError> {
+    let found = discover(state, Priority::Background, None)?;
+    let devices: Vec<(String, DeviceConfig)> = state.devices.lock().map_err(|e| e.to_string())?.devices.clone().into_iter().collect();
+    for (id, config) in devices {
+        let matched = found.iter().find(|d| config.matches(&d.ip, d.ble_mac.as_deref(), d.wifi_mac.as_deref()));
+        let (ip, online) = match matched {
+            Some(device) => {
+                let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+                // Entrée saisie par nom DNS : c'est le nom qui suit l'appareil, pas l'IP du moment
+                let moved = !discovery::is_hostname(&config.ip) && registry.set_address(&id, &device.ip, device.port);
+                if moved {
+                    tracing::info!(device = %id, from = %config.ip, to = %device.ip, "device address changed");
+                }
+                if registry.set_identity(&id, device.ble_mac.as_deref(), device.wifi_mac.as_deref()) || moved {
+                    registry.save(&state.devices_path)?;
+                }
+                (device.ip.clone(), true)
+            }
+            // Absent de la découverte (diffusion filtrée...) : on tente encore l'adresse connue
+            None => {
+                let probe = send_command(state, Priority::Background, &config.ip, config.port, methods::GET_DEVICE, methods::probe_params());
+                (config.ip.clone(), probe.is_ok())
+            }
+        };
+        if state.reachability.update(&id, online) {
+            let event = if online { "device-online" } else { "device-offline" };
+            let _ = app.emit(event, &ReachabilityEvent { id, ip, online });
+        }
+    }
+    Ok(())
+}
+
+fn spawn_rediscovery(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            let interval = state.settings.lock().map(|s| s.discovery.rediscovery_interval_s).unwrap_or(0);
+            // Désactivée : on relit simplement le réglage de temps en temps
+            std::thread::sleep(Duration::from_secs(if interval == 0 { 60 } else { interval }));
+            let empty = state.devices.lock().map(|r| r.devices.is_empty()).unwrap_or(true);
+            if interval == 0 || empty {
+                continue;
+            }
+            if let Err(e) = rediscover(&app, &state) {
+                tracing::warn!("background discovery failed: {}", e);
+            }
+        }
+    });
+}
 
-    let message = r#"{"id":0,"method":"Marstek.GetDevice","params":{"ble_mac":"0"}}"#;
-    socket.send_to(message.as_bytes(), format!("255.255.255.255:{}", DEFAULT_PORT)).map_err(|e| e.to_string())?;
+// Sonde légère de chaque appareil du registre, même polling arrêté
+fn heartbeat(app: &AppHandle, state: &AppState, config: &HeartbeatConfig) -> Result<(), AppError> {
+    let devices: Vec<(String, DeviceConfig)> = state.devices.lock().map_err(|e| e.to_string())?.devices.clone().into_iter().collect();
+    for (id, device) in devices {
+        let ok = send_command(state, Priority::Background, &device.ip, device.port, methods::GET_DEVICE, methods::probe_params()).is_ok();
+        if let Some(online) = state.reachability.record(&id, ok, config.failures_before_offline) {
+            tracing::info!(device = %id, online, "device reachability changed");
+            let event = if online { "device-online" } else { "device-offline" };
+            let _ = app.emit(event, &ReachabilityEvent { id, ip: device.ip, online });
+        }
+    }
+    Ok(())
+}
 
-    let mut devices = Vec::new();
-    let mut buf = [0u8; 4096];
+fn spawn_heartbeat(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            let config = state.settings.lock().map(|s| s.heartbeat.clone()).unwrap_or_default();
+            // Désactivé : on relit simplement le réglage de temps en temps
+            std::thread::sleep(Duration::from_secs(if config.interval_s == 0 { 60 } else { config.interval_s }));
+            if config.interval_s == 0 {
+                continue;
+            }
+            if let Err(e) = heartbeat(&app, &state, &config) {
+                tracing::warn!("heartbeat failed: {}", e);
+            }
+        }
+    });
+}
 
-    loop {
-        match socket.recv_from(&mut buf) {
-            Ok((len, addr)) => {
-                if let Ok(response) = serde_json::from_slice::<serde_json::Value>(&buf[..len]) {
-                    if let Some(result) = response.get("result") {
-                        // Éviter les doublons
-                        let ip = addr.ip().to_string();
-                        if !devices.iter().any(|d: &DiscoveredDevice| d.ip == ip) {
-                            devices.push(DiscoveredDevice {
-                                ip,
-                                port: DEFAULT_PORT,
-                                device: result.get("device").and_then(|v| v.as_str()).map(String::from),
-                                ver: result.get("ver").and_then(|v| v.as_u64()).map(|v| v as u32),
-                            });
-                        }
-                    }
+// Sockets recréés puis appareils relocalisés par leur MAC ; le polling repart aussitôt
+fn recover_network(app: &AppHandle, state: &AppState, reason: RecoveryReason) -> Result<(), AppError> {
+    tracing::info!(?reason, "recovering network access");
+    tauri::async_runtime::block_on(state.transport.reset());
+    let empty = state.devices.lock().map_err(|e| e.to_string())?.devices.is_empty();
+    if !empty {
+        rediscover(app, state)?;
+    }
+    state.poller.configure(state.poller.config());
+    let recovery = Recovery {
+        reason,
+        devices_found: state.reachability.snapshot().values().filter(|online| **online).count(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+    };
+    let _ = app.emit("network-recovered", &recovery);
+    Ok(())
+}
+
+fn spawn_network_watch(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            let config = state.settings.lock().map(|s| s.recovery.clone()).unwrap_or_default();
+            std::thread::sleep(Duration::from_secs(config.check_interval_s.max(1)));
+            // Toujours appelée pour garder les repères à jour, même désactivée
+            let reason = state.network.check(&config);
+            if let (Some(reason), true) = (reason, config.enabled) {
+                if let Err(e) = recover_network(&app, &state, reason) {
+                    tracing::warn!("network recovery failed: {}", e);
                 }
             }
-            Err(_) => break, // Timeout
         }
+    });
+}
+
+#[tauri::command]
+fn get_recovery_config(state: State<AppState>) -> Result<RecoveryConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.recovery.clone())
+}
+
+#[tauri::command]
+fn set_recovery_config(state: State<AppState>, config: RecoveryConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.recovery = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+fn spawn_history_maintenance(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        let Some(history) = state.history.as_ref() else { return };
+        loop {
+            let config = state.settings.lock().map(|s| s.retention.clone()).unwrap_or_default();
+            // Désactivée : on relit simplement le réglage de temps en temps
+            let interval = if config.enabled { u64::from(config.maintenance_interval_h) * 3600 } else { 3600 };
+            std::thread::sleep(Duration::from_secs(interval));
+            if !config.enabled {
+                continue;
+            }
+            match history.maintain(&config, chrono::Utc::now().timestamp(), false) {
+                Ok(report) => tracing::info!(
+                    downsampled = report.downsampled_hours,
+                    samples = report.deleted_samples,
+                    hourly = report.deleted_hourly,
+                    vacuumed = report.vacuumed,
+                    "history maintenance done"
+                ),
+                Err(e) => tracing::warn!("history maintenance failed: {}", e),
+            }
+        }
+    });
+}
+
+#[tauri::command]
+fn get_retention_config(state: State<AppState>) -> Result<RetentionConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.retention.clone())
+}
+
+#[tauri::command]
+fn set_retention_config(state: State<AppState>, config: RetentionConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.retention = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_storage_stats(state: State<AppState>) -> Result<StorageStats, AppError> {
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    Ok(history.storage_stats()?)
+}
+
+// Passe de maintenance immédiate, même désactivée dans les réglages ; vacuum : compactage forcé
+#[tauri::command]
+async fn run_history_maintenance(app: AppHandle, vacuum: Option<bool>) -> Result<MaintenanceReport, AppError> {
+    run_blocking(app, move |_, state| {
+        let history = state.history.as_ref().ok_or("History database is unavailable")?;
+        let config = state.settings.lock().map_err(|e| e.to_string())?.retention.clone();
+        Ok(history.maintain(&config, chrono::Utc::now().timestamp(), vacuum.unwrap_or(false))?)
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_unit_preferences(state: State<AppState>) -> Result<UnitPreferences, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.units.clone())
+}
+
+#[tauri::command]
+fn set_unit_preferences(state: State<AppState>, preferences: UnitPreferences) -> Result<(), AppError> {
+    preferences.validate()?;
+    state.mqtt.set_units(&preferences);
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.units = preferences;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_heartbeat_config(state: State<AppState>) -> Result<HeartbeatConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.heartbeat.clone())
+}
+
+#[tauri::command]
+fn set_heartbeat_config(state: State<AppState>, config: HeartbeatConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.heartbeat = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// mac : BLE ou Wi-Fi, tout format ; nickname et notes vides suppriment le profil
+#[tauri::command]
+fn set_device_profile(state: State<AppState>, mac: String, nickname: Option<String>, notes: Option<String>) -> Result<(), AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    registry.set_profile(&mac, DeviceProfile { nickname, notes })?;
+    registry.save(&state.devices_path)
+}
+
+// modbus absent : l'appareil n'est plus interrogé en Modbus TCP
+#[tauri::command]
+fn set_device_modbus(state: State<AppState>, id: String, modbus: Option<ModbusSettings>) -> Result<(), AppError> {
+    if let Some(modbus) = &modbus {
+        modbus.validate()?;
     }
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    if modbus.is_none() {
+        // Plus de chemin Modbus pour rendre la main : le verrou ne doit pas bloquer ES.SetMode
+        state.modbus_control.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+    registry.set_modbus(&id, modbus)?;
+    registry.save(&state.devices_path)
+}
 
-    Ok(devices)
+#[tauri::command]
+fn get_device_profiles(state: State<AppState>) -> Result<BTreeMap<String, DeviceProfile>, AppError> {
+    Ok(state.devices.lock().map_err(|e| e.to_string())?.profiles.clone())
+}
+
+// id -> joignable au dernier heartbeat ou passage de la redécouverte
+#[tauri::command]
+fn get_device_status(state: State<AppState>) -> HashMap<String, bool> {
+    state.reachability.snapshot()
+}
+
+// Teste une adresse précise, avec les retries habituels
+#[tauri::command]
+async fn probe_ip(app: AppHandle, ip: String, port: Option<u16>) -> Result<DiscoveredDevice, AppError> {
+    run_blocking(app, move |_, state| {
+        let port = port.unwrap_or(DEFAULT_PORT);
+        // IPv4, IPv6, IPv6 lien-local avec sa zone ("fe80::1%eth0") ou nom DNS
+        let ip = checked_host(ip, Some(port))?.trim_start_matches('[').trim_end_matches(']').to_string();
+        let result = send_command(state, Priority::Interactive, &ip, port, methods::GET_DEVICE, methods::probe_params())?;
+        Ok(DiscoveredDevice::from_result(ip, port, &result))
+    })
+    .await
+}
+
+#[tauri::command]
+fn list_interfaces() -> Result<Vec<NetworkInterface>, AppError> {
+    discovery::interfaces()
+}
+
+#[tauri::command]
+fn get_discovery(state: State<AppState>) -> Result<DiscoveryConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.discovery.clone())
+}
+
+#[tauri::command]
+fn set_discovery_config(state: State<AppState>, config: DiscoveryConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.discovery = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// IP ou nom DNS ; le nom est enregistré tel quel et résolu à chaque requête (IP changeante, autre VLAN)
+fn checked_host(host: String, port: Option<u16>) -> Result<String, AppError> {
+    let host = host.trim().to_string();
+    if !discovery::valid_host(&host) {
+        return Err(AppError::InvalidInput(format!("Invalid device address: {}", host)));
+    }
+    if discovery::is_hostname(&host) {
+        discovery::resolve(&host, port.unwrap_or(DEFAULT_PORT))?;
+    }
+    Ok(host)
+}
+
+#[tauri::command]
+fn add_device(state: State<AppState>, ip: String, port: Option<u16>, name: Option<String>, id: Option<String>) -> Result<String, AppError> {
+    let ip = checked_host(ip, port)?;
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let id = registry.add(id, ip, port.unwrap_or(DEFAULT_PORT), name);
+    registry.save(&state.devices_path)?;
+    Ok(id)
+}
+
+#[tauri::command]
+fn remove_device(state: State<AppState>, id: String) -> Result<(), AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    registry.remove(&id)?;
+    state.simulators.stop(&id);
+    registry.save(&state.devices_path)
 }
 
+// Batterie simulée, ajoutée au registre et sélectionnée comme un appareil découvert
 #[tauri::command]
-fn set_device(state: State<AppState>, ip: String, port: Option<u16>) -> Result<(), String> {
-    let mut config = state.device.lock().map_err(|e| e.to_string())?;
-    config.ip = Some(ip);
-    config.port = port.unwrap_or(DEFAULT_PORT);
+fn add_simulated_device(state: State<AppState>, name: Option<String>) -> Result<DeviceEntry, AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let id = registry.add_simulated(0, name, None);
+    let port = state.simulators.start(&id, 0, None)?;
+    registry.set_address(&id, "127.0.0.1", port);
+    registry.select(&id)?;
+    registry.save(&state.devices_path)?;
+    let (id, config) = registry.get(Some(&id))?;
+    Ok(registry.entry(id, config))
+}
+
+// Relance les simulateurs enregistrés, sur leur port d'origine si possible
+fn start_simulators(state: &AppState) -> Result<(), AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let simulated: Vec<(String, u16, Option<String>)> = registry
+        .devices
+        .iter()
+        .filter(|(_, config)| config.simulated)
+        .map(|(id, config)| (id.clone(), config.port, config.replay.clone()))
+        .collect();
+    let mut moved = false;
+    for (id, port, replay) in simulated {
+        match state.simulators.start(&id, port, replay.as_deref()) {
+            Ok(actual) => moved |= registry.set_address(&id, "127.0.0.1", actual),
+            Err(e) => tracing::warn!(device = %id, "cannot start simulator: {}", e),
+        }
+    }
+    if moved {
+        registry.save(&state.devices_path)?;
+    }
     Ok(())
 }
 
-#[derive(Serialize, Clone)]
-struct DeviceConfigResponse {
-    ip: Option<String>,
-    port: u16,
+#[tauri::command]
+fn list_devices(state: State<AppState>) -> Result<Vec<DeviceEntry>, AppError> {
+    Ok(state.devices.lock().map_err(|e| e.to_string())?.list())
 }
 
 #[tauri::command]
-fn get_device(state: State<AppState>) -> Result<DeviceConfigResponse, String> {
-    let config = state.device.lock().map_err(|e| e.to_string())?;
-    Ok(DeviceConfigResponse {
-        ip: config.ip.clone(),
-        port: config.port,
-    })
+fn select_device(state: State<AppState>, id: String) -> Result<(), AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    registry.select(&id)?;
+    registry.save(&state.devices_path)
+}
+
+// Ajoute (ou met à jour) l'appareil et le sélectionne
+#[tauri::command]
+fn set_device(state: State<AppState>, ip: String, port: Option<u16>, name: Option<String>) -> Result<(), AppError> {
+    let ip = checked_host(ip, port)?;
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let id = registry.add(None, ip, port.unwrap_or(DEFAULT_PORT), name);
+    registry.select(&id)?;
+    registry.save(&state.devices_path)
+}
+
+// Retire l'appareil courant
+#[tauri::command]
+fn forget_device(state: State<AppState>) -> Result<(), AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    if let Some(id) = registry.selected.clone() {
+        registry.remove(&id)?;
+        state.simulators.stop(&id);
+    }
+    registry.save(&state.devices_path)
+}
+
+#[tauri::command]
+fn get_device(state: State<AppState>, device: Option<String>) -> Result<Option<DeviceEntry>, AppError> {
+    let registry = state.devices.lock().map_err(|e| e.to_string())?;
+    Ok(registry.get(device.as_deref()).ok().map(|(id, config)| registry.entry(id, config)))
 }
 
 #[derive(Deserialize)]
@@ -207,136 +867,4768 @@ struct SetModeConfig {
 }
 
 #[tauri::command]
-fn set_timeout(state: State<AppState>, timeout_ms: u64) -> Result<(), String> {
-    let mut config = state.device.lock().map_err(|e| e.to_string())?;
-    config.timeout_ms = timeout_ms;
-    Ok(())
+fn set_timeout(state: State<AppState>, timeout_ms: u64) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.connection.timeout_ms = timeout_ms;
+    settings::save(&state.settings_path, &settings)
 }
 
 #[tauri::command]
-fn set_mode(state: State<AppState>, mode: String, config: Option<serde_json::Value>) -> Result<bool, String> {
-    let (ip, port, timeout_ms) = {
-        let device_config = state.device.lock().map_err(|e| e.to_string())?;
-        let ip = device_config.ip.clone().ok_or("Device not configured. Call set_device first.")?;
-        (ip, device_config.port, device_config.timeout_ms)
-    };
-
-    // Construire le payload selon le mode
-    let mode_config = match mode.as_str() {
-        "Auto" => serde_json::json!({
-            "mode": "Auto",
-            "auto_cfg": { "enable": 1 }
-        }),
-        "AI" => serde_json::json!({
-            "mode": "AI",
-            "ai_cfg": { "enable": 1 }
-        }),
-        "Manual" => {
-            // Config doit contenir manual_cfg avec time_num, start_time, end_time, week_set, power, enable
-            let cfg = config.ok_or("Manual mode requires config with manual_cfg")?;
-            let manual_cfg = cfg.get("manual_cfg").ok_or("Missing manual_cfg in config")?;
-            serde_json::json!({
-                "mode": "Manual",
-                "manual_cfg": manual_cfg
-            })
-        },
-        "Passive" => {
-            // Config doit contenir passive_cfg avec power, cd_time
-            let cfg = config.ok_or("Passive mode requires config with passive_cfg")?;
-            let passive_cfg = cfg.get("passive_cfg").ok_or("Missing passive_cfg in config")?;
-            serde_json::json!({
-                "mode": "Passive",
-                "passive_cfg": passive_cfg
-            })
-        },
-        _ => return Err(format!("Unknown mode: {}", mode)),
-    };
+fn get_settings(state: State<AppState>) -> Result<ConnectionSettings, AppError> {
+    state.connection()
+}
 
-    let params = serde_json::json!({
-        "id": 0,
-        "config": mode_config
-    });
+#[tauri::command]
+fn set_settings(state: State<AppState>, settings: ConnectionSettings) -> Result<(), AppError> {
+    settings.validate()?;
+    let mut current = state.settings.lock().map_err(|e| e.to_string())?;
+    current.connection = settings;
+    settings::save(&state.settings_path, &current)
+}
 
-    let result = send_command(&ip, port, timeout_ms, "ES.SetMode", params)?;
+// override_soc : ignore la fenêtre de SOC des réglages pour cette commande
+#[tauri::command]
+async fn set_mode(
+    app: AppHandle,
+    mode: String,
+    config: Option<serde_json::Value>,
+    pin: Option<String>,
+    device: Option<String>,
+    override_soc: Option<bool>,
+) -> Result<bool, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        apply_mode(state, CommandSource::Ui, &target, &mode, config, !override_soc.unwrap_or(false))
+    })
+    .await
+}
 
-    // Retourner set_result si présent, sinon true si pas d'erreur
-    Ok(result.get("set_result").and_then(|v| v.as_bool()).unwrap_or(true))
+#[tauri::command]
+fn list_scenes(state: State<AppState>) -> Vec<Scene> {
+    state.scenes.list()
 }
 
+// Même nom : la scène existante est remplacée
 #[tauri::command]
-fn get_dashboard(state: State<AppState>) -> Result<DashboardData, String> {
-    let (ip, port, timeout_ms) = {
-        let config = state.device.lock().map_err(|e| e.to_string())?;
-        let ip = config.ip.clone().ok_or("Device not configured. Call set_device first.")?;
-        (ip, config.port, config.timeout_ms)
-    };
+fn save_scene(app: AppHandle, state: State<AppState>, scene: Scene) -> Result<(), AppError> {
+    state.scenes.save(scene)?;
+    tray::refresh(&app);
+    Ok(())
+}
 
-    let device_result = send_command(&ip, port, timeout_ms, "Marstek.GetDevice", serde_json::json!({"ble_mac": "0"}))?;
-    let device: DeviceInfo = serde_json::from_value(device_result).unwrap_or(DeviceInfo {
-        device: None, ver: None, ble_mac: None, wifi_mac: None, wifi_name: None, ip: None,
-    });
+#[tauri::command]
+fn delete_scene(app: AppHandle, state: State<AppState>, name: String) -> Result<(), AppError> {
+    state.scenes.delete(&name)?;
+    tray::refresh(&app);
+    Ok(())
+}
 
-    let es_result = send_command(&ip, port, timeout_ms, "ES.GetStatus", serde_json::json!({"id": 0}))?;
-    let energy: EnergyStatus = serde_json::from_value(es_result).unwrap_or(EnergyStatus {
-        bat_soc: None, bat_cap: None, pv_power: None, ongrid_power: None, offgrid_power: None,
-        bat_power: None, total_pv_energy: None, total_grid_output_energy: None,
-        total_grid_input_energy: None, total_load_energy: None,
-    });
+// Mêmes contrôles que set_mode : lecture seule, PIN, fenêtre de SOC
+#[tauri::command]
+async fn apply_scene(app: AppHandle, name: String, pin: Option<String>, device: Option<String>) -> Result<bool, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let scene = state.scenes.get(&name)?;
+        let target = device_target(state, device.as_deref())?;
+        tracing::info!(device = %target.id, "applying scene {}", scene.name);
+        apply_mode(state, CommandSource::Ui, &target, &scene.mode, scene.config, true)
+    })
+    .await
+}
 
-    let bat_result = send_command(&ip, port, timeout_ms, "Bat.GetStatus", serde_json::json!({"id": 0}))?;
-    let battery: BatteryStatus = serde_json::from_value(bat_result).unwrap_or(BatteryStatus {
-        soc: None, charg_flag: None, dischrg_flag: None, bat_temp: None, bat_capacity: None, rated_capacity: None,
-    });
+// Une écriture ES.SetMode par plage, puis désactivation des numéros absents de `slots`
+fn write_slots(state: &AppState, source: CommandSource, target: &DeviceTarget, slots: &[ManualSlot], previous: &[ManualSlot]) -> Result<(), AppError> {
+    let removed = previous.iter().filter(|p| !slots.iter().any(|s| s.time_num == p.time_num)).map(ManualSlot::disabled);
+    for slot in slots.iter().cloned().chain(removed) {
+        let config = serde_json::json!({"manual_cfg": slot});
+        if !apply_mode(state, source, target, "Manual", Some(config), true)? {
+            return Err(AppError::DeviceRejected {
+                code: 0,
+                message: format!("Manual slot {} was not accepted (set_result: false)", slot.time_num),
+            });
+        }
+    }
+    Ok(())
+}
 
-    let wifi_result = send_command(&ip, port, timeout_ms, "Wifi.GetStatus", serde_json::json!({"id": 0}))?;
-    let wifi: WifiStatus = serde_json::from_value(wifi_result).unwrap_or(WifiStatus {
-        ssid: None, rssi: None, sta_ip: None,
-    });
+fn read_schedules(state: &AppState, target: &DeviceTarget) -> Result<Schedules, AppError> {
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::ES_GET_MODE), methods::status_params())?;
+    if let Some(slots) = schedule::from_mode_result(&variant.normalize(result)) {
+        return Ok(Schedules { slots, source: ScheduleSource::Device });
+    }
+    let registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let (_, config) = registry.get(Some(&target.id))?;
+    Ok(Schedules { slots: config.manual_slots.clone(), source: ScheduleSource::Saved })
+}
 
-    let mode_result = send_command(&ip, port, timeout_ms, "ES.GetMode", serde_json::json!({"id": 0}))?;
-    let mode: ModeStatus = serde_json::from_value(mode_result).unwrap_or(ModeStatus {
-        mode: None, ongrid_power: None, offgrid_power: None, bat_soc: None,
-    });
+#[tauri::command]
+async fn get_schedules(app: AppHandle, device: Option<String>) -> Result<Schedules, AppError> {
+    run_blocking(app, move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        read_schedules(state, &target)
+    })
+    .await
+}
 
-    let em_result = send_command(&ip, port, timeout_ms, "EM.GetStatus", serde_json::json!({"id": 0}))?;
-    let meter: MeterStatus = serde_json::from_value(em_result).unwrap_or(MeterStatus {
-        ct_state: None, a_power: None, b_power: None, c_power: None, total_power: None,
-    });
+// Bat.GetStatus complété par le détail BMS quand le firmware l'expose
+#[tauri::command]
+async fn get_battery_details(app: AppHandle, device: Option<String>) -> Result<BatteryDetails, AppError> {
+    run_blocking(app, move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        read_battery_details(state, &target, Priority::Interactive)
+    })
+    .await
+}
 
-    let timestamp = chrono::Local::now().format("%H:%M:%S").to_string();
+fn read_battery_details(state: &AppState, target: &DeviceTarget, priority: Priority) -> Result<BatteryDetails, AppError> {
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let query = |method: &str| -> Result<serde_json::Value, AppError> {
+        let result = send_command(state, priority, &target.ip, target.port, variant.method(method), methods::status_params())?;
+        Ok(variant.normalize(result))
+    };
+    let mut details = BatteryDetails::default();
+    details.merge(methods::BAT_GET_STATUS, &query(methods::BAT_GET_STATUS)?);
+    match query(bms::BMS_METHOD) {
+        Ok(result) => details.merge(bms::BMS_METHOD, &result),
+        Err(e) => tracing::debug!("{} unavailable: {}", bms::BMS_METHOD, e),
+    }
+    record_health(state, &target.id, details.soh, details.cycle_count);
+    Ok(details.finish())
+}
 
-    Ok(DashboardData {
-        device,
-        battery,
-        energy,
-        mode,
-        meter,
-        wifi,
-        timestamp,
-    })
+// Firmwares sans SOH ni compteur de cycles : rien à enregistrer
+fn record_health(state: &AppState, device: &str, soh: Option<u32>, cycle_count: Option<u32>) {
+    let Some(history) = state.history.as_ref().filter(|_| soh.is_some() || cycle_count.is_some()) else { return };
+    let sample = HealthSample { timestamp: chrono::Utc::now().timestamp(), soh, cycle_count };
+    if let Err(e) = history.record_health(device, &sample) {
+        tracing::warn!("failed to record battery health: {}", e);
+    }
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_store::Builder::default().build())
-        .plugin(tauri_plugin_fs::init())
-        .manage(AppState {
-            device: Mutex::new(DeviceConfig {
-                ip: None,
-                port: DEFAULT_PORT,
-                timeout_ms: DEFAULT_TIMEOUT_MS,
-            }),
-        })
-        .invoke_handler(tauri::generate_handler![
-            get_dashboard,
+fn modbus_client(state: &AppState, target: &DeviceTarget) -> Result<ModbusClient, AppError> {
+    let modbus = target
+        .modbus
+        .as_ref()
+        .ok_or_else(|| AppError::NotConfigured(format!("Modbus TCP is not enabled for device {}", target.id)))?;
+    let timeout = Duration::from_millis(state.connection()?.timeout_ms);
+    Ok(ModbusClient::connect(&discovery::endpoint(&target.ip, modbus.port), modbus.unit_id, timeout)?)
+}
+
+// Écrit la commande puis tient à jour le verrou avec le chemin UDP ; chaque écriture est auditée
+fn apply_modbus(state: &AppState, source: CommandSource, target: &DeviceTarget, control: ModbusControl) -> Result<(), AppError> {
+    let params = serde_json::json!({
+        "writes": control.writes()?.iter().map(|(register, value)| serde_json::json!({"register": register, "value": value})).collect::<Vec<_>>(),
+    });
+    let _pause = state.poller.pause();
+    let _permit = state.scheduler.acquire_for(Priority::Control, &target.ip);
+    let start = Instant::now();
+    let outcome = modbus_client(state, target)
+        .and_then(|mut client| Ok(marstek_protocol::apply_control(&mut client, control)?))
+        .map(|_| serde_json::Value::Bool(true));
+    state.metrics.record("Modbus.Write", start.elapsed(), outcome.is_ok());
+    if let Err(e) = state.audit.record(source, &target.ip, "Modbus.Write", &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    outcome?;
+
+    let mut controlled = state.modbus_control.lock().unwrap_or_else(|e| e.into_inner());
+    match control {
+        ModbusControl::Setpoint(_) => controlled.insert(target.id.clone()),
+        ModbusControl::Release => controlled.remove(&target.id),
+        ModbusControl::WorkMode(_) => false,
+    };
+    Ok(())
+}
+
+fn modbus_guards(state: &AppState, pin: Option<&str>) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    state.check_pin(pin)?;
+    state.ensure_advanced_control()
+}
+
+// power : > 0 en décharge, < 0 en charge ; tenue jusqu'à release_modbus_control
+#[tauri::command]
+async fn set_modbus_setpoint(app: AppHandle, power: i64, pin: Option<String>, device: Option<String>, override_soc: Option<bool>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        modbus_guards(state, pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        if state.passive.session().is_some_and(|s| s.device == target.id) {
+            return Err(AppError::Forbidden(format!("A Passive session is running on {}; stop it before taking Modbus control.", target.id)));
+        }
+        check_power_limit(state, &target, power)?;
+        if !override_soc.unwrap_or(false) {
+            check_soc_limits(state, &target, power)?;
+        }
+        apply_modbus(state, CommandSource::Ui, &target, ModbusControl::Setpoint(power))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn release_modbus_control(app: AppHandle, pin: Option<String>, device: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        modbus_guards(state, pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        apply_modbus(state, CommandSource::Ui, &target, ModbusControl::Release)
+    })
+    .await
+}
+
+// Mode de travail en Modbus (manual, auto, ai) ; sans effet sur une consigne en cours
+#[tauri::command]
+async fn set_modbus_work_mode(app: AppHandle, mode: WorkMode, pin: Option<String>, device: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        modbus_guards(state, pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        apply_modbus(state, CommandSource::Ui, &target, ModbusControl::WorkMode(mode))
+    })
+    .await
+}
+
+#[derive(Serialize, Clone)]
+struct ModbusControlStatus {
+    // Réglage advanced_control
+    enabled: bool,
+    // Appareils dont la consigne est tenue en Modbus
+    devices: Vec<String>,
+}
+
+#[tauri::command]
+fn get_modbus_control(state: State<AppState>) -> Result<ModbusControlStatus, AppError> {
+    let enabled = state.settings.lock().map_err(|e| e.to_string())?.advanced_control;
+    let mut devices: Vec<String> = state.modbus_control.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect();
+    devices.sort();
+    Ok(ModbusControlStatus { enabled, devices })
+}
+
+// Désactiver le contrôle avancé rend la main sur tous les appareils tenus en Modbus
+#[tauri::command]
+async fn set_advanced_control(app: AppHandle, enabled: bool, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.check_pin(pin.as_deref())?;
+        let controlled: Vec<String> = match enabled {
+            true => Vec::new(),
+            false => state.modbus_control.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect(),
+        };
+        // Pas d'ensure_writable : rendre la main à l'EMS de l'appareil reste permis en lecture seule, c'est l'état
+        // qu'elle vise. Une mise à jour firmware, elle, ne doit recevoir aucune écriture.
+        if let (false, Some(device)) = (controlled.is_empty(), state.ota.active()) {
+            return Err(AppError::Forbidden(format!("Firmware update in progress on {}: retry once it has finished.", device)));
+        }
+        {
+            let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+            settings.advanced_control = enabled;
+            settings::save(&state.settings_path, &settings)?;
+        }
+        for id in controlled {
+            let target = device_target(state, Some(&id))?;
+            apply_modbus(state, CommandSource::Ui, &target, ModbusControl::Release)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Serialize, Clone)]
+struct WifiMoveResult {
+    // set_result de Wifi.SetConfig
+    accepted: bool,
+    // Retrouvé par la découverte (même MAC) avant la fin de l'attente
+    reconnected: bool,
+    ip: Option<String>,
+}
+
+// Déplace l'appareil sur un autre réseau Wi-Fi puis le recherche par MAC pour relever sa nouvelle IP.
+// Un réseau que ce poste ne voit pas donne reconnected: false, sans être une erreur.
+#[tauri::command]
+async fn configure_wifi(
+    app: AppHandle,
+    ssid: String,
+    password: String,
+    pin: Option<String>,
+    device: Option<String>,
+    timeout_s: Option<u64>,
+) -> Result<WifiMoveResult, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let params = methods::wifi_config_params(&ssid, &password)?;
+        let target = device_target(state, device.as_deref())?;
+        ensure_method(state, &target, methods::WIFI_SET_CONFIG)?;
+
+        // Sans MAC connue, impossible de reconnaître l'appareil sous sa nouvelle adresse
+        let mut config = state.devices.lock().map_err(|e| e.to_string())?.get(Some(&target.id))?.1.clone();
+        if config.ble_mac.is_none() && config.wifi_mac.is_none() {
+            let result = send_command(state, Priority::Interactive, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params())?;
+            let info: DeviceInfo = serde_json::from_value(result).unwrap_or_default();
+            state.set_identity(&target.id, info.ble_mac.as_deref(), info.wifi_mac.as_deref())?;
+            config = state.devices.lock().map_err(|e| e.to_string())?.get(Some(&target.id))?.1.clone();
+            if config.ble_mac.is_none() && config.wifi_mac.is_none() {
+                return Err(AppError::InvalidInput("The device did not report its MAC address; its new IP could not be found.".to_string()));
+            }
+        }
+
+        // Pas de relevés en échec (ni d'alertes) pendant la bascule
+        let _pause = state.poller.pause();
+        let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, methods::WIFI_SET_CONFIG, params);
+        let logged = serde_json::json!({"ssid": ssid});
+        if let Err(e) = state.audit.record(CommandSource::Ui, &target.ip, methods::WIFI_SET_CONFIG, &logged, &outcome) {
+            tracing::warn!("failed to write audit entry: {}", e);
+        }
+        if !client::accepted(&outcome?) {
+            return Ok(WifiMoveResult { accepted: false, reconnected: false, ip: None });
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(timeout_s.unwrap_or(WIFI_RECONNECT_TIMEOUT_S).min(WIFI_RECONNECT_MAX_TIMEOUT_S));
+        while Instant::now() < deadline {
+            std::thread::sleep(WIFI_RECONNECT_POLL);
+            let found = match discover(state, Priority::Background, None) {
+                Ok(found) => found,
+                Err(e) => {
+                    tracing::debug!("discovery during Wi-Fi move failed: {}", e);
+                    continue;
+                }
+            };
+            let matched = found.iter().find(|d| {
+                let same = |a: &Option<String>, b: &Option<String>| {
+                    matches!((a, b), (Some(a), Some(b)) if devices::normalize_mac(a) == devices::normalize_mac(b))
+                };
+                same(&config.ble_mac, &d.ble_mac) || same(&config.wifi_mac, &d.wifi_mac)
+            });
+            if let Some(device) = matched {
+                let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+                if registry.set_address(&target.id, &device.ip, device.port) {
+                    tracing::info!(device = %target.id, from = %target.ip, to = %device.ip, "device moved to a new Wi-Fi network");
+                    registry.save(&state.devices_path)?;
+                }
+                return Ok(WifiMoveResult { accepted: true, reconnected: true, ip: Some(device.ip.clone()) });
+            }
+        }
+        Ok(WifiMoveResult { accepted: true, reconnected: false, ip: None })
+    })
+    .await
+}
+
+#[derive(Serialize, Clone)]
+struct RebootProgress {
+    id: String,
+    ip: String,
+    // "sent", "waiting", "online", "timeout"
+    stage: &'static str,
+    elapsed_s: u64,
+}
+
+// En deux appels : le premier renvoie un jeton décrivant l'opération, le second doit le reprendre dans confirmation.
+// Suivi par l'événement "reboot-progress" ; renvoie true si l'appareil répond à nouveau avant la fin de l'attente.
+#[tauri::command]
+async fn reboot_device(
+    app: AppHandle,
+    confirmation: Option<String>,
+    pin: Option<String>,
+    device: Option<String>,
+    timeout_s: Option<u64>,
+) -> Result<Confirmed<bool>, AppError> {
+    run_blocking(app, move |app, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        let describe = || format!("Reboot {}: it stops answering until it is back on the network", target.id);
+        if let Some(pending) = state.confirmations.check(confirmation.as_deref(), "reboot", &target.id, String::new(), describe).map_err(AppError::Forbidden)? {
+            return Ok(Confirmed::ConfirmationRequired(pending));
+        }
+
+        // Pas de relevés en échec (ni d'alertes) pendant le redémarrage
+        let _pause = state.poller.pause();
+        let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, methods::REBOOT, methods::status_params());
+        if let Err(e) = state.audit.record(CommandSource::Ui, &target.ip, methods::REBOOT, &methods::status_params(), &outcome) {
+            tracing::warn!("failed to write audit entry: {}", e);
+        }
+        // Une API bloquée peut redémarrer sans répondre : seul un refus explicite arrête ici
+        match outcome {
+            Err(e @ AppError::DeviceRejected { .. }) => return Err(e),
+            Err(e) => tracing::info!(device = %target.id, "no reply to reboot request: {}", e),
+            Ok(_) => {}
+        }
+
+        let started = Instant::now();
+        let progress = |stage| {
+            let event = RebootProgress { id: target.id.clone(), ip: target.ip.clone(), stage, elapsed_s: started.elapsed().as_secs() };
+            let _ = app.emit("reboot-progress", &event);
+        };
+        progress("sent");
+        std::thread::sleep(REBOOT_GRACE);
+
+        let deadline = started + Duration::from_secs(timeout_s.unwrap_or(REBOOT_TIMEOUT_S).min(WIFI_RECONNECT_MAX_TIMEOUT_S));
+        while Instant::now() < deadline {
+            progress("waiting");
+            if send_command(state, Priority::Background, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params()).is_ok() {
+                tracing::info!(device = %target.id, elapsed_s = started.elapsed().as_secs(), "device back online after reboot");
+                progress("online");
+                return Ok(Confirmed::Done { result: true });
+            }
+            std::thread::sleep(REBOOT_POLL);
+        }
+        progress("timeout");
+        Ok(Confirmed::Done { result: false })
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct BackupOutput {
+    enabled: bool,
+    // Charge branchée sur la prise de secours, [W]
+    offgrid_power: Option<f32>,
+}
+
+fn read_backup_output(state: &AppState, target: &DeviceTarget) -> Result<BackupOutput, AppError> {
+    if let Some(caps) = target.model.as_deref().map(models::capabilities).filter(|c| c.known && !c.backup_output) {
+        return Err(AppError::InvalidInput(format!("{} has no backup output", caps.model)));
+    }
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let query = |method| -> Result<serde_json::Value, AppError> {
+        let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(method), methods::status_params())?;
+        Ok(variant.normalize(result))
+    };
+    let backup = query(methods::ES_GET_BACKUP)?;
+    let enabled = backup.get("enable").and_then(|v| v.as_u64()).ok_or_else(|| AppError::ParseError("ES.GetBackup: missing enable".to_string()))?;
+    let offgrid_power = query(methods::ES_GET_STATUS).ok().and_then(|es| serde_json::from_value::<EnergyStatus>(es).ok()).and_then(|es| es.offgrid_power);
+    Ok(BackupOutput { enabled: enabled == 1, offgrid_power })
+}
+
+#[tauri::command]
+async fn get_backup_output(app: AppHandle, device: Option<String>) -> Result<BackupOutput, AppError> {
+    run_blocking(app, move |_, state| read_backup_output(state, &device_target(state, device.as_deref())?)).await
+}
+
+// Couper la sortie passe par un jeton de confirmation, comme reboot_device ; renvoie l'état relu
+#[tauri::command]
+async fn set_backup_output(
+    app: AppHandle,
+    enabled: bool,
+    confirmation: Option<String>,
+    pin: Option<String>,
+    device: Option<String>,
+) -> Result<Confirmed<BackupOutput>, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        let current = read_backup_output(state, &target)?;
+        if !enabled && current.enabled {
+            let describe = || {
+                let load = current.offgrid_power.map(|w| format!(" ({} W drawn now)", w)).unwrap_or_default();
+                format!("Switch off the backup output of {}: the emergency socket loses power{}", target.id, load)
+            };
+            if let Some(pending) = state.confirmations.check(confirmation.as_deref(), "backup_off", &target.id, String::new(), describe).map_err(AppError::Forbidden)? {
+                return Ok(Confirmed::ConfirmationRequired(pending));
+            }
+        }
+
+        write_backup_output(state, CommandSource::Ui, &target, enabled)?;
+        Ok(Confirmed::Done { result: read_backup_output(state, &target)? })
+    })
+    .await
+}
+
+fn write_backup_output(state: &AppState, source: CommandSource, target: &DeviceTarget, enabled: bool) -> Result<(), AppError> {
+    ensure_probed(state, target, methods::ES_GET_BACKUP, methods::ES_SET_BACKUP)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let params = methods::backup_params(enabled);
+    let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::ES_SET_BACKUP), params.clone());
+    if let Err(e) = state.audit.record(source, &target.ip, methods::ES_SET_BACKUP, &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    if !client::accepted(&outcome?) {
+        return Err(AppError::DeviceRejected { code: 0, message: "Backup output change was not accepted".to_string() });
+    }
+    tracing::info!(device = %target.id, enabled, "backup output switched");
+    Ok(())
+}
+
+fn read_heater(state: &AppState, target: &DeviceTarget) -> Result<HeaterStatus, AppError> {
+    if let Some(caps) = target.model.as_deref().map(models::capabilities).filter(|c| c.known && !c.heater) {
+        return Err(AppError::InvalidInput(format!("{} has no battery heater", caps.model)));
+    }
+    ensure_method(state, target, methods::BAT_GET_HEATER)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::BAT_GET_HEATER), methods::status_params())?;
+    Ok(parse_lenient(variant.normalize(result)).0)
+}
+
+#[tauri::command]
+async fn get_battery_heater(app: AppHandle, device: Option<String>) -> Result<HeaterStatus, AppError> {
+    run_blocking(app, move |_, state| read_heater(state, &device_target(state, device.as_deref())?)).await
+}
+
+// Chauffage automatique du BMS par temps froid ; renvoie l'état relu
+#[tauri::command]
+async fn set_battery_heater(app: AppHandle, auto_heat: bool, pin: Option<String>, device: Option<String>) -> Result<HeaterStatus, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        read_heater(state, &target)?;
+        write_heater(state, CommandSource::Ui, &target, auto_heat)?;
+        read_heater(state, &target)
+    })
+    .await
+}
+
+fn write_heater(state: &AppState, source: CommandSource, target: &DeviceTarget, auto_heat: bool) -> Result<(), AppError> {
+    ensure_probed(state, target, methods::BAT_GET_HEATER, methods::BAT_SET_HEATER)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let params = methods::heater_params(auto_heat);
+    let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::BAT_SET_HEATER), params.clone());
+    if let Err(e) = state.audit.record(source, &target.ip, methods::BAT_SET_HEATER, &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    if !client::accepted(&outcome?) {
+        return Err(AppError::DeviceRejected { code: 0, message: "Battery heater change was not accepted".to_string() });
+    }
+    tracing::info!(device = %target.id, auto_heat, "battery auto-heating switched");
+    Ok(())
+}
+
+fn read_meter_config(state: &AppState, target: &DeviceTarget) -> Result<MeterConfig, AppError> {
+    ensure_method(state, target, methods::EM_GET_CONFIG)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::EM_GET_CONFIG), methods::status_params())?;
+    serde_json::from_value(variant.normalize(result)).map_err(|e| AppError::ParseError(format!("EM.GetConfig: {}", e)))
+}
+
+#[tauri::command]
+async fn get_meter_config(app: AppHandle, device: Option<String>) -> Result<MeterConfig, AppError> {
+    run_blocking(app, move |_, state| read_meter_config(state, &device_target(state, device.as_deref())?)).await
+}
+
+// Bascule CT / compteur externe de l'appareil (sans rapport avec grid_meter, lu par l'app) et ré-appairage du CT ;
+// renvoie la configuration relue, pair_state vaut 1 tant que l'appairage n'est pas terminé
+#[tauri::command]
+async fn set_meter_config(app: AppHandle, mode: Option<MeterMode>, pair: Option<bool>, pin: Option<String>, device: Option<String>) -> Result<MeterConfig, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        write_meter_config(state, CommandSource::Ui, &target, mode, pair.unwrap_or(false))?;
+        read_meter_config(state, &target)
+    })
+    .await
+}
+
+fn write_meter_config(state: &AppState, source: CommandSource, target: &DeviceTarget, mode: Option<MeterMode>, pair: bool) -> Result<(), AppError> {
+    let params = methods::meter_config_params(mode, pair)?;
+    ensure_probed(state, target, methods::EM_GET_CONFIG, methods::EM_SET_CONFIG)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::EM_SET_CONFIG), params.clone());
+    if let Err(e) = state.audit.record(source, &target.ip, methods::EM_SET_CONFIG, &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    if !client::accepted(&outcome?) {
+        return Err(AppError::DeviceRejected { code: 0, message: "Meter configuration was not accepted".to_string() });
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_firmware_config(state: State<AppState>) -> Result<FirmwareConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.firmware.clone())
+}
+
+#[tauri::command]
+fn set_firmware_config(state: State<AppState>, config: FirmwareConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.firmware = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Version en cours (Marstek.GetDevice) comparée à la dernière connue du manifeste
+#[tauri::command]
+async fn check_firmware(app: AppHandle, device: Option<String>) -> Result<FirmwareCheck, AppError> {
+    run_blocking(app, move |_, state| {
+        let url = state.settings.lock().map_err(|e| e.to_string())?.firmware.manifest_url.clone();
+        let url = url.ok_or_else(|| AppError::NotConfigured("No firmware manifest URL configured".to_string()))?;
+        let target = device_target(state, device.as_deref())?;
+        let result = send_command(state, Priority::Interactive, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params())?;
+        let info: DeviceInfo = serde_json::from_value(result).unwrap_or_default();
+        Ok(firmware::check(&info, &firmware::fetch_manifest(&url)?))
+    })
+    .await
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+struct OtaProgress {
+    id: String,
+    // "started", "waiting", "done", "timeout"
+    stage: &'static str,
+    version: Option<u32>,
+    elapsed_s: u64,
+}
+
+// Déclenche la mise à jour puis rend la main ; suivi par l'événement "ota-progress" jusqu'au changement de version.
+// Pendant ce temps, toute commande de pilotage est refusée (ensure_writable). Confirmée par jeton, comme reboot_device.
+#[tauri::command]
+async fn start_ota(
+    app: AppHandle,
+    confirmation: Option<String>,
+    pin: Option<String>,
+    device: Option<String>,
+    timeout_s: Option<u64>,
+) -> Result<Confirmed<()>, AppError> {
+    run_blocking(app, move |app, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        if state.passive.session().is_some_and(|s| s.device == target.id) {
+            return Err(AppError::Forbidden("Stop Passive mode on this device before updating its firmware".to_string()));
+        }
+        let describe = || format!("Update the firmware of {}: control commands are refused until it is back with a new version", target.id);
+        if let Some(pending) = state.confirmations.check(confirmation.as_deref(), "ota", &target.id, String::new(), describe).map_err(AppError::Forbidden)? {
+            return Ok(Confirmed::ConfirmationRequired(pending));
+        }
+        let result = send_command(state, Priority::Interactive, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params())?;
+        let before: Option<u32> = serde_json::from_value::<DeviceInfo>(result).unwrap_or_default().ver;
+
+        state.ota.start(&target.id)?;
+        let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, methods::OTA_UPDATE, methods::status_params());
+        if let Err(e) = state.audit.record(CommandSource::Ui, &target.ip, methods::OTA_UPDATE, &methods::status_params(), &outcome) {
+            tracing::warn!("failed to write audit entry: {}", e);
+        }
+        // Méthode inconnue du firmware ou mise à jour refusée : rien ne démarre
+        match outcome {
+            Ok(result) if client::accepted(&result) => {}
+            Ok(_) => {
+                state.ota.finish();
+                return Err(AppError::DeviceRejected { code: 0, message: "Firmware update was not accepted".to_string() });
+            }
+            Err(e) => {
+                state.ota.finish();
+                return Err(e);
+            }
+        }
+
+        let app = app.clone();
+        let timeout = Duration::from_secs(timeout_s.unwrap_or(OTA_TIMEOUT_S).min(OTA_TIMEOUT_S * 2));
+        std::thread::spawn(move || {
+            let state = app.state::<AppState>();
+            let _pause = state.poller.pause();
+            let started = Instant::now();
+            let progress = |stage, version| {
+                let event = OtaProgress { id: target.id.clone(), stage, version, elapsed_s: started.elapsed().as_secs() };
+                let _ = app.emit("ota-progress", &event);
+            };
+            progress("started", before);
+            let mut done = false;
+            while started.elapsed() < timeout {
+                std::thread::sleep(OTA_POLL);
+                let probe = send_command(&state, Priority::Background, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params());
+                let version = probe.ok().and_then(|v| serde_json::from_value::<DeviceInfo>(v).ok()).and_then(|info| info.ver);
+                if version.is_some() && version != before {
+                    tracing::info!(device = %target.id, from = ?before, to = ?version, "firmware updated");
+                    progress("done", version);
+                    done = true;
+                    break;
+                }
+                progress("waiting", version);
+            }
+            if !done {
+                tracing::warn!(device = %target.id, "firmware update did not complete within {} s", timeout.as_secs());
+                progress("timeout", None);
+            }
+            state.ota.finish();
+        });
+        Ok(Confirmed::Done { result: () })
+    })
+    .await
+}
+
+// Batterie sortie du Wi-Fi : le Bluetooth reste le seul accès, comme pour l'application Marstek
+#[tauri::command]
+async fn discover_ble_devices(app: AppHandle, window_ms: Option<u64>) -> Result<Vec<BleDevice>, AppError> {
+    run_blocking(app, move |_, state| {
+        let window = Duration::from_millis(window_ms.unwrap_or(BLE_SCAN_MS).min(ble::MAX_SCAN_MS));
+        tauri::async_runtime::block_on(state.ble.discover(window))
+    })
+    .await
+}
+
+// Connecte l'appareil et renvoie sa réponse à Marstek.GetDevice
+#[tauri::command]
+async fn connect_ble(app: AppHandle, address: String) -> Result<DeviceInfo, AppError> {
+    run_blocking(app, move |_, state| {
+        let timeout = Duration::from_millis(state.connection()?.timeout_ms);
+        tauri::async_runtime::block_on(async {
+            state.ble.connect(&address, Duration::from_millis(BLE_SCAN_MS)).await?;
+            let result = state.ble.request(methods::GET_DEVICE, methods::probe_params(), timeout).await?;
+            Ok(serde_json::from_value(result).unwrap_or_default())
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+async fn disconnect_ble(app: AppHandle) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        tauri::async_runtime::block_on(state.ble.disconnect());
+        Ok(())
+    })
+    .await
+}
+
+// Mêmes sections que le dashboard, lues une à une sur la connexion BLE
+#[tauri::command]
+async fn get_ble_status(app: AppHandle) -> Result<DeviceStatus, AppError> {
+    run_blocking(app, move |_, state| {
+        let timeout = Duration::from_millis(state.connection()?.timeout_ms);
+        let sections = client::status_queries().map(|(name, method, params)| {
+            (name, tauri::async_runtime::block_on(state.ble.request(method, params, timeout)))
+        });
+        DeviceStatus::assemble(sections)
+    })
+    .await
+}
+
+// Identifiants Wi-Fi poussés par Bluetooth ; le mot de passe n'est pas écrit dans l'audit
+#[tauri::command]
+async fn ble_configure_wifi(app: AppHandle, ssid: String, password: String, pin: Option<String>) -> Result<bool, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let params = methods::wifi_config_params(&ssid, &password)?;
+        let address = tauri::async_runtime::block_on(state.ble.connected())
+            .ok_or_else(|| AppError::NotConfigured("No Bluetooth device connected. Call connect_ble first.".to_string()))?;
+        let timeout = Duration::from_millis(state.connection()?.timeout_ms);
+        let outcome = tauri::async_runtime::block_on(state.ble.request(methods::WIFI_SET_CONFIG, params, timeout));
+        let logged = serde_json::json!({"ssid": ssid, "transport": "ble"});
+        if let Err(e) = state.audit.record(CommandSource::Ui, &address, methods::WIFI_SET_CONFIG, &logged, &outcome) {
+            tracing::warn!("failed to write audit entry: {}", e);
+        }
+        Ok(client::accepted(&outcome?))
+    })
+    .await
+}
+
+// Table de registres Venus E : bien plus de points de mesure que l'API UDP
+#[tauri::command]
+async fn get_modbus_status(app: AppHandle, device: Option<String>) -> Result<ModbusStatus, AppError> {
+    run_blocking(app, move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        let _permit = state.scheduler.acquire_for(Priority::Interactive, &target.ip);
+        let start = Instant::now();
+        let status = modbus_client(state, &target).and_then(|mut client| Ok(marstek_protocol::read_modbus_status(&mut client)?));
+        state.metrics.record("Modbus.ReadStatus", start.elapsed(), status.is_ok());
+        status
+    })
+    .await
+}
+
+fn read_modbus_faults(state: &AppState, priority: Priority, target: &DeviceTarget) -> Result<Vec<DeviceFault>, AppError> {
+    let _permit = state.scheduler.acquire_for(priority, &target.ip);
+    let start = Instant::now();
+    let bits = modbus_client(state, target).and_then(|mut client| Ok(marstek_protocol::read_fault_bits(&mut client)?));
+    state.metrics.record("Modbus.ReadFaults", start.elapsed(), bits.is_ok());
+    let (alarm_bits, fault_bits) = bits?;
+    Ok(marstek_protocol::decode_fault_registers(alarm_bits, fault_bits))
+}
+
+// err_code de Bat.GetStatus et ES.GetStatus ; None : aucun des deux n'est renvoyé par ce firmware
+fn status_faults(battery: &BatteryStatus, energy: &EnergyStatus) -> Option<Vec<DeviceFault>> {
+    let codes = [battery.err_code, energy.err_code];
+    codes.iter().any(Option::is_some).then(|| merge_faults(Vec::new(), codes.into_iter().flatten().filter_map(marstek_protocol::decode_status_code).collect()))
+}
+
+fn merge_faults(mut faults: Vec<DeviceFault>, more: Vec<DeviceFault>) -> Vec<DeviceFault> {
+    for fault in more {
+        if !faults.iter().any(|f| f.code == fault.code) {
+            faults.push(fault);
+        }
+    }
+    faults
+}
+
+// Registres d'alarme et de défaut en Modbus, err_code des statuts UDP sinon
+#[tauri::command]
+async fn get_device_faults(app: AppHandle, device: Option<String>) -> Result<Vec<DeviceFault>, AppError> {
+    run_blocking(app, move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        let variant = state.protocol_variant(target.model.as_deref())?;
+        let query = |method| -> Result<serde_json::Value, AppError> {
+            let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(method), methods::status_params())?;
+            Ok(variant.normalize(result))
+        };
+        let battery: BatteryStatus = serde_json::from_value(query(methods::BAT_GET_STATUS)?).unwrap_or_default();
+        let energy: EnergyStatus = query(methods::ES_GET_STATUS).ok().and_then(|es| serde_json::from_value(es).ok()).unwrap_or_default();
+        let status = status_faults(&battery, &energy);
+        if target.modbus.is_some() {
+            return Ok(merge_faults(status.unwrap_or_default(), read_modbus_faults(state, Priority::Interactive, &target)?));
+        }
+        status.ok_or_else(|| AppError::NotConfigured(format!("{} reports no fault codes over UDP: enable Modbus TCP to read its fault registers", target.id)))
+    })
+    .await
+}
+
+fn read_power_limits(state: &AppState, priority: Priority, target: &DeviceTarget) -> Result<PowerLimits, AppError> {
+    let _permit = state.scheduler.acquire_for(priority, &target.ip);
+    let start = Instant::now();
+    let limits = modbus_client(state, target).and_then(|mut client| Ok(marstek_protocol::read_power_limits(&mut client)?));
+    state.metrics.record("Modbus.ReadPowerLimits", start.elapsed(), limits.is_ok());
+    if let Ok(limits) = &limits {
+        state.devices.lock().map_err(|e| e.to_string())?.set_power_limits(&target.id, Some(*limits));
+    }
+    limits
+}
+
+#[tauri::command]
+async fn get_power_limits(app: AppHandle, device: Option<String>) -> Result<PowerLimits, AppError> {
+    run_blocking(app, move |_, state| read_power_limits(state, Priority::Interactive, &device_target(state, device.as_deref())?)).await
+}
+
+// Réglage permanent de l'onduleur, borné par la puissance nominale du modèle ; renvoie les limites relues
+#[tauri::command]
+async fn set_power_limits(app: AppHandle, limits: PowerLimits, pin: Option<String>, device: Option<String>) -> Result<PowerLimits, AppError> {
+    run_blocking(app, move |_, state| {
+        modbus_guards(state, pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        write_power_limits(state, CommandSource::Ui, &target, &limits)?;
+        read_power_limits(state, Priority::Interactive, &target)
+    })
+    .await
+}
+
+fn write_power_limits(state: &AppState, source: CommandSource, target: &DeviceTarget, limits: &PowerLimits) -> Result<(), AppError> {
+    let hardware_max = target
+        .model
+        .as_deref()
+        .and_then(|model| models::capabilities(model).rated_power)
+        .map_or(MAX_FORCE_POWER, |rated| rated.min(u32::from(MAX_FORCE_POWER)) as u16);
+    limits.validate(hardware_max)?;
+
+    let params = serde_json::json!({
+        "writes": limits.writes().iter().map(|(register, value)| serde_json::json!({"register": register, "value": value})).collect::<Vec<_>>(),
+    });
+    let outcome = {
+        let _pause = state.poller.pause();
+        let _permit = state.scheduler.acquire_for(Priority::Control, &target.ip);
+        let start = Instant::now();
+        let outcome = modbus_client(state, target)
+            .and_then(|mut client| Ok(marstek_protocol::write_power_limits(&mut client, limits)?))
+            .map(|_| serde_json::Value::Bool(true));
+        state.metrics.record("Modbus.Write", start.elapsed(), outcome.is_ok());
+        outcome
+    };
+    if let Err(e) = state.audit.record(source, &target.ip, "Modbus.Write", &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    outcome.map(|_| ())
+}
+
+// Remplace l'ensemble des plages
+#[tauri::command]
+async fn set_schedules(app: AppHandle, slots: Vec<ManualSlot>, pin: Option<String>, device: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        schedule::validate_slots(&slots)?;
+        let target = device_target(state, device.as_deref())?;
+        let previous = {
+            let registry = state.devices.lock().map_err(|e| e.to_string())?;
+            registry.get(Some(&target.id))?.1.manual_slots.clone()
+        };
+        // Tout l'ensemble est contrôlé avant la première écriture
+        for slot in &slots {
+            check_power_limit(state, &target, slot.power)?;
+        }
+        write_slots(state, CommandSource::Ui, &target, &slots, &previous)?;
+
+        let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+        registry.set_manual_slots(&target.id, slots);
+        registry.save(&state.devices_path)
+    })
+    .await
+}
+
+// Sans chemin, ouvre une boîte d'enregistrement ; renvoie le fichier écrit (None si annulé)
+#[tauri::command]
+async fn export_schedule(app: AppHandle, device: Option<String>, path: Option<String>) -> Result<Option<String>, AppError> {
+    let (target, schedules) = run_blocking(app.clone(), move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        let schedules = read_schedules(state, &target)?;
+        Ok((target, schedules))
+    })
+    .await?;
+    let file = schedule::ScheduleFile {
+        format: schedule::SCHEDULE_FORMAT,
+        exported_at: chrono::Local::now().to_rfc3339(),
+        model: target.model.clone(),
+        slots: schedules.slots,
+    };
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("marstip-schedule-{}.json", target.id))
+                .add_filter("JSON", &["json"])
+                .blocking_save_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&path, serde_json::to_string_pretty(&file)?).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+// Instantané JSON des réglages de l'appareil ; sans chemin, ouvre une boîte d'enregistrement (None si annulé)
+#[tauri::command]
+async fn backup_device_settings(app: AppHandle, device: Option<String>, path: Option<String>) -> Result<Option<String>, AppError> {
+    let backup = run_blocking(app.clone(), move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        read_device_backup(state, &target)
+    })
+    .await?;
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("marstip-backup-{}.json", backup.device.as_deref().unwrap_or("device")))
+                .add_filter("JSON", &["json"])
+                .blocking_save_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&path, serde_json::to_string_pretty(&backup)?).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+fn read_device_backup(state: &AppState, target: &DeviceTarget) -> Result<DeviceBackup, AppError> {
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let query = |method: &str| -> Result<serde_json::Value, AppError> {
+        let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(method), methods::status_params())?;
+        Ok(variant.normalize(result))
+    };
+    let mut errors = BTreeMap::new();
+    let mut section = |name: &str, result: Result<(), AppError>| {
+        if let Err(e) = result {
+            errors.insert(name.to_string(), e.to_string());
+        }
+    };
+    let mut backup = DeviceBackup {
+        format: backup::BACKUP_FORMAT,
+        created_at: units::timestamp(),
+        device: Some(target.id.clone()),
+        model: target.model.clone(),
+        firmware: target.firmware,
+        mode: None,
+        slots: Vec::new(),
+        power_limits: None,
+        meter_mode: None,
+        backup_output: None,
+        auto_heat: None,
+        wifi_name: None,
+        errors: BTreeMap::new(),
+    };
+    section("mode", query(methods::ES_GET_MODE).map(|mode| backup.mode = mode.get("mode").and_then(|v| v.as_str()).map(String::from)));
+    section("schedules", read_schedules(state, target).map(|schedules| backup.slots = schedules.slots));
+    if target.modbus.is_some() {
+        section("power_limits", read_power_limits(state, Priority::Interactive, target).map(|limits| backup.power_limits = Some(limits)));
+    }
+    section("meter", read_meter_config(state, target).map(|config| backup.meter_mode = config.meter_mode));
+    if target.model.as_deref().map(models::capabilities).is_none_or(|c| !c.known || c.backup_output) {
+        section("backup_output", read_backup_output(state, target).map(|output| backup.backup_output = Some(output.enabled)));
+    }
+    if target.model.as_deref().map(models::capabilities).is_none_or(|c| !c.known || c.heater) {
+        section("heater", read_heater(state, target).map(|heater| backup.auto_heat = heater.auto_heat));
+    }
+    let wifi = query(methods::WIFI_GET_STATUS).and_then(|wifi| Ok(serde_json::from_value::<WifiStatus>(wifi)?));
+    section("wifi", wifi.map(|wifi| backup.wifi_name = wifi.ssid));
+    backup.errors = errors;
+    tracing::info!(device = %target.id, "device settings backed up ({} sections unreadable)", backup.errors.len());
+    Ok(backup)
+}
+
+fn restore_slots(state: &AppState, target: &DeviceTarget, slots: &[ManualSlot]) -> Result<(), AppError> {
+    for slot in slots {
+        check_power_limit(state, target, slot.power)?;
+    }
+    let current = read_schedules(state, target)?.slots;
+    write_slots(state, CommandSource::Ui, target, slots, &current)?;
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    registry.set_manual_slots(&target.id, slots.to_vec());
+    registry.save(&state.devices_path)
+}
+
+// Écrit chaque section présente dans la sauvegarde ; une section en échec n'empêche pas les suivantes.
+// Le mode vient en dernier : les plages Manual sont déjà en place quand il est rétabli.
+// Sans chemin, ouvre une boîte de sélection (None si annulé)
+#[tauri::command]
+async fn restore_device_settings(app: AppHandle, path: Option<String>, pin: Option<String>, device: Option<String>) -> Result<Option<RestoreReport>, AppError> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app.dialog().file().add_filter("JSON", &["json"]).blocking_pick_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    let backup = DeviceBackup::parse(&std::fs::read_to_string(&path)?)?;
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        if state.passive.session().is_some_and(|s| s.device == target.id) {
+            return Err(AppError::Forbidden("Stop Passive mode on this device before restoring its settings".to_string()));
+        }
+        let mut report = RestoreReport::default();
+
+        match backup.power_limits {
+            Some(_) if target.modbus.is_none() => report.skip("power_limits", "Modbus TCP is not enabled for this device"),
+            Some(_) if !state.settings.lock().map_err(|e| e.to_string())?.advanced_control => report.skip("power_limits", "Advanced control is disabled"),
+            Some(limits) => report.record("power_limits", write_power_limits(state, CommandSource::Ui, &target, &limits)),
+            None => report.skip("power_limits", "Not in the backup"),
+        }
+        match backup.meter_mode {
+            Some(mode) => {
+                let mode = if mode == 1 { MeterMode::External } else { MeterMode::Ct };
+                report.record("meter", write_meter_config(state, CommandSource::Ui, &target, Some(mode), false));
+            }
+            None => report.skip("meter", "Not in the backup"),
+        }
+        match backup.backup_output {
+            Some(true) => report.record("backup_output", write_backup_output(state, CommandSource::Ui, &target, true)),
+            // Couper la prise de secours demande un jeton de confirmation : jamais au détour d'une restauration
+            Some(false) => match read_backup_output(state, &target) {
+                Ok(current) if current.enabled => {
+                    report.skip("backup_output", "Switching the backup output off needs a confirmation: use set_backup_output")
+                }
+                Ok(_) => report.record("backup_output", Ok(())),
+                Err(e) => report.record("backup_output", Err(e)),
+            },
+            None => report.skip("backup_output", "Not in the backup"),
+        }
+        match backup.auto_heat {
+            Some(auto_heat) => report.record("heater", write_heater(state, CommandSource::Ui, &target, auto_heat)),
+            None => report.skip("heater", "Not in the backup"),
+        }
+        match backup.slots.is_empty() {
+            true => report.skip("schedules", "Not in the backup"),
+            false => report.record("schedules", restore_slots(state, &target, &backup.slots)),
+        }
+        match backup.mode.as_deref() {
+            // L'écriture des plages a déjà mis l'appareil en Manual
+            Some("Manual") if report.restored.iter().any(|s| s == "schedules") => report.record("mode", Ok(())),
+            Some("Passive") => report.skip("mode", "Passive mode is temporary and is not restored"),
+            Some(mode) => report.record("mode", restore_mode(state, CommandSource::Ui, &target, Some(mode))),
+            None => report.skip("mode", "Not in the backup"),
+        }
+        match backup.wifi_name {
+            Some(_) => report.skip("wifi", "Wi-Fi passwords are not backed up: use set_wifi_config"),
+            None => report.skip("wifi", "Not in the backup"),
+        }
+        tracing::info!(device = %target.id, "device settings restored: {} sections, {} failed", report.restored.len(), report.failed.len());
+        Ok(Some(report))
+    })
+    .await
+}
+
+// Fichier validé et comparé aux plages de l'appareil ; rien n'est écrit sans apply.
+// Sans chemin, ouvre une boîte de sélection (None si annulé)
+#[tauri::command]
+async fn import_schedule(
+    app: AppHandle,
+    path: Option<String>,
+    apply: Option<bool>,
+    pin: Option<String>,
+    device: Option<String>,
+) -> Result<Option<schedule::ScheduleImport>, AppError> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app.dialog().file().add_filter("JSON", &["json"]).blocking_pick_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    let slots = schedule::parse_file(&std::fs::read_to_string(&path)?)?;
+    let apply = apply.unwrap_or(false);
+    run_blocking(app, move |_, state| {
+        if apply {
+            state.ensure_writable()?;
+            state.check_pin(pin.as_deref())?;
+        }
+        let target = device_target(state, device.as_deref())?;
+        for slot in &slots {
+            check_power_limit(state, &target, slot.power)?;
+        }
+        let current = read_schedules(state, &target)?.slots;
+        let conflicts = schedule::conflicts(&current, &slots);
+        if apply {
+            write_slots(state, CommandSource::Ui, &target, &slots, &current)?;
+            let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+            registry.set_manual_slots(&target.id, slots.clone());
+            registry.save(&state.devices_path)?;
+            tracing::info!(device = %target.id, "imported {} manual slots from {}", slots.len(), path.display());
+        }
+        Ok(Some(schedule::ScheduleImport { slots, conflicts, applied: apply }))
+    })
+    .await
+}
+
+fn passive_config(power: i64, cd_time: u32) -> serde_json::Value {
+    serde_json::json!({ "passive_cfg": { "power": power, "cd_time": cd_time } })
+}
+
+// Mode Passive maintenu indéfiniment : le thread de maintien renvoie la consigne avant la fin de cd_time
+#[tauri::command]
+async fn start_passive(
+    app: AppHandle,
+    power: i64,
+    cd_time: Option<u32>,
+    pin: Option<String>,
+    device: Option<String>,
+    override_soc: Option<bool>,
+) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        let cd_time = cd_time.unwrap_or(passive::DEFAULT_CD_TIME);
+        hold_passive(state, CommandSource::Ui, &target, power, cd_time, !override_soc.unwrap_or(false))
+    })
+    .await
+}
+
+// Arrête le maintien et rétablit le mode lu au démarrage (Auto à défaut)
+#[tauri::command]
+async fn stop_passive(app: AppHandle, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        release_passive(state, CommandSource::Ui)
+    })
+    .await
+}
+
+// Envoie la consigne et la confie au thread de maintien ; une session en cours garde son mode d'origine
+fn hold_passive(state: &AppState, source: CommandSource, target: &DeviceTarget, power: i64, cd_time: u32, enforce_soc: bool) -> Result<(), AppError> {
+    let _writing = state.passive.exclusive();
+    let session = state.passive.session();
+    if let Some(other) = session.as_ref().filter(|s| s.device != target.id) {
+        return Err(AppError::InvalidInput(format!("Passive mode is already held on {}; stop it first", other.device)));
+    }
+    let previous_mode = match &session {
+        Some(session) => session.previous_mode.clone(),
+        None => {
+            let variant = state.protocol_variant(target.model.as_deref())?;
+            let priority = match source {
+                CommandSource::Ui => Priority::Interactive,
+                _ => Priority::Background,
+            };
+            let result = send_command(state, priority, &target.ip, target.port, variant.method(methods::ES_GET_MODE), methods::status_params())?;
+            variant.normalize(result).get("mode").and_then(|v| v.as_str()).map(String::from)
+        }
+    };
+    if !apply_mode(state, source, target, "Passive", Some(passive_config(power, cd_time)), enforce_soc)? {
+        return Err(AppError::DeviceRejected { code: 0, message: "Passive mode was not accepted (set_result: false)".to_string() });
+    }
+    match session {
+        Some(session) if session.cd_time == cd_time && session.enforce_soc == enforce_soc => state.passive.set_power(&target.id, power),
+        _ => state.passive.start(PassiveSession { device: target.id.clone(), power, cd_time, previous_mode, enforce_soc }),
+    }
+    Ok(())
+}
+
+fn release_passive(state: &AppState, source: CommandSource) -> Result<(), AppError> {
+    let _writing = state.passive.exclusive();
+    let Some(session) = state.passive.stop() else { return Ok(()) };
+    let target = device_target(state, Some(&session.device))?;
+    restore_mode(state, source, &target, session.previous_mode.as_deref())
+}
+
+fn restore_mode(state: &AppState, source: CommandSource, target: &DeviceTarget, mode: Option<&str>) -> Result<(), AppError> {
+    let slots = {
+        let registry = state.devices.lock().map_err(|e| e.to_string())?;
+        registry.get(Some(&target.id))?.1.manual_slots.clone()
+    };
+    match mode {
+        Some("AI") => apply_mode(state, source, target, "AI", None, true).map(|_| ()),
+        // Le mode Manual se rétablit en réécrivant les dernières plages connues
+        Some("Manual") if !slots.is_empty() => write_slots(state, source, target, &slots, &[]),
+        _ => apply_mode(state, source, target, "Auto", None, true).map(|_| ()),
+    }
+}
+
+// Charge complète puis décharge profonde en mode Passive, hors fenêtre de SOC des réglages ; suivi par l'événement
+// "calibration-progress". Le mode d'origine est rétabli à la fin, après un échec ou une annulation aussi.
+// Confirmée par jeton, lié à l'appareil et aux puissances demandées.
+#[tauri::command]
+async fn start_calibration(app: AppHandle, request: CalibrationRequest, confirmation: Option<String>, pin: Option<String>) -> Result<Confirmed<()>, AppError> {
+    run_blocking(app, move |app, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        request.validate()?;
+        let target = device_target(state, request.device.as_deref())?;
+        if state.passive.session().is_some() {
+            return Err(AppError::Forbidden("Stop Passive mode before starting a calibration".to_string()));
+        }
+        let params = format!(
+            "{}/{}/{}/{}/{}",
+            request.charge_power_w, request.discharge_power_w, request.settle_s, request.max_phase_h, request.sample_interval_s
+        );
+        let describe = || {
+            format!(
+                "Calibrate {}: full charge at {} W then deep discharge at {} W in Passive mode, up to {} h per phase",
+                target.id, request.charge_power_w, request.discharge_power_w, request.max_phase_h
+            )
+        };
+        if let Some(pending) = state.confirmations.check(confirmation.as_deref(), "calibration", &target.id, params, describe).map_err(AppError::Forbidden)? {
+            return Ok(Confirmed::ConfirmationRequired(pending));
+        }
+        state.calibration.start(&target.id)?;
+        tracing::info!(device = %target.id, "battery calibration started");
+
+        let app = app.clone();
+        std::thread::spawn(move || {
+            let state = app.state::<AppState>();
+            let (mut phase, mut error) = match run_calibration(&app, &state, &target, &request) {
+                Ok(true) => (CalibrationPhase::Done, None),
+                Ok(false) => (CalibrationPhase::Cancelled, None),
+                Err(e) => (CalibrationPhase::Failed, Some(e.to_string())),
+            };
+            state.calibration.set_phase(CalibrationPhase::Restoring);
+            let _ = app.emit("calibration-progress", &state.calibration.progress());
+            if let Err(e) = release_passive(&state, CommandSource::Ui) {
+                tracing::warn!(device = %target.id, "calibration: previous mode not restored: {}", e);
+                phase = CalibrationPhase::Failed;
+                error.get_or_insert(format!("Previous mode could not be restored: {}", e));
+            }
+            tracing::info!(device = %target.id, "battery calibration finished: {:?}", phase);
+            state.calibration.finish(phase, error);
+            let _ = app.emit("calibration-progress", &state.calibration.progress());
+        });
+        Ok(Confirmed::Done { result: () })
+    })
+    .await
+}
+
+// La calibration en cours s'arrête au prochain relevé et rétablit le mode d'origine
+#[tauri::command]
+fn cancel_calibration(state: State<AppState>, pin: Option<String>) -> Result<bool, AppError> {
+    state.check_pin(pin.as_deref())?;
+    Ok(state.calibration.cancel())
+}
+
+#[tauri::command]
+fn get_calibration_status(state: State<AppState>) -> CalibrationStatus {
+    state.calibration.status()
+}
+
+// Ok(false) : annulée
+fn run_calibration(app: &AppHandle, state: &AppState, target: &DeviceTarget, request: &CalibrationRequest) -> Result<bool, AppError> {
+    let phases = [
+        (CalibrationPhase::Charging, -i64::from(request.charge_power_w)),
+        (CalibrationPhase::Discharging, i64::from(request.discharge_power_w)),
+    ];
+    for (phase, power) in phases {
+        state.calibration.set_phase(phase);
+        hold_passive(state, CommandSource::Ui, target, power, passive::DEFAULT_CD_TIME, false)?;
+        tracing::info!(device = %target.id, "calibration: {:?} at {} W", phase, power);
+        let started = Instant::now();
+        let mut settle = Settle::default();
+        loop {
+            if state.calibration.wait(Duration::from_secs(request.sample_interval_s)) {
+                return Ok(false);
+            }
+            if !state.passive.session().is_some_and(|s| s.device == target.id) {
+                return Err(AppError::Forbidden("Passive mode was stopped during the calibration".to_string()));
+            }
+            let battery_power = match read_calibration_point(state, target, phase) {
+                Ok(point) => {
+                    let power = point.battery_power;
+                    state.calibration.record(point);
+                    power
+                }
+                Err(e) => {
+                    tracing::debug!(device = %target.id, "calibration sample failed: {}", e);
+                    None
+                }
+            };
+            let _ = app.emit("calibration-progress", &state.calibration.progress());
+            // BMS en butée : batterie pleine, puis vide
+            if settle.observe(battery_power, Duration::from_secs(request.settle_s)) {
+                break;
+            }
+            if started.elapsed() >= request.max_phase() {
+                return Err(AppError::Timeout(format!("Calibration phase {:?} did not finish within {} h", phase, request.max_phase_h)));
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn read_calibration_point(state: &AppState, target: &DeviceTarget, phase: CalibrationPhase) -> Result<CalibrationPoint, AppError> {
+    let details = read_battery_details(state, target, Priority::Background)?;
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Background, &target.ip, target.port, variant.method(methods::ES_GET_STATUS), methods::status_params())?;
+    let energy: EnergyStatus = serde_json::from_value(variant.normalize(result))?;
+    Ok(CalibrationPoint {
+        timestamp: chrono::Utc::now().timestamp(),
+        phase,
+        soc: details.soc.or(energy.bat_soc),
+        voltage: details.bat_voltage,
+        battery_power: energy.bat_power,
+    })
+}
+
+// Puissance au point de raccordement, [W] : > 0 en soutirage, < 0 en injection
+fn read_grid_power(state: &AppState, target: &DeviceTarget) -> Result<f32, AppError> {
+    if let Some(meter) = state.settings.lock().map_err(|e| e.to_string())?.grid_meter.clone() {
+        let reading = gridmeter::read(&meter).map_err(AppError::IoError)?;
+        return reading.total_power.ok_or_else(|| AppError::ParseError("Grid meter returned no total power".to_string()));
+    }
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Background, &target.ip, target.port, variant.method(methods::EM_GET_STATUS), methods::status_params())?;
+    let meter: MeterStatus = serde_json::from_value(variant.normalize(result))?;
+    if meter.ct_state == Some(0) {
+        return Err(AppError::NotConfigured("No CT meter connected".to_string()));
+    }
+    meter.total_power.ok_or_else(|| AppError::ParseError("EM.GetStatus returned no total_power".to_string()))
+}
+
+fn read_soc(state: &AppState, target: &DeviceTarget) -> Result<Option<u32>, AppError> {
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let result = send_command(state, Priority::Background, &target.ip, target.port, variant.method(methods::ES_GET_STATUS), methods::status_params())?;
+    Ok(variant.normalize(result).get("bat_soc").and_then(|v| v.as_u64()).map(|v| v as u32))
+}
+
+fn regulation_step(state: &AppState, config: &RegulationConfig) -> Result<RegulationStatus, AppError> {
+    state.ensure_writable()?;
+    state.ensure_automation_running()?;
+    let mut config = config.clone();
+    (config.min_soc, config.max_soc) = soc_limits(state)?.narrow(config.min_soc, config.max_soc);
+    if let Some(cap) = state.ev_charger.discharge_cap() {
+        config.max_discharge_w = config.max_discharge_w.min(cap as u32);
+    }
+    let target = device_target(state, config.device.as_deref())?;
+    state.ensure_online(&target.id)?;
+    let grid_power = read_grid_power(state, &target)?;
+    let soc = read_soc(state, &target)?;
+
+    let session = state.passive.session().filter(|s| s.device == target.id);
+    let current = session.as_ref().map_or(0, |s| s.power);
+    let mut setpoint = current;
+    // Sans session, la première consigne prend la main même à 0 W
+    if let Some(power) = config.next_setpoint(current, grid_power, soc).or(session.is_none().then_some(current)) {
+        hold_passive(state, CommandSource::Automation, &target, power, passive::DEFAULT_CD_TIME, true)?;
+        setpoint = power;
+    }
+    Ok(RegulationStatus {
+        device: Some(target.id),
+        grid_power: Some(grid_power),
+        soc,
+        setpoint: Some(setpoint),
+        last_error: None,
+        timestamp: Some(units::timestamp()),
+    })
+}
+
+// Source MQTT : le pont suit le sujet de la borne
+fn configure_ev_charger(state: &AppState, config: &EvChargerConfig) -> Result<(), AppError> {
+    state.ev_charger.configure(config, state.ev_charger_password()?);
+    let topic = match (&config.source, config.enabled) {
+        (Some(ev::EvChargerSource::Mqtt { topic }), true) => Some(topic.clone()),
+        _ => None,
+    };
+    state.mqtt.watch(topic);
+    Ok(())
+}
+
+fn spawn_ev_charger(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.ev_charger.wait_next();
+            let config = state.ev_charger.config();
+            if !config.enabled {
+                continue;
+            }
+            let result = state.ev_charger.poll().unwrap_or_else(|| match state.mqtt.watched() {
+                Some(payload) => ev::parse_mqtt(&payload, config.min_power_w),
+                None => Err("No EV charger message from the MQTT broker".to_string()),
+            });
+            if let Err(e) = &result {
+                tracing::debug!("EV charger read failed: {}", e);
+            }
+            if state.ev_charger.record(result) {
+                let status = state.ev_charger.status();
+                tracing::info!("EV charging: {:?}", status.charging);
+                let _ = app.emit("ev-charger-update", &status);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+fn get_ev_charger(state: State<AppState>) -> Result<(EvChargerConfig, EvChargerStatus), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.ev_charger.clone();
+    config.password = None;
+    Ok((config, state.ev_charger.status()))
+}
+
+// password absent : on conserve le mot de passe enregistré
+#[tauri::command]
+fn set_ev_charger_config(state: State<AppState>, config: EvChargerConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        config.password = secrets::update(secrets::EV_CHARGER_PASSWORD, config.password.take(), settings.ev_charger.password.clone())?;
+        settings.ev_charger = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    configure_ev_charger(&state, &config)
+}
+
+fn spawn_regulation(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.regulator.wait_next();
+            let config = state.regulator.config();
+            if !config.enabled {
+                continue;
+            }
+            let status = regulation_step(&state, &config).unwrap_or_else(|e| {
+                tracing::warn!("regulation step failed: {}", e);
+                RegulationStatus { last_error: Some(e.to_string()), ..state.regulator.status() }
+            });
+            state.regulator.record(status.clone());
+            let _ = app.emit("regulation-update", &status);
+        }
+    });
+}
+
+#[tauri::command]
+fn get_regulation(state: State<AppState>) -> Result<(RegulationConfig, RegulationStatus), AppError> {
+    Ok((state.regulator.config(), state.regulator.status()))
+}
+
+// Désactiver la régulation rend la main au mode d'origine
+#[tauri::command]
+async fn set_regulation_config(app: AppHandle, config: RegulationConfig, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.check_pin(pin.as_deref())?;
+        config.validate()?;
+        let was_enabled = state.regulator.config().enabled;
+        {
+            let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+            settings.regulation = config.clone();
+            settings::save(&state.settings_path, &settings)?;
+        }
+        state.regulator.configure(config.clone());
+        if was_enabled && !config.enabled && state.ensure_writable().is_ok() {
+            release_passive(state, CommandSource::Automation)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+fn peak_shaving_step(state: &AppState, config: &PeakShavingConfig) -> Result<PeakShavingStatus, AppError> {
+    state.ensure_writable()?;
+    state.ensure_automation_running()?;
+    // Les deux boucles pilotent la même consigne ; la régulation zéro injection écrête déjà tout soutirage
+    if state.regulator.config().enabled {
+        return Err(AppError::InvalidInput("Zero-feed-in regulation is active: peak shaving is suspended".to_string()));
+    }
+    let mut config = config.clone();
+    (config.min_soc, _) = soc_limits(state)?.narrow(config.min_soc, 100);
+    // Le soutirage de la voiture ne doit pas vider la batterie
+    if let Some(cap) = state.ev_charger.discharge_cap() {
+        config.max_discharge_w = config.max_discharge_w.min(cap as u32);
+    }
+    let target = device_target(state, config.device.as_deref())?;
+    state.ensure_online(&target.id)?;
+    let grid_power = read_grid_power(state, &target)?;
+    let window_average = state.peak_shaver.observe(grid_power, Duration::from_secs(config.window_minutes * 60));
+    let soc = read_soc(state, &target)?;
+
+    let current = state.passive.session().filter(|s| s.device == target.id).map_or(0, |s| s.power);
+    let setpoint = config.setpoint(current, grid_power, window_average, soc);
+    if setpoint != current {
+        if setpoint == 0 {
+            release_passive(state, CommandSource::Automation)?;
+        } else {
+            hold_passive(state, CommandSource::Automation, &target, setpoint, passive::DEFAULT_CD_TIME, true)?;
+        }
+    }
+
+    let now = chrono::Local::now();
+    let month = now.format("%Y-%m").to_string();
+    let previous = state.peak_shaver.status();
+    let month_peak = match previous.month_peak.filter(|_| previous.month.as_deref() == Some(month.as_str())) {
+        Some(peak) => peak.max(window_average),
+        None => window_average,
+    };
+    Ok(PeakShavingStatus {
+        device: Some(target.id),
+        grid_power: Some(grid_power),
+        window_average: Some(window_average),
+        month_peak: Some(month_peak),
+        month: Some(month),
+        setpoint: Some(setpoint),
+        last_error: None,
+        timestamp: Some(units::timestamp()),
+    })
+}
+
+fn spawn_peak_shaving(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.peak_shaver.wait_next();
+            let config = state.peak_shaver.config();
+            if !config.enabled {
+                continue;
+            }
+            let status = peak_shaving_step(&state, &config).unwrap_or_else(|e| {
+                tracing::warn!("peak shaving step failed: {}", e);
+                PeakShavingStatus { last_error: Some(e.to_string()), ..state.peak_shaver.status() }
+            });
+            state.peak_shaver.record(status.clone());
+            let _ = app.emit("peak-shaving-update", &status);
+        }
+    });
+}
+
+#[tauri::command]
+fn get_peak_shaving(state: State<AppState>) -> Result<(PeakShavingConfig, PeakShavingStatus), AppError> {
+    Ok((state.peak_shaver.config(), state.peak_shaver.status()))
+}
+
+#[tauri::command]
+async fn set_peak_shaving_config(app: AppHandle, config: PeakShavingConfig, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.check_pin(pin.as_deref())?;
+        config.validate()?;
+        let was_enabled = state.peak_shaver.config().enabled;
+        {
+            let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+            settings.peak_shaving = config.clone();
+            settings::save(&state.settings_path, &settings)?;
+        }
+        state.peak_shaver.configure(config.clone());
+        let discharging = state.peak_shaver.status().setpoint.is_some_and(|p| p > 0);
+        if was_enabled && !config.enabled && discharging && state.ensure_writable().is_ok() {
+            release_passive(state, CommandSource::Automation)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+fn export_limit_step(state: &AppState, config: &ExportLimitConfig) -> Result<ExportLimitStatus, AppError> {
+    state.ensure_writable()?;
+    state.ensure_automation_running()?;
+    // La régulation zéro injection absorbe déjà tout le surplus
+    if state.regulator.config().enabled {
+        return Err(AppError::InvalidInput("Zero-feed-in regulation is active: export limiting is suspended".to_string()));
+    }
+    if config.executor == ExportLimitExecutor::Modbus {
+        state.ensure_advanced_control()?;
+    }
+    let mut config = config.clone();
+    (_, config.max_soc) = soc_limits(state)?.narrow(0, config.max_soc);
+    let target = device_target(state, config.device.as_deref())?;
+    state.ensure_online(&target.id)?;
+    let grid_power = read_grid_power(state, &target)?;
+    let soc = read_soc(state, &target)?;
+
+    let previous = state.export_limiter.status();
+    let current = match config.executor {
+        ExportLimitExecutor::Passive => state.passive.session().filter(|s| s.device == target.id).map_or(0, |s| s.power),
+        // La consigne Modbus n'est pas relue : on reprend la dernière écrite tant que l'appareil reste sous contrôle Modbus
+        ExportLimitExecutor::Modbus => {
+            let controlled = state.modbus_control.lock().unwrap_or_else(|e| e.into_inner()).contains(&target.id);
+            previous.setpoint.filter(|_| controlled && previous.device.as_deref() == Some(target.id.as_str())).unwrap_or(0)
+        }
+    };
+    let setpoint = config.setpoint(current, grid_power, soc);
+    if setpoint != current {
+        match (config.executor, setpoint) {
+            (ExportLimitExecutor::Passive, 0) => release_passive(state, CommandSource::Automation)?,
+            (ExportLimitExecutor::Passive, _) => hold_passive(state, CommandSource::Automation, &target, setpoint, passive::DEFAULT_CD_TIME, true)?,
+            (ExportLimitExecutor::Modbus, 0) => apply_modbus(state, CommandSource::Automation, &target, ModbusControl::Release)?,
+            (ExportLimitExecutor::Modbus, _) => apply_modbus(state, CommandSource::Automation, &target, ModbusControl::Setpoint(setpoint))?,
+        }
+    }
+
+    let excess = -grid_power - config.limit_w;
+    let full = soc.is_some_and(|soc| soc >= config.max_soc);
+    Ok(ExportLimitStatus {
+        device: Some(target.id),
+        grid_power: Some(grid_power),
+        excess_w: Some(excess.max(0.0)),
+        soc,
+        setpoint: Some(setpoint),
+        saturated: excess > 0.0 && (full || setpoint <= -i64::from(config.max_charge_w)),
+        last_error: None,
+        timestamp: Some(units::timestamp()),
+    })
+}
+
+fn spawn_export_limit(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.export_limiter.wait_next();
+            let config = state.export_limiter.config();
+            if !config.enabled {
+                continue;
+            }
+            let status = export_limit_step(&state, &config).unwrap_or_else(|e| {
+                tracing::warn!("export limit step failed: {}", e);
+                ExportLimitStatus { last_error: Some(e.to_string()), ..state.export_limiter.status() }
+            });
+            state.export_limiter.record(status.clone());
+            let _ = app.emit("export-limit-update", &status);
+        }
+    });
+}
+
+// Consigne de charge du limiteur rendue : mode d'origine en Passive, contrôle local en Modbus
+fn release_export_limit(state: &AppState, config: &ExportLimitConfig) -> Result<(), AppError> {
+    let status = state.export_limiter.status();
+    if status.setpoint.is_none_or(|p| p >= 0) || state.ensure_writable().is_err() {
+        return Ok(());
+    }
+    state.export_limiter.record(ExportLimitStatus { setpoint: Some(0), ..status.clone() });
+    match config.executor {
+        ExportLimitExecutor::Passive => release_passive(state, CommandSource::Automation),
+        ExportLimitExecutor::Modbus => {
+            let target = device_target(state, status.device.as_deref())?;
+            apply_modbus(state, CommandSource::Automation, &target, ModbusControl::Release)
+        }
+    }
+}
+
+#[tauri::command]
+fn get_export_limit(state: State<AppState>) -> Result<(ExportLimitConfig, ExportLimitStatus), AppError> {
+    Ok((state.export_limiter.config(), state.export_limiter.status()))
+}
+
+#[tauri::command]
+async fn set_export_limit_config(app: AppHandle, config: ExportLimitConfig, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.check_pin(pin.as_deref())?;
+        config.validate()?;
+        let previous = state.export_limiter.config();
+        {
+            let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+            settings.export_limit = config.clone();
+            settings::save(&state.settings_path, &settings)?;
+        }
+        state.export_limiter.configure(config.clone());
+        if previous.enabled && (!config.enabled || previous.executor != config.executor) {
+            release_export_limit(state, &previous)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_soc_limits(state: State<AppState>) -> Result<SocLimits, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.soc_limits.clone())
+}
+
+// Pris en compte dès la prochaine commande ou le prochain tour des automatismes
+#[tauri::command]
+fn set_soc_limits(state: State<AppState>, limits: SocLimits, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    limits.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.soc_limits = limits;
+    settings::save(&state.settings_path, &settings)
+}
+
+fn price_plan(state: &AppState, rules: &PriceRules, day: chrono::NaiveDate) -> Result<Plan, AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.tariff.clone();
+    if !config.enabled {
+        return Err(AppError::NotConfigured("Dynamic prices are not enabled".to_string()));
+    }
+    let prices = state.prices.get(&config, state.tariff_api_key()?.as_deref(), day)?;
+    Ok(automation::plan(rules, &prices))
+}
+
+fn automation_step(state: &AppState, rules: &PriceRules) -> Result<AutomationStatus, AppError> {
+    use chrono::Timelike;
+    state.ensure_writable()?;
+    state.ensure_automation_running()?;
+    let now = chrono::Local::now();
+    let day = now.format("%Y-%m-%d").to_string();
+    let plan = match state.automation.plan_for(&day) {
+        Some(plan) => plan,
+        None => {
+            let plan = price_plan(state, rules, now.date_naive())?;
+            for warning in &plan.warnings {
+                tracing::warn!("price plan: {}", warning);
+            }
+            state.automation.set_plan(plan.clone());
+            plan
+        }
+    };
+    let target = device_target(state, rules.device.as_deref())?;
+    state.ensure_online(&target.id)?;
+    let exception = exception_today(state)?;
+    let profile = exception.as_ref().map(|e| &e.profile);
+    let planned = plan.action_at(now.hour());
+    let mut action = planned.map_or(PlanAction::Idle, |h| h.action);
+    if action == PlanAction::Discharge && profile.is_some_and(|p| p.no_discharge) {
+        action = PlanAction::Idle;
+    }
+
+    match rules.executor {
+        PlanExecutor::Manual => {
+            if state.automation.written_day().as_deref() != Some(day.as_str()) {
+                let previous = {
+                    let registry = state.devices.lock().map_err(|e| e.to_string())?;
+                    registry.get(Some(&target.id))?.1.manual_slots.clone()
+                };
+                let slots = profile.map_or_else(|| plan.slots.clone(), |p| p.adjust_slots(plan.slots.clone()));
+                write_slots(state, CommandSource::Automation, &target, &slots, &previous)?;
+                let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+                registry.set_manual_slots(&target.id, slots);
+                registry.save(&state.devices_path)?;
+                state.automation.set_written_day(day.clone());
+            }
+        }
+        PlanExecutor::Passive => {
+            let min_soc = profile.and_then(|p| p.min_soc).map_or(rules.min_soc, |soc| soc.max(rules.min_soc));
+            let (min_soc, max_soc) = soc_limits(state)?.narrow(min_soc, rules.max_soc);
+            let soc = read_soc(state, &target)?;
+            let ev_cap = state.ev_charger.discharge_cap();
+            action = match action {
+                PlanAction::Discharge if soc.is_some_and(|soc| soc <= min_soc) => PlanAction::Idle,
+                PlanAction::Charge if soc.is_some_and(|soc| soc >= max_soc) => PlanAction::Idle,
+                PlanAction::Discharge if ev_cap == Some(0) => PlanAction::Idle,
+                action => action,
+            };
+            let held = state.passive.session().filter(|s| s.device == target.id).map(|s| s.power);
+            // Voiture en charge : consigne de décharge ramenée sous le plafond (rétablie à l'heure suivante)
+            let over_cap = held.zip(ev_cap).is_some_and(|(power, cap)| power > cap);
+            match (action, planned) {
+                (PlanAction::Idle, _) => {
+                    // On ne rend la main que si la consigne en cours vient du moteur
+                    if matches!(state.automation.applied(), Some(PlanAction::Charge | PlanAction::Discharge)) {
+                        release_passive(state, CommandSource::Automation)?;
+                    }
+                }
+                (_, Some(hour)) if held.is_none() || over_cap || state.automation.applied() != Some(action) => {
+                    let power = ev_cap.map_or(hour.power, |cap| hour.power.min(cap));
+                    hold_passive(state, CommandSource::Automation, &target, power, passive::DEFAULT_CD_TIME, true)?;
+                }
+                _ => {}
+            }
+            state.automation.set_applied(Some(action));
+        }
+    }
+    Ok(AutomationStatus {
+        day: Some(day),
+        action: Some(action),
+        exception: exception.map(|e| e.name),
+        last_error: None,
+        timestamp: Some(units::timestamp()),
+    })
+}
+
+fn spawn_price_automation(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.automation.wait_tick();
+            let Ok(rules) = state.settings.lock().map(|s| s.price_rules.clone()) else { continue };
+            if !rules.enabled {
+                continue;
+            }
+            let status = automation_step(&state, &rules).unwrap_or_else(|e| {
+                tracing::warn!("price automation failed: {}", e);
+                AutomationStatus { last_error: Some(e.to_string()), ..state.automation.status() }
+            });
+            state.automation.record(status.clone());
+            let _ = app.emit("automation-update", &status);
+        }
+    });
+}
+
+#[tauri::command]
+fn get_price_rules(state: State<AppState>) -> Result<(PriceRules, AutomationStatus), AppError> {
+    let rules = state.settings.lock().map_err(|e| e.to_string())?.price_rules.clone();
+    Ok((rules, state.automation.status()))
+}
+
+#[tauri::command]
+async fn set_price_rules(app: AppHandle, rules: PriceRules, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| {
+        state.check_pin(pin.as_deref())?;
+        rules.validate()?;
+        {
+            let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+            check_slot_writers(&settings.solar_schedule, &rules)?;
+            settings.price_rules = rules.clone();
+            settings::save(&state.settings_path, &settings)?;
+        }
+        let applied = state.automation.applied();
+        state.automation.reset();
+        if (!rules.enabled || rules.executor != PlanExecutor::Passive) && matches!(applied, Some(PlanAction::Charge | PlanAction::Discharge)) {
+            state.automation.set_applied(None);
+            if state.ensure_writable().is_ok() {
+                release_passive(state, CommandSource::Automation)?;
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+// Simulation : plan calculé sur les prix du jour, sans rien envoyer à l'appareil
+#[tauri::command]
+async fn preview_price_plan(app: AppHandle, day: Option<String>, rules: Option<PriceRules>) -> Result<Plan, AppError> {
+    run_blocking(app, move |_, state| {
+        let rules = match rules {
+            Some(rules) => rules,
+            None => state.settings.lock().map_err(|e| e.to_string())?.price_rules.clone(),
+        };
+        rules.validate()?;
+        price_plan(state, &rules, parse_day(day.as_deref())?)
+    })
+    .await
+}
+
+// Période d'exception du jour (vacances, jours fériés) ; relue à chaque tour des automatismes
+fn exception_today(state: &AppState) -> Result<Option<ExceptionPeriod>, AppError> {
+    let calendar = state.settings.lock().map_err(|e| e.to_string())?.exception_calendar.clone();
+    Ok(calendar.active(chrono::Local::now().date_naive()).cloned())
+}
+
+#[tauri::command]
+fn get_exception_calendar(state: State<AppState>) -> Result<(ExceptionCalendar, Option<ExceptionPeriod>), AppError> {
+    let calendar = state.settings.lock().map_err(|e| e.to_string())?.exception_calendar.clone();
+    Ok((calendar, exception_today(&state)?))
+}
+
+// Les plages du jour sont recalculées par les deux moteurs au prochain tour
+#[tauri::command]
+fn set_exception_calendar(state: State<AppState>, calendar: ExceptionCalendar, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    calendar.validate()?;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        settings.exception_calendar = calendar;
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.automation.reset();
+    state.solar.reset();
+    Ok(())
+}
+
+// Position de la configuration, à défaut celle des prévisions solaires
+fn solar_location(state: &AppState, config: &SolarScheduleConfig) -> Result<(f64, f64), AppError> {
+    if let (Some(latitude), Some(longitude)) = (config.latitude, config.longitude) {
+        return Ok((latitude, longitude));
+    }
+    let forecast = state.settings.lock().map_err(|e| e.to_string())?.forecast.clone();
+    if forecast.latitude == 0.0 && forecast.longitude == 0.0 {
+        return Err(AppError::NotConfigured("Set a location for the solar schedule or the solar forecast".to_string()));
+    }
+    Ok((forecast.latitude, forecast.longitude))
+}
+
+fn solar_plan(state: &AppState, config: &SolarScheduleConfig, day: chrono::NaiveDate) -> Result<SolarScheduleStatus, AppError> {
+    let (latitude, longitude) = solar_location(state, config)?;
+    let sun = solar::sun_times(day, latitude, longitude);
+    let (slots, warnings) = solar::resolve(&config.slots, sun);
+    schedule::validate_slots(&slots)?;
+    Ok(SolarScheduleStatus::new(day, sun, slots, warnings))
+}
+
+// Les deux moteurs écriraient chacun leurs plages Manual sur le même appareil
+fn check_slot_writers(solar: &SolarScheduleConfig, rules: &PriceRules) -> Result<(), AppError> {
+    if solar.enabled && rules.enabled && rules.executor == PlanExecutor::Manual && solar.device == rules.device {
+        return Err(AppError::InvalidInput("The solar schedule and the price automation (manual executor) cannot drive the same device".to_string()));
+    }
+    Ok(())
+}
+
+// Plages du jour écrites une fois, puis à chaque changement de jour ou de configuration
+fn solar_step(state: &AppState, config: &SolarScheduleConfig) -> Result<(), AppError> {
+    state.ensure_writable()?;
+    state.ensure_automation_running()?;
+    let today = chrono::Local::now().date_naive();
+    if state.solar.written_day().as_deref() == Some(today.format("%Y-%m-%d").to_string().as_str()) {
+        return Ok(());
+    }
+    let mut plan = solar_plan(state, config, today)?;
+    if let Some(exception) = exception_today(state)? {
+        plan.slots = exception.profile.adjust_slots(plan.slots);
+        plan.exception = Some(exception.name);
+    }
+    for warning in &plan.warnings {
+        tracing::warn!("solar schedule: {}", warning);
+    }
+    let target = device_target(state, config.device.as_deref())?;
+    state.ensure_online(&target.id)?;
+    for slot in &plan.slots {
+        check_power_limit(state, &target, slot.power)?;
+    }
+    let previous = {
+        let registry = state.devices.lock().map_err(|e| e.to_string())?;
+        registry.get(Some(&target.id))?.1.manual_slots.clone()
+    };
+    write_slots(state, CommandSource::Automation, &target, &plan.slots, &previous)?;
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    registry.set_manual_slots(&target.id, plan.slots.clone());
+    registry.save(&state.devices_path)?;
+    tracing::info!(device = %target.id, "solar schedule: {} slots written for {}", plan.slots.len(), today);
+    state.solar.record(plan, true);
+    Ok(())
+}
+
+fn spawn_solar_schedule(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.solar.wait_tick();
+            let Ok(config) = state.settings.lock().map(|s| s.solar_schedule.clone()) else { continue };
+            if !config.enabled {
+                continue;
+            }
+            if let Err(e) = solar_step(&state, &config) {
+                tracing::warn!("solar schedule failed: {}", e);
+                state.solar.record(SolarScheduleStatus { last_error: Some(e.to_string()), ..state.solar.status() }, false);
+            }
+            let _ = app.emit("solar-schedule-update", &state.solar.status());
+        }
+    });
+}
+
+#[tauri::command]
+fn get_solar_schedule(state: State<AppState>) -> Result<(SolarScheduleConfig, SolarScheduleStatus), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.solar_schedule.clone();
+    Ok((config, state.solar.status()))
+}
+
+#[tauri::command]
+fn set_solar_schedule(state: State<AppState>, config: SolarScheduleConfig, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    config.validate()?;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        check_slot_writers(&config, &settings.price_rules)?;
+        settings.solar_schedule = config;
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.solar.reset();
+    Ok(())
+}
+
+// Plages concrètes d'un jour (aujourd'hui par défaut), sans rien envoyer à l'appareil
+#[tauri::command]
+fn preview_solar_schedule(state: State<AppState>, day: Option<String>, config: Option<SolarScheduleConfig>) -> Result<SolarScheduleStatus, AppError> {
+    let config = match config {
+        Some(config) => config,
+        None => state.settings.lock().map_err(|e| e.to_string())?.solar_schedule.clone(),
+    };
+    config.validate()?;
+    solar_plan(&state, &config, parse_day(day.as_deref())?)
+}
+
+#[tauri::command]
+fn get_passive_status(state: State<AppState>) -> PassiveStatus {
+    state.passive.status()
+}
+
+fn spawn_passive_keepalive(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            let session = state.passive.wait_due();
+            let _writing = state.passive.exclusive();
+            // Arrêtée ou modifiée pendant l'attente du verrou
+            let Some(session) = state.passive.session().filter(|s| s.device == session.device) else { continue };
+            let result = device_target(&state, Some(&session.device)).and_then(|target| {
+                // Lecture seule activée ou mise à jour en cours : la session prend fin sans autre écriture,
+                // l'appareil retombe de lui-même à l'expiration de cd_time
+                if let Err(e) = state.ensure_writable() {
+                    tracing::info!(device = %session.device, "passive session ended: {}", e);
+                    state.passive.stop();
+                    return Err(e);
+                }
+                // Fenêtre de SOC atteinte : la session est rendue au lieu d'être renouvelée
+                if session.enforce_soc {
+                    if let Err(e) = check_soc_limits(&state, &target, session.power) {
+                        state.passive.stop();
+                        restore_mode(&state, CommandSource::Automation, &target, session.previous_mode.as_deref())?;
+                        return Err(e);
+                    }
+                }
+                match apply_mode(&state, CommandSource::Automation, &target, "Passive", Some(passive_config(session.power, session.cd_time)), false)? {
+                    true => Ok(()),
+                    false => Err(AppError::DeviceRejected { code: 0, message: "Passive mode renewal was not accepted (set_result: false)".to_string() }),
+                }
+            });
+            state.passive.renewed(&session.device, result);
+            let _ = app.emit("passive-status", &state.passive.status());
+        }
+    });
+}
+
+// Console JSON-RPC : méthode arbitraire (y compris non documentée), réponse brute sans retry
+#[tauri::command]
+async fn send_raw_command(
+    app: AppHandle,
+    method: String,
+    params: Option<serde_json::Value>,
+    pin: Option<String>,
+    device: Option<String>,
+) -> Result<serde_json::Value, AppError> {
+    run_blocking(app, move |_, state| {
+        // La méthode peut modifier l'appareil : mêmes protections qu'un changement de mode
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        let connection = state.connection()?;
+        let params = params.unwrap_or_else(|| serde_json::json!({"id": 0}));
+
+        let _permit = state.scheduler.acquire_for(Priority::Interactive, &target.ip);
+        let start = Instant::now();
+        let outcome = tauri::async_runtime::block_on(state.transport.request_raw(
+            connection.local_port,
+            &discovery::endpoint(&target.ip, target.port),
+            &method,
+            params.clone(),
+            connection.timeout_for(&method),
+        ))
+        .map_err(AppError::from);
+        state.metrics.record(&method, start.elapsed(), outcome.is_ok());
+        if let Err(e) = state.audit.record(CommandSource::Ui, &target.ip, &method, &params, &outcome) {
+            tracing::warn!("failed to write audit entry: {}", e);
+        }
+        outcome
+    })
+    .await
+}
+
+// Copie de la config d'un appareil, pour ne pas garder le registre verrouillé pendant les requêtes
+struct DeviceTarget {
+    id: String,
+    ip: String,
+    port: u16,
+    model: Option<String>,
+    firmware: Option<u32>,
+    nickname: Option<String>,
+    modbus: Option<ModbusSettings>,
+}
+
+fn device_target(state: &AppState, device: Option<&str>) -> Result<DeviceTarget, AppError> {
+    let registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let (id, config) = registry.get(device)?;
+    Ok(DeviceTarget {
+        id: id.to_string(),
+        ip: config.ip.clone(),
+        port: config.port,
+        model: config.model.clone(),
+        firmware: config.firmware,
+        nickname: registry.profile(config.ble_mac.as_deref(), config.wifi_mac.as_deref()).and_then(|p| p.nickname.clone()),
+        modbus: config.modbus.clone(),
+    })
+}
+
+// Marstek.GetDevice : modèle et firmware retenus dans le registre, pour choisir dialecte et méthodes
+fn identify(state: &AppState, priority: Priority, target: &DeviceTarget) -> Result<(String, Option<u32>), AppError> {
+    let result = send_command(state, priority, &target.ip, target.port, methods::GET_DEVICE, methods::probe_params())?;
+    let info: DeviceInfo = serde_json::from_value(result).unwrap_or_default();
+    let model = info.device.ok_or("Device did not report its model")?;
+    state.set_model(&target.id, &model, info.ver)?;
+    Ok((model, info.ver))
+}
+
+// Écriture refusée sans envoi si le modèle n'a pas la méthode, plutôt qu'un délai d'attente ; modèle inconnu : détecté d'abord
+fn ensure_method(state: &AppState, target: &DeviceTarget, method: &str) -> Result<(), AppError> {
+    let (model, firmware) = match &target.model {
+        Some(model) => (model.clone(), target.firmware),
+        None => identify(state, Priority::Interactive, target)?,
+    };
+    state.capabilities.capabilities(&model, firmware).check_method(method)
+}
+
+// Écritures absentes de l'Open API : envoyées seulement si la lecture associée a déjà répondu
+// sur ce modèle et ce firmware, sinon on la sonde d'abord. Un firmware qui ne la connaît pas
+// ne reçoit jamais l'écriture à l'aveugle
+fn ensure_probed(state: &AppState, target: &DeviceTarget, read: &str, write: &str) -> Result<(), AppError> {
+    let (model, firmware) = match &target.model {
+        Some(model) => (model.clone(), target.firmware),
+        None => identify(state, Priority::Interactive, target)?,
+    };
+    state.capabilities.capabilities(&model, firmware).check_method(write)?;
+    if state.capabilities.answered(&model, firmware, read) {
+        return Ok(());
+    }
+    let variant = state.protocol_variant(Some(&model))?;
+    let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(read), methods::status_params());
+    // Un délai isolé ne dit rien de la méthode
+    if !matches!(result, Err(AppError::Timeout(_))) {
+        state.capabilities.observe(&model, firmware, read, &result);
+    }
+    result.map(|_| ()).map_err(|e| AppError::InvalidInput(format!("{} not sent: {} did not answer on {} ({})", write, read, model, e)))
+}
+
+// Chemin commun à toutes les sources de commande (UI, automatisations...) : envoi + audit
+// enforce_soc : refuse une consigne Passive qui sortirait de la fenêtre de SOC des réglages
+fn apply_mode(
+    state: &AppState,
+    source: CommandSource,
+    target: &DeviceTarget,
+    mode: &str,
+    config: Option<serde_json::Value>,
+    enforce_soc: bool,
+) -> Result<bool, AppError> {
+    state.ensure_udp_control(&target.id)?;
+    ensure_method(state, target, methods::ES_SET_MODE)?;
+    let _pause = state.poller.pause();
+
+    let request = ModeRequest::parse(mode, config.as_ref())?;
+    if let Some(power) = request.power() {
+        check_power_limit(state, target, power)?;
+    }
+    if let (ModeRequest::Passive(passive), true) = (&request, enforce_soc) {
+        check_soc_limits(state, target, passive.power)?;
+    }
+    let params = request.params();
+
+    let priority = match source {
+        CommandSource::Ui => Priority::Interactive,
+        _ => Priority::Background,
+    };
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    state.mode_watch.expect(&target.id, mode);
+    let outcome = send_command(state, priority, &target.ip, target.port, variant.method(methods::ES_SET_MODE), params.clone());
+    if let Err(e) = state.audit.record(source, &target.ip, methods::ES_SET_MODE, &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    let accepted = client::accepted(&outcome?);
+    // Une consigne Passive est temporaire : le mode à rétablir reste le précédent
+    if accepted && !matches!(request, ModeRequest::Passive(_)) {
+        let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+        if registry.set_desired_mode(&target.id, mode) {
+            registry.save(&state.devices_path)?;
+        }
+    }
+    Ok(accepted)
+}
+
+fn soc_limits(state: &AppState) -> Result<SocLimits, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.soc_limits.clone())
+}
+
+// Les plages Manual s'exécutent plus tard : seule une consigne immédiate peut être jugée sur le SOC actuel
+fn check_soc_limits(state: &AppState, target: &DeviceTarget, power: i64) -> Result<(), AppError> {
+    let limits = soc_limits(state)?;
+    if !limits.enabled || power == 0 {
+        return Ok(());
+    }
+    limits.check(power, read_soc(state, target)?)
+}
+
+// power : > 0 en décharge, < 0 en charge
+fn check_power_limit(state: &AppState, target: &DeviceTarget, power: i64) -> Result<(), AppError> {
+    let compliance = state.settings.lock().map_err(|e| e.to_string())?.compliance.clone();
+    state.compliance.check_setpoint(&compliance, power)?;
+
+    let Some(caps) = target.model.as_deref().map(models::capabilities) else { return Ok(()) };
+    if let Some(rated) = caps.rated_power {
+        if power.unsigned_abs() > rated as u64 {
+            return Err(AppError::InvalidInput(format!("Power {} W exceeds the {} limit of {} W", power, caps.model, rated)));
+        }
+    }
+    Ok(())
+}
+
+fn monitor_compliance(app: &AppHandle, state: &AppState, target: &DeviceTarget, data: &DashboardData) -> Result<(), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.compliance.clone();
+    let battery_output = data.energy.ongrid_power.unwrap_or(0.0);
+    let pv_output = data.energy.external_pv_power.unwrap_or(0.0);
+    let Some(violation) = state.compliance.observe(&config, battery_output, pv_output) else { return Ok(()) };
+    let _ = app.emit("compliance-violation", &violation);
+
+    // En mode Passive on peut corriger nous-mêmes la consigne de décharge
+    if data.mode.mode.as_deref() == Some("Passive") && battery_output > 0.0 && state.ensure_writable().is_ok() {
+        let allowed = state.compliance.allowed_discharge(&config).unwrap_or(0.0) as i64;
+        apply_mode(
+            state,
+            CommandSource::Automation,
+            target,
+            "Passive",
+            Some(passive_config(allowed, COMPLIANCE_CD_TIME)),
+            // Réduction de décharge : jamais refusée
+            false,
+        )?;
+        // Sinon le maintien du mode Passive rétablirait l'ancienne consigne
+        state.passive.set_power(&target.id, allowed);
+    }
+    Ok(())
+}
+
+// Modèle sans entrée PV : les champs PV renvoyés à zéro ne sont pas des mesures.
+// Un zéro sur un modèle avec PV (la nuit, panneaux débranchés) reste affiché
+fn trim_absent_pv(energy: &mut EnergyStatus, caps: Option<&ModelCapabilities>) {
+    if !caps.is_none_or(|c| c.supports(models::COMPONENT_PV)) {
+        energy.pv_power = None;
+        energy.total_pv_energy = None;
+    }
+}
+
+fn merge_external_pv(energy: &mut EnergyStatus, external: &PvReading) {
+    energy.external_pv_power = Some(external.power);
+    energy.pv_power = Some(energy.pv_power.unwrap_or(0.0) + external.power);
+    if let Some(total) = external.total_energy {
+        energy.total_pv_energy = Some(energy.total_pv_energy.unwrap_or(0.0) + total);
+    }
+}
+
+#[tauri::command]
+async fn get_dashboard(app: AppHandle, device: Option<String>) -> Result<DashboardData, AppError> {
+    run_blocking(app, move |app, state| dashboard_with_fallback(app, state, device.as_deref(), state.interface_ingests())).await
+}
+
+// Mêmes valeurs que DashboardData.flows et les exports ; simple lecture, rien n'est enregistré ni publié
+#[tauri::command]
+async fn get_energy_flows(app: AppHandle, device: Option<String>) -> Result<EnergyFlows, AppError> {
+    run_blocking(app, move |app, state| Ok(dashboard_with_fallback(app, state, device.as_deref(), false)?.flows)).await
+}
+
+// Vue brute : membres renvoyés par l'appareil sans champ typé, par section (vides omises) ; simple lecture
+#[tauri::command]
+async fn get_unknown_fields(app: AppHandle, device: Option<String>) -> Result<BTreeMap<String, UnknownFields>, AppError> {
+    run_blocking(app, move |app, state| {
+        let data = dashboard_with_fallback(app, state, device.as_deref(), false)?;
+        let mut sections = BTreeMap::from([
+            ("device".to_string(), data.device.unknown),
+            ("battery".to_string(), data.battery.unknown),
+            ("energy".to_string(), data.energy.unknown),
+            ("mode".to_string(), data.mode.unknown),
+            ("wifi".to_string(), data.wifi.unknown),
+        ]);
+        if let Some(meter) = data.meter {
+            sections.insert("meter".to_string(), meter.unknown);
+        }
+        sections.retain(|_, fields| !fields.is_empty());
+        Ok(sections)
+    })
+    .await
+}
+
+// Appareil injoignable sur le réseau local : le cloud Marstek s'il est configuré, sinon le dernier relevé marqué stale.
+// ingest : relevé local aussi enregistré et publié (voir ingest_dashboard)
+fn dashboard_with_fallback(app: &AppHandle, state: &AppState, device: Option<&str>, ingest: bool) -> Result<DashboardData, AppError> {
+    match collect_dashboard(state, device, false) {
+        Ok((target, data)) => {
+            if ingest {
+                ingest_dashboard(app, state, &target, &data)?;
+            }
+            Ok(data)
+        }
+        Err(e @ (AppError::Timeout(_) | AppError::IoError(_))) => {
+            cloud_dashboard(state, device, e).or_else(|e| stale_dashboard(state, device, e))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn stale_dashboard(state: &AppState, device: Option<&str>, error: AppError) -> Result<DashboardData, AppError> {
+    let target = device_target(state, device)?;
+    let latest = state.latest.lock().map_err(|e| e.to_string())?;
+    let Some(snapshot) = latest.get(&target.id) else { return Err(error) };
+    tracing::debug!(device = %target.id, "serving last known dashboard: {}", error);
+    let mut data = snapshot.data.clone();
+    data.stale = true;
+    data.age_s = Some(snapshot.collected_at.elapsed().as_secs());
+    Ok(data)
+}
+
+// Données réduites (SOC, puissances) et plus anciennes que le relevé local ; local_error est renvoyée si le repli échoue
+fn cloud_dashboard(state: &AppState, device: Option<&str>, local_error: AppError) -> Result<DashboardData, AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.cloud.clone();
+    if !config.enabled {
+        return Err(local_error);
+    }
+    let Some(password) = state.cloud_password()? else { return Err(local_error) };
+    let (ble_mac, wifi_mac, profile) = {
+        let registry = state.devices.lock().map_err(|e| e.to_string())?;
+        let (_, entry) = registry.get(device)?;
+        let profile = registry.profile(entry.ble_mac.as_deref(), entry.wifi_mac.as_deref()).cloned();
+        (entry.ble_mac.clone(), entry.wifi_mac.clone(), profile)
+    };
+    let devices = match state.cloud.devices(&config, &password) {
+        Ok(devices) => devices,
+        Err(e) => {
+            tracing::warn!("Marstek cloud fallback failed: {}", e);
+            return Err(local_error);
+        }
+    };
+    let Some(found) = cloud::find(&devices, &[ble_mac.as_deref(), wifi_mac.as_deref()]) else {
+        tracing::warn!("device not found in the Marstek cloud account");
+        return Err(local_error);
+    };
+    tracing::info!(device = %found.devid, "dashboard served from the Marstek cloud: {}", local_error);
+
+    let (energy, meter) = (found.energy(), found.meter());
+    let mut data = DashboardData {
+        device: found.device_info(),
+        battery: found.battery(),
+        kpis: kpi::instant(&energy, meter.as_ref()),
+        flows: flows::resolve(&energy, meter.as_ref()),
+        energy,
+        mode: ModeStatus::default(),
+        meter,
+        wifi: WifiStatus::default(),
+        timestamp: units::timestamp(),
+        display: DisplayValues::default(),
+        derived: BTreeMap::new(),
+        errors: BTreeMap::from([("local".to_string(), local_error.to_string())]),
+        unsupported: Vec::new(),
+        ignored_fields: BTreeMap::new(),
+        profile,
+        source: DataSource::Cloud,
+        link: None,
+        stale: false,
+        age_s: None,
+        power_limits: None,
+        faults: None,
+    };
+    data.display = state.settings.lock().map_err(|e| e.to_string())?.units.display(&data);
+    Ok(data)
+}
+
+// Mot de passe jamais renvoyé ; password absent à l'enregistrement : on conserve celui du trousseau
+#[tauri::command]
+fn get_cloud_config(state: State<AppState>) -> Result<CloudConfig, AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.cloud.clone();
+    config.password = None;
+    Ok(config)
+}
+
+#[tauri::command]
+fn set_cloud_config(state: State<AppState>, config: CloudConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut config = config;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    config.password = secrets::update(secrets::CLOUD_PASSWORD, config.password.take(), settings.cloud.password.clone())?;
+    settings.cloud = config;
+    settings::save(&state.settings_path, &settings)?;
+    state.cloud.reset();
+    Ok(())
+}
+
+fn join_query<T>(handle: std::thread::ScopedJoinHandle<'_, Result<T, AppError>>) -> Result<T, AppError> {
+    handle.join().unwrap_or_else(|_| Err(AppError::Internal("Device query panicked".to_string())))
+}
+
+// Relevé seul : le cache latest est mis à jour, l'historique, les exports et les automatismes relèvent d'ingest_dashboard.
+// scheduled : appel du polling, chaque section suit sa propre cadence et les autres reprennent le relevé précédent
+fn collect_dashboard(state: &AppState, device: Option<&str>, scheduled: bool) -> Result<(DeviceTarget, DashboardData), AppError> {
+    let mut target = device_target(state, device)?;
+    let (id, ip, port) = (target.id.clone(), target.ip.clone(), target.port);
+    let previous = match scheduled {
+        true => state.latest.lock().map_err(|e| e.to_string())?.get(&id).map(|snapshot| snapshot.data.clone()),
+        false => None,
+    };
+    let wanted = |section: &str| previous.is_none() || state.poller.section_due(&id, section);
+
+    let query_device = || -> Result<DeviceInfo, AppError> {
+        let result = send_command(state, Priority::Background, &ip, port, methods::GET_DEVICE, methods::probe_params())?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    };
+    // Modèle connu (refresh précédent) : toutes les requêtes partent en parallèle.
+    // Sinon GetDevice passe d'abord pour choisir le dialecte et les composants à interroger.
+    let first_device = match target.model {
+        Some(_) => None,
+        None => Some(query_device()?),
+    };
+    if let Some(first) = &first_device {
+        if let Some(model) = &first.device {
+            state.set_model(&target.id, model, first.ver)?;
+            target.model = Some(model.clone());
+            target.firmware = first.ver.or(target.firmware);
+        }
+    }
+
+    // Méthodes absentes de ce modèle ou de ce firmware : pas d'appel qui finirait en délai d'attente
+    let caps = target.model.as_deref().map(|model| state.capabilities.capabilities(model, target.firmware));
+    let supports = |method: &str| caps.as_ref().is_none_or(|c| c.supports_method(method));
+    let variant = state.protocol_variant(target.model.as_deref())?;
+    let query = |method: &str| -> Result<serde_json::Value, AppError> {
+        let result = send_command(state, Priority::Background, &ip, port, variant.method(method), methods::status_params())?;
+        Ok(variant.normalize(result))
+    };
+    let (pv_sources, grid_meter) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.pv_sources.clone(), settings.grid_meter.clone())
+    };
+
+    let fetch_device = first_device.is_none() && wanted("device");
+    let (fetch_energy, fetch_battery, fetch_wifi, fetch_mode) = (wanted("energy"), wanted("battery"), wanted("wifi"), wanted("mode"));
+    let fetch_meter = grid_meter.is_none() && wanted("meter");
+    // Section non due : valeur du relevé précédent (toujours présent dans ce cas)
+    let kept = |fetched: bool| previous.as_ref().filter(|_| !fetched);
+
+    // Lues une fois en Modbus puis servies depuis le registre ; mises à jour par set_power_limits
+    let cached_limits = state.devices.lock().map_err(|e| e.to_string())?.get(Some(&target.id))?.1.power_limits;
+
+    let has_heater = caps.as_ref().is_some_and(|c| c.heater);
+    let (device, es_result, bat_result, wifi_result, mode_result, em_result, external, external_meter, limits_result, faults_result, heater_result) = std::thread::scope(|s| {
+        let device = fetch_device.then(|| s.spawn(query_device));
+        let spawn_if = |method: &'static str, wanted: bool| (wanted && supports(method)).then(|| s.spawn(move || query(method)));
+        let es = spawn_if(methods::ES_GET_STATUS, fetch_energy);
+        let bat = spawn_if(methods::BAT_GET_STATUS, fetch_battery);
+        let wifi = spawn_if(methods::WIFI_GET_STATUS, fetch_wifi);
+        let mode = spawn_if(methods::ES_GET_MODE, fetch_mode);
+        // Un compteur externe remplace le CT : EM.GetStatus n'est alors pas interrogé
+        let em = spawn_if(methods::EM_GET_STATUS, fetch_meter);
+        // Complète la section batterie ; facultatif comme les limites de puissance
+        let heater = spawn_if(methods::BAT_GET_HEATER, fetch_battery && has_heater);
+        let external = s.spawn(|| inverter::read_all(&pv_sources));
+        let external_meter = grid_meter.as_ref().map(|meter| s.spawn(|| gridmeter::read(meter).map_err(AppError::IoError)));
+        let limits = (cached_limits.is_none() && target.modbus.is_some()).then(|| s.spawn(|| read_power_limits(state, Priority::Background, &target)));
+        let modbus_faults = target.modbus.is_some().then(|| s.spawn(|| read_modbus_faults(state, Priority::Background, &target)));
+        (
+            match device {
+                Some(handle) => join_query(handle),
+                None => first_device
+                    .clone()
+                    .or_else(|| kept(fetch_device).map(|previous| previous.device.clone()))
+                    .ok_or_else(|| AppError::Internal("Missing device info".to_string())),
+            },
+            es.map(join_query),
+            bat.map(join_query),
+            wifi.map(join_query),
+            mode.map(join_query),
+            em.map(join_query),
+            external.join().ok().flatten(),
+            external_meter.map(join_query),
+            limits.map(join_query),
+            modbus_faults.map(join_query),
+            heater.map(join_query),
+        )
+    });
+
+    let queries = [
+        (methods::ES_GET_STATUS, &es_result),
+        (methods::BAT_GET_STATUS, &bat_result),
+        (methods::WIFI_GET_STATUS, &wifi_result),
+        (methods::ES_GET_MODE, &mode_result),
+        (methods::EM_GET_STATUS, &em_result),
+    ];
+    let replied = device.is_ok() || queries.iter().any(|(_, r)| r.as_ref().is_some_and(|r| r.is_ok()));
+    if let (Some(model), true) = (&target.model, replied) {
+        let heater = [(methods::BAT_GET_HEATER, &heater_result)];
+        for (method, result) in queries.iter().chain(&heater).filter_map(|(m, r)| r.as_ref().map(|r| (m, r))) {
+            state.capabilities.observe(model, target.firmware, method, result);
+        }
+    }
+    let device_queried = first_device.is_some() || fetch_device;
+    let queried = usize::from(device_queried) + queries.iter().filter(|(_, r)| r.is_some()).count() + usize::from(grid_meter.is_some());
+
+    // Une sous-requête en échec ne vide que sa section ; on n'échoue que si rien n'a répondu
+    let mut errors = BTreeMap::new();
+    let mut section = |name: &str, result: Result<serde_json::Value, AppError>| {
+        result.unwrap_or_else(|e| {
+            tracing::warn!(section = name, "dashboard sub-query failed: {}", e);
+            errors.insert(name.to_string(), e);
+            serde_json::Value::Null
+        })
+    };
+    let mut skipped = |name: &str, result: Option<Result<serde_json::Value, AppError>>| {
+        result.map(|r| section(name, r)).unwrap_or(serde_json::Value::Null)
+    };
+    let es_result = skipped("energy", es_result);
+    let bat_result = skipped("battery", bat_result);
+    let wifi_result = skipped("wifi", wifi_result);
+    let mode_result = skipped("mode", mode_result);
+    let em_result = em_result.map(|r| section("meter", r));
+    let external_meter = external_meter.and_then(|r| {
+        r.map_err(|e| {
+            tracing::warn!(section = "meter", "external grid meter failed: {}", e);
+            errors.insert("meter".to_string(), e);
+        })
+        .ok()
+    });
+    let device = match device {
+        Ok(device) => device,
+        Err(e) => {
+            errors.insert("device".to_string(), e);
+            DeviceInfo::default()
+        }
+    };
+    // Toutes les sections reprises du relevé précédent : rien à comparer
+    if queried > 0 && errors.len() >= queried {
+        return Err(errors.into_values().next().unwrap_or_else(|| AppError::Internal("No response".to_string())));
+    }
+    let fetched = [
+        ("device", device_queried),
+        ("energy", fetch_energy),
+        ("battery", fetch_battery),
+        ("wifi", fetch_wifi),
+        ("mode", fetch_mode),
+        ("meter", fetch_meter),
+    ];
+    for (section, _) in fetched.iter().filter(|(section, fetched)| *fetched && !errors.contains_key(*section)) {
+        state.poller.section_polled(&id, section);
+    }
+    // Facultative, hors du décompte des sous-requêtes
+    let power_limits = match limits_result {
+        Some(Ok(limits)) => Some(limits),
+        Some(Err(e)) => {
+            tracing::debug!("power limits unavailable: {}", e);
+            errors.insert("power_limits".to_string(), e);
+            None
+        }
+        None => cached_limits,
+    };
+
+    if let Some(model) = &device.device {
+        if target.model.as_ref() != Some(model) || (device.ver.is_some() && target.firmware != device.ver) {
+            state.set_model(&target.id, model, device.ver)?;
+            target.model = Some(model.clone());
+            target.firmware = device.ver.or(target.firmware);
+        }
+    }
+    state.set_identity(&target.id, device.ble_mac.as_deref(), device.wifi_mac.as_deref())?;
+    let profile = state.devices.lock().map_err(|e| e.to_string())?.profile(device.ble_mac.as_deref(), device.wifi_mac.as_deref()).cloned();
+
+    let mut ignored_fields = BTreeMap::new();
+    let mut lenient = |name: &str, result: serde_json::Value| {
+        let (parsed, dropped) = parse_lenient(result);
+        if !dropped.is_empty() {
+            tracing::debug!(section = name, ?dropped, "fields with an unexpected type ignored");
+            ignored_fields.insert(name.to_string(), dropped);
+        }
+        parsed
+    };
+
+    // Reprise telle quelle : le PV externe y est déjà ajouté
+    let energy = match kept(fetch_energy) {
+        Some(previous) => previous.energy.clone(),
+        None => {
+            let mut energy: EnergyStatus = lenient("energy", es_result);
+            trim_absent_pv(&mut energy, caps.as_ref());
+            if let Some(external) = external {
+                merge_external_pv(&mut energy, &external);
+            }
+            energy
+        }
+    };
+
+    let battery: BatteryStatus = match kept(fetch_battery) {
+        Some(previous) => previous.battery.clone(),
+        None => {
+            let mut battery: BatteryStatus = lenient("battery", bat_result);
+            match heater_result {
+                Some(Ok(result)) => {
+                    let heater: HeaterStatus = lenient("heater", result);
+                    battery.heater_on = battery.heater_on.or(heater.heater_on);
+                    battery.heater_power = battery.heater_power.or(heater.heater_power);
+                }
+                Some(Err(e)) => tracing::debug!("battery heater unavailable: {}", e),
+                None => {}
+            }
+            battery
+        }
+    };
+
+    let wifi: WifiStatus = match kept(fetch_wifi) {
+        Some(previous) => previous.wifi.clone(),
+        None => lenient("wifi", wifi_result),
+    };
+
+    let faults = match faults_result {
+        Some(Ok(modbus)) => Some(merge_faults(status_faults(&battery, &energy).unwrap_or_default(), modbus)),
+        Some(Err(e)) => {
+            tracing::debug!("fault registers unavailable: {}", e);
+            errors.insert("faults".to_string(), e);
+            status_faults(&battery, &energy)
+        }
+        None => status_faults(&battery, &energy),
+    };
+
+    let mode: ModeStatus = match kept(fetch_mode) {
+        Some(previous) => previous.mode.clone(),
+        None => lenient("mode", mode_result),
+    };
+
+    let meter = match kept(fetch_meter || grid_meter.is_some()) {
+        Some(previous) => previous.meter.clone(),
+        None => em_result.and_then(|em_result| {
+            let meter: MeterStatus = lenient("meter", em_result);
+            // Pas de CT connecté : la section compteur n'a aucune donnée réelle
+            (meter.ct_state != Some(0)).then_some(meter)
+        }).or(external_meter),
+    };
+
+    // Sections reprises du relevé précédent : leurs champs écartés aussi
+    for (section, fields) in previous.iter().flat_map(|p| &p.ignored_fields) {
+        if fetched.iter().any(|(name, queried)| *name == section.as_str() && !queried) {
+            ignored_fields.entry(section.clone()).or_insert_with(|| fields.clone());
+        }
+    }
+    let unsupported = caps
+        .as_ref()
+        .map(|c| c.unsupported_sections().into_iter().filter(|s| *s != "meter" || grid_meter.is_none()).map(str::to_string).collect())
+        .unwrap_or_default();
+    let timestamp = units::timestamp();
+
+    let mut data = DashboardData {
+        device,
+        battery,
+        energy,
+        mode,
+        meter,
+        wifi,
+        timestamp,
+        display: DisplayValues::default(),
+        derived: BTreeMap::new(),
+        errors: errors.into_iter().map(|(section, e)| (section, e.to_string())).collect(),
+        unsupported,
+        ignored_fields,
+        profile,
+        source: DataSource::Local,
+        link: state.transport.links.address(&discovery::endpoint(&target.ip, target.port)).map(|ip| state.transport.links.get(ip)),
+        stale: false,
+        age_s: None,
+        kpis: Kpis::default(),
+        flows: EnergyFlows::default(),
+        power_limits,
+        faults,
+    };
+    data.kpis = kpi::instant(&data.energy, data.meter.as_ref());
+    data.flows = flows::resolve(&data.energy, data.meter.as_ref());
+    data.display = state.settings.lock().map_err(|e| e.to_string())?.units.display(&data);
+    let sensors = state.settings.lock().map_err(|e| e.to_string())?.derived_sensors.clone();
+    if !sensors.is_empty() {
+        let sample = serde_json::to_value(&data).map_err(|e| e.to_string())?;
+        data.derived = derived::evaluate(&sensors, &sample);
+    }
+    state.latest.lock().map_err(|e| e.to_string())?.insert(
+        target.id.clone(),
+        prometheus::DeviceSnapshot {
+            device: target.id.clone(),
+            ip: target.ip.clone(),
+            model: target.model.clone(),
+            data: data.clone(),
+            collected_at: Instant::now(),
+        },
+    );
+
+    Ok((target, data))
+}
+
+// Effets d'un relevé : automatismes, historique, exports et événements. Un seul appelant par relevé
+// (polling de fond, ou rafraîchissement de l'interface qui en tient lieu), sinon tout serait compté et piloté en double.
+fn ingest_dashboard(app: &AppHandle, state: &AppState, target: &DeviceTarget, data: &DashboardData) -> Result<(), AppError> {
+    if let Some(m) = &data.meter {
+        if let (Some(a), Some(b), Some(c)) = (m.a_power, m.b_power, m.c_power) {
+            state.phases.observe([a, b, c]);
+        }
+    }
+    run_plugins(app, state, target, data);
+    if let Err(e) = monitor_compliance(app, state, target, data) {
+        tracing::warn!("compliance enforcement failed: {}", e);
+    }
+    if data.energy.grid_voltage.is_some() || data.energy.grid_frequency.is_some() {
+        let config = state.settings.lock().map_err(|e| e.to_string())?.grid_quality.clone();
+        for event in state.grid_quality.observe(&config, data.energy.grid_voltage, data.energy.grid_frequency) {
+            let _ = app.emit("grid-quality-alert", &event);
+        }
+    }
+
+    let sg_config = state.settings.lock().map_err(|e| e.to_string())?.sg_ready.clone();
+    if sg_config.enabled || state.sg_ready.status().active {
+        let soc = data.battery.soc.or(data.energy.bat_soc);
+        let grid_power = data.meter.as_ref().and_then(|m| m.total_power);
+        state.sg_ready.evaluate(&sg_config, soc, grid_power);
+    }
+
+    if let Some(history) = &state.history {
+        let sample = HistorySample {
+            soc: data.battery.soc.or(data.energy.bat_soc),
+            pv_power: data.energy.pv_power,
+            grid_power: data.meter.as_ref().and_then(|m| m.total_power),
+            battery_power: data.energy.bat_power,
+            temperature: data.battery.bat_temp,
+        };
+        let now = chrono::Utc::now().timestamp();
+        if let Err(e) = history.record(&target.id, now, &sample) {
+            tracing::warn!("failed to record history: {}", e);
+        }
+        if let Some(phases) = data.meter.as_ref().and_then(PhaseSample::from_meter) {
+            if let Err(e) = history.record_phases(&target.id, now, &phases) {
+                tracing::warn!("failed to record phase history: {}", e);
+            }
+        }
+    }
+    record_health(state, &target.id, data.battery.soh, data.battery.cycle_count);
+    state.influx.push(&target.id, target.model.as_deref(), data, chrono::Utc::now().timestamp());
+    let selected = state.devices.lock().map_err(|e| e.to_string())?.get(None).is_ok_and(|(id, _)| id == target.id);
+    state.pvoutput.push(&target.id, selected, data, chrono::Local::now());
+    state.mqtt.publish(&target.id, target.model.as_deref(), data);
+    let changes = state.settings.lock().map_err(|e| e.to_string())?.changes.clone();
+    if changes.enabled {
+        let soc = data.battery.soc.or(data.energy.bat_soc);
+        let grid_power = data.meter.as_ref().and_then(|m| m.total_power);
+        for event in state.changes.observe(&changes, &target.id, soc, data.mode.mode.as_deref(), grid_power) {
+            let _ = app.emit(event.event, &event);
+            state.mqtt.publish_change(&target.id, &event);
+        }
+    }
+    state.webhooks.observe(&target.id, data.battery.soc.or(data.energy.bat_soc), data.mode.mode.as_deref());
+    Ok(())
+}
+
+fn apply_action(state: &AppState, source: CommandSource, target: &DeviceTarget, action: PluginAction) -> Result<bool, AppError> {
+    match action {
+        PluginAction::SetMode(mode) => apply_mode(state, source, target, &mode, None, true),
+        PluginAction::SetPassivePower { power, cd_time } => apply_mode(
+            state,
+            source,
+            target,
+            "Passive",
+            Some(serde_json::json!({ "passive_cfg": { "power": power, "cd_time": cd_time } })),
+            true,
+        ),
+    }
+}
+
+fn handle_mqtt_command(state: &AppState, command: &MqttCommand) -> Result<bool, AppError> {
+    state.ensure_writable()?;
+    let skip_pin = state.settings.lock().map_err(|e| e.to_string())?.mqtt.commands_skip_pin;
+    if !skip_pin {
+        state.check_pin(command.pin.as_deref())?;
+    }
+    let id = {
+        let registry = state.devices.lock().map_err(|e| e.to_string())?;
+        registry
+            .devices
+            .keys()
+            .find(|id| mqtt::object_id(id) == command.device)
+            .cloned()
+            .ok_or_else(|| format!("Unknown device: {}", command.device))?
+    };
+    let target = device_target(state, Some(&id))?;
+    apply_action(state, CommandSource::Mqtt, &target, command.action.clone())
+}
+
+// Exécute les commandes reçues du broker et publie leur résultat
+fn spawn_mqtt_commands(app: AppHandle, commands: Receiver<MqttCommand>) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        for command in commands {
+            let result = handle_mqtt_command(&state, &command);
+            if let Err(e) = &result {
+                tracing::warn!("MQTT {} for {} failed: {}", command.command, command.device, e);
+            }
+            state.mqtt.ack(&command, &result);
+        }
+    });
+}
+
+// Prix du jour pour les scripts ; sans tarif dynamique (ou hors ligne) la liste reste vide
+fn script_context(state: &AppState) -> ScriptContext {
+    let Ok(config) = state.settings.lock().map(|s| s.tariff.clone()) else { return ScriptContext::default() };
+    if !config.enabled {
+        return ScriptContext::default();
+    }
+    let prices = state
+        .tariff_api_key()
+        .and_then(|key| state.prices.get(&config, key.as_deref(), chrono::Local::now().date_naive()));
+    match prices {
+        Ok(day) => ScriptContext { prices: day.prices, currency: Some(day.currency) },
+        Err(e) => {
+            tracing::debug!("prices unavailable to plugins: {}", e);
+            ScriptContext { prices: Vec::new(), currency: Some(config.currency) }
+        }
+    }
+}
+
+fn apply_plugin_run(app: &AppHandle, state: &AppState, run: PluginRun) {
+    for error in run.errors {
+        let _ = app.emit("plugin-error", &error);
+    }
+    for (plugin, device, action) in run.actions {
+        if let Err(e) = state.ensure_writable().and_then(|_| state.ensure_automation_running()) {
+            tracing::warn!("plugin {} action refused: {}", plugin, e);
+            continue;
+        }
+        let result = device_target(state, Some(&device)).and_then(|target| apply_action(state, CommandSource::Automation, &target, action));
+        if let Err(e) = result {
+            tracing::warn!("plugin {} action failed: {}", plugin, e);
+        }
+    }
+}
+
+fn run_plugins(app: &AppHandle, state: &AppState, target: &DeviceTarget, data: &DashboardData) {
+    let Ok(sample) = serde_json::to_value(data) else { return };
+    if !state.plugins.list().iter().any(|p| p.enabled) {
+        return;
+    }
+    let run = state.plugins.on_sample(&target.id, &sample, &script_context(state));
+    apply_plugin_run(app, state, run);
+}
+
+// Minuteries des scripts (after, every), à la seconde près
+fn spawn_plugin_timers(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+            if state.plugins.timers_due() {
+                let run = state.plugins.tick(&script_context(&state));
+                apply_plugin_run(&app, &state, run);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+fn get_command_stats(state: State<AppState>) -> Vec<MethodStatsEntry> {
+    state.metrics.snapshot()
+}
+
+#[tauri::command]
+fn reset_command_stats(state: State<AppState>) {
+    state.metrics.reset();
+}
+
+// Latence, pertes et nouvelles tentatives vues par le transport : réseau ou appareil en cause
+#[tauri::command]
+fn get_connection_stats(state: State<AppState>, device: Option<String>) -> Result<LinkStats, AppError> {
+    let target = device_target(&state, device.as_deref())?;
+    // Adresse résolue par le transport : pas de requête DNS ici ; encore jamais joint, compteurs vides
+    let links = &state.transport.links;
+    Ok(links.address(&discovery::endpoint(&target.ip, target.port)).map(|ip| links.get(ip)).unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_scheduler_config(state: State<AppState>) -> SchedulerConfig {
+    state.scheduler.config()
+}
+
+#[tauri::command]
+fn set_scheduler_config(state: State<AppState>, max_concurrent: usize, stagger_ms: u64, min_gap_ms: Option<u64>) {
+    let min_gap_ms = min_gap_ms.unwrap_or(state.scheduler.config().min_gap_ms);
+    state.scheduler.configure(max_concurrent, stagger_ms, min_gap_ms);
+}
+
+#[derive(Serialize, Clone)]
+struct ReadOnlyStatus {
+    enabled: bool,
+    locked: bool,
+}
+
+#[tauri::command]
+fn get_read_only(state: State<AppState>) -> Result<ReadOnlyStatus, AppError> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    Ok(ReadOnlyStatus {
+        enabled: state.read_only_locked || settings.read_only,
+        locked: state.read_only_locked,
+    })
+}
+
+// Le quitter rend la main aux automatismes, qui ne demandent pas de PIN : le PIN est exigé s'il existe
+#[tauri::command]
+fn set_read_only(state: State<AppState>, enabled: bool, pin: Option<String>) -> Result<(), AppError> {
+    if state.read_only_locked && !enabled {
+        return Err(AppError::Forbidden("Read-only mode was forced with --read-only and cannot be disabled.".to_string()));
+    }
+    if !enabled {
+        state.check_pin(pin.as_deref())?;
+    }
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.read_only = enabled;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn has_pin(state: State<AppState>) -> Result<bool, AppError> {
+    Ok(state.pin_hash()?.is_some())
+}
+
+// new_pin à None supprime le PIN ; le PIN actuel est exigé s'il existe
+#[tauri::command]
+fn set_pin(state: State<AppState>, current_pin: Option<String>, new_pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(current_pin.as_deref())?;
+    if let Some(new_pin) = &new_pin {
+        pin::validate_new_pin(new_pin)?;
+    }
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.pin_hash = new_pin.as_deref().map(pin::hash_pin);
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_audit_log(state: State<AppState>, limit: Option<usize>) -> Result<AuditLog, AppError> {
+    Ok(state.audit.read(limit)?)
+}
+
+// Commandes envoyées aux appareils (ES.SetMode, consignes passives, requêtes brutes, écritures Modbus...).
+// device : id du registre, traduit en l'adresse enregistrée dans le journal
+#[tauri::command]
+fn get_command_log(state: State<AppState>, query: Option<CommandLogQuery>, device: Option<String>) -> Result<AuditLog, AppError> {
+    let mut query = query.unwrap_or_default();
+    query.validate()?;
+    if let Some(device) = device {
+        query.device = Some(device_target(&state, Some(&device))?.ip);
+    }
+    Ok(state.audit.query(&query)?)
+}
+
+#[tauri::command]
+fn list_plugins(state: State<AppState>) -> Vec<PluginInfo> {
+    state.plugins.list()
+}
+
+#[tauri::command]
+fn reload_plugins(state: State<AppState>) -> Result<Vec<PluginInfo>, AppError> {
+    let enabled = state.settings.lock().map_err(|e| e.to_string())?.enabled_plugins.clone();
+    state.plugins.reload(&enabled)?;
+    Ok(state.plugins.list())
+}
+
+#[tauri::command]
+fn set_plugin_enabled(state: State<AppState>, name: String, enabled: bool, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    state.plugins.set_enabled(&name, enabled)?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.enabled_plugins.retain(|n| n != &name);
+    if enabled {
+        settings.enabled_plugins.push(name);
+    }
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_derived_sensors(state: State<AppState>) -> Result<Vec<DerivedSensor>, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.derived_sensors.clone())
+}
+
+#[tauri::command]
+fn set_derived_sensors(state: State<AppState>, sensors: Vec<DerivedSensor>) -> Result<(), AppError> {
+    derived::validate(&sensors)?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.derived_sensors = sensors;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Tous les appareils du registre en parallèle ; un appareil muet n'empêche pas les autres.
+// Simple lecture : l'historique et les exports de chaque appareil restent au polling
+#[tauri::command]
+async fn get_site_dashboard(app: AppHandle) -> Result<SiteDashboard, AppError> {
+    run_blocking(app, |_, state| {
+        let targets: Vec<(String, Option<String>)> = {
+            let registry = state.devices.lock().map_err(|e| e.to_string())?;
+            registry.list().into_iter().map(|d| (d.id, d.nickname)).collect()
+        };
+        if targets.is_empty() {
+            return Err(AppError::NotConfigured("No device registered".to_string()));
+        }
+        let devices = std::thread::scope(|s| {
+            let handles: Vec<_> = targets
+                .into_iter()
+                .map(|(id, nickname)| {
+                    let handle = s.spawn({
+                        let id = id.clone();
+                        move || collect_dashboard(state, Some(&id), false).map(|(_, data)| data)
+                    });
+                    (id, nickname, handle)
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(id, nickname, handle)| {
+                    let (data, error) = match join_query(handle) {
+                        Ok(data) => (Some(data), None),
+                        Err(e) => (None, Some(e)),
+                    };
+                    SiteDevice { id, nickname, data, error }
+                })
+                .collect()
+        });
+        Ok(site::aggregate(devices))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_capabilities(app: AppHandle, device: Option<String>) -> Result<ModelCapabilities, AppError> {
+    run_blocking(app, move |_, state| {
+        let target = device_target(state, device.as_deref())?;
+        if let (Some(model), Some(_)) = (&target.model, target.firmware) {
+            return Ok(state.capabilities.capabilities(model, target.firmware));
+        }
+        let (model, firmware) = identify(state, Priority::Interactive, &target)?;
+        Ok(state.capabilities.capabilities(&model, firmware))
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_protocol_variants(state: State<AppState>) -> Result<HashMap<String, ProtocolVariant>, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.protocol_variants.clone())
+}
+
+#[tauri::command]
+fn set_protocol_variants(state: State<AppState>, variants: HashMap<String, ProtocolVariant>) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.protocol_variants = variants;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_pv_sources(state: State<AppState>) -> Result<Vec<PvSource>, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.pv_sources.clone())
+}
+
+#[tauri::command]
+fn set_pv_sources(state: State<AppState>, sources: Vec<PvSource>) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.pv_sources = sources;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_grid_meter(state: State<AppState>) -> Result<Option<GridMeter>, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.grid_meter.clone())
+}
+
+// meter absent : retour au CT Marstek
+#[tauri::command]
+fn set_grid_meter(state: State<AppState>, meter: Option<GridMeter>) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.grid_meter = meter;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn test_grid_meter(meter: GridMeter) -> Result<MeterStatus, AppError> {
+    gridmeter::read(&meter).map_err(AppError::IoError)
+}
+
+#[tauri::command]
+fn test_pv_source(source: PvSource) -> Result<PvReading, AppError> {
+    Ok(inverter::read(&source)?)
+}
+
+#[tauri::command]
+fn get_sg_ready(state: State<AppState>) -> Result<(SgReadyConfig, SgReadyStatus), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.sg_ready.clone();
+    Ok((config, state.sg_ready.status()))
+}
+
+#[tauri::command]
+fn set_sg_ready_config(state: State<AppState>, config: SgReadyConfig) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.sg_ready = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_compliance(state: State<AppState>) -> Result<(ComplianceConfig, Vec<Violation>), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.compliance.clone();
+    Ok((config, state.compliance.violations()))
+}
+
+#[tauri::command]
+fn set_compliance_config(state: State<AppState>, config: ComplianceConfig, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.compliance = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_phase_analysis(state: State<AppState>) -> PhaseAnalysis {
+    state.phases.analysis()
+}
+
+#[tauri::command]
+fn reset_phase_analysis(state: State<AppState>) {
+    state.phases.reset();
+}
+
+#[tauri::command]
+fn get_grid_quality(state: State<AppState>) -> GridQualityReport {
+    state.grid_quality.report()
+}
+
+#[tauri::command]
+fn set_grid_quality_config(state: State<AppState>, config: GridQualityConfig) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.grid_quality = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// from/to : secondes Unix, resolution : largeur des intervalles en secondes
+#[tauri::command]
+fn query_history(state: State<AppState>, from: i64, to: i64, resolution: Option<i64>, device: Option<String>) -> Result<Vec<HistoryPoint>, AppError> {
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    Ok(history.query(&target.id, from, to, resolution.unwrap_or(60))?)
+}
+
+// Min, max, moyenne et dernière valeur par heure ou par jour (heure par défaut), tenus à jour à l'enregistrement
+#[tauri::command]
+fn get_history_summaries(
+    state: State<AppState>,
+    from: i64,
+    to: i64,
+    period: Option<SummaryPeriod>,
+    device: Option<String>,
+) -> Result<Vec<MetricSummary>, AppError> {
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    Ok(history.summaries(&target.id, period.unwrap_or_default(), from, to)?)
+}
+
+// Tension, courant et puissance par phase ; vide si le compteur ne les fournit pas
+#[tauri::command]
+fn get_phase_history(state: State<AppState>, from: i64, to: i64, resolution: Option<i64>, device: Option<String>) -> Result<Vec<PhaseHistoryPoint>, AppError> {
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    Ok(history.query_phases(&target.id, from, to, resolution.unwrap_or(60))?)
+}
+
+#[derive(Serialize)]
+struct DeviceEventSync {
+    pages: u32,
+    // Entrées absentes de l'historique local avant cet appel
+    added: usize,
+}
+
+// Parcourt le journal de l'appareil page par page, du plus récent au plus ancien, et l'ajoute à l'historique local.
+// S'arrête à la première page entièrement connue, ou après max_pages (par défaut MAX_EVENT_PAGES).
+#[tauri::command]
+async fn get_device_events(app: AppHandle, device: Option<String>, max_pages: Option<u32>) -> Result<DeviceEventSync, AppError> {
+    run_blocking(app, move |_, state| {
+        let history = state.history.as_ref().ok_or("History database is unavailable")?;
+        let target = device_target(state, device.as_deref())?;
+        let variant = state.protocol_variant(target.model.as_deref())?;
+        let max_pages = max_pages.unwrap_or(MAX_EVENT_PAGES).clamp(1, MAX_EVENT_PAGES);
+        let mut sync = DeviceEventSync { pages: 0, added: 0 };
+        while sync.pages < max_pages {
+            let result = send_command(state, Priority::Interactive, &target.ip, target.port, variant.method(methods::GET_EVENTS), methods::events_params(sync.pages))?;
+            let page: EventPage = serde_json::from_value(variant.normalize(result)).map_err(|e| AppError::ParseError(format!("Marstek.GetEvents: {}", e)))?;
+            sync.pages += 1;
+            let added = history.record_device_events(&target.id, &page.events)?;
+            sync.added += added;
+            if page.events.is_empty() || added == 0 || page.total_pages.is_some_and(|total| sync.pages >= total) {
+                break;
+            }
+        }
+        tracing::info!(device = %target.id, pages = sync.pages, added = sync.added, "device event log synchronised");
+        Ok(sync)
+    })
+    .await
+}
+
+// Journal de l'appareil (synchronisé par get_device_events), alertes et commandes envoyées, dans l'ordre chronologique
+#[tauri::command]
+fn get_timeline(state: State<AppState>, from: i64, to: i64, device: Option<String>) -> Result<Vec<TimelineEntry>, AppError> {
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    let mut entries = history.device_events(&target.id, from, to)?;
+    let unix = |timestamp: &str| chrono::DateTime::parse_from_rfc3339(timestamp).ok().map(|t| t.timestamp()).filter(|t| (from..to).contains(t));
+
+    for event in state.alerts.log(None, Some(&target.id)).history {
+        if let Some(timestamp) = unix(&event.timestamp) {
+            let state = match event.state {
+                alerts::AlertState::Raised => "raised",
+                alerts::AlertState::Cleared => "cleared",
+            };
+            entries.push(TimelineEntry { timestamp, source: TimelineSource::Alert, title: format!("Alert {} {}", event.rule, state), code: None });
+        }
+    }
+    let query = CommandLogQuery { device: Some(target.ip.clone()), ..Default::default() };
+    for entry in state.audit.query(&query)?.entries {
+        if let Some(timestamp) = unix(&entry.timestamp) {
+            let outcome = if entry.error.is_some() { " (failed)" } else { "" };
+            entries.push(TimelineEntry { timestamp, source: TimelineSource::Command, title: format!("{}{}", entry.method, outcome), code: None });
+        }
+    }
+    entries.sort_by_key(|e| e.timestamp);
+    Ok(entries)
+}
+
+// Échantillons des `days` dernières journées locales complètes, la dernière étant aujourd'hui
+fn recent_samples(state: &AppState, days: Option<u32>, device: Option<&str>) -> Result<Vec<(i64, HistorySample)>, AppError> {
+    let days = days.unwrap_or(kpi::DEFAULT_DAYS);
+    if days == 0 || days > kpi::MAX_DAYS {
+        return Err(AppError::InvalidInput(format!("days must be between 1 and {}", kpi::MAX_DAYS)));
+    }
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(state, device)?;
+    let from = chrono::Local::now()
+        .date_naive()
+        .checked_sub_days(chrono::Days::new(u64::from(days - 1)))
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+        .ok_or("Invalid date range")?
+        .timestamp();
+    Ok(history.samples(&target.id, from, chrono::Utc::now().timestamp() + 1)?)
+}
+
+// Journées sans relevés locaux complétées par les bilans importés du cloud
+#[tauri::command]
+fn get_daily_kpis(state: State<AppState>, days: Option<u32>, device: Option<String>) -> Result<Vec<DailyKpis>, AppError> {
+    let computed = kpi::daily(&recent_samples(&state, days, device.as_deref())?);
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    let today = chrono::Local::now().date_naive();
+    let from = today.checked_sub_days(chrono::Days::new(u64::from(days.unwrap_or(kpi::DEFAULT_DAYS) - 1))).ok_or("Invalid date range")?;
+    let imported = history.imported_daily(&target.id, &from.format("%Y-%m-%d").to_string(), &today.format("%Y-%m-%d").to_string())?;
+    Ok(kpi::merge(computed, imported))
+}
+
+#[derive(Serialize)]
+struct CloudImport {
+    // Journées renvoyées par le cloud avec au moins une mesure
+    received: usize,
+    // Journées ajoutées ; les autres étaient déjà importées ou couvertes par les relevés locaux
+    imported: usize,
+    requests: u32,
+}
+
+// Bilans journaliers du compte Marstek, de from (YYYY-MM-DD) à to (par défaut hier), pour les jours antérieurs à marstip
+#[tauri::command]
+async fn import_cloud_history(app: AppHandle, from: String, to: Option<String>, device: Option<String>) -> Result<CloudImport, AppError> {
+    run_blocking(app, move |_, state| {
+        let history = state.history.as_ref().ok_or("History database is unavailable")?;
+        let parse = |date: &str| {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| AppError::InvalidInput(format!("Invalid date: {} (expected YYYY-MM-DD)", date)))
+        };
+        let yesterday = chrono::Local::now().date_naive().pred_opt().ok_or("Invalid date range")?;
+        let from = parse(&from)?;
+        let to = to.as_deref().map(parse).transpose()?.unwrap_or(yesterday).min(yesterday);
+        if from > to {
+            return Err(AppError::InvalidInput("The import range must end before today".to_string()));
+        }
+        let config = state.settings.lock().map_err(|e| e.to_string())?.cloud.clone();
+        if config.email.is_empty() {
+            return Err(AppError::NotConfigured("The Marstek account e-mail is required".to_string()));
+        }
+        let password = state.cloud_password()?.ok_or_else(|| AppError::NotConfigured("The Marstek account password is required".to_string()))?;
+        let target = device_target(state, device.as_deref())?;
+        let (ble_mac, wifi_mac) = {
+            let registry = state.devices.lock().map_err(|e| e.to_string())?;
+            let (_, entry) = registry.get(Some(&target.id))?;
+            (entry.ble_mac.clone(), entry.wifi_mac.clone())
+        };
+        let devices = state.cloud.devices(&config, &password)?;
+        let found = cloud::find(&devices, &[ble_mac.as_deref(), wifi_mac.as_deref()])
+            .ok_or_else(|| AppError::NotConfigured("Device not found in the Marstek cloud account".to_string()))?;
+
+        let mut result = CloudImport { received: 0, imported: 0, requests: 0 };
+        let mut start = from;
+        while start <= to {
+            let end = start.checked_add_days(chrono::Days::new(cloud::DAYS_PER_REQUEST - 1)).unwrap_or(to).min(to);
+            let days: Vec<DailyKpis> = state.cloud.daily(&config, &password, &found.devid, start, end)?.iter().filter_map(|d| d.daily()).collect();
+            result.requests += 1;
+            result.received += days.len();
+            result.imported += history.import_daily(&target.id, &days, "cloud")?;
+            let Some(next) = end.succ_opt() else { break };
+            start = next;
+        }
+        tracing::info!(device = %target.id, received = result.received, imported = result.imported, "Marstek cloud history imported");
+        Ok(result)
+    })
+    .await
+}
+
+// Export CSV ou JSON de l'application Marstek : bilans journaliers et relevés horodatés, pour les jours antérieurs à marstip
+#[tauri::command]
+async fn import_app_export(app: AppHandle, path: Option<String>, device: Option<String>) -> Result<Option<ExportImport>, AppError> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app.dialog().file().add_filter("Marstek export", &["csv", "json", "txt"]).blocking_pick_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    let export = appexport::parse(&std::fs::read_to_string(&path)?)?;
+    run_blocking(app, move |_, state| {
+        let history = state.history.as_ref().ok_or("History database is unavailable")?;
+        let target = device_target(state, device.as_deref())?;
+        let result = ExportImport {
+            days: export.days.len(),
+            samples: export.samples.len(),
+            imported_days: history.import_daily(&target.id, &export.days, "export")?,
+            imported_samples: history.import_samples(&target.id, &export.samples)?,
+            skipped: export.skipped,
+        };
+        tracing::info!(device = %target.id, days = result.imported_days, samples = result.imported_samples, "imported {}", path.display());
+        Ok(Some(result))
+    })
+    .await
+}
+
+// Capacité nominale : annoncée par l'appareil, sinon celle du modèle
+#[tauri::command]
+fn get_health_report(state: State<AppState>, days: Option<u32>, device: Option<String>) -> Result<HealthReport, AppError> {
+    let days = days.unwrap_or(health::DEFAULT_DAYS);
+    let samples = recent_samples(&state, Some(days), device.as_deref())?;
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    let rated = state
+        .latest
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&target.id)
+        .and_then(|snapshot| snapshot.data.battery.rated_capacity)
+        .map(f64::from)
+        .or_else(|| target.model.as_deref().and_then(|model| models::capabilities(model).rated_capacity).map(f64::from))
+        .filter(|rated| *rated > 0.0);
+    let now = chrono::Utc::now().timestamp();
+    let health = history.health(&target.id, now - i64::from(days) * 86400, now + 1)?;
+    Ok(health::report(target.id, rated, &samples, health))
+}
+
+// Tarif dynamique : prix horaires du fournisseur pour chaque jour couvert ; un jour indisponible reste non valorisé
+#[tauri::command]
+async fn get_cost_report(app: AppHandle, days: Option<u32>, device: Option<String>) -> Result<CostReport, AppError> {
+    run_blocking(app, move |_, state| {
+        let samples = recent_samples(state, days, device.as_deref())?;
+        let config = state.settings.lock().map_err(|e| e.to_string())?.costs.clone();
+        let mut spot = HashMap::new();
+        if config.is_dynamic() {
+            let tariff = state.settings.lock().map_err(|e| e.to_string())?.tariff.clone();
+            if !tariff.enabled {
+                return Err(AppError::NotConfigured("Dynamic prices are not enabled".to_string()));
+            }
+            let api_key = state.tariff_api_key()?;
+            let mut dates: Vec<chrono::NaiveDate> = samples
+                .iter()
+                .filter_map(|(ts, _)| chrono::DateTime::from_timestamp(*ts, 0))
+                .map(|t| t.with_timezone(&chrono::Local).date_naive())
+                .collect();
+            dates.dedup();
+            for date in dates {
+                match state.prices.get(&tariff, api_key.as_deref(), date) {
+                    Ok(prices) => spot.extend(prices.prices.iter().filter_map(|p| {
+                        chrono::DateTime::parse_from_rfc3339(&p.start).ok().map(|t| (t.timestamp(), p.price))
+                    })),
+                    Err(e) => tracing::warn!("no prices for {}: {}", date, e),
+                }
+            }
+        }
+        Ok(costs::report(&config, &samples, &spot))
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_cost_config(state: State<AppState>) -> Result<CostConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.costs.clone())
+}
+
+#[tauri::command]
+fn set_cost_config(state: State<AppState>, config: CostConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.costs = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+// Sans chemin, ouvre une boîte d'enregistrement ; renvoie le fichier écrit (None si annulé)
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn export_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    from: i64,
+    to: i64,
+    format: ExportFormat,
+    metrics: Option<Vec<String>>,
+    resolution: Option<i64>,
+    device: Option<String>,
+    path: Option<String>,
+) -> Result<Option<String>, AppError> {
+    let metrics = metrics.unwrap_or_else(|| history::METRICS.iter().map(|m| m.to_string()).collect());
+    history::validate_metrics(&metrics)?;
+    let history = state.history.as_ref().ok_or("History database is unavailable")?;
+    let target = device_target(&state, device.as_deref())?;
+    let points = history.query(&target.id, from, to, resolution.unwrap_or(60))?;
+    let label = target.nickname.clone().unwrap_or_else(|| target.id.clone());
+    let units = state.settings.lock().map_err(|e| e.to_string())?.units.clone();
+    let (content, extension) = match format {
+        ExportFormat::Csv => (history::to_csv(&points, &metrics, &label, &units), "csv"),
+        ExportFormat::Json => (history::to_json(&points, &metrics, &label, &units)?, "json"),
+    };
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("marstip-{}.{}", target.id, extension))
+                .add_filter(extension.to_uppercase(), &[extension])
+                .blocking_save_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+// Statistiques externes pour Home Assistant (recorder.import_statistics), sans passer par MQTT
+fn ha_statistics(state: &AppState, days: Option<u32>, device: Option<&str>) -> Result<Vec<hastats::ExternalStatistic>, AppError> {
+    let target = device_target(state, device)?;
+    let samples = recent_samples(state, days, Some(&target.id))?;
+    let label = target.nickname.clone().unwrap_or_else(|| target.id.clone());
+    Ok(hastats::statistics(&target.id, &label, &samples))
+}
+
+// Sans chemin, ouvre une boîte d'enregistrement ; renvoie le fichier écrit (None si annulé)
+#[tauri::command]
+async fn export_ha_statistics(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    days: Option<u32>,
+    device: Option<String>,
+    path: Option<String>,
+) -> Result<Option<String>, AppError> {
+    let statistics = ha_statistics(&state, days, device.as_deref())?;
+    let content = serde_json::to_string_pretty(&statistics)?;
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name("marstip-ha-statistics.json")
+                .add_filter("JSON", &["json"])
+                .blocking_save_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+#[derive(Serialize)]
+struct CaptureStatus {
+    // Fichier en cours d'écriture ; absent : capture arrêtée
+    path: Option<String>,
+}
+
+// Mode debug : chaque requête unicast et sa réponse brute sont ajoutées au fichier (JSON Lines)
+#[tauri::command]
+fn start_capture(app: AppHandle, state: State<AppState>, path: Option<String>) -> Result<CaptureStatus, AppError> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| e.to_string())?
+            .join(diagnostics::CAPTURES_DIR)
+            .join(format!("capture-{}.jsonl", chrono::Local::now().format("%Y%m%d-%H%M%S"))),
+    };
+    state.transport.traffic.start_capture(&path)?;
+    tracing::info!("capturing UDP traffic to {}", path.display());
+    Ok(CaptureStatus { path: Some(path.display().to_string()) })
+}
+
+#[tauri::command]
+fn stop_capture(state: State<AppState>) -> CaptureStatus {
+    CaptureStatus { path: state.transport.traffic.stop_capture().map(|path| path.display().to_string()) }
+}
+
+#[tauri::command]
+fn get_capture_status(state: State<AppState>) -> CaptureStatus {
+    CaptureStatus { path: state.transport.traffic.capture_path().map(|path| path.display().to_string()) }
+}
+
+// Appareil qui rejoue une capture (fichier du mode debug ou capture.jsonl d'un rapport de diagnostic)
+#[tauri::command]
+fn add_replay_device(state: State<AppState>, path: String, name: Option<String>) -> Result<DeviceEntry, AppError> {
+    let mut registry = state.devices.lock().map_err(|e| e.to_string())?;
+    let id = registry.add_simulated(0, name, Some(path.clone()));
+    let port = match state.simulators.start(&id, 0, Some(&path)) {
+        Ok(port) => port,
+        Err(e) => {
+            registry.remove(&id)?;
+            return Err(e);
+        }
+    };
+    registry.set_address(&id, "127.0.0.1", port);
+    registry.select(&id)?;
+    registry.save(&state.devices_path)?;
+    let (id, config) = registry.get(Some(&id))?;
+    Ok(registry.entry(id, config))
+}
+
+// Archive à joindre à un ticket : réglages sans secrets, appareils, qualité du lien, journaux et derniers échanges UDP
+#[tauri::command]
+async fn create_diagnostics_bundle(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    exchanges: Option<usize>,
+    path: Option<String>,
+) -> Result<Option<String>, AppError> {
+    let mut settings = serde_json::to_value(&*state.settings.lock().map_err(|e| e.to_string())?)?;
+    diagnostics::redact(&mut settings);
+    let devices = state.devices.lock().map_err(|e| e.to_string())?.list();
+    let latest: BTreeMap<String, serde_json::Value> = state
+        .latest
+        .lock()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|(id, snapshot)| {
+            let value = serde_json::json!({
+                "ip": snapshot.ip,
+                "model": snapshot.model,
+                "device": snapshot.data.device,
+                "age_s": snapshot.collected_at.elapsed().as_secs(),
+                "errors": snapshot.data.errors,
+            });
+            (id.clone(), value)
+        })
+        .collect();
+    let links: BTreeMap<String, LinkStats> = state.transport.links.all().into_iter().map(|(ip, stats)| (ip.to_string(), stats)).collect();
+    let connection = serde_json::json!({
+        "links": links,
+        "methods": state.metrics.snapshot(),
+        "scheduler": state.scheduler.config(),
+    });
+    let about = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "generated_at": chrono::Local::now().to_rfc3339(),
+    });
+    let logs: Vec<String> = state.logs.recent(diagnostics::LOG_LINES).iter().map(|line| line.to_string()).collect();
+    let commands = state.audit.read(Some(diagnostics::COMMAND_LOG_ENTRIES))?;
+
+    let mut zip = diagnostics::ZipWriter::default();
+    zip.add("about.json", &serde_json::to_vec_pretty(&about)?)?;
+    zip.add("settings.json", &serde_json::to_vec_pretty(&settings)?)?;
+    zip.add("devices.json", &serde_json::to_vec_pretty(&serde_json::json!({ "registry": devices, "latest": latest }))?)?;
+    zip.add("connection.json", &serde_json::to_vec_pretty(&connection)?)?;
+    let traffic = state.transport.traffic.recent(exchanges.unwrap_or(diagnostics::DEFAULT_EXCHANGES));
+    zip.add("traffic.json", &serde_json::to_vec_pretty(&traffic)?)?;
+    // Même contenu au format des captures, rejouable tel quel
+    let lines = traffic.iter().map(serde_json::to_string).collect::<Result<Vec<_>, _>>()?;
+    zip.add("capture.jsonl", lines.join("\n").as_bytes())?;
+    zip.add("command-log.json", &serde_json::to_vec_pretty(&commands)?)?;
+    zip.add("logs.txt", logs.join("\n").as_bytes())?;
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("marstip-diagnostics-{}.zip", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+                .add_filter("ZIP", &["zip"])
+                .blocking_save_file();
+            let Some(picked) = picked else { return Ok(None) };
+            picked.into_path().map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&path, zip.finish()).map_err(|e| e.to_string())?;
+    Ok(Some(path.display().to_string()))
+}
+
+#[tauri::command]
+fn get_mqtt(state: State<AppState>) -> Result<(MqttConfig, MqttStatus), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.mqtt.clone();
+    config.password = None;
+    Ok((config, state.mqtt.status()))
+}
+
+// password absent : on conserve le mot de passe enregistré
+#[tauri::command]
+fn set_mqtt_config(state: State<AppState>, config: MqttConfig) -> Result<MqttStatus, AppError> {
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        config.password = secrets::update(secrets::MQTT_PASSWORD, config.password.take(), settings.mqtt.password.clone())?;
+        settings.mqtt = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.mqtt.connect(&config, state.mqtt_password()?);
+    Ok(state.mqtt.status())
+}
+
+#[tauri::command]
+fn get_pvoutput(state: State<AppState>) -> Result<(PvOutputConfig, PvOutputStatus), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.pvoutput.clone();
+    config.api_key = None;
+    Ok((config, state.pvoutput.status()))
+}
+
+// api_key absente : on conserve la clé enregistrée
+#[tauri::command]
+fn set_pvoutput_config(state: State<AppState>, config: PvOutputConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        config.api_key = secrets::update(secrets::PVOUTPUT_API_KEY, config.api_key.take(), settings.pvoutput.api_key.clone())?;
+        settings.pvoutput = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.pvoutput.configure(&config, state.pvoutput_api_key()?);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_influx(state: State<AppState>) -> Result<(InfluxConfig, InfluxStatus), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.influx.clone();
+    config.token = None;
+    Ok((config, state.influx.status()))
+}
+
+// token absent : on conserve le jeton enregistré
+#[tauri::command]
+fn set_influx_config(state: State<AppState>, config: InfluxConfig) -> Result<(), AppError> {
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        config.token = secrets::update(secrets::INFLUX_TOKEN, config.token.take(), settings.influx.token.clone())?;
+        settings.influx = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.influx.configure(&config, state.influx_token()?);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_webhooks(state: State<AppState>) -> Result<(WebhookConfig, Vec<WebhookStatus>), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.webhooks.clone();
+    for hook in &mut config.hooks {
+        hook.secret = None;
+    }
+    Ok((config, state.webhooks.status()))
+}
+
+// secret absent : on conserve la clé enregistrée pour ce nom ; un webhook retiré perd la sienne
+#[tauri::command]
+fn set_webhook_config(state: State<AppState>, config: WebhookConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        for hook in &mut config.hooks {
+            let plaintext = settings.webhooks.hooks.iter().find(|h| h.name == hook.name).and_then(|h| h.secret.clone());
+            hook.secret = secrets::update(&secrets::webhook_secret(&hook.name), hook.secret.take(), plaintext)?;
+        }
+        for old in settings.webhooks.hooks.iter().filter(|old| !config.hooks.iter().any(|h| h.name == old.name)) {
+            secrets::delete(&secrets::webhook_secret(&old.name));
+        }
+        settings.webhooks = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.webhooks.configure(&config, state.webhook_secrets()?);
+    Ok(())
+}
+
+// Envoie un événement "test" au webhook enregistré, sans nouvelle tentative
+#[tauri::command]
+async fn test_webhook(app: AppHandle, name: String) -> Result<(), AppError> {
+    run_blocking(app, move |_, state| state.webhooks.test(&name)).await
+}
+
+#[tauri::command]
+fn get_tariff(state: State<AppState>) -> Result<TariffConfig, AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.tariff.clone();
+    config.api_key = None;
+    Ok(config)
+}
+
+// api_key absente : on conserve la clé enregistrée
+#[tauri::command]
+fn set_tariff_config(state: State<AppState>, config: TariffConfig) -> Result<(), AppError> {
+    let mut config = config;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    config.api_key = secrets::update(secrets::TARIFF_API_KEY, config.api_key.take(), settings.tariff.api_key.clone())?;
+    settings.tariff = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// "YYYY-MM-DD" en heure locale ; absent : aujourd'hui
+fn parse_day(day: Option<&str>) -> Result<chrono::NaiveDate, AppError> {
+    match day {
+        Some(day) => chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map_err(|_| AppError::InvalidInput(format!("Invalid day: {} (expected YYYY-MM-DD)", day))),
+        None => Ok(chrono::Local::now().date_naive()),
+    }
+}
+
+#[tauri::command]
+async fn get_prices(app: AppHandle, day: Option<String>) -> Result<DayPrices, AppError> {
+    run_blocking(app, move |_, state| {
+        let config = state.settings.lock().map_err(|e| e.to_string())?.tariff.clone();
+        if !config.enabled {
+            return Err(AppError::NotConfigured("Dynamic prices are not enabled".to_string()));
+        }
+        state.prices.get(&config, state.tariff_api_key()?.as_deref(), parse_day(day.as_deref())?)
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_forecast_config(state: State<AppState>) -> Result<ForecastConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.forecast.clone())
+}
+
+#[tauri::command]
+fn set_forecast_config(state: State<AppState>, config: ForecastConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.forecast = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Prévision mise en cache une heure ; refresh force un nouvel appel au fournisseur
+#[tauri::command]
+async fn get_solar_forecast(app: AppHandle, refresh: Option<bool>) -> Result<SolarForecast, AppError> {
+    run_blocking(app, move |_, state| {
+        let config = state.settings.lock().map_err(|e| e.to_string())?.forecast.clone();
+        if !config.enabled {
+            return Err(AppError::NotConfigured("Solar forecast is not enabled".to_string()));
+        }
+        state.forecast.get(&config, refresh.unwrap_or(false))
+    })
+    .await
+}
+
+// Le jeton est renvoyé en clair : l'utilisateur doit pouvoir le copier dans ses intégrations
+#[tauri::command]
+fn get_api_server(state: State<AppState>) -> Result<(ApiServerConfig, ApiServerStatus), AppError> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?.api_server.clone();
+    let tokens = state.api_tokens()?;
+    config.token = tokens.control;
+    config.read_token = tokens.read;
+    Ok((config, state.api_server.status()))
+}
+
+// token absent : on conserve le jeton enregistré, ou on en génère un s'il est exigé ; read_token absent : conservé, vide : supprimé
+#[tauri::command]
+fn set_api_server_config(app: AppHandle, state: State<AppState>, config: ApiServerConfig, pin: Option<String>) -> Result<(), AppError> {
+    state.check_pin(pin.as_deref())?;
+    config.validate()?;
+    let mut config = config;
+    {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        let mut token = config.token.take();
+        if token.is_none() && config.require_token && secrets::lookup(secrets::API_TOKEN, settings.api_server.token.as_ref())?.is_none() {
+            token = Some(netaccess::generate_token());
+        }
+        config.token = secrets::update(secrets::API_TOKEN, token, settings.api_server.token.clone())?;
+        config.read_token = secrets::update(secrets::API_READ_TOKEN, config.read_token.take(), settings.api_server.read_token.clone())?;
+        settings.api_server = config.clone();
+        settings::save(&state.settings_path, &settings)?;
+    }
+    state.api_server.restart(&app, &config, state.api_tokens()?);
+    Ok(())
+}
+
+// Invalide l'ancien jeton de cette portée (pilotage par défaut) : les intégrations doivent être mises à jour
+#[tauri::command]
+fn regenerate_api_token(app: AppHandle, state: State<AppState>, scope: Option<ApiScope>, pin: Option<String>) -> Result<String, AppError> {
+    state.check_pin(pin.as_deref())?;
+    let token = netaccess::generate_token();
+    let config = {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        let api = &mut settings.api_server;
+        match scope.unwrap_or_default() {
+            ApiScope::Control => api.token = secrets::update(secrets::API_TOKEN, Some(token.clone()), api.token.clone())?,
+            ApiScope::Read => api.read_token = secrets::update(secrets::API_READ_TOKEN, Some(token.clone()), api.read_token.clone())?,
+        }
+        settings::save(&state.settings_path, &settings)?;
+        settings.api_server.clone()
+    };
+    state.api_server.restart(&app, &config, state.api_tokens()?);
+    Ok(token)
+}
+
+#[tauri::command]
+fn get_polling(state: State<AppState>) -> PollingConfig {
+    state.poller.config()
+}
+
+#[tauri::command]
+fn start_polling(state: State<AppState>, interval_ms: Option<u64>) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    let interval_ms = interval_ms.unwrap_or(settings.polling.interval_ms);
+    if interval_ms < polling::MIN_INTERVAL_MS {
+        return Err(AppError::InvalidInput(format!("Polling interval must be at least {} ms", polling::MIN_INTERVAL_MS)));
+    }
+    settings.polling = PollingConfig { enabled: true, interval_ms, ..settings.polling.clone() };
+    state.poller.configure(settings.polling.clone());
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn set_polling_config(state: State<AppState>, config: PollingConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    state.poller.configure(config.clone());
+    settings.polling = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Cadence en cours et sa raison (fixe, manuelle, activité, repos)
+#[tauri::command]
+fn get_poll_rate(state: State<AppState>) -> PollRate {
+    state.poller.rate()
+}
+
+// Forçage temporaire de la cadence ; interval_ms absent : retour à la cadence automatique
+#[tauri::command]
+fn set_poll_rate(state: State<AppState>, interval_ms: Option<u64>) -> Result<PollRate, AppError> {
+    state.poller.set_manual_rate(interval_ms)
+}
+
+#[tauri::command]
+fn stop_polling(state: State<AppState>) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.polling.enabled = false;
+    state.poller.configure(settings.polling.clone());
+    settings::save(&state.settings_path, &settings)
+}
+
+// Interroge l'appareil courant en continu et pousse le résultat au frontend
+// data absent : le poll de l'appareil courant a échoué
+fn evaluate_alerts(app: &AppHandle, state: &AppState, data: Option<&DashboardData>) -> Result<(), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.alerts.clone();
+    // Aucun appareil configuré : rien à surveiller
+    let Ok(target) = device_target(state, None) else { return Ok(()) };
+    let sample = data.map(|data| AlertSample {
+        bat_temp: data.battery.bat_temp,
+        heater_on: data.battery.heater_on,
+        soc: data.battery.soc.or(data.energy.bat_soc),
+        grid_power: data.meter.as_ref().and_then(|m| m.total_power),
+        faults: data.faults.as_ref().map(|faults| faults.iter().map(|f| format!("{} {}", f.code, f.description)).collect()),
+    });
+    let notifications = state.settings.lock().map_err(|e| e.to_string())?.notifications.clone();
+    for event in state.alerts.observe(&config, &target.id, sample.as_ref()) {
+        if notifications.wants(&event) {
+            show_notification(app, &notify::alert_title(&event), &notify::alert_body(&event));
+        }
+        state.webhooks.alert(&event);
+        let _ = app.emit("alert", &event);
+    }
+    Ok(())
+}
+
+fn watch_mode(app: &AppHandle, state: &AppState, data: &DashboardData) -> Result<(), AppError> {
+    let Some(mode) = data.mode.mode.as_deref() else { return Ok(()) };
+    let target = device_target(state, None)?;
+    let changed = state.mode_watch.observe(&target.id, mode);
+    if let Some((previous, mode)) = &changed {
+        tracing::info!(device = %target.id, "mode changed outside the app: {} -> {}", previous, mode);
+        let notifications = state.settings.lock().map_err(|e| e.to_string())?.notifications.clone();
+        if notifications.enabled && notifications.mode_changes {
+            let name = target.nickname.as_deref().unwrap_or(&target.id);
+            show_notification(app, "Battery mode changed", &format!("{}: {} -> {}", name, previous, mode));
+        }
+    }
+    restore_desired_mode(app, state, &target, mode, changed.is_some())
+}
+
+// Mode voulu perdu au redémarrage de l'appareil (coupure, mise à jour) : il est réécrit
+fn restore_desired_mode(app: &AppHandle, state: &AppState, target: &DeviceTarget, mode: &str, unexpected: bool) -> Result<(), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.mode_watchdog.clone();
+    let (desired, slots) = {
+        let registry = state.devices.lock().map_err(|e| e.to_string())?;
+        let (_, device) = registry.get(Some(&target.id))?;
+        (device.desired_mode.clone(), device.manual_slots.len())
+    };
+    // Plage Manual isolée (set_mode) : sans plages enregistrées, rien à réécrire
+    let desired = desired.filter(|mode| mode != "Manual" || slots > 0);
+    let Some(reason) = state.mode_watchdog.check(&config, &target.id, mode, desired.as_deref(), unexpected) else { return Ok(()) };
+    // Consigne Passive en cours : c'est sa boucle de renouvellement qui la remet en place
+    if state.passive.session().is_some_and(|s| s.device == target.id) || state.ensure_writable().is_err() {
+        return Ok(());
+    }
+    let desired = desired.unwrap_or_default();
+    tracing::info!(device = %target.id, "device came back in {} mode, restoring {}", mode, desired);
+    restore_mode(state, CommandSource::Automation, target, Some(&desired))?;
+    let restore = ModeRestore {
+        device: target.id.clone(),
+        from: mode.to_string(),
+        to: desired,
+        reason,
+        timestamp: chrono::Local::now().to_rfc3339(),
+    };
+    let _ = app.emit("mode-restored", &restore);
+    Ok(())
+}
+
+#[derive(Serialize, Clone)]
+struct ClockDriftEvent {
+    id: String,
+    offset_s: i64,
+    corrected: bool,
+}
+
+fn read_device_time(state: &AppState, priority: Priority, target: &DeviceTarget) -> Result<DeviceTime, AppError> {
+    clock::parse(&send_command(state, priority, &target.ip, target.port, methods::GET_TIME, methods::status_params())?)
+}
+
+fn write_device_time(state: &AppState, source: CommandSource, target: &DeviceTarget) -> Result<(), AppError> {
+    let params = clock::set_params();
+    let outcome = send_command(state, Priority::Interactive, &target.ip, target.port, methods::SET_TIME, params.clone());
+    if let Err(e) = state.audit.record(source, &target.ip, methods::SET_TIME, &params, &outcome) {
+        tracing::warn!("failed to write audit entry: {}", e);
+    }
+    if !client::accepted(&outcome?) {
+        return Err(AppError::DeviceRejected { code: 0, message: "Device time was not accepted".to_string() });
+    }
+    Ok(())
+}
+
+// Au rythme de clock.check_interval_s, pas à chaque relevé : l'horloge dérive de quelques secondes par jour
+fn check_clock_drift(app: &AppHandle, state: &AppState) -> Result<(), AppError> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?.clock.clone();
+    let target = device_target(state, None)?;
+    if config.check_interval_s == 0 || !state.clock_watch.due(&target.id, Duration::from_secs(config.check_interval_s)) {
+        return Ok(());
+    }
+    let time = read_device_time(state, Priority::Background, &target)?;
+    if time.offset_s.unsigned_abs() <= config.max_drift_s {
+        return Ok(());
+    }
+    tracing::warn!(device = %target.id, offset_s = time.offset_s, "device clock drift exceeds {} s", config.max_drift_s);
+    let corrected = config.auto_correct && state.ensure_writable().is_ok() && {
+        match write_device_time(state, CommandSource::Automation, &target) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!(device = %target.id, "failed to correct device clock: {}", e);
+                false
+            }
+        }
+    };
+    let _ = app.emit("clock-drift", &ClockDriftEvent { id: target.id, offset_s: time.offset_s, corrected });
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_device_time(app: AppHandle, device: Option<String>) -> Result<DeviceTime, AppError> {
+    run_blocking(app, move |_, state| read_device_time(state, Priority::Interactive, &device_target(state, device.as_deref())?)).await
+}
+
+// Met l'appareil à l'heure de ce poste ; renvoie l'heure relue après coup
+#[tauri::command]
+async fn sync_device_time(app: AppHandle, pin: Option<String>, device: Option<String>) -> Result<DeviceTime, AppError> {
+    run_blocking(app, move |_, state| {
+        state.ensure_writable()?;
+        state.check_pin(pin.as_deref())?;
+        let target = device_target(state, device.as_deref())?;
+        write_device_time(state, CommandSource::Ui, &target)?;
+        read_device_time(state, Priority::Interactive, &target)
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_change_config(state: State<AppState>) -> Result<ChangeConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.changes.clone())
+}
+
+#[tauri::command]
+fn set_change_config(state: State<AppState>, config: ChangeConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.changes = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_clock_config(state: State<AppState>) -> Result<ClockConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.clock.clone())
+}
+
+#[tauri::command]
+fn set_clock_config(state: State<AppState>, config: ClockConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.clock = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+fn show_notification(app: &AppHandle, title: &str, body: &str) {
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        tracing::warn!("failed to show notification: {}", e);
+    }
+}
+
+#[tauri::command]
+fn get_notification_config(state: State<AppState>) -> Result<NotificationConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.notifications.clone())
+}
+
+#[tauri::command]
+fn set_notification_config(state: State<AppState>, config: NotificationConfig) -> Result<(), AppError> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.notifications = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_mode_watchdog_config(state: State<AppState>) -> Result<ModeWatchdogConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.mode_watchdog.clone())
+}
+
+#[tauri::command]
+fn set_mode_watchdog_config(state: State<AppState>, config: ModeWatchdogConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.mode_watchdog = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+#[tauri::command]
+fn get_log_config(state: State<AppState>) -> Result<LogConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.logging.clone())
+}
+
+#[tauri::command]
+fn set_log_config(state: State<AppState>, config: LogConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    state.logs.configure(&config, None);
+    settings.logging = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Journal en direct : l'UI repasse le dernier seq reçu pour n'obtenir que les nouvelles lignes
+#[tauri::command]
+fn get_recent_logs(state: State<AppState>, after: Option<u64>, limit: Option<usize>) -> Vec<LogLine> {
+    state.logs.since(after.unwrap_or(0), limit.unwrap_or(500))
+}
+
+#[tauri::command]
+fn get_alert_config(state: State<AppState>) -> Result<AlertConfig, AppError> {
+    Ok(state.settings.lock().map_err(|e| e.to_string())?.alerts.clone())
+}
+
+#[tauri::command]
+fn set_alert_config(state: State<AppState>, config: AlertConfig) -> Result<(), AppError> {
+    config.validate()?;
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.alerts = config;
+    settings::save(&state.settings_path, &settings)
+}
+
+// Alertes en cours et historique, plus récentes en premier
+#[tauri::command]
+fn get_alerts(state: State<AppState>, limit: Option<usize>, device: Option<String>) -> AlertLog {
+    state.alerts.log(limit, device.as_deref())
+}
+
+#[tauri::command]
+fn clear_alert_history(state: State<AppState>) -> Result<(), AppError> {
+    state.alerts.clear_history()
+}
+
+// La consigne Passive d'un automatisme n'est plus renouvelée pendant la pause
+fn pause_automation(state: &AppState, paused: bool) -> Result<(), AppError> {
+    let was_paused = state.automation_paused.swap(paused, Ordering::Relaxed);
+    if paused && !was_paused && state.ensure_writable().is_ok() {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+        let controlled = settings.regulation.enabled
+            || settings.peak_shaving.enabled
+            || (settings.price_rules.enabled && settings.price_rules.executor == PlanExecutor::Passive);
+        if controlled {
+            release_passive(state, CommandSource::Automation)?;
+            state.automation.set_applied(None);
+        }
+        if settings.export_limit.enabled {
+            release_export_limit(state, &settings.export_limit)?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_automation_paused(state: State<AppState>) -> bool {
+    state.automation_paused.load(Ordering::Relaxed)
+}
+
+#[tauri::command]
+async fn set_automation_paused(app: AppHandle, paused: bool, pin: Option<String>) -> Result<(), AppError> {
+    run_blocking(app, move |app, state| {
+        state.check_pin(pin.as_deref())?;
+        pause_automation(state, paused)?;
+        tray::set_paused(app, paused);
+        Ok(())
+    })
+    .await
+}
+
+// Cadence de surveillance de settings.json (modifications à la main ou par script)
+const SETTINGS_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// Applique un settings.json modifié hors de l'app ; renvoie les sections changées
+fn reload_settings(app: &AppHandle, state: &AppState) -> Result<Vec<&'static str>, AppError> {
+    let mut new = settings::read(&state.settings_path)?;
+    // Un secret saisi en clair n'apparaît plus dans la comparaison une fois migré
+    let secrets_migrated = secrets::migrate_plaintext(&mut new);
+    if secrets_migrated {
+        settings::save(&state.settings_path, &new)?;
+    }
+    new.polling.validate()?;
+    new.connection.validate()?;
+    new.discovery.validate()?;
+    new.heartbeat.validate()?;
+    new.regulation.validate()?;
+    new.peak_shaving.validate()?;
+    new.export_limit.validate()?;
+    new.price_rules.validate()?;
+    new.forecast.validate()?;
+    new.soc_limits.validate()?;
+    new.alerts.validate()?;
+    new.firmware.validate()?;
+    new.cloud.validate()?;
+    new.clock.validate()?;
+    new.changes.validate()?;
+    new.costs.validate()?;
+    new.pvoutput.validate()?;
+    new.webhooks.validate()?;
+    new.solar_schedule.validate()?;
+    new.exception_calendar.validate()?;
+    new.ev_charger.validate()?;
+    new.mode_watchdog.validate()?;
+    new.api_server.validate()?;
+    new.logging.validate()?;
+    new.recovery.validate()?;
+    new.units.validate()?;
+    new.retention.validate()?;
+    check_slot_writers(&new.solar_schedule, &new.price_rules)?;
+
+    let old = std::mem::replace(&mut *state.settings.lock().map_err(|e| e.to_string())?, new.clone());
+    let mut changed = Vec::new();
+    macro_rules! section {
+        ($field:ident) => {
+            if serde_json::to_value(&old.$field)? != serde_json::to_value(&new.$field)? {
+                changed.push(stringify!($field));
+                true
+            } else {
+                false
+            }
+        };
+    }
+    // Relus à chaque usage : rien d'autre à faire que de les signaler
+    section!(connection);
+    section!(read_only);
+    section!(pin_hash);
+    section!(derived_sensors);
+    section!(protocol_variants);
+    section!(pv_sources);
+    section!(grid_meter);
+    section!(sg_ready);
+    section!(compliance);
+    section!(grid_quality);
+    section!(discovery);
+    section!(heartbeat);
+    section!(recovery);
+    section!(tariff);
+    section!(forecast);
+    section!(soc_limits);
+    section!(alerts);
+    section!(notifications);
+    section!(mode_watchdog);
+    section!(firmware);
+    section!(clock);
+    section!(changes);
+    section!(costs);
+    section!(retention);
+    if section!(cloud) || secrets_migrated {
+        state.cloud.reset();
+    }
+    // Appliqués aux composants en cours d'exécution
+    if section!(enabled_plugins) {
+        state.plugins.reload(&new.enabled_plugins)?;
+    }
+    if section!(polling) {
+        state.poller.configure(new.polling.clone());
+    }
+    if section!(mqtt) || secrets_migrated {
+        state.mqtt.connect(&new.mqtt, state.mqtt_password()?);
+    }
+    if section!(units) {
+        state.mqtt.set_units(&new.units);
+    }
+    if section!(logging) {
+        state.logs.configure(&new.logging, None);
+    }
+    if section!(api_server) || secrets_migrated {
+        state.api_server.restart(app, &new.api_server, state.api_tokens()?);
+    }
+    if section!(influx) || secrets_migrated {
+        state.influx.configure(&new.influx, state.influx_token()?);
+    }
+    if section!(pvoutput) || secrets_migrated {
+        state.pvoutput.configure(&new.pvoutput, state.pvoutput_api_key()?);
+    }
+    if section!(ev_charger) || secrets_migrated {
+        configure_ev_charger(state, &new.ev_charger)?;
+    }
+    if section!(webhooks) || secrets_migrated {
+        state.webhooks.configure(&new.webhooks, state.webhook_secrets()?);
+    }
+    if section!(price_rules) {
+        state.automation.reset();
+    }
+    if section!(solar_schedule) {
+        state.solar.reset();
+    }
+    if section!(exception_calendar) {
+        state.automation.reset();
+        state.solar.reset();
+    }
+    if section!(regulation) {
+        state.regulator.configure(new.regulation.clone());
+    }
+    if section!(peak_shaving) {
+        state.peak_shaver.configure(new.peak_shaving.clone());
+    }
+    if section!(export_limit) {
+        state.export_limiter.configure(new.export_limit.clone());
+        if old.export_limit.enabled && (!new.export_limit.enabled || old.export_limit.executor != new.export_limit.executor) {
+            release_export_limit(state, &old.export_limit)?;
+        }
+    }
+    // Même comportement que la désactivation depuis l'interface
+    let stopped = (old.regulation.enabled && !new.regulation.enabled) || (old.peak_shaving.enabled && !new.peak_shaving.enabled);
+    if stopped && state.ensure_writable().is_ok() {
+        release_passive(state, CommandSource::Automation)?;
+    }
+    Ok(changed)
+}
+
+fn spawn_settings_watcher(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        let mut last = settings::modified(&state.settings_path);
+        loop {
+            std::thread::sleep(SETTINGS_WATCH_INTERVAL);
+            let modified = settings::modified(&state.settings_path);
+            if modified == last {
+                continue;
+            }
+            last = modified;
+            // Nos propres écritures relisent un contenu identique : aucune section changée
+            match reload_settings(&app, &state) {
+                Ok(changed) if changed.is_empty() => {}
+                Ok(changed) => {
+                    tracing::info!("settings reloaded from disk: {}", changed.join(", "));
+                    let _ = app.emit("settings-changed", &changed);
+                }
+                // Fichier en cours d'écriture ou invalide : la configuration en cours est conservée
+                Err(e) => tracing::warn!("ignoring settings file change: {}", e),
+            }
+        }
+    });
+}
+
+// Argument passé par le lancement à l'ouverture de session : fenêtre jamais affichée
+const MINIMIZED_ARG: &str = "--minimized";
+
+// Cœur sans interface détecté au lancement : l'interface l'indique et renvoie vers son API
+#[tauri::command]
+fn get_core_status(state: State<AppState>) -> CoreStatus {
+    state.core.clone()
+}
+
+#[tauri::command]
+fn get_autostart(app: AppHandle) -> Result<bool, AppError> {
+    app.autolaunch().is_enabled().map_err(|e| AppError::Internal(e.to_string()))
+}
+
+#[tauri::command]
+fn set_autostart(app: AppHandle, enabled: bool) -> Result<(), AppError> {
+    let autolaunch = app.autolaunch();
+    match enabled {
+        true => autolaunch.enable(),
+        false => autolaunch.disable(),
+    }
+    .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// Alimente le polling adaptatif : flux de puissance et boucles de régulation actives
+fn observe_activity(state: &AppState, data: &DashboardData) {
+    let control_active = match state.settings.lock() {
+        Ok(settings) => settings.regulation.enabled || settings.peak_shaving.enabled || settings.export_limit.enabled,
+        Err(_) => false,
+    };
+    let energy = &data.energy;
+    let mut powers = vec![energy.pv_power, energy.ongrid_power, energy.offgrid_power, energy.bat_power];
+    powers.push(data.meter.as_ref().and_then(|meter| meter.total_power));
+    state.poller.observe(powers, control_active);
+}
+
+fn spawn_polling(app: AppHandle) {
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        loop {
+            state.poller.wait_next();
+            if state.poller.is_paused() {
+                continue;
+            }
+            let result = collect_dashboard(&state, None, true).and_then(|(target, data)| {
+                ingest_dashboard(&app, &state, &target, &data)?;
+                Ok(data)
+            });
+            match &result {
+                Ok(data) => {
+                    observe_activity(&state, data);
+                    let _ = app.emit("dashboard-update", data);
+                    tray::update(&app, Some(data));
+                    if let Err(e) = watch_mode(&app, &state, data) {
+                        tracing::warn!("mode watch failed: {}", e);
+                    }
+                    if let Err(e) = check_clock_drift(&app, &state) {
+                        tracing::warn!("clock drift check failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    let _ = app.emit("dashboard-error", e);
+                    tray::update(&app, None);
+                    if let Ok(target) = device_target(&state, None) {
+                        state.mode_watchdog.unreachable(&target.id);
+                    }
+                }
+            }
+            if let Err(e) = evaluate_alerts(&app, &state, result.as_ref().ok()) {
+                tracing::warn!("alert evaluation failed: {}", e);
+            }
+            let recovery = state.settings.lock().map(|s| s.recovery.clone()).unwrap_or_default();
+            if let (Some(reason), true) = (state.network.record_poll(result.is_ok(), &recovery), recovery.enabled) {
+                if let Err(e) = recover_network(&app, &state, reason) {
+                    tracing::warn!("network recovery failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let logs = logs::install();
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_store::Builder::default().build())
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_autostart::init(MacosLauncher::LaunchAgent, Some(vec![MINIMIZED_ARG])))
+        .setup(|app| {
+            secrets::load_settings_key(&app.path().app_data_dir()?);
+            let settings_path = settings::settings_path(app.handle())?;
+            // Fichier invalide : valeurs par défaut en lecture seule, settings::save refuse de l'écraser
+            let mut settings = settings::load(&settings_path).unwrap_or_else(|e| {
+                tracing::error!("settings not loaded, starting read-only: {}", e);
+                Settings { read_only: true, ..Settings::default() }
+            });
+            if secrets::migrate_plaintext(&mut settings) {
+                settings::save(&settings_path, &settings)?;
+            }
+            let read_only_locked = std::env::args().any(|arg| arg == "--read-only");
+            let minimized = std::env::args().any(|arg| arg == MINIMIZED_ARG);
+            let headless = service::headless();
+            let data_dir = app.path().app_data_dir()?;
+            // Un cœur sans interface tourne déjà : cette instance n'en lance pas un second
+            let core = match headless {
+                true => {
+                    service::claim(&data_dir)?;
+                    None
+                }
+                false => service::running_core(&data_dir),
+            };
+            let attached = core.is_some();
+            logs.configure(&settings.logging, Some(&data_dir.join(logs::LOG_DIR)));
+            let plugins = PluginManager::new(data_dir.join(plugins::PLUGINS_DIR));
+            if let Err(e) = plugins.reload(&settings.enabled_plugins) {
+                tracing::warn!("failed to load plugins: {}", e);
+            }
+
+            let devices_path = data_dir.join(devices::DEVICES_FILE);
+            let devices = DeviceRegistry::load(&devices_path, &data_dir.join(devices::LEGACY_DEVICE_FILE));
+
+            let poller = Poller::new(settings.polling.clone());
+            let regulator = Regulator::new(settings.regulation.clone());
+            let peak_shaver = PeakShaver::new(settings.peak_shaving.clone());
+            let export_limiter = ExportLimiter::new(settings.export_limit.clone());
+            let (mqtt_commands, mqtt_receiver) = mpsc::channel();
+            let history = HistoryStore::open(&data_dir.join(history::HISTORY_FILE))
+                .map_err(|e| tracing::warn!("history database unavailable: {}", e))
+                .ok();
+            app.manage(AppState {
+                devices: Mutex::new(devices),
+                metrics: CommandMetrics::default(),
+                scheduler: RequestScheduler::default(),
+                settings: Mutex::new(settings),
+                settings_path,
+                devices_path,
+                read_only_locked,
+                pin_attempts: PinAttempts::default(),
+                audit: AuditTrail::open(data_dir.join(audit::AUDIT_FILE)),
+                plugins,
+                sg_ready: SgReadyController::default(),
+                compliance: ComplianceMonitor::default(),
+                phases: PhaseAnalyzer::default(),
+                grid_quality: GridQualityMonitor::default(),
+                poller,
+                history,
+                mqtt: MqttBridge::new(mqtt_commands),
+                api_server: ApiServer::default(),
+                influx: InfluxExporter::default(),
+                pvoutput: PvOutputUploader::open(data_dir.join(pvoutput::QUEUE_FILE)),
+                latest: Mutex::new(BTreeMap::new()),
+                transport: UdpTransport::default(),
+                reachability: Reachability::default(),
+                passive: PassiveKeeper::default(),
+                regulator,
+                prices: PriceCache::open(data_dir.join(tariff::PRICES_FILE)),
+                automation: PriceAutomation::default(),
+                forecast: ForecastCache::open(data_dir.join(forecast::FORECAST_FILE)),
+                peak_shaver,
+                export_limiter,
+                alerts: AlertEngine::open(data_dir.join(alerts::ALERTS_FILE)),
+                mode_watch: ModeWatch::default(),
+                mode_watchdog: ModeWatchdog::default(),
+                automation_paused: AtomicBool::new(attached),
+                modbus_control: Mutex::new(HashSet::new()),
+                ble: BleTransport::default(),
+                confirmations: Confirmations::default(),
+                ota: OtaTracker::default(),
+                calibration: Calibration::default(),
+                cloud: CloudClient::default(),
+                capabilities: CapabilityMap::default(),
+                clock_watch: DriftWatch::default(),
+                changes: ChangeDetector::default(),
+                logs,
+                simulators: Simulators::default(),
+                webhooks: WebhookDispatcher::default(),
+                scenes: SceneStore::open(data_dir.join(scenes::SCENES_FILE)),
+                solar: SolarScheduler::default(),
+                ev_charger: EvChargerMonitor::default(),
+                network: NetworkWatch::default(),
+                core: CoreStatus { headless, attached, core },
+            });
+            let state = app.state::<AppState>();
+            start_simulators(&state)?;
+            // Sans tray (bureau Linux sans zone de notification), la fenêtre se ferme normalement
+            if !headless {
+                if let Err(e) = tray::build(app.handle(), false) {
+                    tracing::warn!("system tray unavailable: {}", e);
+                }
+            }
+            // La fenêtre est créée cachée ; au démarrage réduit ou sans interface, le polling n'attend pas le dashboard
+            if headless || (minimized && tray::is_active(app.handle())) {
+                let polling = state.poller.config();
+                if !polling.enabled {
+                    state.poller.configure(PollingConfig { enabled: true, ..polling });
+                }
+            } else {
+                tray::show_window(app.handle());
+            }
+            // Rattachée à un cœur : il garde seul le polling de fond, les exports et les automatismes,
+            // et applique lui-même les modifications de settings.json
+            if let Some(core) = &state.core.core {
+                tracing::info!(pid = core.pid, "headless core running: this window only serves interactive commands");
+                return Ok(());
+            }
+            let (mqtt_config, units) = {
+                let settings = state.settings.lock().map_err(|e| e.to_string())?;
+                (settings.mqtt.clone(), settings.units.clone())
+            };
+            state.mqtt.set_units(&units);
+            state.mqtt.connect(&mqtt_config, state.mqtt_password()?);
+            let api_config = state.settings.lock().map_err(|e| e.to_string())?.api_server.clone();
+            // Serveur activé avant l'ajout des jetons : on en crée un plutôt que de tout refuser
+            if api_config.enabled && api_config.require_token && state.api_token()?.is_none() {
+                let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+                settings.api_server.token = secrets::update(secrets::API_TOKEN, Some(netaccess::generate_token()), settings.api_server.token.clone())?;
+                settings::save(&state.settings_path, &settings)?;
+            }
+            state.api_server.restart(app.handle(), &api_config, state.api_tokens()?);
+            let influx_config = state.settings.lock().map_err(|e| e.to_string())?.influx.clone();
+            state.influx.configure(&influx_config, state.influx_token()?);
+            let pvoutput_config = state.settings.lock().map_err(|e| e.to_string())?.pvoutput.clone();
+            state.pvoutput.configure(&pvoutput_config, state.pvoutput_api_key()?);
+            let ev_config = state.settings.lock().map_err(|e| e.to_string())?.ev_charger.clone();
+            configure_ev_charger(&state, &ev_config)?;
+            let webhook_config = state.settings.lock().map_err(|e| e.to_string())?.webhooks.clone();
+            state.webhooks.configure(&webhook_config, state.webhook_secrets()?);
+            spawn_polling(app.handle().clone());
+            spawn_rediscovery(app.handle().clone());
+            spawn_heartbeat(app.handle().clone());
+            spawn_network_watch(app.handle().clone());
+            spawn_history_maintenance(app.handle().clone());
+            spawn_passive_keepalive(app.handle().clone());
+            spawn_regulation(app.handle().clone());
+            spawn_ev_charger(app.handle().clone());
+            spawn_peak_shaving(app.handle().clone());
+            spawn_export_limit(app.handle().clone());
+            spawn_price_automation(app.handle().clone());
+            spawn_solar_schedule(app.handle().clone());
+            spawn_plugin_timers(app.handle().clone());
+            spawn_mqtt_commands(app.handle().clone(), mqtt_receiver);
+            spawn_settings_watcher(app.handle().clone());
+            if headless {
+                let info = service::announce(&data_dir, &api_config)?;
+                tracing::info!(pid = info.pid, api = ?info.api_address, "running headless");
+            }
+            Ok(())
+        })
+        // Fermer la fenêtre la cache dans le tray : polling et automatismes continuent
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                if tray::is_active(window.app_handle()) {
+                    api.prevent_close();
+                    let _ = window.hide();
+                }
+            }
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_dashboard,
+            get_unknown_fields,
+            get_energy_flows,
+            get_site_dashboard,
             discover_devices,
+            probe_ip,
+            list_interfaces,
+            get_device_status,
+            set_device_profile,
+            set_device_modbus,
+            get_device_profiles,
+            get_discovery,
+            set_discovery_config,
             set_device,
+            forget_device,
+            add_device,
+            remove_device,
+            add_simulated_device,
+            add_replay_device,
+            start_capture,
+            stop_capture,
+            get_capture_status,
+            list_devices,
+            select_device,
             get_device,
             set_mode,
-            set_timeout
+            list_scenes,
+            save_scene,
+            delete_scene,
+            apply_scene,
+            get_battery_details,
+            get_modbus_status,
+            get_power_limits,
+            get_device_faults,
+            set_power_limits,
+            discover_ble_devices,
+            connect_ble,
+            disconnect_ble,
+            get_ble_status,
+            ble_configure_wifi,
+            configure_wifi,
+            reboot_device,
+            get_backup_output,
+            set_backup_output,
+            get_battery_heater,
+            set_battery_heater,
+            get_meter_config,
+            set_meter_config,
+            get_firmware_config,
+            set_firmware_config,
+            check_firmware,
+            start_ota,
+            get_cloud_config,
+            set_cloud_config,
+            get_connection_stats,
+            get_heartbeat_config,
+            get_recovery_config,
+            set_recovery_config,
+            get_unit_preferences,
+            set_unit_preferences,
+            get_retention_config,
+            set_retention_config,
+            get_storage_stats,
+            run_history_maintenance,
+            set_heartbeat_config,
+            get_device_time,
+            sync_device_time,
+            get_change_config,
+            set_change_config,
+            get_clock_config,
+            set_clock_config,
+            set_modbus_setpoint,
+            release_modbus_control,
+            set_modbus_work_mode,
+            get_modbus_control,
+            set_advanced_control,
+            get_schedules,
+            set_schedules,
+            export_schedule,
+            import_schedule,
+            backup_device_settings,
+            restore_device_settings,
+            start_passive,
+            stop_passive,
+            start_calibration,
+            cancel_calibration,
+            get_calibration_status,
+            get_passive_status,
+            get_regulation,
+            get_ev_charger,
+            set_ev_charger_config,
+            set_regulation_config,
+            get_peak_shaving,
+            set_peak_shaving_config,
+            get_export_limit,
+            set_export_limit_config,
+            get_soc_limits,
+            set_soc_limits,
+            send_raw_command,
+            set_timeout,
+            get_settings,
+            set_settings,
+            get_command_stats,
+            reset_command_stats,
+            get_scheduler_config,
+            set_scheduler_config,
+            get_read_only,
+            set_read_only,
+            has_pin,
+            set_pin,
+            get_audit_log,
+            get_command_log,
+            list_plugins,
+            reload_plugins,
+            set_plugin_enabled,
+            get_derived_sensors,
+            set_derived_sensors,
+            get_capabilities,
+            get_protocol_variants,
+            set_protocol_variants,
+            get_pv_sources,
+            set_pv_sources,
+            get_tariff,
+            set_tariff_config,
+            get_prices,
+            get_price_rules,
+            set_price_rules,
+            get_solar_schedule,
+            set_solar_schedule,
+            preview_solar_schedule,
+            get_exception_calendar,
+            set_exception_calendar,
+            preview_price_plan,
+            get_forecast_config,
+            set_forecast_config,
+            get_solar_forecast,
+            get_grid_meter,
+            set_grid_meter,
+            test_grid_meter,
+            test_pv_source,
+            get_sg_ready,
+            set_sg_ready_config,
+            get_compliance,
+            set_compliance_config,
+            get_phase_analysis,
+            reset_phase_analysis,
+            get_grid_quality,
+            set_grid_quality_config,
+            get_alert_config,
+            set_alert_config,
+            get_alerts,
+            clear_alert_history,
+            get_notification_config,
+            set_notification_config,
+            get_mode_watchdog_config,
+            get_log_config,
+            set_log_config,
+            get_recent_logs,
+            set_mode_watchdog_config,
+            get_automation_paused,
+            get_core_status,
+            set_automation_paused,
+            get_autostart,
+            set_autostart,
+            query_history,
+            get_history_summaries,
+            get_phase_history,
+            get_device_events,
+            get_timeline,
+            get_daily_kpis,
+            import_cloud_history,
+            import_app_export,
+            get_health_report,
+            get_cost_report,
+            get_cost_config,
+            set_cost_config,
+            export_history,
+            export_ha_statistics,
+            create_diagnostics_bundle,
+            get_mqtt,
+            set_mqtt_config,
+            get_influx,
+            get_pvoutput,
+            set_pvoutput_config,
+            get_webhooks,
+            set_webhook_config,
+            test_webhook,
+            set_influx_config,
+            get_api_server,
+            set_api_server_config,
+            regenerate_api_token,
+            get_polling,
+            set_polling_config,
+            get_poll_rate,
+            set_poll_rate,
+            start_polling,
+            stop_polling
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            let headless = app.try_state::<AppState>().is_some_and(|state| state.core.headless);
+            if let (RunEvent::Exit, true) = (event, headless) {
+                if let Ok(data_dir) = app.path().app_data_dir() {
+                    service::withdraw(&data_dir);
+                }
+            }
+        });
 }
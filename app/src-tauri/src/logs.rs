@@ -0,0 +1,315 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// Lignes gardées en mémoire pour les rapports de diagnostic
+const CAPACITY: usize = 2000;
+pub const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "marstip.log";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    // error, warn, info, debug ou trace
+    pub level: String,
+    // Niveau par préfixe de cible, ex. "marstip_lib::mqtt" → "debug" ; le préfixe le plus long l'emporte
+    pub modules: HashMap<String, String>,
+    pub file_enabled: bool,
+    // marstip.log est renommé en marstip.log.1 au-delà de cette taille, [Kio]
+    pub max_file_kb: u64,
+    // Fichiers conservés, fichier courant compris
+    pub max_files: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            modules: HashMap::new(),
+            file_enabled: true,
+            max_file_kb: 2048,
+            max_files: 5,
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, AppError> {
+    level
+        .parse()
+        .map_err(|_| AppError::InvalidInput(format!("Invalid log level: {} (expected off, error, warn, info, debug or trace)", level)))
+}
+
+impl LogConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        parse_level(&self.level)?;
+        for level in self.modules.values() {
+            parse_level(level)?;
+        }
+        if self.max_file_kb < 64 {
+            return Err(AppError::InvalidInput("Log files must be allowed at least 64 KiB".to_string()));
+        }
+        if !(1..=20).contains(&self.max_files) {
+            return Err(AppError::InvalidInput("Between 1 and 20 log files can be kept".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Niveaux résolus une fois pour toutes à chaque changement de configuration
+struct Filter {
+    default: LevelFilter,
+    // Triés du préfixe le plus long au plus court
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self { default: LevelFilter::INFO, modules: Vec::new() }
+    }
+}
+
+impl Filter {
+    fn from_config(config: &LogConfig) -> Self {
+        let mut modules: Vec<_> = config
+            .modules
+            .iter()
+            .filter_map(|(target, level)| Some((target.clone(), parse_level(level).ok()?)))
+            .collect();
+        modules.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Self { default: parse_level(&config.level).unwrap_or(LevelFilter::INFO), modules }
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules.iter().find(|(prefix, _)| target.starts_with(prefix.as_str())).map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|(_, level)| *level).fold(self.default, std::cmp::max)
+    }
+}
+
+// Fichier courant et rotation par taille : marstip.log, marstip.log.1 (le plus récent), ...
+struct LogFile {
+    dir: PathBuf,
+    enabled: bool,
+    file: Option<File>,
+    size: u64,
+    max_bytes: u64,
+    max_files: u32,
+}
+
+impl LogFile {
+    fn path(&self, index: u32) -> PathBuf {
+        match index {
+            0 => self.dir.join(LOG_FILE),
+            n => self.dir.join(format!("{}.{}", LOG_FILE, n)),
+        }
+    }
+
+    fn open(&mut self) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new().create(true).append(true).open(self.path(0))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+        let _ = fs::remove_file(self.path(self.max_files.saturating_sub(1).max(1)));
+        for index in (1..self.max_files.saturating_sub(1)).rev() {
+            let _ = fs::rename(self.path(index), self.path(index + 1));
+        }
+        if self.max_files > 1 {
+            fs::rename(self.path(0), self.path(1))?;
+        } else {
+            fs::remove_file(self.path(0))?;
+        }
+        self.open()
+    }
+
+    fn write(&mut self, line: &LogLine) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let text = format!("{}\n", line);
+        if self.size > 0 && self.size + text.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(text.as_bytes())?;
+            self.size += text.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct LogLine {
+    // Croissant depuis le lancement : le journal de l'UI ne redemande que les lignes suivantes
+    pub seq: u64,
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:5} {}: {}", self.timestamp, self.level, self.target, self.message)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct RecentLogs {
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+    filter: Arc<RwLock<Filter>>,
+    // Absent tant que le dossier de données n'est pas connu
+    file: Arc<Mutex<Option<LogFile>>>,
+    next_seq: Arc<AtomicU64>,
+}
+
+impl RecentLogs {
+    fn push(&self, line: LogLine) {
+        if let Some(file) = self.file.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            // Pas de tracing ici : l'événement reviendrait dans ce même abonné
+            if let Err(e) = file.write(&line) {
+                eprintln!("cannot write log file: {}", e);
+            }
+        }
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() >= CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    // Les `limit` dernières lignes, de la plus ancienne à la plus récente
+    pub fn recent(&self, limit: usize) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().skip(lines.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    // Lignes postérieures à `after` (seq), au plus les `limit` dernières
+    pub fn since(&self, after: u64, limit: usize) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        let newer: Vec<_> = lines.iter().filter(|line| line.seq > after).collect();
+        newer.iter().skip(newer.len().saturating_sub(limit)).map(|line| (*line).clone()).collect()
+    }
+
+    // Niveaux et fichier ; `dir` n'est connu qu'une fois l'app lancée
+    pub fn configure(&self, config: &LogConfig, dir: Option<&Path>) {
+        *self.filter.write().unwrap_or_else(|e| e.into_inner()) = Filter::from_config(config);
+        // Les callsites mémorisent leur activation : à recalculer après un changement de niveau
+        tracing::callsite::rebuild_interest_cache();
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = file.take();
+        let dir = dir.map(Path::to_path_buf).or_else(|| previous.as_ref().map(|f| f.dir.clone()));
+        *file = dir.map(|dir| LogFile {
+            dir,
+            enabled: config.file_enabled,
+            size: previous.as_ref().map_or(0, |f| f.size),
+            file: previous.and_then(|f| f.file).filter(|_| config.file_enabled),
+            max_bytes: config.max_file_kb * 1024,
+            max_files: config.max_files,
+        });
+    }
+}
+
+// Message d'abord, puis les autres champs en clé=valeur
+#[derive(Default)]
+struct Fields {
+    message: String,
+    extra: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.extra.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.extra.push_str(&format!(" {}={}", field.name(), value));
+        }
+    }
+}
+
+// Abonné minimal : stderr, fichier tournant et tampon circulaire ; les spans ne sont pas suivis
+struct Collector {
+    logs: RecentLogs,
+    next_span: AtomicU64,
+}
+
+impl Collector {
+    fn filter(&self) -> std::sync::RwLockReadGuard<'_, Filter> {
+        self.logs.filter.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.filter().level(metadata.target())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter().max_level())
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let line = LogLine {
+            seq: self.logs.next_seq.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: format!("{}{}", fields.message, fields.extra),
+        };
+        eprintln!("{}", line);
+        self.logs.push(line);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+// Installe l'abonné global ; renvoie le tampon lu par le rapport de diagnostic
+pub fn install() -> RecentLogs {
+    let logs = RecentLogs::default();
+    let collector = Collector { logs: logs.clone(), next_span: AtomicU64::new(1) };
+    if tracing::subscriber::set_global_default(collector).is_err() {
+        eprintln!("a tracing subscriber is already installed: logs will not be kept for diagnostics");
+    }
+    logs
+}
@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Default)]
+struct MethodStats {
+    calls: u64,
+    failures: u64,
+    total: Duration,
+    max: Duration,
+    last: Duration,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MethodStatsEntry {
+    pub method: String,
+    pub calls: u64,
+    pub failures: u64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub last_ms: f64,
+}
+
+// Latence et échecs agrégés par méthode JSON-RPC
+#[derive(Default)]
+pub struct CommandMetrics {
+    methods: Mutex<HashMap<String, MethodStats>>,
+}
+
+impl CommandMetrics {
+    pub fn record(&self, method: &str, elapsed: Duration, ok: bool) {
+        let Ok(mut methods) = self.methods.lock() else { return };
+        let stats = methods.entry(method.to_string()).or_default();
+        stats.calls += 1;
+        if !ok {
+            stats.failures += 1;
+        }
+        stats.total += elapsed;
+        stats.max = stats.max.max(elapsed);
+        stats.last = elapsed;
+    }
+
+    pub fn snapshot(&self) -> Vec<MethodStatsEntry> {
+        let Ok(methods) = self.methods.lock() else { return Vec::new() };
+        let mut entries: Vec<MethodStatsEntry> = methods
+            .iter()
+            .map(|(method, stats)| MethodStatsEntry {
+                method: method.clone(),
+                calls: stats.calls,
+                failures: stats.failures,
+                avg_ms: if stats.calls > 0 { as_ms(stats.total) / stats.calls as f64 } else { 0.0 },
+                max_ms: as_ms(stats.max),
+                last_ms: as_ms(stats.last),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.method.cmp(&b.method));
+        entries
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut methods) = self.methods.lock() {
+            methods.clear();
+        }
+    }
+}
+
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,200 @@
+use crate::error::AppError;
+use marstek_protocol::methods;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+// Composants JSON-RPC (chapitre IV de la doc Open API)
+pub const COMPONENT_PV: &str = "PV";
+pub const COMPONENT_EM: &str = "EM";
+
+const VENUS_CE_COMPONENTS: &[&str] = &["Marstek", "Wifi", "BLE", "Bat", "ES", COMPONENT_EM];
+const VENUS_D_COMPONENTS: &[&str] = &["Marstek", "Wifi", "BLE", "Bat", COMPONENT_PV, "ES", COMPONENT_EM];
+// Section du tableau de bord -> méthode qui la remplit
+const SECTIONS: &[(&str, &str)] = &[
+    ("energy", methods::ES_GET_STATUS),
+    ("battery", methods::BAT_GET_STATUS),
+    ("wifi", methods::WIFI_GET_STATUS),
+    ("mode", methods::ES_GET_MODE),
+    ("meter", methods::EM_GET_STATUS),
+];
+// Délais d'attente consécutifs d'une méthode, l'appareil répondant au reste, avant de la considérer absente
+const TIMEOUTS_BEFORE_UNSUPPORTED: u32 = 3;
+// JSON-RPC "Method not found"
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Serialize, Clone)]
+pub struct ModelCapabilities {
+    pub model: String,
+    pub known: bool,
+    // Puissance AC max en charge/décharge, [W]
+    pub rated_power: Option<u32>,
+    // Capacité nominale, [Wh]
+    pub rated_capacity: Option<u32>,
+    pub pv_inputs: u8,
+    pub backup_output: bool,
+    // Chauffage de la batterie par temps froid
+    pub heater: bool,
+    pub components: Vec<&'static str>,
+    // Version du firmware (champ ver de Marstek.GetDevice) à laquelle s'appliquent les méthodes absentes
+    pub firmware: Option<u32>,
+    // Méthodes refusées ou jamais répondues par ce modèle dans cette version
+    pub unsupported_methods: BTreeSet<String>,
+}
+
+impl ModelCapabilities {
+    pub fn supports(&self, component: &str) -> bool {
+        self.components.contains(&component)
+    }
+
+    // "EM.GetStatus" : composant présent et méthode pas encore vue absente
+    pub fn supports_method(&self, method: &str) -> bool {
+        let component = method.split('.').next().unwrap_or(method);
+        self.supports(component) && !self.unsupported_methods.contains(method)
+    }
+
+    // Sections non interrogées sur ce modèle : l'interface les masque au lieu de les afficher vides
+    pub fn unsupported_sections(&self) -> Vec<&'static str> {
+        SECTIONS.iter().filter(|(_, method)| !self.supports_method(method)).map(|(section, _)| *section).collect()
+    }
+
+    pub fn check_method(&self, method: &str) -> Result<(), AppError> {
+        if self.supports_method(method) {
+            return Ok(());
+        }
+        let firmware = self.firmware.map(|v| format!(" (firmware {})", v)).unwrap_or_default();
+        Err(AppError::InvalidInput(format!("{} is not supported by {}{}", method, self.model, firmware)))
+    }
+}
+
+#[derive(Default)]
+struct MethodState {
+    timeouts: u32,
+    unsupported: bool,
+    // A déjà répondu sur ce (modèle, version)
+    answered: bool,
+}
+
+// (modèle normalisé, version du firmware)
+type FirmwareKey = (String, Option<u32>);
+
+// Méthodes absentes, apprises à l'usage par (modèle, version) : une mise à jour du firmware repart de zéro
+#[derive(Default)]
+pub struct CapabilityMap {
+    methods: Mutex<HashMap<FirmwareKey, HashMap<String, MethodState>>>,
+}
+
+impl CapabilityMap {
+    pub fn capabilities(&self, model: &str, firmware: Option<u32>) -> ModelCapabilities {
+        let mut caps = capabilities(model);
+        caps.firmware = firmware;
+        let methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(known) = methods.get(&(normalize(model), firmware)) {
+            caps.unsupported_methods = known.iter().filter(|(_, s)| s.unsupported).map(|(m, _)| m.clone()).collect();
+        }
+        caps
+    }
+
+    pub fn answered(&self, model: &str, firmware: Option<u32>, method: &str) -> bool {
+        let methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        methods.get(&(normalize(model), firmware)).and_then(|known| known.get(method)).is_some_and(|s| s.answered)
+    }
+
+    // À n'appeler que si l'appareil a répondu à d'autres requêtes du même relevé :
+    // un délai d'attente isolé ne dit alors rien de la méthode
+    pub fn observe<T>(&self, model: &str, firmware: Option<u32>, method: &str, result: &Result<T, AppError>) {
+        let mut methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        let state = methods.entry((normalize(model), firmware)).or_default().entry(method.to_string()).or_default();
+        match result {
+            Ok(_) => *state = MethodState { answered: true, ..Default::default() },
+            Err(AppError::DeviceRejected { code: METHOD_NOT_FOUND, .. }) => state.unsupported = true,
+            Err(AppError::Timeout(_)) => {
+                state.timeouts += 1;
+                if state.timeouts >= TIMEOUTS_BEFORE_UNSUPPORTED && !state.unsupported {
+                    tracing::info!(model, firmware, method, "method never answers, skipping it from now on");
+                    state.unsupported = true;
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+// "VenusE", "Venus E", "venus-e 3.0" -> "venuse3.0"
+fn normalize(model: &str) -> String {
+    model
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+pub fn capabilities(model: &str) -> ModelCapabilities {
+    let normalized = normalize(model);
+    let caps = |rated_power, rated_capacity, pv_inputs, backup_output, heater, components: &[&'static str]| ModelCapabilities {
+        model: model.to_string(),
+        known: true,
+        rated_power,
+        rated_capacity,
+        pv_inputs,
+        backup_output,
+        heater,
+        components: components.to_vec(),
+        firmware: None,
+        unsupported_methods: BTreeSet::new(),
+    };
+
+    if normalized.starts_with("venusc") {
+        caps(Some(2500), Some(2560), 0, true, false, VENUS_CE_COMPONENTS)
+    } else if normalized.starts_with("venuse") {
+        caps(Some(2500), Some(5120), 0, true, true, VENUS_CE_COMPONENTS)
+    } else if normalized.starts_with("venusd") {
+        caps(None, None, 2, true, false, VENUS_D_COMPONENTS)
+    } else if normalized.contains("b2500") {
+        // Stockage balcon : 2 entrées PV, sortie limitée à 800 W, pas de prise secours
+        caps(Some(800), Some(2240), 2, false, false, VENUS_D_COMPONENTS)
+    } else if normalized.starts_with("jupiter") {
+        caps(None, None, 0, false, false, VENUS_D_COMPONENTS)
+    } else if normalized.starts_with("duo") {
+        // Absent de la doc Open API Rev 1.0 : hybride PV, traité comme le Venus D
+        caps(None, None, 2, true, false, VENUS_D_COMPONENTS)
+    } else {
+        // Modèle inconnu : on n'exclut rien
+        ModelCapabilities {
+            model: model.to_string(),
+            known: false,
+            rated_power: None,
+            rated_capacity: None,
+            pv_inputs: 0,
+            backup_output: false,
+            heater: false,
+            components: VENUS_D_COMPONENTS.to_vec(),
+            firmware: None,
+            unsupported_methods: BTreeSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answered_is_learned_per_model_and_firmware() {
+        let map = CapabilityMap::default();
+        assert!(!map.answered("Venus E", Some(153), methods::ES_GET_BACKUP));
+        map.observe("Venus E", Some(153), methods::ES_GET_BACKUP, &Ok(serde_json::json!({ "enable": 1 })));
+        assert!(map.answered("venus-e", Some(153), methods::ES_GET_BACKUP));
+        // Nouveau firmware : la lecture doit répondre à nouveau avant toute écriture
+        assert!(!map.answered("Venus E", Some(154), methods::ES_GET_BACKUP));
+    }
+
+    #[test]
+    fn method_not_found_is_never_answered() {
+        let map = CapabilityMap::default();
+        let rejected: Result<(), AppError> = Err(AppError::DeviceRejected { code: METHOD_NOT_FOUND, message: "Method not found".to_string() });
+        map.observe("Venus C", None, methods::BAT_GET_HEATER, &rejected);
+        assert!(!map.answered("Venus C", None, methods::BAT_GET_HEATER));
+        assert!(map.capabilities("Venus C", None).check_method(methods::BAT_GET_HEATER).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::changes::ChangeEvent;
+use crate::error::AppError;
+use crate::plugins::PluginAction;
+use crate::units::UnitPreferences;
+use crate::DashboardData;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Délai avant une nouvelle tentative quand le broker est injoignable
+const RECONNECT_DELAY_S: u64 = 5;
+// Durée de la consigne passive si le message ne la précise pas, [s]
+const DEFAULT_CD_TIME: i64 = 300;
+const COMMANDS: [&str; 2] = ["set_mode", "passive_power"];
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    // Uniquement si le trousseau système est indisponible
+    pub password: Option<String>,
+    pub base_topic: String,
+    pub discovery_prefix: String,
+    // Écoute de <base_topic>/<appareil>/set_mode et .../passive_power
+    pub allow_commands: bool,
+    // Exécute les commandes sans PIN : à réserver à un broker authentifié et privé
+    pub commands_skip_pin: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: 1883,
+            username: None,
+            password: None,
+            base_topic: "marstip".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+            allow_commands: false,
+            commands_skip_pin: false,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct MqttStatus {
+    pub connected: bool,
+    pub last_error: Option<String>,
+}
+
+struct Sensor {
+    key: &'static str,
+    name: &'static str,
+    unit: Option<&'static str>,
+    device_class: Option<&'static str>,
+    state_class: Option<&'static str>,
+}
+
+const fn sensor(key: &'static str, name: &'static str, unit: &'static str, device_class: &'static str, state_class: &'static str) -> Sensor {
+    Sensor { key, name, unit: Some(unit), device_class: Some(device_class), state_class: Some(state_class) }
+}
+
+const SENSORS: &[Sensor] = &[
+    sensor("soc", "Battery SOC", "%", "battery", "measurement"),
+    sensor("pv_power", "PV power", "W", "power", "measurement"),
+    sensor("grid_power", "Grid power", "W", "power", "measurement"),
+    sensor("battery_power", "Battery power", "W", "power", "measurement"),
+    sensor("ongrid_power", "On-grid power", "W", "power", "measurement"),
+    sensor("offgrid_power", "Off-grid power", "W", "power", "measurement"),
+    sensor("temperature", "Battery temperature", "°C", "temperature", "measurement"),
+    sensor("heater_power", "Battery heater power", "W", "power", "measurement"),
+    sensor("total_pv_energy", "PV energy", "Wh", "energy", "total_increasing"),
+    sensor("total_grid_output_energy", "Grid output energy", "Wh", "energy", "total_increasing"),
+    sensor("total_grid_input_energy", "Grid input energy", "Wh", "energy", "total_increasing"),
+    Sensor { key: "self_consumption", name: "Self-consumption", unit: Some("%"), device_class: None, state_class: Some("measurement") },
+    Sensor { key: "self_sufficiency", name: "Self-sufficiency", unit: Some("%"), device_class: None, state_class: Some("measurement") },
+    Sensor { key: "round_trip_efficiency", name: "Round-trip efficiency", unit: Some("%"), device_class: None, state_class: Some("measurement") },
+    Sensor { key: "mode", name: "Mode", unit: None, device_class: None, state_class: None },
+];
+
+// Identifiant utilisable dans un topic et un unique_id HA
+pub fn object_id(device: &str) -> String {
+    device.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn state_payload(data: &DashboardData, units: &UnitPreferences) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "soc": data.battery.soc.or(data.energy.bat_soc),
+        "pv_power": data.energy.pv_power,
+        "grid_power": data.meter.as_ref().and_then(|m| m.total_power),
+        "battery_power": data.energy.bat_power,
+        "ongrid_power": data.energy.ongrid_power,
+        "offgrid_power": data.energy.offgrid_power,
+        "temperature": data.battery.bat_temp,
+        "heater_power": data.battery.heater_power,
+        "total_pv_energy": data.energy.total_pv_energy,
+        "total_grid_output_energy": data.energy.total_grid_output_energy,
+        "total_grid_input_energy": data.energy.total_grid_input_energy,
+        "mode": data.mode.mode,
+        "self_consumption": data.kpis.self_consumption,
+        "self_sufficiency": data.kpis.self_sufficiency,
+        "round_trip_efficiency": data.kpis.round_trip_efficiency,
+    });
+    if let Some(fields) = payload.as_object_mut() {
+        for (name, value) in data.flows.named() {
+            fields.insert(name.to_string(), serde_json::json!(value));
+        }
+        units.convert_json(fields);
+    }
+    payload
+}
+
+// Commande reçue du broker, exécutée hors du thread de connexion
+pub struct MqttCommand {
+    // object_id de l'appareil visé
+    pub device: String,
+    pub command: String,
+    pub action: PluginAction,
+    // PIN fourni dans la charge utile JSON, vérifié comme pour l'interface
+    pub pin: Option<String>,
+}
+
+// set_mode : "Auto" ou {"mode": "Auto", "pin": "1234"} ;
+// passive_power : 300 ou {"power": 300, "cd_time": 600, "pin": "1234"}
+fn parse_command(command: &str, payload: &str) -> Result<(PluginAction, Option<String>), String> {
+    let payload = payload.trim();
+    let json = serde_json::from_str::<serde_json::Value>(payload).ok().filter(|v| v.is_object());
+    let pin = json.as_ref().and_then(|v| v.get("pin")).and_then(|v| v.as_str()).map(str::to_string);
+    let action = match command {
+        "set_mode" => match &json {
+            Some(json) => PluginAction::SetMode(json.get("mode").and_then(|v| v.as_str()).ok_or("Missing mode")?.to_string()),
+            None => PluginAction::SetMode(payload.trim_matches('"').to_string()),
+        },
+        "passive_power" => match &json {
+            Some(json) => {
+                let power = json.get("power").and_then(|v| v.as_i64()).ok_or("Missing power")?;
+                let cd_time = json.get("cd_time").and_then(|v| v.as_i64()).unwrap_or(DEFAULT_CD_TIME);
+                PluginAction::SetPassivePower { power, cd_time }
+            }
+            None => {
+                let power = payload.parse::<i64>().map_err(|e| e.to_string())?;
+                PluginAction::SetPassivePower { power, cd_time: DEFAULT_CD_TIME }
+            }
+        },
+        _ => return Err(format!("Unknown command: {}", command)),
+    };
+    Ok((action, pin))
+}
+
+fn result_topic(config: &MqttConfig, device: &str, command: &str) -> String {
+    format!("{}/{}/{}/result", config.base_topic, device, command)
+}
+
+fn result_payload(result: &Result<bool, AppError>) -> String {
+    match result {
+        Ok(accepted) => serde_json::json!({ "ok": true, "set_result": accepted }),
+        Err(e) => serde_json::json!({ "ok": false, "code": e.code(), "error": e.to_string() }),
+    }
+    .to_string()
+}
+
+#[derive(Default)]
+struct BridgeState {
+    client: Option<Client>,
+    // Incrémenté à chaque reconfiguration : l'ancien thread de connexion s'arrête
+    generation: u64,
+    config: MqttConfig,
+    // Appareils dont la config Discovery a été publiée sur la connexion courante
+    announced: HashSet<String>,
+    status: MqttStatus,
+    // Sujet externe suivi (borne de recharge) et dernier message reçu
+    watch_topic: Option<String>,
+    watched: Option<String>,
+    // Unités des charges utiles, reprises dans la config Discovery
+    units: UnitPreferences,
+}
+
+pub struct MqttBridge {
+    state: Arc<Mutex<BridgeState>>,
+    commands: Sender<MqttCommand>,
+}
+
+impl MqttBridge {
+    pub fn new(commands: Sender<MqttCommand>) -> Self {
+        Self { state: Arc::default(), commands }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BridgeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self) -> MqttStatus {
+        self.lock().status.clone()
+    }
+
+    fn availability_topic(config: &MqttConfig) -> String {
+        format!("{}/status", config.base_topic)
+    }
+
+    pub fn connect(&self, config: &MqttConfig, password: Option<String>) {
+        let mut state = self.lock();
+        if let Some(client) = state.client.take() {
+            let _ = client.try_disconnect();
+        }
+        state.generation += 1;
+        state.announced.clear();
+        state.config = config.clone();
+        state.status = MqttStatus::default();
+        if !config.enabled || config.host.is_empty() {
+            return;
+        }
+
+        let availability = Self::availability_topic(config);
+        let mut options = MqttOptions::new(format!("{}-{}", config.base_topic, std::process::id()), &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&availability, "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, password.unwrap_or_default());
+        }
+        let (client, mut connection) = Client::new(options, 64);
+        state.client = Some(client.clone());
+
+        let shared = Arc::clone(&self.state);
+        let commands = self.commands.clone();
+        let config = config.clone();
+        let generation = state.generation;
+        std::thread::spawn(move || {
+            for event in connection.iter() {
+                let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                if state.generation != generation {
+                    break;
+                }
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        tracing::info!("MQTT connected");
+                        state.status = MqttStatus { connected: true, last_error: None };
+                        // Le broker a pu perdre les messages retenus : on réannonce
+                        state.announced.clear();
+                        let _ = client.try_publish(&availability, QoS::AtLeastOnce, true, "online");
+                        if let Some(topic) = &state.watch_topic {
+                            let _ = client.try_subscribe(topic, QoS::AtLeastOnce);
+                        }
+                        if config.allow_commands {
+                            for command in COMMANDS {
+                                let _ = client.try_subscribe(format!("{}/+/{}", config.base_topic, command), QoS::AtLeastOnce);
+                            }
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) if state.watch_topic.as_deref() == Some(publish.topic.as_str()) => {
+                        state.watched = Some(String::from_utf8_lossy(&publish.payload).into_owned());
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) if config.allow_commands => {
+                        let topic = publish.topic.strip_prefix(&format!("{}/", config.base_topic)).unwrap_or_default();
+                        let Some((device, command)) = topic.split_once('/') else { continue };
+                        let payload = String::from_utf8_lossy(&publish.payload);
+                        match parse_command(command, &payload) {
+                            Ok((action, pin)) => {
+                                let _ = commands.send(MqttCommand { device: device.to_string(), command: command.to_string(), action, pin });
+                            }
+                            Err(e) => {
+                                let topic = result_topic(&config, device, command);
+                                let _ = client.try_publish(topic, QoS::AtLeastOnce, false, result_payload(&Err(AppError::InvalidInput(e))));
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("MQTT connection error: {}", e);
+                        state.status = MqttStatus { connected: false, last_error: Some(e.to_string()) };
+                        drop(state);
+                        std::thread::sleep(Duration::from_secs(RECONNECT_DELAY_S));
+                    }
+                }
+            }
+        });
+    }
+
+    fn announce(client: &Client, config: &MqttConfig, units: &UnitPreferences, device: &str, model: Option<&str>) {
+        let id = object_id(device);
+        let state_topic = format!("{}/{}/state", config.base_topic, id);
+        for sensor in SENSORS {
+            let mut payload = serde_json::json!({
+                "name": sensor.name,
+                "unique_id": format!("marstip_{}_{}", id, sensor.key),
+                "state_topic": state_topic,
+                "value_template": format!("{{{{ value_json.{} }}}}", sensor.key),
+                "availability_topic": Self::availability_topic(config),
+                "device": {
+                    "identifiers": [format!("marstip_{}", id)],
+                    "name": device,
+                    "manufacturer": "Marstek",
+                    "model": model,
+                },
+            });
+            for (field, value) in [
+                ("unit_of_measurement", units.unit(sensor.key).or(sensor.unit)),
+                ("device_class", sensor.device_class),
+                ("state_class", sensor.state_class),
+            ] {
+                if let Some(value) = value {
+                    payload[field] = serde_json::json!(value);
+                }
+            }
+            let topic = format!("{}/sensor/marstip_{}/{}/config", config.discovery_prefix, id, sensor.key);
+            if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload.to_string()) {
+                tracing::warn!("MQTT discovery publish failed: {}", e);
+            }
+        }
+    }
+
+    // Nouvelles unités : la config Discovery est republiée avec le prochain état
+    pub fn set_units(&self, units: &UnitPreferences) {
+        let mut state = self.lock();
+        if state.units != *units {
+            state.units = units.clone();
+            state.announced.clear();
+        }
+    }
+
+    // Abonnement conservé d'une connexion à l'autre ; None : plus de suivi
+    pub fn watch(&self, topic: Option<String>) {
+        let mut state = self.lock();
+        if state.watch_topic == topic {
+            return;
+        }
+        if let Some(client) = state.client.clone().filter(|_| state.status.connected) {
+            if let Some(old) = &state.watch_topic {
+                let _ = client.try_unsubscribe(old);
+            }
+            if let Some(topic) = &topic {
+                let _ = client.try_subscribe(topic, QoS::AtLeastOnce);
+            }
+        }
+        state.watch_topic = topic;
+        state.watched = None;
+    }
+
+    // Dernier message du sujet suivi, tant que la connexion tient
+    pub fn watched(&self) -> Option<String> {
+        let state = self.lock();
+        state.watched.clone().filter(|_| state.status.connected)
+    }
+
+    pub fn ack(&self, command: &MqttCommand, result: &Result<bool, AppError>) {
+        let state = self.lock();
+        let Some(client) = &state.client else { return };
+        let topic = result_topic(&state.config, &command.device, &command.command);
+        if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, false, result_payload(result)) {
+            tracing::warn!("MQTT result publish failed: {}", e);
+        }
+    }
+
+    pub fn publish(&self, device: &str, model: Option<&str>, data: &DashboardData) {
+        let mut state = self.lock();
+        if !state.status.connected {
+            return;
+        }
+        let Some(client) = state.client.clone() else { return };
+        if state.announced.insert(device.to_string()) {
+            Self::announce(&client, &state.config, &state.units, device, model);
+        }
+        let topic = format!("{}/{}/state", state.config.base_topic, object_id(device));
+        if let Err(e) = client.try_publish(topic, QoS::AtMostOnce, true, state_payload(data, &state.units).to_string()) {
+            tracing::warn!("MQTT state publish failed: {}", e);
+        }
+    }
+
+    // <base_topic>/<appareil>/events, non retenu : seules les transitions au-delà de l'hystérésis
+    pub fn publish_change(&self, device: &str, event: &ChangeEvent) {
+        let state = self.lock();
+        if !state.status.connected {
+            return;
+        }
+        let Some(client) = &state.client else { return };
+        let topic = format!("{}/{}/events", state.config.base_topic, object_id(device));
+        let payload = serde_json::json!({ "event": event.event, "previous": event.previous, "value": event.value });
+        if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, false, payload.to_string()) {
+            tracing::warn!("MQTT change publish failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_is_read_from_json_payloads() {
+        let (action, pin) = parse_command("set_mode", r#"{"mode": "AI", "pin": "1234"}"#).unwrap();
+        assert!(matches!(action, PluginAction::SetMode(mode) if mode == "AI"));
+        assert_eq!(pin.as_deref(), Some("1234"));
+
+        let (action, pin) = parse_command("passive_power", r#"{"power": -800, "pin": "1234"}"#).unwrap();
+        assert!(matches!(action, PluginAction::SetPassivePower { power: -800, cd_time: DEFAULT_CD_TIME }));
+        assert_eq!(pin.as_deref(), Some("1234"));
+    }
+
+    #[test]
+    fn plain_payloads_carry_no_pin() {
+        let (action, pin) = parse_command("set_mode", "\"Auto\"").unwrap();
+        assert!(matches!(action, PluginAction::SetMode(mode) if mode == "Auto"));
+        assert!(pin.is_none());
+
+        let (action, pin) = parse_command("passive_power", "300").unwrap();
+        assert!(matches!(action, PluginAction::SetPassivePower { power: 300, .. }));
+        assert!(pin.is_none());
+        assert!(parse_command("passive_power", "lots").is_err());
+    }
+}
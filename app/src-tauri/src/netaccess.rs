@@ -0,0 +1,289 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub const TLS_CERT_FILE: &str = "api-cert.pem";
+pub const TLS_KEY_FILE: &str = "api-key.pem";
+
+// Portée d'un jeton : le jeton de lecture ne peut rien piloter
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    Read,
+    #[default]
+    Control,
+}
+
+impl ApiScope {
+    // reads : requête de lecture (GET, HEAD) ; tout le reste pilote la batterie
+    pub fn allows(self, reads: bool) -> bool {
+        self == ApiScope::Control || reads
+    }
+}
+
+// Jetons en vigueur, relus dans le trousseau à chaque (re)démarrage d'une écoute
+#[derive(Clone, Default)]
+pub struct ApiTokens {
+    pub control: Option<String>,
+    pub read: Option<String>,
+}
+
+impl ApiTokens {
+    pub fn is_empty(&self) -> bool {
+        self.control.is_none() && self.read.is_none()
+    }
+
+    // None : jeton absent ou inconnu
+    pub fn scope(&self, provided: Option<&str>) -> Option<ApiScope> {
+        let provided = provided?;
+        if token_matches(self.control.as_deref(), provided) {
+            Some(ApiScope::Control)
+        } else if token_matches(self.read.as_deref(), provided) {
+            Some(ApiScope::Read)
+        } else {
+            None
+        }
+    }
+}
+
+fn token_matches(token: Option<&str>, provided: &str) -> bool {
+    let Some(token) = token else { return false };
+    // Comparaison en temps constant
+    token.len() == provided.len() && token.bytes().zip(provided.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+// 32 octets aléatoires en hexadécimal
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Certificat auto-signé pour `names` (noms d'hôte ou IP), généré une fois dans `dir` puis réutilisé :
+// les clients peuvent l'épingler d'un démarrage à l'autre
+pub fn self_signed_certificate(dir: &Path, names: &[String]) -> Result<(PathBuf, PathBuf), String> {
+    let (cert, key) = (dir.join(TLS_CERT_FILE), dir.join(TLS_KEY_FILE));
+    if cert.is_file() && key.is_file() {
+        return Ok((cert, key));
+    }
+    let generated = rcgen::generate_simple_self_signed(names.to_vec()).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    std::fs::write(&cert, generated.cert.pem()).map_err(|e| e.to_string())?;
+    std::fs::write(&key, generated.key_pair.serialize_pem()).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+    }
+    tracing::info!("generated a self-signed TLS certificate in {}", cert.display());
+    Ok((cert, key))
+}
+
+// Adresse seule ou plage : "192.168.1.0/24", "fd00::/8"
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SourceRange {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl SourceRange {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (addr, prefix) = text.split_once('/').map_or((text, None), |(addr, prefix)| (addr, Some(prefix)));
+        let network: IpAddr = addr.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Self { network, prefix })
+    }
+
+    // ip déjà ramenée à sa forme canonique (IPv4 mappée en IPv6 -> IPv4)
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (ip, self.network) {
+            (IpAddr::V4(ip), IpAddr::V4(network)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(ip) & mask == u32::from(network) & mask
+            }
+            (IpAddr::V6(ip), IpAddr::V6(network)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(ip) & mask == u128::from(network) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// Liste vide : toutes les sources sont acceptées
+pub fn source_allowed(ranges: &[SourceRange], ip: IpAddr) -> bool {
+    ranges.is_empty() || ranges.iter().any(|range| range.contains(ip))
+}
+
+// Jetons de requête d'un client, rechargés en continu jusqu'à la limite par minute
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// Au-delà, les clients revenus à plein sont oubliés
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+// Seau à jetons par IP cliente : per_minute requêtes en rafale, puis per_minute par minute ; 0 : sans limite
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self { per_minute, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    // Err : secondes avant la prochaine requête acceptée
+    pub fn take(&self, ip: IpAddr) -> Result<(), u64> {
+        self.take_at(ip, Instant::now())
+    }
+
+    fn take_at(&self, ip: IpAddr, now: Instant) -> Result<(), u64> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.per_minute);
+        let per_second = capacity / 60.0;
+        let refill = |bucket: &Bucket| (bucket.tokens + now.saturating_duration_since(bucket.refilled).as_secs_f64() * per_second).min(capacity);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&ip) {
+            buckets.retain(|_, bucket| refill(bucket) < capacity);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: capacity, refilled: now });
+        bucket.tokens = refill(bucket);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return Err(((1.0 - bucket.tokens) / per_second).ceil().max(1.0) as u64);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_resolve_to_their_scope() {
+        let tokens = ApiTokens { control: Some("control".to_string()), read: Some("reader".to_string()) };
+        assert_eq!(tokens.scope(Some("control")), Some(ApiScope::Control));
+        assert_eq!(tokens.scope(Some("reader")), Some(ApiScope::Read));
+        assert_eq!(tokens.scope(Some("contro")), None);
+        assert_eq!(tokens.scope(Some("")), None);
+        assert_eq!(tokens.scope(None), None);
+        // Sans jeton configuré, rien n'est accepté, pas même une chaîne vide
+        assert_eq!(ApiTokens::default().scope(Some("")), None);
+    }
+
+    #[test]
+    fn read_scope_only_allows_reads() {
+        assert!(ApiScope::Read.allows(true));
+        assert!(!ApiScope::Read.allows(false));
+        assert!(ApiScope::Control.allows(false));
+    }
+
+    #[test]
+    fn generated_tokens_are_distinct() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn certificate_is_generated_once_then_reused() {
+        let dir = std::env::temp_dir().join(format!("marstip-tls-{}", &generate_token()[..12]));
+        let names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let (cert, key) = self_signed_certificate(&dir, &names).unwrap();
+        let pem = std::fs::read_to_string(&cert).unwrap();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----"));
+        assert!(std::fs::read_to_string(&key).unwrap().contains("PRIVATE KEY"));
+        self_signed_certificate(&dir, &names).unwrap();
+        assert_eq!(std::fs::read_to_string(&cert).unwrap(), pem);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn source_ranges_parse_addresses_and_prefixes() {
+        assert_eq!(SourceRange::parse("192.168.1.7"), SourceRange::parse("192.168.1.7/32"));
+        assert_eq!(SourceRange::parse(" fd00::/8 ").map(|r| r.prefix), Some(8));
+        assert_eq!(SourceRange::parse("::1").map(|r| r.prefix), Some(128));
+        assert!(SourceRange::parse("192.168.1.0/33").is_none());
+        assert!(SourceRange::parse("fd00::/129").is_none());
+        assert!(SourceRange::parse("192.168.1.0/").is_none());
+        assert!(SourceRange::parse("nas.local").is_none());
+    }
+
+    #[test]
+    fn source_ranges_match_by_prefix() {
+        let lan = SourceRange::parse("192.168.1.0/24").unwrap();
+        assert!(lan.contains(ip("192.168.1.200")));
+        assert!(!lan.contains(ip("192.168.2.1")));
+        let host = SourceRange::parse("10.0.0.5/32").unwrap();
+        assert!(host.contains(ip("10.0.0.5")));
+        assert!(!host.contains(ip("10.0.0.4")));
+        let any = SourceRange::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+        assert!(!any.contains(ip("::1")), "an IPv4 range never matches IPv6");
+        let ula = SourceRange::parse("fd00::/8").unwrap();
+        assert!(ula.contains(ip("fd12:3456::1")));
+        assert!(!ula.contains(ip("fe80::1")));
+        assert!(SourceRange::parse("::/0").unwrap().contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn empty_allowlist_accepts_everyone() {
+        assert!(source_allowed(&[], ip("198.51.100.1")));
+        let ranges = [SourceRange::parse("127.0.0.1").unwrap()];
+        assert!(source_allowed(&ranges, ip("127.0.0.1")));
+        assert!(!source_allowed(&ranges, ip("127.0.0.2")));
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst_then_refills() {
+        let limiter = RateLimiter::new(60);
+        let (client, other) = (ip("192.168.1.10"), ip("192.168.1.11"));
+        let start = Instant::now();
+        for _ in 0..60 {
+            assert!(limiter.take_at(client, start).is_ok());
+        }
+        assert_eq!(limiter.take_at(client, start), Err(1));
+        assert!(limiter.take_at(other, start).is_ok(), "each client has its own bucket");
+        // Un jeton par seconde à 60 par minute
+        assert!(limiter.take_at(client, start + std::time::Duration::from_millis(1000)).is_ok());
+        assert!(limiter.take_at(client, start + std::time::Duration::from_millis(1000)).is_err());
+        assert!(limiter.take_at(client, start + std::time::Duration::from_secs(120)).is_ok());
+    }
+
+    #[test]
+    fn retry_after_reflects_the_refill_rate() {
+        let limiter = RateLimiter::new(6);
+        let (client, start) = (ip("10.0.0.1"), Instant::now());
+        for _ in 0..6 {
+            limiter.take_at(client, start).unwrap();
+        }
+        assert_eq!(limiter.take_at(client, start), Err(10));
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let limiter = RateLimiter::new(0);
+        let start = Instant::now();
+        assert!((0..1000).all(|_| limiter.take_at(ip("10.0.0.1"), start).is_ok()));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::alerts::{AlertEvent, AlertMetric, AlertState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Délai pendant lequel un changement de mode envoyé par l'app n'est pas signalé
+const EXPECTED_MODE_GRACE: Duration = Duration::from_secs(120);
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    // Types d'alerte sans notification (l'alerte reste dans l'historique)
+    pub muted_alerts: Vec<AlertMetric>,
+    // Notifier aussi le retour à la normale
+    pub notify_cleared: bool,
+    // Changement de mode non demandé par l'app (appli Marstek, firmware, coupure)
+    pub mode_changes: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self { enabled: true, muted_alerts: Vec::new(), notify_cleared: false, mode_changes: true }
+    }
+}
+
+impl NotificationConfig {
+    pub fn wants(&self, event: &AlertEvent) -> bool {
+        self.enabled && !self.muted_alerts.contains(&event.metric) && (event.state == AlertState::Raised || self.notify_cleared)
+    }
+}
+
+#[derive(Default)]
+struct WatchState {
+    // Dernier mode lu par appareil
+    last: HashMap<String, String>,
+    // Mode envoyé par l'app, avec l'instant de l'envoi
+    expected: HashMap<String, (String, Instant)>,
+}
+
+// Repère les changements de mode faits hors de l'app
+#[derive(Default)]
+pub struct ModeWatch {
+    state: Mutex<WatchState>,
+}
+
+impl ModeWatch {
+    pub fn expect(&self, device: &str, mode: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.expected.insert(device.to_string(), (mode.to_string(), Instant::now()));
+    }
+
+    // Renvoie (ancien, nouveau) si le mode a changé sans que l'app l'ait demandé ; premier relevé exclu
+    pub fn observe(&self, device: &str, mode: &str) -> Option<(String, String)> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = state.last.insert(device.to_string(), mode.to_string())?;
+        if previous == mode {
+            return None;
+        }
+        let expected = state
+            .expected
+            .get(device)
+            .is_some_and(|(expected, at)| expected == mode && at.elapsed() < EXPECTED_MODE_GRACE);
+        (!expected).then(|| (previous, mode.to_string()))
+    }
+}
+
+pub fn alert_title(event: &AlertEvent) -> String {
+    let what = match event.metric {
+        AlertMetric::BatTempAbove => "Battery temperature high",
+        AlertMetric::BatTempBelow => "Battery temperature low",
+        AlertMetric::SocBelow => "Battery charge low",
+        AlertMetric::UnreachableFor => "Battery unreachable",
+        AlertMetric::GridExportAbove => "Grid export high",
+        AlertMetric::DeviceFaults => "Battery fault",
+    };
+    match event.state {
+        AlertState::Raised => what.to_string(),
+        AlertState::Cleared => format!("{} (cleared)", what),
+    }
+}
+
+pub fn alert_body(event: &AlertEvent) -> String {
+    let unit = match event.metric {
+        AlertMetric::BatTempAbove | AlertMetric::BatTempBelow => "°C",
+        AlertMetric::SocBelow => "%",
+        AlertMetric::UnreachableFor => "min",
+        AlertMetric::GridExportAbove => "W",
+        AlertMetric::DeviceFaults => return format!("{}: {}", event.device, event.detail.as_deref().unwrap_or("no active fault")),
+    };
+    let detail = event.detail.as_deref().map(|d| format!(", {}", d)).unwrap_or_default();
+    format!("{}: {:.0} {} (threshold {:.0} {}){}", event.device, event.value, unit, event.threshold, unit, detail)
+}
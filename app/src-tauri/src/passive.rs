@@ -0,0 +1,158 @@
+use crate::error::AppError;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CD_TIME: u32 = 300;
+// Nouvel essai après un renouvellement en échec, [s]
+const RETRY_DELAY_S: u64 = 10;
+
+// Renouvellement aux deux tiers du compte à rebours : il reste de quoi absorber retries et timeouts
+fn renew_after(cd_time: u32) -> Duration {
+    Duration::from_secs(u64::from(cd_time) * 2 / 3).max(Duration::from_secs(1))
+}
+
+#[derive(Clone)]
+pub struct PassiveSession {
+    pub device: String,
+    // [W] : > 0 en décharge, < 0 en charge
+    pub power: i64,
+    pub cd_time: u32,
+    // Mode lu avant la prise de contrôle, rétabli à l'arrêt
+    pub previous_mode: Option<String>,
+    // false : démarrée en ignorant la fenêtre de SOC des réglages
+    pub enforce_soc: bool,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct PassiveStatus {
+    pub active: bool,
+    pub device: Option<String>,
+    pub power: Option<i64>,
+    pub cd_time: Option<u32>,
+    pub previous_mode: Option<String>,
+    pub renewals: u64,
+    pub last_error: Option<String>,
+}
+
+struct KeeperState {
+    session: Option<PassiveSession>,
+    next_renewal: Instant,
+    renewals: u64,
+    last_error: Option<String>,
+}
+
+// Maintient le mode Passive en renvoyant ES.SetMode avant l'expiration de cd_time
+pub struct PassiveKeeper {
+    state: Mutex<KeeperState>,
+    wake: Condvar,
+    // Tenu pendant chaque écriture : un arrêt ne croise jamais un renouvellement en vol
+    writing: Mutex<()>,
+}
+
+impl Default for PassiveKeeper {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(KeeperState {
+                session: None,
+                next_renewal: Instant::now(),
+                renewals: 0,
+                last_error: None,
+            }),
+            wake: Condvar::new(),
+            writing: Mutex::new(()),
+        }
+    }
+}
+
+impl PassiveKeeper {
+    fn lock(&self) -> MutexGuard<'_, KeeperState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn exclusive(&self) -> MutexGuard<'_, ()> {
+        self.writing.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self) -> PassiveStatus {
+        let state = self.lock();
+        let session = state.session.as_ref();
+        PassiveStatus {
+            active: session.is_some(),
+            device: session.map(|s| s.device.clone()),
+            power: session.map(|s| s.power),
+            cd_time: session.map(|s| s.cd_time),
+            previous_mode: session.and_then(|s| s.previous_mode.clone()),
+            renewals: state.renewals,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    pub fn session(&self) -> Option<PassiveSession> {
+        self.lock().session.clone()
+    }
+
+    // La consigne vient d'être envoyée : prochain renvoi dans renew_after(cd_time)
+    pub fn start(&self, session: PassiveSession) {
+        let mut state = self.lock();
+        state.next_renewal = Instant::now() + renew_after(session.cd_time);
+        state.session = Some(session);
+        state.renewals = 0;
+        state.last_error = None;
+        self.wake.notify_all();
+    }
+
+    // Nouvelle consigne déjà envoyée par l'appelant (régulation, conformité)
+    pub fn set_power(&self, device: &str, power: i64) {
+        let mut state = self.lock();
+        let cd_time = match state.session.as_mut().filter(|s| s.device == device) {
+            Some(session) => {
+                session.power = power;
+                session.cd_time
+            }
+            None => return,
+        };
+        state.next_renewal = Instant::now() + renew_after(cd_time);
+    }
+
+    pub fn stop(&self) -> Option<PassiveSession> {
+        let session = self.lock().session.take();
+        self.wake.notify_all();
+        session
+    }
+
+    // Bloque jusqu'à l'échéance de la session en cours
+    pub fn wait_due(&self) -> PassiveSession {
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            state = match &state.session {
+                Some(session) if now >= state.next_renewal => return session.clone(),
+                Some(_) => {
+                    let timeout = state.next_renewal - now;
+                    self.wake.wait_timeout(state, timeout).unwrap_or_else(|e| e.into_inner()).0
+                }
+                None => self.wake.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    pub fn renewed(&self, device: &str, result: Result<(), AppError>) {
+        let mut state = self.lock();
+        let Some(cd_time) = state.session.as_ref().filter(|s| s.device == device).map(|s| s.cd_time) else { return };
+        match result {
+            Ok(()) => {
+                state.next_renewal = Instant::now() + renew_after(cd_time);
+                state.renewals += 1;
+                state.last_error = None;
+            }
+            Err(e) => {
+                tracing::warn!(device, "passive mode renewal failed: {}", e);
+                state.next_renewal = Instant::now() + Duration::from_secs(RETRY_DELAY_S);
+                state.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
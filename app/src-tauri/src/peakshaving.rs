@@ -0,0 +1,151 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub const MIN_INTERVAL_MS: u64 = 2000;
+
+// Tarif à la puissance (capacitaire) : la décharge écrête le soutirage au-delà du seuil
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PeakShavingConfig {
+    pub enabled: bool,
+    // Appareil piloté ; absent : appareil courant
+    pub device: Option<String>,
+    // Soutirage maximal visé, moyenné sur la fenêtre, [W]
+    pub threshold_w: f32,
+    // Fenêtre glissante de calcul de la pointe, [min]
+    pub window_minutes: u64,
+    pub interval_ms: u64,
+    // Variation de consigne ignorée en dessous de cet écart, [W]
+    pub deadband_w: f32,
+    pub max_discharge_w: u32,
+    // Plus de décharge à ce SOC ou en dessous, [%]
+    pub min_soc: u32,
+}
+
+impl Default for PeakShavingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            threshold_w: 2500.0,
+            window_minutes: 15,
+            interval_ms: 5000,
+            deadband_w: 50.0,
+            max_discharge_w: 2500,
+            min_soc: 10,
+        }
+    }
+}
+
+impl PeakShavingConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.interval_ms < MIN_INTERVAL_MS {
+            return Err(AppError::InvalidInput(format!("Peak shaving interval must be at least {} ms", MIN_INTERVAL_MS)));
+        }
+        if self.threshold_w <= 0.0 || self.window_minutes == 0 || self.deadband_w < 0.0 {
+            return Err(AppError::InvalidInput("threshold_w and window_minutes must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+
+    // Nouvelle consigne (> 0 en décharge) ; grid_power : soutirage mesuré, batterie comprise
+    pub fn setpoint(&self, current: i64, grid_power: f32, window_average: f32, soc: Option<u32>) -> i64 {
+        // Fenêtre déjà au-dessus du seuil : on vise plus bas pour ramener la moyenne
+        let limit = if window_average > self.threshold_w {
+            (2.0 * self.threshold_w - window_average).max(0.0)
+        } else {
+            self.threshold_w
+        };
+        let excess = grid_power - limit;
+        // Ni dépassement ni décharge en cours : une charge pilotée ailleurs n'est pas touchée
+        if current <= 0 && excess <= 0.0 {
+            return current;
+        }
+        if soc.is_some_and(|soc| soc <= self.min_soc) {
+            return current.min(0);
+        }
+        let wanted = (current as f32 + excess).clamp(0.0, self.max_discharge_w as f32);
+        if (wanted - current as f32).abs() <= self.deadband_w && wanted > 0.0 {
+            return current;
+        }
+        wanted.round() as i64
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct PeakShavingStatus {
+    pub device: Option<String>,
+    pub grid_power: Option<f32>,
+    // Moyenne glissante du soutirage, [W]
+    pub window_average: Option<f32>,
+    // Plus forte moyenne de fenêtre observée ce mois-ci, [W]
+    pub month_peak: Option<f32>,
+    pub month: Option<String>,
+    pub setpoint: Option<i64>,
+    pub last_error: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+pub struct PeakShaver {
+    config: Mutex<PeakShavingConfig>,
+    wake: Condvar,
+    samples: Mutex<VecDeque<(Instant, f32)>>,
+    status: Mutex<PeakShavingStatus>,
+}
+
+impl PeakShaver {
+    pub fn new(config: PeakShavingConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            wake: Condvar::new(),
+            samples: Mutex::new(VecDeque::new()),
+            status: Mutex::new(PeakShavingStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> PeakShavingConfig {
+        self.config.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn configure(&self, config: PeakShavingConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        self.samples.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.wake.notify_all();
+    }
+
+    pub fn status(&self) -> PeakShavingStatus {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn record(&self, status: PeakShavingStatus) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    // Ajoute une mesure et renvoie la moyenne de la fenêtre glissante
+    pub fn observe(&self, grid_power: f32, window: Duration) -> f32 {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        samples.push_back((now, grid_power));
+        while samples.front().is_some_and(|(t, _)| now.duration_since(*t) > window) {
+            samples.pop_front();
+        }
+        samples.iter().map(|(_, p)| p).sum::<f32>() / samples.len() as f32
+    }
+
+    // Même cadence que le Poller : bloque tant que l'écrêtage est désactivé
+    pub fn wait_next(&self) {
+        let mut config = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        if config.enabled {
+            let interval = Duration::from_millis(config.interval_ms);
+            config = self.wake.wait_timeout(config, interval).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        while !config.enabled {
+            config = self.wake.wait(config).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Écart moyen entre phases au-delà duquel un échantillon est considéré déséquilibré, [W]
+const IMBALANCE_THRESHOLD: f32 = 1000.0;
+// Déséquilibre chronique : plus de la moitié des échantillons au-dessus du seuil
+const CHRONIC_RATIO: f64 = 0.5;
+// Au-delà de cet écart entre deux polls, on n'intègre pas l'énergie, [s]
+const MAX_SAMPLE_GAP_S: f64 = 300.0;
+
+const PHASE_NAMES: [&str; 3] = ["A", "B", "C"];
+
+#[derive(Default, Clone, Copy)]
+struct PhaseAccumulator {
+    import_wh: f64,
+    export_wh: f64,
+    power_sum: f64,
+    min_power: f32,
+    max_power: f32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PhaseStats {
+    pub phase: &'static str,
+    pub avg_power: f64,
+    pub min_power: f32,
+    pub max_power: f32,
+    pub import_wh: f64,
+    pub export_wh: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PhaseAnalysis {
+    pub samples: u64,
+    pub phases: Vec<PhaseStats>,
+    // Part des échantillons dont l'écart entre phases dépasse le seuil
+    pub imbalance_ratio: f64,
+    pub chronic_imbalance: bool,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Default)]
+struct AnalyzerState {
+    phases: [PhaseAccumulator; 3],
+    samples: u64,
+    imbalanced_samples: u64,
+    last_sample: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct PhaseAnalyzer {
+    state: Mutex<AnalyzerState>,
+}
+
+impl PhaseAnalyzer {
+    // powers : a/b/c_power du compteur, positif en soutirage
+    pub fn observe(&self, powers: [f32; 3]) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let hours = state
+            .last_sample
+            .map(|t| now.duration_since(t).as_secs_f64())
+            .filter(|s| *s <= MAX_SAMPLE_GAP_S)
+            .map(|s| s / 3600.0)
+            .unwrap_or(0.0);
+        let first = state.samples == 0;
+
+        for (acc, power) in state.phases.iter_mut().zip(powers) {
+            let energy = power as f64 * hours;
+            if energy >= 0.0 {
+                acc.import_wh += energy;
+            } else {
+                acc.export_wh -= energy;
+            }
+            acc.power_sum += power as f64;
+            acc.min_power = if first { power } else { acc.min_power.min(power) };
+            acc.max_power = if first { power } else { acc.max_power.max(power) };
+        }
+
+        let spread = powers.iter().cloned().fold(f32::MIN, f32::max) - powers.iter().cloned().fold(f32::MAX, f32::min);
+        if spread > IMBALANCE_THRESHOLD {
+            state.imbalanced_samples += 1;
+        }
+        state.samples += 1;
+        state.last_sample = Some(now);
+    }
+
+    pub fn analysis(&self) -> PhaseAnalysis {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = state.samples.max(1) as f64;
+        let phases: Vec<PhaseStats> = state
+            .phases
+            .iter()
+            .zip(PHASE_NAMES)
+            .map(|(acc, phase)| PhaseStats {
+                phase,
+                avg_power: acc.power_sum / samples,
+                min_power: acc.min_power,
+                max_power: acc.max_power,
+                import_wh: acc.import_wh,
+                export_wh: acc.export_wh,
+            })
+            .collect();
+
+        let imbalance_ratio = if state.samples > 0 { state.imbalanced_samples as f64 / samples } else { 0.0 };
+        let chronic_imbalance = imbalance_ratio > CHRONIC_RATIO;
+
+        // Aucun appareil ne permet d'assigner sa phase via l'API locale : suggestions uniquement
+        let mut suggestions = Vec::new();
+        if chronic_imbalance {
+            let by_avg = |a: &&PhaseStats, b: &&PhaseStats| a.avg_power.total_cmp(&b.avg_power);
+            if let (Some(high), Some(low)) = (phases.iter().max_by(by_avg), phases.iter().min_by(by_avg)) {
+                suggestions.push(format!(
+                    "Phase {} draws {:.0} W more than phase {} on average: connect the battery on phase {} or move large loads to phase {}.",
+                    high.phase,
+                    high.avg_power - low.avg_power,
+                    low.phase,
+                    high.phase,
+                    low.phase
+                ));
+            }
+        }
+        for phase in phases.iter().filter(|p| p.import_wh > 0.0 && p.export_wh > p.import_wh) {
+            suggestions.push(format!(
+                "Phase {} exports more than it imports: with a balancing (saldierend) meter this is compensated, otherwise consider shifting PV output.",
+                phase.phase
+            ));
+        }
+
+        PhaseAnalysis {
+            samples: state.samples,
+            phases,
+            imbalance_ratio,
+            chronic_imbalance,
+            suggestions,
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = AnalyzerState::default();
+    }
+}
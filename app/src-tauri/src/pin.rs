@@ -0,0 +1,153 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const HASH_ROUNDS: u32 = 10_000;
+// PIN faux tolérés avant le premier délai
+const FREE_ATTEMPTS: u32 = 3;
+const FIRST_LOCKOUT: Duration = Duration::from_secs(5);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn digest(salt: &str, pin: &str) -> String {
+    let mut hash = Sha256::digest(format!("{}{}", salt, pin).as_bytes());
+    for _ in 1..HASH_ROUNDS {
+        hash = Sha256::digest(hash);
+    }
+    to_hex(&hash)
+}
+
+// Format stocké : "<sel hex>$<hash hex>"
+pub fn hash_pin(pin: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = to_hex(&salt);
+    let hash = digest(&salt, pin);
+    format!("{}${}", salt, hash)
+}
+
+pub fn verify_pin(pin: &str, stored: &str) -> bool {
+    let Some((salt, expected)) = stored.split_once('$') else { return false };
+    let actual = digest(salt, pin);
+    // Comparaison en temps constant
+    actual.len() == expected.len()
+        && actual.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub fn validate_new_pin(pin: &str) -> Result<(), String> {
+    if pin.len() < 4 || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err("PIN must contain at least 4 digits.".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+// Délai doublé à chaque PIN faux après FREE_ATTEMPTS, toutes sources confondues :
+// un PIN à 4 chiffres ne se devine plus en rafale
+#[derive(Default)]
+pub struct PinAttempts {
+    failures: Mutex<Failures>,
+}
+
+impl PinAttempts {
+    // Pendant le délai, aucun PIN n'est vérifié, même juste
+    pub fn ensure_allowed(&self) -> Result<(), String> {
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        match failures.locked_until.map(|until| until.saturating_duration_since(Instant::now())) {
+            Some(wait) if !wait.is_zero() => Err(format!("Too many invalid PIN attempts: retry in {} s.", wait.as_secs().max(1))),
+            _ => Ok(()),
+        }
+    }
+
+    // Délai imposé par cet échec, s'il y en a un
+    pub fn failed(&self) -> Option<Duration> {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.count += 1;
+        if failures.count < FREE_ATTEMPTS {
+            return None;
+        }
+        let doublings = (failures.count - FREE_ATTEMPTS).min(16);
+        let lockout = FIRST_LOCKOUT.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+        failures.locked_until = Some(Instant::now() + lockout);
+        tracing::warn!(attempts = failures.count, lockout_s = lockout.as_secs(), "invalid PIN, further attempts delayed");
+        Some(lockout)
+    }
+
+    pub fn succeeded(&self) {
+        *self.failures.lock().unwrap_or_else(|e| e.into_inner()) = Failures::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_pin_verifies_only_the_same_pin() {
+        let stored = hash_pin("2468");
+        assert!(verify_pin("2468", &stored));
+        assert!(!verify_pin("2469", &stored));
+        assert!(!verify_pin("", &stored));
+    }
+
+    #[test]
+    fn each_hash_gets_its_own_salt() {
+        let (first, second) = (hash_pin("2468"), hash_pin("2468"));
+        assert_ne!(first, second);
+        assert!(verify_pin("2468", &first) && verify_pin("2468", &second));
+    }
+
+    #[test]
+    fn malformed_stored_value_never_verifies() {
+        assert!(!verify_pin("2468", ""));
+        assert!(!verify_pin("2468", "no-separator"));
+        let salt = hash_pin("2468").split_once('$').unwrap().0.to_string();
+        assert!(!verify_pin("2468", &format!("{}$", salt)));
+        assert!(!verify_pin("2468", &format!("{}$abc", salt)));
+    }
+
+    #[test]
+    fn new_pin_needs_four_digits() {
+        assert!(validate_new_pin("1234").is_ok());
+        assert!(validate_new_pin("123").is_err());
+        assert!(validate_new_pin("12a4").is_err());
+    }
+
+    #[test]
+    fn lockout_starts_after_the_free_attempts_and_doubles() {
+        let attempts = PinAttempts::default();
+        assert_eq!(attempts.failed(), None);
+        assert_eq!(attempts.failed(), None);
+        assert!(attempts.ensure_allowed().is_ok());
+        assert_eq!(attempts.failed(), Some(FIRST_LOCKOUT));
+        assert!(attempts.ensure_allowed().is_err());
+        assert_eq!(attempts.failed(), Some(FIRST_LOCKOUT * 2));
+        assert_eq!(attempts.failed(), Some(FIRST_LOCKOUT * 4));
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let attempts = PinAttempts::default();
+        let last = (0..40).filter_map(|_| attempts.failed()).last();
+        assert_eq!(last, Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn success_clears_the_failures() {
+        let attempts = PinAttempts::default();
+        attempts.failed();
+        attempts.failed();
+        attempts.succeeded();
+        assert_eq!(attempts.failed(), None, "the count restarts after a valid PIN");
+        assert!(attempts.ensure_allowed().is_ok());
+    }
+}
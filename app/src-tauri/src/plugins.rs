@@ -0,0 +1,288 @@
+use crate::tariff::PricePoint;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const PLUGINS_DIR: &str = "plugins";
+
+// Limites du bac à sable : un script ne peut ni boucler indéfiniment ni saturer la mémoire
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 4096;
+const MAX_COLLECTION_SIZE: usize = 1024;
+// Minuteries d'un script : nombre et période minimale
+const MAX_TIMERS: usize = 16;
+const MIN_TIMER_S: i64 = 1;
+
+// Actions qu'un script peut demander ; exécutées par le backend après le script
+#[derive(Clone)]
+pub enum PluginAction {
+    SetMode(String),
+    SetPassivePower { power: i64, cd_time: i64 },
+}
+
+// Demandes collectées pendant l'exécution d'un script
+#[derive(Clone)]
+enum Request {
+    Action(PluginAction),
+    // after(s, "fn") / every(s, "fn")
+    Timer { callback: String, delay: Duration, repeat: bool },
+    Cancel(String),
+}
+
+#[derive(Serialize, Clone)]
+pub struct PluginInfo {
+    pub name: String,
+    pub enabled: bool,
+    pub last_error: Option<String>,
+    // Fonctions en attente d'une minuterie
+    pub timers: Vec<String>,
+}
+
+// Échec d'un script, émis vers l'interface (événement plugin-error)
+#[derive(Serialize, Clone)]
+pub struct PluginError {
+    pub plugin: String,
+    // on_sample ou fonction de minuterie
+    pub function: String,
+    pub error: String,
+}
+
+// Données en lecture seule offertes aux scripts, en plus du relevé
+#[derive(Default)]
+pub struct ScriptContext {
+    pub prices: Vec<PricePoint>,
+    pub currency: Option<String>,
+}
+
+// Résultat d'un passage : actions par (script, appareil) et erreurs à signaler
+#[derive(Default)]
+pub struct PluginRun {
+    pub actions: Vec<(String, String, PluginAction)>,
+    pub errors: Vec<PluginError>,
+}
+
+struct Timer {
+    callback: String,
+    due: Instant,
+    every: Option<Duration>,
+}
+
+struct Plugin {
+    name: String,
+    ast: Option<AST>,
+    enabled: bool,
+    last_error: Option<String>,
+    timers: Vec<Timer>,
+}
+
+pub struct PluginManager {
+    dir: PathBuf,
+    plugins: Mutex<Vec<Plugin>>,
+    // Dernier relevé reçu (appareil, relevé), passé aux fonctions de minuterie
+    last_sample: Mutex<Option<(String, Dynamic)>>,
+}
+
+pub fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.set_max_modules(0);
+    engine
+}
+
+fn control_engine(plugin: &str, requests: Arc<Mutex<Vec<Request>>>, context: &ScriptContext) -> Engine {
+    let mut engine = sandboxed_engine();
+    let push = move |request: Request| {
+        if let Ok(mut requests) = requests.lock() {
+            requests.push(request);
+        }
+    };
+    let push_mode = push.clone();
+    engine.register_fn("set_mode", move |mode: &str| push_mode(Request::Action(PluginAction::SetMode(mode.to_string()))));
+    let push_passive = push.clone();
+    engine.register_fn("set_passive_power", move |power: i64, cd_time: i64| {
+        push_passive(Request::Action(PluginAction::SetPassivePower { power, cd_time }))
+    });
+    let push_after = push.clone();
+    engine.register_fn("after", move |seconds: i64, callback: &str| {
+        let delay = Duration::from_secs(seconds.max(MIN_TIMER_S) as u64);
+        push_after(Request::Timer { callback: callback.to_string(), delay, repeat: false })
+    });
+    let push_every = push.clone();
+    engine.register_fn("every", move |seconds: i64, callback: &str| {
+        let delay = Duration::from_secs(seconds.max(MIN_TIMER_S) as u64);
+        push_every(Request::Timer { callback: callback.to_string(), delay, repeat: true })
+    });
+    engine.register_fn("cancel", move |callback: &str| push(Request::Cancel(callback.to_string())));
+
+    // Prix du jour : [#{start, price}], et celui de l'heure en cours (() sans tarif dynamique)
+    let prices: Array = context
+        .prices
+        .iter()
+        .map(|p| {
+            let mut point = Map::new();
+            point.insert("start".into(), p.start.clone().into());
+            point.insert("price".into(), p.price.into());
+            Dynamic::from_map(point)
+        })
+        .collect();
+    let current = chrono::Local::now().format("%Y-%m-%dT%H").to_string();
+    let current_price = context.prices.iter().find(|p| p.start.starts_with(&current)).map_or(Dynamic::UNIT, |p| p.price.into());
+    let currency = context.currency.clone().map_or(Dynamic::UNIT, Dynamic::from);
+    engine.register_fn("prices", move || prices.clone());
+    engine.register_fn("current_price", move || current_price.clone());
+    engine.register_fn("currency", move || currency.clone());
+    engine.register_fn("now", || chrono::Local::now().timestamp());
+
+    let name = plugin.to_string();
+    engine.on_print(move |message| tracing::info!(plugin = %name, "{}", message));
+    let name = plugin.to_string();
+    engine.on_debug(move |message, _, _| tracing::debug!(plugin = %name, "{}", message));
+    engine
+}
+
+impl Plugin {
+    // Exécute `function(sample)` et applique les demandes de minuterie ; les actions sont renvoyées
+    fn run(&mut self, function: &str, device: &str, sample: &Dynamic, context: &ScriptContext, run: &mut PluginRun) {
+        let Some(ast) = &self.ast else { return };
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let engine = control_engine(&self.name, requests.clone(), context);
+        let mut scope = Scope::new();
+
+        if let Err(e) = engine.call_fn::<Dynamic>(&mut scope, ast, function, (sample.clone(),)) {
+            tracing::warn!("plugin {} failed in {}: {}", self.name, function, e);
+            self.last_error = Some(e.to_string());
+            run.errors.push(PluginError { plugin: self.name.clone(), function: function.to_string(), error: e.to_string() });
+            return;
+        }
+        self.last_error = None;
+        let requests = requests.lock().map(|r| r.clone()).unwrap_or_default();
+        for request in requests {
+            match request {
+                Request::Action(action) => run.actions.push((self.name.clone(), device.to_string(), action)),
+                Request::Timer { callback, delay, repeat } => {
+                    self.timers.retain(|t| t.callback != callback);
+                    if self.timers.len() >= MAX_TIMERS {
+                        let error = format!("too many timers (at most {})", MAX_TIMERS);
+                        run.errors.push(PluginError { plugin: self.name.clone(), function: callback, error });
+                        continue;
+                    }
+                    self.timers.push(Timer { callback, due: Instant::now() + delay, every: repeat.then_some(delay) });
+                }
+                Request::Cancel(callback) => self.timers.retain(|t| t.callback != callback),
+            }
+        }
+    }
+}
+
+impl PluginManager {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, plugins: Mutex::new(Vec::new()), last_sample: Mutex::new(None) }
+    }
+
+    // Recharge tous les fichiers *.rhai du dossier plugins ; les minuteries repartent de zéro
+    pub fn reload(&self, enabled: &[String]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let engine = sandboxed_engine();
+        let mut plugins = Vec::new();
+
+        for entry in fs::read_dir(&self.dir).map_err(|e| e.to_string())?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("rhai") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|n| n.to_str()).map(String::from) else { continue };
+            let (ast, last_error) = match engine.compile_file(path) {
+                Ok(ast) => (Some(ast), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            plugins.push(Plugin {
+                enabled: enabled.contains(&name),
+                name,
+                ast,
+                last_error,
+                timers: Vec::new(),
+            });
+        }
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+
+        *self.plugins.lock().map_err(|e| e.to_string())? = plugins;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        let Ok(plugins) = self.plugins.lock() else { return Vec::new() };
+        plugins
+            .iter()
+            .map(|p| PluginInfo {
+                name: p.name.clone(),
+                enabled: p.enabled,
+                last_error: p.last_error.clone(),
+                timers: p.timers.iter().map(|t| t.callback.clone()).collect(),
+            })
+            .collect()
+    }
+
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), String> {
+        let mut plugins = self.plugins.lock().map_err(|e| e.to_string())?;
+        let plugin = plugins
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!("Unknown plugin: {}", name))?;
+        plugin.enabled = enabled;
+        if !enabled {
+            plugin.timers.clear();
+        }
+        Ok(())
+    }
+
+    // Appelle on_sample(sample) dans chaque script actif et collecte les actions demandées
+    pub fn on_sample(&self, device: &str, sample: &serde_json::Value, context: &ScriptContext) -> PluginRun {
+        let mut run = PluginRun::default();
+        let Ok(mut plugins) = self.plugins.lock() else { return run };
+        let Ok(sample) = rhai::serde::to_dynamic(sample) else { return run };
+        *self.last_sample.lock().unwrap_or_else(|e| e.into_inner()) = Some((device.to_string(), sample.clone()));
+
+        for plugin in plugins.iter_mut().filter(|p| p.enabled) {
+            plugin.run("on_sample", device, &sample, context, &mut run);
+        }
+        run
+    }
+
+    // Au moins une minuterie échue : évite de préparer le contexte pour rien
+    pub fn timers_due(&self) -> bool {
+        let now = Instant::now();
+        let Ok(plugins) = self.plugins.lock() else { return false };
+        plugins.iter().filter(|p| p.enabled).any(|p| p.timers.iter().any(|t| t.due <= now))
+    }
+
+    // Appelle les fonctions des minuteries échues avec le dernier relevé ; rien tant qu'aucun relevé n'est arrivé
+    pub fn tick(&self, context: &ScriptContext) -> PluginRun {
+        let mut run = PluginRun::default();
+        let Some((device, sample)) = self.last_sample.lock().unwrap_or_else(|e| e.into_inner()).clone() else { return run };
+        let Ok(mut plugins) = self.plugins.lock() else { return run };
+        let now = Instant::now();
+        for plugin in plugins.iter_mut().filter(|p| p.enabled) {
+            let due: Vec<String> = plugin.timers.iter().filter(|t| t.due <= now).map(|t| t.callback.clone()).collect();
+            plugin.timers.retain_mut(|t| match t.every {
+                _ if t.due > now => true,
+                Some(every) => {
+                    t.due = now + every;
+                    true
+                }
+                None => false,
+            });
+            for callback in due {
+                plugin.run(&callback, &device, &sample, context, &mut run);
+            }
+        }
+        run
+    }
+}
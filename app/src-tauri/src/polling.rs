@@ -0,0 +1,249 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub const MIN_INTERVAL_MS: u64 = 1000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PollingConfig {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    // Remplace interval_ms par une cadence rapide ou lente selon l'activité
+    pub adaptive: AdaptivePolling,
+    pub sections: SectionIntervals,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self { enabled: false, interval_ms: 5000, adaptive: AdaptivePolling::default(), sections: SectionIntervals::default() }
+    }
+}
+
+// Délai minimal entre deux lectures de chaque section par le polling, [s] ; 0 : à chaque cycle.
+// Une section pas encore due reprend la valeur du relevé précédent.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SectionIntervals {
+    pub device_s: u64,
+    pub battery_s: u64,
+    pub energy_s: u64,
+    pub meter_s: u64,
+    pub wifi_s: u64,
+    pub mode_s: u64,
+}
+
+impl Default for SectionIntervals {
+    fn default() -> Self {
+        Self { device_s: 600, battery_s: 0, energy_s: 0, meter_s: 0, wifi_s: 120, mode_s: 30 }
+    }
+}
+
+impl SectionIntervals {
+    fn interval(&self, section: &str) -> Duration {
+        let seconds = match section {
+            "device" => self.device_s,
+            "battery" => self.battery_s,
+            "energy" => self.energy_s,
+            "meter" => self.meter_s,
+            "wifi" => self.wifi_s,
+            "mode" => self.mode_s,
+            _ => 0,
+        };
+        Duration::from_secs(seconds)
+    }
+}
+
+impl PollingConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let shortest = [self.interval_ms, self.adaptive.fast_interval_ms, self.adaptive.idle_interval_ms].into_iter().min().unwrap_or_default();
+        if shortest < MIN_INTERVAL_MS {
+            return Err(AppError::InvalidInput(format!("Polling interval must be at least {} ms", MIN_INTERVAL_MS)));
+        }
+        if self.adaptive.fast_interval_ms > self.adaptive.idle_interval_ms {
+            return Err(AppError::InvalidInput("The fast polling interval must not exceed the idle one".to_string()));
+        }
+        if self.adaptive.change_threshold_w < 0.0 {
+            return Err(AppError::InvalidInput("Power change threshold must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AdaptivePolling {
+    pub enabled: bool,
+    // Tant que les puissances bougent ou qu'une boucle de régulation tourne
+    pub fast_interval_ms: u64,
+    // Système au repos, la nuit typiquement
+    pub idle_interval_ms: u64,
+    // Variation d'une puissance entre deux relevés comptée comme activité, [W]
+    pub change_threshold_w: f32,
+    // Sans activité pendant ce délai, on passe à la cadence lente, [s]
+    pub idle_after_s: u64,
+}
+
+impl Default for AdaptivePolling {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fast_interval_ms: 3000,
+            idle_interval_ms: 60_000,
+            change_threshold_w: 50.0,
+            idle_after_s: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateReason {
+    // interval_ms, polling adaptatif désactivé
+    Fixed,
+    Manual,
+    Active,
+    Idle,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PollRate {
+    pub interval_ms: u64,
+    pub reason: RateReason,
+}
+
+struct Cadence {
+    config: PollingConfig,
+    // Puissances du relevé précédent, comparées au suivant
+    last_powers: Vec<Option<f32>>,
+    // Absent : pas encore de relevé, on démarre en cadence rapide
+    last_active: Option<Instant>,
+    // set_poll_rate : prioritaire jusqu'à son retrait, non enregistré
+    manual_ms: Option<u64>,
+}
+
+impl Cadence {
+    fn rate(&self) -> PollRate {
+        let adaptive = &self.config.adaptive;
+        if let Some(interval_ms) = self.manual_ms {
+            return PollRate { interval_ms, reason: RateReason::Manual };
+        }
+        if !adaptive.enabled {
+            return PollRate { interval_ms: self.config.interval_ms, reason: RateReason::Fixed };
+        }
+        let idle_after = Duration::from_secs(adaptive.idle_after_s);
+        match self.last_active {
+            Some(at) if at.elapsed() >= idle_after => PollRate { interval_ms: adaptive.idle_interval_ms, reason: RateReason::Idle },
+            _ => PollRate { interval_ms: adaptive.fast_interval_ms, reason: RateReason::Active },
+        }
+    }
+}
+
+// Cadence du thread de polling ; start/stop le réveillent immédiatement
+pub struct Poller {
+    cadence: Mutex<Cadence>,
+    wake: Condvar,
+    // Changements de mode en cours : on ne lit pas un état intermédiaire
+    paused: AtomicUsize,
+    // Dernière lecture réussie par (appareil, section)
+    sections: Mutex<HashMap<(String, String), Instant>>,
+}
+
+pub struct PauseGuard<'a> {
+    poller: &'a Poller,
+}
+
+impl Drop for PauseGuard<'_> {
+    fn drop(&mut self) {
+        self.poller.paused.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Poller {
+    pub fn new(config: PollingConfig) -> Self {
+        Self {
+            cadence: Mutex::new(Cadence { config, last_powers: Vec::new(), last_active: None, manual_ms: None }),
+            wake: Condvar::new(),
+            paused: AtomicUsize::new(0),
+            sections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cadence> {
+        self.cadence.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn config(&self) -> PollingConfig {
+        self.lock().config.clone()
+    }
+
+    pub fn configure(&self, config: PollingConfig) {
+        self.lock().config = config;
+        self.wake.notify_all();
+    }
+
+    pub fn rate(&self) -> PollRate {
+        self.lock().rate()
+    }
+
+    // None : retour à la cadence automatique
+    pub fn set_manual_rate(&self, interval_ms: Option<u64>) -> Result<PollRate, AppError> {
+        if interval_ms.is_some_and(|ms| ms < MIN_INTERVAL_MS) {
+            return Err(AppError::InvalidInput(format!("Polling interval must be at least {} ms", MIN_INTERVAL_MS)));
+        }
+        let mut cadence = self.lock();
+        cadence.manual_ms = interval_ms;
+        self.wake.notify_all();
+        Ok(cadence.rate())
+    }
+
+    // Relevé réussi : une puissance qui bouge ou une régulation en cours maintient la cadence rapide
+    pub fn observe(&self, powers: Vec<Option<f32>>, control_active: bool) {
+        let mut cadence = self.lock();
+        let threshold = cadence.config.adaptive.change_threshold_w;
+        let changed = powers.len() != cadence.last_powers.len()
+            || powers.iter().zip(&cadence.last_powers).any(|(new, old)| match (new, old) {
+                (Some(new), Some(old)) => (new - old).abs() >= threshold,
+                (new, old) => new.is_some() != old.is_some(),
+            });
+        if changed || control_active || cadence.last_active.is_none() {
+            cadence.last_active = Some(Instant::now());
+        }
+        cadence.last_powers = powers;
+    }
+
+    pub fn section_due(&self, device: &str, section: &str) -> bool {
+        let interval = self.lock().config.sections.interval(section);
+        let sections = self.sections.lock().unwrap_or_else(|e| e.into_inner());
+        sections.get(&(device.to_string(), section.to_string())).is_none_or(|at| at.elapsed() >= interval)
+    }
+
+    pub fn section_polled(&self, device: &str, section: &str) {
+        let mut sections = self.sections.lock().unwrap_or_else(|e| e.into_inner());
+        sections.insert((device.to_string(), section.to_string()), Instant::now());
+    }
+
+    pub fn pause(&self) -> PauseGuard<'_> {
+        self.paused.fetch_add(1, Ordering::SeqCst);
+        PauseGuard { poller: self }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst) > 0
+    }
+
+    // Bloque jusqu'au prochain cycle : intervalle écoulé, ou polling (re)démarré
+    pub fn wait_next(&self) {
+        let mut cadence = self.lock();
+        if cadence.config.enabled {
+            let interval = Duration::from_millis(cadence.rate().interval_ms);
+            cadence = self.wake.wait_timeout(cadence, interval).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        while !cadence.config.enabled {
+            cadence = self.wake.wait(cadence).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
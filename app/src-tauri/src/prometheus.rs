@@ -0,0 +1,182 @@
+use crate::metrics::MethodStatsEntry;
+use crate::DashboardData;
+use std::fmt::Write;
+use std::time::Instant;
+
+// Dernier snapshot d'un appareil, tel qu'exposé sur /metrics
+pub struct DeviceSnapshot {
+    pub device: String,
+    pub ip: String,
+    pub model: Option<String>,
+    pub data: DashboardData,
+    // Sert l'âge du relevé quand il est rendu faute de mieux
+    pub collected_at: Instant,
+}
+
+struct Metric<T> {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&T) -> Option<f64>,
+}
+
+// Noms stables : toute modification casse les dashboards Grafana existants.
+// Labels de chaque série d'appareil : device (id du registre), ip, model.
+const DEVICE_METRICS: &[Metric<DashboardData>] = &[
+    Metric {
+        name: "marstip_battery_soc_percent",
+        kind: "gauge",
+        help: "Battery state of charge.",
+        value: |d| d.battery.soc.or(d.energy.bat_soc).map(f64::from),
+    },
+    Metric {
+        name: "marstip_battery_temperature_celsius",
+        kind: "gauge",
+        help: "Battery temperature.",
+        value: |d| d.battery.bat_temp.map(f64::from),
+    },
+    Metric {
+        name: "marstip_pv_power_watts",
+        kind: "gauge",
+        help: "PV power, including external inverters.",
+        value: |d| d.energy.pv_power.map(f64::from),
+    },
+    Metric {
+        name: "marstip_battery_power_watts",
+        kind: "gauge",
+        help: "Battery power reported by ES.GetStatus.",
+        value: |d| d.energy.bat_power.map(f64::from),
+    },
+    Metric {
+        name: "marstip_ongrid_power_watts",
+        kind: "gauge",
+        help: "On-grid port power, positive when discharging.",
+        value: |d| d.energy.ongrid_power.map(f64::from),
+    },
+    Metric {
+        name: "marstip_offgrid_power_watts",
+        kind: "gauge",
+        help: "Off-grid (backup) port power.",
+        value: |d| d.energy.offgrid_power.map(f64::from),
+    },
+    Metric {
+        name: "marstip_grid_power_watts",
+        kind: "gauge",
+        help: "CT meter total power, negative when exporting.",
+        value: |d| d.meter.as_ref().and_then(|m| m.total_power).map(f64::from),
+    },
+    Metric {
+        name: "marstip_pv_energy_watt_hours_total",
+        kind: "counter",
+        help: "Cumulative PV energy.",
+        value: |d| d.energy.total_pv_energy.map(f64::from),
+    },
+    Metric {
+        name: "marstip_grid_output_energy_watt_hours_total",
+        kind: "counter",
+        help: "Cumulative energy sent to the grid port.",
+        value: |d| d.energy.total_grid_output_energy.map(f64::from),
+    },
+    Metric {
+        name: "marstip_grid_input_energy_watt_hours_total",
+        kind: "counter",
+        help: "Cumulative energy drawn from the grid port.",
+        value: |d| d.energy.total_grid_input_energy.map(f64::from),
+    },
+    Metric {
+        name: "marstip_load_energy_watt_hours_total",
+        kind: "counter",
+        help: "Cumulative load energy.",
+        value: |d| d.energy.total_load_energy.map(f64::from),
+    },
+    Metric {
+        name: "marstip_self_consumption_percent",
+        kind: "gauge",
+        help: "Share of PV production consumed on site.",
+        value: |d| d.kpis.self_consumption,
+    },
+    Metric {
+        name: "marstip_self_sufficiency_percent",
+        kind: "gauge",
+        help: "Share of household load covered without grid import.",
+        value: |d| d.kpis.self_sufficiency,
+    },
+    Metric {
+        name: "marstip_round_trip_efficiency_percent",
+        kind: "gauge",
+        help: "Lifetime battery round-trip efficiency from the grid port energy counters.",
+        value: |d| d.kpis.round_trip_efficiency,
+    },
+];
+
+// Latence des requêtes UDP, label method (méthode JSON-RPC envoyée à l'appareil)
+const REQUEST_METRICS: &[Metric<MethodStatsEntry>] = &[
+    Metric {
+        name: "marstip_device_requests_total",
+        kind: "counter",
+        help: "Device requests sent.",
+        value: |s| Some(s.calls as f64),
+    },
+    Metric {
+        name: "marstip_device_request_failures_total",
+        kind: "counter",
+        help: "Device requests that failed or timed out.",
+        value: |s| Some(s.failures as f64),
+    },
+    Metric {
+        name: "marstip_device_request_duration_seconds_avg",
+        kind: "gauge",
+        help: "Average request latency.",
+        value: |s| Some(s.avg_ms / 1000.0),
+    },
+    Metric {
+        name: "marstip_device_request_duration_seconds_max",
+        kind: "gauge",
+        help: "Maximum request latency.",
+        value: |s| Some(s.max_ms / 1000.0),
+    },
+    Metric {
+        name: "marstip_device_request_duration_seconds_last",
+        kind: "gauge",
+        help: "Latency of the last request.",
+        value: |s| Some(s.last_ms / 1000.0),
+    },
+];
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Format texte d'exposition Prometheus 0.0.4
+pub fn render(snapshots: &[&DeviceSnapshot], stats: &[MethodStatsEntry]) -> String {
+    let mut out = String::new();
+    for metric in DEVICE_METRICS {
+        header(&mut out, metric.name, metric.kind, metric.help);
+        for snapshot in snapshots {
+            let Some(value) = (metric.value)(&snapshot.data) else { continue };
+            let _ = writeln!(
+                out,
+                "{}{{device=\"{}\",ip=\"{}\",model=\"{}\"}} {}",
+                metric.name,
+                escape(&snapshot.device),
+                escape(&snapshot.ip),
+                escape(snapshot.model.as_deref().unwrap_or("")),
+                value
+            );
+        }
+    }
+
+    for metric in REQUEST_METRICS {
+        header(&mut out, metric.name, metric.kind, metric.help);
+        for entry in stats {
+            let Some(value) = (metric.value)(entry) else { continue };
+            let _ = writeln!(out, "{}{{method=\"{}\"}} {}", metric.name, escape(&entry.method), value);
+        }
+    }
+    out
+}
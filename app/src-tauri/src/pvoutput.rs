@@ -0,0 +1,246 @@
+use crate::error::AppError;
+use crate::kpi;
+use crate::DashboardData;
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const QUEUE_FILE: &str = "pvoutput-queue.json";
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+const BATCH_URL: &str = "https://pvoutput.org/service/r2/addbatchstatus.jsp";
+// Limite de addbatchstatus pour un compte sans donation
+const MAX_BATCH: usize = 30;
+// PVOutput refuse les statuts de plus de 14 jours : inutile de les garder au-delà
+const MAX_AGE_DAYS: i64 = 13;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PvOutputConfig {
+    pub enabled: bool,
+    pub system_id: String,
+    // Uniquement si le trousseau système est indisponible
+    pub api_key: Option<String>,
+    // Intervalle des statuts, à régler comme celui du système sur pvoutput.org, [min]
+    pub interval_min: u32,
+    // Appareil envoyé ; absent : l'appareil sélectionné
+    pub device: Option<String>,
+    // SOC en valeur étendue v7 (réservé aux comptes donateurs)
+    pub soc_extended: bool,
+}
+
+impl Default for PvOutputConfig {
+    fn default() -> Self {
+        Self { enabled: false, system_id: String::new(), api_key: None, interval_min: 5, device: None, soc_extended: false }
+    }
+}
+
+impl PvOutputConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if ![5, 10, 15].contains(&self.interval_min) {
+            return Err(AppError::InvalidInput("PVOutput status interval must be 5, 10 or 15 minutes".to_string()));
+        }
+        if self.enabled && (self.system_id.is_empty() || !self.system_id.chars().all(|c| c.is_ascii_digit())) {
+            return Err(AppError::InvalidInput(format!("Invalid PVOutput system id: {}", self.system_id)));
+        }
+        Ok(())
+    }
+}
+
+// Moyenne d'un intervalle, horodatée à sa fin comme le veut PVOutput
+#[derive(Serialize, Deserialize, Clone)]
+struct Status {
+    timestamp: i64,
+    generation_w: f64,
+    consumption_w: Option<f64>,
+    soc: Option<u32>,
+}
+
+impl Status {
+    // d,t,v1,v2,v3,v4,v5,v6[,v7] ; -1 : valeur absente
+    fn csv(&self, soc_extended: bool) -> String {
+        let time = Local.timestamp_opt(self.timestamp, 0).single().unwrap_or_default();
+        let consumption = self.consumption_w.map_or("-1".to_string(), |w| format!("{:.0}", w));
+        let mut line = format!("{},-1,{:.0},-1,{},-1,-1", time.format("%Y%m%d,%H:%M"), self.generation_w, consumption);
+        if let (true, Some(soc)) = (soc_extended, self.soc) {
+            line.push_str(&format!(",{}", soc));
+        }
+        line
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    start: i64,
+    samples: u32,
+    generation: f64,
+    consumption: f64,
+    consumption_samples: u32,
+    soc: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct PvOutputStatus {
+    pub buffered: usize,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+}
+
+struct UploaderState {
+    config: PvOutputConfig,
+    api_key: Option<String>,
+    slot: Option<Slot>,
+    queue: VecDeque<Status>,
+    status: PvOutputStatus,
+}
+
+// Statuts en attente conservés sur disque : une coupure de l'app ne perd pas les intervalles non envoyés
+pub struct PvOutputUploader {
+    path: PathBuf,
+    state: Mutex<UploaderState>,
+}
+
+fn send(config: &PvOutputConfig, api_key: &str, batch: &[Status]) -> Result<(), AppError> {
+    let data = batch.iter().map(|s| s.csv(config.soc_extended)).collect::<Vec<_>>().join(";");
+    let response = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .and_then(|client| {
+            client
+                .post(BATCH_URL)
+                .header("X-Pvoutput-Apikey", api_key)
+                .header("X-Pvoutput-SystemId", &config.system_id)
+                .form(&[("data", data)])
+                .send()
+        })
+        .map_err(|e| if e.is_timeout() { AppError::Timeout(e.to_string()) } else { AppError::IoError(e.to_string()) })?;
+    let status = response.status();
+    let body = response.text().unwrap_or_default();
+    match status.as_u16() {
+        200 => Ok(()),
+        400 => Err(AppError::InvalidInput(format!("PVOutput rejected the batch: {}", body.trim()))),
+        401 | 403 => Err(AppError::Forbidden(format!("PVOutput: {}", body.trim()))),
+        _ => Err(AppError::IoError(format!("PVOutput returned HTTP {}: {}", status, body.trim()))),
+    }
+}
+
+impl PvOutputUploader {
+    pub fn open(path: PathBuf) -> Self {
+        let queue = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            state: Mutex::new(UploaderState {
+                config: PvOutputConfig::default(),
+                api_key: None,
+                slot: None,
+                queue,
+                status: PvOutputStatus::default(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UploaderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn configure(&self, config: &PvOutputConfig, api_key: Option<String>) {
+        let mut state = self.lock();
+        state.config = config.clone();
+        state.api_key = api_key;
+        state.slot = None;
+        if !config.enabled {
+            state.queue.clear();
+            self.save(&state.queue);
+        }
+        state.status = PvOutputStatus::default();
+    }
+
+    pub fn status(&self) -> PvOutputStatus {
+        let state = self.lock();
+        PvOutputStatus { buffered: state.queue.len(), ..state.status.clone() }
+    }
+
+    // selected : l'appareil est celui sélectionné dans l'app (config.device absent)
+    pub fn push(&self, device: &str, selected: bool, data: &DashboardData, now: DateTime<Local>) {
+        let mut state = self.lock();
+        let config = state.config.clone();
+        if !config.enabled || !config.device.as_deref().map_or(selected, |d| d == device) {
+            return;
+        }
+        let interval = i64::from(config.interval_min) * 60;
+        let start = now.timestamp() - now.timestamp().rem_euclid(interval);
+        if state.slot.as_ref().is_some_and(|s| s.start != start) {
+            if let Some(slot) = state.slot.take().filter(|s| s.samples > 0) {
+                state.queue.push_back(Status {
+                    timestamp: slot.start + interval,
+                    generation_w: slot.generation / f64::from(slot.samples),
+                    consumption_w: (slot.consumption_samples > 0).then(|| slot.consumption / f64::from(slot.consumption_samples)),
+                    soc: slot.soc,
+                });
+                self.flush(&mut state);
+            }
+        }
+
+        let slot = state.slot.get_or_insert_with(|| Slot { start, ..Default::default() });
+        let pv = f64::from(data.energy.pv_power.unwrap_or(0.0));
+        slot.samples += 1;
+        slot.generation += pv;
+        if let Some(grid) = data.meter.as_ref().and_then(|m| m.total_power) {
+            slot.consumption += kpi::load(pv, f64::from(grid), f64::from(data.energy.bat_power.unwrap_or(0.0)));
+            slot.consumption_samples += 1;
+        }
+        slot.soc = data.battery.soc.or(data.energy.bat_soc).or(slot.soc);
+    }
+
+    // Une tentative par intervalle terminé : pendant une panne, la file grossit et repart d'un bloc au retour
+    fn flush(&self, state: &mut UploaderState) {
+        let oldest = Local::now().timestamp() - MAX_AGE_DAYS * 86_400;
+        state.queue.retain(|s| s.timestamp >= oldest);
+        let Some(api_key) = state.api_key.clone().filter(|k| !k.is_empty()) else {
+            state.status.last_error = Some("PVOutput API key is not set".to_string());
+            self.save(&state.queue);
+            return;
+        };
+        while !state.queue.is_empty() {
+            let count = state.queue.len().min(MAX_BATCH);
+            let batch: Vec<Status> = state.queue.iter().take(count).cloned().collect();
+            match send(&state.config, &api_key, &batch) {
+                Ok(()) => {
+                    state.queue.drain(..count);
+                    state.status.last_success = Some(Local::now().to_rfc3339());
+                    state.status.last_error = None;
+                }
+                // Données refusées : les renvoyer ne changerait rien
+                Err(AppError::InvalidInput(e)) => {
+                    tracing::warn!("dropping {} PVOutput statuses: {}", count, e);
+                    state.queue.drain(..count);
+                    state.status.last_error = Some(e);
+                }
+                Err(e) => {
+                    tracing::warn!("PVOutput upload failed ({} statuses buffered): {}", state.queue.len(), e);
+                    state.status.last_error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        self.save(&state.queue);
+    }
+
+    fn save(&self, queue: &VecDeque<Status>) {
+        let result = serde_json::to_string(queue).map_err(|e| e.to_string()).and_then(|content| {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            fs::write(&self.path, content).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            tracing::warn!("failed to write PVOutput queue: {}", e);
+        }
+    }
+}
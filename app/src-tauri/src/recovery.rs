@@ -0,0 +1,117 @@
+use crate::discovery;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+// Écart au-delà duquel un intervalle de surveillance trop long signale une mise en veille
+const SLEEP_GAP: Duration = Duration::from_secs(30);
+
+// Recrée les sockets et relocalise les appareils après une veille, un changement de réseau ou des échecs répétés
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    pub enabled: bool,
+    // Vérification de la veille et des adresses locales, [s]
+    pub check_interval_s: u64,
+    // Relevés du polling en échec d'affilée avant une reprise ; 0 : pas de reprise sur échecs
+    pub failures_before_recovery: u32,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self { enabled: true, check_interval_s: 10, failures_before_recovery: 5 }
+    }
+}
+
+impl RecoveryConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.check_interval_s == 0 {
+            return Err(AppError::InvalidInput("Network check interval must be at least 1 s".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryReason {
+    Resumed,
+    NetworkChanged,
+    RepeatedFailures,
+}
+
+// Événement "network-recovered"
+#[derive(Serialize, Clone)]
+pub struct Recovery {
+    pub reason: RecoveryReason,
+    // Appareils relocalisés par la redécouverte
+    pub devices_found: usize,
+    pub timestamp: String,
+}
+
+struct Watch {
+    checked: Option<(Instant, SystemTime)>,
+    // Adresses IPv4 locales (interface, adresse), triées
+    addresses: Option<Vec<(String, String)>>,
+    failures: u32,
+}
+
+pub struct NetworkWatch {
+    watch: Mutex<Watch>,
+}
+
+impl Default for NetworkWatch {
+    fn default() -> Self {
+        Self { watch: Mutex::new(Watch { checked: None, addresses: None, failures: 0 }) }
+    }
+}
+
+fn local_addresses() -> Vec<(String, String)> {
+    let mut addresses: Vec<_> = discovery::interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| (i.name, i.ip.to_string()))
+        .collect();
+    addresses.sort();
+    addresses
+}
+
+impl NetworkWatch {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Watch> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Appelée toutes les check_interval_s. L'horloge murale avance pendant la veille et pas
+    // l'horloge monotone sous Linux et macOS ; sous Windows c'est l'intervalle lui-même qui s'allonge.
+    pub fn check(&self, config: &RecoveryConfig) -> Option<RecoveryReason> {
+        let mut watch = self.lock();
+        let now = (Instant::now(), SystemTime::now());
+        let resumed = watch.checked.is_some_and(|(mono, wall)| {
+            let mono_elapsed = mono.elapsed();
+            let wall_elapsed = now.1.duration_since(wall).unwrap_or_default();
+            wall_elapsed.saturating_sub(mono_elapsed) > SLEEP_GAP || mono_elapsed > Duration::from_secs(config.check_interval_s) + SLEEP_GAP
+        });
+        watch.checked = Some(now);
+
+        let addresses = local_addresses();
+        let changed = watch.addresses.as_ref().is_some_and(|previous| *previous != addresses);
+        watch.addresses = Some(addresses);
+        match (resumed, changed) {
+            (true, _) => Some(RecoveryReason::Resumed),
+            (false, true) => Some(RecoveryReason::NetworkChanged),
+            (false, false) => None,
+        }
+    }
+
+    // Résultat d'un relevé du polling ; renvoie la reprise à lancer au seuil d'échecs
+    pub fn record_poll(&self, ok: bool, config: &RecoveryConfig) -> Option<RecoveryReason> {
+        let mut watch = self.lock();
+        watch.failures = if ok { 0 } else { watch.failures + 1 };
+        if config.failures_before_recovery == 0 || watch.failures < config.failures_before_recovery {
+            return None;
+        }
+        watch.failures = 0;
+        Some(RecoveryReason::RepeatedFailures)
+    }
+}
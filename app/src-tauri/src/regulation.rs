@@ -0,0 +1,141 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+pub const MIN_INTERVAL_MS: u64 = 2000;
+
+// Régulation "zéro injection" : la consigne Passive suit la puissance mesurée au point de raccordement
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RegulationConfig {
+    pub enabled: bool,
+    // Appareil régulé ; absent : appareil courant
+    pub device: Option<String>,
+    pub interval_ms: u64,
+    // Puissance réseau visée, [W] (> 0 : léger soutirage pour ne jamais injecter)
+    pub target_w: f32,
+    // Écart toléré autour de la cible avant de corriger, [W]
+    pub deadband_w: f32,
+    // Variation maximale de la consigne, [W/s]
+    pub ramp_w_per_s: f32,
+    pub max_charge_w: u32,
+    pub max_discharge_w: u32,
+    // Plus de décharge à ce SOC ou en dessous, [%]
+    pub min_soc: u32,
+    // Plus de charge à ce SOC ou au-dessus, [%]
+    pub max_soc: u32,
+}
+
+impl Default for RegulationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            interval_ms: 5000,
+            target_w: 0.0,
+            deadband_w: 30.0,
+            ramp_w_per_s: 100.0,
+            max_charge_w: 2500,
+            max_discharge_w: 800,
+            min_soc: 10,
+            max_soc: 100,
+        }
+    }
+}
+
+impl RegulationConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.interval_ms < MIN_INTERVAL_MS {
+            return Err(AppError::InvalidInput(format!("Regulation interval must be at least {} ms", MIN_INTERVAL_MS)));
+        }
+        if self.deadband_w < 0.0 || self.ramp_w_per_s <= 0.0 {
+            return Err(AppError::InvalidInput("deadband_w must be >= 0 and ramp_w_per_s > 0".to_string()));
+        }
+        if self.min_soc >= self.max_soc || self.max_soc > 100 {
+            return Err(AppError::InvalidInput(format!("Invalid SOC window {}-{} %", self.min_soc, self.max_soc)));
+        }
+        Ok(())
+    }
+
+    // Nouvelle consigne (> 0 en décharge), ou None pour garder `current`
+    // grid_power : > 0 en soutirage, < 0 en injection
+    pub fn next_setpoint(&self, current: i64, grid_power: f32, soc: Option<u32>) -> Option<i64> {
+        let mut low = -(self.max_charge_w as f32);
+        let mut high = self.max_discharge_w as f32;
+        if soc.is_some_and(|soc| soc <= self.min_soc) {
+            high = high.min(0.0);
+        }
+        if soc.is_some_and(|soc| soc >= self.max_soc) {
+            low = low.max(0.0);
+        }
+        let current = current as f32;
+        let error = grid_power - self.target_w;
+        // Dans la bande morte on ne bouge que si la consigne sort des limites (SOC atteint)
+        let wanted = if error.abs() <= self.deadband_w {
+            current.clamp(low, high)
+        } else {
+            let step = self.ramp_w_per_s * Duration::from_millis(self.interval_ms).as_secs_f32();
+            (current + error).clamp(current - step, current + step).clamp(low, high)
+        };
+        let wanted = wanted.round() as i64;
+        (wanted != current as i64).then_some(wanted)
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct RegulationStatus {
+    pub device: Option<String>,
+    pub grid_power: Option<f32>,
+    pub soc: Option<u32>,
+    pub setpoint: Option<i64>,
+    pub last_error: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+pub struct Regulator {
+    config: Mutex<RegulationConfig>,
+    wake: Condvar,
+    status: Mutex<RegulationStatus>,
+}
+
+impl Regulator {
+    pub fn new(config: RegulationConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            wake: Condvar::new(),
+            status: Mutex::new(RegulationStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> RegulationConfig {
+        self.config.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn configure(&self, config: RegulationConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        self.wake.notify_all();
+    }
+
+    pub fn status(&self) -> RegulationStatus {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn record(&self, status: RegulationStatus) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    // Même cadence que le Poller : bloque tant que la régulation est désactivée
+    pub fn wait_next(&self) {
+        let mut config = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        if config.enabled {
+            let interval = Duration::from_millis(config.interval_ms);
+            config = self.wake.wait_timeout(config, interval).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        while !config.enabled {
+            config = self.wake.wait(config).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
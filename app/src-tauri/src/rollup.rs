@@ -0,0 +1,119 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+const HOUR_S: i64 = 3600;
+const DAY_S: i64 = 86_400;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryPeriod {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl SummaryPeriod {
+    // Début de l'heure, ou minuit local du jour, qui contient timestamp ; secondes Unix
+    pub fn start(self, timestamp: i64) -> i64 {
+        match self {
+            SummaryPeriod::Hour => timestamp.div_euclid(HOUR_S) * HOUR_S,
+            SummaryPeriod::Day => DateTime::from_timestamp(timestamp, 0)
+                .and_then(|t| t.with_timezone(&Local).date_naive().and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest())
+                .map_or(timestamp.div_euclid(DAY_S) * DAY_S, |midnight| midnight.timestamp()),
+        }
+    }
+}
+
+// Statistiques d'une métrique sur une heure ou un jour
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct MetricSummary {
+    // Début de l'heure, ou minuit local pour un jour ; secondes Unix
+    pub timestamp: i64,
+    pub metric: String,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    // Valeur du relevé le plus récent de la période
+    pub last: f64,
+    pub samples: i64,
+}
+
+// Résumé tenu à jour relevé par relevé, sans relire les relevés précédents
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rollup {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: i64,
+    pub last: f64,
+    // Horodatage de `last` : un relevé arrivé en retard ne le remplace pas
+    pub last_at: i64,
+}
+
+impl Rollup {
+    pub fn new(value: f64, at: i64) -> Self {
+        Self { min: value, max: value, sum: value, count: 1, last: value, last_at: at }
+    }
+
+    pub fn add(&mut self, value: f64, at: i64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+        if at >= self.last_at {
+            self.last = value;
+            self.last_at = at;
+        }
+    }
+
+    pub fn summary(&self, timestamp: i64, metric: String) -> MetricSummary {
+        MetricSummary {
+            timestamp,
+            metric,
+            min: self.min,
+            max: self.max,
+            avg: self.sum / self.count.max(1) as f64,
+            last: self.last,
+            samples: self.count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    #[test]
+    fn rollup_tracks_min_max_avg_and_last() {
+        let mut rollup = Rollup::new(40.0, 100);
+        rollup.add(10.0, 110);
+        rollup.add(70.0, 120);
+        let summary = rollup.summary(0, "soc".to_string());
+        assert_eq!((summary.min, summary.max, summary.avg, summary.last, summary.samples), (10.0, 70.0, 40.0, 70.0, 3));
+    }
+
+    #[test]
+    fn a_late_sample_does_not_replace_last() {
+        let mut rollup = Rollup::new(5.0, 200);
+        rollup.add(-3.0, 150);
+        assert_eq!((rollup.last, rollup.last_at, rollup.min), (5.0, 200, -3.0));
+    }
+
+    #[test]
+    fn hours_start_on_the_hour() {
+        assert_eq!(SummaryPeriod::Hour.start(7_205), 7_200);
+        assert_eq!(SummaryPeriod::Hour.start(7_200), 7_200);
+        assert_eq!(SummaryPeriod::Hour.start(-1), -3_600);
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let now = chrono::Utc::now().timestamp();
+        let midnight = SummaryPeriod::Day.start(now);
+        assert!(midnight <= now && now - midnight < 25 * HOUR_S);
+        assert_eq!(SummaryPeriod::Day.start(midnight), midnight);
+        let local = DateTime::from_timestamp(midnight, 0).unwrap().with_timezone(&Local);
+        assert_eq!((local.hour(), local.minute()), (0, 0));
+    }
+}
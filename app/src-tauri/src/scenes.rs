@@ -0,0 +1,90 @@
+use crate::error::AppError;
+use marstek_protocol::ModeRequest;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const SCENES_FILE: &str = "scenes.json";
+const MAX_NAME_LEN: usize = 64;
+
+// Préréglage nommé : un mode et sa configuration, tels qu'envoyés par set_mode
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scene {
+    pub name: String,
+    pub mode: String,
+    // {"manual_cfg": {...}} ou {"passive_cfg": {...}} selon le mode
+    #[serde(default)]
+    pub config: Option<serde_json::Value>,
+    // Proposée dans le menu du tray
+    #[serde(default)]
+    pub tray: bool,
+}
+
+impl Scene {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(AppError::InvalidInput(format!("Scene name must be 1 to {} characters", MAX_NAME_LEN)));
+        }
+        ModeRequest::parse(&self.mode, self.config.as_ref())?;
+        Ok(())
+    }
+}
+
+// Scènes dans l'ordre de création, conservées dans leur propre fichier
+pub struct SceneStore {
+    path: PathBuf,
+    scenes: Mutex<Vec<Scene>>,
+}
+
+impl SceneStore {
+    // Fichier absent ou invalide : aucune scène
+    pub fn open(path: PathBuf) -> Self {
+        let scenes = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, scenes: Mutex::new(scenes) }
+    }
+
+    pub fn list(&self) -> Vec<Scene> {
+        self.scenes.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Scene, AppError> {
+        self.list()
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| AppError::NotConfigured(format!("Unknown scene: {}", name)))
+    }
+
+    // Une scène du même nom est remplacée sur place
+    pub fn save(&self, scene: Scene) -> Result<(), AppError> {
+        scene.validate()?;
+        let scene = Scene { name: scene.name.trim().to_string(), ..scene };
+        let mut scenes = self.scenes.lock().unwrap_or_else(|e| e.into_inner());
+        match scenes.iter_mut().find(|s| s.name == scene.name) {
+            Some(existing) => *existing = scene,
+            None => scenes.push(scene),
+        }
+        self.write(&scenes)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), AppError> {
+        let mut scenes = self.scenes.lock().unwrap_or_else(|e| e.into_inner());
+        let before = scenes.len();
+        scenes.retain(|s| s.name != name);
+        if scenes.len() == before {
+            return Err(AppError::NotConfigured(format!("Unknown scene: {}", name)));
+        }
+        self.write(&scenes)
+    }
+
+    fn write(&self, scenes: &[Scene]) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(fs::write(&self.path, serde_json::to_string_pretty(scenes)?)?)
+    }
+}
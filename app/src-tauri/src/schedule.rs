@@ -0,0 +1,75 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+
+pub use marstek_protocol::{from_mode_result, validate_slots, ManualSlot, MAX_SLOTS};
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleSource {
+    // Plages lues dans la réponse ES.GetMode
+    Device,
+    // Firmware muet sur ses plages : dernière version écrite par l'application
+    Saved,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Schedules {
+    pub slots: Vec<ManualSlot>,
+    pub source: ScheduleSource,
+}
+
+// Version du format de fichier ; un fichier plus récent est refusé
+pub const SCHEDULE_FORMAT: u32 = 1;
+
+// Semaine complète du mode Manual, partageable entre appareils
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleFile {
+    pub format: u32,
+    pub exported_at: String,
+    // Appareil d'origine, à titre indicatif
+    #[serde(default)]
+    pub model: Option<String>,
+    pub slots: Vec<ManualSlot>,
+}
+
+// Accepte aussi un simple tableau de plages
+pub fn parse_file(content: &str) -> Result<Vec<ManualSlot>, AppError> {
+    let slots = match serde_json::from_str::<ScheduleFile>(content) {
+        Ok(file) if file.format > SCHEDULE_FORMAT => {
+            return Err(AppError::InvalidInput(format!("Schedule file format {} is not supported", file.format)));
+        }
+        Ok(file) => file.slots,
+        Err(e) => serde_json::from_str::<Vec<ManualSlot>>(content).map_err(|_| AppError::ParseError(format!("Invalid schedule file: {}", e)))?,
+    };
+    validate_slots(&slots)?;
+    Ok(slots)
+}
+
+// Écart entre le fichier et les plages actuelles de l'appareil
+#[derive(Serialize, Clone)]
+pub struct ScheduleConflict {
+    pub time_num: u8,
+    // Plage actuelle, remplacée (présente dans le fichier) ou désactivée (absente)
+    pub current: ManualSlot,
+    pub imported: Option<ManualSlot>,
+}
+
+pub fn conflicts(current: &[ManualSlot], imported: &[ManualSlot]) -> Vec<ScheduleConflict> {
+    current
+        .iter()
+        // Une plage déjà désactivée ne perd rien
+        .filter(|c| c.enable != 0)
+        .filter_map(|c| {
+            let imported = imported.iter().find(|s| s.time_num == c.time_num);
+            (imported != Some(c)).then(|| ScheduleConflict { time_num: c.time_num, current: c.clone(), imported: imported.cloned() })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct ScheduleImport {
+    pub slots: Vec<ManualSlot>,
+    pub conflicts: Vec<ScheduleConflict>,
+    // false : simple aperçu, rien n'a été envoyé
+    pub applied: bool,
+}
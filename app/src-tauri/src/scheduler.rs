@@ -0,0 +1,192 @@
+use marstek_protocol::methods;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+pub const DEFAULT_STAGGER_MS: u64 = 100;
+// Silence minimal entre deux requêtes au même appareil : un Venus sollicité en rafale cesse de répondre une minute
+pub const DEFAULT_MIN_GAP_MS: u64 = 150;
+// Commandes qui changent l'état de l'appareil : jamais retardées par des lectures en attente
+const CONTROL_METHODS: [&str; 4] = [methods::ES_SET_MODE, methods::SET_TIME, methods::WIFI_SET_CONFIG, methods::OTA_UPDATE];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // Écritures (ES.SetMode...), quelle que soit leur origine
+    Control,
+    // Commandes déclenchées par l'utilisateur (découverte, lectures à la demande...)
+    Interactive,
+    // Polling et intégrations
+    Background,
+}
+
+impl Priority {
+    // Une écriture demandée par une automatisation passe quand même devant le polling
+    pub fn for_method(self, method: &str) -> Priority {
+        if CONTROL_METHODS.contains(&method) {
+            Priority::Control
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct SchedulerConfig {
+    pub max_concurrent: usize,
+    pub stagger_ms: u64,
+    pub min_gap_ms: u64,
+}
+
+// File d'un appareil : une requête à la fois, espacées de min_gap
+#[derive(Default)]
+struct DeviceSlot {
+    busy: bool,
+    next_allowed: Option<Instant>,
+}
+
+struct SchedulerState {
+    max_concurrent: usize,
+    stagger: Duration,
+    min_gap: Duration,
+    in_flight: usize,
+    waiting_control: usize,
+    waiting_interactive: usize,
+    next_background: Instant,
+    devices: HashMap<String, DeviceSlot>,
+}
+
+impl SchedulerState {
+    fn waiting(&mut self, priority: Priority) -> Option<&mut usize> {
+        match priority {
+            Priority::Control => Some(&mut self.waiting_control),
+            Priority::Interactive => Some(&mut self.waiting_interactive),
+            Priority::Background => None,
+        }
+    }
+
+    // Une requête de priorité supérieure attend : on lui laisse la place
+    fn yields(&self, priority: Priority) -> bool {
+        match priority {
+            Priority::Control => false,
+            Priority::Interactive => self.waiting_control > 0,
+            Priority::Background => self.waiting_control > 0 || self.waiting_interactive > 0,
+        }
+    }
+}
+
+// Limite le nombre de sockets ouverts simultanément, sérialise les requêtes de chaque appareil
+// et espace les requêtes de fond. Les écritures passent devant tout le reste, les requêtes
+// interactives devant les requêtes de fond en attente.
+pub struct RequestScheduler {
+    state: Mutex<SchedulerState>,
+    released: Condvar,
+}
+
+pub struct Permit<'a> {
+    scheduler: &'a RequestScheduler,
+    device: Option<String>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.lock();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        let min_gap = state.min_gap;
+        if let Some(slot) = self.device.as_ref().and_then(|d| state.devices.get_mut(d)) {
+            slot.busy = false;
+            slot.next_allowed = Some(Instant::now() + min_gap);
+        }
+        drop(state);
+        self.scheduler.released.notify_all();
+    }
+}
+
+impl Default for RequestScheduler {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(SchedulerState {
+                max_concurrent: DEFAULT_MAX_CONCURRENT,
+                stagger: Duration::from_millis(DEFAULT_STAGGER_MS),
+                min_gap: Duration::from_millis(DEFAULT_MIN_GAP_MS),
+                in_flight: 0,
+                waiting_control: 0,
+                waiting_interactive: 0,
+                next_background: Instant::now(),
+                devices: HashMap::new(),
+            }),
+            released: Condvar::new(),
+        }
+    }
+}
+
+impl RequestScheduler {
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Requêtes qui ne visent pas un appareil précis (découverte)
+    pub fn acquire(&self, priority: Priority) -> Permit<'_> {
+        self.acquire_inner(priority, None)
+    }
+
+    // device : adresse de l'appareil, clé de sa file
+    pub fn acquire_for(&self, priority: Priority, device: &str) -> Permit<'_> {
+        self.acquire_inner(priority, Some(device))
+    }
+
+    fn acquire_inner(&self, priority: Priority, device: Option<&str>) -> Permit<'_> {
+        let mut state = self.lock();
+        if let Some(waiting) = state.waiting(priority) {
+            *waiting += 1;
+        }
+        loop {
+            let now = Instant::now();
+            let slot = device.and_then(|d| state.devices.get(d));
+            if state.in_flight >= state.max_concurrent || state.yields(priority) || slot.is_some_and(|s| s.busy) {
+                state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+            let mut ready_at = slot.and_then(|s| s.next_allowed).unwrap_or(now);
+            if priority == Priority::Background {
+                // Étaler les polls pour ne pas saturer le réseau
+                ready_at = ready_at.max(state.next_background);
+            }
+            if now < ready_at {
+                state = self.released.wait_timeout(state, ready_at - now).unwrap_or_else(|e| e.into_inner()).0;
+                continue;
+            }
+            break;
+        }
+        if let Some(waiting) = state.waiting(priority) {
+            *waiting -= 1;
+        }
+        if priority == Priority::Background {
+            state.next_background = Instant::now() + state.stagger;
+        }
+        state.in_flight += 1;
+        if let Some(device) = device {
+            state.devices.entry(device.to_string()).or_default().busy = true;
+        }
+        Permit { scheduler: self, device: device.map(str::to_string) }
+    }
+
+    pub fn config(&self) -> SchedulerConfig {
+        let state = self.lock();
+        SchedulerConfig {
+            max_concurrent: state.max_concurrent,
+            stagger_ms: state.stagger.as_millis() as u64,
+            min_gap_ms: state.min_gap.as_millis() as u64,
+        }
+    }
+
+    pub fn configure(&self, max_concurrent: usize, stagger_ms: u64, min_gap_ms: u64) {
+        let mut state = self.lock();
+        state.max_concurrent = max_concurrent.max(1);
+        state.stagger = Duration::from_millis(stagger_ms);
+        state.min_gap = Duration::from_millis(min_gap_ms);
+        drop(state);
+        self.released.notify_all();
+    }
+}
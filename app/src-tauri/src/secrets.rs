@@ -0,0 +1,325 @@
+use crate::settings::Settings;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use keyring::Entry;
+use rand::RngCore;
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+const SERVICE: &str = "com.jsys.marstip";
+// Clé du repli chiffré, dans le dossier de données (pas à côté de settings.json)
+pub const SETTINGS_KEY_FILE: &str = "settings.key";
+// Valeur chiffrée dans settings.json : "enc:v1:<iv><chiffré><tag>" en hexadécimal
+const SEALED_PREFIX: &str = "enc:v1:";
+const IV_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+static SETTINGS_KEY: OnceLock<[u8; 32]> = OnceLock::new();
+// Un seul avertissement pour un trousseau injoignable, pas un par lecture
+static UNAVAILABLE_WARNED: AtomicBool = AtomicBool::new(false);
+
+pub const PIN_HASH: &str = "pin_hash";
+pub const MQTT_PASSWORD: &str = "mqtt_password";
+pub const INFLUX_TOKEN: &str = "influx_token";
+pub const TARIFF_API_KEY: &str = "tariff_api_key";
+pub const CLOUD_PASSWORD: &str = "cloud_password";
+pub const PVOUTPUT_API_KEY: &str = "pvoutput_api_key";
+pub const EV_CHARGER_PASSWORD: &str = "ev_charger_password";
+pub const API_TOKEN: &str = "api_token";
+pub const API_READ_TOKEN: &str = "api_read_token";
+
+// Une clé de signature par webhook, désignée par son nom
+pub fn webhook_secret(hook: &str) -> String {
+    format!("webhook_secret:{}", hook)
+}
+
+fn entry(name: &str) -> Result<Entry, keyring::Error> {
+    Entry::new(SERVICE, name)
+}
+
+// Pas de service de secrets (Linux sans session D-Bus, service système) : traité comme un trousseau vide,
+// les copies de settings.json prennent le relais
+fn unavailable(error: &keyring::Error) -> bool {
+    matches!(error, keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_))
+}
+
+fn warn_unavailable(error: &keyring::Error) {
+    if !UNAVAILABLE_WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!("keyring unavailable, using settings.json only: {}", error);
+    }
+}
+
+fn read(result: Result<String, keyring::Error>) -> Result<Option<String>, String> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) if unavailable(&e) => {
+            warn_unavailable(&e);
+            Ok(None)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn get(name: &str) -> Result<Option<String>, String> {
+    read(entry(name).and_then(|entry| entry.get_password()))
+}
+
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    entry(name).and_then(|entry| entry.set_password(value)).map_err(|e| e.to_string())
+}
+
+// Au mieux : un trousseau injoignable ne doit pas empêcher d'effacer la copie de settings.json
+pub fn delete(name: &str) {
+    match entry(name).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) if unavailable(&e) => warn_unavailable(&e),
+        Err(e) => tracing::warn!("failed to remove {} from the keyring: {}", name, e),
+    }
+}
+
+// Clé aléatoire créée au premier lancement ; sans elle, le repli reste en clair comme avant.
+// Elle protège les copies de settings.json (sauvegardes, rapports de bug), pas un accès au profil utilisateur.
+pub fn load_settings_key(data_dir: &Path) {
+    let path = data_dir.join(SETTINGS_KEY_FILE);
+    let key = match fs::read(&path) {
+        Ok(bytes) => bytes.try_into().map_err(|_| format!("{} is corrupted", path.display())),
+        Err(_) => {
+            let mut key = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            write_key(&path, &key).map(|_| key)
+        }
+    };
+    match key {
+        Ok(key) => {
+            let _ = SETTINGS_KEY.set(key);
+        }
+        Err(e) => tracing::warn!("settings encryption key unavailable: {}", e),
+    }
+}
+
+fn write_key(path: &Path, key: &[u8; 32]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, key).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Sous-clés de chiffrement et d'authentification tirées de la clé du fichier
+fn subkey(key: &[u8; 32], label: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(label);
+    mac.finalize().into_bytes().into()
+}
+
+// AES-256-CBC puis HMAC-SHA256 sur iv + chiffré
+fn seal(value: &str) -> Option<String> {
+    let key = SETTINGS_KEY.get()?;
+    let mut iv = [0u8; IV_LEN];
+    rand::thread_rng().fill_bytes(&mut iv);
+    let cipher = cbc::Encryptor::<aes::Aes256>::new(&subkey(key, b"enc").into(), &iv.into());
+    let mut sealed = iv.to_vec();
+    sealed.extend(cipher.encrypt_padded_vec_mut::<Pkcs7>(value.as_bytes()));
+    let mut mac = Hmac::<Sha256>::new_from_slice(&subkey(key, b"mac")).expect("HMAC accepts any key length");
+    mac.update(&sealed);
+    sealed.extend(mac.finalize().into_bytes());
+    Some(format!("{}{}", SEALED_PREFIX, to_hex(&sealed)))
+}
+
+fn open(value: &str) -> Result<String, String> {
+    let Some(hex) = value.strip_prefix(SEALED_PREFIX) else { return Ok(value.to_string()) };
+    let key = SETTINGS_KEY.get().ok_or("Encrypted setting found but the settings key is missing")?;
+    let sealed = from_hex(hex).filter(|s| s.len() > IV_LEN + TAG_LEN).ok_or("Corrupted encrypted setting")?;
+    let (data, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let mut mac = Hmac::<Sha256>::new_from_slice(&subkey(key, b"mac")).expect("HMAC accepts any key length");
+    mac.update(data);
+    // Autre clé (settings.json copié d'une autre machine) ou valeur modifiée
+    mac.verify_slice(tag).map_err(|_| "Encrypted setting does not match the settings key".to_string())?;
+    let (iv, ciphertext) = data.split_at(IV_LEN);
+    let cipher = cbc::Decryptor::<aes::Aes256>::new(&subkey(key, b"enc").into(), iv.into());
+    let plain = cipher.decrypt_padded_vec_mut::<Pkcs7>(ciphertext).map_err(|_| "Corrupted encrypted setting".to_string())?;
+    String::from_utf8(plain).map_err(|e| e.to_string())
+}
+
+// Repli dans settings.json quand le trousseau refuse : chiffré si la clé est là, sinon en clair
+fn fallback(name: &str, value: String, error: String) -> String {
+    match seal(&value) {
+        Some(sealed) => sealed,
+        None => {
+            tracing::warn!("keyring unavailable, keeping {} in plain text: {}", name, error);
+            value
+        }
+    }
+}
+
+// Secret saisi par l'utilisateur : None conserve la valeur actuelle, "" la supprime.
+// Retourne la valeur à garder dans settings.json (trousseau indisponible).
+pub fn update(name: &str, value: Option<String>, stored: Option<String>) -> Result<Option<String>, String> {
+    match value {
+        None => Ok(stored),
+        Some(value) if value.is_empty() => {
+            delete(name);
+            Ok(None)
+        }
+        Some(value) => Ok(set(name, &value).err().map(|e| fallback(name, value, e))),
+    }
+}
+
+// La copie de settings.json n'existe que si le trousseau était indisponible : elle est prioritaire
+pub fn lookup(name: &str, stored: Option<&String>) -> Result<Option<String>, String> {
+    match stored {
+        Some(value) => open(value).map(Some),
+        None => get(name),
+    }
+}
+
+fn migrate(name: &str, stored: &mut Option<String>) -> bool {
+    let Some(value) = stored.clone() else { return false };
+    if value.starts_with(SEALED_PREFIX) {
+        // Déjà chiffrée : déplacée dans le trousseau s'il est revenu
+        let Ok(plain) = open(&value) else { return false };
+        return set(name, &plain).is_ok() && stored.take().is_some();
+    }
+    match set(name, &value) {
+        Ok(()) => *stored = None,
+        Err(e) => {
+            let sealed = fallback(name, value.clone(), e);
+            if sealed == value {
+                return false;
+            }
+            *stored = Some(sealed);
+        }
+    }
+    true
+}
+
+// L'empreinte du PIN reste dans settings.json : un trousseau injoignable ne doit pas faire disparaître le PIN.
+// Celle qu'une version précédente y avait rangée revient dès que le trousseau répond.
+fn reclaim_pin_hash(stored: &mut Option<String>) -> bool {
+    if stored.is_some() {
+        return false;
+    }
+    match get(PIN_HASH) {
+        Ok(Some(hash)) => {
+            *stored = Some(hash);
+            delete(PIN_HASH);
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            tracing::warn!("cannot read {} from the keyring: {}", PIN_HASH, e);
+            false
+        }
+    }
+}
+
+// Déplace les secrets encore en clair dans settings.json vers le trousseau du système, ou les y chiffre.
+// Retourne true si les settings ont changé et doivent être réécrits.
+pub fn migrate_plaintext(settings: &mut Settings) -> bool {
+    let mut changed = reclaim_pin_hash(&mut settings.pin_hash);
+    changed |= migrate(MQTT_PASSWORD, &mut settings.mqtt.password);
+    changed |= migrate(INFLUX_TOKEN, &mut settings.influx.token);
+    changed |= migrate(TARIFF_API_KEY, &mut settings.tariff.api_key);
+    changed |= migrate(CLOUD_PASSWORD, &mut settings.cloud.password);
+    changed |= migrate(PVOUTPUT_API_KEY, &mut settings.pvoutput.api_key);
+    changed |= migrate(EV_CHARGER_PASSWORD, &mut settings.ev_charger.password);
+    changed |= migrate(API_TOKEN, &mut settings.api_server.token);
+    changed |= migrate(API_READ_TOKEN, &mut settings.api_server.read_token);
+    for hook in &mut settings.webhooks.hooks {
+        changed |= migrate(&webhook_secret(&hook.name), &mut hook.secret);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trousseau factice : chaque entrée est neuve, donc vide
+    fn mock_keyring() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        SETTINGS_KEY.get_or_init(|| [7; 32]);
+    }
+
+    #[test]
+    fn sealed_values_round_trip() {
+        mock_keyring();
+        let sealed = seal("s3cret").unwrap();
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert_ne!(seal("s3cret").unwrap(), sealed, "a fresh IV per value");
+        assert_eq!(open(&sealed).unwrap(), "s3cret");
+        // Valeur encore en clair d'une version précédente
+        assert_eq!(open("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn tampered_values_are_rejected() {
+        mock_keyring();
+        let sealed = seal("s3cret").unwrap();
+        let last = sealed.chars().last().unwrap();
+        let tampered = format!("{}{}", &sealed[..sealed.len() - 1], if last == '0' { '1' } else { '0' });
+        assert!(open(&tampered).is_err());
+        assert!(open(&format!("{}zz", SEALED_PREFIX)).is_err());
+        assert!(open(&format!("{}{}", SEALED_PREFIX, "00".repeat(IV_LEN))).is_err());
+    }
+
+    #[test]
+    fn lookup_prefers_the_settings_copy() {
+        mock_keyring();
+        let sealed = seal("from-settings").unwrap();
+        assert_eq!(lookup(MQTT_PASSWORD, Some(&sealed)).unwrap().as_deref(), Some("from-settings"));
+        assert_eq!(lookup(MQTT_PASSWORD, None).unwrap(), None);
+    }
+
+    #[test]
+    fn update_keeps_replaces_or_clears() {
+        mock_keyring();
+        let stored = Some("kept".to_string());
+        assert_eq!(update(INFLUX_TOKEN, None, stored.clone()).unwrap(), stored);
+        assert_eq!(update(INFLUX_TOKEN, Some(String::new()), stored.clone()).unwrap(), None);
+        // Accepté par le trousseau : plus de copie dans settings.json
+        assert_eq!(update(INFLUX_TOKEN, Some("new".to_string()), stored).unwrap(), None);
+    }
+
+    #[test]
+    fn unreachable_keyring_reads_as_empty() {
+        let no_dbus = || keyring::Error::PlatformFailure("no D-Bus session".into());
+        assert_eq!(read(Err(no_dbus())).unwrap(), None);
+        assert_eq!(read(Err(keyring::Error::NoStorageAccess("locked".into()))).unwrap(), None);
+        assert_eq!(read(Err(keyring::Error::NoEntry)).unwrap(), None);
+        assert_eq!(read(Ok("value".to_string())).unwrap().as_deref(), Some("value"));
+        assert!(read(Err(keyring::Error::TooLong("name".to_string(), 255))).is_err());
+    }
+
+    #[test]
+    fn pin_hash_stays_in_settings() {
+        mock_keyring();
+        let mut settings = Settings { pin_hash: Some("salt$hash".to_string()), ..Default::default() };
+        migrate_plaintext(&mut settings);
+        assert_eq!(settings.pin_hash.as_deref(), Some("salt$hash"));
+        let mut empty = None;
+        assert!(!reclaim_pin_hash(&mut empty));
+        assert_eq!(empty, None);
+    }
+}
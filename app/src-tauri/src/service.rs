@@ -0,0 +1,87 @@
+use crate::api::ApiServerConfig;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::sync::OnceLock;
+
+// Cœur sans fenêtre ni tray (unité systemd, service Windows) : polling, historique, MQTT et automatismes
+// continuent hors session. L'interface ouverte à côté s'y rattache au lieu de piloter la batterie en double.
+pub const HEADLESS_ARG: &str = "--headless";
+pub const CORE_FILE: &str = "core.json";
+// Verrou tenu par le cœur tant qu'il tourne ; le système le libère même après un arrêt brutal
+pub const CORE_LOCK: &str = "core.lock";
+
+static LOCK: OnceLock<File> = OnceLock::new();
+
+// Annonce du cœur dans le dossier de données, retirée à l'arrêt
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CoreInfo {
+    pub pid: u32,
+    // UTC ISO-8601
+    pub started_at: String,
+    // API REST du cœur, "http://127.0.0.1:8080" ; absente : désactivée ou cœur encore en démarrage
+    pub api_address: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CoreStatus {
+    // Ce processus est le cœur sans interface
+    pub headless: bool,
+    // Interface rattachée à un cœur : ni polling de fond, ni automatismes, ni exports ici
+    pub attached: bool,
+    pub core: Option<CoreInfo>,
+}
+
+pub fn headless() -> bool {
+    std::env::args().any(|arg| arg == HEADLESS_ARG)
+}
+
+pub fn announce(data_dir: &Path, api: &ApiServerConfig) -> Result<CoreInfo, AppError> {
+    let info = CoreInfo {
+        pid: std::process::id(),
+        started_at: crate::units::timestamp(),
+        api_address: api.enabled.then(|| api.local_url()),
+    };
+    std::fs::create_dir_all(data_dir)?;
+    std::fs::write(data_dir.join(CORE_FILE), serde_json::to_string_pretty(&info)?)?;
+    Ok(info)
+}
+
+pub fn withdraw(data_dir: &Path) {
+    match std::fs::remove_file(data_dir.join(CORE_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => tracing::warn!("failed to remove {}: {}", CORE_FILE, e),
+        _ => {}
+    }
+}
+
+fn open_lock(data_dir: &Path) -> std::io::Result<File> {
+    std::fs::create_dir_all(data_dir)?;
+    OpenOptions::new().create(true).truncate(false).write(true).open(data_dir.join(CORE_LOCK))
+}
+
+// Pris avant tout service : un second cœur est refusé, une fenêtre ouverte entre-temps se rattache déjà
+pub fn claim(data_dir: &Path) -> Result<(), AppError> {
+    let file = open_lock(data_dir)?;
+    match file.try_lock() {
+        Ok(()) => {
+            let _ = LOCK.set(file);
+            Ok(())
+        }
+        Err(TryLockError::WouldBlock) => Err(AppError::Forbidden("A headless MarsTip core is already running".to_string())),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+// Cœur dont le verrou est tenu, API activée ou non ; un core.json laissé par un arrêt brutal est ignoré
+pub fn running_core(data_dir: &Path) -> Option<CoreInfo> {
+    let file = open_lock(data_dir).ok()?;
+    match file.try_lock() {
+        Err(TryLockError::WouldBlock) => {}
+        // Libre : relâché à la fermeture du fichier
+        Ok(()) | Err(TryLockError::Error(_)) => return None,
+    }
+    let info = std::fs::read_to_string(data_dir.join(CORE_FILE)).ok().and_then(|text| serde_json::from_str(&text).ok());
+    // Verrou pris mais pas encore annoncé : le cœur démarre
+    Some(info.unwrap_or_else(|| CoreInfo { pid: 0, started_at: String::new(), api_address: None }))
+}
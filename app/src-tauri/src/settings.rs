@@ -0,0 +1,254 @@
+use crate::alerts::AlertConfig;
+use crate::api::ApiServerConfig;
+use crate::calendar::ExceptionCalendar;
+use crate::changes::ChangeConfig;
+use crate::automation::PriceRules;
+use crate::clock::ClockConfig;
+use crate::cloud::CloudConfig;
+use crate::compliance::ComplianceConfig;
+use crate::costs::CostConfig;
+use crate::derived::DerivedSensor;
+use crate::discovery::{DiscoveryConfig, HeartbeatConfig};
+use crate::error::AppError;
+use crate::ev::EvChargerConfig;
+use crate::firmware::FirmwareConfig;
+use crate::exportlimit::ExportLimitConfig;
+use crate::forecast::ForecastConfig;
+use crate::gridmeter::GridMeter;
+use crate::gridquality::GridQualityConfig;
+use crate::history::RetentionConfig;
+use crate::influx::InfluxConfig;
+use crate::inverter::PvSource;
+use crate::logs::LogConfig;
+use crate::mqtt::MqttConfig;
+use crate::notify::NotificationConfig;
+use crate::peakshaving::PeakShavingConfig;
+use crate::polling::PollingConfig;
+use crate::pvoutput::PvOutputConfig;
+use crate::recovery::RecoveryConfig;
+use marstek_protocol::{methods, ProtocolVariant};
+use crate::regulation::RegulationConfig;
+use crate::sgready::SgReadyConfig;
+use crate::soclimits::SocLimits;
+use crate::solar::SolarScheduleConfig;
+use crate::tariff::TariffConfig;
+use crate::units::UnitPreferences;
+use crate::watchdog::ModeWatchdogConfig;
+use crate::webhooks::WebhookConfig;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+
+const SETTINGS_FILE: &str = "settings.json";
+// Méthodes dont la latence s'écarte nettement de timeout_ms, [ms]
+const DEFAULT_METHOD_TIMEOUTS_MS: [(&str, u64); 5] = [
+    // Réponse immédiate : sert aussi de sonde de joignabilité
+    (methods::GET_DEVICE, 1500),
+    // Le mode est écrit en flash avant la réponse
+    (methods::ES_SET_MODE, 5000),
+    (methods::SET_TIME, 3000),
+    (methods::WIFI_SET_CONFIG, 10_000),
+    (methods::OTA_UPDATE, 10_000),
+];
+
+// Paramètres réseau communs à toutes les requêtes UDP, découverte comprise
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    pub timeout_ms: u64,
+    // Nouvelles tentatives après la première en cas d'échec
+    pub retries: u32,
+    // Délai avant la première nouvelle tentative, doublé ensuite jusqu'à retry_max_delay_ms
+    pub retry_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    // Port source des requêtes (0 : port éphémère)
+    pub local_port: u16,
+    // Délai par méthode ("ES.SetMode": 8000), prioritaire sur les valeurs par défaut et timeout_ms
+    pub method_timeouts_ms: HashMap<String, u64>,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 2000,
+            retries: 2,
+            retry_delay_ms: 200,
+            retry_max_delay_ms: 2000,
+            local_port: 30000,
+            method_timeouts_ms: HashMap::new(),
+        }
+    }
+}
+
+impl ConnectionSettings {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.timeout_ms == 0 || self.method_timeouts_ms.values().any(|&ms| ms == 0) {
+            return Err(AppError::InvalidInput("timeout_ms must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn timeout_for(&self, method: &str) -> Duration {
+        let ms = self.method_timeouts_ms.get(method).copied().or_else(|| {
+            DEFAULT_METHOD_TIMEOUTS_MS.iter().find(|(m, _)| *m == method).map(|(_, ms)| *ms)
+        });
+        Duration::from_millis(ms.unwrap_or(self.timeout_ms))
+    }
+
+    // Backoff exponentiel, tiré entre la moitié et la totalité du délai pour que
+    // plusieurs requêtes perdues en même temps ne repartent pas ensemble
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .retry_delay_ms
+            .saturating_mul(1u64.checked_shl(retry.saturating_sub(1)).unwrap_or(u64::MAX))
+            .min(self.retry_max_delay_ms.max(self.retry_delay_ms));
+        Duration::from_millis(rand::thread_rng().gen_range(delay / 2..=delay))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(flatten)]
+    pub connection: ConnectionSettings,
+    pub read_only: bool,
+    // Écriture directe des registres de commande en Modbus TCP
+    pub advanced_control: bool,
+    pub pin_hash: Option<String>,
+    pub enabled_plugins: Vec<String>,
+    pub derived_sensors: Vec<DerivedSensor>,
+    // Clé : famille de produits ("b2500", "jupiter"...)
+    pub protocol_variants: HashMap<String, ProtocolVariant>,
+    pub pv_sources: Vec<PvSource>,
+    // Compteur de référence (tableau de bord, régulation) ; absent : CT Marstek
+    pub grid_meter: Option<GridMeter>,
+    pub sg_ready: SgReadyConfig,
+    pub compliance: ComplianceConfig,
+    pub grid_quality: GridQualityConfig,
+    pub polling: PollingConfig,
+    pub mqtt: MqttConfig,
+    pub api_server: ApiServerConfig,
+    pub influx: InfluxConfig,
+    pub pvoutput: PvOutputConfig,
+    pub discovery: DiscoveryConfig,
+    pub heartbeat: HeartbeatConfig,
+    // Reprise après une veille ou un changement de réseau
+    pub recovery: RecoveryConfig,
+    pub regulation: RegulationConfig,
+    pub peak_shaving: PeakShavingConfig,
+    // Plafond d'injection : le surplus PV au-delà est absorbé par la charge
+    pub export_limit: ExportLimitConfig,
+    // Borne de recharge : la décharge est bridée pendant la charge de la voiture
+    pub ev_charger: EvChargerConfig,
+    pub tariff: TariffConfig,
+    // Tarifs d'achat et de revente du rapport de coûts
+    pub costs: CostConfig,
+    pub price_rules: PriceRules,
+    // Plages Manual relatives au lever/coucher du soleil, recalculées chaque jour
+    pub solar_schedule: SolarScheduleConfig,
+    // Jours de vacances ou fériés où les automatismes suivent un autre profil
+    pub exception_calendar: ExceptionCalendar,
+    pub forecast: ForecastConfig,
+    pub soc_limits: SocLimits,
+    pub alerts: AlertConfig,
+    pub notifications: NotificationConfig,
+    // Mode remis en place après un redémarrage de l'appareil
+    pub mode_watchdog: ModeWatchdogConfig,
+    pub firmware: FirmwareConfig,
+    // Repli du tableau de bord quand le réseau local ne répond pas
+    pub cloud: CloudConfig,
+    pub clock: ClockConfig,
+    // Événements soc-changed, mode-changed et grid-power-changed
+    pub changes: ChangeConfig,
+    // Requêtes HTTP sortantes sur alertes, changements de mode et seuils de SOC
+    pub webhooks: WebhookConfig,
+    // Niveaux par module et fichiers tournants dans le dossier de données
+    pub logging: LogConfig,
+    // Unités, précision et format d'heure de l'affichage, des exports et de MQTT
+    pub units: UnitPreferences,
+    // Durées de conservation de l'historique et compactage de la base
+    pub retention: RetentionConfig,
+}
+
+pub fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(SETTINGS_FILE))
+}
+
+// Fichier absent : valeurs par défaut ; illisible ou invalide : erreur, le fichier reste intact
+pub fn load(path: &Path) -> Result<Settings, AppError> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| AppError::ParseError(format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Settings::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Relecture à chaud : contrairement à load, un fichier invalide est une erreur
+pub fn read(path: &Path) -> Result<Settings, AppError> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| AppError::ParseError(format!("{}: {}", path.display(), e)))
+}
+
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn save(path: &Path, settings: &Settings) -> Result<(), AppError> {
+    // Un fichier que l'on ne sait pas relire est peut-être en cours d'édition : on ne l'écrase pas
+    if let Err(e @ AppError::ParseError(_)) = load(path) {
+        return Err(AppError::Forbidden(format!("Settings file is invalid, fix it before saving: {}", e)));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string_pretty(settings)?;
+    // Écriture dans un fichier voisin puis renommage : jamais de fichier tronqué
+    let tmp = path.with_extension("json.tmp");
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+    Ok(fs::rename(&tmp, path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marstip-settings-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(SETTINGS_FILE)
+    }
+
+    #[test]
+    fn missing_file_loads_defaults_and_saves_atomically() {
+        let path = settings_file("missing");
+        assert!(!load(&path).unwrap().read_only);
+
+        let settings = Settings { read_only: true, ..Settings::default() };
+        save(&path, &settings).unwrap();
+        assert!(load(&path).unwrap().read_only);
+        assert!(!path.with_extension("json.tmp").exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn invalid_file_is_an_error_and_never_overwritten() {
+        let path = settings_file("invalid");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ \"read_only\": tru").unwrap();
+
+        assert!(matches!(load(&path), Err(AppError::ParseError(_))));
+        assert!(matches!(save(&path, &Settings::default()), Err(AppError::Forbidden(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ \"read_only\": tru");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const HTTP_TIMEOUT_MS: u64 = 2000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SgReadyConfig {
+    pub enabled: bool,
+    // Ex : http://192.168.1.50/relay/0?turn=on (Shelly)
+    pub on_url: String,
+    pub off_url: String,
+    // Batterie considérée pleine au-delà de ce SOC, [%]
+    pub min_soc: u32,
+    // Injection réseau minimale pour activer le signal, [W]
+    pub min_export: f32,
+    // Délai minimal entre deux bascules du relais, [s]
+    pub min_switch_interval_s: u64,
+}
+
+impl Default for SgReadyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_url: String::new(),
+            off_url: String::new(),
+            min_soc: 95,
+            min_export: 500.0,
+            min_switch_interval_s: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct SgReadyStatus {
+    pub active: bool,
+    pub last_error: Option<String>,
+}
+
+struct ControllerState {
+    active: bool,
+    last_switch: Option<Instant>,
+    last_error: Option<String>,
+}
+
+pub struct SgReadyController {
+    state: Mutex<ControllerState>,
+}
+
+impl Default for SgReadyController {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ControllerState {
+                active: false,
+                last_switch: None,
+                last_error: None,
+            }),
+        }
+    }
+}
+
+fn call_relay(url: &str) -> Result<(), String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .and_then(|client| client.get(url).send())
+        .and_then(|r| r.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+impl SgReadyController {
+    pub fn status(&self) -> SgReadyStatus {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        SgReadyStatus {
+            active: state.active,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    // grid_power : compteur CT, négatif en injection
+    pub fn evaluate(&self, config: &SgReadyConfig, soc: Option<u32>, grid_power: Option<f32>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let surplus = config.enabled
+            && soc.is_some_and(|soc| soc >= config.min_soc)
+            && grid_power.is_some_and(|p| -p >= config.min_export);
+        // Hystérésis : on ne coupe que lorsque le réseau n'absorbe plus d'injection
+        let wanted = if state.active {
+            config.enabled && grid_power.is_some_and(|p| p < 0.0)
+        } else {
+            surplus
+        };
+        if wanted == state.active {
+            return;
+        }
+        let interval = Duration::from_secs(config.min_switch_interval_s);
+        if state.last_switch.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+
+        let url = if wanted { &config.on_url } else { &config.off_url };
+        match call_relay(url) {
+            Ok(()) => {
+                tracing::info!("SG-Ready signal {}", if wanted { "on" } else { "off" });
+                state.active = wanted;
+                state.last_switch = Some(Instant::now());
+                state.last_error = None;
+            }
+            Err(e) => state.last_error = Some(e),
+        }
+    }
+}
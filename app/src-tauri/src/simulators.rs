@@ -0,0 +1,48 @@
+use crate::error::AppError;
+use chrono::Local;
+use marstek_protocol::simulator::{self, Responder, Simulator, SimulatorHandle};
+use marstek_protocol::Replay;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Simulateurs en cours, par id d'appareil : chacun écoute sur 127.0.0.1 comme une vraie batterie
+#[derive(Default)]
+pub struct Simulators {
+    running: Mutex<HashMap<String, SimulatorHandle>>,
+}
+
+impl Simulators {
+    // replay : capture à rejouer au lieu de la batterie simulée.
+    // Renvoie le port d'écoute : celui demandé, ou un port éphémère s'il est pris (0 : éphémère)
+    pub fn start(&self, id: &str, port: u16, replay: Option<&str>) -> Result<u16, AppError> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(handle) = running.get(id).filter(|h| port == 0 || h.addr().port() == port) {
+            return Ok(handle.addr().port());
+        }
+        let responder: Arc<dyn Responder> = match replay {
+            Some(path) => Arc::new(Replay::load(Path::new(path), None)?),
+            None => {
+                // Graine tirée de l'id : MAC et profil restent les mêmes d'un lancement à l'autre
+                let mut hasher = DefaultHasher::new();
+                id.hash(&mut hasher);
+                let offset = i64::from(Local::now().offset().local_minus_utc());
+                Arc::new(Simulator::new(hasher.finish(), offset))
+            }
+        };
+        running.remove(id);
+        let handle = simulator::serve(Arc::clone(&responder), port).or_else(|e| {
+            tracing::warn!(device = %id, "simulator port {} unavailable ({}), using an ephemeral port", port, e);
+            simulator::serve(responder, 0)
+        })?;
+        let port = handle.addr().port();
+        running.insert(id.to_string(), handle);
+        Ok(port)
+    }
+
+    pub fn stop(&self, id: &str) {
+        self.running.lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+    }
+}
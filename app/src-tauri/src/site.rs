@@ -0,0 +1,85 @@
+use crate::error::AppError;
+use crate::units;
+use crate::DashboardData;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone)]
+pub struct SiteDevice {
+    pub id: String,
+    pub nickname: Option<String>,
+    pub data: Option<DashboardData>,
+    pub error: Option<AppError>,
+}
+
+// Sommes sur les appareils ayant répondu ; une valeur absente partout reste absente
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct SiteTotals {
+    pub pv_power: Option<f32>,
+    pub ongrid_power: Option<f32>,
+    pub offgrid_power: Option<f32>,
+    pub bat_power: Option<f32>,
+    pub total_pv_energy: Option<f32>,
+    pub total_grid_output_energy: Option<f32>,
+    pub total_grid_input_energy: Option<f32>,
+    pub total_load_energy: Option<f32>,
+    // SOC pondéré par la capacité ; moyenne simple si aucune capacité n'est connue
+    pub soc: Option<f32>,
+    pub capacity: Option<f32>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SiteDashboard {
+    pub totals: SiteTotals,
+    pub devices: Vec<SiteDevice>,
+    pub online: usize,
+    pub timestamp: String,
+}
+
+fn sum(values: impl Iterator<Item = Option<f32>>) -> Option<f32> {
+    values.flatten().fold(None, |total, v| Some(total.unwrap_or(0.0) + v))
+}
+
+// Le compteur CT n'est pas additionné : plusieurs batteries voient souvent le même point de raccordement
+pub fn aggregate(devices: Vec<SiteDevice>) -> SiteDashboard {
+    let data: Vec<&DashboardData> = devices.iter().filter_map(|d| d.data.as_ref()).collect();
+    let energy = |f: fn(&DashboardData) -> Option<f32>| sum(data.iter().map(|d| f(d)));
+
+    let socs: Vec<(f32, Option<f32>)> = data
+        .iter()
+        .filter_map(|d| {
+            let soc = d.battery.soc.or(d.energy.bat_soc)? as f32;
+            let capacity = d.battery.rated_capacity.or(d.energy.bat_cap).filter(|c| *c > 0.0);
+            Some((soc, capacity))
+        })
+        .collect();
+    let capacity = sum(socs.iter().map(|(_, c)| *c));
+    let soc = match capacity {
+        Some(total) if socs.iter().all(|(_, c)| c.is_some()) => {
+            Some(socs.iter().map(|(soc, c)| soc * c.unwrap_or_default()).sum::<f32>() / total)
+        }
+        _ if !socs.is_empty() => Some(socs.iter().map(|(soc, _)| soc).sum::<f32>() / socs.len() as f32),
+        _ => None,
+    };
+
+    let totals = SiteTotals {
+        pv_power: energy(|d| d.energy.pv_power),
+        ongrid_power: energy(|d| d.energy.ongrid_power),
+        offgrid_power: energy(|d| d.energy.offgrid_power),
+        bat_power: energy(|d| d.energy.bat_power),
+        total_pv_energy: energy(|d| d.energy.total_pv_energy),
+        total_grid_output_energy: energy(|d| d.energy.total_grid_output_energy),
+        total_grid_input_energy: energy(|d| d.energy.total_grid_input_energy),
+        total_load_energy: energy(|d| d.energy.total_load_energy),
+        soc,
+        capacity,
+    };
+    SiteDashboard {
+        totals,
+        online: data.len(),
+        devices,
+        timestamp: units::timestamp(),
+    }
+}
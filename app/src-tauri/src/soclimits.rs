@@ -0,0 +1,48 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+
+// Fenêtre de SOC imposée par l'application, plus fine que les seuils de l'appareil
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SocLimits {
+    pub enabled: bool,
+    // Pas de décharge à ce SOC ou en dessous, [%]
+    pub min_soc: u32,
+    // Pas de charge à ce SOC ou au-dessus, [%]
+    pub max_soc: u32,
+}
+
+impl Default for SocLimits {
+    fn default() -> Self {
+        Self { enabled: false, min_soc: 10, max_soc: 100 }
+    }
+}
+
+impl SocLimits {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.min_soc >= self.max_soc || self.max_soc > 100 {
+            return Err(AppError::InvalidInput(format!("Invalid SOC window {}-{} %", self.min_soc, self.max_soc)));
+        }
+        Ok(())
+    }
+
+    // Fenêtre d'un automatisme resserrée par la fenêtre globale
+    pub fn narrow(&self, min_soc: u32, max_soc: u32) -> (u32, u32) {
+        match self.enabled {
+            true => (min_soc.max(self.min_soc), max_soc.min(self.max_soc)),
+            false => (min_soc, max_soc),
+        }
+    }
+
+    // power : > 0 en décharge, < 0 en charge
+    pub fn check(&self, power: i64, soc: Option<u32>) -> Result<(), AppError> {
+        let Some(soc) = soc.filter(|_| self.enabled) else { return Ok(()) };
+        if power > 0 && soc <= self.min_soc {
+            return Err(AppError::Forbidden(format!("SOC {} % is at or below the {} % floor: discharge refused", soc, self.min_soc)));
+        }
+        if power < 0 && soc >= self.max_soc {
+            return Err(AppError::Forbidden(format!("SOC {} % is at or above the {} % ceiling: charge refused", soc, self.max_soc)));
+        }
+        Ok(())
+    }
+}
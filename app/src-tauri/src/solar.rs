@@ -0,0 +1,243 @@
+use crate::error::AppError;
+use crate::schedule::{validate_slots, ManualSlot, MAX_SLOTS};
+use chrono::{Local, NaiveDate, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// Cadence de vérification du jour : les plages du jour partent au plus une minute après minuit
+const TICK_S: u64 = 60;
+// Hauteur du soleil au lever/coucher : réfraction et demi-diamètre apparent, [°]
+const HORIZON_DEG: f64 = -0.833;
+const DAY_MINUTES: i64 = 24 * 60;
+
+// Heure d'une plage : "hh:mm", "sunrise", "sunset", avec un décalage "+45", "-30" ou "+1:30"
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SolarSlot {
+    pub time_num: u8,
+    pub start: String,
+    pub end: String,
+    pub week_set: u8,
+    // [W] : > 0 en décharge, < 0 en charge
+    pub power: i64,
+    pub enable: u8,
+}
+
+impl Default for SolarSlot {
+    fn default() -> Self {
+        Self { time_num: 0, start: "sunset".to_string(), end: "24:00".to_string(), week_set: 127, power: 800, enable: 1 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SolarScheduleConfig {
+    pub enabled: bool,
+    // Appareil piloté ; absent : appareil courant
+    pub device: Option<String>,
+    // Absents : position des prévisions solaires
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub slots: Vec<SolarSlot>,
+}
+
+impl SolarScheduleConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.latitude.is_some_and(|lat| !(-90.0..=90.0).contains(&lat)) || self.longitude.is_some_and(|lon| !(-180.0..=180.0).contains(&lon)) {
+            return Err(AppError::InvalidInput("Invalid location for the solar schedule".to_string()));
+        }
+        if self.latitude.is_some() != self.longitude.is_some() {
+            return Err(AppError::InvalidInput("Latitude and longitude must be set together".to_string()));
+        }
+        if self.slots.len() > MAX_SLOTS as usize {
+            return Err(AppError::InvalidInput(format!("At most {} slots", MAX_SLOTS)));
+        }
+        for slot in &self.slots {
+            parse_time(&slot.start)?;
+            parse_time(&slot.end)?;
+        }
+        // Contrôle complet sur une journée type (équinoxe) : numéros, jours, chevauchements évidents
+        let (slots, _) = resolve(&self.slots, Some((6 * 60, 18 * 60)));
+        validate_slots(&slots)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Anchor {
+    Midnight,
+    Sunrise,
+    Sunset,
+}
+
+// -> (repère, décalage en minutes)
+fn parse_time(value: &str) -> Result<(Anchor, i64), AppError> {
+    let invalid = || AppError::InvalidInput(format!("Invalid slot time {:?}: expected hh:mm, sunrise or sunset with an optional +/- offset", value));
+    let clock = |text: &str| -> Option<i64> {
+        match text.split_once(':') {
+            Some((h, m)) => {
+                let (h, m): (i64, i64) = (h.parse().ok()?, m.parse().ok()?);
+                (m < 60).then_some(h * 60 + m)
+            }
+            None => text.parse().ok(),
+        }
+    };
+    let value = value.trim();
+    let (anchor, rest) = if let Some(rest) = value.strip_prefix("sunrise") {
+        (Anchor::Sunrise, rest.trim())
+    } else if let Some(rest) = value.strip_prefix("sunset") {
+        (Anchor::Sunset, rest.trim())
+    } else {
+        let minutes = value.contains(':').then(|| clock(value)).flatten().filter(|m| (0..=DAY_MINUTES).contains(m)).ok_or_else(invalid)?;
+        return Ok((Anchor::Midnight, minutes));
+    };
+    let offset = match rest.chars().next() {
+        None => 0,
+        Some('+') => clock(rest[1..].trim()).ok_or_else(invalid)?,
+        Some('-') => -clock(rest[1..].trim()).ok_or_else(invalid)?,
+        Some(_) => return Err(invalid()),
+    };
+    if offset.abs() > 12 * 60 {
+        return Err(invalid());
+    }
+    Ok((anchor, offset))
+}
+
+fn hhmm(minutes: i64) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+// Lever et coucher en minutes locales depuis minuit ; None : soleil toujours levé ou toujours couché
+pub fn sun_times(day: NaiveDate, latitude: f64, longitude: f64) -> Option<(i64, i64)> {
+    let (sin, cos) = (|deg: f64| deg.to_radians().sin(), |deg: f64| deg.to_radians().cos());
+    // Équation du lever de soleil, jours depuis J2000
+    let j2000 = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let mean_solar_noon = (day - j2000).num_days() as f64 - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_solar_noon).rem_euclid(360.0);
+    let center = 1.9148 * sin(anomaly) + 0.02 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+    let ecliptic = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let transit = 2_451_545.0 + mean_solar_noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic);
+    let declination = (sin(ecliptic) * sin(23.4397)).asin().to_degrees();
+    let cos_hour_angle = (sin(HORIZON_DEG) - sin(latitude) * sin(declination)) / (cos(latitude) * cos(declination));
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let local_minutes = |julian: f64| -> Option<i64> {
+        let unix = ((julian - 2_440_587.5) * 86_400.0).round() as i64;
+        let time = Local.timestamp_opt(unix, 0).single()?;
+        Some(i64::from(time.hour()) * 60 + i64::from(time.minute()))
+    };
+    Some((local_minutes(transit - hour_angle / 360.0)?, local_minutes(transit + hour_angle / 360.0)?))
+}
+
+// Plages concrètes du jour ; une plage devenue vide (décalage trop grand, soleil absent) est ignorée avec un avertissement
+pub fn resolve(slots: &[SolarSlot], sun: Option<(i64, i64)>) -> (Vec<ManualSlot>, Vec<String>) {
+    let mut resolved = Vec::new();
+    let mut warnings = Vec::new();
+    for slot in slots {
+        let at = |value: &str| -> Option<i64> {
+            let (anchor, offset) = parse_time(value).ok()?;
+            let base = match anchor {
+                Anchor::Midnight => 0,
+                Anchor::Sunrise => sun?.0,
+                Anchor::Sunset => sun?.1,
+            };
+            Some((base + offset).clamp(0, DAY_MINUTES))
+        };
+        let (Some(start), Some(end)) = (at(&slot.start), at(&slot.end)) else {
+            warnings.push(format!("Slot {}: no sunrise or sunset today, skipped", slot.time_num));
+            continue;
+        };
+        if end <= start {
+            warnings.push(format!("Slot {}: {}-{} is empty today ({} to {}), skipped", slot.time_num, slot.start, slot.end, hhmm(start), hhmm(end)));
+            continue;
+        }
+        resolved.push(ManualSlot {
+            time_num: slot.time_num,
+            start_time: hhmm(start),
+            end_time: hhmm(end),
+            week_set: slot.week_set,
+            power: slot.power,
+            enable: slot.enable,
+        });
+    }
+    (resolved, warnings)
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Clone, Default)]
+pub struct SolarScheduleStatus {
+    pub day: Option<String>,
+    pub sunrise: Option<String>,
+    pub sunset: Option<String>,
+    pub slots: Vec<ManualSlot>,
+    pub warnings: Vec<String>,
+    // Période du calendrier d'exceptions appliquée aujourd'hui
+    pub exception: Option<String>,
+    pub last_error: Option<String>,
+}
+
+impl SolarScheduleStatus {
+    pub fn new(day: NaiveDate, sun: Option<(i64, i64)>, slots: Vec<ManualSlot>, warnings: Vec<String>) -> Self {
+        Self {
+            day: Some(day.format("%Y-%m-%d").to_string()),
+            sunrise: sun.map(|(rise, _)| hhmm(rise)),
+            sunset: sun.map(|(_, set)| hhmm(set)),
+            slots,
+            warnings,
+            exception: None,
+            last_error: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    // Jour dont les plages ont été écrites
+    written_day: Option<String>,
+    status: SolarScheduleStatus,
+}
+
+// Recalcule les plages chaque jour et les écrit une fois sur l'appareil
+pub struct SolarScheduler {
+    state: Mutex<SchedulerState>,
+    wake: Condvar,
+}
+
+impl Default for SolarScheduler {
+    fn default() -> Self {
+        Self { state: Mutex::new(SchedulerState::default()), wake: Condvar::new() }
+    }
+}
+
+impl SolarScheduler {
+    pub fn status(&self) -> SolarScheduleStatus {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).status.clone()
+    }
+
+    // Configuration modifiée : plages recalculées et réécrites au prochain tour
+    pub fn reset(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).written_day = None;
+        self.wake.notify_all();
+    }
+
+    pub fn wait_tick(&self) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self.wake.wait_timeout(state, Duration::from_secs(TICK_S));
+    }
+
+    pub fn written_day(&self) -> Option<String> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).written_day.clone()
+    }
+
+    pub fn record(&self, status: SolarScheduleStatus, written: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if written {
+            state.written_day = status.day.clone();
+        }
+        state.status = status;
+    }
+}
@@ -0,0 +1,319 @@
+use crate::error::AppError;
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const PRICES_FILE: &str = "prices.json";
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+// Jours conservés dans le cache
+const RETENTION_DAYS: i64 = 90;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceProvider {
+    // API GraphQL, jeton personnel ; prix TTC du contrat (aujourd'hui et demain uniquement)
+    Tibber,
+    // Plateforme de transparence ENTSO-E (EPEX), jeton gratuit ; prix spot [€/MWh]
+    Entsoe,
+    // Data portal Nord Pool, sans clé ; prix spot [€/MWh]
+    NordPool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TariffConfig {
+    pub enabled: bool,
+    pub provider: PriceProvider,
+    // Code EIC pour ENTSO-E (ex. 10YFR-RTE------C), zone pour Nord Pool (ex. FR, NO1) ; ignoré par Tibber
+    pub area: String,
+    pub currency: String,
+    // Uniquement si le trousseau système est indisponible
+    pub api_key: Option<String>,
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: PriceProvider::Entsoe,
+            area: String::new(),
+            currency: "EUR".to_string(),
+            api_key: None,
+        }
+    }
+}
+
+impl TariffConfig {
+    // Clé du cache : changer de fournisseur ou de zone invalide les prix enregistrés
+    fn source(&self) -> String {
+        format!("{:?}:{}:{}", self.provider, self.area, self.currency)
+    }
+}
+
+// Prix moyen d'une heure, [devise/kWh]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PricePoint {
+    // Début de l'heure, heure locale RFC 3339
+    pub start: String,
+    pub price: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DayPrices {
+    pub day: String,
+    pub currency: String,
+    pub prices: Vec<PricePoint>,
+}
+
+// Point brut d'un fournisseur, à sa résolution (15 ou 60 min)
+struct RawPrice {
+    start: DateTime<Utc>,
+    per_kwh: f64,
+}
+
+fn client() -> Result<reqwest::blocking::Client, AppError> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+fn http_error(e: reqwest::Error) -> AppError {
+    if e.is_timeout() {
+        AppError::Timeout(e.to_string())
+    } else {
+        AppError::IoError(e.to_string())
+    }
+}
+
+// [minuit local, minuit local du lendemain[ en UTC
+fn day_bounds(day: NaiveDate) -> Result<(DateTime<Utc>, DateTime<Utc>), AppError> {
+    let midnight = |d: NaiveDate| {
+        Local
+            .from_local_datetime(&d.and_hms_opt(0, 0, 0).unwrap_or_default())
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| AppError::InvalidInput(format!("Invalid local date {}", d)))
+    };
+    Ok((midnight(day)?, midnight(day + ChronoDuration::days(1))?))
+}
+
+fn fetch_tibber(token: &str) -> Result<Vec<RawPrice>, AppError> {
+    let query = "{ viewer { homes { currentSubscription { priceInfo { today { total startsAt } tomorrow { total startsAt } } } } } }";
+    let json: serde_json::Value = client()?
+        .post("https://api.tibber.com/v1-beta/gql")
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "query": query }))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(http_error)?;
+    if let Some(error) = json.pointer("/errors/0/message").and_then(|v| v.as_str()) {
+        return Err(AppError::IoError(format!("Tibber: {}", error)));
+    }
+    let info = json
+        .pointer("/data/viewer/homes/0/currentSubscription/priceInfo")
+        .ok_or_else(|| AppError::NotConfigured("Tibber: no home with an active subscription".to_string()))?;
+    let mut prices = Vec::new();
+    for day in ["today", "tomorrow"] {
+        for entry in info.get(day).and_then(|v| v.as_array()).into_iter().flatten() {
+            let start = entry.get("startsAt").and_then(|v| v.as_str()).and_then(|s| DateTime::parse_from_rfc3339(s).ok());
+            let total = entry.get("total").and_then(|v| v.as_f64());
+            if let (Some(start), Some(total)) = (start, total) {
+                prices.push(RawPrice { start: start.with_timezone(&Utc), per_kwh: total });
+            }
+        }
+    }
+    Ok(prices)
+}
+
+// Contenu du premier <tag>...</tag> de `xml`
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..start + end].trim())
+}
+
+// Document A44 (Publication_MarketDocument) : une <Period> par série, positions 1..n
+fn parse_entsoe(xml: &str) -> Result<Vec<RawPrice>, AppError> {
+    let mut prices = Vec::new();
+    for period in xml.split("<Period>").skip(1) {
+        let start = xml_value(period, "start")
+            .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%MZ").ok())
+            .ok_or_else(|| AppError::ParseError("ENTSO-E: missing period start".to_string()))?
+            .and_utc();
+        let end = xml_value(period, "end")
+            .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%MZ").ok())
+            .map(|t| t.and_utc())
+            .unwrap_or(start);
+        let step = match xml_value(period, "resolution") {
+            Some("PT15M") => ChronoDuration::minutes(15),
+            Some("PT30M") => ChronoDuration::minutes(30),
+            _ => ChronoDuration::minutes(60),
+        };
+        let mut points: BTreeMap<i32, f64> = BTreeMap::new();
+        for point in period.split("<Point>").skip(1) {
+            let position = xml_value(point, "position").and_then(|v| v.parse().ok());
+            let amount = xml_value(point, "price.amount").and_then(|v| v.parse().ok());
+            if let (Some(position), Some(amount)) = (position, amount) {
+                points.insert(position, amount);
+            }
+        }
+        // Courbe A03 : une position absente reprend le prix précédent
+        let slots = ((end - start).num_minutes() / step.num_minutes().max(1)) as i32;
+        let mut last = None;
+        for position in 1..=slots.max(points.keys().last().copied().unwrap_or(0)) {
+            last = points.get(&position).copied().or(last);
+            if let Some(per_mwh) = last {
+                prices.push(RawPrice { start: start + step * (position - 1), per_kwh: per_mwh / 1000.0 });
+            }
+        }
+    }
+    Ok(prices)
+}
+
+fn fetch_entsoe(token: &str, area: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<RawPrice>, AppError> {
+    let format = "%Y%m%d%H%M";
+    let response = client()?
+        .get("https://web-api.tp.entsoe.eu/api")
+        .query(&[
+            ("securityToken", token),
+            ("documentType", "A44"),
+            ("in_Domain", area),
+            ("out_Domain", area),
+            ("periodStart", &from.format(format).to_string()),
+            ("periodEnd", &to.format(format).to_string()),
+        ])
+        .send()
+        .map_err(http_error)?;
+    let status = response.status();
+    let body = response.text().map_err(http_error)?;
+    // Prix pas encore publiés : Acknowledgement_MarketDocument avec un motif 999
+    if body.contains("Acknowledgement_MarketDocument") {
+        tracing::debug!("ENTSO-E: no prices: {}", xml_value(&body, "text").unwrap_or_default());
+        return Ok(Vec::new());
+    }
+    if !status.is_success() {
+        return Err(AppError::IoError(format!("ENTSO-E returned HTTP {}", status)));
+    }
+    parse_entsoe(&body)
+}
+
+fn fetch_nord_pool(area: &str, currency: &str, day: NaiveDate) -> Result<Vec<RawPrice>, AppError> {
+    let date = day.format("%Y-%m-%d").to_string();
+    let response = client()?
+        .get("https://dataportal-api.nordpoolgroup.com/api/DayAheadPrices")
+        .query(&[("date", date.as_str()), ("market", "DayAhead"), ("deliveryArea", area), ("currency", currency)])
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(http_error)?;
+    // 204 : enchères du jour pas encore publiées
+    if response.status() == reqwest::StatusCode::NO_CONTENT {
+        return Ok(Vec::new());
+    }
+    let json: serde_json::Value = response.json().map_err(http_error)?;
+    Ok(json
+        .get("multiAreaEntries")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let start = entry.get("deliveryStart").and_then(|v| v.as_str()).and_then(|s| DateTime::parse_from_rfc3339(s).ok())?;
+            let per_mwh = entry.pointer(&format!("/entryPerArea/{}", area)).and_then(|v| v.as_f64())?;
+            Some(RawPrice { start: start.with_timezone(&Utc), per_kwh: per_mwh / 1000.0 })
+        })
+        .collect())
+}
+
+// Moyenne horaire des points tombant dans la journée locale
+fn hourly(raw: &[RawPrice], from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<PricePoint> {
+    let mut hours: BTreeMap<DateTime<Utc>, (f64, u32)> = BTreeMap::new();
+    for point in raw.iter().filter(|p| p.start >= from && p.start < to) {
+        let hour = point.start.with_minute(0).and_then(|t| t.with_second(0)).unwrap_or(point.start);
+        let entry = hours.entry(hour).or_default();
+        entry.0 += point.per_kwh;
+        entry.1 += 1;
+    }
+    hours
+        .into_iter()
+        .map(|(start, (sum, count))| PricePoint {
+            start: start.with_timezone(&Local).to_rfc3339(),
+            price: sum / f64::from(count),
+        })
+        .collect()
+}
+
+pub fn fetch(config: &TariffConfig, api_key: Option<&str>, day: NaiveDate) -> Result<Vec<PricePoint>, AppError> {
+    let (from, to) = day_bounds(day)?;
+    let key = || api_key.filter(|k| !k.is_empty()).ok_or_else(|| AppError::NotConfigured("Price provider API key is not set".to_string()));
+    let area = || Some(config.area.trim()).filter(|a| !a.is_empty()).ok_or_else(|| AppError::NotConfigured("Price area is not set".to_string()));
+    let raw = match config.provider {
+        PriceProvider::Tibber => fetch_tibber(key()?)?,
+        PriceProvider::Entsoe => fetch_entsoe(key()?, area()?, from, to)?,
+        PriceProvider::NordPool => fetch_nord_pool(area()?, &config.currency, day)?,
+    };
+    Ok(hourly(&raw, from, to))
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CacheFile {
+    source: String,
+    // Clé : jour local "YYYY-MM-DD"
+    days: BTreeMap<String, Vec<PricePoint>>,
+}
+
+// Prix déjà récupérés, sur disque : un jour publié ne change plus
+pub struct PriceCache {
+    path: PathBuf,
+    file: Mutex<CacheFile>,
+}
+
+impl PriceCache {
+    pub fn open(path: PathBuf) -> Self {
+        let file = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, file: Mutex::new(file) }
+    }
+
+    pub fn get(&self, config: &TariffConfig, api_key: Option<&str>, day: NaiveDate) -> Result<DayPrices, AppError> {
+        let key = day.format("%Y-%m-%d").to_string();
+        let source = config.source();
+        {
+            let file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(prices) = file.days.get(&key).filter(|_| file.source == source) {
+                return Ok(DayPrices { day: key, currency: config.currency.clone(), prices: prices.clone() });
+            }
+        }
+
+        let prices = fetch(config, api_key, day)?;
+        // Journée incomplète (publication en cours, changement d'heure mal couvert) : pas de mise en cache
+        if prices.len() >= 23 {
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            if file.source != source {
+                *file = CacheFile { source, days: BTreeMap::new() };
+            }
+            file.days.insert(key.clone(), prices.clone());
+            let oldest = (Local::now().date_naive() - ChronoDuration::days(RETENTION_DAYS)).format("%Y-%m-%d").to_string();
+            file.days.retain(|day, _| *day >= oldest);
+            if let Err(e) = self.save(&file) {
+                tracing::warn!("failed to write price cache: {}", e);
+            }
+        }
+        Ok(DayPrices { day: key, currency: config.currency.clone(), prices })
+    }
+
+    fn save(&self, file: &CacheFile) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(fs::write(&self.path, serde_json::to_string(file)?)?)
+    }
+}
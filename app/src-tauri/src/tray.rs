@@ -0,0 +1,156 @@
+use crate::audit::CommandSource;
+use crate::error::AppError;
+use crate::{apply_mode, device_target, pause_automation, AppState, DashboardData};
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Wry};
+
+const TRAY_ID: &str = "main";
+const PAUSE_ID: &str = "pause";
+// Entrées de scène : préfixe suivi du nom
+const SCENE_PREFIX: &str = "scene:";
+
+// Case « Pause automation », resynchronisée quand la pause vient d'ailleurs
+struct TrayMenu {
+    pause: CheckMenuItem<Wry>,
+}
+
+pub fn is_active(app: &AppHandle) -> bool {
+    app.tray_by_id(TRAY_ID).is_some()
+}
+
+pub fn set_paused(app: &AppHandle, paused: bool) {
+    if let Some(menu) = app.try_state::<TrayMenu>() {
+        let _ = menu.pause.set_checked(paused);
+    }
+}
+
+pub fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+// Modes sans configuration, ou scène enregistrée : Manual et Passive se règlent dans le dashboard
+fn quick_mode(app: &AppHandle, mode: &str, config: Option<serde_json::Value>) -> Result<(), AppError> {
+    let state = app.state::<AppState>();
+    state.ensure_writable()?;
+    // Pas de saisie possible depuis le menu : refusé si un PIN est configuré
+    state.check_pin(None)?;
+    let target = device_target(&state, None)?;
+    match apply_mode(&state, CommandSource::Ui, &target, mode, config, true)? {
+        true => Ok(()),
+        false => Err(AppError::DeviceRejected { code: 0, message: format!("{} mode was not accepted", mode) }),
+    }
+}
+
+fn scene(app: &AppHandle, name: &str) -> Result<(), AppError> {
+    let scene = app.state::<AppState>().scenes.get(name)?;
+    quick_mode(app, &scene.mode, scene.config)
+}
+
+fn on_menu(app: &AppHandle, id: &str, pause: &CheckMenuItem<Wry>) {
+    let result = match id {
+        "open" => {
+            show_window(app);
+            Ok(())
+        }
+        PAUSE_ID => {
+            let paused = pause.is_checked().unwrap_or(false);
+            pause_automation(&app.state::<AppState>(), paused)
+        }
+        "mode_auto" => quick_mode(app, "Auto", None),
+        "mode_ai" => quick_mode(app, "AI", None),
+        id if id.starts_with(SCENE_PREFIX) => scene(app, &id[SCENE_PREFIX.len()..]),
+        "quit" => {
+            app.exit(0);
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        tracing::warn!("tray action {} failed: {}", id, e);
+        crate::show_notification(app, "MarsTip", &e.to_string());
+    }
+}
+
+// Scènes marquées « tray » dans un sous-menu, absent s'il n'y en a aucune
+fn menu(app: &AppHandle, pause: &CheckMenuItem<Wry>) -> tauri::Result<Menu<Wry>> {
+    let open = MenuItem::with_id(app, "open", "Open dashboard", true, None::<&str>)?;
+    let auto = MenuItem::with_id(app, "mode_auto", "Auto", true, None::<&str>)?;
+    let ai = MenuItem::with_id(app, "mode_ai", "AI", true, None::<&str>)?;
+    let modes = Submenu::with_items(app, "Mode", true, &[&auto, &ai])?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&open, pause, &modes])?;
+
+    let scenes = app.try_state::<AppState>().map(|state| state.scenes.list()).unwrap_or_default();
+    let items = scenes
+        .iter()
+        .filter(|s| s.tray)
+        .map(|s| MenuItem::with_id(app, format!("{}{}", SCENE_PREFIX, s.name), &s.name, true, None::<&str>))
+        .collect::<tauri::Result<Vec<_>>>()?;
+    if !items.is_empty() {
+        let scenes = Submenu::with_id(app, "scenes", "Scenes", true)?;
+        for item in &items {
+            scenes.append(item)?;
+        }
+        menu.append(&scenes)?;
+    }
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&quit)?;
+    Ok(menu)
+}
+
+// Après modification des scènes ; la case de pause est reprise telle quelle
+pub fn refresh(app: &AppHandle) {
+    let (Some(tray), Some(state)) = (app.tray_by_id(TRAY_ID), app.try_state::<TrayMenu>()) else { return };
+    match menu(app, &state.pause) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => tracing::warn!("failed to rebuild tray menu: {}", e),
+    }
+}
+
+pub fn build(app: &AppHandle, paused: bool) -> tauri::Result<()> {
+    let pause = CheckMenuItem::with_id(app, PAUSE_ID, "Pause automation", true, paused, None::<&str>)?;
+    let menu = menu(app, &pause)?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("MarsTip")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event({
+            let pause = pause.clone();
+            move |app, event| on_menu(app, event.id.as_ref(), &pause)
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                show_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    app.manage(TrayMenu { pause });
+    Ok(())
+}
+
+// Infobulle (et titre macOS) : SOC et puissance batterie du dernier poll
+pub fn update(app: &AppHandle, data: Option<&DashboardData>) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else { return };
+    let soc = data.and_then(|d| d.battery.soc.or(d.energy.bat_soc));
+    let (tooltip, title) = match (data, soc) {
+        (Some(data), Some(soc)) => {
+            let power = data.energy.bat_power.map(|p| format!(" · {:.0} W", p)).unwrap_or_default();
+            (format!("MarsTip · {} %{}", soc, power), Some(format!("{} %", soc)))
+        }
+        (Some(_), None) => ("MarsTip".to_string(), None),
+        (None, _) => ("MarsTip · offline".to_string(), None),
+    };
+    let _ = tray.set_tooltip(Some(tooltip));
+    let _ = tray.set_title(title);
+}
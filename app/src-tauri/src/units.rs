@@ -0,0 +1,229 @@
+use crate::error::AppError;
+use crate::DashboardData;
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_DECIMALS: u8 = 3;
+// Clés des valeurs converties dans l'affichage, les exports et MQTT
+pub const POWER_KEYS: &[&str] = &[
+    "pv_power",
+    "grid_power",
+    "battery_power",
+    "ongrid_power",
+    "offgrid_power",
+    "heater_power",
+    "pv_to_load",
+    "pv_to_battery",
+    "pv_to_grid",
+    "grid_to_load",
+    "grid_to_battery",
+    "battery_to_load",
+    "battery_to_grid",
+    "load_power",
+];
+pub const ENERGY_KEYS: &[&str] = &["total_pv_energy", "total_grid_output_energy", "total_grid_input_energy", "total_load_energy"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum PowerUnit {
+    #[default]
+    W,
+    #[serde(rename = "kW")]
+    Kw,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum EnergyUnit {
+    #[default]
+    Wh,
+    #[serde(rename = "kWh")]
+    Kwh,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    // 14:03:27, heure locale
+    #[default]
+    Time,
+    // 2024-05-01 14:03:27, heure locale
+    DateTime,
+    // 2024-05-01T14:03:27+02:00
+    Iso,
+    // 2024-05-01T12:03:27Z
+    Utc,
+}
+
+// Préférences d'affichage : les valeurs internes restent en W, Wh et horodatages UTC ISO-8601
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct UnitPreferences {
+    pub power: PowerUnit,
+    pub energy: EnergyUnit,
+    // Décimales des valeurs converties
+    pub decimals: u8,
+    pub timestamp: TimestampFormat,
+}
+
+impl Default for UnitPreferences {
+    fn default() -> Self {
+        Self { power: PowerUnit::W, energy: EnergyUnit::Wh, decimals: 1, timestamp: TimestampFormat::Time }
+    }
+}
+
+// Bloc "display" du tableau de bord : mêmes valeurs que les champs numériques, converties et mises en forme
+#[derive(Serialize, Clone, Default)]
+pub struct DisplayValues {
+    pub timestamp: String,
+    pub power_unit: &'static str,
+    pub energy_unit: &'static str,
+    // "pv_power" -> "1.2 kW" ; valeurs absentes omises
+    pub values: BTreeMap<&'static str, String>,
+}
+
+fn dashboard_values(data: &DashboardData) -> Vec<(&'static str, Option<f64>)> {
+    let value = |v: Option<f32>| v.map(f64::from);
+    let mut values = vec![
+        ("pv_power", value(data.energy.pv_power)),
+        ("grid_power", value(data.meter.as_ref().and_then(|m| m.total_power))),
+        ("battery_power", value(data.energy.bat_power)),
+        ("ongrid_power", value(data.energy.ongrid_power)),
+        ("offgrid_power", value(data.energy.offgrid_power)),
+        ("total_pv_energy", value(data.energy.total_pv_energy)),
+        ("total_grid_output_energy", value(data.energy.total_grid_output_energy)),
+        ("total_grid_input_energy", value(data.energy.total_grid_input_energy)),
+        ("total_load_energy", value(data.energy.total_load_energy)),
+    ];
+    values.extend(data.flows.named());
+    values
+}
+
+// Horodatage stocké et échangé : UTC, sans ambiguïté d'heure d'été ni de jour
+pub fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl UnitPreferences {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.decimals > MAX_DECIMALS {
+            return Err(AppError::InvalidInput(format!("Decimal precision must be between 0 and {}", MAX_DECIMALS)));
+        }
+        Ok(())
+    }
+
+    fn round(&self, value: f64) -> f64 {
+        let factor = 10f64.powi(i32::from(self.decimals));
+        (value * factor).round() / factor
+    }
+
+    pub fn power_unit(&self) -> &'static str {
+        match self.power {
+            PowerUnit::W => "W",
+            PowerUnit::Kw => "kW",
+        }
+    }
+
+    pub fn energy_unit(&self) -> &'static str {
+        match self.energy {
+            EnergyUnit::Wh => "Wh",
+            EnergyUnit::Kwh => "kWh",
+        }
+    }
+
+    // watts : valeur interne, [W]
+    pub fn power(&self, watts: f64) -> f64 {
+        self.round(match self.power {
+            PowerUnit::W => watts,
+            PowerUnit::Kw => watts / 1000.0,
+        })
+    }
+
+    pub fn energy(&self, wh: f64) -> f64 {
+        self.round(match self.energy {
+            EnergyUnit::Wh => wh,
+            EnergyUnit::Kwh => wh / 1000.0,
+        })
+    }
+
+    // Valeur interne convertie selon sa clé ; les autres (SOC, températures...) sont seulement arrondies
+    pub fn convert(&self, key: &str, value: f64) -> f64 {
+        if POWER_KEYS.contains(&key) {
+            self.power(value)
+        } else if ENERGY_KEYS.contains(&key) {
+            self.energy(value)
+        } else {
+            self.round(value)
+        }
+    }
+
+    pub fn unit(&self, key: &str) -> Option<&'static str> {
+        if POWER_KEYS.contains(&key) {
+            Some(self.power_unit())
+        } else if ENERGY_KEYS.contains(&key) {
+            Some(self.energy_unit())
+        } else {
+            None
+        }
+    }
+
+    // "1.2 kW" ; None : valeur absente
+    pub fn format(&self, key: &str, value: Option<f64>) -> Option<String> {
+        let value = self.convert(key, value?);
+        let decimals = usize::from(self.decimals);
+        Some(match self.unit(key) {
+            Some(unit) => format!("{:.*} {}", decimals, value, unit),
+            None => format!("{:.*}", decimals, value),
+        })
+    }
+
+    pub fn format_time(&self, time: DateTime<Utc>) -> String {
+        match self.timestamp {
+            TimestampFormat::Time => time.with_timezone(&Local).format("%H:%M:%S").to_string(),
+            TimestampFormat::DateTime => time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+            TimestampFormat::Iso => time.with_timezone(&Local).to_rfc3339_opts(SecondsFormat::Secs, false),
+            TimestampFormat::Utc => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+
+    // Un export couvre plusieurs jours : l'heure seule devient date et heure
+    pub fn export_time(&self, time: DateTime<Utc>) -> String {
+        match self.timestamp {
+            TimestampFormat::Time => Self { timestamp: TimestampFormat::DateTime, ..self.clone() }.format_time(time),
+            _ => self.format_time(time),
+        }
+    }
+
+    // Colonne d'export : suffixe d'unité quand la valeur n'est plus en W ou Wh
+    pub fn column(&self, key: &str) -> String {
+        match self.unit(key).filter(|unit| !matches!(*unit, "W" | "Wh")) {
+            Some(unit) => format!("{}_{}", key, unit.to_lowercase()),
+            None => key.to_string(),
+        }
+    }
+
+    pub fn display(&self, data: &DashboardData) -> DisplayValues {
+        DisplayValues {
+            timestamp: self.display_time(&data.timestamp),
+            power_unit: self.power_unit(),
+            energy_unit: self.energy_unit(),
+            values: dashboard_values(data).into_iter().filter_map(|(key, value)| Some((key, self.format(key, value)?))).collect(),
+        }
+    }
+
+    // Charge utile MQTT ou ligne d'export : les nombres des grandeurs connues sont convertis sur place
+    pub fn convert_json(&self, fields: &mut serde_json::Map<String, serde_json::Value>) {
+        for (key, value) in fields.iter_mut() {
+            if let Some(number) = value.as_f64().filter(|_| self.unit(key).is_some()) {
+                *value = serde_json::json!(self.convert(key, number));
+            }
+        }
+    }
+
+    // Horodatage stocké (RFC 3339) mis en forme ; rendu tel quel s'il ne se lit pas
+    pub fn display_time(&self, stored: &str) -> String {
+        match DateTime::parse_from_rfc3339(stored) {
+            Ok(time) => self.format_time(time.with_timezone(&Utc)),
+            Err(_) => stored.to_string(),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Mode dans lequel le firmware redémarre après une coupure ou une mise à jour
+const FACTORY_MODE: &str = "Auto";
+
+// Remet le dernier mode choisi (Auto, AI ou Manual avec ses plages) quand l'appareil a redémarré
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ModeWatchdogConfig {
+    pub enabled: bool,
+    // Délai minimal entre deux restaurations sur un même appareil, [s]
+    pub cooldown_s: u64,
+}
+
+impl Default for ModeWatchdogConfig {
+    fn default() -> Self {
+        Self { enabled: false, cooldown_s: 300 }
+    }
+}
+
+impl ModeWatchdogConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.cooldown_s < 30 {
+            return Err(AppError::InvalidInput("Mode watchdog cooldown must be at least 30 s".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreReason {
+    // Appareil revenu après des relevés sans réponse
+    Reconnected,
+    // Repassé seul dans le mode d'usine
+    FactoryMode,
+}
+
+// Événement "mode-restored"
+#[derive(Serialize, Clone)]
+pub struct ModeRestore {
+    pub device: String,
+    pub from: String,
+    pub to: String,
+    pub reason: RestoreReason,
+    pub timestamp: String,
+}
+
+#[derive(Default)]
+struct DeviceWatch {
+    unreachable: bool,
+    restored: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct ModeWatchdog {
+    state: Mutex<HashMap<String, DeviceWatch>>,
+}
+
+impl ModeWatchdog {
+    pub fn unreachable(&self, device: &str) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).entry(device.to_string()).or_default().unreachable = true;
+    }
+
+    // unexpected : changement de mode non demandé par l'app (ModeWatch)
+    pub fn check(&self, config: &ModeWatchdogConfig, device: &str, mode: &str, desired: Option<&str>, unexpected: bool) -> Option<RestoreReason> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let watch = state.entry(device.to_string()).or_default();
+        let reconnected = std::mem::take(&mut watch.unreachable);
+        let desired = desired?;
+        if !config.enabled || mode == desired {
+            return None;
+        }
+        let reason = if reconnected {
+            RestoreReason::Reconnected
+        } else if unexpected && mode == FACTORY_MODE {
+            RestoreReason::FactoryMode
+        } else {
+            return None;
+        };
+        // Changement voulu dans l'appli Marstek : pas de bras de fer au-delà d'une tentative par période
+        if watch.restored.is_some_and(|at| at.elapsed() < Duration::from_secs(config.cooldown_s)) {
+            return None;
+        }
+        watch.restored = Some(Instant::now());
+        Some(reason)
+    }
+}
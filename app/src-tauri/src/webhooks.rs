@@ -0,0 +1,380 @@
+use crate::alerts::AlertEvent;
+use crate::error::AppError;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const HTTP_TIMEOUT_MS: u64 = 10_000;
+// Envois en cours (tentatives comprises) ; au-delà, un événement est abandonné
+const MAX_IN_FLIGHT: usize = 32;
+const MAX_RETRIES: u32 = 10;
+pub const SIGNATURE_HEADER: &str = "X-Marstip-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Marstip-Timestamp";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Alert,
+    // Tout changement de mode, demandé par l'app ou non
+    ModeChanged,
+    // Franchissement d'un des soc_thresholds, dans un sens ou dans l'autre
+    SocThreshold,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum WebhookMethod {
+    #[default]
+    Post,
+    Put,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Webhook {
+    pub name: String,
+    pub enabled: bool,
+    pub url: String,
+    pub method: WebhookMethod,
+    pub events: Vec<WebhookEvent>,
+    // [%]
+    pub soc_thresholds: Vec<u32>,
+    // Corps avec des {{champs}} de l'événement ; absent : l'événement en JSON
+    pub template: Option<String>,
+    pub content_type: String,
+    // Les valeurs acceptent aussi les {{champs}} (ex : Title pour ntfy.sh)
+    pub headers: BTreeMap<String, String>,
+    // Clé de signature HMAC-SHA256 ; uniquement si le trousseau système est indisponible
+    pub secret: Option<String>,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            url: String::new(),
+            method: WebhookMethod::Post,
+            events: vec![WebhookEvent::Alert],
+            soc_thresholds: Vec::new(),
+            template: None,
+            content_type: "application/json".to_string(),
+            headers: BTreeMap::new(),
+            secret: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub hooks: Vec<Webhook>,
+    // Nouvelles tentatives après une erreur réseau, un 429 ou un 5xx
+    pub retries: u32,
+    // Délai avant la première nouvelle tentative, doublé ensuite, [s]
+    pub retry_delay_s: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self { enabled: false, hooks: Vec::new(), retries: 3, retry_delay_s: 5 }
+    }
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.retries > MAX_RETRIES {
+            return Err(AppError::InvalidInput(format!("Webhook retries must be at most {}", MAX_RETRIES)));
+        }
+        if self.retry_delay_s == 0 {
+            return Err(AppError::InvalidInput("Webhook retry delay must be at least 1 s".to_string()));
+        }
+        for (i, hook) in self.hooks.iter().enumerate() {
+            if hook.name.trim().is_empty() {
+                return Err(AppError::InvalidInput("Webhook name is required".to_string()));
+            }
+            if self.hooks[..i].iter().any(|h| h.name == hook.name) {
+                return Err(AppError::InvalidInput(format!("Duplicate webhook name: {}", hook.name)));
+            }
+            if !hook.url.starts_with("http://") && !hook.url.starts_with("https://") {
+                return Err(AppError::InvalidInput(format!("Invalid webhook URL: {}", hook.url)));
+            }
+            if let Some(threshold) = hook.soc_thresholds.iter().find(|t| **t > 100) {
+                return Err(AppError::InvalidInput(format!("Invalid SOC threshold: {} %", threshold)));
+            }
+            if hook.events.contains(&WebhookEvent::SocThreshold) && hook.soc_thresholds.is_empty() {
+                return Err(AppError::InvalidInput(format!("Webhook {} needs at least one SOC threshold", hook.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct WebhookStatus {
+    pub name: String,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+}
+
+// {{champ}} remplacé par la valeur de l'événement (texte brut, sans guillemets) ; champ inconnu : vide
+pub fn render(template: &str, payload: &Map<String, Value>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else { break };
+        out.push_str(&rest[..start]);
+        match payload.get(rest[start + 2..start + 2 + end].trim()) {
+            Some(Value::String(s)) => out.push_str(s),
+            Some(Value::Null) | None => {}
+            Some(value) => out.push_str(&value.to_string()),
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// HMAC-SHA256 de "<horodatage>.<corps>", en hexadécimal : le destinataire rejette un rejeu ancien
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn send(hook: &Webhook, secret: Option<&str>, payload: &Map<String, Value>) -> Result<(), AppError> {
+    let body = match &hook.template {
+        Some(template) => render(template, payload),
+        None => Value::Object(payload.clone()).to_string(),
+    };
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(HTTP_TIMEOUT_MS))
+        .build()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let mut request = match hook.method {
+        WebhookMethod::Post => client.post(&hook.url),
+        WebhookMethod::Put => client.put(&hook.url),
+    };
+    request = request.header("Content-Type", &hook.content_type);
+    for (name, value) in &hook.headers {
+        request = request.header(name, render(value, payload));
+    }
+    if let Some(secret) = secret {
+        let timestamp = chrono::Utc::now().timestamp();
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, format!("sha256={}", signature(secret, timestamp, &body)));
+    }
+    let response = request
+        .body(body)
+        .send()
+        .map_err(|e| if e.is_timeout() { AppError::Timeout(e.to_string()) } else { AppError::IoError(e.to_string()) })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().unwrap_or_default();
+    let message = format!("{} returned HTTP {}: {}", hook.name, status, body.trim());
+    match status.as_u16() {
+        401 | 403 => Err(AppError::Forbidden(message)),
+        429 => Err(AppError::IoError(message)),
+        400..=499 => Err(AppError::InvalidInput(message)),
+        _ => Err(AppError::IoError(message)),
+    }
+}
+
+// Seules les erreurs passagères sont retentées ; un refus (4xx) ne changera pas
+fn retryable(error: &AppError) -> bool {
+    matches!(error, AppError::Timeout(_) | AppError::IoError(_))
+}
+
+struct DispatchState {
+    config: WebhookConfig,
+    // Par nom de webhook
+    secrets: HashMap<String, String>,
+    // Dernières valeurs par appareil, pour détecter changements de mode et franchissements
+    soc: HashMap<String, u32>,
+    mode: HashMap<String, String>,
+}
+
+// Chaque envoi part dans son propre thread : un serveur lent ne retarde ni le polling ni les autres webhooks
+pub struct WebhookDispatcher {
+    state: Mutex<DispatchState>,
+    status: Arc<Mutex<BTreeMap<String, WebhookStatus>>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(DispatchState {
+                config: WebhookConfig::default(),
+                secrets: HashMap::new(),
+                soc: HashMap::new(),
+                mode: HashMap::new(),
+            }),
+            status: Arc::new(Mutex::new(BTreeMap::new())),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+fn payload(event: &str, device: &str) -> Map<String, Value> {
+    let mut payload = Map::new();
+    payload.insert("event".into(), event.into());
+    payload.insert("device".into(), device.into());
+    payload.insert("timestamp".into(), chrono::Local::now().to_rfc3339().into());
+    payload
+}
+
+impl WebhookDispatcher {
+    fn lock(&self) -> std::sync::MutexGuard<'_, DispatchState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn configure(&self, config: &WebhookConfig, secrets: HashMap<String, String>) {
+        let mut state = self.lock();
+        state.config = config.clone();
+        state.secrets = secrets;
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.retain(|name, _| config.hooks.iter().any(|h| &h.name == name));
+    }
+
+    pub fn status(&self) -> Vec<WebhookStatus> {
+        let state = self.lock();
+        let status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .config
+            .hooks
+            .iter()
+            .map(|h| status.get(&h.name).cloned().unwrap_or(WebhookStatus { name: h.name.clone(), ..WebhookStatus::default() }))
+            .collect()
+    }
+
+    pub fn alert(&self, event: &AlertEvent) {
+        let state = self.lock();
+        if !state.config.enabled {
+            return;
+        }
+        let mut payload = payload("alert", &event.device);
+        if let Ok(Value::Object(fields)) = serde_json::to_value(event) {
+            payload.extend(fields);
+        }
+        self.fire(&state, |hook| hook.events.contains(&WebhookEvent::Alert), &payload);
+    }
+
+    // Premier relevé exclu : seule une vraie transition déclenche un envoi
+    pub fn observe(&self, device: &str, soc: Option<u32>, mode: Option<&str>) {
+        let mut state = self.lock();
+        let previous_mode = match mode {
+            Some(mode) => state.mode.insert(device.to_string(), mode.to_string()),
+            None => None,
+        };
+        let previous_soc = match soc {
+            Some(soc) => state.soc.insert(device.to_string(), soc),
+            None => None,
+        };
+        if !state.config.enabled {
+            return;
+        }
+
+        if let (Some(previous), Some(mode)) = (previous_mode, mode) {
+            if previous != mode {
+                let mut payload = payload("mode_changed", device);
+                payload.insert("previous".into(), previous.into());
+                payload.insert("mode".into(), mode.into());
+                self.fire(&state, |hook| hook.events.contains(&WebhookEvent::ModeChanged), &payload);
+            }
+        }
+
+        let (Some(previous), Some(soc)) = (previous_soc, soc) else { return };
+        let hooks: Vec<(String, u32)> = state
+            .config
+            .hooks
+            .iter()
+            .filter(|h| h.events.contains(&WebhookEvent::SocThreshold))
+            .flat_map(|h| h.soc_thresholds.iter().map(|t| (h.name.clone(), *t)))
+            .filter(|(_, t)| (previous < *t && soc >= *t) || (previous >= *t && soc < *t))
+            .collect();
+        for (name, threshold) in hooks {
+            let mut payload = payload("soc_threshold", device);
+            payload.insert("previous".into(), previous.into());
+            payload.insert("soc".into(), soc.into());
+            payload.insert("threshold".into(), threshold.into());
+            payload.insert("direction".into(), if soc >= threshold { "up" } else { "down" }.into());
+            self.fire(&state, |hook| hook.name == name, &payload);
+        }
+    }
+
+    // Envoi immédiat, sans nouvelle tentative, même si les webhooks sont désactivés
+    pub fn test(&self, name: &str) -> Result<(), AppError> {
+        let (hook, secret) = {
+            let state = self.lock();
+            let hook = state
+                .config
+                .hooks
+                .iter()
+                .find(|h| h.name == name)
+                .cloned()
+                .ok_or_else(|| AppError::NotConfigured(format!("Unknown webhook: {}", name)))?;
+            (hook, state.secrets.get(name).cloned())
+        };
+        let mut payload = payload("test", "");
+        payload.insert("message".into(), "Test notification from Marstip".into());
+        let result = send(&hook, secret.as_deref(), &payload);
+        record(&self.status, &hook.name, &result);
+        result
+    }
+
+    fn fire(&self, state: &DispatchState, wants: impl Fn(&Webhook) -> bool, payload: &Map<String, Value>) {
+        for hook in state.config.hooks.iter().filter(|h| h.enabled && wants(h)) {
+            if self.in_flight.fetch_add(1, Ordering::SeqCst) >= MAX_IN_FLIGHT {
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                tracing::warn!("too many webhook deliveries in flight, dropping {} for {}", payload["event"], hook.name);
+                continue;
+            }
+            let hook = hook.clone();
+            let secret = state.secrets.get(&hook.name).cloned();
+            let payload = payload.clone();
+            let (retries, delay) = (state.config.retries, state.config.retry_delay_s);
+            let (status, in_flight) = (Arc::clone(&self.status), Arc::clone(&self.in_flight));
+            std::thread::spawn(move || {
+                let mut attempt = 0;
+                let result = loop {
+                    let result = send(&hook, secret.as_deref(), &payload);
+                    match &result {
+                        Err(e) if attempt < retries && retryable(e) => {
+                            tracing::debug!("webhook {} failed (attempt {}): {}", hook.name, attempt + 1, e);
+                            std::thread::sleep(Duration::from_secs(delay.saturating_mul(1 << attempt.min(16))));
+                            attempt += 1;
+                        }
+                        _ => break result,
+                    }
+                };
+                if let Err(e) = &result {
+                    tracing::warn!("webhook {} failed: {}", hook.name, e);
+                }
+                record(&status, &hook.name, &result);
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+}
+
+fn record(status: &Mutex<BTreeMap<String, WebhookStatus>>, name: &str, result: &Result<(), AppError>) {
+    let mut status = status.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = status.entry(name.to_string()).or_insert_with(|| WebhookStatus { name: name.to_string(), ..WebhookStatus::default() });
+    match result {
+        Ok(()) => {
+            entry.last_success = Some(chrono::Local::now().to_rfc3339());
+            entry.last_error = None;
+        }
+        Err(e) => entry.last_error = Some(e.to_string()),
+    }
+}
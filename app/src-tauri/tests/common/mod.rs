@@ -0,0 +1,115 @@
+// Faux appareil UDP sur la boucle locale, parlant le dialecte JSON-RPC Marstek
+use marstip_lib::client::ConnectionSettings;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub enum Reply {
+    Json(serde_json::Value),
+    // Datagramme tel quel (JSON invalide, déchets)
+    Raw(Vec<u8>),
+    // Réponse découpée en plusieurs datagrammes de `chunk` octets
+    Split(serde_json::Value, usize),
+}
+
+// (requête, rang de la requête pour cette méthode) -> datagrammes envoyés en retour
+type Handler = dyn Fn(&serde_json::Value, usize) -> Vec<Reply> + Send + Sync;
+
+pub struct MockDevice {
+    port: u16,
+    received: Arc<Mutex<HashMap<String, usize>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl MockDevice {
+    pub fn start(handler: impl Fn(&serde_json::Value, usize) -> Vec<Reply> + Send + Sync + 'static) -> Self {
+        let handler: Box<Handler> = Box::new(handler);
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (counts, stopped) = (Arc::clone(&received), Arc::clone(&stop));
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; 65_536];
+            while !stopped.load(Ordering::Relaxed) {
+                let Ok((len, from)) = socket.recv_from(&mut buf) else { continue };
+                let request: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+                let method = request["method"].as_str().unwrap_or_default().to_string();
+                let rank = {
+                    let mut counts = counts.lock().unwrap();
+                    let count = counts.entry(method).or_insert(0);
+                    *count += 1;
+                    *count - 1
+                };
+                for reply in handler(&request, rank) {
+                    let datagrams = match reply {
+                        Reply::Json(value) => vec![serde_json::to_vec(&value).unwrap()],
+                        Reply::Raw(bytes) => vec![bytes],
+                        Reply::Split(value, chunk) => serde_json::to_vec(&value).unwrap().chunks(chunk).map(<[u8]>::to_vec).collect(),
+                    };
+                    for datagram in datagrams {
+                        socket.send_to(&datagram, from).unwrap();
+                    }
+                }
+            }
+        });
+        Self { port, received, stop }
+    }
+
+    // Appareil bien élevé : chaque méthode renvoie un résultat type Venus E
+    pub fn venus() -> Self {
+        Self::start(|request, _| vec![result(request, venus_result(request["method"].as_str().unwrap_or_default()))])
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Requêtes reçues pour cette méthode
+    pub fn received(&self, method: &str) -> usize {
+        self.received.lock().unwrap().get(method).copied().unwrap_or(0)
+    }
+}
+
+impl Drop for MockDevice {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn result(request: &serde_json::Value, result: serde_json::Value) -> Reply {
+    Reply::Json(serde_json::json!({"id": request["id"], "src": "VenusE-mock", "result": result}))
+}
+
+pub fn error(request: &serde_json::Value, code: i64) -> Reply {
+    Reply::Json(serde_json::json!({"id": request["id"], "error": {"code": code, "message": "mock error"}}))
+}
+
+pub fn venus_result(method: &str) -> serde_json::Value {
+    match method {
+        "Marstek.GetDevice" => serde_json::json!({"device": "VenusE", "ver": 153, "ble_mac": "aabbccddeeff", "wifi_mac": "112233445566", "ip": "127.0.0.1"}),
+        "ES.GetStatus" => serde_json::json!({"id": 0, "bat_soc": 64, "bat_cap": 5120, "pv_power": 1200, "ongrid_power": -300, "bat_power": 300, "total_pv_energy": 4321}),
+        "Bat.GetStatus" => serde_json::json!({"id": 0, "soc": 64, "charg_flag": true, "dischrg_flag": true, "bat_temp": 22.5, "rated_capacity": 5120}),
+        "Wifi.GetStatus" => serde_json::json!({"id": 0, "ssid": "home", "rssi": -61, "sta_ip": "127.0.0.1"}),
+        "ES.GetMode" => serde_json::json!({"id": 0, "mode": "Auto", "bat_soc": 64}),
+        "EM.GetStatus" => serde_json::json!({"id": 0, "ct_state": 1, "a_power": 100, "b_power": 50, "c_power": -20, "total_power": 130}),
+        "ES.SetMode" => serde_json::json!({"id": 0, "set_result": true}),
+        _ => serde_json::Value::Null,
+    }
+}
+
+// Délais courts et une seule nouvelle tentative : les cas d'échec restent rapides
+pub fn connection() -> ConnectionSettings {
+    let fast = ["Marstek.GetDevice", "ES.SetMode"].map(|method| (method.to_string(), 300));
+    ConnectionSettings {
+        timeout_ms: 300,
+        retries: 1,
+        retry_delay_ms: 10,
+        retry_max_delay_ms: 10,
+        local_port: 0,
+        method_timeouts_ms: HashMap::from(fast),
+    }
+}
@@ -0,0 +1,173 @@
+// Client appareil face à un faux appareil UDP : assemblage du dashboard, délais, id, réponses abîmées
+mod common;
+
+use common::{connection, error, result, venus_result, MockDevice, Reply};
+use marstip_lib::client::{AppError, DeviceClient, DiscoveryConfig, ModeRequest, ProtocolVariant, UdpTransport};
+use std::collections::HashMap;
+
+const IP: &str = "127.0.0.1";
+
+fn call(device: &MockDevice, method: &str) -> Result<serde_json::Value, AppError> {
+    let transport = UdpTransport::default();
+    DeviceClient::new(&transport, connection()).call(IP, device.port(), method, serde_json::json!({"id": 0}))
+}
+
+#[test]
+fn status_assembles_every_section() {
+    let device = MockDevice::venus();
+    let transport = UdpTransport::default();
+    let status = DeviceClient::new(&transport, connection()).status(IP, device.port(), &ProtocolVariant::default()).unwrap();
+    assert_eq!(status.device.device.as_deref(), Some("VenusE"));
+    assert_eq!(status.device.ver, Some(153));
+    assert_eq!(status.battery.soc, Some(64));
+    assert_eq!(status.energy.pv_power, Some(1200.0));
+    assert_eq!(status.mode.mode.as_deref(), Some("Auto"));
+    assert_eq!(status.meter.and_then(|m| m.total_power), Some(130.0));
+    assert_eq!(status.wifi.rssi, Some(-61));
+    assert!(status.errors.is_empty());
+}
+
+#[test]
+fn failed_section_is_reported_and_the_rest_kept() {
+    let device = MockDevice::start(|request, _| match request["method"].as_str() {
+        Some("EM.GetStatus") => vec![error(request, -32601)],
+        Some(method) => vec![result(request, venus_result(method))],
+        None => vec![],
+    });
+    let transport = UdpTransport::default();
+    let status = DeviceClient::new(&transport, connection()).status(IP, device.port(), &ProtocolVariant::default()).unwrap();
+    assert!(status.meter.is_none());
+    assert!(status.errors["meter"].contains("-32601"));
+    assert_eq!(status.battery.soc, Some(64));
+    // Un refus explicite n'est pas répété
+    assert_eq!(device.received("EM.GetStatus"), 1);
+}
+
+#[test]
+fn disconnected_ct_drops_the_meter() {
+    let device = MockDevice::start(|request, _| match request["method"].as_str() {
+        Some("EM.GetStatus") => vec![result(request, serde_json::json!({"ct_state": 0, "total_power": 0}))],
+        Some(method) => vec![result(request, venus_result(method))],
+        None => vec![],
+    });
+    let transport = UdpTransport::default();
+    let status = DeviceClient::new(&transport, connection()).status(IP, device.port(), &ProtocolVariant::default()).unwrap();
+    assert!(status.meter.is_none());
+    assert!(status.errors.is_empty());
+}
+
+#[test]
+fn silent_device_fails_the_whole_status() {
+    let device = MockDevice::start(|_, _| vec![]);
+    let transport = UdpTransport::default();
+    let status = DeviceClient::new(&transport, connection()).status(IP, device.port(), &ProtocolVariant::default());
+    assert!(matches!(status, Err(AppError::Timeout(_))));
+}
+
+#[test]
+fn lost_datagram_is_retried() {
+    let device = MockDevice::start(|request, rank| if rank == 0 { vec![] } else { vec![result(request, serde_json::json!({"soc": 50}))] });
+    assert_eq!(call(&device, "Bat.GetStatus").unwrap()["soc"], 50);
+    assert_eq!(device.received("Bat.GetStatus"), 2);
+}
+
+#[test]
+fn timeout_after_every_retry() {
+    let device = MockDevice::start(|_, _| vec![]);
+    assert!(matches!(call(&device, "Bat.GetStatus"), Err(AppError::Timeout(_))));
+    assert_eq!(device.received("Bat.GetStatus"), 1 + connection().retries as usize);
+}
+
+#[test]
+fn rejection_is_not_retried() {
+    let device = MockDevice::start(|request, _| vec![error(request, -32602)]);
+    assert!(matches!(call(&device, "ES.SetMode"), Err(AppError::DeviceRejected { code: -32602, .. })));
+    assert_eq!(device.received("ES.SetMode"), 1);
+}
+
+#[test]
+fn reply_with_another_id_is_ignored() {
+    let device = MockDevice::start(|request, _| {
+        let id = request["id"].as_u64().unwrap() + 1000;
+        vec![Reply::Json(serde_json::json!({"id": id, "result": {"soc": 1}}))]
+    });
+    assert!(matches!(call(&device, "Bat.GetStatus"), Err(AppError::Timeout(_))));
+}
+
+#[test]
+fn reply_without_id_is_matched_to_the_only_pending_request() {
+    let device = MockDevice::start(|_, _| vec![Reply::Json(serde_json::json!({"result": {"soc": 12}}))]);
+    assert_eq!(call(&device, "Bat.GetStatus").unwrap()["soc"], 12);
+}
+
+#[test]
+fn garbage_before_the_reply_is_skipped() {
+    let device = MockDevice::start(|request, _| vec![Reply::Raw(b"\xff\x00AT+OK\r\n".to_vec()), result(request, serde_json::json!({"soc": 33}))]);
+    assert_eq!(call(&device, "Bat.GetStatus").unwrap()["soc"], 33);
+}
+
+#[test]
+fn split_reply_is_reassembled() {
+    let slots: Vec<_> = (0..10).map(|n| serde_json::json!({"time_num": n, "start_time": "00:00", "end_time": "01:00"})).collect();
+    let expected = slots.clone();
+    let device = MockDevice::start(move |request, _| {
+        vec![Reply::Split(serde_json::json!({"id": request["id"], "result": {"mode": "Manual", "manual_cfg": slots}}), 64)]
+    });
+    assert_eq!(call(&device, "ES.GetMode").unwrap()["manual_cfg"], serde_json::json!(expected));
+}
+
+#[test]
+fn truncated_reply_times_out() {
+    let device = MockDevice::start(|request, _| {
+        let full = serde_json::to_vec(&serde_json::json!({"id": request["id"], "result": {"soc": 9}})).unwrap();
+        vec![Reply::Raw(full[..full.len() / 2].to_vec())]
+    });
+    assert!(matches!(call(&device, "Bat.GetStatus"), Err(AppError::Timeout(_))));
+}
+
+#[test]
+fn missing_result_is_null() {
+    let device = MockDevice::start(|request, _| vec![Reply::Json(serde_json::json!({"id": request["id"]}))]);
+    assert_eq!(call(&device, "Marstek.Reboot").unwrap(), serde_json::Value::Null);
+}
+
+// Firmware au dialecte différent : méthode renommée et champ au nom propre, déclarés par la variante
+#[test]
+fn protocol_variant_quirks() {
+    let device = MockDevice::start(|request, _| match request["method"].as_str() {
+        Some("ES.GetStat") => vec![result(request, serde_json::json!({"soc_pct": 71, "pv_power": 300, "extra": [1, 2]}))],
+        Some("ES.GetStatus") => vec![error(request, -32601)],
+        Some(method) => vec![result(request, venus_result(method))],
+        None => vec![],
+    });
+    let variant = ProtocolVariant {
+        methods: HashMap::from([("ES.GetStatus".to_string(), "ES.GetStat".to_string())]),
+        fields: HashMap::from([("soc_pct".to_string(), "bat_soc".to_string())]),
+    };
+    let transport = UdpTransport::default();
+    let status = DeviceClient::new(&transport, connection()).status(IP, device.port(), &variant).unwrap();
+    assert_eq!(status.energy.bat_soc, Some(71));
+    assert_eq!(status.energy.pv_power, Some(300.0));
+    assert_eq!(device.received("ES.GetStatus"), 0);
+}
+
+#[test]
+fn set_mode_reports_refusal() {
+    let device = MockDevice::start(|request, rank| vec![result(request, serde_json::json!({"set_result": rank > 0}))]);
+    let transport = UdpTransport::default();
+    let client = DeviceClient::new(&transport, connection());
+    assert!(!client.set_mode(IP, device.port(), &ProtocolVariant::default(), &ModeRequest::Auto).unwrap());
+    assert!(client.set_mode(IP, device.port(), &ProtocolVariant::default(), &ModeRequest::Auto).unwrap());
+}
+
+#[test]
+fn sweep_finds_the_device() {
+    let device = MockDevice::venus();
+    let transport = UdpTransport::default();
+    let config = DiscoveryConfig { sweep_timeout_ms: 300, ..DiscoveryConfig::default() };
+    let found = DeviceClient::new(&transport, connection()).sweep("127.0.0.1/32", device.port(), &config).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].ip.as_str(), found[0].port), (IP, device.port()));
+    assert_eq!(found[0].device.as_deref(), Some("VenusE"));
+    assert_eq!(found[0].ble_mac.as_deref(), Some("aabbccddeeff"));
+}